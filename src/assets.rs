@@ -0,0 +1,257 @@
+//! Loads assets once and shares them wherever they're used.
+//!
+//! Assets are cached by the path they were loaded from, so asking for the same file twice hands
+//! back the same asset instead of loading it again. Arena and character files can also be
+//! re-read whenever they change on disk, so that they can be tweaked while the game runs.
+use ggez::{filesystem, Context};
+use ggez::graphics::Image;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use crate::{
+    screens::battle::{arena::Arena, player::blueprint::PlayerBlueprint},
+    util::result::WalpurgisResult,
+};
+
+/// Every asset the game has loaded so far, by path.
+#[derive(Debug)]
+pub struct AssetManager {
+    root: PathBuf,
+    /// Textures, by path relative to the asset root.
+    images: HashMap<PathBuf, Image>,
+    arenas: RonCache<Arena>,
+    blueprints: RonCache<PlayerBlueprint>,
+}
+
+/// The assets of one kind parsed from RON files, by canonical path.
+#[derive(Debug)]
+struct RonCache<T> {
+    entries: HashMap<PathBuf, Cached<T>>,
+    load: fn(&Path) -> WalpurgisResult<T>,
+}
+
+/// A parsed asset, along with when its file was last changed as of parsing it.
+#[derive(Debug)]
+struct Cached<T> {
+    asset: Rc<T>,
+    modified: Option<SystemTime>,
+}
+
+impl AssetManager {
+    /// An empty cache for the assets under `root`.
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        AssetManager {
+            root: root.into(),
+            images: HashMap::new(),
+            arenas: RonCache::new(|file| Arena::load(file)),
+            blueprints: RonCache::new(|file| PlayerBlueprint::load(file)),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Mounts the asset root in `ggez`'s filesystem, which images and sprite sheets are loaded
+    /// through.
+    pub fn mount(&self, ctx: &mut Context) -> WalpurgisResult {
+        filesystem::mount(ctx, &fs::canonicalize(&self.root)?, true);
+        Ok(())
+    }
+
+    /// The texture `file`, relative to the asset root. The asset root has to be mounted.
+    pub fn image(&mut self, ctx: &mut Context, file: &Path) -> WalpurgisResult<Image> {
+        if let Some(image) = self.images.get(file) {
+            return Ok(image.clone());
+        }
+        let image = Image::new(ctx, Path::new("/").join(file))?;
+        self.images.insert(file.to_path_buf(), image.clone());
+        Ok(image)
+    }
+
+    /// The arena described in `arena_file`. Arenas are cached by their canonical path.
+    pub fn arena<P: AsRef<Path>>(&mut self, arena_file: P) -> WalpurgisResult<Rc<Arena>> {
+        self.arenas.get(arena_file.as_ref())
+    }
+
+    /// The arena loaded from `arena_file`, if one was. Never loads anything.
+    pub fn loaded_arena(&self, arena_file: &Path) -> Option<Rc<Arena>> {
+        self.arenas.entries.get(arena_file).map(|cached| Rc::clone(&cached.asset))
+    }
+
+    /// The first arena in the arena directory, along with the file it's in.
+    pub fn first_arena(&mut self) -> WalpurgisResult<(PathBuf, Rc<Arena>)> {
+        let arena_dir = self.root.join("arenas");
+        log::info!("Loading first arena from assets directory: `{}`", arena_dir.display());
+        let arena_file = canonical(&Arena::first_file(arena_dir)?);
+        let arena = self.arena(&arena_file)?;
+        Ok((arena_file, arena))
+    }
+
+    // TODO: remove this once we don't need it anymore
+    /// The arena called `name` in the arena directory, along with the file it's in.
+    pub fn arena_named(&mut self, name: &str) -> WalpurgisResult<(PathBuf, Rc<Arena>)> {
+        let arena_dir = self.root.join("arenas");
+        for arena_file in Arena::files(&arena_dir)? {
+            let arena = self.arena(&arena_file)?;
+            if arena.name() == name {
+                return Ok((canonical(&arena_file), arena));
+            }
+        }
+        Err(format!("No arena called `{}` found in the directory `{}`.", name, arena_dir.display()))?
+    }
+
+    /// The character described in `blueprint_file`.
+    pub fn blueprint<P: AsRef<Path>>(&mut self, blueprint_file: P) -> WalpurgisResult<Rc<PlayerBlueprint>> {
+        self.blueprints.get(blueprint_file.as_ref())
+    }
+
+    /// Every character in the characters directory, in order of file name.
+    pub fn blueprints(&mut self) -> WalpurgisResult<Vec<Rc<PlayerBlueprint>>> {
+        let character_dir = self.root.join("characters");
+        log::info!("Loading characters from `{}`", character_dir.display());
+        PlayerBlueprint::files(character_dir)?
+            .iter()
+            .map(|blueprint_file| self.blueprint(blueprint_file))
+            .collect()
+    }
+
+    /// Re-reads the arena and character files changed since they were last read, swapping the new
+    /// versions in. Files that no longer parse keep their old version. Returns the paths of the
+    /// assets swapped in.
+    pub fn poll_reload(&mut self) -> Vec<PathBuf> {
+        let mut reloaded = self.arenas.reload_changed();
+        reloaded.extend(self.blueprints.reload_changed());
+        reloaded
+    }
+}
+
+impl<T> RonCache<T> {
+    fn new(load: fn(&Path) -> WalpurgisResult<T>) -> Self {
+        RonCache { entries: HashMap::new(), load }
+    }
+
+    /// The asset in `file`, parsed the first time it's asked for.
+    fn get(&mut self, file: &Path) -> WalpurgisResult<Rc<T>> {
+        let file = canonical(file);
+        if let Some(cached) = self.entries.get(&file) {
+            return Ok(Rc::clone(&cached.asset));
+        }
+        let modified = modified(&file);
+        let asset = Rc::new((self.load)(&file)?);
+        self.entries.insert(file, Cached { asset: Rc::clone(&asset), modified });
+        Ok(asset)
+    }
+
+    /// Re-parses every file whose modification time changed. Failures are only logged, and tried
+    /// again once the file changes again.
+    fn reload_changed(&mut self) -> Vec<PathBuf> {
+        let load = self.load;
+        let mut reloaded = vec![];
+        for (file, cached) in &mut self.entries {
+            let modified = modified(file);
+            if modified == cached.modified {
+                continue;
+            }
+            cached.modified = modified;
+            match load(file) {
+                Ok(asset) => {
+                    log::info!("Reloaded `{}`.", file.display());
+                    cached.asset = Rc::new(asset);
+                    reloaded.push(file.clone());
+                },
+                Err(e) => log::error!("Failed to reload an asset, keeping the old one: {}", e),
+            }
+        }
+        reloaded.sort();
+        reloaded
+    }
+}
+
+/// `file` with its links and `..`s resolved, so that every way of naming a file names it the same.
+/// Files that can't be resolved are left as they are, to fail when they're read.
+fn canonical(file: &Path) -> PathBuf {
+    fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf())
+}
+
+fn modified(file: &Path) -> Option<SystemTime> {
+    fs::metadata(file).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod assets_test {
+    use super::*;
+    use std::fs::File;
+    use std::time::Duration;
+
+    /// The contents of an arena file for an empty arena called `name`.
+    fn empty_arena(name: &str) -> String {
+        format!("(name: \"{}\", platforms: [], spawn_points: [[0, 0], [100, 0]])", name)
+    }
+
+    /// A fresh directory with an arena file called `name` in it.
+    fn arena_dir(dir_name: &str, name: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("walpurgis_{}_{}", dir_name, std::process::id()));
+        fs::create_dir_all(dir.join("arenas")).unwrap();
+        let arena_file = dir.join("arenas").join("arena.ron");
+        write_arena(&arena_file, &empty_arena(name));
+        (dir, arena_file)
+    }
+
+    /// Writes `contents` to `arena_file`, making sure its modification time moves on even on
+    /// filesystems that only keep whole seconds.
+    fn write_arena(arena_file: &Path, contents: &str) {
+        let previous = modified(arena_file);
+        fs::write(arena_file, contents).unwrap();
+        if let Some(previous) = previous {
+            File::options().write(true).open(arena_file).unwrap()
+                .set_modified(previous + Duration::from_secs(2)).unwrap();
+        }
+    }
+
+    #[test]
+    fn same_path_loaded_once() {
+        let (dir, arena_file) = arena_dir("cached_arena", "Cached");
+        let mut assets = AssetManager::new(&dir);
+        let arena = assets.arena(&arena_file).unwrap();
+        let roundabout = dir.join("arenas").join("..").join("arenas").join("arena.ron");
+        assert!(Rc::ptr_eq(&arena, &assets.arena(roundabout).unwrap()));
+        let (first_file, first) = assets.first_arena().unwrap();
+        assert!(Rc::ptr_eq(&arena, &first) && first_file == canonical(&arena_file));
+        assert!(assets.arenas.entries.len() == 1);
+        assert!(assets.poll_reload().is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn changed_files_reloaded() {
+        let (dir, arena_file) = arena_dir("reloaded_arena", "Before");
+        let mut assets = AssetManager::new(&dir);
+        let before = assets.arena(&arena_file).unwrap();
+        write_arena(&arena_file, &empty_arena("After"));
+        assert!(assets.poll_reload() == vec![canonical(&arena_file)]);
+        assert!(assets.loaded_arena(&canonical(&arena_file)).unwrap().name() == "After");
+        // Whoever held on to the old one still has it.
+        assert!(before.name() == "Before");
+        assert!(assets.poll_reload().is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn broken_reloads_keep_old_asset() {
+        let (dir, arena_file) = arena_dir("broken_arena", "Working");
+        let mut assets = AssetManager::new(&dir);
+        assets.arena(&arena_file).unwrap();
+        write_arena(&arena_file, "(name: \"Broken\", platforms: [");
+        assert!(assets.poll_reload().is_empty());
+        assert!(assets.arena(&arena_file).unwrap().name() == "Working");
+        // Fixing the file picks it up again.
+        write_arena(&arena_file, &empty_arena("Fixed"));
+        assert!(assets.poll_reload().len() == 1);
+        assert!(assets.arena(&arena_file).unwrap().name() == "Fixed");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
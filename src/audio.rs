@@ -0,0 +1,664 @@
+//! Ambient sound for arenas: global loops like wind or a crowd, and emitters placed in the arena
+//! whose volume and pan follow the listener. Also the sound effects and music played for what
+//! happens in the game.
+//!
+//! Which emitters are heard and how loud is worked out by pure functions, while the sounds
+//! themselves are played through an `AudioBackend`, so that the mixing can be tested without an
+//! audio device. Sound effects and music go through a `SoundBank` the same way.
+//!
+//! ## Lifecycle
+//! An `Ambience` is started along with a battle, updated every tick and stops every sound it
+//! started once dropped, so that leaving a battle never leaves loops playing. The `AudioManager`
+//! lasts as long as the game, playing the soundtrack of whichever screen is shown.
+use ggez::Context;
+use ggez::audio::{SoundData, SoundSource, Source, SpatialSource};
+use ggez::nalgebra as na;
+use serde::{Serialize, Deserialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::settings;
+use crate::util::result::{WalpurgisError, WalpurgisResult};
+
+/// Where sound effects are loaded from, relative to the asset root. Each is named after its file,
+/// without the extension.
+pub const SFX_DIR: &str = "sounds/sfx";
+/// Where music is loaded from, relative to the asset root, named the same way.
+pub const MUSIC_DIR: &str = "sounds/music";
+
+/// How quickly an emitter fades out with distance.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Rolloff {
+    /// Fades evenly until silent at the max distance.
+    #[default]
+    Linear,
+    /// Fades quickly up close and slowly further away.
+    Quadratic,
+}
+
+impl Rolloff {
+    /// The fraction of full volume heard `distance` away from an emitter heard up to
+    /// `max_distance` away.
+    pub fn attenuation(self, distance: f32, max_distance: f32) -> f32 {
+        if distance >= max_distance {
+            return 0.;
+        }
+        let closeness = 1. - distance / max_distance;
+        match self {
+            Rolloff::Linear => closeness,
+            Rolloff::Quadratic => closeness * closeness,
+        }
+    }
+}
+
+/// Where an emitter is in the arena.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum Placement {
+    /// Heard the same everywhere.
+    #[default]
+    Global,
+    /// Fixed in place.
+    At(na::Vector2<f32>),
+    /// Carried along by a platform, `offset` from its top left corner.
+    Platform {
+        index: usize,
+        offset: na::Vector2<f32>,
+    },
+}
+
+/// A looping sound declared by an arena.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AmbientEmitter {
+    /// The sound file, relative to the asset root.
+    pub sound: PathBuf,
+    /// The volume heard right at the emitter, from 0 to 1.
+    #[serde(default = "full_volume")]
+    pub volume: f32,
+    #[serde(default)]
+    pub placement: Placement,
+    /// How far away the emitter can be heard from. Also the distance at which it is panned fully
+    /// to one side.
+    #[serde(default = "default_max_distance")]
+    pub max_distance: f32,
+    #[serde(default)]
+    pub rolloff: Rolloff,
+}
+
+fn full_volume() -> f32 {
+    1.
+}
+
+fn default_max_distance() -> f32 {
+    600.
+}
+
+impl AmbientEmitter {
+    /// Where the emitter currently is, given the top left corner of each platform. `None` for
+    /// global loops, or emitters on platforms that don't exist.
+    pub fn position(&self, platform_origins: &[na::Vector2<f32>]) -> Option<na::Vector2<f32>> {
+        match &self.placement {
+            Placement::Global => None,
+            Placement::At(position) => Some(*position),
+            Placement::Platform { index, offset } => platform_origins.get(*index).map(|origin| origin + offset),
+        }
+    }
+
+    /// How far the emitter is from `listener`. Global loops are always right on top of it.
+    pub fn distance(&self, platform_origins: &[na::Vector2<f32>], listener: na::Vector2<f32>) -> f32 {
+        self.position(platform_origins).map_or(0., |position| (position - listener).norm())
+    }
+
+    /// How the emitter sounds from `listener`.
+    pub fn mix(&self, platform_origins: &[na::Vector2<f32>], listener: na::Vector2<f32>) -> Mix {
+        match self.position(platform_origins) {
+            None => Mix { volume: self.volume, pan: 0. },
+            Some(position) => {
+                let offset = position - listener;
+                Mix {
+                    volume: self.volume * self.rolloff.attenuation(offset.norm(), self.max_distance),
+                    pan: (offset[0] / self.max_distance).clamp(-1., 1.),
+                }
+            },
+        }
+    }
+}
+
+/// How loud a sound plays and where it is heard from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mix {
+    /// From 0 to 1.
+    pub volume: f32,
+    /// From -1 for fully left to 1 for fully right.
+    pub pan: f32,
+}
+
+/// The indices of the emitters to play when at most `cap` can play at once. Emitters too far to be
+/// heard are left out, and the nearest of the rest win.
+pub fn select_nearest(emitters: &[AmbientEmitter], distances: &[f32], cap: usize) -> Vec<usize> {
+    let mut audible: Vec<usize> = (0..emitters.len())
+        .filter(|idx| emitters[*idx].placement == Placement::Global || distances[*idx] < emitters[*idx].max_distance)
+        .collect();
+    // Stable, so ties go to whichever the arena declared first.
+    audible.sort_by(|a, b| distances[*a].partial_cmp(&distances[*b]).unwrap_or(std::cmp::Ordering::Equal));
+    audible.truncate(cap);
+    audible
+}
+
+/// Plays the loops of an `Ambience`, which are referred to by emitter index.
+pub trait AudioBackend: fmt::Debug {
+    /// Starts looping the emitter's sound from the beginning.
+    fn play(&mut self, emitter: usize);
+    fn set_mix(&mut self, emitter: usize, mix: Mix);
+    fn stop(&mut self, emitter: usize);
+}
+
+/// Plays loops through `ggez`.
+#[derive(Debug)]
+pub struct GgezAudio {
+    sources: Vec<SpatialSource>,
+}
+
+impl GgezAudio {
+    /// Loads the sound of every emitter.
+    pub fn load<P: AsRef<Path>>(ctx: &mut Context, asset_root: P, emitters: &[AmbientEmitter]) -> WalpurgisResult<Self> {
+        let mut sources = vec![];
+        for emitter in emitters {
+            let sound_file = asset_root.as_ref().join(&emitter.sound);
+            log::info!("Loading ambient sound `{}`.", sound_file.display());
+            let bytes = fs::read(&sound_file).map_err(|e| WalpurgisError::reading(&sound_file, "sound", e))?;
+            let mut source = SpatialSource::from_data(ctx, SoundData::from_bytes(&bytes))?;
+            source.set_repeat(true);
+            sources.push(source);
+        }
+        Ok(Self { sources })
+    }
+}
+
+impl AudioBackend for GgezAudio {
+    fn play(&mut self, emitter: usize) {
+        if let Err(e) = self.sources[emitter].play() {
+            log::warn!("Failed to play ambient sound {}: {}", emitter, e);
+        }
+    }
+
+    fn set_mix(&mut self, emitter: usize, mix: Mix) {
+        let source = &mut self.sources[emitter];
+        source.set_volume(mix.volume);
+        // Between the ears, which sit at -1 and 1.
+        source.set_position([mix.pan, 0., 0.]);
+    }
+
+    fn stop(&mut self, emitter: usize) {
+        self.sources[emitter].stop();
+    }
+}
+
+/// The ambient loops of a battle.
+#[derive(Debug)]
+pub struct Ambience {
+    emitters: Vec<AmbientEmitter>,
+    backend: Box<dyn AudioBackend>,
+    /// Whether each emitter is playing.
+    playing: Vec<bool>,
+    /// The most emitters that may play at once.
+    cap: usize,
+}
+
+impl Ambience {
+    /// Nothing plays until the first `update`.
+    pub fn new(emitters: Vec<AmbientEmitter>, backend: Box<dyn AudioBackend>, cap: usize) -> Self {
+        let playing = vec![false; emitters.len()];
+        Self {
+            emitters,
+            backend,
+            playing,
+            cap,
+        }
+    }
+
+    /// Plays the emitters nearest to `listener` and mixes them for where it is, stopping any
+    /// that are no longer among the nearest.
+    pub fn update(&mut self, listener: na::Vector2<f32>, platform_origins: &[na::Vector2<f32>]) {
+        let distances: Vec<_> = self.emitters.iter()
+            .map(|emitter| emitter.distance(platform_origins, listener))
+            .collect();
+        let selected = select_nearest(&self.emitters, &distances, self.cap);
+        for idx in 0..self.emitters.len() {
+            if selected.contains(&idx) {
+                if !self.playing[idx] {
+                    self.backend.play(idx);
+                    self.playing[idx] = true;
+                }
+                self.backend.set_mix(idx, self.emitters[idx].mix(platform_origins, listener));
+            } else if self.playing[idx] {
+                self.backend.stop(idx);
+                self.playing[idx] = false;
+            }
+        }
+    }
+
+    /// Stops every emitter.
+    pub fn stop(&mut self) {
+        for (idx, playing) in self.playing.iter_mut().enumerate() {
+            if *playing {
+                self.backend.stop(idx);
+                *playing = false;
+            }
+        }
+    }
+}
+
+impl Drop for Ambience {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Something happening in the game that makes a sound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundEvent {
+    /// A player landed on a platform.
+    Land,
+    /// A player started an attack.
+    Attack,
+    /// A player took damage.
+    Hurt,
+    /// The highlight moved to another menu entry.
+    MenuMove,
+    /// A menu entry was picked.
+    MenuSelect,
+}
+
+impl SoundEvent {
+    /// The name of the sound effect played for the event.
+    pub fn sfx(self) -> &'static str {
+        match self {
+            SoundEvent::Land => "land",
+            SoundEvent::Attack => "attack",
+            SoundEvent::Hurt => "hurt",
+            SoundEvent::MenuMove => "menu_move",
+            SoundEvent::MenuSelect => "menu_select",
+        }
+    }
+}
+
+/// Plays the sound effects and music of an `AudioManager`, which are referred to by name.
+pub trait SoundBank: fmt::Debug {
+    /// Plays sound effect `name` once, over anything already playing. Returns whether there is
+    /// such a sound.
+    fn play_sfx(&mut self, name: &str, volume: f32) -> bool;
+    /// Starts music `name` from the beginning, stopping any other music. Returns whether there is
+    /// such music.
+    fn play_music(&mut self, name: &str, looping: bool, volume: f32) -> bool;
+    fn stop_music(&mut self);
+    /// Changes the volume of the music playing, if any, without starting it over.
+    fn set_music_volume(&mut self, volume: f32);
+}
+
+/// Plays sound effects and music through `ggez`.
+#[derive(Debug)]
+pub struct GgezSounds {
+    sfx: HashMap<String, Source>,
+    music: HashMap<String, Source>,
+    /// The name of the music playing, if any.
+    playing: Option<String>,
+}
+
+impl GgezSounds {
+    /// Loads every sound in `SFX_DIR` and `MUSIC_DIR`. Sounds that can't be loaded are left out,
+    /// which is only logged.
+    pub fn load<P: AsRef<Path>>(ctx: &mut Context, asset_root: P) -> Self {
+        Self {
+            sfx: load_sounds(ctx, &asset_root.as_ref().join(SFX_DIR)),
+            music: load_sounds(ctx, &asset_root.as_ref().join(MUSIC_DIR)),
+            playing: None,
+        }
+    }
+}
+
+/// Loads every file in `dir` as a sound, by file name without the extension.
+fn load_sounds(ctx: &mut Context, dir: &Path) -> HashMap<String, Source> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::warn!("No sounds loaded from `{}`: {}", dir.display(), e);
+            return HashMap::new();
+        },
+    };
+    let mut sounds = HashMap::new();
+    for path in entries.filter_map(Result::ok).map(|entry| entry.path()) {
+        let name = match path.file_stem().and_then(|stem| stem.to_str()) {
+            Some(name) => name.to_owned(),
+            None => continue,
+        };
+        let loaded = fs::read(&path)
+            .map_err(|e| WalpurgisError::reading(&path, "sound", e))
+            .and_then(|bytes| Ok(Source::from_data(ctx, SoundData::from_bytes(&bytes))?));
+        match loaded {
+            Ok(source) => {
+                sounds.insert(name, source);
+            },
+            Err(e) => log::warn!("Failed to load sound `{}`: {}", path.display(), e),
+        }
+    }
+    sounds
+}
+
+impl SoundBank for GgezSounds {
+    fn play_sfx(&mut self, name: &str, volume: f32) -> bool {
+        let source = match self.sfx.get_mut(name) {
+            Some(source) => source,
+            None => return false,
+        };
+        source.set_volume(volume);
+        if let Err(e) = source.play_detached() {
+            log::warn!("Failed to play sound `{}`: {}", name, e);
+        }
+        true
+    }
+
+    fn play_music(&mut self, name: &str, looping: bool, volume: f32) -> bool {
+        if !self.music.contains_key(name) {
+            return false;
+        }
+        self.stop_music();
+        let source = self.music.get_mut(name).expect("Checked above.");
+        source.set_repeat(looping);
+        source.set_volume(volume);
+        if let Err(e) = source.play() {
+            log::warn!("Failed to play music `{}`: {}", name, e);
+        }
+        self.playing = Some(name.to_owned());
+        true
+    }
+
+    fn stop_music(&mut self) {
+        if let Some(source) = self.playing.take().and_then(|name| self.music.get_mut(&name)) {
+            source.stop();
+        }
+    }
+
+    fn set_music_volume(&mut self, volume: f32) {
+        let music = &mut self.music;
+        if let Some(source) = self.playing.as_ref().and_then(|name| music.get_mut(name)) {
+            source.set_volume(volume);
+        }
+    }
+}
+
+/// Plays sound effects and music by name at the volumes in the settings.
+#[derive(Debug)]
+pub struct AudioManager {
+    bank: Box<dyn SoundBank>,
+    /// Already scaled by the master volume.
+    music_volume: f32,
+    sfx_volume: f32,
+    /// The name of the music playing, if any.
+    music: Option<String>,
+    /// The sounds asked for that don't exist, which have already been warned about.
+    missing: HashSet<String>,
+}
+
+impl AudioManager {
+    pub fn new(bank: Box<dyn SoundBank>, audio: &settings::Audio) -> Self {
+        Self {
+            bank,
+            music_volume: audio.effective_music_volume(),
+            sfx_volume: audio.effective_sfx_volume(),
+            music: None,
+            missing: HashSet::new(),
+        }
+    }
+
+    pub fn play_sfx(&mut self, name: &str) {
+        if !self.bank.play_sfx(name, self.sfx_volume) {
+            self.warn_missing("sound effect", name);
+        }
+    }
+
+    /// Starts music `name` in place of whatever music is playing.
+    pub fn play_music(&mut self, name: &str, looping: bool) {
+        self.stop_music();
+        if self.bank.play_music(name, looping, self.music_volume) {
+            self.music = Some(name.to_owned());
+        } else {
+            self.warn_missing("music", name);
+        }
+    }
+
+    pub fn stop_music(&mut self) {
+        if self.music.take().is_some() {
+            self.bank.stop_music();
+        }
+    }
+
+    /// Plays everything at the volumes in `audio` from now on, including the music playing.
+    pub fn set_volumes(&mut self, audio: &settings::Audio) {
+        self.sfx_volume = audio.effective_sfx_volume();
+        self.music_volume = audio.effective_music_volume();
+        if self.music.is_some() {
+            self.bank.set_music_volume(self.music_volume);
+        }
+    }
+
+    /// Plays the sound effect of each event, in order.
+    pub fn handle_events(&mut self, events: &[SoundEvent]) {
+        for event in events {
+            self.play_sfx(event.sfx());
+        }
+    }
+
+    /// Loops `soundtrack` unless it's already playing, or stops the music if there is none.
+    /// Missing music is only asked for again once the soundtrack changes.
+    pub fn set_soundtrack(&mut self, soundtrack: Option<&str>) {
+        match soundtrack {
+            Some(name) if self.music.as_deref() == Some(name) || self.missing.contains(name) => (),
+            Some(name) => self.play_music(name, true),
+            None => self.stop_music(),
+        }
+    }
+
+    /// Warns about a missing sound the first time it's asked for.
+    fn warn_missing(&mut self, what: &str, name: &str) {
+        if self.missing.insert(name.to_owned()) {
+            log::warn!("There is no {} `{}`, playing nothing instead.", what, name);
+        }
+    }
+}
+
+/// An `AudioBackend` which only keeps track of what would be playing.
+#[cfg(test)]
+#[derive(Debug, Clone, Default)]
+pub struct MockAudio {
+    /// The mix of every playing emitter, shared with clones so it can be checked after the
+    /// backend is handed off.
+    pub playing: std::rc::Rc<std::cell::RefCell<std::collections::HashMap<usize, Option<Mix>>>>,
+}
+
+#[cfg(test)]
+impl MockAudio {
+    pub fn active_sources(&self) -> usize {
+        self.playing.borrow().len()
+    }
+}
+
+#[cfg(test)]
+impl AudioBackend for MockAudio {
+    fn play(&mut self, emitter: usize) {
+        assert!(self.playing.borrow_mut().insert(emitter, None).is_none(), "{} played twice", emitter);
+    }
+
+    fn set_mix(&mut self, emitter: usize, mix: Mix) {
+        *self.playing.borrow_mut().get_mut(&emitter).expect("Mixed a stopped emitter.") = Some(mix);
+    }
+
+    fn stop(&mut self, emitter: usize) {
+        assert!(self.playing.borrow_mut().remove(&emitter).is_some(), "{} stopped twice", emitter);
+    }
+}
+
+/// A `SoundBank` with the sounds in `available`, which logs what it plays.
+#[cfg(test)]
+#[derive(Debug, Clone, Default)]
+pub struct MockSounds {
+    pub available: Vec<&'static str>,
+    /// Everything played or stopped, e.g. `sfx land 0.5`, `music theme true 1` or `stop`. Shared
+    /// with clones so it can be checked after the bank is handed off.
+    pub log: std::rc::Rc<std::cell::RefCell<Vec<String>>>,
+}
+
+#[cfg(test)]
+impl SoundBank for MockSounds {
+    fn play_sfx(&mut self, name: &str, volume: f32) -> bool {
+        self.available.contains(&name) && {
+            self.log.borrow_mut().push(format!("sfx {} {}", name, volume));
+            true
+        }
+    }
+
+    fn play_music(&mut self, name: &str, looping: bool, volume: f32) -> bool {
+        self.available.contains(&name) && {
+            self.log.borrow_mut().push(format!("music {} {} {}", name, looping, volume));
+            true
+        }
+    }
+
+    fn stop_music(&mut self) {
+        self.log.borrow_mut().push("stop".to_owned());
+    }
+
+    fn set_music_volume(&mut self, volume: f32) {
+        self.log.borrow_mut().push(format!("volume {}", volume));
+    }
+}
+
+#[cfg(test)]
+mod audio_test {
+    use super::*;
+
+    fn audio_settings(master_volume: f32, music_volume: f32, sfx_volume: f32) -> settings::Audio {
+        settings::Audio { master_volume, music_volume, sfx_volume, ..Default::default() }
+    }
+
+    fn emitter(placement: Placement) -> AmbientEmitter {
+        AmbientEmitter {
+            sound: "loop.ogg".into(),
+            volume: 1.,
+            placement,
+            max_distance: 100.,
+            rolloff: Rolloff::Linear,
+        }
+    }
+
+    fn at(x: f32, y: f32) -> AmbientEmitter {
+        emitter(Placement::At(na::Vector2::new(x, y)))
+    }
+
+    #[test]
+    fn attenuation_falls_off() {
+        assert!(Rolloff::Linear.attenuation(0., 100.) == 1.);
+        assert!(Rolloff::Linear.attenuation(50., 100.) == 0.5);
+        assert!(Rolloff::Quadratic.attenuation(50., 100.) == 0.25);
+        assert!(Rolloff::Linear.attenuation(100., 100.) == 0.);
+        assert!(Rolloff::Quadratic.attenuation(150., 100.) == 0.);
+    }
+
+    #[test]
+    fn mix_follows_listener() {
+        let waterfall = at(-50., 0.);
+        let mix = waterfall.mix(&[], na::Vector2::zeros());
+        assert!(mix.volume == 0.5);
+        assert!(mix.pan == -0.5);
+        let mix = waterfall.mix(&[], na::Vector2::new(-100., 0.));
+        assert!(mix.pan == 0.5);
+        // Too far to hear, but still panned all the way.
+        let mix = waterfall.mix(&[], na::Vector2::new(200., 0.));
+        assert!(mix.volume == 0. && mix.pan == -1.);
+
+        let wind = emitter(Placement::Global);
+        assert!(wind.mix(&[], na::Vector2::new(1000., 1000.)) == Mix { volume: 1., pan: 0. });
+    }
+
+    #[test]
+    fn platform_emitters_ride_along() {
+        let machinery = emitter(Placement::Platform { index: 1, offset: na::Vector2::new(10., 0.) });
+        let mut origins = vec![na::Vector2::zeros(), na::Vector2::new(100., 50.)];
+        assert!(machinery.position(&origins) == Some(na::Vector2::new(110., 50.)));
+        origins[1][0] += 30.;
+        assert!(machinery.position(&origins) == Some(na::Vector2::new(140., 50.)));
+        assert!(machinery.position(&origins[..1]).is_none());
+    }
+
+    #[test]
+    fn nearest_emitters_win() {
+        let emitters = vec![at(90., 0.), emitter(Placement::Global), at(10., 0.), at(50., 0.), at(500., 0.)];
+        let distances: Vec<_> = emitters.iter().map(|e| e.distance(&[], na::Vector2::zeros())).collect();
+        assert!(select_nearest(&emitters, &distances, 10) == vec![1, 2, 3, 0]);
+        assert!(select_nearest(&emitters, &distances, 2) == vec![1, 2]);
+        assert!(select_nearest(&emitters, &distances, 0).is_empty());
+    }
+
+    #[test]
+    fn ambience_swaps_emitters_as_listener_moves() {
+        let backend = MockAudio::default();
+        let mut ambience = Ambience::new(vec![at(0., 0.), at(80., 0.)], Box::new(backend.clone()), 1);
+        ambience.update(na::Vector2::new(10., 0.), &[]);
+        assert!(backend.active_sources() == 1);
+        assert!(backend.playing.borrow().contains_key(&0));
+        ambience.update(na::Vector2::new(70., 0.), &[]);
+        assert!(backend.active_sources() == 1);
+        let mix = backend.playing.borrow()[&1].unwrap();
+        assert!((mix.volume - 0.9).abs() < 1e-5 && (mix.pan - 0.1).abs() < 1e-5);
+    }
+
+    #[test]
+    fn dropping_stops_everything() {
+        let backend = MockAudio::default();
+        let emitters = vec![emitter(Placement::Global), at(0., 0.), at(20., 0.)];
+        let mut ambience = Ambience::new(emitters, Box::new(backend.clone()), 8);
+        ambience.update(na::Vector2::zeros(), &[]);
+        assert!(backend.active_sources() == 3);
+        ambience.stop();
+        assert!(backend.active_sources() == 0);
+        ambience.update(na::Vector2::zeros(), &[]);
+        drop(ambience);
+        assert!(backend.active_sources() == 0);
+    }
+
+    #[test]
+    fn events_play_their_sounds() {
+        let bank = MockSounds { available: vec!["land", "hurt"], ..Default::default() };
+        let mut audio = AudioManager::new(Box::new(bank.clone()), &audio_settings(0.5, 1., 0.5));
+        audio.handle_events(&[SoundEvent::Land, SoundEvent::Attack, SoundEvent::Hurt, SoundEvent::Attack]);
+        // Attacks have no sound, which is only warned about once.
+        assert!(*bank.log.borrow() == vec!["sfx land 0.25", "sfx hurt 0.25"]);
+        assert!(audio.missing.len() == 1 && audio.missing.contains("attack"));
+    }
+
+    #[test]
+    fn soundtrack_follows_screen() {
+        let bank = MockSounds { available: vec!["theme", "finale"], ..Default::default() };
+        let mut audio = AudioManager::new(Box::new(bank.clone()), &audio_settings(1., 0.5, 1.));
+        audio.set_soundtrack(Some("theme"));
+        audio.set_soundtrack(Some("theme"));
+        audio.set_soundtrack(None);
+        audio.set_soundtrack(None);
+        audio.set_soundtrack(Some("finale"));
+        audio.set_soundtrack(Some("missing"));
+        audio.set_soundtrack(Some("missing"));
+        assert!(*bank.log.borrow() == vec!["music theme true 0.5", "stop", "music finale true 0.5", "stop"]);
+    }
+
+    #[test]
+    fn volumes_changed_while_playing() {
+        let bank = MockSounds { available: vec!["theme", "land"], ..Default::default() };
+        let mut audio = AudioManager::new(Box::new(bank.clone()), &audio_settings(1., 0.5, 1.));
+        audio.set_volumes(&audio_settings(1., 0.25, 0.5));
+        audio.set_soundtrack(Some("theme"));
+        audio.set_volumes(&audio_settings(0.5, 1., 0.5));
+        audio.handle_events(&[SoundEvent::Land]);
+        assert!(*bank.log.borrow() == vec!["music theme true 0.25", "volume 0.5", "sfx land 0.25"]);
+    }
+}
@@ -0,0 +1,520 @@
+//! Debug drawing, split into channels that can be toggled independently at runtime.
+//!
+//! | Key | Console name | Channel                               |
+//! |-----|--------------|---------------------------------------|
+//! | F1  | `hurtboxes`  | Hurtboxes                             |
+//! | F2  | `contacts`   | Contacts from the last collision pass |
+//! | F3  | `velocities` | Velocities                            |
+//! | F4  | `trails`     | Trails of where players have been     |
+//! | F9  | `attacks`    | Attack boxes currently out            |
+//! | F10 | `platforms`  | Platform bodies                       |
+//! |     | `camera`     | The camera's view                     |
+//! |     | `blastzones` | Blast zones                           |
+//! |     | `zones`      | Named trigger zones                   |
+//! |     | `texts`      | Text cache entries, hits and misses   |
+//! | F12 | `all`        | All on/all off                        |
+//!
+//! In debug builds, `CONSOLE_KEY` opens a console, where e.g. `draw zones` toggles a channel and
+//! `draw zones off` turns it off. The console also takes the simulation commands in `timing`,
+//! e.g. `speed 0.5` or `until tick 600`.
+use ggez::{Context, GameResult};
+use ggez::event::KeyCode;
+use ggez::graphics::{self, Color, DrawMode, Drawable, DrawParam, Mesh, MeshBuilder, Text};
+use ggez::nalgebra as na;
+use serde::{Serialize, Deserialize};
+
+use crate::{
+    physics::{self, BoundingBox},
+    settings,
+    timing::SimCommand,
+    viewport,
+};
+
+/// The key that turns every channel on, or off if they are all on already.
+pub const TOGGLE_ALL_KEY: KeyCode = KeyCode::F12;
+/// Opens and closes the debug console.
+pub const CONSOLE_KEY: KeyCode = KeyCode::Grave;
+
+/// A category of debug drawing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DebugChannel {
+    /// The bounding boxes of players.
+    Hurtboxes,
+    /// The bounding boxes found to overlap during the last collision pass.
+    Contacts,
+    /// Arrows showing the velocity of players, scaled by magnitude.
+    Velocities,
+    /// The paths players took over the last few seconds, colored by speed.
+    Trails,
+    /// The boxes of attacks that are out and can hit.
+    Attacks,
+    /// The bodies of platforms, whatever they're drawn as.
+    Platforms,
+    /// What the camera shows, and the players it's framing.
+    CameraView,
+    /// Where players are knocked out.
+    BlastZones,
+    /// The arena's named trigger zones, which set off effects in an area.
+    AoeZones,
+    /// How many texts are cached, and how often they were found there.
+    TextCache,
+}
+
+impl DebugChannel {
+    pub const ALL: [DebugChannel; 10] = [
+        DebugChannel::Hurtboxes,
+        DebugChannel::Contacts,
+        DebugChannel::Velocities,
+        DebugChannel::Trails,
+        DebugChannel::Attacks,
+        DebugChannel::Platforms,
+        DebugChannel::CameraView,
+        DebugChannel::BlastZones,
+        DebugChannel::AoeZones,
+        DebugChannel::TextCache,
+    ];
+
+    pub fn color(self) -> Color {
+        match self {
+            DebugChannel::Hurtboxes => Color::from_rgba(255, 0, 0, 130),
+            DebugChannel::Contacts => Color::from_rgba(255, 255, 0, 160),
+            DebugChannel::Velocities => Color::from_rgba(0, 255, 255, 255),
+            // Trails are colored by speed instead.
+            DebugChannel::Trails => Color::from_rgba(255, 255, 255, 255),
+            DebugChannel::Attacks => Color::from_rgba(255, 120, 0, 160),
+            DebugChannel::Platforms => Color::from_rgba(0, 200, 80, 130),
+            DebugChannel::CameraView => Color::from_rgba(255, 0, 255, 255),
+            DebugChannel::BlastZones => Color::from_rgba(120, 0, 0, 90),
+            DebugChannel::AoeZones => Color::from_rgba(80, 120, 255, 110),
+            DebugChannel::TextCache => Color::from_rgba(255, 255, 255, 255),
+        }
+    }
+
+    /// The key toggling the channel. Channels without one are toggled from the console.
+    pub fn toggle_key(self) -> Option<KeyCode> {
+        match self {
+            DebugChannel::Hurtboxes => Some(KeyCode::F1),
+            DebugChannel::Contacts => Some(KeyCode::F2),
+            DebugChannel::Velocities => Some(KeyCode::F3),
+            DebugChannel::Trails => Some(KeyCode::F4),
+            DebugChannel::Attacks => Some(KeyCode::F9),
+            DebugChannel::Platforms => Some(KeyCode::F10),
+            DebugChannel::CameraView
+            | DebugChannel::BlastZones
+            | DebugChannel::AoeZones
+            | DebugChannel::TextCache => None,
+        }
+    }
+
+    pub fn from_toggle_key(key: KeyCode) -> Option<Self> {
+        Self::ALL.iter().cloned().find(|channel| channel.toggle_key() == Some(key))
+    }
+
+    /// The channel's name in the console.
+    pub fn name(self) -> &'static str {
+        match self {
+            DebugChannel::Hurtboxes => "hurtboxes",
+            DebugChannel::Contacts => "contacts",
+            DebugChannel::Velocities => "velocities",
+            DebugChannel::Trails => "trails",
+            DebugChannel::Attacks => "attacks",
+            DebugChannel::Platforms => "platforms",
+            DebugChannel::CameraView => "camera",
+            DebugChannel::BlastZones => "blastzones",
+            DebugChannel::AoeZones => "zones",
+            DebugChannel::TextCache => "texts",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().cloned().find(|channel| channel.name() == name)
+    }
+}
+
+/// The set of enabled debug channels.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DebugChannels {
+    enabled: Vec<DebugChannel>,
+}
+
+impl DebugChannels {
+    /// The channels saved in the settings are only honored in debug builds.
+    pub fn from_settings(debug_draw: &settings::DebugDraw) -> Self {
+        let mut channels = Self::default();
+        let saved = if cfg!(debug_assertions) {
+            debug_draw.channels.clone()
+        } else {
+            settings::DebugDraw::default().channels
+        };
+        for channel in saved.iter().filter_map(settings::Lenient::valid) {
+            channels.set(*channel, true);
+        }
+        channels
+    }
+
+    pub fn is_enabled(&self, channel: DebugChannel) -> bool {
+        self.enabled.contains(&channel)
+    }
+
+    pub fn set(&mut self, channel: DebugChannel, enabled: bool) {
+        self.enabled.retain(|c| *c != channel);
+        if enabled {
+            self.enabled.push(channel);
+        }
+    }
+
+    pub fn toggle(&mut self, channel: DebugChannel) {
+        let enabled = self.is_enabled(channel);
+        self.set(channel, !enabled);
+    }
+
+    /// Turns every channel on, or off if they are all on already.
+    pub fn toggle_all(&mut self) {
+        let all_enabled = DebugChannel::ALL.iter().all(|c| self.is_enabled(*c));
+        for channel in DebugChannel::ALL.iter() {
+            self.set(*channel, !all_enabled);
+        }
+    }
+
+    /// Sets `channel`, or every channel if there's none, to `enabled`. Toggles it if that's
+    /// `None`.
+    pub fn apply(&mut self, channel: Option<DebugChannel>, enabled: Option<bool>) {
+        match (channel, enabled) {
+            (Some(channel), Some(enabled)) => self.set(channel, enabled),
+            (Some(channel), None) => self.toggle(channel),
+            (None, Some(enabled)) => {
+                for channel in DebugChannel::ALL.iter() {
+                    self.set(*channel, enabled);
+                }
+            },
+            (None, None) => self.toggle_all(),
+        }
+    }
+
+    /// The names of the enabled channels, for the console.
+    pub fn describe(&self) -> String {
+        let names: Vec<_> = self.enabled.iter().map(|channel| channel.name()).collect();
+        if names.is_empty() {
+            "Drawing nothing.".to_owned()
+        } else {
+            format!("Drawing {}.", names.join(", "))
+        }
+    }
+
+    /// Handles a debug key. Returns whether the key was used.
+    pub fn handle_key(&mut self, key: KeyCode) -> bool {
+        if key == TOGGLE_ALL_KEY {
+            self.toggle_all();
+        } else if let Some(channel) = DebugChannel::from_toggle_key(key) {
+            self.toggle(channel);
+        } else {
+            return false;
+        }
+        log::debug!("Debug channels: {:?}", self.enabled);
+        true
+    }
+}
+
+/// A command typed into the debug console.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConsoleCommand {
+    /// Sets a channel, or every channel if there's none, on or off. Toggles it if neither is
+    /// given.
+    Draw { channel: Option<DebugChannel>, enabled: Option<bool> },
+    /// Controls how fast the simulation runs.
+    Sim(SimCommand),
+}
+
+impl ConsoleCommand {
+    /// Parses commands such as `draw attacks`, `draw zones off` or `draw all`, and anything
+    /// `SimCommand::parse` does.
+    pub fn parse(command: &str) -> Result<Self, String> {
+        let words: Vec<_> = command.split_whitespace().collect();
+        match words.as_slice() {
+            ["draw", channel, rest @ ..] => {
+                let channel = match *channel {
+                    "all" => None,
+                    name => Some(DebugChannel::from_name(name).ok_or_else(|| {
+                        let names: Vec<_> = DebugChannel::ALL.iter().map(|channel| channel.name()).collect();
+                        format!("Unknown channel `{}`. Channels: all, {}", name, names.join(", "))
+                    })?),
+                };
+                let enabled = match rest {
+                    [] => None,
+                    ["on"] => Some(true),
+                    ["off"] => Some(false),
+                    _ => Err(format!("Expected `on` or `off` after the channel in `{}`.", command))?,
+                };
+                Ok(ConsoleCommand::Draw { channel, enabled })
+            },
+            _ => SimCommand::parse(command).map(ConsoleCommand::Sim),
+        }
+    }
+}
+
+/// A line typed into in debug builds to run `ConsoleCommand`s, shown at the top of the screen
+/// while open.
+#[derive(Debug, Default)]
+pub struct DebugConsole {
+    open: bool,
+    line: String,
+    /// What the last command did, or why it didn't.
+    reply: String,
+    /// The line and reply, rebuilt whenever either changes.
+    text: Text,
+}
+
+impl DebugConsole {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.refresh();
+    }
+
+    /// Adds a typed character to the line. The console key's own character is left out.
+    pub fn type_char(&mut self, ch: char) {
+        if self.open && !ch.is_control() && ch != '`' {
+            self.line.push(ch);
+            self.refresh();
+        }
+    }
+
+    /// Edits the line with `key`. Returns the line once Enter submits it.
+    pub fn handle_key(&mut self, key: KeyCode) -> Option<String> {
+        match key {
+            KeyCode::Back => {
+                self.line.pop();
+                self.refresh();
+                None
+            },
+            KeyCode::Return | KeyCode::NumpadEnter => {
+                let line = std::mem::take(&mut self.line);
+                self.refresh();
+                Some(line)
+            },
+            _ => None,
+        }
+    }
+
+    pub fn set_reply(&mut self, reply: String) {
+        self.reply = reply;
+        self.refresh();
+    }
+
+    fn refresh(&mut self) {
+        self.text = Text::new(format!("> {}_\n{}", self.line, self.reply));
+    }
+
+    /// Draws the console across the top of the virtual screen, if it's open.
+    pub fn draw(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
+        const MARGIN: f32 = 8.;
+        if !self.open {
+            return Ok(());
+        }
+        let height = self.text.height(ctx) as f32 + 2. * MARGIN;
+        let backdrop = graphics::Rect::new(0., 0., viewport::VIRTUAL_WIDTH, height);
+        let backdrop = Mesh::new_rectangle(ctx, DrawMode::fill(), backdrop, Color::from_rgba(0, 0, 0, 200))?;
+        backdrop.draw(ctx, param)?;
+        let mut text_param = param;
+        text_param.dest.x += MARGIN;
+        text_param.dest.y += MARGIN;
+        self.text.draw(ctx, text_param)
+    }
+}
+
+/// Keeps the contacts from the last collision pass around for drawing.
+#[derive(Debug, Default)]
+pub struct ContactLog {
+    enabled: bool,
+    /// Pairs of overlapping `BoundingBox`es, in world coordinates.
+    contacts: Vec<(BoundingBox, BoundingBox)>,
+}
+
+impl ContactLog {
+    /// Starts a new collision pass. Contacts are only retained when `enabled`.
+    pub fn begin_pass(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.contacts.clear();
+    }
+
+    /// Records contacts. `find_contacts` is not called at all when retention is disabled.
+    pub fn record<F>(&mut self, find_contacts: F)
+    where
+        F: FnOnce() -> Vec<(BoundingBox, BoundingBox)>,
+    {
+        if self.enabled {
+            self.contacts.append(&mut find_contacts());
+        }
+    }
+
+    pub fn contacts(&self) -> &[(BoundingBox, BoundingBox)] {
+        &self.contacts
+    }
+}
+
+/// Builds an arrow starting at `origin` and pointing along `vector`.
+pub fn arrow_mesh(
+    ctx: &mut Context,
+    origin: na::Point2<f32>,
+    vector: na::Vector2<f32>,
+    color: Color,
+) -> GameResult<Mesh> {
+    const WIDTH: f32 = 2.;
+    const HEAD_SIZE: f32 = 6.;
+
+    let tip = origin + vector;
+    let back = -vector.normalize() * HEAD_SIZE;
+    let side = na::Vector2::new(-back[1], back[0]) / 2.;
+    let point = |p: na::Point2<f32>| physics::screen_point(p.coords);
+
+    MeshBuilder::new()
+        .line(&[point(origin), point(tip)], WIDTH, color)?
+        .line(&[point(tip + back + side), point(tip), point(tip + back - side)], WIDTH, color)?
+        .build(ctx)
+}
+
+#[cfg(test)]
+mod debug_test {
+    use super::*;
+    use crate::physics::HitboxTag;
+    type V2 = na::Vector2<f32>;
+
+    #[test]
+    fn channel_toggle() {
+        let mut channels = DebugChannels::default();
+        assert!(!channels.is_enabled(DebugChannel::Contacts));
+        channels.toggle(DebugChannel::Contacts);
+        assert!(channels.is_enabled(DebugChannel::Contacts));
+        assert!(!channels.is_enabled(DebugChannel::Velocities));
+        channels.toggle(DebugChannel::Contacts);
+        assert!(!channels.is_enabled(DebugChannel::Contacts));
+    }
+
+    #[test]
+    fn channel_set_is_idempotent() {
+        let mut channels = DebugChannels::default();
+        channels.set(DebugChannel::Hurtboxes, true);
+        channels.set(DebugChannel::Hurtboxes, true);
+        channels.set(DebugChannel::Hurtboxes, false);
+        assert!(!channels.is_enabled(DebugChannel::Hurtboxes));
+    }
+
+    #[test]
+    fn channel_toggle_all() {
+        let mut channels = DebugChannels::default();
+        channels.set(DebugChannel::Hurtboxes, true);
+        channels.toggle_all();
+        assert!(DebugChannel::ALL.iter().all(|c| channels.is_enabled(*c)));
+        channels.toggle_all();
+        assert!(DebugChannel::ALL.iter().all(|c| !channels.is_enabled(*c)));
+    }
+
+    #[test]
+    fn channel_keys() {
+        let mut channels = DebugChannels::default();
+        for channel in DebugChannel::ALL.iter() {
+            if let Some(key) = channel.toggle_key() {
+                assert!(DebugChannel::from_toggle_key(key) == Some(*channel));
+                assert!(channels.handle_key(key));
+                assert!(channels.is_enabled(*channel));
+            }
+        }
+        assert!(channels.handle_key(TOGGLE_ALL_KEY));
+        assert!(DebugChannel::ALL.iter().all(|c| channels.is_enabled(*c)));
+        assert!(!channels.handle_key(KeyCode::A));
+    }
+
+    #[test]
+    fn channels_applied() {
+        let mut channels = DebugChannels::default();
+        channels.apply(Some(DebugChannel::AoeZones), None);
+        channels.apply(Some(DebugChannel::Trails), Some(true));
+        channels.apply(Some(DebugChannel::Trails), Some(true));
+        assert!(channels.describe() == "Drawing zones, trails.");
+        channels.apply(None, Some(false));
+        assert!(channels.describe() == "Drawing nothing.");
+        channels.apply(None, None);
+        assert!(DebugChannel::ALL.iter().all(|c| channels.is_enabled(*c)));
+    }
+
+    #[test]
+    fn channel_names() {
+        for channel in DebugChannel::ALL.iter() {
+            assert!(DebugChannel::from_name(channel.name()) == Some(*channel));
+        }
+        assert!(DebugChannel::from_name("all").is_none());
+    }
+
+    #[test]
+    fn console_commands_parsed() {
+        assert!(ConsoleCommand::parse("draw zones") == Ok(ConsoleCommand::Draw {
+            channel: Some(DebugChannel::AoeZones),
+            enabled: None,
+        }));
+        assert!(ConsoleCommand::parse("  draw  attacks on ") == Ok(ConsoleCommand::Draw {
+            channel: Some(DebugChannel::Attacks),
+            enabled: Some(true),
+        }));
+        assert!(ConsoleCommand::parse("draw all off") == Ok(ConsoleCommand::Draw { channel: None, enabled: Some(false) }));
+        assert!(ConsoleCommand::parse("draw hitboxes").is_err());
+        assert!(ConsoleCommand::parse("draw zones maybe").is_err());
+        assert!(ConsoleCommand::parse("draw").is_err());
+        assert!(ConsoleCommand::parse("").is_err());
+    }
+
+    #[test]
+    fn console_takes_sim_commands() {
+        use crate::timing::{RunUntil, SimSpeed};
+
+        assert!(ConsoleCommand::parse("speed 0.5") == Ok(ConsoleCommand::Sim(SimCommand::Speed(SimSpeed::Half))));
+        assert!(ConsoleCommand::parse("until tick 600") == Ok(ConsoleCommand::Sim(SimCommand::RunUntil(RunUntil::Tick(600)))));
+        assert!(ConsoleCommand::parse("speed 3").is_err());
+    }
+
+    #[test]
+    fn console_line_edited_and_submitted() {
+        let mut console = DebugConsole::default();
+        console.type_char('d');
+        assert!(console.handle_key(KeyCode::Return) == Some(String::new()));
+        console.toggle();
+        for ch in "`draw zonesx\r".chars() {
+            console.type_char(ch);
+        }
+        assert!(console.handle_key(KeyCode::Back).is_none());
+        assert!(console.handle_key(KeyCode::Return) == Some("draw zones".to_owned()));
+        assert!(console.handle_key(KeyCode::Return) == Some(String::new()));
+    }
+
+    fn contact() -> (BoundingBox, BoundingBox) {
+        let bbox = BoundingBox {
+            mode: None,
+            tag: HitboxTag::Solid,
+            pos: V2::zeros(),
+            size: V2::new(1., 1.),
+            ori: 0.,
+        };
+        (bbox.clone(), bbox)
+    }
+
+    #[test]
+    fn contact_log_disabled() {
+        let mut log = ContactLog::default();
+        log.begin_pass(false);
+        log.record(|| panic!("Contacts should not be computed while disabled."));
+        assert!(log.contacts().is_empty());
+    }
+
+    #[test]
+    fn contact_log_enabled() {
+        let mut log = ContactLog::default();
+        log.begin_pass(true);
+        log.record(|| vec![contact()]);
+        log.record(|| vec![contact(), contact()]);
+        assert!(log.contacts().len() == 3);
+        log.begin_pass(true);
+        assert!(log.contacts().is_empty());
+    }
+}
@@ -1,8 +1,292 @@
 use ggez::Context;
-use ggez::event::{KeyCode, KeyMods};
+use ggez::event::{Axis, Button, KeyCode, KeyMods};
+use std::collections::VecDeque;
 
-pub type Input = (KeyCode, KeyMods);
+pub mod keys;
+pub mod layout;
+
+/// A key along with the modifiers held when it was pressed.
+pub type KeyPress = (KeyCode, KeyMods);
+
+/// Numbers gamepads in the order they were first used. `ggez`'s own ids can't be made outside of
+/// it, so this is what the rest of the game refers to gamepads by.
+pub type PadId = usize;
+
+/// Something done on any input device.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Input {
+    Key(KeyCode, KeyMods),
+    Button(Button, PadId),
+    /// A stick moved to the given position along an axis, from -1 to 1.
+    Axis(Axis, f32, PadId),
+}
+
+/// An input an action can be bound to. Gamepad bindings are read from whichever gamepad the player
+/// was given.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Binding {
+    Keyboard(KeyCode, KeyMods),
+    GamepadButton(Button),
+    /// A stick pushed at least this far along an axis, from -1 to 1. Negative thresholds are
+    /// pushed the other way.
+    GamepadAxis(Axis, f32),
+}
+
+impl Binding {
+    /// Whether `input` sets off the binding. Gamepad inputs only count if they come from `pad`.
+    pub fn is_pressed_by(&self, input: &Input, pad: Option<PadId>) -> bool {
+        match (*self, *input) {
+            (Binding::Keyboard(key, mods), Input::Key(pressed, pressed_mods)) => key == pressed && mods == pressed_mods,
+            (Binding::GamepadButton(button), Input::Button(pressed, from)) => Some(from) == pad && button == pressed,
+            (Binding::GamepadAxis(axis, threshold), Input::Axis(moved, value, from)) => {
+                Some(from) == pad && axis == moved && is_past(value, threshold)
+            },
+            _ => false,
+        }
+    }
+
+    /// Whether `input` lets go of the binding. Modifiers are ignored since they may have been let
+    /// go of first.
+    pub fn is_released_by(&self, input: &Input, pad: Option<PadId>) -> bool {
+        match (*self, *input) {
+            (Binding::Keyboard(key, _), Input::Key(released, _)) => key == released,
+            (Binding::GamepadButton(button), Input::Button(released, from)) => Some(from) == pad && button == released,
+            _ => false,
+        }
+    }
+
+    /// Whether the binding is being held down, given the keys held along with the modifiers and
+    /// the state of the player's gamepad.
+    pub fn is_held(&self, keys: &[KeyPress], pad: Option<&PadState>) -> bool {
+        match *self {
+            Binding::Keyboard(key, mods) => keys.contains(&(key, mods)),
+            Binding::GamepadButton(button) => pad.iter().any(|pad| pad.buttons.contains(&button)),
+            Binding::GamepadAxis(axis, threshold) => pad.iter().any(|pad| is_past(pad.axis(axis), threshold)),
+        }
+    }
+}
+
+/// Whether a stick at `value` is pushed past `threshold`, in the direction of its sign.
+fn is_past(value: f32, threshold: f32) -> bool {
+    if threshold < 0. {
+        value <= threshold
+    } else {
+        value >= threshold
+    }
+}
+
+/// What is being held on one gamepad. Only changes are reported, so they are kept track of here.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PadState {
+    pub pad: PadId,
+    buttons: Vec<Button>,
+    /// Where each stick that has moved was last moved to.
+    axes: Vec<(Axis, f32)>,
+}
+
+impl PadState {
+    pub fn new(pad: PadId) -> Self {
+        PadState { pad, buttons: vec![], axes: vec![] }
+    }
+
+    /// Takes in the presses and stick moves made on this gamepad.
+    pub fn press(&mut self, pressed: &[Input]) {
+        for input in pressed {
+            match *input {
+                Input::Button(button, pad) if pad == self.pad && !self.buttons.contains(&button) => {
+                    self.buttons.push(button);
+                },
+                Input::Axis(axis, value, pad) if pad == self.pad => {
+                    self.axes.retain(|(moved, _)| *moved != axis);
+                    self.axes.push((axis, value));
+                },
+                _ => (),
+            }
+        }
+    }
+
+    /// Takes in the buttons let go of on this gamepad.
+    pub fn release(&mut self, released: &[Input]) {
+        for input in released {
+            if let Input::Button(button, pad) = *input {
+                if pad == self.pad {
+                    self.buttons.retain(|held| *held != button);
+                }
+            }
+        }
+    }
+
+    /// Where `axis` was last moved to. Sticks start out centered.
+    pub fn axis(&self, axis: Axis) -> f32 {
+        self.axes.iter()
+            .find(|(moved, _)| *moved == axis)
+            .map_or(0., |(_, value)| *value)
+    }
+}
 
 pub trait HandleInput {
     fn handle_input(&mut self, ctx: &mut Context, fire_once_key_buffer: &Vec<Input>);
+    /// Handles the keys released since the last tick.
+    fn handle_key_up(&mut self, _ctx: &mut Context, _released_key_buffer: &Vec<Input>) {}
+}
+
+/// Key presses and releases waiting to be handled.
+///
+/// ## Tick assignment
+/// Inputs are handled once per update window. Every event is assigned to exactly one window when
+/// it arrives: the first window processed after it. Each window only sees the events assigned to
+/// it, so when an update catches up by running several windows in a row, the presses that arrived
+/// before the update are all handled on the first of them and none are seen twice. Since the
+/// assignment only depends on the order events and windows come in, replaying that order assigns
+/// every event to the same window again.
+#[derive(Debug, Default)]
+pub struct InputQueue {
+    /// The window events arriving now are assigned to.
+    next_window: u64,
+    pressed: VecDeque<(Input, u64)>,
+    released: VecDeque<(Input, u64)>,
+}
+
+impl InputQueue {
+    pub fn press(&mut self, input: Input) {
+        self.pressed.push_back((input, self.next_window));
+    }
+
+    pub fn release(&mut self, input: Input) {
+        self.released.push_back((input, self.next_window));
+    }
+
+    /// Starts the next update window, returning the presses and releases assigned to it.
+    pub fn begin_window(&mut self) -> (Vec<Input>, Vec<Input>) {
+        let window = self.next_window;
+        self.next_window += 1;
+        (Self::take_until(&mut self.pressed, window), Self::take_until(&mut self.released, window))
+    }
+
+    fn take_until(events: &mut VecDeque<(Input, u64)>, window: u64) -> Vec<Input> {
+        let due = events.iter().take_while(|(_, assigned)| *assigned <= window).count();
+        events.drain(..due).map(|(input, _)| input).collect()
+    }
+}
+
+/// Presses from the last few update windows, each with the window it was handled on. Lets inputs be
+/// read together with ones made shortly before them, like a direction tapped just before attacking.
+#[derive(Debug, Default)]
+pub struct InputBuffer {
+    buffer: VecDeque<(Input, u64)>,
+    /// The window being handled.
+    frame: u64,
+}
+
+impl InputBuffer {
+    /// The number of windows presses are remembered for.
+    pub const LENGTH: u64 = 30;
+
+    /// Moves on to the next window, remembering `pressed` as pressed on it.
+    pub fn advance(&mut self, pressed: &[Input]) {
+        self.frame += 1;
+        let frame = self.frame;
+        self.buffer.extend(pressed.iter().map(|input| (*input, frame)));
+        while let Some((_, pressed_on)) = self.buffer.front() {
+            if frame - pressed_on < Self::LENGTH {
+                break;
+            }
+            self.buffer.pop_front();
+        }
+    }
+
+    /// The window being handled, counting from 1.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Whether anything `matches` was pressed on the current window or the `frames - 1` before it.
+    /// Inputs are matched rather than compared, since a binding can be pressed by more than one,
+    /// e.g. a stick pushed any distance past its deadzone.
+    pub fn any_pressed_within<F: Fn(&Input) -> bool>(&self, frames: u64, matches: F) -> bool {
+        self.buffer.iter()
+            .rev()
+            .take_while(|(_, pressed_on)| self.frame - pressed_on < frames)
+            .any(|(input, _)| matches(input))
+    }
+}
+
+#[cfg(test)]
+mod input_queue_test {
+    use super::*;
+
+    const A: Input = Input::Key(KeyCode::A, KeyMods::NONE);
+    const B: Input = Input::Key(KeyCode::B, KeyMods::NONE);
+    const C: Input = Input::Button(Button::South, 0);
+
+    #[test]
+    fn catch_up_windows_see_each_press_once() {
+        let mut queue = InputQueue::default();
+        // Pressed before the first update, which then catches up by 3 windows.
+        queue.press(A);
+        queue.press(B);
+        assert!(queue.begin_window() == (vec![A, B], vec![]));
+        // Arrives partway through the catch-up.
+        queue.press(C);
+        queue.release(A);
+        assert!(queue.begin_window() == (vec![C], vec![A]));
+        assert!(queue.begin_window() == (vec![], vec![]));
+    }
+
+    #[test]
+    fn repeated_presses_stay_separate() {
+        let mut queue = InputQueue::default();
+        queue.press(A);
+        queue.release(A);
+        queue.press(A);
+        assert!(queue.begin_window() == (vec![A, A], vec![A]));
+        queue.begin_window();
+        queue.press(A);
+        assert!(queue.begin_window() == (vec![A], vec![]));
+    }
+
+    #[test]
+    fn same_order_same_windows() {
+        let play = || {
+            let mut queue = InputQueue::default();
+            let mut handled = vec![];
+            for window in 0..6 {
+                if window % 2 == 0 {
+                    queue.press(A);
+                }
+                if window == 3 {
+                    queue.press(B);
+                    queue.press(C);
+                }
+                handled.push(queue.begin_window().0);
+            }
+            handled
+        };
+        let handled = play();
+        assert!(handled == play());
+        assert!(handled[3] == vec![B, C]);
+        assert!(handled.iter().map(Vec::len).sum::<usize>() == 5);
+    }
+
+    #[test]
+    fn buffered_presses_expire() {
+        let was_pressed_within = |buffer: &InputBuffer, input: Input, frames| {
+            buffer.any_pressed_within(frames, |pressed| *pressed == input)
+        };
+        let mut buffer = InputBuffer::default();
+        buffer.advance(&[A, B]);
+        assert!(was_pressed_within(&buffer, A, 1));
+        buffer.advance(&[]);
+        buffer.advance(&[C]);
+        // Pressed 2 windows ago, so within the last 3 but not the last 2.
+        assert!(was_pressed_within(&buffer, A, 3));
+        assert!(!was_pressed_within(&buffer, A, 2));
+        assert!(was_pressed_within(&buffer, C, 1));
+        assert!(!was_pressed_within(&buffer, C, 0));
+        for _ in 0..InputBuffer::LENGTH {
+            buffer.advance(&[]);
+        }
+        assert!(!buffer.any_pressed_within(u64::MAX, |_| true));
+        assert!(buffer.buffer.is_empty());
+    }
 }
@@ -0,0 +1,150 @@
+//! Names of keys for writing them in the settings file, e.g. `"Shift + A"`.
+//!
+//! Names are those of the `KeyCode`s, so they name positions on a QWERTY keyboard whatever the
+//! player's layout is. Parsing ignores case and the spaces around `+`.
+use std::fmt;
+use std::str::FromStr;
+
+use ggez::event::{KeyCode, KeyMods};
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de;
+
+use super::KeyPress;
+
+/// Modifiers in the order they are written.
+const MODIFIERS: [(KeyMods, &str); 4] = [
+    (KeyMods::CTRL, "Ctrl"),
+    (KeyMods::ALT, "Alt"),
+    (KeyMods::SHIFT, "Shift"),
+    (KeyMods::LOGO, "Super"),
+];
+
+/// Every key that can be bound, with its name.
+const KEYS: &[(KeyCode, &str)] = {
+    use KeyCode::*;
+    &[
+        (A, "A"), (B, "B"), (C, "C"), (D, "D"), (E, "E"), (F, "F"), (G, "G"), (H, "H"), (I, "I"),
+        (J, "J"), (K, "K"), (L, "L"), (M, "M"), (N, "N"), (O, "O"), (P, "P"), (Q, "Q"), (R, "R"),
+        (S, "S"), (T, "T"), (U, "U"), (V, "V"), (W, "W"), (X, "X"), (Y, "Y"), (Z, "Z"),
+        (Key0, "0"), (Key1, "1"), (Key2, "2"), (Key3, "3"), (Key4, "4"),
+        (Key5, "5"), (Key6, "6"), (Key7, "7"), (Key8, "8"), (Key9, "9"),
+        (F1, "F1"), (F2, "F2"), (F3, "F3"), (F4, "F4"), (F5, "F5"), (F6, "F6"),
+        (F7, "F7"), (F8, "F8"), (F9, "F9"), (F10, "F10"), (F11, "F11"), (F12, "F12"),
+        (Up, "Up"), (Down, "Down"), (Left, "Left"), (Right, "Right"),
+        (Space, "Space"), (Return, "Enter"), (Tab, "Tab"), (Back, "Backspace"), (Escape, "Escape"),
+        (Insert, "Insert"), (Delete, "Delete"), (Home, "Home"), (End, "End"),
+        (PageUp, "PageUp"), (PageDown, "PageDown"),
+        (LShift, "LShift"), (RShift, "RShift"), (LControl, "LCtrl"), (RControl, "RCtrl"),
+        (LAlt, "LAlt"), (RAlt, "RAlt"),
+        (Minus, "Minus"), (Equals, "Equals"), (LBracket, "LBracket"), (RBracket, "RBracket"),
+        (Backslash, "Backslash"), (Semicolon, "Semicolon"), (Apostrophe, "Apostrophe"),
+        (Grave, "Grave"), (Comma, "Comma"), (Period, "Period"), (Slash, "Slash"),
+    ]
+};
+
+/// The names of every key that can be bound.
+pub fn key_names() -> Vec<&'static str> {
+    KEYS.iter().map(|(_, name)| *name).collect()
+}
+
+pub fn key_name(key: KeyCode) -> Option<&'static str> {
+    KEYS.iter().find(|(code, _)| *code == key).map(|(_, name)| *name)
+}
+
+pub fn key_from_name(name: &str) -> Option<KeyCode> {
+    KEYS.iter().find(|(_, known)| known.eq_ignore_ascii_case(name)).map(|(code, _)| *code)
+}
+
+/// A key along with the modifiers that have to be held with it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyBinding(pub KeyPress);
+
+impl FromStr for KeyBinding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let key_part = parts.pop().unwrap_or("");
+        let key = key_from_name(key_part).ok_or_else(|| format!("Unknown key `{}` in `{}`.", key_part, s))?;
+        let mut mods = KeyMods::NONE;
+        for part in parts {
+            let (modifier, _) = MODIFIERS.iter()
+                .find(|(_, name)| name.eq_ignore_ascii_case(part))
+                .ok_or_else(|| format!("Unknown modifier `{}` in `{}`.", part, s))?;
+            mods |= *modifier;
+        }
+        Ok(KeyBinding((key, mods)))
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (key, mods) = self.0;
+        for (modifier, name) in &MODIFIERS {
+            if mods.contains(*modifier) {
+                write!(f, "{} + ", name)?;
+            }
+        }
+        match key_name(key) {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "{:?}", key),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyBinding {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        raw.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for KeyBinding {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_string().serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod keys_test {
+    use super::*;
+
+    #[test]
+    fn every_key_round_trips() {
+        for (key, name) in KEYS.iter() {
+            assert!(key_from_name(name) == Some(*key));
+            assert!(key_name(*key) == Some(*name));
+            let binding: KeyBinding = name.parse().unwrap();
+            assert!(binding == KeyBinding((*key, KeyMods::NONE)));
+            assert!(binding.to_string() == *name);
+        }
+    }
+
+    #[test]
+    fn names_are_unique() {
+        let mut names: Vec<_> = key_names().iter().map(|name| name.to_lowercase()).collect();
+        names.sort();
+        names.dedup();
+        assert!(names.len() == KEYS.len());
+    }
+
+    #[test]
+    fn modifiers_parse_in_any_order_and_case() {
+        let expected = KeyBinding((KeyCode::A, KeyMods::SHIFT | KeyMods::CTRL));
+        assert!("Shift + A".parse::<KeyBinding>().unwrap() == KeyBinding((KeyCode::A, KeyMods::SHIFT)));
+        assert!("shift+ctrl+a".parse::<KeyBinding>().unwrap() == expected);
+        assert!(" Ctrl +Shift+ A ".parse::<KeyBinding>().unwrap() == expected);
+        assert!(expected.to_string() == "Ctrl + Shift + A");
+        assert!("space".parse::<KeyBinding>().unwrap() == KeyBinding((KeyCode::Space, KeyMods::NONE)));
+    }
+
+    #[test]
+    fn typos_name_the_bad_part() {
+        let error = "Shift + Spcae".parse::<KeyBinding>().unwrap_err();
+        assert!(error.contains("`Spcae`"));
+        let error = "Shfit + A".parse::<KeyBinding>().unwrap_err();
+        assert!(error.contains("`Shfit`"));
+        assert!("".parse::<KeyBinding>().is_err());
+        assert!("Shift +".parse::<KeyBinding>().is_err());
+    }
+}
@@ -0,0 +1,188 @@
+//! Names of keys as they are printed on the player's keyboard.
+//!
+//! `KeyCode`s name the position of a key on a QWERTY keyboard. On other layouts the same position
+//! has a different label, e.g. `KeyCode::A` is the key labelled "Q" on an AZERTY keyboard. Any
+//! text telling the player which key to press should go through `input_label`, or `prompt` when
+//! it says what the key does.
+use std::env;
+
+use ggez::event::{KeyCode, KeyMods};
+use serde::{Serialize, Deserialize};
+
+use super::KeyPress;
+
+/// The keyboard layouts keys can be labelled for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyboardLayout {
+    Qwerty,
+    /// French and Belgian keyboards.
+    Azerty,
+    /// German, Austrian, Swiss and central European keyboards.
+    Qwertz,
+}
+
+impl KeyboardLayout {
+    pub const ALL: [KeyboardLayout; 3] = [
+        KeyboardLayout::Qwerty,
+        KeyboardLayout::Azerty,
+        KeyboardLayout::Qwertz,
+    ];
+
+    /// Guesses the layout from the locale in the environment, defaulting to QWERTY.
+    pub fn guess() -> Self {
+        ["LC_ALL", "LC_CTYPE", "LANG"].iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|locale| !locale.is_empty())
+            .map(|locale| Self::from_locale(&locale))
+            .unwrap_or(KeyboardLayout::Qwerty)
+    }
+
+    /// Guesses the layout from a locale such as `fr_FR.UTF-8` or `de-CH`.
+    pub fn from_locale(locale: &str) -> Self {
+        let mut parts = locale.split(&['_', '-', '.'][..]);
+        let language = parts.next().unwrap_or("").to_lowercase();
+        let region = parts.next().unwrap_or("").to_uppercase();
+        match (language.as_str(), region.as_str()) {
+            // French Canadians and the Swiss use QWERTY and QWERTZ respectively.
+            ("fr", "CA") => KeyboardLayout::Qwerty,
+            ("fr", "CH") => KeyboardLayout::Qwertz,
+            ("fr", _) | ("nl", "BE") => KeyboardLayout::Azerty,
+            ("de", _) | ("cs", _) | ("sk", _) | ("hu", _) | ("sl", _) | ("hr", _) => KeyboardLayout::Qwertz,
+            _ => KeyboardLayout::Qwerty,
+        }
+    }
+}
+
+/// The label of `key` under `layout`, falling back to the name of the `KeyCode`.
+pub fn key_label(key: KeyCode, layout: KeyboardLayout) -> String {
+    match known_label(key, layout) {
+        Some(label) => label.to_owned(),
+        None => format!("{:?}", key),
+    }
+}
+
+/// The label of `key` and its modifiers, e.g. "Shift + A".
+pub fn input_label((key, mods): KeyPress, layout: KeyboardLayout) -> String {
+    let mut label = String::new();
+    for (modifier, name) in &[
+        (KeyMods::CTRL, "Ctrl"),
+        (KeyMods::ALT, "Alt"),
+        (KeyMods::SHIFT, "Shift"),
+        (KeyMods::LOGO, "Super"),
+    ] {
+        if mods.contains(*modifier) {
+            label.push_str(name);
+            label.push_str(" + ");
+        }
+    }
+    label.push_str(&key_label(key, layout));
+    label
+}
+
+/// Tells the player to press any of `presses` to `verb`, e.g. "Left/Right: pick a player".
+pub fn prompt(verb: &str, presses: &[KeyPress], layout: KeyboardLayout) -> String {
+    let labels: Vec<_> = presses.iter().map(|press| input_label(*press, layout)).collect();
+    format!("{}: {}", labels.join("/"), verb)
+}
+
+/// The label of `key` under `layout`, if it is one we know.
+pub fn known_label(key: KeyCode, layout: KeyboardLayout) -> Option<&'static str> {
+    let label = match layout {
+        KeyboardLayout::Qwerty => None,
+        KeyboardLayout::Azerty => azerty_label(key),
+        KeyboardLayout::Qwertz => qwertz_label(key),
+    };
+    label.or_else(|| qwerty_label(key))
+}
+
+/// Everything QWERTY labels, which other layouts share unless they say otherwise.
+fn qwerty_label(key: KeyCode) -> Option<&'static str> {
+    use KeyCode::*;
+    Some(match key {
+        A => "A", B => "B", C => "C", D => "D", E => "E", F => "F", G => "G", H => "H", I => "I",
+        J => "J", K => "K", L => "L", M => "M", N => "N", O => "O", P => "P", Q => "Q", R => "R",
+        S => "S", T => "T", U => "U", V => "V", W => "W", X => "X", Y => "Y", Z => "Z",
+        Key1 => "1", Key2 => "2", Key3 => "3", Key4 => "4", Key5 => "5",
+        Key6 => "6", Key7 => "7", Key8 => "8", Key9 => "9", Key0 => "0",
+        Minus => "-", Equals => "=", LBracket => "[", RBracket => "]", Backslash => "\\",
+        Semicolon => ";", Apostrophe => "'", Grave => "`", Comma => ",", Period => ".", Slash => "/",
+        Space => "Space", Escape => "Esc", Return => "Enter", Tab => "Tab", Back => "Backspace",
+        LShift | RShift => "Shift", LControl | RControl => "Ctrl", LAlt | RAlt => "Alt",
+        Up => "Up", Down => "Down", Left => "Left", Right => "Right",
+        _ => return None,
+    })
+}
+
+fn azerty_label(key: KeyCode) -> Option<&'static str> {
+    use KeyCode::*;
+    Some(match key {
+        A => "Q", Q => "A", Z => "W", W => "Z", Semicolon => "M", M => ",",
+        Key1 => "&", Key2 => "é", Key3 => "\"", Key4 => "'", Key5 => "(",
+        Key6 => "-", Key7 => "è", Key8 => "_", Key9 => "ç", Key0 => "à",
+        Minus => ")", LBracket => "^", RBracket => "$", Backslash => "*",
+        Apostrophe => "ù", Grave => "²", Comma => ";", Period => ":", Slash => "!",
+        _ => return None,
+    })
+}
+
+fn qwertz_label(key: KeyCode) -> Option<&'static str> {
+    use KeyCode::*;
+    Some(match key {
+        Y => "Z", Z => "Y",
+        Minus => "ß", Equals => "´", LBracket => "Ü", RBracket => "+", Backslash => "#",
+        Semicolon => "Ö", Apostrophe => "Ä", Grave => "^", Slash => "-",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod layout_test {
+    use super::*;
+
+    #[test]
+    fn azerty_swaps_letters() {
+        assert!(key_label(KeyCode::A, KeyboardLayout::Azerty) == "Q");
+        assert!(key_label(KeyCode::Q, KeyboardLayout::Azerty) == "A");
+        assert!(key_label(KeyCode::W, KeyboardLayout::Azerty) == "Z");
+        assert!(key_label(KeyCode::Semicolon, KeyboardLayout::Azerty) == "M");
+        assert!(key_label(KeyCode::D, KeyboardLayout::Azerty) == "D");
+    }
+
+    #[test]
+    fn qwertz_swaps_letters() {
+        assert!(key_label(KeyCode::Z, KeyboardLayout::Qwertz) == "Y");
+        assert!(key_label(KeyCode::Y, KeyboardLayout::Qwertz) == "Z");
+        assert!(key_label(KeyCode::Semicolon, KeyboardLayout::Qwertz) == "Ö");
+        assert!(key_label(KeyCode::Key1, KeyboardLayout::Qwertz) == "1");
+    }
+
+    #[test]
+    fn unknown_keys_use_debug_name() {
+        assert!(known_label(KeyCode::F5, KeyboardLayout::Qwerty).is_none());
+        assert!(key_label(KeyCode::F5, KeyboardLayout::Azerty) == "F5");
+    }
+
+    #[test]
+    fn modifiers() {
+        let input = (KeyCode::A, KeyMods::SHIFT);
+        assert!(input_label(input, KeyboardLayout::Qwerty) == "Shift + A");
+        assert!(input_label(input, KeyboardLayout::Azerty) == "Shift + Q");
+        assert!(input_label((KeyCode::Space, KeyMods::NONE), KeyboardLayout::Qwertz) == "Space");
+    }
+
+    #[test]
+    fn prompts_name_every_key() {
+        let presses = [(KeyCode::A, KeyMods::NONE), (KeyCode::D, KeyMods::NONE)];
+        assert!(prompt("move", &presses, KeyboardLayout::Azerty) == "Q/D: move");
+        assert!(prompt("quit", &[(KeyCode::Q, KeyMods::CTRL)], KeyboardLayout::Qwerty) == "Ctrl + Q: quit");
+    }
+
+    #[test]
+    fn layout_from_locale() {
+        assert!(KeyboardLayout::from_locale("fr_FR.UTF-8") == KeyboardLayout::Azerty);
+        assert!(KeyboardLayout::from_locale("fr_CA.UTF-8") == KeyboardLayout::Qwerty);
+        assert!(KeyboardLayout::from_locale("de-CH") == KeyboardLayout::Qwertz);
+        assert!(KeyboardLayout::from_locale("en_US.UTF-8") == KeyboardLayout::Qwerty);
+        assert!(KeyboardLayout::from_locale("C") == KeyboardLayout::Qwerty);
+    }
+}
@@ -33,20 +33,34 @@ use ggez::ContextBuilder;
 use ggez::conf::{WindowSetup, WindowMode};
 use ggez::event;
 
+mod assets;
+mod audio;
+mod debug;
 mod inputs;
 mod logging;
+mod persistence;
 mod physics;
+mod power;
+mod saves;
 mod screens;
 mod settings;
+mod skill_tree;
+mod sprites;
+mod text;
+mod timing;
 mod util;
+mod viewport;
 mod walpurgis;
 
 use walpurgis::Walpurgis;
 
 fn main() {
-    let settings = settings::load().expect("Failed to parse settings.");
+    let (settings, settings_problems) = settings::load();
     logging::setup(&settings.logging).expect("Failed to setup logging.");
     log::debug!("{:?}", settings);
+    for problem in &settings_problems {
+        log::warn!("{}", problem);
+    }
 
     // Make a Context and an EventLoop.
     let (mut ctx, mut event_loop) =
@@ -56,6 +70,9 @@ fn main() {
                 ..WindowSetup::default()
             })
             .window_mode(WindowMode {
+                width: settings.window.width,
+                height: settings.window.height,
+                fullscreen_type: settings.window.fullscreen_type(),
                 resizable: true,
                 ..WindowMode::default()
             })
@@ -63,10 +80,10 @@ fn main() {
            .unwrap();
 
      // Construct a game.
-     let mut my_game = match Walpurgis::new(&mut ctx, &settings.assets) {
+     let mut my_game = match Walpurgis::new(&mut ctx, settings, &settings_problems) {
         Ok(game) => game,
         Err(reason) => {
-            log::error!("Game construction failed: {:?}", reason);
+            log::error!("Game construction failed: {}", reason);
             return
         },
     };
@@ -76,5 +93,6 @@ fn main() {
         Ok(_) => println!("Exited cleanly."),
         Err(e) => println!("Error occured: {}", e)
     }
+    my_game.shutdown();
 }
 
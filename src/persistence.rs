@@ -0,0 +1,355 @@
+//! Writes files on a background thread, so that saving never stalls a frame.
+//!
+//! Payloads are serialized on the main thread and handed to a `PersistenceWorker` as `WriteJob`s.
+//! A single worker thread writes them in the order they were queued, so writes to the same path
+//! never overtake each other. Results come back over a channel and are logged by `poll`.
+//!
+//! ## Backpressure
+//! The queue is bounded. Once full, the lowest priority job is dropped to make room, or the new
+//! job itself if nothing queued is of lower priority. Saves are therefore only dropped once the
+//! queue is full of nothing but saves.
+//!
+//! ## Shutdown
+//! `shutdown` stops new jobs from being queued and waits for the queue to drain, giving up once
+//! its time budget runs out.
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, mpsc};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How many jobs may be waiting at once before some are dropped.
+pub const DEFAULT_CAPACITY: usize = 64;
+
+/// How important it is that a write happens at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WritePriority {
+    /// Nice to have, e.g. the proof of the last trial run, which the next one replaces.
+    Low,
+    /// Replays, screenshots and match history.
+    Normal,
+    /// Saves. Losing these loses progress.
+    Critical,
+}
+
+/// A pre-serialized file to write.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriteJob {
+    pub path: PathBuf,
+    pub bytes: Vec<u8>,
+    /// Write to a temporary file first and rename it over `path`, so that a crash never leaves a
+    /// half written file behind.
+    pub atomic: bool,
+    pub priority: WritePriority,
+}
+
+impl WriteJob {
+    pub fn new<P: Into<PathBuf>>(path: P, bytes: Vec<u8>, priority: WritePriority) -> Self {
+        Self {
+            path: path.into(),
+            bytes,
+            atomic: false,
+            priority,
+        }
+    }
+
+    pub fn atomic(self) -> Self {
+        Self {
+            atomic: true,
+            ..self
+        }
+    }
+
+    /// Where atomic writes are written before being renamed into place.
+    fn temp_path(&self) -> PathBuf {
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        temp.into()
+    }
+}
+
+/// The file operations the worker needs. Lets tests run without touching the disk.
+pub trait FileSystem: Send + 'static {
+    fn write(&mut self, path: &Path, bytes: &[u8]) -> io::Result<()>;
+    fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()>;
+}
+
+/// The actual file system.
+#[derive(Debug, Default)]
+pub struct RealFileSystem;
+
+impl FileSystem for RealFileSystem {
+    fn write(&mut self, path: &Path, bytes: &[u8]) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, bytes)
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+}
+
+/// The outcome of a write.
+#[derive(Debug)]
+pub struct WriteReport {
+    pub path: PathBuf,
+    pub result: io::Result<()>,
+}
+
+/// A bounded queue of jobs which drops low priority jobs first once full.
+#[derive(Debug)]
+struct WriteQueue {
+    jobs: VecDeque<WriteJob>,
+    capacity: usize,
+    /// Set once no more jobs are accepted. The worker stops once the queue is empty.
+    closed: bool,
+}
+
+impl WriteQueue {
+    fn new(capacity: usize) -> Self {
+        Self {
+            jobs: VecDeque::new(),
+            capacity,
+            closed: false,
+        }
+    }
+
+    /// Queues `job`, returning whichever job had to be dropped to make room, if any.
+    fn push(&mut self, job: WriteJob) -> Option<WriteJob> {
+        if self.jobs.len() < self.capacity {
+            self.jobs.push_back(job);
+            return None;
+        }
+        // The newest of the least important jobs goes first.
+        let lowest = self.jobs.iter()
+            .enumerate()
+            .rev()
+            .min_by_key(|(_, queued)| queued.priority)
+            .map(|(idx, queued)| (idx, queued.priority));
+        match lowest {
+            Some((idx, priority)) if priority < job.priority => {
+                let dropped = self.jobs.remove(idx);
+                self.jobs.push_back(job);
+                dropped
+            },
+            _ => Some(job),
+        }
+    }
+
+    fn pop(&mut self) -> Option<WriteJob> {
+        self.jobs.pop_front()
+    }
+}
+
+type SharedQueue = Arc<(Mutex<WriteQueue>, Condvar)>;
+
+/// Owns the background thread doing all file writes.
+#[derive(Debug)]
+pub struct PersistenceWorker {
+    queue: SharedQueue,
+    reports: mpsc::Receiver<WriteReport>,
+    /// Receives a message once the worker has written everything and stopped.
+    drained: mpsc::Receiver<()>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl PersistenceWorker {
+    pub fn new<F: FileSystem>(file_system: F, capacity: usize) -> Self {
+        let queue: SharedQueue = Arc::new((Mutex::new(WriteQueue::new(capacity)), Condvar::new()));
+        let (report_tx, reports) = mpsc::channel();
+        let (drained_tx, drained) = mpsc::channel();
+        let worker_queue = Arc::clone(&queue);
+        let thread = thread::Builder::new()
+            .name("persistence".to_owned())
+            .spawn(move || {
+                run_worker(file_system, &worker_queue, &report_tx);
+                // The worker may outlive the receiver if shutdown gave up waiting.
+                drained_tx.send(()).ok();
+            })
+            .expect("Failed to spawn the persistence thread.");
+        Self {
+            queue,
+            reports,
+            drained,
+            thread: Some(thread),
+        }
+    }
+
+    /// Queues a write. Returns false if the job was dropped, either to backpressure or because
+    /// the worker is shutting down.
+    pub fn enqueue(&self, job: WriteJob) -> bool {
+        let (lock, condvar) = &*self.queue;
+        let mut queue = lock.lock().expect("Persistence queue poisoned.");
+        if queue.closed {
+            log::warn!("Dropped write to `{}`: shutting down.", job.path.display());
+            return false;
+        }
+        let path = job.path.clone();
+        let dropped = queue.push(job);
+        condvar.notify_one();
+        match dropped {
+            Some(dropped) => {
+                log::warn!("Write queue full. Dropped write to `{}`.", dropped.path.display());
+                dropped.path != path
+            },
+            None => true,
+        }
+    }
+
+    /// Collects the results of finished writes, logging any failures.
+    pub fn poll(&self) -> Vec<WriteReport> {
+        let reports: Vec<_> = self.reports.try_iter().collect();
+        for report in &reports {
+            match &report.result {
+                Ok(()) => log::debug!("Wrote `{}`.", report.path.display()),
+                Err(e) => log::error!("Failed to write `{}`: {}", report.path.display(), e),
+            }
+        }
+        reports
+    }
+
+    /// Stops accepting jobs and waits up to `budget` for queued jobs to be written. Returns the
+    /// number of jobs left unwritten.
+    pub fn shutdown(&mut self, budget: Duration) -> usize {
+        let (lock, condvar) = &*self.queue;
+        lock.lock().expect("Persistence queue poisoned.").closed = true;
+        condvar.notify_one();
+
+        if self.thread.is_none() || self.drained.recv_timeout(budget).is_ok() {
+            if let Some(thread) = self.thread.take() {
+                thread.join().ok();
+            }
+            self.poll();
+            return 0;
+        }
+        self.poll();
+        let left = lock.lock().expect("Persistence queue poisoned.").jobs.len();
+        log::warn!("Gave up on {} queued writes after {:?}.", left, budget);
+        left
+    }
+}
+
+/// Writes jobs until the queue is closed and empty.
+fn run_worker<F: FileSystem>(mut file_system: F, queue: &SharedQueue, reports: &mpsc::Sender<WriteReport>) {
+    let (lock, condvar) = &**queue;
+    loop {
+        let job = {
+            let mut queue = lock.lock().expect("Persistence queue poisoned.");
+            loop {
+                if let Some(job) = queue.pop() {
+                    break job;
+                }
+                if queue.closed {
+                    return;
+                }
+                queue = condvar.wait(queue).expect("Persistence queue poisoned.");
+            }
+        };
+        let result = if job.atomic {
+            let temp = job.temp_path();
+            file_system.write(&temp, &job.bytes)
+                .and_then(|_| file_system.rename(&temp, &job.path))
+        } else {
+            file_system.write(&job.path, &job.bytes)
+        };
+        // Nobody may be listening during shutdown.
+        reports.send(WriteReport { path: job.path, result }).ok();
+    }
+}
+
+#[cfg(test)]
+mod persistence_test {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Keeps files in memory and records every operation.
+    #[derive(Debug, Clone, Default)]
+    struct MockFileSystem {
+        files: Arc<Mutex<HashMap<PathBuf, Vec<u8>>>>,
+        operations: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl FileSystem for MockFileSystem {
+        fn write(&mut self, path: &Path, bytes: &[u8]) -> io::Result<()> {
+            self.operations.lock().unwrap().push(format!("write {} {:?}", path.display(), bytes));
+            self.files.lock().unwrap().insert(path.to_owned(), bytes.to_vec());
+            Ok(())
+        }
+
+        fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+            self.operations.lock().unwrap().push(format!("rename {} {}", from.display(), to.display()));
+            let mut files = self.files.lock().unwrap();
+            let bytes = files.remove(from).ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+            files.insert(to.to_owned(), bytes);
+            Ok(())
+        }
+    }
+
+    fn job(path: &str, byte: u8, priority: WritePriority) -> WriteJob {
+        WriteJob::new(path, vec![byte], priority)
+    }
+
+    #[test]
+    fn writes_to_a_path_stay_in_order() {
+        let file_system = MockFileSystem::default();
+        let mut worker = PersistenceWorker::new(file_system.clone(), 1000);
+        for byte in 0..100 {
+            assert!(worker.enqueue(job("save.ron", byte, WritePriority::Critical)));
+            assert!(worker.enqueue(job("other.ron", byte, WritePriority::Low)));
+        }
+        assert!(worker.shutdown(Duration::from_secs(10)) == 0);
+
+        let operations = file_system.operations.lock().unwrap();
+        let saves: Vec<_> = operations.iter().filter(|op| op.starts_with("write save.ron")).collect();
+        let expected: Vec<_> = (0..100_u8).map(|byte| format!("write save.ron [{}]", byte)).collect();
+        assert!(saves.len() == 100);
+        assert!(saves.iter().zip(&expected).all(|(op, expected)| *op == expected));
+        assert!(file_system.files.lock().unwrap()[Path::new("save.ron")] == vec![99]);
+    }
+
+    #[test]
+    fn full_queue_drops_lowest_priority() {
+        let mut queue = WriteQueue::new(3);
+        assert!(queue.push(job("save", 0, WritePriority::Critical)).is_none());
+        assert!(queue.push(job("telemetry", 0, WritePriority::Low)).is_none());
+        assert!(queue.push(job("replay", 0, WritePriority::Normal)).is_none());
+
+        let dropped = queue.push(job("save", 1, WritePriority::Critical)).unwrap();
+        assert!(dropped.path == Path::new("telemetry"));
+        // Nothing queued is less important than more telemetry.
+        let dropped = queue.push(job("telemetry", 1, WritePriority::Low)).unwrap();
+        assert!(dropped.path == Path::new("telemetry"));
+        let dropped = queue.push(job("save", 2, WritePriority::Critical)).unwrap();
+        assert!(dropped.path == Path::new("replay"));
+
+        let left: Vec<_> = std::iter::from_fn(|| queue.pop()).map(|job| job.bytes[0]).collect();
+        assert!(left == vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn shutdown_drains_queue() {
+        let file_system = MockFileSystem::default();
+        let mut worker = PersistenceWorker::new(file_system.clone(), 1000);
+        for idx in 0..500 {
+            worker.enqueue(job(&format!("replay{}.ron", idx), 0, WritePriority::Normal));
+        }
+        assert!(worker.shutdown(Duration::from_secs(10)) == 0);
+        assert!(file_system.files.lock().unwrap().len() == 500);
+        assert!(!worker.enqueue(job("late.ron", 0, WritePriority::Critical)));
+    }
+
+    #[test]
+    fn atomic_writes_rename_into_place() {
+        let file_system = MockFileSystem::default();
+        let mut worker = PersistenceWorker::new(file_system.clone(), 10);
+        worker.enqueue(job("save.ron", 7, WritePriority::Critical).atomic());
+        worker.shutdown(Duration::from_secs(10));
+        let operations = file_system.operations.lock().unwrap();
+        assert!(*operations == vec!["write save.ron.tmp [7]", "rename save.ron.tmp save.ron"]);
+        assert!(file_system.files.lock().unwrap().keys().collect::<Vec<_>>() == vec![Path::new("save.ron")]);
+    }
+}
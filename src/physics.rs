@@ -1,19 +1,47 @@
 //! A collection of structs, traits, and functions for use with physics.
 //!
+//! ## Coordinates
+//! Physics works in screen coordinates: +x goes right and +y goes down, so gravity is a positive
+//! y force and a positive y velocity means falling. Rotations by positive angles turn clockwise on
+//! the screen, the same way `ggez` rotates drawings. The battle's camera moves and zooms the world
+//! on the screen through the `DrawParam` it hands out, so drawing code should go through `drawn_at`
+//! and `screen_point` rather than indexing positions itself.
+//!
 //! ## Collisions
-//! The game engine will check for collisions. It will pairwise compare all Collidable objects.
+//! The game engine will check for collisions. A broad phase pairs up Collidable objects whose
+//! envelopes overlap, then a narrow phase compares their bounding boxes exactly.
 //!
 //! ### Algorithmic complexity
-//! If we have `n` `Collidable` objects, each with `b` `BoundingBoxes`, then we can iterate over
-//! each collidable, iterate over every collidable, iterate over the pairs of bounding boxes in
-//! each, and finally inflict collisions, resulting in runtime complexity `O(b*n^2)`.
+//! If we have `n` `Collidable` objects, each with `b` `BoundingBoxes`, then comparing every pair of
+//! bounding boxes of every pair of collidables takes `O(b^2*n^2)`. The broad phase sorts the
+//! envelopes in `O(n*log(n))` and sweeps over them, so only the `k` pairs that are actually close
+//! are compared, for `O(n*log(n) + k*b^2)` overall.
 //!
 //! ### Parallelization
 //! Will likely need to benchmark parallel and single-threaded versions of the code.
 //!
 //! We’ll deal with it when perf becomes an issue.
 
+pub mod broad;
 pub mod collision;
 pub use collision::{Collidable, Collision};
 pub mod obb;
-pub use obb::BoundingBox;
+pub use obb::{BoundingBox, HitboxTag};
+
+use ggez::graphics::DrawParam;
+use ggez::nalgebra as na;
+
+/// Moves where `param` draws by `offset`, a displacement in the world. The displacement is scaled
+/// and turned along with everything else `param` draws, so it holds up under a zooming camera.
+pub fn drawn_at(mut param: DrawParam, offset: na::Vector2<f32>) -> DrawParam {
+    let (sin, cos) = param.rotation.sin_cos();
+    let (x, y) = (offset[0] * param.scale.x, offset[1] * param.scale.y);
+    param.dest.x += x * cos - y * sin;
+    param.dest.y += x * sin + y * cos;
+    param
+}
+
+/// Where `position`, a point in the world, is on the screen. For building meshes.
+pub fn screen_point(position: na::Vector2<f32>) -> [f32; 2] {
+    [position[0], position[1]]
+}
@@ -0,0 +1,139 @@
+//! The broad phase of collision detection.
+//!
+//! Every `Collidable` is enclosed in an axis aligned envelope, and only pairs whose envelopes
+//! overlap are handed to the exact, and much slower, narrow phase. Overlapping envelopes are found
+//! by sorting them along the x axis and sweeping over them, keeping a list of the envelopes the
+//! sweep is currently inside of. Objects spread out over an arena are then compared against their
+//! few neighbours rather than against everything else.
+use ggez::nalgebra as na;
+use std::cmp::Ordering;
+
+use crate::physics::obb::BoundingBox;
+
+/// How much envelopes are grown on every side, so that rounding in the narrow phase can't find
+/// collisions between boxes whose envelopes only just miss each other.
+const MARGIN: f32 = 1e-3;
+
+/// An axis aligned box, in world coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: na::Vector2<f32>,
+    pub max: na::Vector2<f32>,
+}
+
+impl Aabb {
+    /// The smallest `Aabb` containing all of `hitboxes` once moved by `offset`, or `None` if there
+    /// are no hitboxes.
+    pub fn enclosing(hitboxes: &[BoundingBox], offset: na::Vector2<f32>) -> Option<Self> {
+        hitboxes.iter()
+            .map(|hb| {
+                let (min, max) = hb.extents();
+                Aabb { min: min + offset, max: max + offset }
+            })
+            .fold(None, |acc: Option<Aabb>, aabb| Some(match acc {
+                Some(acc) => acc.union(&aabb),
+                None => aabb,
+            }))
+    }
+
+    /// The smallest `Aabb` containing both boxes.
+    pub fn union(&self, other: &Self) -> Self {
+        Aabb {
+            min: self.min.zip_map(&other.min, f32::min),
+            max: self.max.zip_map(&other.max, f32::max),
+        }
+    }
+
+    /// Everything the box passes over while moving by `displacement`.
+    pub fn swept(&self, displacement: na::Vector2<f32>) -> Self {
+        self.union(&Aabb { min: self.min + displacement, max: self.max + displacement })
+    }
+
+    /// Whether the boxes overlap or touch.
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.min[0] <= other.max[0] + MARGIN && other.min[0] <= self.max[0] + MARGIN
+            && self.min[1] <= other.max[1] + MARGIN && other.min[1] <= self.max[1] + MARGIN
+    }
+}
+
+/// The pairs `(i, j)` with `i < j` whose envelopes overlap, in ascending order. Objects without an
+/// envelope overlap nothing.
+pub fn candidates(envelopes: &[Option<Aabb>]) -> Vec<(usize, usize)> {
+    let mut pairs = sweep(envelopes);
+    pairs.sort_unstable();
+    pairs
+}
+
+/// The pairs `(i, j)` of an object from `set1` and one from `set2` whose envelopes overlap, in
+/// ascending order.
+pub fn candidate_pairs(set1: &[Option<Aabb>], set2: &[Option<Aabb>]) -> Vec<(usize, usize)> {
+    let split = set1.len();
+    let combined: Vec<_> = set1.iter().chain(set2).cloned().collect();
+    let mut pairs: Vec<_> = sweep(&combined).into_iter()
+        .filter(|&(i, j)| i < split && j >= split)
+        .map(|(i, j)| (i, j - split))
+        .collect();
+    pairs.sort_unstable();
+    pairs
+}
+
+/// Sort and sweep along the x axis. Pairs come out as `(i, j)` with `i < j`, in no particular
+/// order.
+fn sweep(envelopes: &[Option<Aabb>]) -> Vec<(usize, usize)> {
+    let mut sorted: Vec<(usize, &Aabb)> = envelopes.iter()
+        .enumerate()
+        .filter_map(|(id, envelope)| envelope.as_ref().map(|envelope| (id, envelope)))
+        .collect();
+    sorted.sort_by(|(_, a), (_, b)| a.min[0].partial_cmp(&b.min[0]).unwrap_or(Ordering::Equal));
+
+    let mut pairs = vec![];
+    let mut active: Vec<(usize, &Aabb)> = vec![];
+    for (id, envelope) in sorted {
+        // Everything ending before this envelope starts ends before every later one does too.
+        active.retain(|(_, other)| other.max[0] + MARGIN >= envelope.min[0]);
+        for (other_id, other) in active.iter() {
+            if envelope.overlaps(other) {
+                pairs.push((id.min(*other_id), id.max(*other_id)));
+            }
+        }
+        active.push((id, envelope));
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod broad_test {
+    use super::*;
+
+    fn aabb(x: f32, y: f32, w: f32, h: f32) -> Option<Aabb> {
+        Some(Aabb { min: na::Vector2::new(x, y), max: na::Vector2::new(x + w, y + h) })
+    }
+
+    #[test]
+    fn candidates_overlap() {
+        let envelopes = [
+            aabb(0., 0., 10., 10.),
+            aabb(20., 0., 10., 10.),
+            // Touches the first one.
+            aabb(10., 10., 5., 5.),
+            None,
+            // Overlaps the first along x only.
+            aabb(5., 50., 10., 10.),
+            aabb(-5., -5., 30., 6.),
+        ];
+        assert!(candidates(&envelopes) == vec![(0, 2), (0, 5), (1, 5)]);
+    }
+
+    #[test]
+    fn candidate_pairs_only_across_sets() {
+        let set1 = [aabb(0., 0., 10., 10.), aabb(5., 5., 10., 10.)];
+        let set2 = [aabb(100., 0., 10., 10.), aabb(8., 8., 1., 1.), None];
+        assert!(candidate_pairs(&set1, &set2) == vec![(0, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn swept_covers_path() {
+        let moved = aabb(0., 0., 1., 1.).unwrap().swept(na::Vector2::new(5., -2.));
+        assert!(moved == aabb(0., -2., 6., 3.).unwrap());
+    }
+}
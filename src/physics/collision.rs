@@ -1,13 +1,13 @@
 use ggez::nalgebra as na;
 use std::any::Any;
 
-use crate::physics::obb::BoundingBox;
+use crate::physics::{
+    broad::{self, Aabb},
+    obb::{BoundingBox, HitboxTag, PreparedBox},
+};
 use crate::util::{
-    cartesian::{
-        product as cartesian_product,
-        unique_square as unique_cartesian_square,
-    },
-    tuple::{transpose, flip_tuple_vec},
+    cartesian::product as cartesian_product,
+    tuple::flip_tuple_vec,
 };
 
 pub trait Mergeable {
@@ -25,15 +25,36 @@ impl Mergeable for () {
 pub trait Collidable: Any {
     type ChangeSet: Mergeable;
     /// Gets the list of hitboxes comprising the person.
-    ///
-    /// TODO: Make this reflect a tree of collidables that we can narrow down in a broad and narrow
-    /// phase.
     fn get_hitboxes<'tick>(&'tick self) -> &'tick[BoundingBox];
     fn get_offset(&self) -> na::Vector2<f32>;
+    /// An axis aligned box around all of the hitboxes, in world coordinates. Only objects whose
+    /// boxes overlap are checked for collisions. `None` if there is nothing to collide with.
+    fn get_broad_aabb(&self) -> Option<Aabb> {
+        Aabb::enclosing(self.get_hitboxes(), self.get_offset())
+    }
+    /// How far the object expects to move over the next tick. Used to catch fast objects passing
+    /// through thin ones.
+    fn get_velocity(&self) -> na::Vector2<f32> {
+        na::Vector2::zeros()
+    }
+    /// The layers the object is on, as bits.
+    fn collision_layer(&self) -> u32 {
+        u32::MAX
+    }
+    /// The layers the object collides with, as bits. Objects only collide when each is on a layer
+    /// the other collides with.
+    fn collision_mask(&self) -> u32 {
+        u32::MAX
+    }
     fn apply_changeset(&mut self, _changes: Self::ChangeSet) {}
     fn handle_phys_update(&mut self) {}
 }
 
+/// Whether the layers of `a` and `b` allow them to collide at all.
+fn layers_collide<T: Collidable, S: Collidable>(a: &T, b: &S) -> bool {
+    a.collision_layer() & b.collision_mask() != 0 && b.collision_layer() & a.collision_mask() != 0
+}
+
 /// Returns the details of a collision.
 ///
 /// Bound by lifetime to a single `tick` of the program.
@@ -45,17 +66,38 @@ pub struct Collision<'tick, T: Collidable, S: Collidable> {
     ///
     /// The bounding boxes on the left belongs to the `Collidable` on the left and vice versa.
     pub overlapping_hitboxes: Vec<(&'tick BoundingBox, &'tick BoundingBox)>,
+    /// The earliest time of impact within the coming tick, in [0, 1]. 0 when the objects already
+    /// overlap, in which case `overlapping_hitboxes` overlap now. Otherwise they only will once
+    /// moved by their velocities.
+    pub toi: f32,
 }
 
 impl<'tick, T: Collidable, S: Collidable> Collision<'tick, T, S> {
     pub fn flipped(self) -> Collision<'tick, S, T> {
-        let Collision { ids, objs, overlapping_hitboxes } = self;
+        let Collision { ids, objs, overlapping_hitboxes, toi } = self;
         Collision {
             ids: (ids.1, ids.0),
             objs: (objs.1, objs.0),
             overlapping_hitboxes: flip_tuple_vec(overlapping_hitboxes),
+            toi,
         }
     }
+
+    /// The kinds of each pair of overlapping hitboxes, in the same order as the hitboxes.
+    pub fn kinds(&self) -> Vec<(HitboxTag, HitboxTag)> {
+        self.overlapping_hitboxes.iter().map(|(hb0, hb1)| (hb0.tag, hb1.tag)).collect()
+    }
+
+    /// The overlapping hitboxes, moved into world coordinates.
+    pub fn world_hitboxes(&self) -> Vec<(BoundingBox, BoundingBox)> {
+        let (offset0, offset1) = (self.objs.0.get_offset(), self.objs.1.get_offset());
+        self.overlapping_hitboxes.iter()
+            .map(|(hb0, hb1)| (
+                BoundingBox { pos: hb0.pos + offset0, ..**hb0 },
+                BoundingBox { pos: hb1.pos + offset1, ..**hb1 },
+            ))
+            .collect()
+    }
 }
 
 type CollisionTuple<'tick, T, S> = (
@@ -72,62 +114,46 @@ impl<'tick, T: Collidable, S: Collidable> From<CollisionTuple<'tick, T, S>> for
             ids: (id0, id1),
             objs: (e0, e1),
             overlapping_hitboxes: hb_collisions,
+            toi: 0.,
         }
     }
 }
 
-/// Check for hit box collisions between two `IntoIterator`s of `BoundingBox`es.
-///
-/// Applies the provided offset to all of the hitboxes in either the first or the second iterator
-/// depending on which iterator was requested to be offset.
-fn check_for_hb_collisions<'a, I, II>(offset_second: bool, offset: na::Vector2<f32>, (hb0, hb1): (II, II)) -> Vec<(&'a BoundingBox, &'a BoundingBox)>
-where
-    I: std::iter::Iterator<Item = &'a BoundingBox> + std::clone::Clone,
-    II: std::iter::IntoIterator<Item = &'a BoundingBox, IntoIter = I>,
-{
-    // Use the second set of hitboxes as the first when requested to do so.
-    let (hb0, hb1) = if offset_second {
-        (hb1, hb0)
-    } else {
-        (hb0, hb1)
-    };
-    let hb0_mapped_mem: Vec<_> = hb0.into_iter().map(|bb| (bb, BoundingBox {
-        pos: bb.pos + offset,
-        ..*bb
-    })).collect();
-    let hb0 = hb0_mapped_mem.iter();
+/// Check for hit box collisions between two lists of prepared `BoundingBox`es.
+fn check_for_hb_collisions<'a>(hb0: &[PreparedBox<'a>], hb1: &[PreparedBox<'a>]) -> Vec<(&'a BoundingBox, &'a BoundingBox)> {
     cartesian_product(hb0, hb1)
-        .filter(|((_, offset_hb0), hb1)| {
-            BoundingBox::check_collision(offset_hb0, hb1)
-        })
-        .map(|(&(hb0, _), hb1)| if offset_second { // flip again to counteract initial flip
-            (hb1, hb0)
-        } else {
-            (hb0, hb1)
-        })
+        .filter(|(hb0, hb1)| PreparedBox::check_collision(hb0, hb1))
+        .map(|(hb0, hb1)| (hb0.source, hb1.source))
         .collect()
 }
+/// Checks two `Collidable`s for overlapping hitboxes, given their prepared hitboxes.
+fn narrow_phase<'tick, T: Collidable, S: Collidable>(
+    (e0, hb0): ((usize, &'tick T), &[PreparedBox<'tick>]),
+    (e1, hb1): ((usize, &'tick S), &[PreparedBox<'tick>]),
+) -> Option<Collision<'tick, T, S>> {
+    let hb_collisions = check_for_hb_collisions(hb0, hb1);
+    if hb_collisions.is_empty() {
+        return None;
+    }
+    Some(Collision::from(((e0, e1), hb_collisions)))
+}
+
+/// The hitboxes of every entity in world coordinates, ready to be checked against each other.
+fn prepare_all<T: Collidable>(entities: &[T]) -> Vec<Vec<PreparedBox<'_>>> {
+    entities.iter().map(|e| PreparedBox::all(e.get_hitboxes(), e.get_offset())).collect()
+}
+
 /// Check for collisions within a slice of [`Collidable`]s
 pub fn check_for_collisions<'tick, T:Collidable>(entities: &'tick[T]) -> Vec<Collision<'tick, T, T>> {
-    let entity_with_hitboxes = entities
-        .iter()
-        .enumerate()
-        .map(|(id, e)| ((id, e), e.get_hitboxes()));
-    unique_cartesian_square(entity_with_hitboxes)
-        .map(transpose)
-        .map(|(e_pair, hb_pair)| {
-            // If the first list of hitboxes is shorter, we want to offset all the hitboxes of the
-            // first vs the second.
-            let should_offset_second = hb_pair.0.len() > hb_pair.1.len();
-            let offset = if should_offset_second {
-                (e_pair.1).1.get_offset() - (e_pair.0).1.get_offset()
-            } else {
-                (e_pair.0).1.get_offset() - (e_pair.1).1.get_offset()
-            };
-            (e_pair, check_for_hb_collisions(should_offset_second, offset, hb_pair))
-        })
-        .filter(|(_, hb_collisions): &(_, Vec<_>)| !hb_collisions.is_empty())
-        .map(Collision::from)
+    let envelopes: Vec<_> = entities.iter().map(Collidable::get_broad_aabb).collect();
+    let prepared = prepare_all(entities);
+    broad::candidates(&envelopes)
+        .into_iter()
+        .filter(|&(id0, id1)| layers_collide(&entities[id0], &entities[id1]))
+        .filter_map(|(id0, id1)| narrow_phase(
+            ((id0, &entities[id0]), &prepared[id0]),
+            ((id1, &entities[id1]), &prepared[id1]),
+        ))
         .collect()
 }
 
@@ -140,40 +166,78 @@ pub fn check_for_collision_pairs<
     set1: &'tick[T],
     set2: &'tick[S],
 ) -> Vec<Collision<'tick, T, S>> {
-
-    let set1_with_hitboxes: Vec<_> = set1
-        .iter()
-        .enumerate()
-        .map(|(id, e)| ((id, e), e.get_hitboxes()))
-        .collect();
-    let set2_with_hitboxes: Vec<_> = set2
-        .iter()
-        .enumerate()
-        .map(|(id, e)| ((id, e), e.get_hitboxes()))
-        .collect();
-    cartesian_product(set1_with_hitboxes, set2_with_hitboxes)
-        .map(transpose)
-        .map(|(e_pair, hb_pair)| {
-            // If the first list of hitboxes is shorter, we want to offset all the hitboxes of the
-            // first vs the second.
-            let should_offset_second = hb_pair.0.len() > hb_pair.1.len();
-            let offset = if should_offset_second {
-                (e_pair.1).1.get_offset() - (e_pair.0).1.get_offset()
-            } else {
-                (e_pair.0).1.get_offset() - (e_pair.1).1.get_offset()
-            };
-            (e_pair, check_for_hb_collisions(should_offset_second, offset, hb_pair))
-        })
-        .filter(|(_, hb_collisions): &(_, Vec<_>)| !hb_collisions.is_empty())
-        .map(Collision::from)
+    let envelopes1: Vec<_> = set1.iter().map(Collidable::get_broad_aabb).collect();
+    let envelopes2: Vec<_> = set2.iter().map(Collidable::get_broad_aabb).collect();
+    let (prepared1, prepared2) = (prepare_all(set1), prepare_all(set2));
+    broad::candidate_pairs(&envelopes1, &envelopes2)
+        .into_iter()
+        .filter(|&(id0, id1)| layers_collide(&set1[id0], &set2[id1]))
+        .filter_map(|(id0, id1)| narrow_phase(
+            ((id0, &set1[id0]), &prepared1[id0]),
+            ((id1, &set2[id1]), &prepared2[id1]),
+        ))
         .collect()
 }
 
+/// Everything `e` covers while moving by its velocity over the coming tick.
+fn swept_envelope<T: Collidable>(e: &T) -> Option<Aabb> {
+    e.get_broad_aabb().map(|aabb| aabb.swept(e.get_velocity()))
+}
+
+/// Check for collisions between two slices of [`Collidable`]s, including those that would happen
+/// partway through the coming tick as the objects move by their velocities.
+///
+/// Objects that already overlap are reported exactly like `check_for_collision_pairs` does. All
+/// collisions come out in ascending order of ids, so that they're resolved in the same order
+/// every time.
+pub fn check_for_swept_collision_pairs<
+    'tick,
+    T: Collidable,
+    S: Collidable,
+>(
+    set1: &'tick[T],
+    set2: &'tick[S],
+) -> Vec<Collision<'tick, T, S>> {
+    let mut collisions = check_for_collision_pairs(set1, set2);
+    let already_colliding: Vec<_> = collisions.iter().map(|c| c.ids).collect();
+
+    // Only objects whose paths over the tick overlap can run into each other.
+    let envelopes1: Vec<_> = set1.iter().map(swept_envelope).collect();
+    let envelopes2: Vec<_> = set2.iter().map(swept_envelope).collect();
+    let ids = broad::candidate_pairs(&envelopes1, &envelopes2)
+        .into_iter()
+        .filter(|&(id0, id1)| layers_collide(&set1[id0], &set2[id1]))
+        .filter(|ids| !already_colliding.contains(ids));
+    for (id0, id1) in ids {
+        let (e0, e1) = (&set1[id0], &set2[id1]);
+        let displacement = e0.get_velocity() - e1.get_velocity();
+        let (offset0, offset1) = (e0.get_offset(), e1.get_offset());
+        let hits: Vec<_> = cartesian_product(e0.get_hitboxes(), e1.get_hitboxes())
+            .filter_map(|(hb0, hb1)| {
+                let world0 = BoundingBox { pos: hb0.pos + offset0, ..*hb0 };
+                let world1 = BoundingBox { pos: hb1.pos + offset1, ..*hb1 };
+                world0.sweep_collision(displacement, &world1).map(|toi| ((hb0, hb1), toi))
+            })
+            .collect();
+        let toi = hits.iter().map(|(_, toi)| *toi).fold(f32::INFINITY, f32::min);
+        if !hits.is_empty() {
+            collisions.push(Collision {
+                ids: (id0, id1),
+                objs: (e0, e1),
+                overlapping_hitboxes: hits.into_iter().map(|(pair, _)| pair).collect(),
+                toi,
+            });
+        }
+    }
+    collisions.sort_by_key(|c| c.ids);
+    collisions
+}
 
 
 #[cfg(test)]
 mod cartesian_collision_test {
     use super::*;
+    use crate::util::cartesian::unique_square as unique_cartesian_square;
     type V2 = ggez::nalgebra::Vector2<f32>;
 
     pub struct DummyStruct {
@@ -194,11 +258,13 @@ mod cartesian_collision_test {
     fn box_list1() -> Vec<BoundingBox> {
         vec![BoundingBox {
             mode: None,
+            tag: HitboxTag::Solid,
             pos: V2::zeros(),
             size: V2::new(1., 1.),
             ori: 0.,
         }, BoundingBox {
             mode: None,
+            tag: HitboxTag::Solid,
             pos: V2::new(1.5, 0.),
             size: V2::new(1., 1.),
             ori: 0.,
@@ -207,11 +273,13 @@ mod cartesian_collision_test {
     fn box_list2() -> Vec<BoundingBox> {
         vec![BoundingBox {
             mode: None,
+            tag: HitboxTag::Solid,
             pos: V2::new(-50.1, -50.1),
             size: V2::new(1., 1.),
             ori: 0.,
         }, BoundingBox {
             mode: None,
+            tag: HitboxTag::Solid,
             pos: V2::new(1.25, 0.),
             size: V2::new(1., 1.),
             ori: std::f32::consts::PI/4.,
@@ -220,11 +288,13 @@ mod cartesian_collision_test {
     fn box_list3() -> Vec<BoundingBox> {
         vec![BoundingBox {
             mode: None,
+            tag: HitboxTag::Solid,
             pos: V2::new(50.1, 50.1),
             size: V2::new(1., 1.),
             ori: 0.,
         }, BoundingBox {
             mode: None,
+            tag: HitboxTag::Solid,
             pos: V2::new(51.25, 50.),
             size: V2::new(1., 1.),
             ori: std::f32::consts::PI/4.,
@@ -241,7 +311,7 @@ mod cartesian_collision_test {
         let boxes1 = box_list1();
         let boxes2 = box_list2();
         let correct_collisions = vec![(&boxes1[0], &boxes2[1]), (&boxes1[1], &boxes2[1])];
-        let pairs = check_for_hb_collisions(false, na::Vector2::new(0_f32, 0_f32), (&boxes1, &boxes2));
+        let pairs = check_for_hb_collisions(&PreparedBox::all(&boxes1, V2::zeros()), &PreparedBox::all(&boxes2, V2::zeros()));
         assert!(pairs.len() == correct_collisions.len());
 
         for element in correct_collisions.iter() {
@@ -254,7 +324,7 @@ mod cartesian_collision_test {
         let boxes1 = box_list1();
         let boxes2 = box_list2();
         let correct_collisions = vec![(&boxes1[0], &boxes2[1]), (&boxes1[1], &boxes2[1])];
-        let pairs = check_for_hb_collisions(true, na::Vector2::new(0_f32, 0_f32), (&boxes1, &boxes2));
+        let pairs = check_for_hb_collisions(&PreparedBox::all(&boxes2, V2::zeros()), &PreparedBox::all(&boxes1, V2::zeros()));
         assert!(pairs.len() == correct_collisions.len());
 
         for element in correct_collisions.iter() {
@@ -279,6 +349,7 @@ mod cartesian_collision_test {
             ids: (id0, id1),
             overlapping_hitboxes: overlaps,
             objs: (obj0, obj1),
+            ..
         } = collisions.pop().unwrap();
 
         if std::ptr::eq(obj0, el_refs[0]) {
@@ -300,4 +371,186 @@ mod cartesian_collision_test {
             assert!(ref_pair_eq_order_independent(overlaps[0], match1));
         }
     }
+
+    #[test]
+    fn collisions_report_kinds() {
+        let hurt = DummyStruct { boxes: vec![BoundingBox { tag: HitboxTag::Hurt, ..box_list1()[0].clone() }] };
+        let hit = DummyStruct { boxes: vec![BoundingBox { tag: HitboxTag::Hit, ..box_list2()[1].clone() }] };
+        let collisions = check_for_collision_pairs(std::slice::from_ref(&hit), std::slice::from_ref(&hurt));
+        assert!(collisions[0].kinds() == vec![(HitboxTag::Hit, HitboxTag::Hurt)]);
+        assert!(collisions.into_iter().next().unwrap().flipped().kinds() == vec![(HitboxTag::Hurt, HitboxTag::Hit)]);
+        assert!(HitboxTag::Hurt.can_be_hit() && HitboxTag::Shield.can_be_hit());
+        assert!(!HitboxTag::Solid.can_be_hit() && !HitboxTag::Hit.can_be_hit() && !HitboxTag::Grab.can_be_hit());
+        assert!(HitboxTag::Hurt.can_be_grabbed() && HitboxTag::Shield.can_be_grabbed());
+        assert!(!HitboxTag::Solid.can_be_grabbed() && !HitboxTag::Grab.can_be_grabbed());
+    }
+
+    struct MovingStruct {
+        boxes: Vec<BoundingBox>,
+        velocity: na::Vector2<f32>,
+    }
+    impl Collidable for MovingStruct {
+        type ChangeSet = ();
+        fn get_hitboxes<'tick>(&'tick self) -> &'tick[BoundingBox] {
+            &self.boxes
+        }
+        fn get_offset(&self) -> na::Vector2<f32> {
+            na::Vector2::new(0_f32, 0_f32)
+        }
+        fn get_velocity(&self) -> na::Vector2<f32> {
+            self.velocity
+        }
+    }
+
+    #[test]
+    fn swept_collisions_test() {
+        let falling = [MovingStruct { boxes: box_list1(), velocity: V2::new(0., 50.) }];
+        let thin = [DummyStruct {
+            boxes: vec![BoundingBox {
+                mode: None,
+                tag: HitboxTag::Solid,
+                pos: V2::new(-10., 20.),
+                size: V2::new(20., 1.),
+                ori: 0.,
+            }],
+        }];
+        assert!(check_for_collision_pairs(&falling, &thin).is_empty());
+
+        let collisions = check_for_swept_collision_pairs(&falling, &thin);
+        assert!(collisions.len() == 1);
+        assert!(collisions[0].overlapping_hitboxes.len() == 2);
+        assert!((collisions[0].toi - 19. / 50.).abs() < 1e-5);
+
+        // Already overlapping.
+        let overlapping = [DummyStruct { boxes: box_list2() }];
+        let collisions = check_for_swept_collision_pairs(&falling, &overlapping);
+        assert!(collisions.len() == 1);
+        assert!(collisions[0].toi == 0.);
+    }
+
+    /// `n` objects scattered over a 1000x1000 area by a fixed pseudorandom sequence, each made of
+    /// a box and a rotated box next to it.
+    fn scattered(n: usize) -> Vec<DummyStruct> {
+        let mut seed = 0x2545_f491_u32;
+        let mut next = move || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 8) as f32 / (1 << 24) as f32
+        };
+        (0..n)
+            .map(|_| {
+                let pos = V2::new(next() * 1000., next() * 1000.);
+                DummyStruct {
+                    boxes: vec![BoundingBox {
+                        mode: None,
+                        tag: HitboxTag::Solid,
+                        pos,
+                        size: V2::new(10. + next() * 30., 10. + next() * 30.),
+                        ori: 0.,
+                    }, BoundingBox {
+                        mode: None,
+                        tag: HitboxTag::Hurt,
+                        pos: pos + V2::new(20., 0.),
+                        size: V2::new(15., 5.),
+                        ori: next() * std::f32::consts::PI,
+                    }],
+                }
+            })
+            .collect()
+    }
+
+    /// Whether any hitboxes of the two overlap, checking every pair of hitboxes directly.
+    fn naive_collides<T: Collidable, S: Collidable>(e0: &T, e1: &S) -> bool {
+        cartesian_product(e0.get_hitboxes(), e1.get_hitboxes())
+            .any(|(hb0, hb1)| PreparedBox::check_collision(&PreparedBox::new(hb0, e0.get_offset()), &PreparedBox::new(hb1, e1.get_offset())))
+    }
+
+    fn ids<T: Collidable, S: Collidable>(collisions: &[Collision<T, S>]) -> Vec<(usize, usize)> {
+        collisions.iter().map(|c| c.ids).collect()
+    }
+
+    #[test]
+    fn broad_phase_culls_candidates() {
+        let els = scattered(200);
+        let envelopes: Vec<_> = els.iter().map(Collidable::get_broad_aabb).collect();
+        let all_pairs = 200 * 199 / 2;
+        let candidates = broad::candidates(&envelopes).len();
+        assert!(candidates * 50 < all_pairs, "{} of {} pairs are candidates", candidates, all_pairs);
+        assert!(candidates >= check_for_collisions(&els).len());
+    }
+
+    #[test]
+    fn broad_phase_finds_every_collision() {
+        let els = scattered(200);
+        let expected: Vec<_> = unique_cartesian_square(0..els.len())
+            .filter(|&(id0, id1)| naive_collides(&els[id0], &els[id1]))
+            .collect();
+        assert!(!expected.is_empty());
+        assert!(ids(&check_for_collisions(&els)) == expected);
+
+        let (set1, set2) = els.split_at(50);
+        let expected: Vec<_> = cartesian_product(0..set1.len(), 0..set2.len())
+            .filter(|&(id0, id1)| naive_collides(&set1[id0], &set2[id1]))
+            .collect();
+        assert!(!expected.is_empty());
+        assert!(ids(&check_for_collision_pairs(set1, set2)) == expected);
+    }
+
+    #[test]
+    fn swept_broad_phase_follows_movement() {
+        let thin = [DummyStruct {
+            boxes: vec![BoundingBox {
+                mode: None,
+                tag: HitboxTag::Solid,
+                pos: V2::new(500., 20.),
+                size: V2::new(20., 1.),
+                ori: 0.,
+            }],
+        }];
+        // Far away, but gets there this tick.
+        let moving = [MovingStruct { boxes: box_list1(), velocity: V2::new(500., 20.) }];
+        assert!(check_for_swept_collision_pairs(&moving, &thin).len() == 1);
+        // Stays far away.
+        let still = [MovingStruct { boxes: box_list1(), velocity: V2::zeros() }];
+        assert!(check_for_swept_collision_pairs(&still, &thin).is_empty());
+    }
+
+    struct LayeredStruct {
+        boxes: Vec<BoundingBox>,
+        layer: u32,
+        mask: u32,
+    }
+    impl Collidable for LayeredStruct {
+        type ChangeSet = ();
+        fn get_hitboxes(&self) -> &[BoundingBox] {
+            &self.boxes
+        }
+        fn get_offset(&self) -> na::Vector2<f32> {
+            na::Vector2::new(0_f32, 0_f32)
+        }
+        fn collision_layer(&self) -> u32 {
+            self.layer
+        }
+        fn collision_mask(&self) -> u32 {
+            self.mask
+        }
+    }
+
+    #[test]
+    fn masked_pairs_skipped() {
+        let layered = |layer, mask| LayeredStruct { boxes: box_list1(), layer, mask };
+        // The first two ignore each other's layer, but the third collides with both.
+        let els = [layered(0b01, 0b10), layered(0b01, 0b10), layered(0b10, 0b11)];
+        assert!(ids(&check_for_collisions(&els)) == vec![(0, 2), (1, 2)]);
+        // Both have to collide with the other's layer.
+        let one_sided = [layered(0b01, 0b11), layered(0b10, 0b10)];
+        assert!(check_for_collisions(&one_sided).is_empty());
+
+        let moving = [MovingStruct { boxes: box_list1(), velocity: V2::new(0., 50.) }];
+        // Collides with nothing at all, even objects on every layer.
+        let ignoring = [layered(0b01, 0)];
+        assert!(check_for_collision_pairs(&moving, &ignoring).is_empty());
+        assert!(check_for_swept_collision_pairs(&moving, &ignoring).is_empty());
+        let colliding = [layered(0b01, 0b01)];
+        assert!(check_for_collision_pairs(&moving, &colliding).len() == 1);
+    }
 }
@@ -3,9 +3,44 @@ use ggez::graphics::{self, Drawable, DrawParam, Rect, BlendMode, Mesh, DrawMode}
 use ggez::nalgebra as na;
 use serde::{Serialize, Deserialize};
 
-use crate::physics::Collidable;
+use crate::physics::{Collidable, drawn_at};
 
-type Radians = f32;
+pub type Radians = f32;
+
+/// Boxes whose orientations differ by less than this are treated as aligned with each other.
+const ORI_EPSILON: f32 = 1e-7;
+
+/// What a `BoundingBox` stands for, which decides what happens when it overlaps another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum HitboxTag {
+    /// Takes up space. Solid boxes push each other apart, and are what platforms are made of.
+    #[default]
+    Solid,
+    /// Where a player can be hurt.
+    Hurt,
+    /// Deals damage to any `Hurt` or `Shield` box it overlaps.
+    Hit,
+    /// Catches the owner of any `Hurt` or `Shield` box it overlaps. Grabs ignore shields.
+    Grab,
+    /// A player's shield, taking the place of their `Hurt` boxes. Hits landing on it damage the
+    /// shield instead of the player.
+    Shield,
+}
+
+impl HitboxTag {
+    /// Whether `Hit` boxes do anything to this box.
+    pub fn can_be_hit(self) -> bool {
+        match self {
+            HitboxTag::Hurt | HitboxTag::Shield => true,
+            HitboxTag::Solid | HitboxTag::Hit | HitboxTag::Grab => false,
+        }
+    }
+
+    /// Whether `Grab` boxes do anything to this box.
+    pub fn can_be_grabbed(self) -> bool {
+        self.can_be_hit()
+    }
+}
 
 /// Denotes an `area` is being occupied.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,17 +48,20 @@ pub struct BoundingBox {
     /// `ggez`-specific. Not used for anything atm.
     #[serde(skip)]
     pub mode: Option<BlendMode>,
-    /// The pos (x, h) of the bounds. +x goes up and +y goes right.
+    #[serde(default)]
+    pub tag: HitboxTag,
+    /// The pos (x, y) of the bounds' top left corner, before rotating. +x goes right and +y goes
+    /// down, like on the screen.
     pub pos: na::Vector2<f32>,
     /// The size (w, h) of the bounds.
     pub size: na::Vector2<f32>,
-    /// Orientation, i.e. radians to rotate the box in the counterclockwise directions.
+    /// Orientation, i.e. radians to rotate the box about `pos`, clockwise on the screen.
     pub ori: Radians,
 }
 
 impl BoundingBox {
-    /// Rotates a point counterclockwise.
-    fn rotate(point: na::Vector2<f32>, ori: Radians) -> na::Vector2<f32> {
+    /// Rotates a point about the origin, clockwise on the screen since +y goes down.
+    pub fn rotate(point: na::Vector2<f32>, ori: Radians) -> na::Vector2<f32> {
         na::Vector2::new(
             ori.cos() * point[0] - ori.sin() * point[1],
             ori.sin() * point[0] + ori.cos() * point[1],
@@ -34,10 +72,10 @@ impl BoundingBox {
     /// column corresponds to which corner when the bounding box is not rotated.
     ///
     /// ```
-    /// 2 ---------------- 4
+    /// 1 ---------------- 3
     /// |                  |
     /// |                  |
-    /// 1 ---------------- 3
+    /// 2 ---------------- 4
     /// ```
     fn base_corners(&self) -> na::Matrix2x4<f32> {
         na::Matrix4x2::new(
@@ -47,7 +85,7 @@ impl BoundingBox {
             self.size[0], self.size[1]
         ).transpose()
     }
-    /// A rotation matrix for turning a 2D point counterclockwise `ori` radians.
+    /// A rotation matrix for turning a 2D point `ori` radians clockwise on the screen.
     fn rot_matrix(&self) -> na::Matrix2<f32> {
         na::Matrix2::new(
             self.ori.cos(), -self.ori.sin(),
@@ -80,54 +118,158 @@ impl BoundingBox {
         )
     }
 
-    /// Check if a collision can be detected from one of the two boxes.
-    /// Check the module-level doc to understand our collision detection algorithm.
-    ///
-    /// A full collision check requires two calls to this with flipped parameters, so this is
-    /// termed `half` a collision check.
-    fn check_half_collision(&self, basis: &BoundingBox) -> bool {
-        let rhs = self.normalized_wrt(basis);
-        let lhs_bounds = basis.size;
-        let rhs_bounds = rhs.bounds();
-        // Bounds checking is rather ... complicated/involved.
-        //
-        // There are four obvious cases, but all four can be collapsed to the following two.
-        // The key insight is that the maximum of the minimum of both bounds must be less than the
-        // minimum of the maximum of both bounds for there to be an overlap of two bounds.
-
-        f32::max(rhs_bounds[(0, 0)], 0f32) <= f32::min(rhs_bounds[(0, 1)], lhs_bounds[0])
-            && f32::max(rhs_bounds[(1, 0)], 0f32) <= f32::min(rhs_bounds[(1, 1)], lhs_bounds[1])
-    }
-    /// Checks if two `BoundingBox`es collide.
-    /// Check the module-level doc to understand our collision detection algorithm.
-    ///
-    /// The underlying logic is that if any edge can show a separation between the two boxes, then
-    /// the two boxes do not intersect.
-    pub fn check_collision(lhs: &BoundingBox, rhs: &BoundingBox) -> bool {
-        // If bounding boxes have almost the same EPSILON, only half of the check is necessary
-        // since it approximates an AABB check. The secondary check becomes a simple offset of the
-        // first check.
-        let does_not_need_secondary_check = BoundingBox::is_almost_axis_aligned(lhs, rhs);
+    /// The middle of the box, wherever it's turned.
+    pub fn center(&self) -> na::Vector2<f32> {
+        self.pos + Self::rotate(self.size / 2., self.ori)
+    }
 
-        lhs.check_half_collision(rhs) && (does_not_need_secondary_check || rhs.check_half_collision(lhs))
+    /// The corners of the smallest axis aligned box containing this one, as (min, max).
+    pub fn extents(&self) -> (na::Vector2<f32>, na::Vector2<f32>) {
+        let bounds = self.bounds();
+        (
+            na::Vector2::new(bounds[(0, 0)], bounds[(1, 0)]),
+            na::Vector2::new(bounds[(0, 1)], bounds[(1, 1)]),
+        )
     }
 
-    /// Checks if two bounding boxes are almost axis aligned. Namely, if their orientations are
-    /// almost equivalent.
-    fn is_almost_axis_aligned(lhs: &BoundingBox, rhs: &BoundingBox) -> bool {
-        const ORI_EPSILON: f32 = 1e-7;
-        (rhs.ori - lhs.ori).abs() < ORI_EPSILON
+    /// The top of the box straight above or below `x`, or `None` if the box doesn't reach that far
+    /// across. Unlike `extents`, this follows the outline of rotated boxes.
+    pub fn top_at(&self, x: f32) -> Option<f32> {
+        let corners = self.corners();
+        // Going around the outline, i.e. 1, 3, 4 and 2 in the diagram of `base_corners`.
+        let outline = [0, 2, 3, 1, 0];
+        outline.windows(2)
+            .filter_map(|edge| {
+                let (a, b) = (corners.column(edge[0]), corners.column(edge[1]));
+                let (left, right) = if a[0] <= b[0] { (a, b) } else { (b, a) };
+                if x < left[0] || x > right[0] {
+                    None
+                } else if (right[0] - left[0]).abs() < f32::EPSILON {
+                    Some(left[1].min(right[1]))
+                } else {
+                    Some(left[1] + (right[1] - left[1]) * (x - left[0]) / (right[0] - left[0]))
+                }
+            })
+            .fold(None, |top: Option<f32>, y| Some(top.map_or(y, |top| top.min(y))))
+    }
+
+    /// The shortest translation that moves this box out of `other`, or zero if they don't
+    /// overlap. Uses the axis aligned extents of both boxes, so rotated boxes are pushed out of
+    /// their extents rather than their exact outlines.
+    pub fn penetration(&self, other: &BoundingBox) -> na::Vector2<f32> {
+        let (min, max) = self.extents();
+        let (other_min, other_max) = other.extents();
+        let overlap = max.zip_map(&other_max, f32::min) - min.zip_map(&other_min, f32::max);
+        if overlap[0] <= 0. || overlap[1] <= 0. {
+            return na::Vector2::zeros();
+        }
+        // Push away from the center of `other` along whichever axis overlaps the least.
+        let axis = if overlap[0] < overlap[1] { 0 } else { 1 };
+        let direction = if min[axis] + max[axis] < other_min[axis] + other_max[axis] { -1. } else { 1. };
+        let mut translation = na::Vector2::zeros();
+        translation[axis] = direction * overlap[axis];
+        translation
+    }
+
+    /// The earliest time in [0, 1] at which this box, moving by `displacement` over a tick,
+    /// touches `other`. Boxes that already overlap collide at 0. Like `penetration`, only the axis
+    /// aligned extents of both boxes are considered.
+    pub fn sweep_collision(&self, displacement: na::Vector2<f32>, other: &BoundingBox) -> Option<f32> {
+        let (min, max) = self.extents();
+        let (other_min, other_max) = other.extents();
+        let (mut entry, mut exit) = (0f32, 1f32);
+        for axis in 0..2 {
+            let d = displacement[axis];
+            if d.abs() < f32::EPSILON {
+                // Never moves along this axis, so it must already overlap along it.
+                if max[axis] < other_min[axis] || min[axis] > other_max[axis] {
+                    return None;
+                }
+            } else {
+                let t0 = (other_min[axis] - max[axis]) / d;
+                let t1 = (other_max[axis] - min[axis]) / d;
+                entry = entry.max(t0.min(t1));
+                exit = exit.min(t0.max(t1));
+            }
+        }
+        if entry <= exit {
+            Some(entry)
+        } else {
+            None
+        }
     }
+}
 
-    /// Normalize a box w.r.t. a basis.
-    fn normalized_wrt(&self, basis: &Self) -> Self {
+/// A `BoundingBox` moved into world coordinates, with the sine and cosine of its orientation
+/// worked out ahead of time. Collision checks between prepared boxes don't call any trigonometric
+/// functions, so boxes are prepared once per tick rather than for every pair they are checked
+/// against.
+#[derive(Debug, Clone)]
+pub struct PreparedBox<'a> {
+    /// The box this was prepared from.
+    pub source: &'a BoundingBox,
+    pos: na::Vector2<f32>,
+    size: na::Vector2<f32>,
+    ori: Radians,
+    cos: f32,
+    sin: f32,
+}
+
+impl<'a> PreparedBox<'a> {
+    /// Prepares `source` as if moved by `offset`.
+    pub fn new(source: &'a BoundingBox, offset: na::Vector2<f32>) -> Self {
         Self {
-            mode: None,
-            pos: Self::rotate(self.pos - basis.pos, self.ori - basis.ori),
-            size: self.size,
-            ori: self.ori - basis.ori,
+            source,
+            pos: source.pos + offset,
+            size: source.size,
+            ori: source.ori,
+            cos: source.ori.cos(),
+            sin: source.ori.sin(),
         }
     }
+
+    /// Prepares all of `boxes` as if moved by `offset`.
+    pub fn all(boxes: &'a [BoundingBox], offset: na::Vector2<f32>) -> Vec<Self> {
+        boxes.iter().map(|bb| Self::new(bb, offset)).collect()
+    }
+
+    /// Checks if a collision can be detected from `basis`, by turning this box into the basis' frame
+    /// and checking whether the two overlap along the basis' axes. The cosine and sine of the angle
+    /// between the boxes come from the angle difference identities.
+    ///
+    /// A full collision check requires two calls to this with flipped parameters, so this is
+    /// termed `half` a collision check.
+    fn check_half_collision(&self, basis: &PreparedBox) -> bool {
+        let (cos, sin) = if self.ori == basis.ori {
+            (1., 0.)
+        } else {
+            (
+                self.cos * basis.cos + self.sin * basis.sin,
+                self.sin * basis.cos - self.cos * basis.sin,
+            )
+        };
+        let d = self.pos - basis.pos;
+        let pos = na::Vector2::new(cos * d[0] - sin * d[1], sin * d[0] + cos * d[1]);
+        // The corners are `pos` plus any combination of the two rotated edges, so each bound is
+        // `pos` plus the smaller or larger end of both edges. The bounds of both boxes overlap when
+        // the larger of their minimums is no more than the smaller of their maximums.
+        let (w, h) = (self.size[0], self.size[1]);
+        let edge_bounds = |a: f32, b: f32| (a.min(0.) + b.min(0.), a.max(0.) + b.max(0.));
+        let (min_x, max_x) = edge_bounds(cos * w, -sin * h);
+        let (min_y, max_y) = edge_bounds(sin * w, cos * h);
+
+        f32::max(pos[0] + min_x, 0f32) <= f32::min(pos[0] + max_x, basis.size[0])
+            && f32::max(pos[1] + min_y, 0f32) <= f32::min(pos[1] + max_y, basis.size[1])
+    }
+
+    /// Checks if two prepared boxes collide. If any edge can show a separation between the two
+    /// boxes, then the two boxes do not intersect.
+    pub fn check_collision(lhs: &PreparedBox, rhs: &PreparedBox) -> bool {
+        // Boxes turned almost the same way only need half of the check, since it approximates an
+        // AABB check and the other half would be a simple offset of the first.
+        let does_not_need_secondary_check = (rhs.ori - lhs.ori).abs() < ORI_EPSILON;
+        lhs.check_half_collision(rhs) && (does_not_need_secondary_check || rhs.check_half_collision(lhs))
+    }
 }
 
 impl Collidable for BoundingBox {
@@ -146,11 +288,10 @@ impl Collidable for BoundingBox {
 impl Drawable for BoundingBox {
     fn draw(&self, ctx: &mut Context, mut param: DrawParam) -> GameResult {
         let rect = Rect::new(0f32, 0f32, 1.0, 1.0);
+        param = drawn_at(param, self.pos);
         param.rotation += self.ori;
         param.scale.x *= self.size[0];
         param.scale.y *= self.size[1];
-        param.dest.x += self.pos[0];
-        param.dest.y += self.pos[1];
         let mesh = Mesh::new_rectangle(ctx, DrawMode::fill(), rect, graphics::WHITE)?;
         graphics::draw(ctx, &mesh, param)
     }
@@ -180,6 +321,7 @@ mod obb_test {
     fn build_bounding() -> BoundingBox {
         BoundingBox {
             mode: None,
+            tag: HitboxTag::Solid,
             pos: V2::new(1., 2.),
             size: V2::new(3., 4.),
             ori: std::f32::consts::PI / 2.,
@@ -228,14 +370,32 @@ mod obb_test {
         assert!(approx_eq(V2::from(bounds.row(1).transpose()), V2::new( 2., 5.)));
     }
 
+    #[test]
+    fn obb_top_follows_outline() {
+        let mut bounding = build_bounding();
+        // Standing on end, from x = -3 to 1.
+        assert!(bounding.top_at(-1.).unwrap() == 2.);
+        assert!(bounding.top_at(1.5).is_none());
+        // A diamond, peaking at its top left corner.
+        bounding.ori = std::f32::consts::PI / 4.;
+        assert!(bounding.top_at(1.).unwrap() == 2.);
+        let side = |x: f32| 2. + (x - 1.).abs();
+        for &x in &[-1., 0., 2., 3.] {
+            assert!((bounding.top_at(x).unwrap() - side(x)).abs() < 1e-5);
+        }
+        assert!(bounding.top_at(-2.).is_none());
+    }
+
     fn colliding_boxes() -> (BoundingBox, BoundingBox) {
         (BoundingBox {
             mode: None,
+            tag: HitboxTag::Solid,
             pos: V2::zeros(),
             size: V2::new(1., 1.),
             ori: 0.,
         }, BoundingBox {
             mode: None,
+            tag: HitboxTag::Solid,
             pos: V2::zeros(),
             size: V2::new(1., 1.),
             ori: 0.,
@@ -244,11 +404,13 @@ mod obb_test {
     fn separate_boxes() -> (BoundingBox, BoundingBox)  {
         (BoundingBox {
             mode: None,
+            tag: HitboxTag::Solid,
             pos: V2::zeros(),
             size: V2::new(1., 1.),
             ori: 0.,
         }, BoundingBox {
             mode: None,
+            tag: HitboxTag::Solid,
             pos: V2::new(-0.1, -0.1),
             size: V2::new(1., 1.),
             ori: std::f32::consts::PI,
@@ -257,30 +419,39 @@ mod obb_test {
     fn pathological_separate_boxes() -> (BoundingBox, BoundingBox) {
         (BoundingBox {
             mode: None,
+            tag: HitboxTag::Solid,
             pos: V2::zeros(),
             size: V2::new(1., 1.),
             ori: 0.,
         }, BoundingBox {
             mode: None,
+            tag: HitboxTag::Solid,
             pos: V2::new(1.5, 0.5),
             size: V2::new(5., 0.5),
             ori: std::f32::consts::PI / 4.,
         })
     }
 
+    fn collides(a: &BoundingBox, b: &BoundingBox) -> bool {
+        PreparedBox::check_collision(&PreparedBox::new(a, V2::zeros()), &PreparedBox::new(b, V2::zeros()))
+    }
+
     #[test]
     fn obb_half_collision() {
+        let half = |a: &BoundingBox, b: &BoundingBox| {
+            PreparedBox::new(a, V2::zeros()).check_half_collision(&PreparedBox::new(b, V2::zeros()))
+        };
         { // separate
             let (a, b) = separate_boxes();
-            assert!(!a.check_half_collision(&b))
+            assert!(!half(&a, &b))
         }
         { // colliding
             let (a, b) = colliding_boxes();
-            assert!(a.check_half_collision(&b));
+            assert!(half(&a, &b));
         }
         { // pathological separate
             let (a, b) = pathological_separate_boxes();
-            assert!(a.check_half_collision(&b));
+            assert!(half(&a, &b));
         }
     }
 
@@ -288,31 +459,124 @@ mod obb_test {
     fn obb_collision() {
         { // separate
             let (a, b) = separate_boxes();
-            assert!(!BoundingBox::check_collision(&a, &b));
+            assert!(!collides(&a, &b));
         }
         { // colliding
             let (a, b) = colliding_boxes();
-            assert!(BoundingBox::check_collision(&a, &b));
+            assert!(collides(&a, &b));
         }
         { // pathological separate
             let (a, b) = pathological_separate_boxes();
-            assert!(!BoundingBox::check_collision(&a, &b));
+            assert!(!collides(&a, &b));
         }
     }
 
     #[test]
-    fn obb_norm_wrt() {
-        let b = build_bounding();
-        let normed = b.normalized_wrt(&b);
-        assert!(approx_eq(normed.pos, V2::new(0., 0.)));
-        assert!(approx_eq(normed.size, V2::new(3., 4.)));
-        assert!(normed.ori.abs() < 1e-5);
-        let mut weird_b = b.clone();
-        weird_b.pos[0] = 0.;
-        weird_b.ori = -std::f32::consts::PI / 2.;
-        let normed = b.normalized_wrt(&weird_b);
-        assert!(approx_eq(normed.pos, V2::new(-1., 0.)));
-        assert!(approx_eq(normed.size, V2::new(3., 4.)));
-        assert!((normed.ori - std::f32::consts::PI).abs() < 1e-5);
+    fn obb_extents() {
+        let (min, max) = build_bounding().extents();
+        assert!(approx_eq(min, V2::new(-3., 2.)));
+        assert!(approx_eq(max, V2::new(1., 5.)));
+    }
+
+    #[test]
+    fn obb_penetration() {
+        let (a, mut b) = colliding_boxes();
+        b.pos = V2::new(0.2, 0.9);
+        // Overlaps least vertically, and `a` is above `b`.
+        assert!(approx_eq(a.penetration(&b), V2::new(0., -0.1)));
+        assert!(approx_eq(b.penetration(&a), V2::new(0., 0.1)));
+        b.pos = V2::new(-0.8, 0.5);
+        assert!(approx_eq(a.penetration(&b), V2::new(0.2, 0.)));
+        let (a, b) = separate_boxes();
+        assert!(approx_eq(a.penetration(&BoundingBox { pos: V2::new(2., 2.), ..b }), V2::zeros()));
+    }
+
+    fn thin_platform() -> BoundingBox {
+        BoundingBox {
+            mode: None,
+            tag: HitboxTag::Solid,
+            pos: V2::new(-10., 10.),
+            size: V2::new(20., 1.),
+            ori: 0.,
+        }
+    }
+
+    #[test]
+    fn obb_sweep_through_thin_box() {
+        let (a, _) = colliding_boxes();
+        let toi = a.sweep_collision(V2::new(0., 50.), &thin_platform());
+        // The bottom of `a` reaches the top of the platform after moving 9 of 50.
+        assert!((toi.unwrap() - 9. / 50.).abs() < 1e-5);
+        assert!(!collides(&BoundingBox { pos: V2::new(0., 50.), ..a.clone() }, &thin_platform()));
+    }
+
+    #[test]
+    fn obb_sweep_misses() {
+        let (a, _) = colliding_boxes();
+        assert!(a.sweep_collision(V2::new(0., 5.), &thin_platform()).is_none());
+        assert!(a.sweep_collision(V2::new(0., -50.), &thin_platform()).is_none());
+        assert!(a.sweep_collision(V2::new(50., 30.), &thin_platform()).is_none());
+        assert!(a.sweep_collision(V2::zeros(), &thin_platform()).is_none());
+    }
+
+    #[test]
+    fn obb_sweep_overlapping() {
+        let (a, b) = colliding_boxes();
+        assert!(a.sweep_collision(V2::new(3., 0.), &b) == Some(0.));
+        assert!(a.sweep_collision(V2::zeros(), &b) == Some(0.));
+    }
+
+    /// Boxes of all sizes and orientations scattered around the origin, from a fixed pseudorandom
+    /// sequence.
+    fn scattered_boxes(n: usize) -> Vec<BoundingBox> {
+        let mut seed = 0x9e37_79b9_u32;
+        let mut next = move || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 8) as f32 / (1 << 24) as f32
+        };
+        (0..n)
+            .map(|i| BoundingBox {
+                mode: None,
+                tag: HitboxTag::Solid,
+                pos: V2::new(next() * 20. - 10., next() * 20. - 10.),
+                size: V2::new(1. + next() * 5., 1. + next() * 5.),
+                // Leave some boxes unrotated, as most are in practice.
+                ori: if i % 3 == 0 { 0. } else { next() * 2. * std::f32::consts::PI },
+            })
+            .collect()
+    }
+
+    #[test]
+    fn prepared_agrees_with_extents() {
+        let boxes = scattered_boxes(100);
+        let prepared = PreparedBox::all(&boxes, V2::zeros());
+        let extents_overlap = |a: &BoundingBox, b: &BoundingBox| {
+            let ((a_min, a_max), (b_min, b_max)) = (a.extents(), b.extents());
+            (0..2).all(|axis| a_min[axis] <= b_max[axis] && b_min[axis] <= a_max[axis])
+        };
+        let mut collisions = 0;
+        for (a, prepared_a) in boxes.iter().zip(prepared.iter()) {
+            for (b, prepared_b) in boxes.iter().zip(prepared.iter()) {
+                let collides = PreparedBox::check_collision(prepared_a, prepared_b);
+                assert!(collides == PreparedBox::check_collision(prepared_b, prepared_a), "{:?} {:?}", a, b);
+                // Unrotated boxes collide exactly when their extents overlap.
+                if a.ori == 0. && b.ori == 0. {
+                    assert!(collides == extents_overlap(a, b), "{:?} {:?}", a, b);
+                }
+                collisions += collides as usize;
+            }
+        }
+        // Both colliding and separate pairs were checked.
+        assert!(collisions > 100 && collisions < 100 * 100);
+
+        let (a, b) = pathological_separate_boxes();
+        let (a, b) = (PreparedBox::new(&a, V2::zeros()), PreparedBox::new(&b, V2::zeros()));
+        assert!(a.check_half_collision(&b));
+        assert!(!PreparedBox::check_collision(&a, &b));
+        // Offsets move the boxes.
+        let (a, b) = colliding_boxes();
+        let a = PreparedBox::new(&a, V2::new(10., 10.));
+        assert!(PreparedBox::check_collision(&a, &PreparedBox::new(&b, V2::new(10.5, 10.5))));
+        assert!(!PreparedBox::check_collision(&a, &PreparedBox::new(&b, V2::new(11.5, 10.))));
     }
 }
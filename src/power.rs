@@ -0,0 +1,127 @@
+//! Saves power while the game is left in the background.
+//!
+//! While the window isn't focused, e.g. once minimized, the game only runs a few frames a second.
+//! The simulation still runs in full, catching up on the update windows that passed during each
+//! frame. If no input arrives for a while on top of that, battles are paused so nothing needs
+//! simulating at all. Focusing the window again goes back to full speed straight away.
+use std::time::Duration;
+
+/// How long each frame lasts in low power mode, i.e. 5 frames a second.
+pub const LOW_POWER_FRAME: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerState {
+    /// Running at full speed.
+    Focused,
+    /// In the background, running at `LOW_POWER_FRAME`s.
+    Unfocused,
+    /// In the background without any input for a while. Battles should be paused.
+    Idle,
+}
+
+/// Keeps track of focus and input to decide the `PowerState`. Times are measured from the start
+/// of the game.
+#[derive(Debug)]
+pub struct PowerMonitor {
+    focused: bool,
+    last_input: Duration,
+    /// How long without input in the background before going idle.
+    idle_after: Duration,
+}
+
+impl PowerMonitor {
+    pub fn new(idle_after: Duration) -> Self {
+        Self {
+            focused: true,
+            last_input: Duration::from_secs(0),
+            idle_after,
+        }
+    }
+
+    /// Focusing the window counts as input.
+    pub fn handle_focus(&mut self, gained: bool, now: Duration) {
+        self.focused = gained;
+        if gained {
+            self.handle_input(now);
+        }
+    }
+
+    pub fn handle_input(&mut self, now: Duration) {
+        self.last_input = self.last_input.max(now);
+    }
+
+    pub fn state(&self, now: Duration) -> PowerState {
+        if self.focused {
+            PowerState::Focused
+        } else if now.checked_sub(self.last_input).unwrap_or_default() >= self.idle_after {
+            PowerState::Idle
+        } else {
+            PowerState::Unfocused
+        }
+    }
+}
+
+#[cfg(test)]
+mod power_test {
+    use super::*;
+    use crate::timing::WindowClock;
+    use crate::walpurgis::TICKS_PER_SECOND;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn focus_and_input_transitions() {
+        let mut power = PowerMonitor::new(secs(60));
+        assert!(power.state(secs(1000)) == PowerState::Focused);
+        power.handle_focus(false, secs(10));
+        // Losing focus isn't input, so idling counts from the last input.
+        assert!(power.state(secs(10)) == PowerState::Unfocused);
+        assert!(power.state(secs(59)) == PowerState::Unfocused);
+        assert!(power.state(secs(60)) == PowerState::Idle);
+        power.handle_input(secs(70));
+        assert!(power.state(secs(100)) == PowerState::Unfocused);
+        assert!(power.state(secs(130)) == PowerState::Idle);
+        power.handle_focus(true, secs(200));
+        assert!(power.state(secs(200)) == PowerState::Focused);
+        power.handle_focus(false, secs(210));
+        // Regaining focus counted as input.
+        assert!(power.state(secs(259)) == PowerState::Unfocused);
+        assert!(power.state(secs(260)) == PowerState::Idle);
+    }
+
+    #[test]
+    fn late_events_never_go_back_in_time() {
+        let mut power = PowerMonitor::new(secs(60));
+        power.handle_focus(false, secs(0));
+        power.handle_input(secs(50));
+        power.handle_input(secs(40));
+        assert!(power.state(secs(100)) == PowerState::Unfocused);
+        // An input timestamped after `now` doesn't make `now` idle either.
+        power.handle_input(secs(200));
+        assert!(power.state(secs(150)) == PowerState::Unfocused);
+    }
+
+    /// Counts the update windows run over `frames` frames of length `frame`.
+    fn windows_over(frames: u32, frame: Duration) -> u32 {
+        let mut clock = WindowClock::default();
+        let mut windows = 0;
+        for _ in 0..frames {
+            clock.advance(frame);
+            while clock.take_window(TICKS_PER_SECOND) {
+                windows += 1;
+            }
+        }
+        windows
+    }
+
+    #[test]
+    fn low_power_runs_the_same_ticks() {
+        // A minute at 60 frames a second or in low power mode.
+        let focused = windows_over(3600, Duration::from_secs(1) / 60);
+        let unfocused = windows_over(300, LOW_POWER_FRAME);
+        assert!(unfocused == 60 * TICKS_PER_SECOND);
+        assert!((focused as i64 - unfocused as i64).abs() <= 1);
+    }
+}
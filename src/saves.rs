@@ -0,0 +1,377 @@
+//! The progress of everyone who plays on this machine, kept between sessions.
+//!
+//! Saves are versioned. A save written by an older version of the game is brought up to date by
+//! running every migration after its version in turn, so that changes to the format never lose
+//! progress. Saves from newer versions are refused rather than read wrong.
+//!
+//! Saves end with a line holding the BLAKE3 hash of everything before it, in hex, so that saves
+//! damaged on disk are noticed instead of read wrong. Saves from before checksums have no such
+//! line and are read as they are.
+use ron::de::from_str;
+use ron::ser::{to_string_pretty, PrettyConfig};
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    persistence::{PersistenceWorker, WriteJob, WritePriority},
+    screens::battle::player::meta::Race,
+    settings,
+    skill_tree::SkillTree,
+    util::result::{WalpurgisError, WalpurgisResult},
+};
+
+/// Brings a save up from the version before it. Fields added since come in at their defaults,
+/// so migrations only have to fill in what can be worked out from the old ones.
+pub type Migration = fn(&mut SaveData);
+
+/// The migration up to each version after the first, in order: the first one brings version 1
+/// saves up to version 2.
+const MIGRATIONS: &[Migration] = &[];
+
+/// The version of the save format, bumped by adding a migration.
+pub const SAVE_VERSION: u32 = MIGRATIONS.len() as u32 + 1;
+
+/// The save's file name in the user's data directory, unless the settings say otherwise.
+pub const SAVE_FILE: &str = "save.ron";
+
+/// The number of matches kept in the history. Older ones are forgotten.
+pub const MATCH_HISTORY_LENGTH: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SaveData {
+    /// The version of the format the save was written in.
+    pub version: u32,
+    /// Everyone's profile, in the order they play in.
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+    /// The last `MATCH_HISTORY_LENGTH` matches played, oldest first.
+    #[serde(default)]
+    pub match_history: Vec<MatchRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub name: String,
+    /// The player in `settings.controls.players` whose controls the profile plays with.
+    pub input_scheme: usize,
+    /// The character last played, by name.
+    pub character: Option<String>,
+    /// Not used yet.
+    pub skill_points: u32,
+    /// The characters unlocked, by name.
+    pub unlocked_characters: Vec<String>,
+    /// The arenas unlocked, by name.
+    pub unlocked_arenas: Vec<String>,
+    /// The matches won.
+    pub total_wins: u32,
+    /// The skill tree of each race played, as left on the results screen.
+    pub skill_trees: BTreeMap<Race, SkillTree>,
+    /// The combo trials completed, by name.
+    pub completed_trials: Vec<String>,
+}
+
+/// How a match went.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MatchRecord {
+    /// When the match ended, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The race each player played, in the order they play in.
+    pub participants: Vec<Race>,
+    /// The index of the player who won, unless it was a draw.
+    pub winner: Option<usize>,
+    /// How long the match lasted, in ticks.
+    pub duration: u64,
+}
+
+impl MatchRecord {
+    /// The time right now, as a `timestamp`.
+    pub fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs())
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl Profile {
+    /// A fresh profile for the player at `idx`.
+    pub fn new(idx: usize) -> Self {
+        Self {
+            name: format!("Player {}", idx + 1),
+            input_scheme: idx,
+            character: None,
+            skill_points: 0,
+            unlocked_characters: vec![],
+            unlocked_arenas: vec![],
+            total_wins: 0,
+            skill_trees: BTreeMap::new(),
+            completed_trials: vec![],
+        }
+    }
+
+    pub fn has_completed_trial(&self, trial: &str) -> bool {
+        self.completed_trials.iter().any(|completed| completed == trial)
+    }
+
+    /// Marks the combo trial called `trial` as completed. Returns whether it wasn't already.
+    pub fn complete_trial(&mut self, trial: &str) -> bool {
+        if self.has_completed_trial(trial) {
+            return false;
+        }
+        self.completed_trials.push(trial.to_owned());
+        true
+    }
+}
+
+/// Only the version of a save, which can be read from saves of any version.
+#[derive(Deserialize)]
+struct Versioned {
+    version: u32,
+}
+
+impl Default for SaveData {
+    fn default() -> Self {
+        Self {
+            version: SAVE_VERSION,
+            profiles: vec![Profile::new(0)],
+            match_history: vec![],
+        }
+    }
+}
+
+impl SaveData {
+    /// Tries to load a `SaveData` from the given file, migrating it from older versions. Errors
+    /// name the file.
+    pub fn load<P: AsRef<Path>>(save_file: P) -> WalpurgisResult<Self> {
+        let save_file = save_file.as_ref();
+        let contents = fs::read_to_string(save_file).map_err(|e| WalpurgisError::reading(save_file, "save", e))?;
+        Self::parse(verified(&contents, save_file)?, save_file, MIGRATIONS)
+    }
+
+    /// Loads the save in `save_file`, starting afresh if there isn't one or it can't be read.
+    pub fn load_or_default<P: AsRef<Path>>(save_file: P) -> Self {
+        let save_file = save_file.as_ref();
+        match Self::load(save_file) {
+            Ok(save) => save,
+            Err(WalpurgisError::AssetNotFound { .. }) => {
+                log::info!("No save at `{}` yet, starting afresh.", save_file.display());
+                Self::default()
+            },
+            Err(e @ WalpurgisError::CorruptSave(_)) => {
+                log::error!("{} Starting afresh.", e);
+                Self::default()
+            },
+            Err(e) => {
+                log::warn!("Failed to load the save, starting afresh: {}", e);
+                Self::default()
+            },
+        }
+    }
+
+    /// Parses the contents of a save file, `source`, and runs whichever of `migrations` it needs.
+    /// Saves that don't parse are blamed on their version when it's newer than `migrations` know.
+    fn parse(contents: &str, source: &Path, migrations: &[Migration]) -> WalpurgisResult<Self> {
+        let supported = migrations.len() as u32 + 1;
+        let mut save = match from_str::<Self>(contents) {
+            Ok(save) => save,
+            Err(e) => return match from_str::<Versioned>(contents) {
+                Ok(Versioned { version }) if version > supported => Err(Self::unsupported(source, version, supported)),
+                _ => Err(WalpurgisError::parsing(source, "save", e)),
+            },
+        };
+        if save.version == 0 || save.version > supported {
+            return Err(Self::unsupported(source, save.version, supported));
+        }
+        for (version, migration) in (save.version..supported).zip(&migrations[save.version as usize - 1..]) {
+            log::info!("Migrating the save from version {} to {}.", version, version + 1);
+            migration(&mut save);
+            save.version = version + 1;
+        }
+        Ok(save)
+    }
+
+    fn unsupported(source: &Path, found: u32, supported: u32) -> WalpurgisError {
+        WalpurgisError::UnsupportedVersion {
+            path: source.to_path_buf(),
+            what: "save",
+            found,
+            supported,
+        }
+    }
+
+    /// Queues the save to be written to `save_file` by `persistence`. The file is replaced whole,
+    /// so quitting halfway through leaves the last save as it was.
+    pub fn save<P: AsRef<Path>>(&self, save_file: P, persistence: &PersistenceWorker) -> WalpurgisResult {
+        let job = WriteJob::new(save_file.as_ref(), self.serialize()?.into_bytes(), WritePriority::Critical).atomic();
+        if !persistence.enqueue(job) {
+            Err(format!("Dropped save write to `{}`.", save_file.as_ref().display()))?
+        }
+        Ok(())
+    }
+
+    /// The save as written to its file, checksum and all.
+    fn serialize(&self) -> WalpurgisResult<String> {
+        let serialized = to_string_pretty(self, PrettyConfig::default())
+            .map_err(|e| format!("Failed to serialize save: {}", e))?;
+        let checksum = blake3::hash(serialized.as_bytes()).to_hex();
+        Ok(format!("{}\n{}\n", serialized, checksum))
+    }
+
+    /// The profile of the player at `idx`, made for them if they don't have one yet.
+    pub fn profile_mut(&mut self, idx: usize) -> &mut Profile {
+        while self.profiles.len() <= idx {
+            self.profiles.push(Profile::new(self.profiles.len()));
+        }
+        &mut self.profiles[idx]
+    }
+
+    /// Adds `record` to the match history, crediting the winner's profile with the win.
+    pub fn record_match(&mut self, record: MatchRecord) {
+        if let Some(winner) = record.winner {
+            self.profile_mut(winner).total_wins += 1;
+        }
+        self.match_history.push(record);
+        let forgotten = self.match_history.len().saturating_sub(MATCH_HISTORY_LENGTH);
+        self.match_history.drain(..forgotten);
+    }
+
+    /// The saved skill tree of `race` for the player at `idx`, if they have played it.
+    pub fn skill_tree(&self, idx: usize, race: &Race) -> Option<&SkillTree> {
+        self.profiles.get(idx)?.skill_trees.get(race)
+    }
+
+    /// The character each profile last played, in order.
+    pub fn characters(&self) -> Vec<Option<String>> {
+        self.profiles.iter().map(|profile| profile.character.clone()).collect()
+    }
+}
+
+/// The contents of the save file `source` without their checksum line, if they match it. Contents
+/// without one are taken as they are.
+fn verified<'a>(contents: &'a str, source: &Path) -> WalpurgisResult<&'a str> {
+    let (body, last_line) = match contents.trim_end_matches('\n').rsplit_once('\n') {
+        Some(split) => split,
+        None => return Ok(contents),
+    };
+    let is_checksum = last_line.len() == 2 * blake3::OUT_LEN && last_line.chars().all(|c| c.is_ascii_hexdigit());
+    if !is_checksum {
+        Ok(contents)
+    } else if blake3::hash(body.as_bytes()).to_hex().as_str() == last_line {
+        Ok(body)
+    } else {
+        Err(WalpurgisError::CorruptSave(source.to_path_buf()))
+    }
+}
+
+/// Where the save is kept: the file set in the settings, or `SAVE_FILE` in `data_dir`.
+pub fn save_file(settings: &settings::Saves, data_dir: &Path) -> PathBuf {
+    settings.file.clone().unwrap_or_else(|| data_dir.join(SAVE_FILE))
+}
+
+#[cfg(test)]
+mod saves_test {
+    use super::*;
+
+    fn serialized(save: &SaveData) -> String {
+        to_string_pretty(save, PrettyConfig::default()).unwrap()
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut save = SaveData::default();
+        let profile = save.profile_mut(1);
+        profile.character = Some("Alien".to_owned());
+        profile.unlocked_arenas.push("Simple".to_owned());
+        let mut tree = SkillTree::for_race(&Race::Alien);
+        tree.skill_points = 3;
+        tree.unlock(0).unwrap();
+        profile.skill_trees.insert(Race::Alien, tree.clone());
+        assert!(profile.complete_trial("Alien Basics"));
+        assert!(!profile.complete_trial("Alien Basics"));
+        save.record_match(MatchRecord {
+            timestamp: 1_700_000_000,
+            participants: vec![Race::Mage, Race::Alien],
+            winner: Some(1),
+            duration: 3600,
+        });
+        let parsed = SaveData::parse(&serialized(&save), Path::new("save.ron"), MIGRATIONS).unwrap();
+        assert!(parsed == save);
+        assert!(parsed.characters() == vec![None, Some("Alien".to_owned())]);
+        assert!(parsed.skill_tree(1, &Race::Alien) == Some(&tree));
+        assert!(parsed.skill_tree(0, &Race::Alien).is_none());
+        assert!(parsed.profiles[1].total_wins == 1);
+        assert!(parsed.profiles[1].has_completed_trial("Alien Basics"));
+        assert!(!parsed.profiles[0].has_completed_trial("Alien Basics"));
+    }
+
+    #[test]
+    fn match_history_keeps_the_latest() {
+        let mut save = SaveData::default();
+        for timestamp in 0..MATCH_HISTORY_LENGTH as u64 + 5 {
+            save.record_match(MatchRecord { timestamp, participants: vec![], winner: None, duration: 0 });
+        }
+        assert!(save.match_history.len() == MATCH_HISTORY_LENGTH);
+        assert!(save.match_history[0].timestamp == 5);
+        assert!(save.profiles[0].total_wins == 0);
+    }
+
+    #[test]
+    fn corrupted_save_starts_afresh() {
+        let save_file = std::env::temp_dir().join("walpurgis_corrupted_save.ron");
+        fs::write(&save_file, "(version: 1, profiles: [(name: ").unwrap();
+        assert!(matches!(SaveData::load(&save_file), Err(WalpurgisError::AssetParse { .. })));
+        assert!(SaveData::load_or_default(&save_file) == SaveData::default());
+        fs::remove_file(&save_file).unwrap();
+        assert!(SaveData::load_or_default(&save_file) == SaveData::default());
+    }
+
+    #[test]
+    fn tampered_save_is_corrupt() {
+        let save_file = std::env::temp_dir().join("walpurgis_tampered_save.ron");
+        let mut save = SaveData::default();
+        save.profile_mut(0).total_wins = 3;
+        fs::write(&save_file, save.serialize().unwrap()).unwrap();
+        assert!(SaveData::load(&save_file).unwrap() == save);
+        let tampered = save.serialize().unwrap().replace("total_wins: 3", "total_wins: 300");
+        fs::write(&save_file, tampered).unwrap();
+        assert!(matches!(SaveData::load(&save_file), Err(WalpurgisError::CorruptSave(_))));
+        assert!(SaveData::load_or_default(&save_file) == SaveData::default());
+        // Saves from before checksums still load.
+        fs::write(&save_file, serialized(&save)).unwrap();
+        assert!(SaveData::load(&save_file).unwrap() == save);
+        fs::remove_file(&save_file).unwrap();
+    }
+
+    /// A made up change to version 2, where every profile has its last character unlocked.
+    fn unlock_last_character(save: &mut SaveData) {
+        for profile in &mut save.profiles {
+            profile.unlocked_characters.extend(profile.character.clone());
+        }
+    }
+
+    #[test]
+    fn old_saves_migrated() {
+        let old = "(version: 1, profiles: [(name: \"Ann\", character: Some(\"Alien\"))])";
+        let migrations: &[Migration] = &[unlock_last_character];
+        let save = SaveData::parse(old, Path::new("save.ron"), migrations).unwrap();
+        assert!(save.version == 2);
+        assert!(save.profiles[0].name == "Ann");
+        assert!(save.profiles[0].unlocked_characters == vec!["Alien".to_owned()]);
+        // Already up to date.
+        let save = SaveData::parse(&serialized(&save), Path::new("save.ron"), migrations).unwrap();
+        assert!(save.profiles[0].unlocked_characters.len() == 1);
+        // But not for this version.
+        let newer = SaveData::parse(&serialized(&save), Path::new("save.ron"), MIGRATIONS);
+        assert!(matches!(newer, Err(WalpurgisError::UnsupportedVersion { found: 2, .. })));
+        let future = "(version: 3, achievements: [])";
+        let future = SaveData::parse(future, Path::new("save.ron"), migrations);
+        assert!(matches!(future, Err(WalpurgisError::UnsupportedVersion { found: 3, supported: 2, .. })));
+    }
+}
@@ -1,26 +1,93 @@
 //! Structs for storing the data related to different screens within the game.
 use ggez::{Context, GameResult};
 use ggez::graphics::{Drawable, DrawParam, Rect, BlendMode};
+use std::path::Path;
 
 use crate::{
+    assets::AssetManager,
+    audio::SoundEvent,
+    debug::DebugChannels,
+    persistence::PersistenceWorker,
+    saves::{MatchRecord, SaveData},
     settings,
-    inputs::{HandleInput, Input},
-    util::result::WalpurgisResult,
+    skill_tree::SkillTree,
+    text::{Fonts, MeasureText},
+    timing::SimEvent,
+    inputs::{HandleInput, Input, layout::KeyboardLayout},
+    util::result::{WalpurgisError, WalpurgisResult},
 };
 
-mod battle;
-use self::battle::BattleData;
+pub mod battle;
+use self::battle::{
+    BattleData,
+    MatchState,
+    arena::Arena,
+    player::{inputs::InputScheme, meta::{Handicap, Race}},
+    proof::RunProof,
+    replay::LATEST_REPLAY,
+    trial::TrialRun,
+};
+mod charselect;
+use self::charselect::CharSelectData;
+mod error;
+use self::error::ErrorData;
 mod mainmenu;
 use self::mainmenu::MainMenuData;
+mod pause;
+use self::pause::PauseData;
+mod results;
+use self::results::ResultsData;
+mod settings_screen;
+use self::settings_screen::SettingsScreenData;
+mod trial_screen;
+use self::trial_screen::TrialScreenData;
 
 /// Enum denoting the state of a particular screen. Will implement the `ggez::Drawable` trait.
 #[derive(Debug)]
 pub enum Screen {
     // TODO: add more screens.
     /// The state for the core gameplay screen/loop.
-    Battle(BattleData),
+    Battle(Box<BattleData>),
     /// Main menu for game.
     MainMenu(MainMenuData),
+    /// Where players pick their races before a battle.
+    CharacterSelect(CharSelectData),
+    /// Where settings are changed in game.
+    Settings(SettingsScreenData),
+    /// A battle that has been paused.
+    Pause(Box<PauseData>),
+    /// A combo trial being played.
+    Trial(Box<TrialScreenData>),
+    /// The outcome of a finished battle.
+    Results(Box<ResultsData>),
+    /// Why the screen asked for couldn't be loaded.
+    Error(ErrorData),
+}
+
+/// A change of screen asked for by the current one. Screens can't always build the next one
+/// themselves, so the game does it for them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transition {
+    /// Stay on the current screen.
+    None,
+    /// Have the players pick their races.
+    CharacterSelect,
+    /// Load the first arena and characters and start a battle.
+    StartBattle,
+    /// Play back the last battle recorded.
+    WatchReplay,
+    /// Load the first combo trial and play it.
+    ComboTrial,
+    /// Go back to the main menu.
+    MainMenu,
+    /// Change the settings.
+    Settings,
+    /// Keep the settings changed on the settings screen and go back to the main menu.
+    SaveSettings,
+    /// Carry on with the paused battle.
+    Resume,
+    /// Stop the game.
+    Quit,
 }
 
 impl HandleInput for Screen {
@@ -28,20 +95,314 @@ impl HandleInput for Screen {
         match self {
             Self::Battle(data) => data.handle_input(ctx, fire_once_key_buffer),
             Self::MainMenu(data) => data.handle_input(ctx, fire_once_key_buffer),
+            Self::CharacterSelect(data) => data.handle_input(ctx, fire_once_key_buffer),
+            Self::Settings(data) => data.handle_input(ctx, fire_once_key_buffer),
+            Self::Pause(data) => data.handle_input(ctx, fire_once_key_buffer),
+            Self::Trial(data) => data.handle_input(ctx, fire_once_key_buffer),
+            Self::Results(data) => data.handle_input(ctx, fire_once_key_buffer),
+            Self::Error(data) => data.handle_input(ctx, fire_once_key_buffer),
+        }
+    }
+
+    fn handle_key_up(&mut self, ctx: &mut Context, released_key_buffer: &Vec<Input>) {
+        match self {
+            Self::Battle(data) => data.handle_key_up(ctx, released_key_buffer),
+            Self::MainMenu(data) => data.handle_key_up(ctx, released_key_buffer),
+            Self::CharacterSelect(data) => data.handle_key_up(ctx, released_key_buffer),
+            Self::Settings(data) => data.handle_key_up(ctx, released_key_buffer),
+            Self::Pause(data) => data.handle_key_up(ctx, released_key_buffer),
+            Self::Trial(data) => data.handle_key_up(ctx, released_key_buffer),
+            Self::Results(data) => data.handle_key_up(ctx, released_key_buffer),
+            Self::Error(data) => data.handle_key_up(ctx, released_key_buffer),
         }
     }
 }
 
 impl Screen {
-    pub fn handle_update(&mut self) {
+    /// Advances the screen by a tick. Returns the screen to switch to, if any.
+    pub fn handle_update(&mut self) -> Transition {
+        match self {
+            Self::Battle(data) => {
+                let inputs = data.take_inputs();
+                data.handle_update(&inputs);
+            },
+            Self::MainMenu(data) => return data.handle_update(),
+            Self::CharacterSelect(data) => return data.handle_update(),
+            Self::Settings(data) => return data.handle_update(),
+            Self::Pause(data) => return data.handle_update(),
+            Self::Trial(data) => return data.handle_update(),
+            Self::Results(data) => return data.handle_update(),
+            Self::Error(data) => return data.handle_update(),
+        }
+        Transition::None
+    }
+
+    pub fn main_menu() -> Self {
+        Self::MainMenu(MainMenuData::default())
+    }
+
+    /// Has each of the local players set in `controls` pick a race.
+    pub fn character_select(controls: &settings::Controls) -> WalpurgisResult<Self> {
+        let schemes = (0..controls.local_players)
+            .map(|idx| InputScheme::for_player(idx, controls))
+            .collect::<WalpurgisResult<Vec<_>>>()?;
+        Ok(Self::CharacterSelect(CharSelectData::new(schemes)))
+    }
+
+    /// Changes a copy of `settings`, with keys labelled for `layout`.
+    pub fn settings(settings: &settings::Settings, layout: KeyboardLayout) -> Self {
+        Self::Settings(SettingsScreenData::new(settings.clone(), layout))
+    }
+
+    pub fn first_battle(
+        ctx: &mut Context,
+        settings: &settings::Settings,
+        assets: &mut AssetManager,
+        fonts: &Fonts,
+        characters: &[Option<String>],
+        races: &[Race],
+        handicaps: &[Handicap],
+    ) -> WalpurgisResult<Self> {
+        let battle = BattleData::load_first_arena_and_characters(ctx, settings, assets, fonts, characters, races, handicaps)?;
+        Ok(Self::Battle(Box::new(battle)))
+    }
+
+    /// The first combo trial, played by the first player. Whether they've completed it before is
+    /// looked up in their profile in `save`, and their last run is checked from its proof in
+    /// `proof_file`.
+    pub fn first_trial(
+        ctx: &mut Context,
+        settings: &settings::Settings,
+        assets: &mut AssetManager,
+        fonts: &Fonts,
+        save: &SaveData,
+        proof_file: &Path,
+    ) -> WalpurgisResult<Self> {
+        let run = TrialRun::load_first(ctx, settings, assets, fonts)?;
+        let is_cleared = matches!(save.profiles.first(), Some(profile) if profile.has_completed_trial(&run.trial().name));
+        let last_proof = match RunProof::load(proof_file) {
+            Ok(proof) => Some(proof),
+            Err(WalpurgisError::AssetNotFound { .. }) => None,
+            Err(e) => {
+                log::warn!("Failed to load the last run proof: {}", e);
+                None
+            },
+        };
+        Ok(Self::Trial(Box::new(TrialScreenData::new(run, is_cleared, last_proof))))
+    }
+
+    /// Explains `error` to the players, who can go back to the main menu from there.
+    pub fn error<M: MeasureText>(error: &WalpurgisError, measure: &mut M) -> Self {
+        Self::Error(ErrorData::new(error, measure))
+    }
+
+    /// The screen `loaded`, or one explaining why it couldn't be loaded.
+    pub fn loaded_or_error<M: MeasureText>(loaded: WalpurgisResult<Self>, measure: &mut M) -> Self {
+        loaded.unwrap_or_else(|e| Self::error(&e, measure))
+    }
+
+    /// Plays back the last battle recorded.
+    pub fn latest_replay(
+        ctx: &mut Context,
+        assets: &mut AssetManager,
+        audio: &settings::Audio,
+        fonts: &Fonts,
+    ) -> WalpurgisResult<Self> {
+        let battle = BattleData::load_replay(ctx, assets, fonts, audio, LATEST_REPLAY)?;
+        Ok(Self::Battle(Box::new(battle)))
+    }
+
+    /// Pauses a battle or resumes a paused one. Does nothing on other screens.
+    pub fn toggle_pause(&mut self, layout: KeyboardLayout) {
+        // The placeholder is immediately overwritten below.
+        let screen = std::mem::replace(self, Self::MainMenu(MainMenuData::default()));
+        *self = match screen {
+            Self::Battle(data) => Self::Pause(Box::new(PauseData::new(*data, layout))),
+            Self::Pause(data) => Self::Battle(Box::new(data.resume())),
+            screen => screen,
+        };
+    }
+
+    /// Shows the results of a battle once it has been decided and the rewards handed out, saving it
+    /// first if it was recorded. Players spend their points on the skill trees in `save`. Returns
+    /// how the battle went, and does nothing otherwise.
+    pub fn finish_battle(
+        &mut self,
+        layout: KeyboardLayout,
+        persistence: &PersistenceWorker,
+        save: &SaveData,
+    ) -> Option<MatchRecord> {
+        if let Self::Battle(data) = self {
+            if let MatchState::PostMatch(_) = data.match_state() {
+                log::info!("Battle over after {} ticks.", data.elapsed_ticks());
+                if data.is_recording() {
+                    if let Err(e) = data.save_replay(LATEST_REPLAY, persistence) {
+                        log::error!("Failed to save the replay: {}", e);
+                    }
+                }
+                let record = data.match_record(MatchRecord::now());
+                *self = Self::Results(Box::new(ResultsData::new(data, layout, save)));
+                return record;
+            }
+        }
+        None
+    }
+
+    /// The skill trees spent on the results screen, for each player in order. Empty on other
+    /// screens.
+    pub fn skill_trees(&self) -> Vec<(Race, SkillTree)> {
+        match self {
+            Self::Results(data) => data.skill_trees(),
+            Self::Battle(_) | Self::MainMenu(_) | Self::CharacterSelect(_) | Self::Settings(_) | Self::Pause(_)
+            | Self::Trial(_) | Self::Error(_) => vec![],
+        }
+    }
+
+    pub fn set_debug_channels(&mut self, channels: &DebugChannels) {
+        match self {
+            Self::Battle(data) => data.set_debug_channels(channels),
+            Self::MainMenu(_) | Self::CharacterSelect(_) | Self::Settings(_) | Self::Results(_) | Self::Error(_) => (),
+            Self::Pause(data) => data.set_debug_channels(channels),
+            Self::Trial(data) => data.set_debug_channels(channels),
+        }
+    }
+
+    /// Swaps the new layout of the arena in `arena_file` into the battle being played, if it's
+    /// played there.
+    pub fn reload_arena(&mut self, arena_file: &Path, arena: &Arena) {
+        match self {
+            Self::Battle(data) => data.reload_arena(arena_file, arena),
+            Self::MainMenu(_) | Self::CharacterSelect(_) | Self::Settings(_) | Self::Results(_) | Self::Trial(_)
+            | Self::Error(_) => (),
+            Self::Pause(data) => data.reload_arena(arena_file, arena),
+        }
+    }
+
+    /// Gets the screen ready to be drawn. Drawing takes `&self`, so anything drawing needs worked
+    /// out ahead of time is done here, after the tick updates.
+    pub fn prepare(&mut self) {
+        match self {
+            Self::Battle(data) => data.prepare(),
+            Self::MainMenu(_) | Self::CharacterSelect(_) | Self::Settings(_) | Self::Results(_) | Self::Error(_) => (),
+            Self::Pause(data) => data.prepare(),
+            Self::Trial(data) => data.prepare(),
+        }
+    }
+
+    /// Advances the simulation by a tick, even while paused. Returns the screen to switch to, if
+    /// any.
+    pub fn step(&mut self) -> Transition {
+        match self {
+            Self::Pause(data) => {
+                data.step();
+                Transition::None
+            },
+            _ => self.handle_update(),
+        }
+    }
+
+    /// The number of ticks simulated in the current battle, if any.
+    pub fn elapsed_ticks(&self) -> Option<u64> {
+        match self {
+            Self::Battle(data) => Some(data.elapsed_ticks()),
+            Self::MainMenu(_) | Self::CharacterSelect(_) | Self::Settings(_) | Self::Results(_) | Self::Error(_) => None,
+            Self::Pause(data) => Some(data.battle().elapsed_ticks()),
+            Self::Trial(data) => Some(data.battle().elapsed_ticks()),
+        }
+    }
+
+    /// What happened during the last tick simulated.
+    pub fn tick_events(&self) -> &[SimEvent] {
+        match self {
+            Self::Battle(data) => data.tick_events(),
+            Self::MainMenu(_) | Self::CharacterSelect(_) | Self::Settings(_) | Self::Results(_) | Self::Error(_) => &[],
+            Self::Pause(data) => data.battle().tick_events(),
+            Self::Trial(data) => data.battle().tick_events(),
+        }
+    }
+
+    /// The sounds made since the last call, in order.
+    pub fn take_sounds(&mut self) -> Vec<SoundEvent> {
+        match self {
+            Self::Battle(data) => data.take_sounds(),
+            Self::MainMenu(data) => data.take_sounds(),
+            Self::CharacterSelect(data) => data.take_sounds(),
+            Self::Settings(data) => data.take_sounds(),
+            Self::Pause(data) => data.take_sounds(),
+            Self::Trial(data) => data.take_sounds(),
+            Self::Results(data) => data.take_sounds(),
+            Self::Error(data) => data.take_sounds(),
+        }
+    }
+
+    /// The music to loop on the screen, if any. Battles keep theirs while paused.
+    pub fn soundtrack(&self) -> Option<&str> {
+        match self {
+            Self::Battle(data) => data.soundtrack(),
+            Self::MainMenu(_) | Self::CharacterSelect(_) | Self::Settings(_) | Self::Results(_) | Self::Error(_) => None,
+            Self::Pause(data) => data.battle().soundtrack(),
+            Self::Trial(data) => data.battle().soundtrack(),
+        }
+    }
+
+    /// The settings with the changes made on the settings screen. There are none on other screens.
+    pub fn edited_settings(&self) -> Option<&settings::Settings> {
         match self {
-            Self::Battle(data) => data.handle_update(),
-            Self::MainMenu(data) => data.handle_update(),
+            Self::Settings(data) => Some(data.settings()),
+            _ => None,
         }
     }
 
-    pub fn first_battle(ctx: &mut Context, assets: &settings::Assets) -> WalpurgisResult<Self> {
-        Ok(Self::Battle(battle::BattleData::load_first_arena_and_test_player(ctx, &assets.root)?))
+    /// The race each player picked on the character select screen. Nobody has picked one on
+    /// other screens.
+    pub fn picked_races(&self) -> Vec<Race> {
+        match self {
+            Self::CharacterSelect(data) => data.races(),
+            _ => vec![],
+        }
+    }
+
+    /// The handicap each player picked on the character select screen. Nobody has picked one on
+    /// other screens.
+    pub fn picked_handicaps(&self) -> Vec<Handicap> {
+        match self {
+            Self::CharacterSelect(data) => data.handicaps(),
+            _ => vec![],
+        }
+    }
+
+    /// The proof of the combo trial run completed since the last call, if any.
+    pub fn take_trial_proof(&mut self) -> Option<RunProof> {
+        match self {
+            Self::Trial(data) => data.take_proof(),
+            _ => None,
+        }
+    }
+
+    /// The name of the combo trial completed since the last call, if any.
+    pub fn take_completed_trial(&mut self) -> Option<String> {
+        match self {
+            Self::Trial(data) => data.take_completed(),
+            _ => None,
+        }
+    }
+
+    /// Whether a battle is running, i.e. not paused or over.
+    pub fn is_battle(&self) -> bool {
+        matches!(self, Self::Battle(_))
+    }
+
+    /// Whether a battle or combo trial is being played, i.e. ticks move the game on.
+    pub fn is_simulating(&self) -> bool {
+        matches!(self, Self::Battle(_) | Self::Trial(_))
+    }
+
+    pub fn is_paused(&self) -> bool {
+        matches!(self, Self::Pause(_))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Results(_))
     }
 }
 
@@ -50,6 +411,12 @@ impl Drawable for Screen {
         match self {
             Self::Battle(data) => data.draw(ctx, param),
             Self::MainMenu(data) => data.draw(ctx, param),
+            Self::CharacterSelect(data) => data.draw(ctx, param),
+            Self::Settings(data) => data.draw(ctx, param),
+            Self::Pause(data) => data.draw(ctx, param),
+            Self::Trial(data) => data.draw(ctx, param),
+            Self::Results(data) => data.draw(ctx, param),
+            Self::Error(data) => data.draw(ctx, param),
         }
     }
 
@@ -57,6 +424,12 @@ impl Drawable for Screen {
         match self {
             Self::Battle(battle_data) => battle_data.dimensions(ctx),
             Self::MainMenu(data) => data.dimensions(ctx),
+            Self::CharacterSelect(data) => data.dimensions(ctx),
+            Self::Settings(data) => data.dimensions(ctx),
+            Self::Pause(data) => data.dimensions(ctx),
+            Self::Trial(data) => data.dimensions(ctx),
+            Self::Results(data) => data.dimensions(ctx),
+            Self::Error(data) => data.dimensions(ctx),
         }
     }
 
@@ -64,6 +437,12 @@ impl Drawable for Screen {
         match self {
             Self::Battle(battle_data) => battle_data.set_blend_mode(mode),
             Self::MainMenu(data) => data.set_blend_mode(mode),
+            Self::CharacterSelect(data) => data.set_blend_mode(mode),
+            Self::Settings(data) => data.set_blend_mode(mode),
+            Self::Pause(data) => data.set_blend_mode(mode),
+            Self::Trial(data) => data.set_blend_mode(mode),
+            Self::Results(data) => data.set_blend_mode(mode),
+            Self::Error(data) => data.set_blend_mode(mode),
         }
     }
 
@@ -71,6 +450,12 @@ impl Drawable for Screen {
         match self {
             Self::Battle(battle_data) => battle_data.blend_mode(),
             Self::MainMenu(data) => data.blend_mode(),
+            Self::CharacterSelect(data) => data.blend_mode(),
+            Self::Settings(data) => data.blend_mode(),
+            Self::Pause(data) => data.blend_mode(),
+            Self::Trial(data) => data.blend_mode(),
+            Self::Results(data) => data.blend_mode(),
+            Self::Error(data) => data.blend_mode(),
         }
     }
 }
@@ -3,88 +3,953 @@
 //! ## Rendering Details
 //! Overlapping Attacks
 //! If Player A launches an attack and so does Player B, their attacks could overlap. If their attacks overlap, which attack appears on top?
-mod arena;
+pub mod arena;
+mod camera;
+pub mod hud;
 mod platform;
-mod player;
+pub mod player;
+mod projectile;
+pub mod proof;
+pub mod replay;
+mod strike;
 mod interactions;
+mod layer;
+mod trail;
+pub mod trial;
+mod trigger;
+mod tuning;
 
 use ggez::{Context, GameResult};
-use ggez::graphics::{Drawable, DrawParam, Rect, Text, BlendMode};
+use ggez::graphics::{self, Drawable, DrawMode, DrawParam, MeshBuilder, Rect, Text, BlendMode};
 use ggez::nalgebra as na;
-use std::time::Instant;
-use std::path::Path;
+use serde::{Serialize, Deserialize};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 use crate::{
-    util::{
-        result::WalpurgisResult
-    },
+    assets::AssetManager,
+    audio::{Ambience, GgezAudio, SoundEvent},
+    debug::{self, ContactLog, DebugChannel, DebugChannels},
+    settings,
+    text::{Fonts, FrameStamp, GlyphAtlas, TextCache, TextKey},
+    timing::SimEvent,
+    persistence::PersistenceWorker,
+    saves::MatchRecord,
+    util::result::{WalpurgisError, WalpurgisResult},
+    viewport,
+    walpurgis::TICKS_PER_SECOND,
     screens::battle::{
         arena::Arena,
+        camera::Camera,
+        hud::{Hud, PlayerSnapshot},
+        trail::{Trail, TrailEvent, TrailSample},
         platform::Platform,
-        player::{Player, Changes as PlayerChangeSet, test_player},
+        player::{
+            Player,
+            Changes as PlayerChangeSet,
+            race::Passive,
+            action::{Action, Attack},
+            blueprint::PlayerBlueprint,
+            meta::{Ability, Handicap, Race},
+            inputs::InputScheme,
+        },
+        projectile::Projectile,
+        replay::{Playback, Replay},
+        trigger::{TriggerKind, TriggerZone},
+        tuning::{Tuning, TUNING_FILE},
     },
     inputs::{HandleInput, Input},
-    physics::collision::*,
+    physics::{self, collision::*},
 };
 
 /// The data specific to each battle.
 /// Every battle between `Player`s will be played in an `Arena`.
 #[derive(Debug)]
 pub struct BattleData {
-    game_start: Instant,
+    /// The number of ticks the battle has been running for. Does not advance while paused.
+    tick_count: u64,
     players: Vec<Player>,
+    /// Projectiles in flight.
+    projectiles: Vec<Projectile>,
     arena: Arena,
+    /// The file `arena` was loaded from, if it was, so that changes to it can be reloaded.
+    arena_file: Option<PathBuf>,
     gravity: na::Vector2<f32>,
+    /// The falling speed past which gravity stops speeding players up.
+    terminal_velocity: f32,
+    debug: DebugChannels,
+    contacts: ContactLog,
+    /// What happened during the last tick.
+    tick_events: Vec<SimEvent>,
+    /// The attacks that connected during the last tick.
+    hits: Vec<Hit>,
+    /// Glyphs for drawing the timer, which changes too often to cache whole strings.
+    timer_glyphs: GlyphAtlas,
+    /// Glyphs for drawing the countdown before the battle starts.
+    countdown_glyphs: GlyphAtlas,
+    /// The fonts of the messages shown over the battle.
+    fonts: Fonts,
+    /// The banners and labels shown over the battle, built once and kept while they're shown.
+    texts: TextCache<Text>,
+    /// The number of times the battle has been prepared for drawing, which tells how long cached
+    /// texts have gone unused.
+    frame: FrameStamp,
+    /// The message shown between rounds, if there is one.
+    banner: Option<TextKey>,
+    /// Each player's damage and stocks.
+    hud: Hud,
+    /// What the HUD shows of each player, as of the last `prepare`.
+    snapshots: Vec<PlayerSnapshot>,
+    /// The arena's looping background sounds. `None` for battles played without a `Context`.
+    ambience: Option<Ambience>,
+    /// Where each player has been recently, recorded every tick.
+    trails: Vec<Trail>,
+    /// The (player, trigger) pairs of players inside each of the arena's triggers as of the last
+    /// tick.
+    trigger_occupants: Vec<(usize, usize)>,
+    /// The triggers entered during the last tick, along with who entered them.
+    triggered: Vec<(usize, TriggerKind)>,
+    /// The sounds made since the last `take_sounds`.
+    sounds: Vec<SoundEvent>,
+    /// Follows the players. Only moves the view, never the simulation.
+    camera: Camera,
+    /// The actions each player's inputs resolved to since the last tick.
+    pending_inputs: Vec<TickInput>,
+    /// Every tick's inputs so far, if the battle is being recorded.
+    recording: Option<Replay>,
+    /// The recorded inputs played instead of the players', if the battle is a replay.
+    playback: Option<Playback>,
+    /// How many skill points the players earn once the battle is over.
+    rewards: settings::Rewards,
+    /// How many rounds the battle is played in, and how each is played.
+    rounds: settings::MatchConfig,
+    /// The rounds each player has won so far, in player order.
+    round_wins: Vec<u32>,
+    /// The tick the round being played started on.
+    round_start_tick: u64,
+    match_state: MatchState,
+}
+
+/// Whether a battle is still being played.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MatchState {
+    /// The battle is about to start. Nothing moves and the players' inputs are ignored until
+    /// the countdown runs out.
+    Countdown { frames_remaining: u32 },
+    Active,
+    /// A round was won, or drawn and about to be played again, and the next is about to be set up.
+    /// Nothing moves and the players' inputs are ignored meanwhile.
+    Intermission { round_winner: Option<usize>, frames_remaining: u32 },
+    /// The battle is over, and each player earned the given skill points. Replays earn nothing.
+    PostMatch(Vec<(usize, u32)>),
+}
+
+/// The actions a player takes on a tick, already resolved from their inputs. Ticks depend on
+/// nothing else, so feeding a battle the same `TickInput`s plays it out the same way.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TickInput {
+    pub actions: Vec<Action>,
+    /// Held actions let go of, after `actions` are taken.
+    #[serde(default)]
+    pub released: Vec<Action>,
+}
+
+impl TickInput {
+    /// The inputs for a tick on which only `player` acts.
+    #[cfg(test)]
+    pub fn only(player: usize, actions: Vec<Action>) -> Vec<TickInput> {
+        let mut inputs = vec![TickInput::default(); player + 1];
+        inputs[player].actions = actions;
+        inputs
+    }
+}
+
+/// An attack that connected.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hit {
+    pub attacker: usize,
+    pub defender: usize,
+    pub attack: Attack,
 }
 
 impl BattleData {
     // TODO: remove this once we don't need it anymore
-    pub fn load_first_arena_and_test_player<P: AsRef<Path>>(ctx: &mut Context, asset_dir: P) -> WalpurgisResult<BattleData> {
-        let asset_dir = asset_dir.as_ref();
-        log::info!("Loading first arena from assets directory: `{}`", asset_dir.display());
-
-        let arena_dir = asset_dir.join("arenas");
-        Ok(BattleData {
-            game_start: Instant::now(),
-            arena: Arena::load_first(arena_dir)?,
-            players: vec![test_player(ctx)?],
-            gravity: na::Vector2::<f32>::new(0.0, 0.01),
+    /// Loads the first arena with the local players set in the controls settings, each with their
+    /// own controls. Players play the races they picked in `races`, if they picked any, and the
+    /// characters they picked in `characters`, by name, if those are of the right race. See
+    /// `pick_blueprints` for everyone else. Players play with the handicaps they picked in
+    /// `handicaps`, and without one if they didn't. Battles are recorded to be saved as replays if the
+    /// settings say so, and players earn skill points by the rewards settings once it's over.
+    pub fn load_first_arena_and_characters(
+        ctx: &mut Context,
+        settings: &settings::Settings,
+        assets: &mut AssetManager,
+        fonts: &Fonts,
+        characters: &[Option<String>],
+        races: &[Race],
+        handicaps: &[Handicap],
+    ) -> WalpurgisResult<BattleData> {
+        let (arena_file, arena) = assets.first_arena()?;
+        let mut arena = Arena::clone(&arena);
+        let tuning = Tuning::load(assets.root().join(TUNING_FILE))?;
+        let player_count = settings.controls.local_players;
+        Self::check_player_count(&arena, player_count)?;
+        let schemes = (0..player_count)
+            .map(|idx| InputScheme::for_player(idx, &settings.controls))
+            .collect::<WalpurgisResult<Vec<_>>>()?;
+        arena.load_background(ctx, assets)?;
+        let blueprints: Vec<_> = pick_blueprints(&assets.blueprints()?, characters, races, player_count)
+            .iter()
+            .map(|blueprint| tuning.tune(blueprint))
+            .collect();
+        let players = blueprints.iter()
+            .zip(schemes)
+            .map(|(blueprint, inputs)| Player::from_blueprint(ctx, assets, blueprint, inputs))
+            .collect::<WalpurgisResult<Vec<_>>>()?;
+        let handicaps: Vec<_> = (0..player_count)
+            .map(|idx| handicaps.get(idx).cloned().unwrap_or_default())
+            .collect();
+        let mut battle = Self::new(arena, players, fonts);
+        battle.tune(&tuning);
+        battle.arena_file = Some(arena_file);
+        battle.match_state = MatchState::Countdown { frames_remaining: COUNTDOWN_TICKS };
+        battle.rewards = settings.rewards.clone();
+        battle.play_rounds(&settings.rounds, &handicaps);
+        if settings.development.record_replays {
+            battle.recording = Some(Replay::new(&battle.arena, blueprints, handicaps, tuning, settings.rounds.clone())?);
+        }
+        battle.finish_loading(ctx, assets.root(), &settings.audio);
+        Ok(battle)
+    }
+
+    /// Loads the battle recorded in `replay_file`, to be played back instead of played. The arena
+    /// has to be the same as when it was recorded.
+    pub fn load_replay<P: AsRef<Path>>(
+        ctx: &mut Context,
+        assets: &mut AssetManager,
+        fonts: &Fonts,
+        audio: &settings::Audio,
+        replay_file: P,
+    ) -> WalpurgisResult<BattleData> {
+        let replay = Replay::load(replay_file)?;
+        log::info!("Loading a replay in arena `{}`.", replay.arena);
+        let (_, arena) = assets.arena_named(&replay.arena)?;
+        let mut arena = Arena::clone(&arena);
+        replay.check_arena(&arena)?;
+        arena.load_background(ctx, assets)?;
+        let players = replay.blueprints.iter()
+            .map(|blueprint| Player::from_blueprint(ctx, assets, blueprint, InputScheme::default()))
+            .collect::<WalpurgisResult<Vec<_>>>()?;
+        let mut battle = Self::new(arena, players, fonts);
+        battle.tune(&replay.tuning);
+        battle.play_rounds(&replay.rounds, &replay.handicaps);
+        battle.playback = Some(Playback::new(replay));
+        battle.finish_loading(ctx, assets.root(), audio);
+        Ok(battle)
+    }
+
+    /// Sizes the battle to the virtual screen and starts its sounds.
+    fn finish_loading(&mut self, ctx: &mut Context, asset_dir: &Path, audio: &settings::Audio) {
+        self.resize(viewport::VIRTUAL_WIDTH, viewport::VIRTUAL_HEIGHT);
+        self.ambience = self.load_ambience(ctx, asset_dir, audio);
+    }
+
+    /// Plays the battle with the gravity in `tuning`. The players were tuned when they were built.
+    fn tune(&mut self, tuning: &Tuning) {
+        self.gravity = tuning.gravity();
+        self.terminal_velocity = tuning.terminal_velocity;
+    }
+
+    /// Plays the battle in the rounds set in `rounds`, each player with their handicap in
+    /// `handicaps`, if they have one, and starts every player off with the stocks they get each
+    /// round.
+    fn play_rounds(&mut self, rounds: &settings::MatchConfig, handicaps: &[Handicap]) {
+        self.rounds = rounds.clone();
+        self.round_wins = vec![0; self.players.len()];
+        for (player, handicap) in self.players.iter_mut().zip(handicaps) {
+            player.set_handicap(handicap.clone());
+        }
+        for player in &mut self.players {
+            player.start_round(rounds.stocks_per_round);
+        }
+    }
+
+    /// Checks that every one of `player_count` local players gets their own spawn point.
+    fn check_player_count(arena: &Arena, player_count: usize) -> WalpurgisResult {
+        let spawn_points = arena.spawn_points().len();
+        if player_count == 0 || player_count > spawn_points {
+            Err(WalpurgisError::WrongPlayerCount { players: player_count, spawn_points })?
+        }
+        Ok(())
+    }
+
+    /// Loads the arena's ambient sounds. A battle without them is still playable, so failures are
+    /// only logged.
+    fn load_ambience(&self, ctx: &mut Context, asset_dir: &Path, audio: &settings::Audio) -> Option<Ambience> {
+        let emitters = self.arena.ambience();
+        if emitters.is_empty() {
+            return None;
+        }
+        match GgezAudio::load(ctx, asset_dir, emitters) {
+            Ok(backend) => Some(Ambience::new(emitters.to_vec(), Box::new(backend), audio.ambient_emitter_cap())),
+            Err(e) => {
+                log::warn!("Failed to load ambient sounds, playing without them: {:?}", e);
+                None
+            },
+        }
+    }
+
+    /// Starts a battle with the players at the arena's spawn points.
+    fn new(arena: Arena, mut players: Vec<Player>, fonts: &Fonts) -> Self {
+        Self::spawn_players(&arena, &mut players);
+        BattleData {
+            tick_count: 0,
+            arena,
+            arena_file: None,
+            players,
+            projectiles: vec![],
+            gravity: Tuning::default().gravity(),
+            terminal_velocity: Tuning::default().terminal_velocity,
+            debug: DebugChannels::default(),
+            contacts: ContactLog::default(),
+            tick_events: vec![],
+            hits: vec![],
+            trails: vec![],
+            timer_glyphs: GlyphAtlas::digits(fonts.get(fonts.default_id()), TIMER_FONT_SIZE),
+            countdown_glyphs: GlyphAtlas::new(COUNTDOWN_CHARSET, fonts.get(fonts.default_id()), COUNTDOWN_FONT_SIZE),
+            fonts: fonts.clone(),
+            texts: TextCache::new(TEXT_CACHE_CAPACITY),
+            frame: 0,
+            banner: None,
+            hud: Hud::new(fonts),
+            snapshots: vec![],
+            ambience: None,
+            trigger_occupants: vec![],
+            triggered: vec![],
+            sounds: vec![],
+            camera: Camera::default(),
+            pending_inputs: vec![],
+            recording: None,
+            playback: None,
+            rewards: settings::Rewards::default(),
+            rounds: settings::MatchConfig::default(),
+            round_wins: vec![],
+            round_start_tick: 0,
+            match_state: MatchState::Active,
+        }
+    }
+
+    /// Starts the battle over in `arena` with `players`, keeping the fonts, sounds, camera and
+    /// debug channels it was loaded with.
+    fn restart(&mut self, arena: Arena, mut players: Vec<Player>) {
+        Self::spawn_players(&arena, &mut players);
+        self.arena = arena;
+        self.round_wins = vec![0; players.len()];
+        self.players = players;
+        self.tick_count = 0;
+        self.projectiles.clear();
+        self.contacts = ContactLog::default();
+        self.tick_events.clear();
+        self.hits.clear();
+        self.trails.clear();
+        self.trigger_occupants.clear();
+        self.triggered.clear();
+        self.pending_inputs.clear();
+        self.round_start_tick = 0;
+        self.match_state = MatchState::Active;
+    }
+
+    /// Puts each of `players` at the arena's spawn point for them, if it has one.
+    fn spawn_players(arena: &Arena, players: &mut [Player]) {
+        for (idx, player) in players.iter_mut().enumerate() {
+            if let Some(spawn_point) = arena.spawn_point(idx) {
+                player.spawn_at(spawn_point);
+            }
+        }
+    }
+
+    /// The pull of gravity on `player`, which stops once they fall at terminal velocity.
+    fn gravity_on(&self, player: &Player) -> na::Vector2<f32> {
+        if player.velocity()[1] >= self.terminal_velocity {
+            na::Vector2::zeros()
+        } else {
+            self.gravity
+        }
+    }
+
+    /// The inputs to play on the next tick: the ones resolved since the last tick, or the recorded
+    /// ones when playing back a replay. Nobody gets to act during the countdown, and replays don't
+    /// move on until it's over.
+    pub fn take_inputs(&mut self) -> Vec<TickInput> {
+        if matches!(self.match_state, MatchState::Countdown { .. } | MatchState::Intermission { .. }) {
+            self.pending_inputs.clear();
+            return vec![];
+        }
+        match &mut self.playback {
+            Some(playback) => playback.next_tick(),
+            None => std::mem::take(&mut self.pending_inputs),
+        }
+    }
+
+    /// Swaps in the new layout of the battle's arena if `arena_file` is where it was loaded from.
+    /// Replays keep the arena they were recorded in, since they'd play out differently otherwise.
+    pub fn reload_arena(&mut self, arena_file: &Path, arena: &Arena) {
+        if self.arena_file.as_deref() != Some(arena_file) || self.playback.is_some() {
+            return;
+        }
+        log::info!("Reloading the layout of arena `{}`.", self.arena.name());
+        self.arena.reload_layout(arena);
+        // The triggers may have moved around, so whoever is inside them is worked out afresh.
+        self.trigger_occupants.clear();
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Queues the recording of the battle so far to be written to `replay_file` as RON.
+    pub fn save_replay<P: AsRef<Path>>(&self, replay_file: P, persistence: &PersistenceWorker) -> WalpurgisResult {
+        match &self.recording {
+            Some(replay) => replay.save(replay_file, persistence),
+            None => Err("The battle isn't being recorded.".to_owned())?,
+        }
+    }
+
+    pub fn set_debug_channels(&mut self, channels: &DebugChannels) {
+        self.debug = channels.clone();
+    }
+
+    /// Takes in the size of the screen, in virtual units.
+    pub fn resize(&mut self, width: f32, height: f32) {
+        self.camera.resize(width, height);
+        self.hud.resize(width, height);
+    }
+
+    /// Works out what the next draw needs. Only touches what is drawn, never the simulation.
+    pub fn prepare(&mut self) {
+        self.frame += 1;
+        self.snapshots = self.players.iter().map(PlayerSnapshot::of).collect();
+        self.hud.prepare(&self.snapshots, &mut self.texts, &self.fonts, self.frame);
+        self.banner = self.intermission_label()
+            .map(|contents| TextKey::new(contents, self.fonts.default_id(), BANNER_FONT_SIZE, graphics::WHITE));
+        if let Some(key) = &self.banner {
+            let fonts = &self.fonts;
+            self.texts.get_or_insert_with(key.clone(), self.frame, |key| key.build(fonts));
+        }
+        if !self.texts.is_empty() {
+            self.texts.evict_unused_since(self.frame.saturating_sub(TEXT_UNUSED_FRAMES));
+        }
+        if self.debug.is_enabled(DebugChannel::Trails) {
+            for trail in &mut self.trails {
+                trail.prepare();
+            }
+        }
+    }
+
+    /// The number of ticks simulated so far.
+    pub fn elapsed_ticks(&self) -> u64 {
+        self.tick_count
+    }
+
+    pub fn tick_events(&self) -> &[SimEvent] {
+        &self.tick_events
+    }
+
+    /// The sounds made since the last call, in order.
+    pub fn take_sounds(&mut self) -> Vec<SoundEvent> {
+        std::mem::take(&mut self.sounds)
+    }
+
+    /// The music to loop during the battle, if any.
+    pub fn soundtrack(&self) -> Option<&str> {
+        self.arena.soundtrack()
+    }
+
+    pub fn hits(&self) -> &[Hit] {
+        &self.hits
+    }
+
+    /// The triggers entered during the last tick, and the players who entered them.
+    #[cfg(test)]
+    pub fn triggered(&self) -> &[(usize, TriggerKind)] {
+        &self.triggered
+    }
+
+    /// Whether the round being played has been fought out. Rounds between several players end
+    /// once at most one is left standing, and solo rounds once the only player is eliminated.
+    pub fn is_over(&self) -> bool {
+        if matches!(&self.playback, Some(playback) if playback.is_finished()) {
+            return true;
+        }
+        let remaining = self.players.iter().filter(|player| !player.is_eliminated()).count();
+        remaining < self.players.len().min(2)
+    }
+
+    /// Whether the round being played has run out of time.
+    pub fn is_timed_out(&self) -> bool {
+        matches!(self.rounds.time_limit_ticks, Some(limit) if self.round_ticks() >= limit)
+    }
+
+    /// The number of ticks the round being played has lasted so far.
+    fn round_ticks(&self) -> u64 {
+        self.tick_count - self.round_start_tick
+    }
+
+    /// The player who won the round being played, once it's decided: the last player standing,
+    /// or once time runs out, whoever has the most stocks left and then the least damage.
+    fn round_winner(&self) -> Option<usize> {
+        let remaining: Vec<_> = self.players.iter()
+            .enumerate()
+            .filter(|(_, player)| !player.is_eliminated())
+            .collect();
+        let leaders: Vec<_> = if self.is_over() {
+            remaining
+        } else if self.is_timed_out() {
+            let leads = |a: &Player, b: &Player| {
+                a.stocks() > b.stocks() || (a.stocks() == b.stocks() && a.damage_pct() < b.damage_pct())
+            };
+            remaining.iter()
+                .filter(|(_, player)| !remaining.iter().any(|(_, other)| leads(other, player)))
+                .copied()
+                .collect()
+        } else {
+            return None;
+        };
+        match leaders.as_slice() {
+            [(idx, _)] => Some(*idx),
+            _ => None,
+        }
+    }
+
+    /// The player who won the battle, once someone has: the first to win as many rounds as it
+    /// takes.
+    pub fn winner(&self) -> Option<usize> {
+        let rounds_to_win = self.rounds.rounds_to_win.max(1);
+        let wins = |idx: usize| self.round_wins.get(idx).copied().unwrap_or(0);
+        if let Some(idx) = (0..self.players.len()).find(|idx| wins(*idx) >= rounds_to_win) {
+            return Some(idx);
+        }
+        // Until the round being played is counted, winning it may be enough.
+        match self.round_winner() {
+            Some(idx) if self.match_state == MatchState::Active && wins(idx) + 1 >= rounds_to_win => Some(idx),
+            _ => None,
+        }
+    }
+
+    /// Whether the battle ended without a winner, i.e. a replay ran out on a drawn round. Drawn
+    /// rounds are otherwise played again.
+    pub fn is_draw(&self) -> bool {
+        self.players.len() > 1 && matches!(self.match_state, MatchState::PostMatch(_)) && self.winner().is_none()
+    }
+
+    /// The rounds each player has won so far, in player order.
+    pub fn round_wins(&self) -> Vec<u32> {
+        (0..self.players.len())
+            .map(|idx| self.round_wins.get(idx).copied().unwrap_or(0))
+            .collect()
+    }
+
+    /// The stocks left for each player, in order.
+    #[cfg(test)]
+    pub fn stocks(&self) -> Vec<u8> {
+        self.players.iter().map(Player::stocks).collect()
+    }
+
+    pub fn match_state(&self) -> &MatchState {
+        &self.match_state
+    }
+
+    /// What the countdown shows: the seconds left before the battle starts, rounded up, then "GO!"
+    /// for a moment once it has.
+    fn countdown_label(&self) -> Option<String> {
+        match self.match_state {
+            MatchState::Countdown { frames_remaining } => {
+                Some(frames_remaining.div_ceil(TICKS_PER_SECOND).to_string())
+            },
+            MatchState::Active if self.round_ticks() < GO_TICKS => Some("GO!".to_owned()),
+            _ => None,
+        }
+    }
+
+    /// What's shown between rounds: who won the last one, if anyone, and the score so far.
+    fn intermission_label(&self) -> Option<String> {
+        match self.match_state {
+            MatchState::Intermission { round_winner, .. } => {
+                let score: Vec<_> = self.round_wins().iter().map(u32::to_string).collect();
+                let outcome = match round_winner {
+                    Some(idx) => format!("Player {} wins the round", idx + 1),
+                    None => "Round drawn".to_owned(),
+                };
+                Some(format!("{} \u{2014} {}", outcome, score.join(":")))
+            },
+            _ => None,
+        }
+    }
+
+    pub fn players(&self) -> &[Player] {
+        &self.players
+    }
+
+    /// How the battle went, once it's over, as of `timestamp`. Replays were already recorded when
+    /// they were played, so they have none.
+    pub fn match_record(&self, timestamp: u64) -> Option<MatchRecord> {
+        if !matches!(self.match_state, MatchState::PostMatch(_)) || self.playback.is_some() {
+            return None;
+        }
+        Some(MatchRecord {
+            timestamp,
+            participants: self.players.iter().map(|player| player.race().clone()).collect(),
+            winner: self.winner(),
+            duration: self.tick_count,
         })
     }
+
+    /// The skill points each player has earned so far, as `(player, points)` pairs in player
+    /// order. Everyone gets the points for taking part, stocks left and damage done, the winner
+    /// gets a bonus on top, and longer battles are worth more up to a cap.
+    pub fn compute_rewards(&self) -> Vec<(usize, u32)> {
+        let rewards = &self.rewards;
+        let minutes = self.tick_count / (60 * u64::from(TICKS_PER_SECOND));
+        let duration_points = minutes.saturating_mul(u64::from(rewards.per_minute))
+            .min(u64::from(rewards.max_duration_points)) as u32;
+        let winner = self.winner();
+        self.players.iter()
+            .enumerate()
+            .map(|(idx, player)| {
+                let hundreds_of_damage = (player.damage_dealt() / 100.).floor() as u32;
+                let mut points = rewards.participation
+                    + u32::from(player.stocks()) * rewards.per_stock
+                    + hundreds_of_damage * rewards.per_hundred_damage
+                    + duration_points;
+                if winner == Some(idx) {
+                    points += rewards.victory;
+                }
+                (idx, points)
+            })
+            .collect()
+    }
+
+    /// A hash of where everything is, how it's moving and how the players are doing. Battles fed
+    /// the same inputs hash the same after every tick.
+    #[cfg(test)]
+    pub fn state_hash(&self) -> u64 {
+        let mut bytes = self.tick_count.to_le_bytes().to_vec();
+        let push_vector = |bytes: &mut Vec<u8>, v: na::Vector2<f32>| {
+            bytes.extend_from_slice(&v[0].to_bits().to_le_bytes());
+            bytes.extend_from_slice(&v[1].to_bits().to_le_bytes());
+        };
+        for player in &self.players {
+            push_vector(&mut bytes, player.get_offset());
+            push_vector(&mut bytes, player.get_velocity());
+            bytes.extend_from_slice(&player.damage_pct().to_bits().to_le_bytes());
+            bytes.push(player.stocks());
+        }
+        for projectile in &self.projectiles {
+            push_vector(&mut bytes, projectile.get_offset());
+            push_vector(&mut bytes, projectile.get_velocity());
+        }
+        crate::util::hash::fnv1a(&bytes)
+    }
+}
+
+const TIMER_FONT_SIZE: u32 = 16;
+const COUNTDOWN_FONT_SIZE: u32 = 96;
+const BANNER_FONT_SIZE: u32 = 48;
+/// The most banners and labels kept built at once.
+const TEXT_CACHE_CAPACITY: usize = 32;
+/// How many frames a banner or label is kept built after it was last shown.
+const TEXT_UNUSED_FRAMES: FrameStamp = 2 * TICKS_PER_SECOND as FrameStamp;
+const COUNTDOWN_CHARSET: &str = "123GO!";
+/// How long the countdown before every battle lasts.
+const COUNTDOWN_TICKS: u32 = 3 * TICKS_PER_SECOND;
+/// How long "GO!" stays up once the battle has started.
+const GO_TICKS: u64 = TICKS_PER_SECOND as u64;
+/// How long the result of a round is shown before the next one is set up.
+const INTERMISSION_TICKS: u32 = 3 * TICKS_PER_SECOND;
+
+/// The characters each of `player_count` players plays, out of `blueprints`. Players play the
+/// character they picked in `characters` as long as it's of the race they picked in `races`, and
+/// otherwise the first character of that race, or its placeholder if it has none. Players who
+/// didn't pick a race take the characters in turn.
+pub fn pick_blueprints(
+    blueprints: &[Rc<PlayerBlueprint>],
+    characters: &[Option<String>],
+    races: &[Race],
+    player_count: usize,
+) -> Vec<PlayerBlueprint> {
+    (0..player_count)
+        .map(|idx| {
+            let race = races.get(idx);
+            let picked = characters.get(idx)
+                .and_then(Option::as_ref)
+                .and_then(|name| blueprints.iter().find(|blueprint| blueprint.name == *name))
+                .filter(|blueprint| race.iter().all(|race| blueprint.race == **race));
+            match (picked, race) {
+                (Some(picked), _) => PlayerBlueprint::clone(picked),
+                (None, Some(race)) => blueprints.iter()
+                    .find(|blueprint| blueprint.race == *race)
+                    .map_or_else(|| PlayerBlueprint::placeholder(race.clone()), |blueprint| PlayerBlueprint::clone(blueprint)),
+                (None, None) => PlayerBlueprint::clone(&blueprints[idx % blueprints.len()]),
+            }
+        })
+        .collect()
+}
+
+/// Formats a number of ticks as `mm:ss`, or `h:mm:ss` once past an hour.
+pub fn format_timer(ticks: u64) -> String {
+    let seconds = ticks / u64::from(TICKS_PER_SECOND);
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:0>2}:{:0>2}", hours, minutes, seconds)
+    } else {
+        format!("{:0>2}:{:0>2}", minutes, seconds)
+    }
 }
 
 impl HandleInput for BattleData {
+    /// Resolves each player's inputs into actions, which are held until the next tick. Replays
+    /// ignore the players' inputs.
     fn handle_input(&mut self, ctx: &mut Context, fire_once_key_buffer: &Vec<Input>) {
-        for player in &mut self.players {
-            player.handle_input(ctx, fire_once_key_buffer);
+        if self.playback.is_some() {
+            return;
+        }
+        self.pending_inputs.resize_with(self.players.len(), TickInput::default);
+        for (player, input) in self.players.iter_mut().zip(&mut self.pending_inputs) {
+            input.actions.extend(player.resolve_actions(ctx, fire_once_key_buffer));
+        }
+    }
+
+    fn handle_key_up(&mut self, _ctx: &mut Context, released_key_buffer: &Vec<Input>) {
+        if self.playback.is_some() {
+            return;
+        }
+        self.pending_inputs.resize_with(self.players.len(), TickInput::default);
+        for (player, input) in self.players.iter_mut().zip(&mut self.pending_inputs) {
+            input.released.extend(player.resolve_releases(released_key_buffer));
         }
     }
 }
 
 // Helpers for drawing.
 impl BattleData {
+    /// Draws the time the round has lasted, or the time it has left if it can run out.
     fn draw_timer(&self, ctx: &mut Context, mut param: DrawParam) -> GameResult {
-        let seconds = self.game_start.elapsed().as_secs();
-        let seconds = format!("{:0>2}:{:0>2}", seconds / 60, seconds % 60);
-        let timer = Text::new(seconds);
         param.dest.x += 383_f32;
-        timer.draw(ctx, param)
+        let ticks = match self.rounds.time_limit_ticks {
+            Some(limit) => limit.saturating_sub(self.round_ticks()),
+            None => self.round_ticks(),
+        };
+        self.timer_glyphs.draw(ctx, &format_timer(ticks), param)
+    }
+
+    /// Draws who won the last round and the score so far in the middle of the screen, between
+    /// rounds.
+    fn draw_banner(&self, ctx: &mut Context, mut param: DrawParam) -> GameResult {
+        if let Some(banner) = self.banner.as_ref().and_then(|key| self.texts.get(key)) {
+            param.dest.x += (viewport::VIRTUAL_WIDTH - banner.width(ctx) as f32) / 2.;
+            param.dest.y += (viewport::VIRTUAL_HEIGHT - banner.height(ctx) as f32) / 2.;
+            banner.draw(ctx, param)?;
+        }
+        Ok(())
+    }
+
+    /// Draws the seconds left before the battle starts, or "GO!" right after, in the middle of the
+    /// screen.
+    fn draw_countdown(&self, ctx: &mut Context, mut param: DrawParam) -> GameResult {
+        let label = match self.countdown_label() {
+            Some(label) => label,
+            None => return Ok(()),
+        };
+        param.dest.x += (viewport::VIRTUAL_WIDTH - self.countdown_glyphs.width(&label, ctx)) / 2.;
+        param.dest.y += (viewport::VIRTUAL_HEIGHT - COUNTDOWN_FONT_SIZE as f32) / 2.;
+        self.countdown_glyphs.draw(ctx, &label, param)
+    }
+
+    /// Draws everything placed in the world, with `param` already through the camera.
+    fn draw_world(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
+        self.arena.draw(ctx, param)?;
+        for projectile in &self.projectiles {
+            projectile.draw(ctx, param)?;
+        }
+        for player in &self.players {
+            player.draw(ctx, param)?;
+        }
+        if self.debug.is_enabled(DebugChannel::Hurtboxes) {
+            for player in &self.players {
+                player.draw_boxes(ctx, param)?;
+            }
+        }
+        if self.debug.is_enabled(DebugChannel::Attacks) {
+            for player in &self.players {
+                player.draw_attack_boxes(ctx, param)?;
+            }
+        }
+        if self.debug.is_enabled(DebugChannel::Platforms) {
+            self.draw_platform_bodies(ctx, param)?;
+        }
+        if self.debug.is_enabled(DebugChannel::BlastZones) || self.debug.is_enabled(DebugChannel::AoeZones) {
+            self.draw_zones(ctx, param)?;
+        }
+        if self.debug.is_enabled(DebugChannel::CameraView) {
+            self.draw_camera_view(ctx, param)?;
+        }
+        if self.debug.is_enabled(DebugChannel::Contacts) {
+            self.draw_contacts(ctx, param)?;
+        }
+        if self.debug.is_enabled(DebugChannel::Velocities) {
+            self.draw_velocities(ctx, param)?;
+        }
+        if self.debug.is_enabled(DebugChannel::Trails) {
+            self.draw_trails(ctx, param)?;
+        }
+        Ok(())
+    }
+
+    /// Draws the timer and HUD, which stay put on the screen whatever the camera does.
+    fn draw_screen(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
+        self.draw_timer(ctx, param)?;
+        self.draw_countdown(ctx, param)?;
+        self.draw_banner(ctx, param)?;
+        self.hud.draw(ctx, &self.snapshots, &self.texts, param)?;
+        if self.debug.is_enabled(DebugChannel::TextCache) {
+            self.draw_text_cache(ctx, param)?;
+        }
+        Ok(())
+    }
+
+    /// Draws how many texts are cached and how often they were found there, in the top left.
+    fn draw_text_cache(&self, ctx: &mut Context, mut param: DrawParam) -> GameResult {
+        param.color = DebugChannel::TextCache.color();
+        param.dest.x += 10.;
+        param.dest.y += 10.;
+        Text::new(self.texts.describe()).draw(ctx, param)
+    }
+
+    fn draw_contacts(&self, ctx: &mut Context, mut param: DrawParam) -> GameResult {
+        param.color = DebugChannel::Contacts.color();
+        for (hb0, hb1) in self.contacts.contacts() {
+            hb0.draw(ctx, param)?;
+            hb1.draw(ctx, param)?;
+        }
+        Ok(())
+    }
+
+    fn draw_velocities(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
+        // Velocities are tiny compared to the screen, so arrows are scaled up.
+        const ARROW_SCALE: f32 = 20.;
+        for player in &self.players {
+            let velocity = player.velocity();
+            if velocity.norm() < f32::EPSILON {
+                continue;
+            }
+            let origin = na::Point2::from(player.get_offset());
+            let arrow = debug::arrow_mesh(ctx, origin, velocity * ARROW_SCALE, DebugChannel::Velocities.color())?;
+            graphics::draw(ctx, &arrow, param)?;
+        }
+        Ok(())
+    }
+
+    /// Draws what platforms collide as, including broken ones.
+    fn draw_platform_bodies(&self, ctx: &mut Context, mut param: DrawParam) -> GameResult {
+        param.color = DebugChannel::Platforms.color();
+        for platform in self.arena.platforms() {
+            platform.body.draw(ctx, physics::drawn_at(param, platform.get_offset()))?;
+        }
+        Ok(())
+    }
+
+    /// Draws the blast zones and named trigger zones, each if its channel is on.
+    fn draw_zones(&self, ctx: &mut Context, mut param: DrawParam) -> GameResult {
+        for trigger in self.arena.triggers() {
+            let channel = match trigger.kind {
+                TriggerKind::BlastZone => DebugChannel::BlastZones,
+                TriggerKind::Named(_) => DebugChannel::AoeZones,
+            };
+            if self.debug.is_enabled(channel) {
+                param.color = channel.color();
+                trigger.body.draw(ctx, param)?;
+            }
+        }
+        Ok(())
     }
 
-    pub fn handle_update(&mut self) {
+    /// Outlines what the camera shows and what it's trying to keep in view.
+    fn draw_camera_view(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
+        let mut outlines = MeshBuilder::new();
+        for aabb in std::iter::once(self.camera.view()).chain(self.camera.framed()) {
+            let size = aabb.max - aabb.min;
+            let rect = Rect::new(aabb.min[0], aabb.min[1], size[0], size[1]);
+            outlines.rectangle(DrawMode::stroke(2.), rect, DebugChannel::CameraView.color());
+        }
+        outlines.build(ctx)?.draw(ctx, param)
+    }
+
+    fn draw_trails(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
+        for trail in &self.trails {
+            if let Some(mesh) = trail.mesh(ctx)? {
+                graphics::draw(ctx, &mesh, param)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Plays a tick, with each player taking the actions in their entry of `inputs`. Players
+    /// without one stay idle. During the countdown and between rounds, ticks only count down.
+    pub fn handle_update(&mut self, inputs: &[TickInput]) {
         use interactions as res;
 
+        match self.match_state {
+            MatchState::Countdown { frames_remaining } => {
+                self.match_state = match frames_remaining.saturating_sub(1) {
+                    0 => MatchState::Active,
+                    frames_remaining => MatchState::Countdown { frames_remaining },
+                };
+                return;
+            },
+            MatchState::Intermission { round_winner, frames_remaining } => {
+                match frames_remaining.saturating_sub(1) {
+                    0 => self.start_round(),
+                    frames_remaining => self.match_state = MatchState::Intermission { round_winner, frames_remaining },
+                }
+                return;
+            },
+            MatchState::Active | MatchState::PostMatch(_) => (),
+        }
+
+        // Players without inputs for the tick do nothing, but their hitstun still counts down.
+        for (idx, player) in self.players.iter_mut().enumerate() {
+            let input = inputs.get(idx).cloned().unwrap_or_default();
+            player.handle_actions(input.actions);
+            player.handle_releases(&input.released);
+        }
+        if let Some(replay) = &mut self.recording {
+            replay.ticks.push(inputs.to_vec());
+        }
+
+        self.fire_projectiles();
+
         // Find changes.
-        let grav_changeset = PlayerChangeSet {
-            force: self.gravity,
-            ..Default::default()
-        };
-        let mut player_changesets: Vec<<Player as Collidable>::ChangeSet>
-            = vec![grav_changeset; self.players.len()];
+        let mut player_changesets: Vec<<Player as Collidable>::ChangeSet> = self.players.iter()
+            .map(|player| PlayerChangeSet {
+                force: self.gravity_on(player),
+                ..Default::default()
+            })
+            .collect();
         let mut platform_changesets: Vec<Option<<Platform as Collidable>::ChangeSet>>
-            = vec![None; self.arena.platforms.len()];
+            = vec![None; self.arena.platforms().len()];
+        let mut projectile_changesets: Vec<<Projectile as Collidable>::ChangeSet>
+            = vec![Default::default(); self.projectiles.len()];
+        for (thrown, changeset) in self.throw_players() {
+            player_changesets[thrown] = player_changesets[thrown].merge(&changeset);
+        }
 
-        let collisions = check_for_collision_pairs(self.players.as_slice(), self.arena.platforms.as_slice());
+        self.contacts.begin_pass(self.debug.is_enabled(DebugChannel::Contacts));
+        self.tick_events.clear();
+        self.hits.clear();
+
+        // Swept so that fast players can't skip over thin platforms.
+        let collisions = check_for_swept_collision_pairs(self.players.as_slice(), self.arena.platforms());
+        self.contacts.record(|| collisions.iter().flat_map(Collision::world_hitboxes).collect());
         for c in collisions {
             let (player_id, platform_id) = c.ids;
             let (player_changeset, platform_changeset) = res::handle_player_platform_collision(c);
@@ -93,7 +958,7 @@ impl BattleData {
                     = player_changesets[player_id].merge(&player_changeset);
             }
             if let Some(platform_changeset) = platform_changeset {
-                platform_changesets[platform_id] = match platform_changesets[platform_id] {
+                platform_changesets[platform_id] = match platform_changesets[platform_id].take() {
                     Some(changeset) => Some(changeset.merge(&platform_changeset)),
                     None => Some(platform_changeset),
                 };
@@ -101,8 +966,19 @@ impl BattleData {
         }
 
         let collisions = check_for_collisions(self.players.as_slice());
+        self.contacts.record(|| collisions.iter().flat_map(Collision::world_hitboxes).collect());
+        if !collisions.is_empty() {
+            self.tick_events.push(SimEvent::Collision);
+        }
+        let mut grabs = vec![];
         for c in collisions {
             let (p0_id, p1_id) = c.ids;
+            grabs.extend(res::handle_player_grab_collision(&c));
+            for (attacker, defender) in res::landed_strikes(&c) {
+                if let Some(strike) = self.players[attacker].strike(attacker) {
+                    self.hits.push(Hit { attacker, defender, attack: strike.attack });
+                }
+            }
             let (changeset0, changeset1) = res::handle_player_player_collision(c);
             if let Some(changeset0) = changeset0 {
                 player_changesets[p0_id]
@@ -113,38 +989,296 @@ impl BattleData {
                     = player_changesets[p1_id].merge(&changeset1);
             }
         }
+        for (grabber, grabbed) in grabs {
+            if self.players[grabbed].held_by().is_none() {
+                self.players[grabber].hold(grabbed);
+                self.players[grabbed].be_held(grabber);
+            }
+        }
+
+        let collisions = check_for_collision_pairs(self.projectiles.as_slice(), self.players.as_slice());
+        self.contacts.record(|| collisions.iter().flat_map(Collision::world_hitboxes).collect());
+        for c in collisions {
+            let (projectile_id, player_id) = c.ids;
+            let (attacker, attack) = (c.objs.0.owner, c.objs.0.attack.clone());
+            let (projectile_changeset, player_changeset) = res::handle_projectile_player_collision(c);
+            if let Some(projectile_changeset) = projectile_changeset {
+                projectile_changesets[projectile_id]
+                    = projectile_changesets[projectile_id].merge(&projectile_changeset);
+            }
+            if let Some(player_changeset) = player_changeset {
+                player_changesets[player_id]
+                    = player_changesets[player_id].merge(&player_changeset);
+                self.hits.push(Hit { attacker, defender: player_id, attack });
+            }
+        }
+
+        let collisions = check_for_collision_pairs(self.projectiles.as_slice(), self.arena.platforms());
+        for c in collisions {
+            let (projectile_id, platform_id) = c.ids;
+            let (projectile_changeset, platform_changeset) = res::handle_projectile_platform_collision(c);
+            if let Some(projectile_changeset) = projectile_changeset {
+                projectile_changesets[projectile_id]
+                    = projectile_changesets[projectile_id].merge(&projectile_changeset);
+            }
+            if let Some(platform_changeset) = platform_changeset {
+                platform_changesets[platform_id] = match platform_changesets[platform_id].take() {
+                    Some(changeset) => Some(changeset.merge(&platform_changeset)),
+                    None => Some(platform_changeset),
+                };
+            }
+        }
 
         // TODO consider rollback, generic collision resolution
 
         // Apply changes.
+        let damage_before: Vec<_> = self.players.iter().map(Player::damage_pct).collect();
         for (idx, changeset) in player_changesets.into_iter().enumerate() {
             self.players[idx].apply_changeset(changeset);
         }
+        self.credit_damage(&damage_before);
         for (idx, changeset) in platform_changesets.into_iter().enumerate() {
             match changeset {
-                Some(changeset) => self.arena.platforms[idx].apply_changeset(changeset),
+                Some(changeset) => self.arena.platforms_mut()[idx].apply_changeset(changeset),
                 None => (),
             };
         }
+        for (idx, changeset) in projectile_changesets.into_iter().enumerate() {
+            self.projectiles[idx].apply_changeset(changeset);
+        }
 
         // Advance time.
+        self.tick_count += 1;
         for player in &mut self.players {
             player.handle_phys_update();
+            player.passive_tick();
         }
-        for platform in &mut self.arena.platforms {
+        for platform in self.arena.platforms_mut() {
             platform.handle_phys_update();
         }
+        for projectile in &mut self.projectiles {
+            projectile.handle_phys_update();
+        }
+        self.projectiles.retain(|projectile| !projectile.is_spent());
+
+        self.check_triggers();
+        self.release_players();
+        self.record_trails();
+        self.update_ambience();
+        self.update_camera();
+        self.check_round_over();
+        for player in &mut self.players {
+            self.sounds.extend(player.take_sfx());
+        }
+    }
+
+    /// Credits the damage each player took this tick, from `damage_before` to now, to whoever hit
+    /// them, split evenly between them.
+    fn credit_damage(&mut self, damage_before: &[f32]) {
+        for (defender, before) in damage_before.iter().enumerate() {
+            let taken = self.players[defender].damage_pct() - before;
+            if taken <= 0. {
+                continue;
+            }
+            let mut attackers: Vec<_> = self.hits.iter()
+                .filter(|hit| hit.defender == defender && hit.attacker != defender)
+                .map(|hit| hit.attacker)
+                .collect();
+            attackers.sort_unstable();
+            attackers.dedup();
+            for &attacker in &attackers {
+                self.players[attacker].add_damage_dealt(taken / attackers.len() as f32);
+            }
+        }
+    }
+
+    /// Scores the round once it's decided. Until someone has won enough rounds, the next one is
+    /// set up after an intermission, and a drawn round is played again. Once someone has, or the
+    /// only player is eliminated, or a replay runs out, the battle is over and the skill points
+    /// are handed out.
+    fn check_round_over(&mut self) {
+        if self.match_state != MatchState::Active || !(self.is_over() || self.is_timed_out()) {
+            return;
+        }
+        let round_winner = self.round_winner();
+        let finished_playback = matches!(&self.playback, Some(playback) if playback.is_finished());
+        let rewards = match self.playback {
+            Some(_) => vec![],
+            None => self.compute_rewards(),
+        };
+        let battle_winner = self.winner();
+        // A lone player being eliminated has nothing to play again for.
+        let has_next_round = match round_winner {
+            Some(idx) => {
+                self.round_wins.resize(self.players.len(), 0);
+                self.round_wins[idx] += 1;
+                log::info!("Player {} wins the round. Rounds won: {:?}", idx + 1, self.round_wins);
+                true
+            },
+            None => self.players.len() > 1,
+        };
+        if has_next_round && battle_winner.is_none() && !finished_playback {
+            if round_winner.is_none() {
+                log::info!("Round drawn, playing it again.");
+            }
+            self.match_state = MatchState::Intermission { round_winner, frames_remaining: INTERMISSION_TICKS };
+            return;
+        }
+        log::info!("Battle rewards: {:?}", rewards);
+        self.match_state = MatchState::PostMatch(rewards);
+    }
+
+    /// Puts everyone back at their spawn points for the next round, with nothing left over from
+    /// the last one, and counts down to it.
+    fn start_round(&mut self) {
+        for player in &mut self.players {
+            player.start_round(self.rounds.stocks_per_round);
+        }
+        self.projectiles.clear();
+        for trail in &mut self.trails {
+            trail.clear();
+        }
+        self.trigger_occupants.clear();
+        self.pending_inputs.clear();
+        self.round_start_tick = self.tick_count;
+        self.match_state = MatchState::Countdown { frames_remaining: COUNTDOWN_TICKS };
+    }
+
+    /// Keeps every player still in the battle in view.
+    fn update_camera(&mut self) {
+        let bodies: Vec<_> = self.players.iter()
+            .filter(|player| !player.is_eliminated())
+            .filter_map(|player| player.get_broad_aabb())
+            .collect();
+        self.camera.follow(&bodies, &self.arena.bounds());
+    }
+
+    /// Mixes the ambient sounds for where the players are, since that's what the screen shows.
+    fn update_ambience(&mut self) {
+        let ambience = match &mut self.ambience {
+            Some(ambience) => ambience,
+            None => return,
+        };
+        let positions: Vec<_> = self.players.iter()
+            .filter(|player| !player.is_eliminated())
+            .map(Collidable::get_offset)
+            .collect();
+        if positions.is_empty() {
+            return;
+        }
+        let listener = positions.iter().fold(na::Vector2::zeros(), |sum, position| sum + position) / positions.len() as f32;
+        let platform_origins: Vec<_> = self.arena.platforms().iter()
+            .map(|platform| platform.get_offset() + platform.body.pos)
+            .collect();
+        ambience.update(listener, &platform_origins);
+    }
+
+    /// Spawns a projectile for every player who fired since the last tick.
+    fn fire_projectiles(&mut self) {
+        for (owner, player) in self.players.iter_mut().enumerate() {
+            if let Some((attack, position, direction)) = player.take_fire_request() {
+                self.projectiles.push(match attack {
+                    Attack::Ability(Ability::Fireball) => Projectile::fireball(owner, position, &direction),
+                    _ => Projectile::new(owner, position, &direction),
+                });
+            }
+        }
+    }
+
+    /// Lets go of every player who was thrown since the last tick, returning their changes.
+    fn throw_players(&mut self) -> Vec<(usize, PlayerChangeSet)> {
+        let mut throws = vec![];
+        for thrower in 0..self.players.len() {
+            if let Some((thrown, dir)) = self.players[thrower].take_throw_request() {
+                self.players[thrown].release();
+                if let Some(changeset) = interactions::throw(&self.players[thrower], &self.players[thrown], &dir) {
+                    throws.push((thrown, changeset));
+                }
+            }
+        }
+        throws
+    }
+
+    /// Frees every held player whose holder let go of them.
+    fn release_players(&mut self) {
+        for idx in 0..self.players.len() {
+            if let Some(holder) = self.players[idx].held_by() {
+                if self.players[holder].grabbed_player() != Some(idx) {
+                    self.players[idx].release();
+                }
+            }
+        }
+    }
+
+    /// Calls `on_trigger_enter` for every player who entered one of the arena's triggers since
+    /// the last tick.
+    fn check_triggers(&mut self) {
+        self.triggered.clear();
+        let triggers = self.arena.triggers();
+        let occupants: Vec<_> = check_for_collision_pairs(self.players.as_slice(), triggers.as_slice())
+            .iter()
+            .filter(|c| interactions::is_inside_trigger(c))
+            .map(|c| c.ids)
+            .collect();
+        for &(player, trigger) in &occupants {
+            if !self.trigger_occupants.contains(&(player, trigger)) {
+                self.on_trigger_enter(player, &triggers[trigger]);
+            }
+        }
+        self.trigger_occupants = occupants;
+    }
+
+    /// Reacts to `player` entering `trigger`.
+    fn on_trigger_enter(&mut self, player: usize, trigger: &TriggerZone) {
+        log::debug!("Player {} entered {:?}.", player, trigger.kind);
+        self.triggered.push((player, trigger.kind.clone()));
+        match trigger.kind {
+            TriggerKind::BlastZone => self.knock_out_player(player),
+            TriggerKind::Named(_) => (),
+        }
+    }
+
+    /// Takes a stock from a player who left the blast zone.
+    fn knock_out_player(&mut self, idx: usize) {
+        let player = &mut self.players[idx];
+        if player.is_eliminated() {
+            return;
+        }
+        log::info!("Player knocked out at {:?}.", player.get_offset());
+        player.knock_out();
+        self.tick_events.push(SimEvent::Ko);
+        // The respawned player shouldn't have a trail across the screen.
+        if let Some(trail) = self.trails.get_mut(idx) {
+            trail.clear();
+        }
+    }
+
+    /// Adds the tick that just ran to every player's trail.
+    fn record_trails(&mut self) {
+        self.trails.resize_with(self.players.len(), Trail::default);
+        for (idx, player) in self.players.iter_mut().enumerate() {
+            let mut events = vec![];
+            if player.take_jumped() {
+                events.push(TrailEvent::Jump);
+            }
+            if self.hits.iter().any(|hit| hit.defender == idx) {
+                events.push(TrailEvent::Hit);
+            }
+            self.trails[idx].record(TrailSample {
+                tick: self.tick_count,
+                position: player.get_offset(),
+                speed: player.velocity().norm(),
+                events,
+            });
+        }
     }
 }
 
 impl Drawable for BattleData {
+    /// Draws the world through the camera, then the timer and HUD over it as they are.
     fn draw(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
-        self.arena.draw(ctx, param)?;
-        for player in &self.players {
-            player.draw(ctx, param)?;
-        }
-        self.draw_timer(ctx, param)?;
-        Ok(())
+        self.draw_world(ctx, self.camera.apply(param))?;
+        self.draw_screen(ctx, param)
     }
 
     fn dimensions(&self, _ctx: &mut Context) -> Option<Rect> {
@@ -159,3 +1293,1073 @@ impl Drawable for BattleData {
         self.arena.blend_mode()
     }
 }
+
+/// A battle between `players` in an empty arena, to be used for testing. Doesn't need a `Context`.
+#[cfg(test)]
+pub fn test_battle(players: Vec<Player>) -> BattleData {
+    let arena = ron::de::from_str("(name: \"Empty\", platforms: [])").unwrap();
+    BattleData::new(arena, players, &Fonts::default())
+}
+
+#[cfg(test)]
+mod battle_test {
+    use super::*;
+
+    fn empty_battle() -> BattleData {
+        test_battle(vec![])
+    }
+
+    #[test]
+    fn battle_counts_ticks() {
+        let mut battle = empty_battle();
+        for _ in 0..90 {
+            battle.handle_update(&[]);
+        }
+        assert!(battle.elapsed_ticks() == 90);
+        assert!(format_timer(battle.elapsed_ticks()) == "00:01");
+    }
+
+    #[test]
+    fn falling_off_loses_a_stock() {
+        let mut battle = empty_battle();
+        battle.players.push(player::bare_test_player());
+        let mut kos = 0;
+        for _ in 0..2000 {
+            battle.handle_update(&[]);
+            if battle.tick_events().contains(&SimEvent::Ko) {
+                kos += 1;
+            }
+        }
+        // Keeps falling with nothing to land on, so every stock is lost.
+        assert!(kos == 3);
+        assert!(battle.players[0].is_eliminated());
+    }
+
+    #[test]
+    fn trails_record_movement_and_restart_on_ko() {
+        let mut battle = empty_battle();
+        battle.players.push(player::bare_test_player());
+        battle.handle_update(&TickInput::only(0, vec![Action::Jump]));
+        battle.handle_update(&[]);
+        let samples = battle.trails[0].samples();
+        assert!(samples.len() == 2);
+        assert!(samples[0].events == vec![TrailEvent::Jump] && samples[1].events.is_empty());
+        assert!(samples[1].tick == 2 && samples[1].position == battle.players[0].get_offset());
+        while !battle.tick_events().contains(&SimEvent::Ko) {
+            battle.handle_update(&[]);
+        }
+        assert!(battle.trails[0].samples().len() == 1);
+    }
+
+    #[test]
+    fn platforms_and_projectiles_skip_their_own_kind() {
+        let arena: Arena = ron::de::from_str("(
+            name: \"Overlapping\",
+            platforms: [
+                (body: (pos: [0, 0], size: [100, 10], ori: 0), can_move_through: false),
+                (body: (pos: [50, 0], size: [100, 10], ori: 0), can_move_through: true),
+            ],
+        )").unwrap();
+        assert!(check_for_collisions(arena.platforms()).is_empty());
+
+        let player = player::bare_test_player();
+        let at_player = player.get_offset();
+        let projectiles = [
+            Projectile::new(1, at_player, &player::stance::HorizontalStance::Left),
+            Projectile::new(1, at_player, &player::stance::HorizontalStance::Right),
+        ];
+        assert!(check_for_collisions(&projectiles).is_empty());
+        assert!(check_for_collision_pairs(&projectiles, std::slice::from_ref(&player)).len() == 2);
+    }
+
+    /// A battle with two players standing level, facing each other 100 apart.
+    fn duel() -> BattleData {
+        let mut battle = empty_battle();
+        battle.gravity = na::Vector2::zeros();
+        battle.players.push(player::bare_test_player());
+        battle.players.push(player::bare_test_player_at(na::Vector2::new(0., 0.)));
+        battle
+    }
+
+    const TWO_SPAWN_ARENA: &str = "(
+        name: \"Two\",
+        platforms: [(body: (pos: [-200, 100], size: [500, 10], ori: 0), can_move_through: false)],
+        spawn_points: [[0, 70], [20, 70]],
+    )";
+
+    #[test]
+    fn player_count_limited_by_spawn_points_and_controls() {
+        let arena: Arena = ron::de::from_str(TWO_SPAWN_ARENA).unwrap();
+        assert!(BattleData::check_player_count(&arena, 2).is_ok());
+        let none = BattleData::check_player_count(&arena, 0);
+        assert!(matches!(none, Err(WalpurgisError::WrongPlayerCount { players: 0, spawn_points: 2 })));
+        match BattleData::check_player_count(&arena, 3) {
+            Err(e @ WalpurgisError::WrongPlayerCount { players: 3, spawn_points: 2 }) => {
+                assert!(e.to_string().contains("3 players"))
+            },
+            other => panic!("Expected a player count error, got {:?}", other),
+        }
+        let crowded: Arena = ron::de::from_str(&TWO_SPAWN_ARENA.replace("[20, 70]]", "[20, 70], [40, 70]]")).unwrap();
+        assert!(BattleData::check_player_count(&crowded, 3).is_ok());
+        assert!(InputScheme::for_player(2, &settings::Controls::default()).is_err());
+    }
+
+    #[test]
+    fn characters_picked_by_race() {
+        let named = |name: &str, race| Rc::new(PlayerBlueprint { name: name.to_owned(), ..PlayerBlueprint::placeholder(race) });
+        let blueprints = vec![named("Zorp", Race::Alien), named("Clank", Race::Robot), named("Bolt", Race::Robot)];
+        let picked = |characters: &[Option<String>], races: &[Race]| -> Vec<String> {
+            pick_blueprints(&blueprints, characters, races, 3).into_iter().map(|blueprint| blueprint.name).collect()
+        };
+        // Without races, players take the characters in turn.
+        assert!(picked(&[], &[]) == vec!["Zorp", "Clank", "Bolt"]);
+        assert!(picked(&[None, Some("Zorp".to_owned())], &[]) == vec!["Zorp", "Zorp", "Bolt"]);
+        // Picked characters of another race give way to the first of the race picked.
+        let races = [Race::Robot, Race::Robot, Race::Mage];
+        assert!(picked(&[Some("Zorp".to_owned()), Some("Bolt".to_owned())], &races) == vec!["Clank", "Bolt", "Mage"]);
+        let placeholder = pick_blueprints(&blueprints, &[], &races, 3).pop().unwrap();
+        assert!(placeholder.race == Race::Mage && placeholder.sprite_sheet.is_none());
+    }
+
+    #[test]
+    fn reloaded_arena_swapped_in() {
+        let mut battle = empty_battle();
+        let reloaded: Arena = ron::de::from_str(TWO_SPAWN_ARENA).unwrap();
+        // Battles in other arenas, or not loaded from a file at all, are left alone.
+        battle.reload_arena(Path::new("two.ron"), &reloaded);
+        assert!(battle.arena.platforms().is_empty());
+        battle.arena_file = Some(PathBuf::from("two.ron"));
+        battle.reload_arena(Path::new("other.ron"), &reloaded);
+        assert!(battle.arena.platforms().is_empty());
+        battle.reload_arena(Path::new("two.ron"), &reloaded);
+        assert!(battle.arena.platforms().len() == 1 && battle.arena.spawn_points().len() == 2);
+        // Only the layout changes.
+        assert!(battle.arena.name() == "Empty");
+    }
+
+    #[test]
+    fn gravity_stops_at_terminal_velocity() {
+        let mut battle = empty_battle();
+        battle.players.push(player::bare_test_player());
+        battle.tune(&Tuning { terminal_velocity: 0.5, ..Tuning::default() });
+        for _ in 0..200 {
+            battle.handle_update(&[]);
+        }
+        let falling = battle.players[0].velocity()[1];
+        assert!(falling >= 0.5 && falling < 0.5 + battle.gravity[1] + 1e-5);
+    }
+
+    #[test]
+    fn two_players_settle_apart() {
+        let arena = ron::de::from_str(TWO_SPAWN_ARENA).unwrap();
+        let players = vec![player::bare_test_player(), player::bare_test_player()];
+        let mut battle = BattleData::new(arena, players, &Fonts::default());
+        // Spawned overlapping, so they get pushed apart.
+        for _ in 0..100 {
+            battle.handle_update(&[]);
+            for player in &battle.players {
+                assert!(player.get_offset().iter().all(|coord| coord.is_finite()));
+                assert!(player.velocity().norm() < 5.);
+            }
+        }
+        let (left, right) = (battle.players[0].get_offset(), battle.players[1].get_offset());
+        assert!(right[0] - left[0] > 20.);
+        assert!(battle.players.iter().all(|player| player.stocks() == 3 && player.damage_pct() == 0.));
+    }
+
+    /// Hashes everything the simulation depends on, leaving out what is only kept for drawing.
+    fn full_state_hash(battle: &BattleData) -> u64 {
+        let trails: Vec<_> = battle.trails.iter().map(Trail::samples).collect();
+        let state = format!("{} {:?} {:?} {:?} {:?}", battle.tick_count, battle.players, battle.projectiles, battle.hits, trails);
+        crate::util::hash::fnv1a(state.as_bytes())
+    }
+
+    /// Plays a scripted battle headlessly with every debug channel on, calling `between_ticks`
+    /// after every tick the way the game prepares for drawing. Returns the state hash of every
+    /// tick.
+    fn scripted_run<F: FnMut(&mut BattleData)>(mut between_ticks: F) -> Vec<u64> {
+        let arena = ron::de::from_str(TWO_SPAWN_ARENA).unwrap();
+        let players = vec![player::bare_test_player(), player::bare_test_player()];
+        let mut battle = BattleData::new(arena, players, &Fonts::default());
+        let mut channels = DebugChannels::default();
+        for channel in DebugChannel::ALL.iter() {
+            channels.set(*channel, true);
+        }
+        battle.set_debug_channels(&channels);
+        let light = Action::Attack(Attack::Basics(player::meta::BasicClass::Light, player::action::AttackDir::Side));
+        (0..300)
+            .map(|tick| {
+                let inputs = match tick % 60 {
+                    0 => TickInput::only(0, vec![Action::Jump]),
+                    10..=30 => TickInput::only(0, vec![Action::Walk(player::stance::HorizontalStance::Right)]),
+                    40 => TickInput::only(1, vec![light.clone()]),
+                    _ => vec![],
+                };
+                battle.handle_update(&inputs);
+                between_ticks(&mut battle);
+                full_state_hash(&battle)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn preparing_to_draw_leaves_simulation_alone() {
+        let undrawn = scripted_run(|_| ());
+        let mut prepares = 0;
+        let drawn = scripted_run(|battle| {
+            battle.prepare();
+            // Drawing may happen more often than ticks.
+            battle.prepare();
+            prepares += 2;
+        });
+        assert!(prepares == 600);
+        assert!(undrawn == drawn);
+        assert!(undrawn.windows(2).any(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn same_inputs_replay_same_battle() {
+        use player::{action::AttackDir, meta::BasicClass, stance::HorizontalStance};
+        let battle = || {
+            let arena = ron::de::from_str(TWO_SPAWN_ARENA).unwrap();
+            let players = vec![player::bare_test_player(), Player::new(player::meta::Race::Mage, InputScheme::default()).unwrap()];
+            BattleData::new(arena, players, &Fonts::default())
+        };
+        let script = |tick: u64| {
+            let actions = |player: u64| match (tick + 37 * player) % 120 {
+                0 => vec![Action::Jump],
+                5..=25 => vec![Action::Walk(HorizontalStance::Right)],
+                30 => vec![Action::Attack(Attack::Basics(BasicClass::Light, AttackDir::Side))],
+                50..=70 => vec![Action::Walk(HorizontalStance::Left)],
+                80 => vec![Action::Attack(Attack::Ranged), Action::UseAbility(0)],
+                100 => vec![Action::Grab],
+                _ => vec![],
+            };
+            vec![TickInput { actions: actions(0), ..Default::default() }, TickInput { actions: actions(1), ..Default::default() }]
+        };
+        let (mut first, mut second) = (battle(), battle());
+        let start = first.state_hash();
+        for tick in 0..1000 {
+            let inputs = script(tick);
+            first.handle_update(&inputs);
+            second.handle_update(&inputs);
+            assert!(first.state_hash() == second.state_hash());
+        }
+        assert!(first.elapsed_ticks() == 1000);
+        assert!(first.state_hash() != start);
+    }
+
+    #[test]
+    fn recorded_battles_play_back() {
+        use player::{action::AttackDir, meta::{BasicClass, Race}, stance::HorizontalStance};
+        let arena = || ron::de::from_str::<Arena>(TWO_SPAWN_ARENA).unwrap();
+        let blueprints = vec![
+            player::test_blueprint(Race::Robot, na::Vector2::new(0., 0.)),
+            player::test_blueprint(Race::Mage, na::Vector2::new(0., 0.)),
+        ];
+        let battle = |blueprints: &[player::blueprint::PlayerBlueprint]| {
+            let players = blueprints.iter()
+                .map(|blueprint| Player::without_sprites(blueprint, InputScheme::default()))
+                .collect();
+            BattleData::new(arena(), players, &Fonts::default())
+        };
+
+        let mut recorded = battle(&blueprints);
+        recorded.recording = Some(Replay::new(&recorded.arena, blueprints.clone(), vec![], Tuning::default(), settings::MatchConfig::default()).unwrap());
+        for tick in 0..600_u64 {
+            recorded.pending_inputs = match tick % 90 {
+                0 => TickInput::only(1, vec![Action::Jump]),
+                10..=30 => TickInput::only(0, vec![Action::Walk(HorizontalStance::Right)]),
+                40 => TickInput::only(1, vec![Action::Attack(Attack::Basics(BasicClass::Light, AttackDir::Side))]),
+                60 => TickInput::only(1, vec![Action::UseAbility(0)]),
+                _ => vec![],
+            };
+            let inputs = recorded.take_inputs();
+            recorded.handle_update(&inputs);
+        }
+
+        let replay = recorded.recording.take().unwrap();
+        let serialized = ron::ser::to_string(&replay).unwrap();
+        let replay: Replay = ron::de::from_str(&serialized).unwrap();
+        replay.check_arena(&arena()).unwrap();
+        let mut played_back = battle(&replay.blueprints);
+        played_back.playback = Some(Playback::new(replay));
+        // Keyboard input is ignored while watching.
+        played_back.pending_inputs = TickInput::only(0, vec![Action::Jump]);
+        while !played_back.is_over() {
+            let inputs = played_back.take_inputs();
+            played_back.handle_update(&inputs);
+        }
+        assert!(played_back.elapsed_ticks() == 600);
+        assert!(played_back.state_hash() == recorded.state_hash());
+    }
+
+    /// Grabs the first player with the second, which stands in reach.
+    fn grabbed_duel() -> BattleData {
+        let mut battle = empty_battle();
+        battle.gravity = na::Vector2::zeros();
+        battle.players.push(player::bare_test_player());
+        battle.players.push(player::bare_test_player_at(na::Vector2::new(120., 0.)));
+        let mut inputs = TickInput::only(1, vec![Action::Grab]);
+        while battle.players[0].held_by().is_none() {
+            battle.handle_update(&inputs);
+            inputs.clear();
+        }
+        battle
+    }
+
+    #[test]
+    fn thrown_players_are_released_and_hurt() {
+        let mut battle = grabbed_duel();
+        assert!(battle.players[1].grabbed_player() == Some(0));
+        battle.handle_update(&TickInput::only(1, vec![Action::Throw(player::action::AttackDir::Up)]));
+        assert!(battle.players[0].held_by().is_none());
+        assert!(battle.players[0].damage_pct() > 0.);
+        assert!(battle.players[0].velocity()[1] < 0.);
+    }
+
+    #[test]
+    fn held_players_released_when_let_go() {
+        let mut battle = grabbed_duel();
+        for _ in 0..player::GRAB_HOLD_FRAMES {
+            battle.handle_update(&[]);
+        }
+        assert!(battle.players[1].grabbed_player().is_none());
+        assert!(battle.players[0].held_by().is_none());
+        assert!(battle.players[0].damage_pct() == 0.);
+    }
+
+    #[test]
+    fn projectile_damages_target_and_vanishes() {
+        let mut battle = duel();
+        battle.projectiles.push(Projectile::new(0, na::Vector2::new(100., 15.), &player::stance::HorizontalStance::Left));
+        for _ in 0..30 {
+            battle.handle_update(&[]);
+        }
+        assert!(battle.projectiles.is_empty());
+        assert!(battle.players[1].damage_pct() == projectile::DAMAGE);
+        assert!(battle.players[0].damage_pct() == 0.);
+        // Knocked away from where the projectile came from.
+        assert!(battle.players[1].velocity()[0] < 0.);
+    }
+
+    #[test]
+    fn mage_fireball_flies_and_hits_hard() {
+        let mut battle = duel();
+        battle.players[0] = Player::new(player::meta::Race::Mage, InputScheme::default()).unwrap();
+        battle.handle_update(&TickInput::only(0, vec![Action::UseAbility(0)]));
+        assert!(battle.projectiles.len() == 1);
+        let fired_at = battle.projectiles[0].position;
+        battle.handle_update(&[]);
+        assert!(battle.projectiles[0].position[0] < fired_at[0]);
+        let mut hits = vec![];
+        for _ in 0..30 {
+            battle.handle_update(&[]);
+            hits.extend(battle.hits().iter().map(|hit| hit.attack.clone()));
+        }
+        assert!(battle.projectiles.is_empty());
+        assert!(battle.players[1].damage_pct() == player::race::mage::FIREBALL_DAMAGE);
+        assert!(hits == vec![Attack::Ability(Ability::Fireball)]);
+    }
+
+    #[test]
+    fn haste_only_speeds_up_the_caster() {
+        use player::{meta::Race, race::mage};
+        let mut battle = empty_battle();
+        battle.arena = ron::de::from_str("(name: \"Floor\", platforms: [
+            (body: (pos: [0, 30], size: [400, 10], ori: 0), can_move_through: false),
+        ])").unwrap();
+        for x in &[0., 200.] {
+            let blueprint = player::test_blueprint(Race::Mage, na::Vector2::new(*x, 0.));
+            battle.players.push(Player::without_sprites(&blueprint, Default::default()));
+        }
+        battle.handle_update(&TickInput::only(0, vec![Action::UseAbility(1)]));
+        for _ in 0..mage::HASTE_STARTUP {
+            battle.handle_update(&[]);
+        }
+        let before: Vec<_> = battle.players.iter().map(|player| player.get_offset()[0]).collect();
+        let walk = TickInput { actions: vec![Action::Walk(player::stance::HorizontalStance::Right)], ..Default::default() };
+        for _ in 0..30 {
+            battle.handle_update(&[walk.clone(), walk.clone()]);
+        }
+        let walked: Vec<_> = battle.players.iter().zip(&before).map(|(player, x)| player.get_offset()[0] - x).collect();
+        assert!((walked[0] - walked[1] * mage::HASTE_MULTIPLIER).abs() < 0.01);
+    }
+
+    #[test]
+    fn alien_teleports_through_walls() {
+        let mut battle = empty_battle();
+        battle.arena = ron::de::from_str("(name: \"Wall\", platforms: [
+            (body: (pos: [40, -100], size: [10, 200], ori: 0), can_move_through: false),
+        ])").unwrap();
+        battle.players.push(Player::new(player::meta::Race::Alien, InputScheme::default()).unwrap());
+        // Walking into the wall doesn't get through it.
+        for _ in 0..60 {
+            battle.handle_update(&TickInput::only(0, vec![Action::Walk(player::stance::HorizontalStance::Left)]));
+        }
+        let blocked = battle.players[0].get_offset();
+        assert!(blocked[0] > 45.);
+        battle.handle_update(&TickInput::only(0, vec![Action::UseAbility(1)]));
+        let arrived = battle.players[0].get_offset();
+        assert!(arrived[0] + 30. < 40.);
+        assert!(arrived[0] <= blocked[0] - player::race::alien::TELEPORT_DISTANCE);
+        for _ in 0..10 {
+            battle.handle_update(&[]);
+        }
+        assert!(battle.players[0].get_offset()[0] + 30. < 40.);
+    }
+
+    #[test]
+    fn firing_and_getting_hit_make_sounds() {
+        let mut battle = duel();
+        battle.handle_update(&TickInput::only(0, vec![Action::Attack(Attack::Ranged)]));
+        assert!(battle.take_sounds() == vec![SoundEvent::Attack]);
+        for _ in 0..60 {
+            battle.handle_update(&[]);
+        }
+        assert!(battle.players[1].damage_pct() > 0.);
+        assert!(battle.take_sounds() == vec![SoundEvent::Hurt]);
+    }
+
+    #[test]
+    fn landing_makes_a_sound() {
+        let mut battle = battle_with_triggers("");
+        battle.players[0] = player::bare_test_player_at(na::Vector2::new(100., -50.));
+        let mut sounds = vec![];
+        for _ in 0..200 {
+            battle.handle_update(&[]);
+            sounds.extend(battle.take_sounds());
+        }
+        assert!(sounds == vec![SoundEvent::Land]);
+    }
+
+    #[test]
+    fn projectile_never_hits_owner() {
+        let mut battle = empty_battle();
+        battle.gravity = na::Vector2::zeros();
+        battle.players.push(player::bare_test_player());
+        let center = battle.players[0].get_offset() + na::Vector2::new(15., 15.);
+        battle.projectiles.push(Projectile::new(0, center, &player::stance::HorizontalStance::Left));
+        battle.handle_update(&[]);
+        assert!(battle.projectiles.len() == 1);
+        assert!(battle.players[0].damage_pct() == 0.);
+    }
+
+    #[test]
+    fn projectile_expires() {
+        let mut battle = empty_battle();
+        battle.projectiles.push(Projectile::new(0, na::Vector2::zeros(), &player::stance::HorizontalStance::Right));
+        let mut ticks = 0;
+        while !battle.projectiles.is_empty() {
+            battle.handle_update(&[]);
+            ticks += 1;
+        }
+        assert!(ticks == 90);
+    }
+
+    #[test]
+    fn projectile_stops_at_solid_platforms() {
+        let mut battle: BattleData = empty_battle();
+        battle.arena = ron::de::from_str("(name: \"Wall\", platforms: [
+            (body: (pos: [20, -50], size: [10, 100], ori: 0), can_move_through: false),
+            (body: (pos: [-30, -50], size: [10, 100], ori: 0), can_move_through: true),
+        ])").unwrap();
+        battle.projectiles.push(Projectile::new(0, na::Vector2::zeros(), &player::stance::HorizontalStance::Right));
+        battle.projectiles.push(Projectile::new(0, na::Vector2::zeros(), &player::stance::HorizontalStance::Left));
+        for _ in 0..10 {
+            battle.handle_update(&[]);
+        }
+        assert!(battle.projectiles.len() == 1);
+        assert!(battle.projectiles[0].velocity[0] < 0.);
+    }
+
+    #[test]
+    fn shot_platforms_break_and_come_back() {
+        let mut battle: BattleData = empty_battle();
+        battle.arena = ron::de::from_str(&format!("(name: \"Wall\", platforms: [
+            (body: (pos: [20, -50], size: [10, 100], ori: 0), can_move_through: false, hp: Some({}), respawn_timer: Some(30)),
+        ])", projectile::DAMAGE * 2.5)).unwrap();
+        let shoot = |battle: &mut BattleData| {
+            battle.projectiles.push(Projectile::new(0, na::Vector2::zeros(), &player::stance::HorizontalStance::Right));
+            for _ in 0..10 {
+                battle.handle_update(&[]);
+            }
+        };
+        for _ in 0..2 {
+            shoot(&mut battle);
+            assert!(battle.projectiles.is_empty());
+            assert!(!battle.arena.platforms()[0].is_broken());
+        }
+        battle.projectiles.push(Projectile::new(0, na::Vector2::zeros(), &player::stance::HorizontalStance::Right));
+        while !battle.arena.platforms()[0].is_broken() {
+            battle.handle_update(&[]);
+        }
+        // Back 30 ticks after the one it broke on.
+        let mut ticks = 1;
+        while battle.arena.platforms()[0].is_broken() {
+            assert!(battle.arena.platforms()[0].get_hitboxes().is_empty());
+            battle.handle_update(&[]);
+            ticks += 1;
+        }
+        assert!(ticks == 30);
+        battle.projectiles.clear();
+        shoot(&mut battle);
+        assert!(battle.projectiles.is_empty() && !battle.arena.platforms()[0].is_broken());
+    }
+
+    /// A battle in an arena whose blast zone only just fits the players' spawn points.
+    fn tight_battle(players: usize) -> BattleData {
+        let mut battle = empty_battle();
+        battle.arena = ron::de::from_str("(
+            name: \"Tight\",
+            platforms: [],
+            blast_zone: (left: -10, right: 200, top: -10, bottom: 50),
+            spawn_points: [[0, 0], [100, 0]],
+        )").unwrap();
+        for idx in 0..players {
+            let mut player = player::bare_test_player();
+            player.spawn_at(battle.arena.spawn_point(idx).unwrap());
+            battle.players.push(player);
+        }
+        battle
+    }
+
+    #[test]
+    fn leaving_blast_zone_respawns_at_spawn_point() {
+        let mut battle = tight_battle(2);
+        let spawn_point = battle.arena.spawn_point(1).unwrap();
+        let mut ko_tick = None;
+        for tick in 0..200 {
+            battle.handle_update(&[]);
+            if battle.tick_events().contains(&SimEvent::Ko) {
+                ko_tick = Some(tick);
+                break;
+            }
+        }
+        assert!(ko_tick.is_some());
+        assert!(battle.stocks() == vec![2, 2]);
+        assert!(battle.players[1].get_offset() == spawn_point);
+        assert!(!battle.is_over());
+    }
+
+    #[test]
+    fn last_player_standing_wins() {
+        let mut battle = tight_battle(3);
+        // The third player shares the first spawn point.
+        assert!(battle.players[2].get_offset() == battle.players[0].get_offset());
+        battle.players[0].knock_out();
+        for _ in 0..3 {
+            battle.players[1].knock_out();
+            battle.players[2].knock_out();
+        }
+        assert!(battle.is_over());
+        assert!(battle.winner() == Some(0));
+    }
+
+    #[test]
+    fn last_players_knocked_out_together_play_again() {
+        let mut battle = tight_battle(2);
+        for player in &mut battle.players {
+            player.knock_out();
+            player.knock_out();
+        }
+        assert!(battle.stocks() == vec![1, 1]);
+        // Both fall out of the bottom on the same tick.
+        for _ in 0..200 {
+            battle.handle_update(&[]);
+            if battle.is_over() {
+                break;
+            }
+        }
+        assert!(battle.stocks() == vec![0, 0]);
+        assert!(battle.winner().is_none() && !battle.is_draw());
+        assert!(matches!(battle.match_state(), MatchState::Intermission { round_winner: None, .. }));
+        assert!(battle.intermission_label() == Some("Round drawn \u{2014} 0:0".to_owned()));
+        skip_to_next_round(&mut battle);
+        assert!(battle.stocks().iter().all(|stocks| *stocks > 0));
+        assert!(battle.round_wins() == vec![0, 0]);
+    }
+
+    #[test]
+    fn solo_battle_ends_on_elimination() {
+        let mut battle = tight_battle(1);
+        for _ in 0..2 {
+            battle.players[0].knock_out();
+        }
+        assert!(!battle.is_over());
+        battle.players[0].knock_out();
+        assert!(battle.is_over());
+        assert!(battle.winner().is_none());
+        assert!(!battle.is_draw());
+    }
+
+    #[test]
+    fn one_stock_win_rewards() {
+        let mut battle = duel();
+        battle.tick_count = 2 * 60 * u64::from(TICKS_PER_SECOND);
+        battle.players[0].add_damage_dealt(250.);
+        battle.players[0].knock_out();
+        battle.players[0].knock_out();
+        for _ in 0..3 {
+            battle.players[1].knock_out();
+        }
+        // Taking part, winning, one stock, two hundreds of damage and two minutes.
+        assert!(battle.compute_rewards() == vec![(0, 1 + 3 + 1 + 2 * 2 + 2), (1, 1 + 2)]);
+        assert!(battle.match_state() == &MatchState::Active);
+        assert!(battle.match_record(0).is_none());
+        battle.handle_update(&[]);
+        assert!(battle.match_state() == &MatchState::PostMatch(vec![(0, 11), (1, 3)]));
+        let record = battle.match_record(42).unwrap();
+        assert!(record.timestamp == 42 && record.winner == Some(0));
+        assert!(record.duration == battle.elapsed_ticks() && record.participants.len() == 2);
+    }
+
+    fn eliminate(battle: &mut BattleData, idx: usize) {
+        while !battle.players[idx].is_eliminated() {
+            battle.players[idx].knock_out();
+        }
+    }
+
+    /// Ticks through the intermission and countdown until the next round is being played.
+    fn skip_to_next_round(battle: &mut BattleData) {
+        while battle.match_state != MatchState::Active {
+            let inputs = battle.take_inputs();
+            battle.handle_update(&inputs);
+        }
+    }
+
+    #[test]
+    fn rounds_played_until_battle_won() {
+        let mut battle = duel();
+        battle.play_rounds(&settings::MatchConfig { rounds_to_win: 2, ..Default::default() }, &[]);
+        let spawn_point = battle.players[1].get_offset();
+        battle.handle_update(&TickInput::only(1, vec![Action::Attack(Attack::Ranged), Action::Jump]));
+        assert!(battle.projectiles.len() == 1);
+        battle.players[1].knock_out();
+        eliminate(&mut battle, 0);
+        battle.handle_update(&[]);
+        assert!(battle.match_state() == &MatchState::Intermission { round_winner: Some(1), frames_remaining: INTERMISSION_TICKS });
+        assert!(battle.round_wins() == vec![0, 1] && battle.winner().is_none());
+        assert!(battle.intermission_label() == Some("Player 2 wins the round \u{2014} 0:1".to_owned()));
+        // Nobody acts between rounds.
+        for _ in 0..INTERMISSION_TICKS - 1 {
+            battle.pending_inputs = TickInput::only(1, vec![Action::Jump]);
+            let inputs = battle.take_inputs();
+            assert!(inputs.is_empty());
+            battle.handle_update(&inputs);
+        }
+        assert!(battle.match_state() == &MatchState::Intermission { round_winner: Some(1), frames_remaining: 1 });
+        battle.handle_update(&[]);
+        assert!(battle.match_state() == &MatchState::Countdown { frames_remaining: COUNTDOWN_TICKS });
+        // Everyone starts the next round afresh.
+        assert!(battle.stocks() == vec![3, 3] && battle.projectiles.is_empty());
+        let fresh = &battle.players[1];
+        assert!(fresh.get_offset() == spawn_point && fresh.velocity() == na::Vector2::zeros());
+        assert!(fresh.damage_pct() == 0.);
+        skip_to_next_round(&mut battle);
+        assert!(battle.countdown_label() == Some("GO!".to_owned()));
+        eliminate(&mut battle, 0);
+        battle.handle_update(&[]);
+        assert!(battle.winner() == Some(1) && battle.round_wins() == vec![0, 2]);
+        match battle.match_state() {
+            MatchState::PostMatch(rewards) => assert!(rewards[1].1 > rewards[0].1),
+            other => panic!("Expected the battle to be over, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn handicapped_stocks_end_battle_on_the_right_knock_out() {
+        let mut battle = duel();
+        let handicaps = [
+            Handicap { stock_offset: 1, ..Default::default() },
+            Handicap { starting_damage: 50., ..Default::default() },
+        ];
+        battle.play_rounds(&settings::MatchConfig { stocks_per_round: Some(2), ..Default::default() }, &handicaps);
+        assert!(battle.stocks() == vec![3, 2]);
+        assert!(battle.players[0].damage_pct() == 0. && battle.players[1].damage_pct() == 50.);
+        battle.players[0].knock_out();
+        battle.players[0].knock_out();
+        battle.players[1].knock_out();
+        battle.handle_update(&[]);
+        // Two stocks down would have been the end without the extra one.
+        assert!(battle.match_state() == &MatchState::Active && battle.winner().is_none());
+        battle.players[0].knock_out();
+        battle.handle_update(&[]);
+        assert!(matches!(battle.match_state(), MatchState::PostMatch(_)));
+        assert!(battle.winner() == Some(1));
+        // Respawning takes the starting damage away, like any knock out.
+        assert!(battle.players[1].damage_pct() == 0.);
+    }
+
+    #[test]
+    fn timed_out_rounds_go_to_stocks_then_damage() {
+        let mut battle = duel();
+        battle.play_rounds(&settings::MatchConfig {
+            rounds_to_win: 3,
+            time_limit_ticks: Some(10),
+            stocks_per_round: Some(2),
+        }, &[]);
+        assert!(battle.stocks() == vec![2, 2]);
+        battle.players[0].knock_out();
+        for _ in 0..10 {
+            battle.handle_update(&[]);
+        }
+        assert!(battle.is_timed_out());
+        assert!(matches!(battle.match_state(), MatchState::Intermission { round_winner: Some(1), .. }));
+        skip_to_next_round(&mut battle);
+        assert!(battle.stocks() == vec![2, 2] && !battle.is_timed_out());
+        // Level on stocks, so the least damaged wins.
+        battle.players[1].apply_changeset(PlayerChangeSet {
+            effects: vec![player::meta::Effect::Damage(30.)],
+            ..Default::default()
+        });
+        for _ in 0..10 {
+            battle.handle_update(&[]);
+        }
+        assert!(matches!(battle.match_state(), MatchState::Intermission { round_winner: Some(0), .. }));
+        assert!(battle.round_wins() == vec![1, 1]);
+        // Level on both, so nobody wins and the round is played again.
+        skip_to_next_round(&mut battle);
+        for _ in 0..10 {
+            battle.handle_update(&[]);
+        }
+        assert!(matches!(battle.match_state(), MatchState::Intermission { round_winner: None, .. }));
+        assert!(battle.winner().is_none() && !battle.is_draw());
+        assert!(battle.round_wins() == vec![1, 1]);
+        skip_to_next_round(&mut battle);
+        battle.players[0].knock_out();
+        for _ in 0..10 {
+            battle.handle_update(&[]);
+        }
+        assert!(matches!(battle.match_state(), MatchState::Intermission { round_winner: Some(1), .. }));
+        assert!(battle.round_wins() == vec![1, 2]);
+    }
+
+    #[test]
+    fn hitstun_wears_off_without_inputs() {
+        let mut battle = duel();
+        battle.players[0].apply_changeset(PlayerChangeSet { hitstun: 3, ..Default::default() });
+        assert!(battle.players[0].is_in_hitstun());
+        // Fast-forwarded ticks after the first of a window have no inputs.
+        for _ in 0..3 {
+            battle.handle_update(&[]);
+        }
+        assert!(!battle.players[0].is_in_hitstun());
+    }
+
+    #[test]
+    fn countdown_before_battle() {
+        let mut battle = duel();
+        battle.match_state = MatchState::Countdown { frames_remaining: COUNTDOWN_TICKS };
+        let start = battle.players[0].get_offset();
+        assert!(battle.countdown_label() == Some("3".to_owned()));
+        for _ in 0..TICKS_PER_SECOND + 1 {
+            battle.pending_inputs = TickInput::only(0, vec![Action::Jump]);
+            let inputs = battle.take_inputs();
+            battle.handle_update(&inputs);
+        }
+        assert!(battle.match_state() == &MatchState::Countdown { frames_remaining: COUNTDOWN_TICKS - TICKS_PER_SECOND - 1 });
+        assert!(battle.countdown_label() == Some("2".to_owned()));
+        // Nothing moved and the timer hasn't started.
+        assert!(battle.players[0].get_offset() == start && battle.elapsed_ticks() == 0);
+        for _ in 0..COUNTDOWN_TICKS - TICKS_PER_SECOND - 2 {
+            battle.handle_update(&[]);
+        }
+        assert!(battle.countdown_label() == Some("1".to_owned()));
+        battle.handle_update(&[]);
+        assert!(battle.match_state() == &MatchState::Active);
+        assert!(battle.countdown_label() == Some("GO!".to_owned()));
+        battle.handle_update(&TickInput::only(0, vec![Action::Jump]));
+        assert!(battle.players[0].get_offset() != start && battle.elapsed_ticks() == 1);
+        battle.tick_count = GO_TICKS;
+        assert!(battle.countdown_label().is_none());
+    }
+
+    #[test]
+    fn drawn_rounds_reward_nothing_until_won() {
+        let mut battle = tight_battle(2);
+        for player in &mut battle.players {
+            player.knock_out();
+            player.knock_out();
+        }
+        while !battle.is_over() {
+            battle.handle_update(&[]);
+        }
+        assert!(!matches!(battle.match_state(), MatchState::PostMatch(_)));
+        skip_to_next_round(&mut battle);
+        while battle.players[1].stocks() > 0 {
+            battle.players[1].knock_out();
+        }
+        battle.handle_update(&[]);
+        assert!(battle.winner() == Some(0) && !battle.is_draw());
+        assert!(matches!(battle.match_state(), MatchState::PostMatch(rewards) if rewards == &battle.compute_rewards()));
+    }
+
+    #[test]
+    fn duration_rewards_capped() {
+        let mut battle = duel();
+        battle.rewards.participation = 0;
+        battle.rewards.per_stock = 0;
+        battle.tick_count = 60 * 60 * u64::from(TICKS_PER_SECOND);
+        assert!(battle.compute_rewards() == vec![(0, 3), (1, 3)]);
+    }
+
+    #[test]
+    fn damage_credited_to_attacker() {
+        let mut battle = duel();
+        battle.players[0] = Player::new(player::meta::Race::Mage, InputScheme::default()).unwrap();
+        battle.handle_update(&TickInput::only(0, vec![Action::UseAbility(0)]));
+        for _ in 0..30 {
+            battle.handle_update(&[]);
+        }
+        assert!(battle.players[1].damage_pct() == player::race::mage::FIREBALL_DAMAGE);
+        assert!(battle.players[0].damage_dealt() == player::race::mage::FIREBALL_DAMAGE);
+        assert!(battle.players[1].damage_dealt() == 0.);
+    }
+
+    /// A battle with a player standing on a platform, with `triggers` in the arena.
+    fn battle_with_triggers(triggers: &str) -> BattleData {
+        let mut battle = empty_battle();
+        battle.arena = ron::de::from_str(&format!("(
+            name: \"Triggers\",
+            platforms: [(body: (pos: [0, 30], size: [300, 10], ori: 0), can_move_through: false)],
+            triggers: [{}],
+        )", triggers)).unwrap();
+        battle.players.push(player::bare_test_player());
+        battle
+    }
+
+    #[test]
+    fn triggers_report_entering_without_pushing() {
+        let mut battle = battle_with_triggers("(kind: Named(\"pickup\"), body: (pos: [90, 0], size: [20, 20], ori: 0))");
+        let mut untriggered = battle_with_triggers("");
+        let mut entered = vec![];
+        for _ in 0..60 {
+            battle.handle_update(&[]);
+            untriggered.handle_update(&[]);
+            entered.extend(battle.triggered().iter().cloned());
+            assert!(battle.players[0].get_offset() == untriggered.players[0].get_offset());
+        }
+        // Staying inside doesn't count as entering again.
+        assert!(entered == vec![(0, TriggerKind::Named("pickup".to_owned()))]);
+        assert!(battle.stocks() == vec![3]);
+    }
+
+    #[test]
+    fn blast_zone_is_entered_once_per_stock() {
+        let mut battle = tight_battle(1);
+        let mut kos = 0;
+        for _ in 0..1000 {
+            battle.handle_update(&[]);
+            let blasts = battle.triggered().iter().filter(|(_, kind)| *kind == TriggerKind::BlastZone).count();
+            assert!(blasts <= 1);
+            kos += blasts;
+        }
+        // The last stock leaves the player falling through the blast zone for good.
+        assert!(kos == 3);
+        assert!(battle.players[0].is_eliminated());
+    }
+
+    #[test]
+    fn dropped_player_lands_on_platform_below() {
+        let mut battle = battle_with_triggers("");
+        battle.players[0].spawn_at(na::Vector2::new(100., -100.));
+        for _ in 0..300 {
+            battle.handle_update(&[]);
+        }
+        // Resting on top of the platform, whose top is at y = 30.
+        let body = battle.players[0].get_broad_aabb().unwrap();
+        assert!((body.max[1] - 30.).abs() < 1e-3);
+        assert!(battle.players[0].velocity()[1].abs() < 1e-3);
+        assert!(battle.stocks() == vec![3]);
+    }
+
+    /// A battle with a player standing still on a platform that moves along `path` at `speed`.
+    fn riding_battle(path: &str, speed: f32) -> BattleData {
+        let mut battle = empty_battle();
+        battle.arena = ron::de::from_str(&format!("(name: \"Moving\", platforms: [(
+            body: (pos: [0, 30], size: [300, 10], ori: 0),
+            can_move_through: false,
+            path: {},
+            speed: {},
+        )])", path, speed)).unwrap();
+        battle.players.push(player::bare_test_player());
+        battle
+    }
+
+    /// How far the bottom of the player is from the top of the platform, and how far along it the
+    /// player stands.
+    fn rider_offset(battle: &BattleData) -> na::Vector2<f32> {
+        let body = battle.players[0].get_broad_aabb().unwrap();
+        let platform = &battle.arena.platforms()[0];
+        na::Vector2::new(body.min[0], body.max[1]) - (platform.body.pos + platform.get_offset())
+    }
+
+    #[test]
+    fn riders_move_with_platforms() {
+        // Turning back every 20 ticks, and going up, across and back down.
+        for &(path, mode) in &[("[[30, 0]]", "PingPong"), ("[[0, -40], [40, -40]]", "Loop")] {
+            let mut battle = riding_battle(&format!("{}, path_mode: {}", path, mode), 1.5);
+            battle.handle_update(&[]);
+            let start = rider_offset(&battle);
+            assert!(start[1].abs() < 1e-3);
+            let mut moved = 0_f32;
+            for _ in 0..100 {
+                battle.handle_update(&[]);
+                let offset = rider_offset(&battle);
+                assert!((offset - start).norm() < 1e-2, "{} drifted to {:?} from {:?}", path, offset, start);
+                moved = moved.max(battle.arena.platforms()[0].get_offset().norm());
+            }
+            assert!(moved > 25.);
+        }
+    }
+
+    #[test]
+    fn riders_orbit_turning_platforms() {
+        let mut battle = riding_battle("[], rotation_speed: 0.004", 0.);
+        // Standing near the right edge, 120 right of and 5 above the middle of the platform.
+        battle.players[0].spawn_at(na::Vector2::new(255., 0.));
+        let feet = |battle: &BattleData| {
+            let body = battle.players[0].get_broad_aabb().unwrap();
+            na::Vector2::new((body.min[0] + body.max[0]) / 2., body.max[1]) - battle.arena.platforms()[0].body.center()
+        };
+        battle.handle_update(&[]);
+        let start = feet(&battle);
+        for _ in 0..100 {
+            battle.handle_update(&[]);
+        }
+        // Turned 0.4 radians clockwise about the middle, sinking with the right end.
+        let end = feet(&battle);
+        assert!((end.norm() - start.norm()).abs() < 0.1, "Drifted from {:?} to {:?}", start, end);
+        let turned = end[1].atan2(end[0]) - start[1].atan2(start[0]);
+        assert!((turned - 0.4).abs() < 1e-2, "Turned {}", turned);
+        assert!(battle.players[0].carried()[1] > 0.);
+    }
+
+    #[test]
+    fn rising_platforms_pick_players_up() {
+        let mut battle = riding_battle("[[0, -200]]", 2.);
+        battle.gravity = na::Vector2::zeros();
+        battle.players[0].spawn_at(na::Vector2::new(100., -100.));
+        for _ in 0..100 {
+            battle.handle_update(&[]);
+        }
+        // The platform's top went from y = 30 to y = -170, taking the player up with it.
+        assert!(rider_offset(&battle)[1].abs() < 1e-2);
+        assert!(battle.players[0].get_broad_aabb().unwrap().max[1] < -150.);
+    }
+
+    #[test]
+    fn pushed_player_moves_the_same_way_everywhere() {
+        for &push in &[na::Vector2::new(1., 0.), na::Vector2::new(-1., 0.)] {
+            let mut battle = battle_with_triggers("");
+            let player = &battle.players[0];
+            let (body, drawn) = (player.get_broad_aabb().unwrap(), crate::physics::drawn_at(DrawParam::default(), player.get_offset()));
+            battle.players[0].handle_push(push);
+            battle.handle_update(&[]);
+            let player = &battle.players[0];
+            let (pushed_body, pushed_drawn) = (player.get_broad_aabb().unwrap(), crate::physics::drawn_at(DrawParam::default(), player.get_offset()));
+            // In the collision data.
+            assert!((pushed_body.min[0] - body.min[0]) * push[0] > 0.);
+            assert!(pushed_body.min[1] == body.min[1]);
+            // And on the screen.
+            assert!((pushed_drawn.dest.x - drawn.dest.x) * push[0] > 0.);
+            assert!(pushed_drawn.dest.y == drawn.dest.y);
+        }
+    }
+
+    #[test]
+    fn timer_format() {
+        let tps = u64::from(TICKS_PER_SECOND);
+        assert!(format_timer(0) == "00:00");
+        assert!(format_timer(tps - 1) == "00:00");
+        assert!(format_timer(59 * tps) == "00:59");
+        assert!(format_timer(61 * tps) == "01:01");
+        assert!(format_timer((59 * 60 + 59) * tps) == "59:59");
+    }
+
+    #[test]
+    fn timer_only_uses_atlas_glyphs() {
+        struct FixedWidth;
+        impl crate::text::MeasureText for FixedWidth {
+            fn width(&mut self, _text: &graphics::Text) -> f32 {
+                10.
+            }
+        }
+
+        let mut battle = empty_battle();
+        battle.players = vec![player::bare_test_player(), player::bare_test_player()];
+        battle.prepare();
+        let entries = battle.texts.len();
+        let misses = battle.texts.misses();
+        for frame in 0..600 {
+            let timer = format_timer(frame * 7);
+            let layout = battle.timer_glyphs.layout(&timer, &mut FixedWidth);
+            assert!(layout.len() == timer.chars().count());
+            battle.handle_update(&[]);
+            battle.prepare();
+        }
+        // Only the labels are cached, and they were built on the first frame.
+        assert!(battle.texts.len() == entries && entries == 2);
+        assert!(battle.texts.misses() == misses);
+    }
+
+    #[test]
+    fn banners_cached_while_shown() {
+        let mut battle = empty_battle();
+        battle.match_state = MatchState::Intermission { round_winner: Some(0), frames_remaining: INTERMISSION_TICKS };
+        battle.round_wins = vec![1];
+        battle.prepare();
+        battle.prepare();
+        let banner = battle.banner.clone().unwrap();
+        assert!(battle.texts.get(&banner).is_some());
+        assert!(battle.texts.misses() == 1 && battle.texts.hits() == 1);
+        battle.match_state = MatchState::Active;
+        for _ in 0..=TEXT_UNUSED_FRAMES {
+            battle.prepare();
+        }
+        assert!(battle.banner.is_none() && battle.texts.is_empty());
+    }
+
+    #[test]
+    fn ambience_follows_players_and_stops_with_battle() {
+        use crate::audio::MockAudio;
+
+        let arena: Arena = ron::de::from_str("(
+            name: \"Noisy\",
+            platforms: [(body: (pos: [300, 100], size: [100, 10], ori: 0), can_move_through: true)],
+            ambience: [
+                (sound: \"wind.ogg\", volume: 0.5),
+                (sound: \"waterfall.ogg\", placement: At([-200, 0]), max_distance: 300),
+                (sound: \"machinery.ogg\", placement: Platform(index: 0, offset: [50, 0]), max_distance: 300),
+            ],
+        )").unwrap();
+        let backend = MockAudio::default();
+        let mut battle = empty_battle();
+        battle.ambience = Some(Ambience::new(arena.ambience().to_vec(), Box::new(backend.clone()), 2));
+        battle.arena = arena;
+        battle.players.push(player::bare_test_player_at(na::Vector2::new(-150., 0.)));
+
+        battle.handle_update(&[]);
+        // Capped at two, so the machinery is left out even though it is in range.
+        assert!(backend.active_sources() == 2);
+        assert!(backend.playing.borrow()[&1].unwrap().pan < 0.);
+
+        battle.players[0] = player::bare_test_player_at(na::Vector2::new(300., 0.));
+        battle.handle_update(&[]);
+        assert!(backend.active_sources() == 2);
+        assert!(backend.playing.borrow()[&2].unwrap().pan > 0.);
+        assert!(!backend.playing.borrow().contains_key(&1));
+
+        drop(battle);
+        assert!(backend.active_sources() == 0);
+    }
+
+    #[test]
+    fn timer_format_over_an_hour() {
+        let tps = u64::from(TICKS_PER_SECOND);
+        assert!(format_timer(3600 * tps) == "1:00:00");
+        assert!(format_timer(100 * 60 * tps) == "1:40:00");
+        assert!(format_timer((25 * 3600 + 61) * tps) == "25:01:01");
+    }
+}
@@ -1,56 +1,298 @@
 use ggez::{Context, GameResult};
 use ggez::graphics::{Drawable, DrawParam, Rect, BlendMode};
+use ggez::nalgebra as na;
 use ron::de::from_reader;
 use serde::{Serialize, Deserialize};
 use std::fs::{self, File};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use crate::{
-    util::result::WalpurgisResult,
-    screens::battle::platform::Platform,
+    assets::AssetManager,
+    audio::AmbientEmitter,
+    sprites::SpriteSheet,
+    util::result::{WalpurgisError, WalpurgisResult},
+    physics::{broad::Aabb, drawn_at, BoundingBox, HitboxTag},
+    screens::battle::{
+        platform::Platform,
+        trigger::{TriggerKind, TriggerZone},
+    },
 };
 
+/// How far the blast zone's triggers reach past its edges. Anything further out is long gone.
+const BLAST_ZONE_DEPTH: f32 = 10_000.;
+
+/// How far past its platforms an arena without a blast zone of its own lets players go.
+const BLAST_ZONE_MARGIN: f32 = 600.;
+
+/// The fewest spawn points an arena can be loaded with.
+const MIN_SPAWN_POINTS: usize = 2;
+
 /// Stores data for the `Arena` outside of actual players.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Arena {
     /// Name of the Arena.
     name: String,
     /// `ggez`-specific. Not really used for anything atm.
     #[serde(skip)]
     mode: Option<BlendMode>,
-    // background_images: Vec<ggez::Image>,
-    // soundtracks: Vec<ggez::SoundData>,
-    pub platforms: Vec<Platform>,
+    /// Drawn behind everything else.
+    #[serde(default)]
+    background: Option<Background>,
+    /// The sprite sheet `background` is cut out of, once loaded.
+    #[serde(skip)]
+    background_sheet: Option<SpriteSheet>,
+    /// The music looped during battles in the arena, by name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    soundtrack: Option<String>,
+    /// Looping background sounds.
+    #[serde(default)]
+    ambience: Vec<AmbientEmitter>,
+    platforms: Vec<Platform>,
+    /// Players outside of this area lose a stock. Arenas without one get one around their
+    /// platforms.
+    #[serde(default, deserialize_with = "explicit_blast_zone")]
+    blast_zone: Option<BlastZone>,
+    /// Areas that notice players entering them, besides the blast zone.
+    #[serde(default)]
+    triggers: Vec<TriggerZone>,
+    /// Where players start and respawn. Players are assigned these in order. Loaded battles need
+    /// one for every player, while headless ones wrap around.
+    #[serde(default)]
+    spawn_points: Vec<na::Vector2<f32>>,
+}
+
+/// An image cut out of a sprite sheet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Background {
+    /// The sprite sheet's descriptor file relative to the asset root.
+    pub sprite_sheet: PathBuf,
+    pub frame: String,
+    /// Where the frame's top left corner goes.
+    pub pos: na::Vector2<f32>,
+}
+
+/// The edges of the area players have to stay inside of.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BlastZone {
+    pub left: f32,
+    pub right: f32,
+    pub top: f32,
+    pub bottom: f32,
+}
+
+/// Reads a blast zone written out in full, without the `Some` RON would otherwise want around it.
+fn explicit_blast_zone<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<BlastZone>, D::Error> {
+    BlastZone::deserialize(deserializer).map(Some)
+}
+
+impl Default for BlastZone {
+    fn default() -> Self {
+        BlastZone {
+            left: -400.,
+            right: 1200.,
+            top: -400.,
+            bottom: 1000.,
+        }
+    }
 }
 
 impl Arena {
-    // TODO: remove this once we don't need it anymore
-    /// Load the first arena in the arena directory.
-    pub fn load_first<P: AsRef<Path>>(arena_dir: P) -> WalpurgisResult<Self> {
+    /// Every file in the arena directory.
+    pub fn files<P: AsRef<Path>>(arena_dir: P) -> WalpurgisResult<Vec<PathBuf>> {
+        let arena_dir = arena_dir.as_ref();
+        let unreadable = |e| WalpurgisError::reading(arena_dir, "arena directory", e);
+        fs::read_dir(arena_dir)
+            .map_err(unreadable)?
+            .map(|entry| entry.map(|entry| entry.path()).map_err(unreadable))
+            .collect()
+    }
+
+    /// The first file in the arena directory.
+    pub fn first_file<P: AsRef<Path>>(arena_dir: P) -> WalpurgisResult<PathBuf> {
         let arena_dir = arena_dir.as_ref();
-        log::info!("Loading first arena from assets directory: `{}`", arena_dir.display());
 
         // Really should be using the `glob` crate but don't want to
         // introduce an extra dependency just for this.
         let opt_arena_file = fs::read_dir(arena_dir)
-            .and_then(|mut entries| entries.next().transpose())?;
+            .and_then(|mut entries| entries.next().transpose())
+            .map_err(|e| WalpurgisError::reading(arena_dir, "arena directory", e))?;
+
+        match opt_arena_file {
+            Some(arena_file) => Ok(arena_file.path()),
+            None => Err(WalpurgisError::AssetNotFound { path: arena_dir.to_path_buf(), what: "arena" }),
+        }
+    }
+
+    /// Takes on the platforms, blast zone, triggers and spawn points of `other`, e.g. a newer
+    /// version of the same arena. The background and sounds are kept.
+    pub fn reload_layout(&mut self, other: &Arena) {
+        self.platforms = other.platforms.clone();
+        self.blast_zone = other.blast_zone;
+        self.triggers = other.triggers.clone();
+        self.spawn_points = other.spawn_points.clone();
+        self.set_blend_mode(self.mode);
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn platforms(&self) -> &[Platform] {
+        &self.platforms
+    }
+
+    pub fn platforms_mut(&mut self) -> &mut [Platform] {
+        &mut self.platforms
+    }
+
+    /// The blast zone, or if the arena doesn't have one, the area around everywhere its platforms
+    /// can go, with room to spare.
+    fn blast_zone(&self) -> BlastZone {
+        if let Some(blast_zone) = self.blast_zone {
+            return blast_zone;
+        }
+        let reach = self.platforms.iter()
+            .flat_map(|platform| {
+                std::iter::once(na::Vector2::zeros())
+                    .chain(platform.path.iter().copied())
+                    .filter_map(move |offset| Aabb::enclosing(std::slice::from_ref(&platform.body), offset))
+            })
+            .fold(None, |reach: Option<Aabb>, aabb| Some(match reach {
+                Some(reach) => reach.union(&aabb),
+                None => aabb,
+            }));
+        match reach {
+            Some(Aabb { min, max }) => BlastZone {
+                left: min[0] - BLAST_ZONE_MARGIN,
+                right: max[0] + BLAST_ZONE_MARGIN,
+                top: min[1] - BLAST_ZONE_MARGIN,
+                bottom: max[1] + BLAST_ZONE_MARGIN,
+            },
+            None => BlastZone::default(),
+        }
+    }
 
-        if let Some(arena_file) = opt_arena_file {
-            Arena::load(arena_file.path())
+    /// All of the arena's triggers, including the four making up the outside of the blast zone.
+    pub fn triggers(&self) -> Vec<TriggerZone> {
+        let BlastZone { left, right, top, bottom } = self.blast_zone();
+        let (width, height) = (right - left, bottom - top);
+        let depth = BLAST_ZONE_DEPTH;
+        let blast_zone = |x, y, w, h| TriggerZone {
+            kind: TriggerKind::BlastZone,
+            body: BoundingBox {
+                mode: None,
+                tag: HitboxTag::Solid,
+                pos: na::Vector2::new(x, y),
+                size: na::Vector2::new(w, h),
+                ori: 0.,
+            },
+        };
+        self.triggers.iter()
+            .cloned()
+            .chain(vec![
+                blast_zone(left - depth, top - depth, depth, height + 2. * depth),
+                blast_zone(right, top - depth, depth, height + 2. * depth),
+                blast_zone(left, top - depth, width, depth),
+                blast_zone(left, bottom, width, depth),
+            ])
+            .collect()
+    }
+
+    /// The area players have to stay inside of.
+    pub fn bounds(&self) -> Aabb {
+        let BlastZone { left, right, top, bottom } = self.blast_zone();
+        Aabb { min: na::Vector2::new(left, top), max: na::Vector2::new(right, bottom) }
+    }
+
+    /// Where players start and respawn, in the order they're assigned.
+    pub fn spawn_points(&self) -> &[na::Vector2<f32>] {
+        &self.spawn_points
+    }
+
+    /// The spawn point of the player at `idx`, if the arena has any.
+    pub fn spawn_point(&self, idx: usize) -> Option<na::Vector2<f32>> {
+        if self.spawn_points.is_empty() {
+            None
         } else {
-            Err(format!("No arena file found in the directory `{}`.", arena_dir.display()))?
+            Some(self.spawn_points[idx % self.spawn_points.len()])
+        }
+    }
+
+    pub fn soundtrack(&self) -> Option<&str> {
+        self.soundtrack.as_deref()
+    }
+
+    pub fn ambience(&self) -> &[AmbientEmitter] {
+        &self.ambience
+    }
+
+    /// Loads the sprite sheet of the arena's background, if it has one. The asset root has to be
+    /// mounted in `ggez`'s filesystem.
+    pub fn load_background(&mut self, ctx: &mut Context, assets: &mut AssetManager) -> WalpurgisResult {
+        if let Some(background) = &self.background {
+            let sheet = SpriteSheet::load(ctx, assets, &background.sprite_sheet)?;
+            if sheet.frame(&background.frame).is_none() {
+                Err(format!(
+                    "The background of arena `{}` is frame `{}`, which `{}` doesn't have.",
+                    self.name,
+                    background.frame,
+                    background.sprite_sheet.display(),
+                ))?
+            }
+            self.background_sheet = Some(sheet);
         }
+        Ok(())
     }
 
-    /// Tries to load an `Arena` from the given file.
+    /// Tries to load an `Arena` from the given file, and checks that battles can be played in it.
+    /// Errors name the file.
     pub fn load<P: AsRef<Path>>(arena_file: P) -> WalpurgisResult<Self> {
-        let f = File::open(arena_file)?;
-        Ok(from_reader(f)?)
+        let arena_file = arena_file.as_ref();
+        let f = File::open(arena_file).map_err(|e| WalpurgisError::reading(arena_file, "arena", e))?;
+        let arena: Self = from_reader(f).map_err(|e| WalpurgisError::parsing(arena_file, "arena", e))?;
+        arena.validate().map_err(|e| format!("`{}`: {}", arena_file.display(), e))?;
+        Ok(arena)
+    }
+
+    /// Checks that the arena has room for at least two players, and that its platforms and spawn
+    /// points are inside its blast zone.
+    pub fn validate(&self) -> WalpurgisResult {
+        if self.spawn_points.len() < MIN_SPAWN_POINTS {
+            Err(format!(
+                "Arena `{}` has {} spawn points, but needs at least {}.",
+                self.name,
+                self.spawn_points.len(),
+                MIN_SPAWN_POINTS,
+            ))?
+        }
+        let bounds = self.bounds();
+        let inside = |aabb: &Aabb| {
+            bounds.min[0] <= aabb.min[0] && aabb.max[0] <= bounds.max[0]
+                && bounds.min[1] <= aabb.min[1] && aabb.max[1] <= bounds.max[1]
+        };
+        for (idx, platform) in self.platforms.iter().enumerate() {
+            let outside = std::iter::once(na::Vector2::zeros())
+                .chain(platform.path.iter().copied())
+                .filter_map(|offset| Aabb::enclosing(std::slice::from_ref(&platform.body), offset))
+                .any(|aabb| !inside(&aabb));
+            if outside {
+                Err(format!("Platform {} of arena `{}` goes outside of its blast zone.", idx, self.name))?
+            }
+        }
+        for (idx, spawn_point) in self.spawn_points.iter().enumerate() {
+            if !inside(&Aabb { min: *spawn_point, max: *spawn_point }) {
+                Err(format!("Spawn point {} of arena `{}` is outside of its blast zone.", idx, self.name))?
+            }
+        }
+        Ok(())
     }
 }
 
 impl Drawable for Arena {
     fn draw(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
+        if let (Some(background), Some(sheet)) = (&self.background, &self.background_sheet) {
+            sheet.draw_frame(ctx, &background.frame, drawn_at(param, background.pos))?;
+        }
         for platform in &self.platforms {
             platform.draw(ctx, param)?;
         }
@@ -72,3 +314,72 @@ impl Drawable for Arena {
         self.mode
     }
 }
+
+#[cfg(test)]
+mod arena_test {
+    use super::*;
+
+    #[test]
+    fn blast_zone_and_spawn_points_parsed() {
+        let arena: Arena = ron::de::from_str("(
+            name: \"Minimal\",
+            platforms: [],
+            blast_zone: (left: -50, right: 250, top: -100, bottom: 150),
+            spawn_points: [[10, 20], [30, 40]],
+        )").unwrap();
+        assert!(arena.bounds() == Aabb { min: na::Vector2::new(-50., -100.), max: na::Vector2::new(250., 150.) });
+        assert!(arena.spawn_points().len() == 2);
+        assert!(arena.spawn_point(1) == Some(na::Vector2::new(30., 40.)));
+        // More players than spawn points share them in turn.
+        assert!(arena.spawn_point(2) == Some(na::Vector2::new(10., 20.)));
+
+        let bare: Arena = ron::de::from_str("(name: \"Bare\", platforms: [])").unwrap();
+        assert!(bare.blast_zone() == BlastZone::default());
+        assert!(bare.spawn_point(0).is_none());
+    }
+
+    #[test]
+    fn blast_zone_defaults_around_platforms() {
+        let arena: Arena = ron::de::from_str("(
+            name: \"Moving\",
+            platforms: [
+                (body: (pos: [0, 100], size: [200, 10], ori: 0), can_move_through: false),
+                (body: (pos: [300, 0], size: [50, 10], ori: 0), can_move_through: true, path: [[0, 0], [0, -200]]),
+            ],
+        )").unwrap();
+        let margin = BLAST_ZONE_MARGIN;
+        assert!(arena.bounds() == Aabb {
+            min: na::Vector2::new(-margin, -200. - margin),
+            max: na::Vector2::new(350. + margin, 110. + margin),
+        });
+        assert!(arena.platforms().len() == 2 && arena.platforms()[1].can_move_through);
+    }
+
+    #[test]
+    fn unplayable_arenas_rejected() {
+        let arena = |platform_x: i32, spawn_points: &str| -> Arena {
+            ron::de::from_str(&format!("(
+                name: \"Checked\",
+                platforms: [(body: (pos: [{}, 100], size: [200, 10], ori: 0), can_move_through: false)],
+                blast_zone: (left: -100, right: 300, top: -100, bottom: 300),
+                spawn_points: {},
+            )", platform_x, spawn_points)).unwrap()
+        };
+        assert!(arena(0, "[[50, 50], [150, 50]]").validate().is_ok());
+        let message = |platform_x, spawn_points| arena(platform_x, spawn_points).validate().unwrap_err().to_string();
+        assert!(message(0, "[[50, 50]]").contains("1 spawn points"));
+        assert!(message(0, "[[50, 50], [500, 50]]").contains("Spawn point 1"));
+        assert!(message(200, "[[50, 50], [150, 50]]").contains("Platform 0"));
+    }
+
+    #[test]
+    fn load_checks_arena() {
+        let path = std::env::temp_dir().join(format!("walpurgis_lonely_arena_{}.ron", std::process::id()));
+        fs::write(&path, "(name: \"Lonely\", platforms: [], spawn_points: [[0, 0]])").unwrap();
+        let message = Arena::load(&path).unwrap_err().to_string();
+        fs::remove_file(&path).unwrap();
+        assert!(message.contains(&path.display().to_string()) && message.contains("spawn points"));
+        let shipped = Path::new(env!("CARGO_MANIFEST_DIR")).join("data").join("arenas").join("simple.ron");
+        assert!(Arena::load(shipped).is_ok());
+    }
+}
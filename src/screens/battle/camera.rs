@@ -0,0 +1,219 @@
+//! Moves and zooms the view of a battle to keep every player in it.
+//!
+//! The camera frames the envelope of all players' bodies, grown by `padding`, as closely as the
+//! zoom limits allow, and keeps the view inside the arena wherever the arena is big enough. It
+//! glides there over a few ticks rather than jumping, so that players moving about don't shake it.
+use ggez::graphics::DrawParam;
+use ggez::nalgebra as na;
+
+use crate::physics::broad::Aabb;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Camera {
+    /// Space kept around the players, in world units.
+    pub padding: f32,
+    /// The furthest the camera zooms out, in pixels per world unit.
+    pub min_zoom: f32,
+    /// The furthest the camera zooms in, in pixels per world unit.
+    pub max_zoom: f32,
+    /// The fraction of the way to the framed view the camera goes each tick, from 0 for never
+    /// moving to 1 for going straight there.
+    pub smoothing: f32,
+    /// The size of the screen in pixels.
+    screen: na::Vector2<f32>,
+    /// The point in the world at the middle of the screen.
+    center: na::Vector2<f32>,
+    /// Pixels per world unit.
+    zoom: f32,
+    /// The bodies last followed, grown by `padding`.
+    framed: Option<Aabb>,
+}
+
+impl Default for Camera {
+    /// Shows the world as it is laid out, pixel for pixel, on `ggez`'s default window.
+    fn default() -> Self {
+        Camera {
+            padding: 100.,
+            min_zoom: 0.5,
+            max_zoom: 2.,
+            smoothing: 0.2,
+            screen: na::Vector2::new(800., 600.),
+            center: na::Vector2::new(400., 300.),
+            zoom: 1.,
+            framed: None,
+        }
+    }
+}
+
+impl Camera {
+    /// Takes in the new size of the screen, in pixels.
+    pub fn resize(&mut self, width: f32, height: f32) {
+        self.screen = na::Vector2::new(width, height);
+    }
+
+    #[cfg(test)]
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    #[cfg(test)]
+    pub fn center(&self) -> na::Vector2<f32> {
+        self.center
+    }
+
+    /// The part of the world on the screen.
+    pub fn view(&self) -> Aabb {
+        let half = self.screen / self.zoom / 2.;
+        Aabb { min: self.center - half, max: self.center + half }
+    }
+
+    /// What the camera is trying to keep in view, if it has followed anything yet.
+    pub fn framed(&self) -> Option<Aabb> {
+        self.framed
+    }
+
+    /// Moves toward framing the union of `bodies`, staying inside `bounds` where the view fits in
+    /// them. Keeps the last view if there is nothing to follow.
+    pub fn follow(&mut self, bodies: &[Aabb], bounds: &Aabb) {
+        let mut framed = match bodies.first() {
+            Some(body) => *body,
+            None => return,
+        };
+        for body in &bodies[1..] {
+            framed = framed.union(body);
+        }
+        let padding = na::Vector2::new(self.padding, self.padding);
+        self.framed = Some(Aabb { min: framed.min - padding, max: framed.max + padding });
+        let size = framed.max - framed.min + 2. * padding;
+        // Bodies too far apart to fit at `min_zoom` are centered, with the edges cut off.
+        let zoom = (self.screen[0] / size[0]).min(self.screen[1] / size[1]).clamp(self.min_zoom, self.max_zoom);
+        let view = self.screen / zoom;
+        let wanted = (framed.min + framed.max) / 2.;
+        let center = na::Vector2::new(
+            clamp_center(wanted[0], view[0], bounds.min[0], bounds.max[0]),
+            clamp_center(wanted[1], view[1], bounds.min[1], bounds.max[1]),
+        );
+        self.zoom += (zoom - self.zoom) * self.smoothing;
+        self.center += (center - self.center) * self.smoothing;
+    }
+
+    /// Where `point` in the world ends up on the screen.
+    pub fn to_screen(&self, point: na::Vector2<f32>) -> na::Vector2<f32> {
+        (point - self.center) * self.zoom + self.screen / 2.
+    }
+
+    /// `param` drawing through the camera, for anything placed in the world.
+    pub fn apply(&self, mut param: DrawParam) -> DrawParam {
+        let origin = self.to_screen(na::Vector2::zeros());
+        param.dest.x += origin[0];
+        param.dest.y += origin[1];
+        param.scale.x *= self.zoom;
+        param.scale.y *= self.zoom;
+        param
+    }
+}
+
+/// The middle of a view `view` wide along an axis, as close to `wanted` as it gets while staying
+/// between `min` and `max`. Views wider than that are centered on it instead.
+fn clamp_center(wanted: f32, view: f32, min: f32, max: f32) -> f32 {
+    if view >= max - min {
+        (min + max) / 2.
+    } else {
+        wanted.clamp(min + view / 2., max - view / 2.)
+    }
+}
+
+#[cfg(test)]
+mod camera_test {
+    use super::*;
+
+    fn aabb(x: f32, y: f32, w: f32, h: f32) -> Aabb {
+        Aabb { min: na::Vector2::new(x, y), max: na::Vector2::new(x + w, y + h) }
+    }
+
+    fn camera() -> Camera {
+        Camera { padding: 50., smoothing: 1., ..Camera::default() }
+    }
+
+    fn arena() -> Aabb {
+        aabb(-1000., -1000., 3000., 3000.)
+    }
+
+    #[test]
+    fn frames_players_with_padding() {
+        let mut camera = camera();
+        // 700 wide with the padding, 300 high, so the width decides.
+        camera.follow(&[aabb(0., 0., 30., 30.), aabb(570., 170., 30., 30.)], &arena());
+        assert!((camera.zoom() - 800. / 700.).abs() < 1e-5);
+        assert!(camera.center() == na::Vector2::new(300., 100.));
+        let left = camera.to_screen(na::Vector2::new(-50., 100.));
+        assert!(left[0].abs() < 1e-3 && (left[1] - 300.).abs() < 1e-3);
+    }
+
+    #[test]
+    fn single_player_zooms_in_as_far_as_allowed() {
+        let mut camera = camera();
+        camera.follow(&[aabb(100., 200., 30., 30.)], &arena());
+        assert!(camera.zoom() == camera.max_zoom);
+        assert!(camera.center() == na::Vector2::new(115., 215.));
+        assert!(camera.to_screen(camera.center()) == na::Vector2::new(400., 300.));
+    }
+
+    #[test]
+    fn far_apart_players_clamp_zoom_and_center() {
+        let mut camera = camera();
+        camera.follow(&[aabb(-900., 0., 10., 10.), aabb(1890., 0., 10., 10.)], &arena());
+        assert!(camera.zoom() == camera.min_zoom);
+        assert!(camera.center() == na::Vector2::new(500., 5.));
+    }
+
+    #[test]
+    fn view_stays_inside_arena() {
+        let mut camera = camera();
+        let bounds = aabb(0., 0., 1000., 500.);
+        camera.follow(&[aabb(0., 0., 30., 30.)], &bounds);
+        // 400x300 in view at 2x zoom, pushed in from the corner.
+        assert!(camera.center() == na::Vector2::new(200., 150.));
+        assert!(camera.view() == aabb(0., 0., 400., 300.));
+        assert!(camera.framed() == Some(aabb(-50., -50., 130., 130.)));
+        // An arena smaller than the view is centered.
+        camera.max_zoom = 0.5;
+        camera.follow(&[aabb(0., 0., 30., 30.)], &bounds);
+        assert!(camera.center() == na::Vector2::new(500., 250.));
+        assert!(camera.to_screen(na::Vector2::new(500., 250.)) == na::Vector2::new(400., 300.));
+    }
+
+    #[test]
+    fn nothing_to_follow_keeps_view() {
+        let mut camera = camera();
+        camera.follow(&[aabb(100., 200., 30., 30.)], &arena());
+        let before = camera.clone();
+        camera.resize(1024., 768.);
+        camera.follow(&[], &arena());
+        assert!(camera.center() == before.center() && camera.zoom() == before.zoom());
+    }
+
+    #[test]
+    fn glides_toward_players() {
+        let mut camera = Camera { smoothing: 0.5, ..camera() };
+        // Framed at 2x zoom around (415, 315), from 1x around (400, 300).
+        let player = [aabb(400., 300., 30., 30.)];
+        camera.follow(&player, &arena());
+        assert!(camera.zoom() == 1.5);
+        assert!(camera.center() == na::Vector2::new(407.5, 307.5));
+        for _ in 0..30 {
+            camera.follow(&player, &arena());
+        }
+        assert!((camera.zoom() - 2.).abs() < 1e-3);
+        assert!((camera.center() - na::Vector2::new(415., 315.)).norm() < 1e-3);
+    }
+
+    #[test]
+    fn applied_param_matches_to_screen() {
+        let mut camera = camera();
+        camera.follow(&[aabb(0., 0., 30., 30.), aabb(570., 170., 30., 30.)], &arena());
+        let param = crate::physics::drawn_at(camera.apply(DrawParam::default()), na::Vector2::new(123., 45.));
+        let expected = camera.to_screen(na::Vector2::new(123., 45.));
+        assert!((param.dest.x - expected[0]).abs() < 1e-3 && (param.dest.y - expected[1]).abs() < 1e-3);
+    }
+}
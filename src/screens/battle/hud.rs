@@ -0,0 +1,218 @@
+//! The damage and stocks of every player, drawn along the bottom of the screen.
+//!
+//! The HUD is drawn in screen space, over the world and unaffected by the camera. Each player
+//! gets an equal slice of the screen's width, in the order players were added to the battle.
+//! The HUD never sees the players themselves, only `PlayerSnapshot`s of them taken once they are
+//! done updating.
+use ggez::{Context, GameResult};
+use ggez::graphics::{self, Color, Drawable, DrawMode, DrawParam, MeshBuilder, Text};
+use ggez::nalgebra as na;
+
+use crate::{
+    screens::battle::player::{Player, meta::Handicap},
+    text::{FontId, Fonts, FrameStamp, GlyphAtlas, TextCache, TextKey},
+};
+
+const DAMAGE_FONT_SIZE: u32 = 36;
+const LABEL_FONT_SIZE: u32 = 16;
+const STOCK_RADIUS: f32 = 5.;
+/// The distance between the centers of neighbouring stock icons.
+const STOCK_SPACING: f32 = 14.;
+/// Space left between the HUD and the bottom of the screen, in pixels.
+const MARGIN: f32 = 12.;
+/// The height of each player's HUD: their label, their damage and a row of stocks.
+const HEIGHT: f32 = LABEL_FONT_SIZE as f32 + DAMAGE_FONT_SIZE as f32 + 2. * STOCK_RADIUS;
+
+/// Players past the fourth reuse these from the start.
+const PLAYER_COLORS: [(u8, u8, u8); 4] = [
+    (230, 60, 60),
+    (60, 120, 230),
+    (240, 200, 40),
+    (60, 190, 90),
+];
+
+pub fn player_color(idx: usize) -> Color {
+    let (r, g, b) = PLAYER_COLORS[idx % PLAYER_COLORS.len()];
+    Color::from_rgb(r, g, b)
+}
+
+/// Where the HUD of each of `count` players is centered horizontally, and where its top is.
+pub fn anchors(count: usize, screen: na::Vector2<f32>) -> Vec<na::Point2<f32>> {
+    let slot = screen[0] / count as f32;
+    let top = screen[1] - MARGIN - HEIGHT;
+    (0..count)
+        .map(|idx| na::Point2::new(slot * (idx as f32 + 0.5), top))
+        .collect()
+}
+
+/// Where the center of each of `stocks` icons goes, relative to the middle of the row.
+pub fn stock_offsets(stocks: u8) -> Vec<f32> {
+    let middle = (f32::from(stocks) - 1.) / 2.;
+    (0..stocks)
+        .map(|stock| (f32::from(stock) - middle) * STOCK_SPACING)
+        .collect()
+}
+
+/// What the HUD shows of a player.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerSnapshot {
+    pub damage_pct: f32,
+    pub stocks: u8,
+    pub name: String,
+    /// What the player's handicap does, if they have one.
+    pub handicap: Option<String>,
+}
+
+impl PlayerSnapshot {
+    pub fn of(player: &Player) -> Self {
+        Self {
+            damage_pct: player.damage_pct(),
+            stocks: player.stocks(),
+            name: player.name().to_owned(),
+            handicap: Some(player.handicap())
+                .filter(|handicap| **handicap != Handicap::default())
+                .map(Handicap::label),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Hud {
+    glyphs: GlyphAtlas,
+    font: FontId,
+    /// Each player's label, built in the battle's `TextCache`.
+    labels: Vec<TextKey>,
+    /// The size of the screen in pixels.
+    screen: na::Vector2<f32>,
+}
+
+impl Hud {
+    pub fn new(fonts: &Fonts) -> Self {
+        Self {
+            glyphs: GlyphAtlas::new("0123456789.%", fonts.get(fonts.default_id()), DAMAGE_FONT_SIZE),
+            font: fonts.default_id(),
+            labels: vec![],
+            screen: na::Vector2::new(800., 600.),
+        }
+    }
+
+    /// Takes in the new size of the screen, in pixels.
+    pub fn resize(&mut self, width: f32, height: f32) {
+        self.screen = na::Vector2::new(width, height);
+    }
+
+    /// Builds the labels of the players in `snapshots` into `texts`, marking them used on `frame`.
+    /// Drawing only borrows the cache, so it can't build them itself.
+    pub fn prepare(&mut self, snapshots: &[PlayerSnapshot], texts: &mut TextCache<Text>, fonts: &Fonts, frame: FrameStamp) {
+        self.labels = snapshots.iter()
+            .enumerate()
+            .map(|(idx, snapshot)| {
+                let contents = match &snapshot.handicap {
+                    Some(handicap) => format!("P{} {} ({})", idx + 1, snapshot.name, handicap),
+                    None => format!("P{} {}", idx + 1, snapshot.name),
+                };
+                TextKey::new(contents, self.font, LABEL_FONT_SIZE, graphics::WHITE)
+            })
+            .collect();
+        for key in &self.labels {
+            texts.get_or_insert_with(key.clone(), frame, |key| key.build(fonts));
+        }
+    }
+
+    /// Draws each player's label, their damage below it and a dot for each of their stocks left
+    /// below that, all in the player's colour.
+    pub fn draw(&self, ctx: &mut Context, snapshots: &[PlayerSnapshot], texts: &TextCache<Text>, param: DrawParam) -> GameResult {
+        let mut stocks = MeshBuilder::new();
+        let mut any_stocks = false;
+        for (idx, (snapshot, anchor)) in snapshots.iter().zip(anchors(snapshots.len(), self.screen)).enumerate() {
+            let color = player_color(idx);
+            if let Some(label) = self.labels.get(idx).and_then(|key| texts.get(key)) {
+                let mut label_param = param.color(color);
+                label_param.dest.x += anchor.x - label.width(ctx) as f32 / 2.;
+                label_param.dest.y += anchor.y;
+                label.draw(ctx, label_param)?;
+            }
+            let damage = format!("{:.0}%", snapshot.damage_pct);
+            let mut damage_param = param.color(color);
+            damage_param.dest.x += anchor.x - self.glyphs.width(&damage, ctx) / 2.;
+            damage_param.dest.y += anchor.y + LABEL_FONT_SIZE as f32;
+            self.glyphs.draw(ctx, &damage, damage_param)?;
+            let y = anchor.y + HEIGHT - STOCK_RADIUS;
+            for x in stock_offsets(snapshot.stocks) {
+                stocks.circle(DrawMode::fill(), [anchor.x + x, y], STOCK_RADIUS, 0.5, color);
+                any_stocks = true;
+            }
+        }
+        if any_stocks {
+            stocks.build(ctx)?.draw(ctx, param.color(graphics::WHITE))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod hud_test {
+    use super::*;
+
+    fn xs(count: usize, width: f32) -> Vec<f32> {
+        anchors(count, na::Vector2::new(width, 600.)).iter().map(|anchor| anchor.x).collect()
+    }
+
+    fn snapshot(name: &str) -> PlayerSnapshot {
+        PlayerSnapshot { damage_pct: 0., stocks: 3, name: name.to_owned(), handicap: None }
+    }
+
+    #[test]
+    fn players_spaced_evenly() {
+        assert!(xs(1, 800.) == vec![400.]);
+        assert!(xs(2, 800.) == vec![200., 600.]);
+        assert!(xs(3, 1200.) == vec![200., 600., 1000.]);
+        assert!(xs(4, 800.) == vec![100., 300., 500., 700.]);
+        assert!(xs(4, 1024.) == vec![128., 384., 640., 896.]);
+        assert!(xs(0, 800.).is_empty());
+    }
+
+    #[test]
+    fn anchored_to_bottom() {
+        let top = 600. - MARGIN - HEIGHT;
+        assert!(anchors(2, na::Vector2::new(800., 600.)).iter().all(|anchor| anchor.y == top));
+        let resized = anchors(2, na::Vector2::new(800., 900.));
+        assert!(resized.iter().all(|anchor| anchor.y == top + 300.));
+    }
+
+    #[test]
+    fn colors_distinct_then_repeat() {
+        let colors: Vec<_> = (0..4).map(|idx| player_color(idx).to_rgba()).collect();
+        for (idx, color) in colors.iter().enumerate() {
+            assert!(!colors[idx + 1..].contains(color));
+        }
+        assert!(player_color(4) == player_color(0));
+    }
+
+    #[test]
+    fn stocks_centered_under_damage() {
+        assert!(stock_offsets(0).is_empty());
+        assert!(stock_offsets(1) == vec![0.]);
+        assert!(stock_offsets(2) == vec![-STOCK_SPACING / 2., STOCK_SPACING / 2.]);
+        assert!(stock_offsets(3) == vec![-STOCK_SPACING, 0., STOCK_SPACING]);
+    }
+
+    #[test]
+    fn labels_follow_players() {
+        let fonts = Fonts::default();
+        let mut texts = TextCache::new(8);
+        let mut hud = Hud::new(&fonts);
+        hud.prepare(&[snapshot("Alien"), snapshot("Robot")], &mut texts, &fonts, 0);
+        let contents: Vec<_> = hud.labels.iter().map(|key| key.contents.as_str()).collect();
+        assert!(contents == vec!["P1 Alien", "P2 Robot"]);
+        assert!(hud.labels.iter().all(|key| texts.get(key).is_some()));
+        hud.prepare(&[snapshot("Mage")], &mut texts, &fonts, 1);
+        assert!(hud.labels.len() == 1 && hud.labels[0].contents == "P1 Mage");
+        let handicapped = PlayerSnapshot { handicap: Some("+1 stock".to_owned()), ..snapshot("Mage") };
+        hud.prepare(std::slice::from_ref(&handicapped), &mut texts, &fonts, 2);
+        hud.prepare(&[handicapped], &mut texts, &fonts, 3);
+        assert!(hud.labels[0].contents == "P1 Mage (+1 stock)");
+        // Labels are only built when they change.
+        assert!(texts.misses() == 4 && texts.hits() == 1);
+    }
+}
@@ -1,10 +1,14 @@
 // use std::any::TypeId; // Related to commented code.
+use ggez::nalgebra as na;
+
 use crate::{
     screens::battle::{
-        platform::Platform,
-        player::{Player, Changes as PlayerChangeSet},
+        platform::{Platform, Changes as PlatformChangeSet},
+        player::{Player, Changes as PlayerChangeSet, action::{Action, AttackDir}, meta::Effect, stance::HorizontalStance},
+        projectile::{Projectile, Changes as ProjectileChangeSet},
+        trigger::TriggerZone,
     },
-    physics::{Collision, Collidable},
+    physics::{BoundingBox, Collision, Collidable, HitboxTag, collision::Mergeable},
 };
 
 // Replace handle_x_x_collision with specialization once available.
@@ -23,31 +27,324 @@ pub fn handle_collision<'tick, A: Collidable, B: Collidable>(
             Some((a_changeset, b_changeset))
         },
         (player, player) => handle_player_player_collision(c),
-        (platform, platform) => handle_platform_platform_collision(c),
         _ => return (None, None),
     }
 }
 */
 
-pub fn handle_platform_platform_collision<'tick>(
-    c: Collision<'tick, Platform, Platform>,
-) -> Changes<Platform, Platform> {
-    log::trace!("Platform {} collided with platform {}.", c.ids.0, c.ids.1);
-    (None, None)
+/// Whether a player overlapping a trigger is inside of it. Only `Solid` boxes count, so an attack
+/// reaching into a trigger doesn't set it off. Triggers never push or hurt players, so there are no
+/// changes to make.
+pub fn is_inside_trigger(c: &Collision<Player, TriggerZone>) -> bool {
+    c.overlapping_hitboxes.iter().any(|(player_hb, _)| player_hb.tag == HitboxTag::Solid)
 }
+/// Overlapping `Solid` boxes push the players apart. A player is only damaged where the other's
+/// `Hit` boxes overlap their `Hurt` boxes, so an attack landing never hurts the attacker.
 pub fn handle_player_player_collision<'tick>(
     c: Collision<'tick, Player, Player>,
 ) -> Changes<Player, Player> {
     log::trace!("Player {} collided with player {}.", c.ids.0, c.ids.1);
-    (None, None)
+    let (p0, p1) = c.objs;
+    let push = c.world_hitboxes().iter()
+        .filter(|(hb0, hb1)| hb0.tag == HitboxTag::Solid && hb1.tag == HitboxTag::Solid)
+        .map(|(hb0, hb1)| hb0.penetration(hb1))
+        .fold(na::Vector2::zeros(), larger_correction) * PUSH_STIFFNESS;
+    let kinds = c.kinds();
+    let flipped_kinds: Vec<_> = kinds.iter().map(|&(kind0, kind1)| (kind1, kind0)).collect();
+    (
+        merge_changes(pushed(push), struck(c.ids.1, p1, p0, &flipped_kinds)),
+        merge_changes(pushed(-push), struck(c.ids.0, p0, p1, &kinds)),
+    )
+}
+/// The (attacker, defender) pairs of every strike landed between two colliding players.
+pub fn landed_strikes(c: &Collision<Player, Player>) -> Vec<(usize, usize)> {
+    let (p0, p1) = c.objs;
+    let kinds = c.kinds();
+    let mut landed = vec![];
+    if lands(c.ids.0, p0, p1, kinds.iter().cloned()) {
+        landed.push(c.ids);
+    }
+    if lands(c.ids.1, p1, p0, kinds.iter().map(|&(kind0, kind1)| (kind1, kind0))) {
+        landed.push((c.ids.1, c.ids.0));
+    }
+    landed
+}
+/// The (grabber, grabbed) pair if either player's `Grab` box caught the other, through shields
+/// too. Players already holding or being held can't be grabbed.
+pub fn handle_player_grab_collision(c: &Collision<Player, Player>) -> Option<(usize, usize)> {
+    let (p0, p1) = c.objs;
+    let kinds = c.kinds();
+    if catches(p0, p1, kinds.iter().cloned()) {
+        log::trace!("Player {} grabbed player {}.", c.ids.0, c.ids.1);
+        Some(c.ids)
+    } else if catches(p1, p0, kinds.iter().map(|&(kind0, kind1)| (kind1, kind0))) {
+        log::trace!("Player {} grabbed player {}.", c.ids.1, c.ids.0);
+        Some((c.ids.1, c.ids.0))
+    } else {
+        None
+    }
+}
+/// The changes to `thrown` from being thrown in `dir` by `thrower`. Throws can't be shielded.
+pub fn throw(thrower: &Player, thrown: &Player, dir: &AttackDir) -> Option<PlayerChangeSet> {
+    let data = Action::Grab.frame_data()?;
+    let knockback = thrown.knockback_towards(thrower.throw_direction(dir), data.knockback);
+    hit(thrown, knockback, vec![Effect::Damage(thrower.scale_damage_dealt(data.damage))], false)
+}
+/// Projectiles hit every player except the one who fired them, then vanish.
+pub fn handle_projectile_player_collision<'tick>(
+    c: Collision<'tick, Projectile, Player>,
+) -> Changes<Projectile, Player> {
+    let (projectile, player) = c.objs;
+    if projectile.owner == c.ids.1 || !c.kinds().iter().any(|(_, kind)| kind.can_be_hit()) {
+        return (None, None);
+    }
+    log::trace!("Projectile {} hit player {}.", c.ids.0, c.ids.1);
+    let knockback = player.knockback_from(projectile.get_offset());
+    match hit(player, knockback, vec![Effect::Damage(projectile.damage)], hits_shield(&c)) {
+        Some(changes) => (Some(ProjectileChangeSet { hit: true }), Some(changes)),
+        None => (None, None),
+    }
+}
+/// Projectiles fly through platforms that can be dropped through, and vanish on any others,
+/// damaging them.
+pub fn handle_projectile_platform_collision<'tick>(
+    c: Collision<'tick, Projectile, Platform>,
+) -> Changes<Projectile, Platform> {
+    if c.objs.1.can_move_through {
+        return (None, None);
+    }
+    log::trace!("Projectile {} hit platform {}.", c.ids.0, c.ids.1);
+    let damage = PlatformChangeSet { effects: vec![Effect::Damage(c.objs.0.damage)] };
+    (Some(ProjectileChangeSet { hit: true }), Some(damage))
+}
+
+/// The force pushing overlapping players apart, per unit of overlap.
+const PUSH_STIFFNESS: f32 = 0.05;
+/// Ticks of hitstun per unit of knockback.
+const HITSTUN_PER_KNOCKBACK: f32 = 20.;
+/// The fraction of a blocked hit's damage that gets through the shield.
+pub const SHIELD_CHIP_FRACTION: f32 = 0.1;
+
+/// Whether a hit on a player landed on their shield.
+fn hits_shield<T: Collidable>(c: &Collision<T, Player>) -> bool {
+    c.overlapping_hitboxes.iter().any(|(_, hb)| hb.tag == HitboxTag::Shield)
+}
+
+/// Whether `attacker`'s `Hit` boxes reach where `defender` can be hit, by a swing that hasn't hit
+/// them yet. `kinds` are the overlapping boxes' kinds, the attacker's first.
+fn lands<I: Iterator<Item = (HitboxTag, HitboxTag)>>(
+    attacker_id: usize,
+    attacker: &Player,
+    defender: &Player,
+    mut kinds: I,
+) -> bool {
+    let fresh = match attacker.strike(attacker_id) {
+        Some(strike) => !defender.was_struck_by(&strike),
+        None => false,
+    };
+    fresh
+        && !defender.is_in_hitstun()
+        && kinds.any(|(kind0, kind1)| kind0 == HitboxTag::Hit && kind1.can_be_hit())
+}
+
+/// Whether `grabber`'s `Grab` boxes reach where `grabbed` can be grabbed. `kinds` are the
+/// overlapping boxes' kinds, the grabber's first.
+fn catches<I: Iterator<Item = (HitboxTag, HitboxTag)>>(grabber: &Player, grabbed: &Player, mut kinds: I) -> bool {
+    grabber.is_grabbing()
+        && grabbed.held_by().is_none()
+        && grabbed.grabbed_player().is_none()
+        && kinds.any(|(kind0, kind1)| kind0 == HitboxTag::Grab && kind1.can_be_grabbed())
+}
+
+/// The changes to `defender` from `attacker`'s strike, if it landed.
+fn struck(
+    attacker_id: usize,
+    attacker: &Player,
+    defender: &Player,
+    kinds: &[(HitboxTag, HitboxTag)],
+) -> Option<PlayerChangeSet> {
+    if !lands(attacker_id, attacker, defender, kinds.iter().cloned()) {
+        return None;
+    }
+    let strike = attacker.strike(attacker_id)?;
+    log::trace!("Player {} struck a player.", strike.owner);
+    let shielded = kinds.iter().any(|&(kind0, kind1)| kind0 == HitboxTag::Hit && kind1 == HitboxTag::Shield);
+    let knockback = defender.knockback_from_attack(strike.position, strike.knockback);
+    let struck = Effect::Struck { owner: strike.owner, instance: strike.instance };
+    hit(defender, knockback, vec![Effect::Damage(strike.damage), struck], shielded)
+}
+
+/// Pushes a player by `force`, if there is any.
+/// Players pushed sideways by another player can use them as a wall.
+fn pushed(force: na::Vector2<f32>) -> Option<PlayerChangeSet> {
+    if force == na::Vector2::zeros() {
+        return None;
+    }
+    Some(PlayerChangeSet {
+        force,
+        wall_contact: wall_side(force),
+        ..Default::default()
+    })
+}
+
+fn merge_changes(a: Option<PlayerChangeSet>, b: Option<PlayerChangeSet>) -> Option<PlayerChangeSet> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.merge(&b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// The changes to `defender` from being hit with `knockback`. Players in hitstun can't be hit again
+/// until it runs out, so touching players don't hit each other every tick. Hits on a shield damage
+/// the shield instead, letting only chip damage through, and don't push the defender at all.
+fn hit(defender: &Player, knockback: na::Vector2<f32>, effects: Vec<Effect>, shielded: bool) -> Option<PlayerChangeSet> {
+    if defender.is_in_hitstun() {
+        return None;
+    }
+    let (knockback, effects) = if shielded {
+        let effects = effects.into_iter()
+            .flat_map(|effect| match effect {
+                Effect::Damage(amount) => vec![Effect::ShieldDamage(amount), Effect::Damage(amount * SHIELD_CHIP_FRACTION)],
+                effect => vec![effect],
+            })
+            .collect();
+        (na::Vector2::zeros(), effects)
+    } else {
+        (knockback, effects)
+    };
+    Some(PlayerChangeSet {
+        force: knockback,
+        hitstun: (knockback.norm() * HITSTUN_PER_KNOCKBACK).ceil().min(255.) as u8,
+        effects,
+        ..Default::default()
+    })
 }
 pub fn handle_player_platform_collision<'tick>(
     c: Collision<'tick, Player, Platform>,
 ) -> Changes<Player, Platform> {
     log::trace!("Player {} collided with platform {}.", c.ids.0, c.ids.1);
+    // Only the player's `Solid` boxes stand on platforms.
+    let hitboxes: Vec<_> = c.world_hitboxes().into_iter()
+        .filter(|(player_hb, _)| player_hb.tag == HitboxTag::Solid)
+        .collect();
+    // Platforms hit later in the tick are only landed on early, by snapping onto them. Other hits
+    // are left for when the boxes actually overlap.
+    if hitboxes.is_empty() || c.toi > 0. && !is_landing(c.objs.0, c.objs.1, &hitboxes) {
+        return (None, None);
+    }
+    let passable_platforms = if c.objs.1.can_move_through {
+        vec![c.ids.1]
+    } else {
+        vec![]
+    };
+    let positional_correction = if c.objs.0.is_ignoring_platform(c.ids.1) {
+        na::Vector2::zeros()
+    } else {
+        hitboxes.iter()
+            .map(|(player_hb, platform_hb)| platform_correction(c.objs.0, c.objs.1, player_hb, platform_hb))
+            .fold(na::Vector2::zeros(), larger_correction)
+    };
+    // Being pushed out sideways means running into a wall, which isn't something to stand on.
+    let wall_contact = wall_side(positional_correction);
+    let contacted_platforms = if wall_contact.is_some() {
+        vec![]
+    } else {
+        vec![c.ids.1]
+    };
+    // Players left standing on top of a moving platform move along with it, turning with it from
+    // where they stand.
+    let standing_on = hitboxes.iter()
+        .map(|(player_hb, platform_hb)| (feet(player_hb) + positional_correction, platform_hb))
+        .find(|(feet, platform_hb)| {
+            !c.objs.0.is_ignoring_platform(c.ids.1) && (feet[1] - top_at(platform_hb, feet[0])).abs() <= SIDE_EPSILON
+        });
+    let carry = match standing_on {
+        Some((feet, _)) if wall_contact.is_none() => c.objs.1.carry_at(feet),
+        _ => na::Vector2::zeros(),
+    };
     (Some(PlayerChangeSet {
-        contacted_platforms: vec![c.ids.1],
+        contacted_platforms,
+        passable_platforms,
+        positional_correction,
+        wall_contact,
+        carry,
         ..Default::default()
     }), None)
 }
 
+/// The side of the player a wall is on, if `correction` pushes them sideways out of one.
+fn wall_side(correction: na::Vector2<f32>) -> Option<HorizontalStance> {
+    if correction[0].abs() <= correction[1].abs() {
+        None
+    } else if correction[0] < 0. {
+        Some(HorizontalStance::Right)
+    } else {
+        Some(HorizontalStance::Left)
+    }
+}
+
+/// Whether a player is about to land on top of a platform they don't overlap yet.
+fn is_landing(player: &Player, platform: &Platform, hitboxes: &[(BoundingBox, BoundingBox)]) -> bool {
+    let falling = player.velocity()[1] > platform.get_velocity()[1];
+    falling && hitboxes.iter().all(|(player_hb, platform_hb)| {
+        player_hb.extents().1[1] <= platform_hb.extents().0[1] + SIDE_EPSILON
+    })
+}
+
+/// Allowed slack when deciding which side of a platform a player came from.
+const SIDE_EPSILON: f32 = 1e-3;
+
+/// How far a player must be moved to stop overlapping a platform.
+///
+/// Which side to push the player out of is decided by where the player was on the last tick, so
+/// that a player falling deep into a platform in one tick is still put on top of it. Passable
+/// platforms can only be landed on.
+fn platform_correction(
+    player: &Player,
+    platform: &Platform,
+    player_hb: &BoundingBox,
+    platform_hb: &BoundingBox,
+) -> na::Vector2<f32> {
+    let (player_min, _) = player_hb.extents();
+    let (_, platform_max) = platform_hb.extents();
+    let feet = feet(player_hb);
+    // How far the player moved relative to the platform on the last tick.
+    let velocity = player.velocity() + player.carried() - platform.last_delta_at(feet);
+
+    let top = top_at(platform_hb, feet[0]);
+    let was_above = feet[1] - velocity[1] <= top_at(platform_hb, feet[0] - velocity[0]) + SIDE_EPSILON;
+    let was_below = player_min[1] - velocity[1] >= platform_max[1] - SIDE_EPSILON;
+    // Turning platforms only match their riders to within rounding.
+    if velocity[1] > -SIDE_EPSILON && was_above {
+        na::Vector2::new(0., top - feet[1])
+    } else if platform.can_move_through {
+        na::Vector2::zeros()
+    } else if velocity[1] < 0. && was_below {
+        na::Vector2::new(0., platform_max[1] - player_min[1])
+    } else {
+        player_hb.penetration(platform_hb)
+    }
+}
+
+/// The middle of the bottom of a player's box, which is what they stand on platforms with.
+fn feet(player_hb: &BoundingBox) -> na::Vector2<f32> {
+    let (min, max) = player_hb.extents();
+    na::Vector2::new((min[0] + max[0]) / 2., max[1])
+}
+
+/// The top of a platform's box at `x`. Turned platforms slope, so standing on them depends on
+/// where along them the player is.
+fn top_at(platform_hb: &BoundingBox, x: f32) -> f32 {
+    Some(platform_hb)
+        .filter(|platform_hb| platform_hb.ori != 0.)
+        .and_then(|platform_hb| platform_hb.top_at(x))
+        .unwrap_or_else(|| platform_hb.extents().0[1])
+}
+
+/// Combines corrections from several overlaps by taking the largest along each axis, so that
+/// standing across two platforms doesn't push the player out twice.
+pub fn larger_correction(a: na::Vector2<f32>, b: na::Vector2<f32>) -> na::Vector2<f32> {
+    let larger = |a: f32, b: f32| if a.abs() >= b.abs() { a } else { b };
+    na::Vector2::new(larger(a[0], b[0]), larger(a[1], b[1]))
+}
+
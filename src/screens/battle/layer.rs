@@ -0,0 +1,14 @@
+//! The collision layers of everything in a battle. Each `Collidable` sits on one layer and only
+//! collides with things whose layer is in its mask, and whose mask has its layer.
+
+pub const PLAYER: u32 = 1;
+pub const PLATFORM: u32 = 1 << 1;
+pub const PROJECTILE: u32 = 1 << 2;
+pub const TRIGGER: u32 = 1 << 3;
+
+/// Platforms never move, so they don't need to be checked against each other.
+pub const PLATFORM_MASK: u32 = !PLATFORM;
+/// Projectiles pass through each other.
+pub const PROJECTILE_MASK: u32 = !PROJECTILE;
+/// Only players set off triggers.
+pub const TRIGGER_MASK: u32 = PLAYER;
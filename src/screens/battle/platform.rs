@@ -3,34 +3,276 @@ use ggez::graphics::{Drawable, DrawParam, Rect, BlendMode};
 use ggez::nalgebra as na;
 use serde::{Serialize, Deserialize};
 
-use crate::physics::{Collidable, BoundingBox};
+use crate::physics::{drawn_at, Collidable, BoundingBox, collision::Mergeable};
+use crate::screens::battle::{layer, player::meta::Effect};
 
-/// Denotes a collidable, static section of the `Arena`.
-#[derive(Debug, Serialize, Deserialize)]
+/// Denotes a collidable section of the `Arena`. Platforms with a `path` move along it, and
+/// platforms with a `rotation_speed` turn about their center, carrying whoever stands on them.
+/// Platforms with `hp` break once they've taken that much damage.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Platform {
     /// `ggez`-specific. Not used for anything atm.
     #[serde(skip)]
     pub mode: Option<BlendMode>,
     /// The space occupied by the platform.
     pub body: BoundingBox,
+    /// Whether players can drop down through the platform.
+    pub can_move_through: bool,
+    /// The waypoints the platform moves through, as offsets from `body`. `body` itself is the
+    /// first waypoint, so platforms without any stay put.
+    #[serde(default)]
+    pub path: Vec<na::Vector2<f32>>,
+    /// The distance moved along `path` every tick.
+    #[serde(default)]
+    pub speed: f32,
+    /// What happens at the end of `path`.
+    #[serde(default)]
+    pub path_mode: PathMode,
+    /// The radians the platform turns about its center every tick, clockwise on the screen.
+    #[serde(default)]
+    pub rotation_speed: f32,
+    /// The damage the platform takes before breaking. Platforms without any can't be broken.
+    #[serde(default)]
+    pub hp: Option<f32>,
+    /// The ticks a broken platform takes to come back. Platforms without any stay broken.
+    #[serde(default)]
+    pub respawn_timer: Option<u32>,
+    #[serde(skip)]
+    motion: Motion,
+    #[serde(skip)]
+    condition: Condition,
     // TODO: Add storage for the assets' handles.
 }
 
+/// How a platform keeps moving once it reaches the last waypoint of its path.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum PathMode {
+    /// Turns around and goes back the way it came.
+    #[default]
+    PingPong,
+    /// Heads straight back to the first waypoint and starts over.
+    Loop,
+}
+
+/// Whether a platform is still there.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Condition {
+    Intact { damage: f32 },
+    /// Gone, with no hitboxes to stand on or hit, until `ticks_left` runs out.
+    Broken { ticks_left: Option<u32> },
+}
+
+impl Default for Condition {
+    fn default() -> Self {
+        Condition::Intact { damage: 0. }
+    }
+}
+
+/// What happens to a platform when something hits it.
+#[derive(Debug, Clone, Default)]
+pub struct Changes {
+    pub effects: Vec<Effect>,
+}
+
+impl Mergeable for Changes {
+    fn merge(&self, other: &Self) -> Self {
+        Changes {
+            effects: self.effects.iter().chain(&other.effects).cloned().collect(),
+        }
+    }
+}
+
+/// Where a platform is along its path.
+#[derive(Debug, Clone, PartialEq)]
+struct Motion {
+    offset: na::Vector2<f32>,
+    /// The index of the waypoint being headed for, counting `body` as waypoint 0.
+    target: usize,
+    /// Whether a ping-ponging platform is on its way out rather than back.
+    forward: bool,
+    /// How far the platform moved on the last tick.
+    last_delta: na::Vector2<f32>,
+}
+
+impl Default for Motion {
+    fn default() -> Self {
+        Motion {
+            offset: na::Vector2::zeros(),
+            target: 1,
+            forward: true,
+            last_delta: na::Vector2::zeros(),
+        }
+    }
+}
+
+impl Platform {
+    /// A platform that stays put.
+    #[cfg(test)]
+    pub fn new(body: BoundingBox, can_move_through: bool) -> Self {
+        Platform {
+            mode: None,
+            body,
+            can_move_through,
+            path: vec![],
+            speed: 0.,
+            path_mode: PathMode::default(),
+            rotation_speed: 0.,
+            hp: None,
+            respawn_timer: None,
+            motion: Motion::default(),
+            condition: Condition::default(),
+        }
+    }
+
+    pub fn is_broken(&self) -> bool {
+        matches!(self.condition, Condition::Broken { .. })
+    }
+
+    /// Breaks the platform once its damage reaches its `hp`.
+    fn take_damage(&mut self, amount: f32) {
+        if let (Some(hp), Condition::Intact { damage }) = (self.hp, &mut self.condition) {
+            *damage += amount;
+            if *damage >= hp {
+                log::debug!("A platform broke.");
+                self.condition = Condition::Broken { ticks_left: self.respawn_timer };
+            }
+        }
+    }
+
+    pub fn is_rotating(&self) -> bool {
+        self.rotation_speed != 0.
+    }
+
+    /// How far the platform moved on the last tick where it's now at `point`, in world
+    /// coordinates, counting its turning as well as its moving along its path.
+    pub fn last_delta_at(&self, point: na::Vector2<f32>) -> na::Vector2<f32> {
+        let arm = self.arm(point);
+        self.last_delta() + arm - BoundingBox::rotate(arm, -self.rotation_speed)
+    }
+
+    /// How far the coming tick moves whatever rides on the platform at `point`, in world
+    /// coordinates. Riders are turned about the platform's center as well as moved along its path.
+    pub fn carry_at(&self, point: na::Vector2<f32>) -> na::Vector2<f32> {
+        let arm = self.arm(point);
+        self.get_velocity() + BoundingBox::rotate(arm, self.rotation_speed) - arm
+    }
+
+    /// Where `point` is from the platform's center.
+    fn arm(&self, point: na::Vector2<f32>) -> na::Vector2<f32> {
+        point - (self.body.center() + self.get_offset())
+    }
+
+    /// How far the platform moved on the last tick.
+    pub fn last_delta(&self) -> na::Vector2<f32> {
+        self.motion.last_delta
+    }
+
+    fn waypoint(&self, idx: usize) -> na::Vector2<f32> {
+        match idx {
+            0 => na::Vector2::zeros(),
+            idx => self.path[idx - 1],
+        }
+    }
+
+    /// The waypoint to head for after reaching `motion.target`.
+    fn next_target(&self, motion: &mut Motion) -> usize {
+        let last = self.path.len();
+        match self.path_mode {
+            PathMode::Loop => (motion.target + 1) % (last + 1),
+            PathMode::PingPong => {
+                if motion.target == last {
+                    motion.forward = false;
+                } else if motion.target == 0 {
+                    motion.forward = true;
+                }
+                if motion.forward { motion.target + 1 } else { motion.target - 1 }
+            },
+        }
+    }
+
+    /// Where the platform will be after the coming tick. Distance left over after reaching a
+    /// waypoint is spent heading for the next one, so platforms keep an even pace around corners
+    /// and when turning back.
+    fn advanced(&self) -> Motion {
+        let mut motion = self.motion.clone();
+        let start = motion.offset;
+        let mut left = self.speed;
+        if !self.path.is_empty() {
+            // Bounded in case every waypoint is in the same place.
+            for _ in 0..2 * (self.path.len() + 1) {
+                let to_target = self.waypoint(motion.target) - motion.offset;
+                let distance = to_target.norm();
+                if distance > left {
+                    motion.offset += to_target * (left / distance);
+                    break;
+                }
+                motion.offset += to_target;
+                left -= distance;
+                motion.target = self.next_target(&mut motion);
+                if left <= 0. {
+                    break;
+                }
+            }
+        }
+        motion.last_delta = motion.offset - start;
+        motion
+    }
+}
+
 impl Collidable for Platform {
-    type ChangeSet = ();
+    type ChangeSet = Changes;
     fn get_hitboxes<'tick>(&'tick self) -> &'tick[BoundingBox] {
-        self.body.get_hitboxes()
+        if self.is_broken() {
+            &[]
+        } else {
+            self.body.get_hitboxes()
+        }
+    }
+    fn apply_changeset(&mut self, changes: Self::ChangeSet) {
+        for effect in changes.effects {
+            if let Effect::Damage(amount) = effect {
+                self.take_damage(amount);
+            }
+        }
+    }
+    fn handle_phys_update(&mut self) {
+        if let Condition::Broken { ticks_left: Some(ticks_left) } = &mut self.condition {
+            *ticks_left = ticks_left.saturating_sub(1);
+            if *ticks_left == 0 {
+                log::debug!("A broken platform came back.");
+                self.condition = Condition::default();
+            }
+        }
+        self.motion = self.advanced();
+        if self.is_rotating() {
+            // `body` turns about `pos`, so it's moved back to keep its center in place.
+            let center = self.body.center();
+            self.body.ori += self.rotation_speed;
+            self.body.pos = center - BoundingBox::rotate(self.body.size / 2., self.body.ori);
+        }
     }
-    fn apply_changeset(&mut self, _changes: Self::ChangeSet) {}
-    fn handle_phys_update(&mut self) {}
     fn get_offset(&self) -> na::Vector2<f32> {
-        na::Vector2::new(0_f32, 0_f32)
+        self.motion.offset
+    }
+    /// How far the platform will move over the coming tick, which is how far it carries anyone
+    /// standing on it.
+    fn get_velocity(&self) -> na::Vector2<f32> {
+        self.advanced().offset - self.motion.offset
+    }
+    fn collision_layer(&self) -> u32 {
+        layer::PLATFORM
+    }
+    fn collision_mask(&self) -> u32 {
+        layer::PLATFORM_MASK
     }
 }
 
 impl Drawable for Platform {
     fn draw(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
-        self.body.draw(ctx, param)
+        if self.is_broken() {
+            return Ok(());
+        }
+        self.body.draw(ctx, drawn_at(param, self.get_offset()))
     }
 
     fn dimensions(&self, _ctx: &mut Context) -> Option<Rect> {
@@ -45,3 +287,78 @@ impl Drawable for Platform {
         self.mode
     }
 }
+
+#[cfg(test)]
+mod platform_test {
+    use super::*;
+    use crate::physics::HitboxTag;
+
+    fn moving(path: Vec<na::Vector2<f32>>, speed: f32, path_mode: PathMode) -> Platform {
+        let body = BoundingBox {
+            mode: None,
+            tag: HitboxTag::Solid,
+            pos: na::Vector2::new(0., 30.),
+            size: na::Vector2::new(100., 10.),
+            ori: 0.,
+        };
+        Platform { path, speed, path_mode, ..Platform::new(body, false) }
+    }
+
+    #[test]
+    fn ping_pong_oscillates_between_endpoints() {
+        let mut platform = moving(vec![na::Vector2::new(10., 0.)], 2., PathMode::PingPong);
+        let mut xs = vec![];
+        for _ in 0..12 {
+            let expected = platform.get_offset() + platform.get_velocity();
+            platform.handle_phys_update();
+            assert!(platform.get_offset() == expected);
+            assert!(platform.get_offset()[1] == 0.);
+            xs.push(platform.get_offset()[0]);
+        }
+        assert!(xs == vec![2., 4., 6., 8., 10., 8., 6., 4., 2., 0., 2., 4.]);
+        assert!(platform.last_delta() == na::Vector2::new(2., 0.));
+    }
+
+    #[test]
+    fn turning_back_keeps_pace() {
+        let mut platform = moving(vec![na::Vector2::new(10., 0.)], 4., PathMode::PingPong);
+        let xs: Vec<f32> = (0..4).map(|_| {
+            platform.handle_phys_update();
+            platform.get_offset()[0]
+        }).collect();
+        assert!(xs == vec![4., 8., 8., 4.]);
+        assert!(platform.last_delta() == na::Vector2::new(-4., 0.));
+    }
+
+    #[test]
+    fn broken_platforms_come_back() {
+        let damage = |amount| Changes { effects: vec![Effect::Damage(amount), Effect::ShieldDamage(100.)] };
+        let mut platform = moving(vec![], 0., PathMode::PingPong);
+        platform.apply_changeset(damage(100.));
+        assert!(!platform.is_broken());
+        platform.hp = Some(10.);
+        platform.respawn_timer = Some(3);
+        platform.apply_changeset(damage(6.));
+        assert!(!platform.is_broken());
+        platform.apply_changeset(damage(6.).merge(&damage(1.)));
+        assert!(platform.is_broken() && platform.get_hitboxes().is_empty());
+        // Hitting a broken platform doesn't keep it down longer.
+        platform.handle_phys_update();
+        platform.apply_changeset(damage(6.));
+        platform.handle_phys_update();
+        assert!(platform.is_broken());
+        platform.handle_phys_update();
+        assert!(!platform.is_broken() && platform.get_hitboxes().len() == 1);
+        // At full health again.
+        platform.apply_changeset(damage(6.));
+        assert!(!platform.is_broken());
+    }
+
+    #[test]
+    fn platforms_without_paths_stay_put() {
+        let mut platform = moving(vec![], 3., PathMode::PingPong);
+        platform.handle_phys_update();
+        assert!(platform.get_offset() == na::Vector2::zeros());
+        assert!(platform.get_velocity() == na::Vector2::zeros());
+    }
+}
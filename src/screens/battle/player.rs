@@ -1,11 +1,17 @@
 use ggez::{Context, GameResult};
-use ggez::graphics::{Image, Drawable, DrawParam, Rect, BlendMode};
+use ggez::graphics::{Color, Drawable, DrawParam, DrawMode, Mesh, Rect, BlendMode};
 use ggez::nalgebra as na;
 
-use crate::inputs::{HandleInput, Input};
+use crate::assets::AssetManager;
+use crate::audio::SoundEvent;
+use crate::debug::DebugChannel;
+use crate::inputs::Input;
 use crate::physics::*;
 use crate::physics::collision::*;
-use crate::util::result::WalpurgisResult;
+use crate::screens::battle::{interactions, layer, strike::Strike};
+use crate::skill_tree::SkillTree;
+use crate::sprites::SpriteSheet;
+use crate::util::{hash, result::{WalpurgisError, WalpurgisResult}};
 
 pub mod inputs;
 use self::inputs::{InputScheme};
@@ -13,29 +19,82 @@ use self::inputs::{InputScheme};
 pub mod meta;
 use self::meta::*;
 
-mod stance;
+pub mod stance;
 use self::stance::*;
 
-mod action;
+pub mod action;
 use self::action::*;
 
+pub mod blueprint;
+use self::blueprint::PlayerBlueprint;
+
+pub mod animation;
+use self::animation::Animation;
+
+pub mod race;
+use self::race::{alien, robot, Passive};
+
 /// The current frame being run. Allows for approximately four seconds of frames.
 pub type FrameNumber = u8;
 
+/// The minimum number of ticks platforms are fallen through for after dropping.
+const IGNORE_DURATION: FrameNumber = 20;
+/// The number of ticks between ranged attacks.
+const RANGED_COOLDOWN: FrameNumber = 30;
+/// How far in front of the player a basic attack reaches.
+const ATTACK_REACH: f32 = 20.;
+/// How much further ahead a dash attack's box is than a basic attack's, since the player is
+/// moving into it.
+const DASH_ATTACK_LUNGE: f32 = 10.;
+/// The number of ticks a grabbed player can be held for before they break free.
+pub const GRAB_HOLD_FRAMES: FrameNumber = 60;
+/// The number of ticks after respawning during which the player can't be hit or grabbed.
+pub const SPAWN_INVULNERABLE_FRAMES: FrameNumber = 60;
+/// Hits dealing at least this many ticks of hitstun send the player tumbling.
+pub const TUMBLE_HITSTUN: FrameNumber = 40;
+/// The number of ticks after being sent tumbling, on top of the hitstun, during which the player
+/// can't be hit or grabbed, so they get a chance to recover.
+pub const TUMBLE_INVULNERABLE_FRAMES: FrameNumber = 30;
+/// The number of ticks after landing from a tumble during which the player can tech.
+pub const TECH_WINDOW: FrameNumber = 7;
+/// The furthest directional influence can turn a knockback, in radians. About 18 degrees.
+pub const DI_MAX_ANGLE: f32 = std::f32::consts::PI / 10.;
+/// The colour players flash in hitstun, every other `HITSTUN_FLASH_FRAMES` ticks.
+const HITSTUN_FLASH_COLOR: Color = Color { r: 1., g: 0.5, b: 0.5, a: 0.6 };
+const HITSTUN_FLASH_FRAMES: u8 = 4;
+/// The colour of a raised shield, which players can be seen through.
+const SHIELD_COLOR: Color = Color { r: 0.4, g: 0.7, b: 1., a: 0.35 };
+/// The colour of the body drawn for players without a sprite to show.
+const PLACEHOLDER_COLOR: Color = Color { r: 0.85, g: 0.85, b: 0.85, a: 1. };
+/// The smallest a shield is drawn, as a fraction of its full size, however weak it gets.
+const MIN_SHIELD_SCALE: f32 = 0.4;
+
 #[derive(Debug)]
 pub struct Player {
     /// `ggez`-specific. Not really used for anything atm.
     mode: Option<BlendMode>,
 
-    /// The sprites for animating the character.
-    sprites: Vec<Image>,
-    /// The sounds made by the character.
-    sfx: Vec</*SoundData*/()>,
+    /// The frames for animating the character, if it has any.
+    sprite_sheet: Option<SpriteSheet>,
+    /// The animation shown for each action, as frames of `sprite_sheet`.
+    animations: Vec<(Action, Animation)>,
+    /// The action being animated and the number of ticks it has been animated for.
+    animation: (Action, u32),
+    /// The sounds made since the last `take_sfx`.
+    sfx: Vec<SoundEvent>,
 
     bboxes: Vec <BoundingBox>,
+    /// The boxes each attack or grab puts out while facing left, along with the frames of it they
+    /// are out on.
+    attack_bboxes: Vec<(Action, Vec<(BoundingBox, HitboxWindow)>)>,
+    /// The boxes currently out, returned by `get_hitboxes`. Rebuilt by `refresh_hitboxes` after
+    /// anything that may change them.
+    hitboxes: Vec<BoundingBox>,
 
     /// The position of the character.
     position: na::Vector2<f32>,
+    /// Where the character comes back after losing a stock.
+    spawn_point: na::Vector2<f32>,
     /// The velocity of the character.
     velocity: na::Vector2<f32>,
     /// The acceleration of the character.
@@ -46,13 +105,27 @@ pub struct Player {
 
     /// Animation variations.
     stance: (VerticalStance, HorizontalStance),
-    /// Animation state.
+    /// The action in progress and its current frame, the number of ticks it has been going for.
+    /// Attacks stay here until they are over.
     movement: (Action, FrameNumber),
 
+    /// The name of the player character.
+    name: String,
     /// The race of the player character.
     race: Race,
     /// Various stats.
     stats: Stats,
+    /// The skills unlocked so far, some of which change `stats`.
+    skill_tree: SkillTree,
+    /// The number of ticks since the player last took damage. Pauses healing.
+    frames_since_damage: u32,
+    /// The damage the player has done to others this battle.
+    damage_dealt: f32,
+    /// Adjustments for evening out mixed-skill matches.
+    handicap: Handicap,
+    /// The stocks the handicap gave, or took, at the start of the round, so they aren't counted
+    /// twice when the next round starts with as many as the last.
+    stocks_from_handicap: i32,
     /// The selected `Ability`s of the player character.
     abilities: Vec<Ability>,
     /// The input options allowed for a player.
@@ -61,37 +134,86 @@ pub struct Player {
     /// Tracking data for platform fall-through.
     platforms_to_ignore: Vec<usize>,
     touched_platforms: Vec<usize>,
-}
+    touched_passable_platforms: Vec<usize>,
+    /// How far a moving platform carried the player on the last tick.
+    carried: na::Vector2<f32>,
+    /// Ticks until `platforms_to_ignore` may be cleared.
+    ignore_ticks_left: FrameNumber,
 
-impl HandleInput for Player {
-    fn handle_input(&mut self, ctx: &mut Context, fire_once_key_buffer: &Vec<Input>) {
-        let actions = self.inputs.get_possible_actions(ctx, fire_once_key_buffer);
-        for action in actions {
-            match action {
-                Action::Walk(HorizontalStance::Left) => {
-                    if let VerticalStance::OnGround(_) = self.stance.0 {
-                        log::info!("Walking left");
-                        self.stance.1 = HorizontalStance::Left;
-                        self.position[0] -= 2_f32;
-                    }
-                },
-                Action::Walk(HorizontalStance::Right) => {
-                    if let VerticalStance::OnGround(_) = self.stance.0 {
-                        log::info!("Walking right");
-                        self.stance.1 = HorizontalStance::Right;
-                        self.position[0] += 2_f32;
-                    }
-                },
-                _ => (),
-            }
-        }
-    }
+    /// The number of ticks extra lift has been given for the current jump.
+    jump_held_frames: FrameNumber,
+    /// Whether the jump key has been held since the current jump started.
+    is_jump_held: bool,
+    /// Whether a jump was started since the last `take_jumped`.
+    jumped: bool,
+    /// The side of the wall being pressed into while in the air, if any. Jumping pushes off it.
+    wall_contact: Option<HorizontalStance>,
+
+    /// Whether the player walked since the last update. Friction doesn't slow walking players.
+    is_walking: bool,
+
+    /// Ticks left during which the player has no hitboxes.
+    dash_invincible_frames: u8,
+    /// Ticks left before another dash can be started.
+    dash_cooldown: FrameNumber,
+    /// Ticks left during which the player has no hitboxes, after teleporting.
+    teleport_invincible_frames: u8,
+    /// Ticks left during which the player has no boxes that can be hit, after respawning, teching
+    /// or tumbling.
+    invulnerable_frames: FrameNumber,
+    /// Ticks left to tech after landing from a tumble.
+    tech_window_frames: FrameNumber,
+    /// Ticks of input left to ignore after being hit.
+    hitstun_frames: u8,
+    /// The number of attacks started, which tells one swing from the next.
+    attacks_started: u32,
+    /// The last attack of each player that hit this one, by owner, as in `Effect::Struck`.
+    struck_by: Vec<(usize, u32)>,
+    /// The way being held to influence the current knockback on the next update, if any.
+    di_direction: Option<HorizontalStance>,
+    /// How far directional influence has turned the current knockback, clockwise on screen. Never
+    /// beyond `DI_MAX_ANGLE` either way.
+    di_angle: f32,
+
+    /// The attack to fire a projectile for on the next update, if any.
+    wants_to_fire: Option<Attack>,
+    /// Ticks until another projectile can be fired.
+    ranged_cooldown: FrameNumber,
+    /// Ticks until the ability in each slot can be used again.
+    ability_cooldown: [FrameNumber; ABILITY_SLOTS],
+    /// The ability being started and the ticks until it takes effect, if any.
+    pending_ability: Option<(Ability, FrameNumber)>,
+
+    /// Whether the shield is up.
+    shielding: bool,
+    /// The damage the shield can still take before breaking.
+    shield_health: f32,
+
+    /// The index of the player being held, if any.
+    grabbed_player: Option<usize>,
+    /// Ticks left to throw the player being held before they break free.
+    grab_hold_frames: FrameNumber,
+    /// Which way to throw the player being held on the next update, if at all.
+    wants_to_throw: Option<AttackDir>,
+    /// The index of the player holding this one, if any. Held players ignore all input.
+    held_by: Option<usize>,
 }
 
 #[derive(Clone)]
 pub struct Changes {
     pub force: na::Vector2<f32>,
     pub contacted_platforms: Vec<usize>,
+    /// The subset of `contacted_platforms` that can be dropped through.
+    pub passable_platforms: Vec<usize>,
+    /// Moves the player out of any platforms they sank into.
+    pub positional_correction: na::Vector2<f32>,
+    /// Ticks of input to ignore after being hit.
+    pub hitstun: FrameNumber,
+    pub effects: Vec<Effect>,
+    /// The side a wall was run into on, if any.
+    pub wall_contact: Option<HorizontalStance>,
+    /// How far the moving platform being stood on moves this tick, taking the player along.
+    pub carry: na::Vector2<f32>,
 }
 
 impl Default for Changes {
@@ -99,6 +221,12 @@ impl Default for Changes {
         Changes {
             force: na::Vector2::new(0_f32, 0_f32),
             contacted_platforms: vec![],
+            passable_platforms: vec![],
+            positional_correction: na::Vector2::new(0_f32, 0_f32),
+            hitstun: 0,
+            effects: vec![],
+            wall_contact: None,
+            carry: na::Vector2::new(0_f32, 0_f32),
         }
     }
 }
@@ -111,6 +239,22 @@ impl Mergeable for Changes {
                 .cloned()
                 .chain(other.contacted_platforms.iter().cloned())
                 .collect(),
+            passable_platforms: self.passable_platforms.iter()
+                .cloned()
+                .chain(other.passable_platforms.iter().cloned())
+                .collect(),
+            positional_correction: interactions::larger_correction(
+                self.positional_correction,
+                other.positional_correction,
+            ),
+            hitstun: self.hitstun.max(other.hitstun),
+            effects: self.effects.iter()
+                .cloned()
+                .chain(other.effects.iter().cloned())
+                .collect(),
+            wall_contact: self.wall_contact.clone().or_else(|| other.wall_contact.clone()),
+            // Standing across two platforms, only one of them can carry the player.
+            carry: if self.carry != na::Vector2::zeros() { self.carry } else { other.carry },
         }
     }
 }
@@ -119,34 +263,127 @@ impl Collidable for Player {
     type ChangeSet = Changes;
 
     fn get_hitboxes<'tick>(&'tick self) -> &'tick[BoundingBox] {
-        self.bboxes.as_ref()
+        self.hitboxes.as_ref()
     }
-    fn apply_changeset(&mut self, Changes { mut force, contacted_platforms }: Self::ChangeSet) {
+    fn apply_changeset(&mut self, Changes {
+        mut force,
+        contacted_platforms,
+        passable_platforms,
+        positional_correction,
+        hitstun,
+        effects,
+        wall_contact,
+        mut carry,
+    }: Self::ChangeSet) {
         log::trace!("Running changeset application on player.");
 
+        // Platforms can't be touched while intangible, so hold height instead of sinking through
+        // them.
+        if self.is_intangible() {
+            force[1] = 0.;
+        }
+        // Held players stay where they were grabbed.
+        if self.held_by.is_some() {
+            force = na::Vector2::zeros();
+            carry = na::Vector2::zeros();
+        }
         log::info!("Moving at velocity: {:?}", self.velocity);
+        self.position += positional_correction + carry;
+        self.carried = carry;
+        // Getting hit interrupts any attack in progress and lets go of any player being held.
+        if hitstun > 0 {
+            self.movement = (Action::Idle, 0);
+            self.grabbed_player = None;
+            self.di_angle = 0.;
+            self.pending_ability = None;
+        }
+        self.hitstun_frames = self.hitstun_frames.max(hitstun);
+        for effect in effects {
+            match effect {
+                Effect::Damage(_) if self.has_armor() => {
+                    log::info!("Armor broke");
+                    self.buff.retain(|buff| *buff != Buff::Armor);
+                },
+                Effect::Damage(amount) => {
+                    self.stats.take_damage(self.resist_damage(amount));
+                    self.frames_since_damage = 0;
+                    self.sfx.push(SoundEvent::Hurt);
+                },
+                Effect::ShieldDamage(amount) => self.damage_shield(amount),
+                Effect::Buff(buff) => add_buff(&mut self.buff, buff),
+                Effect::Struck { owner, instance } => {
+                    self.struck_by.retain(|(struck_by, _)| *struck_by != owner);
+                    self.struck_by.push((owner, instance));
+                },
+            }
+        }
+        self.touched_passable_platforms = passable_platforms;
         self.update_for_platforms(contacted_platforms, &mut force);
+        self.handle_wall_collision(wall_contact);
         self.handle_push(force);
+        if hitstun >= TUMBLE_HITSTUN {
+            self.tumble();
+            self.invulnerable_frames = self.invulnerable_frames.max(hitstun.saturating_add(TUMBLE_INVULNERABLE_FRAMES));
+        }
+        self.refresh_hitboxes();
     }
     fn handle_phys_update(&mut self) {
+        self.apply_di();
+        self.apply_jump_hold();
+        self.apply_fast_fall();
+        self.apply_ground_friction();
         self.velocity += self.acceleration;
+        self.cap_fall_speed();
         self.position += self.velocity;
+        self.update_dash();
+        self.update_knockdown();
+        self.update_repair();
+        self.update_air_stance();
+        self.ranged_cooldown = self.ranged_cooldown.saturating_sub(1);
+        for cooldown in &mut self.ability_cooldown {
+            *cooldown = cooldown.saturating_sub(1);
+        }
+        self.update_pending_ability();
+        self.invulnerable_frames = self.invulnerable_frames.saturating_sub(1);
+        self.advance_attack();
+        self.advance_animation();
+        self.update_hold();
+        self.update_shield();
+        self.expire_buffs();
+        self.expire_ignored_platforms();
         self.reset_for_update();
+        self.refresh_hitboxes();
     }
     fn get_offset(&self) -> na::Vector2<f32> {
         self.position.clone()
     }
+    fn get_velocity(&self) -> na::Vector2<f32> {
+        self.velocity
+    }
+    fn collision_layer(&self) -> u32 {
+        layer::PLAYER
+    }
 }
 
 
+impl Passive for Player {
+    fn passive_tick(&mut self) {
+        self.frames_since_damage = self.frames_since_damage.saturating_add(1);
+        match self.race {
+            Race::Alien => self.stats.heal(alien::regen(self.frames_since_damage)),
+            Race::Robot | Race::Mage => (),
+        }
+    }
+}
+
 impl Drawable for Player {
+    /// Players without a sprite to show are drawn as their body, so that they're never invisible.
     fn draw(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
-        for bbox in &self.bboxes {
-            let mut box_param = param;
-            box_param.color = ggez::graphics::Color::from_rgba(255, 0, 0, 130);
-            box_param.dest.x += self.position[0];
-            box_param.dest.y += self.position[1];
-            bbox.draw(ctx, box_param)?;
+        if !self.draw_sprite(ctx, param)? {
+            self.draw_placeholder(ctx, param)?;
+        }
+        if self.shielding {
+            self.draw_shield(ctx, param)?;
         }
         Ok(())
     }
@@ -165,8 +402,357 @@ impl Drawable for Player {
 }
 
 impl Player {
+    /// Draws the current frame of the player's sprite sheet. Returns whether there was one.
+    fn draw_sprite(&self, ctx: &mut Context, param: DrawParam) -> GameResult<bool> {
+        let (sheet, name) = match (&self.sprite_sheet, self.frame_name()) {
+            (Some(sheet), Some(name)) => (sheet, name),
+            _ => return Ok(false),
+        };
+        let frame = match sheet.frame(name) {
+            Some(frame) => frame,
+            None => return Ok(false),
+        };
+        let mut sprite_param = drawn_at(param, self.position);
+        sprite_param.color = self.tint(sprite_param.color);
+        // Frames face left. Flipping one draws it leftwards from `dest`, so it is moved over by
+        // its width to cover the same spot.
+        if self.stance.1 == HorizontalStance::Right {
+            sprite_param.dest.x += frame.w as f32 * sprite_param.scale.x;
+            sprite_param.scale.x = -sprite_param.scale.x;
+        }
+        sheet.draw_frame(ctx, name, sprite_param)?;
+        Ok(true)
+    }
+
+    /// Draws the player's body as a plain box.
+    fn draw_placeholder(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
+        let mut body_param = drawn_at(param, self.position);
+        body_param.color = self.tint(PLACEHOLDER_COLOR);
+        self.bboxes[0].draw(ctx, body_param)
+    }
+
+    /// `color` changed to show armor and hitstun.
+    fn tint(&self, color: Color) -> Color {
+        if self.hitstun_frames / HITSTUN_FLASH_FRAMES % 2 == 1 {
+            HITSTUN_FLASH_COLOR
+        } else if self.has_armor() {
+            robot::ARMOR_COLOR
+        } else {
+            color
+        }
+    }
+
+    /// Draws the shield as a bubble around the player, which shrinks as the shield weakens.
+    fn draw_shield(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
+        let body = match self.get_broad_aabb() {
+            Some(body) => body,
+            None => return Ok(()),
+        };
+        let strength = self.shield_health / self.stats.max_shield_health();
+        let radius = (body.max - body.min).norm() / 2. * strength.max(MIN_SHIELD_SCALE);
+        let center = (body.min + body.max) / 2.;
+        let bubble = Mesh::new_circle(ctx, DrawMode::fill(), screen_point(na::Vector2::zeros()), radius, 0.5, SHIELD_COLOR)?;
+        bubble.draw(ctx, drawn_at(param, center))
+    }
+}
+
+impl Player {
+    /// The placeholder character of `race`, played with `inputs`.
+    #[cfg(test)]
+    pub fn new(race: Race, inputs: InputScheme) -> WalpurgisResult<Self> {
+        let blueprint = PlayerBlueprint::placeholder(race);
+        blueprint.validate()?;
+        Ok(Self::without_sprites(&blueprint, inputs))
+    }
+
+    /// Builds the character described by `blueprint`, loading its sprite sheet from the asset
+    /// root. The asset root has to be mounted in `ggez`'s filesystem.
+    pub fn from_blueprint(
+        ctx: &mut Context,
+        assets: &mut AssetManager,
+        blueprint: &PlayerBlueprint,
+        inputs: InputScheme,
+    ) -> WalpurgisResult<Self> {
+        let sprite_sheet = match &blueprint.sprite_sheet {
+            Some(descriptor_file) => Some(SpriteSheet::load(ctx, assets, descriptor_file)?),
+            None => None,
+        };
+        if let Some(sheet) = &sprite_sheet {
+            for (action, animation) in &blueprint.animations {
+                if let Some(missing) = animation.frames.iter().find(|frame| sheet.frame(frame).is_none()) {
+                    Err(WalpurgisError::MissingFrame {
+                        character: blueprint.name.clone(),
+                        animation: format!("{:?}", action),
+                        frame: missing.clone(),
+                    })?
+                }
+            }
+        }
+        Ok(Player {
+            sprite_sheet,
+            ..Self::without_sprites(blueprint, inputs)
+        })
+    }
+    /// Builds the character described by `blueprint` afresh, keeping the sprite sheet and controls
+    /// already loaded.
+    pub(super) fn rebuilt(self, blueprint: &PlayerBlueprint) -> Self {
+        Player {
+            sprite_sheet: self.sprite_sheet,
+            ..Self::without_sprites(blueprint, self.inputs)
+        }
+    }
+    /// Builds the character described by `blueprint` without loading anything, so it doesn't
+    /// need a `Context`.
+    pub(super) fn without_sprites(blueprint: &PlayerBlueprint, inputs: InputScheme) -> Self {
+        let bboxes = blueprint.bboxes.clone();
+        let attack_bboxes = melee_attacks()
+            .into_iter()
+            .filter_map(|action| {
+                let window = action.frame_data()?.active_window();
+                let bbox = match action {
+                    Action::Grab => attack_box(&bboxes[0], HitboxTag::Grab),
+                    Action::Attack(Attack::DashAttack) => lunging(attack_box(&bboxes[0], HitboxTag::Hit)),
+                    _ => attack_box(&bboxes[0], HitboxTag::Hit),
+                };
+                Some((action, vec![(bbox, window)]))
+            })
+            .collect();
+        let hitboxes = bboxes.clone();
+
+        Player {
+            mode: None,
+            sprite_sheet: None,
+            animations: blueprint.animations.clone(),
+            animation: (Action::Idle, 0),
+            sfx: vec![],
+
+            position: blueprint.spawn_position,
+            spawn_point: blueprint.spawn_position,
+            velocity: na::Vector2::new(0_f32, 0_f32),
+            acceleration: na::Vector2::new(0_f32, 0_f32),
+            bboxes,
+            attack_bboxes,
+            hitboxes,
+
+            buff: race::starting_buffs(&blueprint.race),
+            stance: (
+                VerticalStance::OnGround(GroundStance::Standing),
+                HorizontalStance::Left,
+            ),
+            movement: (Action::Idle, 0),
+
+            name: blueprint.name.clone(),
+            race: blueprint.race.clone(),
+            stats: blueprint.stats(),
+            skill_tree: SkillTree::for_race(&blueprint.race),
+            frames_since_damage: 0,
+            damage_dealt: 0.,
+            handicap: Handicap::default(),
+            stocks_from_handicap: 0,
+            abilities: race::abilities(&blueprint.race),
+            inputs,
+
+            platforms_to_ignore: vec![],
+            touched_platforms: vec![],
+            touched_passable_platforms: vec![],
+            carried: na::Vector2::zeros(),
+            ignore_ticks_left: 0,
+
+            jump_held_frames: 0,
+            is_jump_held: false,
+            jumped: false,
+            wall_contact: None,
+
+            is_walking: false,
+
+            dash_invincible_frames: 0,
+            dash_cooldown: 0,
+            teleport_invincible_frames: 0,
+            invulnerable_frames: 0,
+            tech_window_frames: 0,
+            hitstun_frames: 0,
+            attacks_started: 0,
+            struck_by: vec![],
+            di_direction: None,
+            di_angle: 0.,
+
+            wants_to_fire: None,
+            ranged_cooldown: 0,
+            ability_cooldown: [0; ABILITY_SLOTS],
+            pending_ability: None,
+
+            shielding: false,
+            shield_health: blueprint.stats().max_shield_health(),
+
+            grabbed_player: None,
+            grab_hold_frames: 0,
+            wants_to_throw: None,
+            held_by: None,
+        }
+    }
+    /// The actions the player's inputs resolve to, given what was pressed since the last tick.
+    /// They are only taken once handed back through `handle_actions`.
+    pub fn resolve_actions(&mut self, ctx: &mut Context, fire_once_key_buffer: &[Input]) -> Vec<Action> {
+        self.inputs.get_possible_actions(ctx, fire_once_key_buffer)
+    }
+    /// The actions whose inputs were released since the last tick.
+    pub fn resolve_releases(&mut self, released_key_buffer: &[Input]) -> Vec<Action> {
+        self.inputs.get_released_actions(released_key_buffer)
+    }
+    /// Lets go of held actions.
+    pub fn handle_releases(&mut self, released: &[Action]) {
+        for action in released {
+            if let Action::Jump = action {
+                self.is_jump_held = false;
+            }
+        }
+    }
+    pub fn handle_actions(&mut self, actions: Vec<Action>) {
+        self.perform_actions(actions);
+        self.refresh_hitboxes();
+    }
+    fn perform_actions(&mut self, actions: Vec<Action>) {
+        if self.held_by.is_some() || self.is_stunned() {
+            return;
+        }
+        // Throwing is all that can be done while holding a player.
+        if self.grabbed_player.is_some() {
+            for action in actions {
+                if let Action::Throw(dir) = action {
+                    log::info!("Throwing {:?}", dir);
+                    self.wants_to_throw = Some(dir);
+                }
+            }
+            return;
+        }
+        // Right after a hard landing teching is all that can be done, even in hitstun.
+        if self.tech_window_frames > 0 {
+            self.hitstun_frames = self.hitstun_frames.saturating_sub(1);
+            if let Some(dir) = actions.into_iter().find_map(Action::into_tech) {
+                self.tech(dir);
+            }
+            return;
+        }
+        if let VerticalStance::OnGround(GroundStance::TechRolling(_)) | VerticalStance::OnGround(GroundStance::KnockedDown(_)) = self.stance.0 {
+            return;
+        }
+        // Walking is ignored in hitstun, but still steers the knockback.
+        if self.hitstun_frames > 0 {
+            self.hitstun_frames -= 1;
+            self.di_direction = actions.iter().find_map(|action| match action {
+                Action::Walk(dir) => Some(dir.clone()),
+                _ => None,
+            });
+            return;
+        }
+        // The shield can still be put up while charging, but nothing else can be done.
+        let actions = if self.is_charging() {
+            actions.into_iter().filter(|action| *action == Action::Shield).collect()
+        } else {
+            actions
+        };
+        // Nothing else can be done with the shield up.
+        if self.shield_health > 0. && actions.contains(&Action::Shield) {
+            self.raise_shield();
+            return;
+        }
+        self.lower_shield();
+        for action in actions {
+            match action {
+                Action::Walk(dir) => {
+                    match self.stance.0 {
+                        VerticalStance::OnGround(_) => self.walk(dir),
+                        VerticalStance::InAir { .. } => self.drift(dir),
+                    }
+                },
+                Action::Jump => {
+                    if let VerticalStance::OnGround(_) = self.stance.0 {
+                        log::info!("Jumping");
+                        self.jump();
+                    } else if let Some(wall) = self.wall_contact.take() {
+                        log::info!("Jumping off a wall on the {:?}", wall);
+                        self.wall_jump(wall);
+                    } else if let VerticalStance::InAir { jumps_spent, .. } = self.stance.0 {
+                        // The jump off the ground is spent even if the player walked off instead.
+                        let jumps_spent = jumps_spent.max(1);
+                        if jumps_spent <= u32::from(self.stats.max_air_jumps()) {
+                            log::info!("Jumping in the air");
+                            self.rise(jumps_spent + 1);
+                        }
+                    }
+                },
+                Action::DropThrough => {
+                    if let VerticalStance::OnGround(_) = self.stance.0 {
+                        log::info!("Dropping through platforms");
+                        self.drop_through();
+                    }
+                },
+                Action::Dash(dir) => {
+                    if let VerticalStance::OnGround(GroundStance::Standing) = self.stance.0 {
+                        if self.dash_cooldown == 0 {
+                            log::info!("Dashing {:?}", dir);
+                            self.dash(dir);
+                        }
+                    }
+                },
+                // Attacking while dashing turns the dash into an attack.
+                Action::Attack(Attack::Basics(..)) if self.is_dashing() && self.attack_phase().is_none() => {
+                    log::info!("Dash attacking");
+                    self.start_attack(Attack::DashAttack);
+                },
+                // Only made by attacking while dashing.
+                Action::Attack(Attack::DashAttack) => (),
+                Action::Attack(Attack::Ranged) if self.ranged_cooldown == 0 => {
+                    log::info!("Firing");
+                    self.sfx.push(SoundEvent::Attack);
+                    self.wants_to_fire = Some(Attack::Ranged);
+                    self.ranged_cooldown = RANGED_COOLDOWN;
+                },
+                Action::UseAbility(slot) => {
+                    let ability = self.abilities.iter().find(|ability| ability.data().slot == slot).cloned();
+                    match ability {
+                        Some(ability) if self.ability_cooldown[slot] == 0 && self.start_ability(ability.clone()) => {
+                            self.ability_cooldown[slot] = ability.data().cooldown;
+                        },
+                        _ => (),
+                    }
+                },
+                Action::Attack(attack) if attack.frame_data().is_some() && self.attack_phase().is_none() => {
+                    log::info!("Attacking: {:?}", attack);
+                    self.start_attack(attack);
+                },
+                Action::Grab if self.attack_phase().is_none() => {
+                    log::info!("Grabbing");
+                    self.movement = (Action::Grab, 0);
+                },
+                Action::FastFall => {
+                    if let VerticalStance::InAir { stance: AirStance::Falling, .. } = self.stance.0 {
+                        log::info!("Fast falling");
+                        self.fast_fall();
+                    }
+                },
+                _ => (),
+            }
+        }
+    }
     fn reset_for_update(&mut self) {
         self.acceleration = na::Vector2::zeros();
+        self.di_direction = None;
+        self.is_walking = false;
+    }
+    /// Turns the velocity towards the direction held during hitstun, without changing its speed.
+    fn apply_di(&mut self) {
+        let held = match self.di_direction {
+            Some(HorizontalStance::Left) => na::Vector2::new(-1., 0.),
+            Some(HorizontalStance::Right) => na::Vector2::new(1., 0.),
+            None => return,
+        };
+        // The angle from the velocity to the held direction, clockwise on screen.
+        let cross = self.velocity[0] * held[1] - self.velocity[1] * held[0];
+        let to_held = cross.atan2(self.velocity.dot(&held));
+        let di_angle = (self.di_angle + to_held).clamp(-DI_MAX_ANGLE, DI_MAX_ANGLE);
+        self.velocity = BoundingBox::rotate(self.velocity, di_angle - self.di_angle);
+        self.di_angle = di_angle;
     }
     fn update_for_platforms(
         &mut self,
@@ -181,62 +767,2297 @@ impl Player {
                 break;
             }
         }
-        // If falling (aka velocity is downwards) and we hit a platform
-        // we aren't falling through, we want to stop.
-        if touching_new_platform && self.velocity[1] > 0. {
-            // TODO Fix slight offsets.
+        // If falling (aka velocity is downwards) or standing and we hit a platform
+        // we aren't falling through, we want to stop. Any overlap has already been corrected.
+        if touching_new_platform && self.velocity[1] >= 0. {
             self.acceleration[1] = -self.velocity[1];
             f[1] = 0.;
+            if let VerticalStance::InAir { stance: AirStance::Tumbling, .. } = self.stance.0 {
+                log::debug!("Landed hard");
+                self.tech_window_frames = TECH_WINDOW;
+            }
+            if let VerticalStance::InAir { .. } = self.stance.0 {
+                log::debug!("Landed");
+                self.sfx.push(SoundEvent::Land);
+                self.stance.0 = VerticalStance::OnGround(GroundStance::Standing);
+                let run_speed = self.stats.run_speed();
+                self.velocity[0] = self.velocity[0].clamp(-run_speed, run_speed);
+            }
+        } else if !touching_new_platform && !self.is_intangible() {
+            if let VerticalStance::OnGround(_) = self.stance.0 {
+                self.stance.0 = VerticalStance::InAir {
+                    jumps_spent: 0,
+                    stance: AirStance::Falling,
+                };
+            }
+        }
+    }
+    fn walk(&mut self, dir: HorizontalStance) {
+        log::info!("Walking {:?}", dir);
+        self.is_walking = true;
+        self.position[0] += dir.sign() * self.walk_speed();
+        self.stance.1 = dir;
+    }
+    /// The walking speed, sped up by any buff.
+    fn walk_speed(&self) -> f32 {
+        self.buff.iter()
+            .map(|buff| match buff {
+                Buff::SpeedUp { multiplier, .. } => *multiplier,
+                _ => 1.,
+            })
+            .product::<f32>() * self.stats.walk_speed()
+    }
+    /// The part of `amount` of damage that gets through any damage resistance.
+    fn resist_damage(&self, amount: f32) -> f32 {
+        self.buff.iter()
+            .map(|buff| match buff {
+                Buff::DamageResist { fraction, .. } => 1. - fraction.clamp(0., 1.),
+                _ => 1.,
+            })
+            .product::<f32>() * amount
+    }
+    /// Armor doesn't hold while charging.
+    pub fn has_armor(&self) -> bool {
+        self.buff.contains(&Buff::Armor) && !self.is_charging()
+    }
+    pub fn is_charging(&self) -> bool {
+        matches!(self.stance.0, VerticalStance::OnGround(GroundStance::Charging(_)))
+    }
+    pub fn is_stunned(&self) -> bool {
+        self.buff.iter().any(|buff| matches!(buff, Buff::Stun { .. }))
+    }
+    /// Counts down every buff, dropping the ones that ran out.
+    fn expire_buffs(&mut self) {
+        self.buff.retain_mut(Buff::tick);
+    }
+    /// Speeds up towards `dir`, up to `stats.air_speed()`. Doesn't turn the player around.
+    fn drift(&mut self, dir: HorizontalStance) {
+        let sign = dir.sign();
+        let headroom = (self.stats.air_speed() - sign * self.velocity[0]).max(0.);
+        self.acceleration[0] += sign * self.stats.air_acceleration().min(headroom);
+    }
+    fn jump(&mut self) {
+        self.rise(1);
+    }
+    /// Starts rising at jumping speed, having spent `jumps_spent` jumps since landing.
+    fn rise(&mut self, jumps_spent: u32) {
+        self.stance.0 = VerticalStance::InAir {
+            jumps_spent,
+            stance: AirStance::Upping,
+        };
+        self.velocity[1] = -self.stats.jump_speed();
+        self.jump_held_frames = 0;
+        self.is_jump_held = true;
+        self.jumped = true;
+    }
+    /// Holding jump while rising gives a bit of extra lift, up to a limit.
+    fn apply_jump_hold(&mut self) {
+        if let VerticalStance::InAir { stance: AirStance::Upping, .. } = self.stance.0 {
+            if self.is_jump_held && self.jump_held_frames < self.stats.max_jump_hold_frames() {
+                self.jump_held_frames += 1;
+                self.acceleration[1] -= self.stats.jump_hold_acceleration();
+            }
+        }
+    }
+    fn drop_through(&mut self) {
+        self.platforms_to_ignore.extend(self.touched_passable_platforms.iter().cloned());
+        self.ignore_ticks_left = IGNORE_DURATION;
+    }
+    /// Stops ignoring platforms once `IGNORE_DURATION` is up. Platforms still being touched are
+    /// ignored until they are left so that the player doesn't land inside of them.
+    fn expire_ignored_platforms(&mut self) {
+        if self.ignore_ticks_left > 0 {
+            self.ignore_ticks_left -= 1;
+        } else if !self.platforms_to_ignore.is_empty() {
+            let touched = &self.touched_platforms;
+            self.platforms_to_ignore.retain(|platform| touched.contains(platform));
+        }
+    }
+    fn dash(&mut self, dir: HorizontalStance) {
+        let speed = self.stats.dash_speed();
+        let spike = match dir {
+            HorizontalStance::Left => -speed,
+            HorizontalStance::Right => speed,
+        };
+        self.velocity[0] = (self.velocity[0] + spike).max(-speed).min(speed);
+        self.stance = (VerticalStance::OnGround(GroundStance::Dashing(self.stats.dash_duration())), dir);
+        self.dash_invincible_frames = self.stats.dash_intangible_frames();
+        self.dash_cooldown = self.stats.dash_cooldown();
+    }
+    pub fn is_dashing(&self) -> bool {
+        matches!(self.stance.0, VerticalStance::OnGround(GroundStance::Dashing(_)))
+    }
+    /// Starts using `ability`, returning whether it could be used. Abilities with no startup take
+    /// effect right away, and only one ability can be starting at a time.
+    fn start_ability(&mut self, ability: Ability) -> bool {
+        let startup = ability.data().startup;
+        if self.pending_ability.is_some() {
+            false
+        } else if startup == 0 {
+            self.use_ability(&ability)
+        } else {
+            log::info!("Starting {:?}", ability);
+            self.pending_ability = Some((ability, startup));
+            true
+        }
+    }
+    /// Counts down the startup of the pending ability, using it once it's over.
+    fn update_pending_ability(&mut self) {
+        if let Some((ability, ticks_left)) = &mut self.pending_ability {
+            *ticks_left -= 1;
+            if *ticks_left == 0 {
+                let ability = ability.clone();
+                self.pending_ability = None;
+                self.use_ability(&ability);
+            }
+        }
+    }
+    /// Uses `ability`, returning whether it could be used.
+    fn use_ability(&mut self, ability: &Ability) -> bool {
+        match ability.data().effect {
+            AbilityEffect::Projectile => {
+                log::info!("Firing {:?}", ability);
+                self.wants_to_fire = Some(Attack::Ability(ability.clone()));
+            },
+            AbilityEffect::Teleport(distance) => {
+                log::info!("Teleporting {:?}", self.stance.1);
+                self.teleport(distance);
+            },
+            AbilityEffect::Charge(frames) => {
+                if let VerticalStance::OnGround(GroundStance::Standing) = self.stance.0 {
+                    log::info!("Charging {:?}", ability);
+                    self.stance.0 = VerticalStance::OnGround(GroundStance::Charging(frames));
+                    self.velocity[0] = 0.;
+                } else {
+                    return false;
+                }
+            },
+            AbilityEffect::SelfBuff(buff) => {
+                log::info!("Buffing with {:?}", ability);
+                add_buff(&mut self.buff, buff);
+            },
+        }
+        true
+    }
+    /// Ticks until the ability in `slot` can be used again.
+    #[cfg(test)]
+    pub fn ability_cooldown(&self, slot: usize) -> FrameNumber {
+        self.ability_cooldown.get(slot).copied().unwrap_or(0)
+    }
+    /// Moves straight to a point ahead, with no hitboxes on the way there, so nothing in between
+    /// is touched.
+    fn teleport(&mut self, distance: f32) {
+        self.position[0] += self.stance.1.sign() * distance;
+        self.teleport_invincible_frames = alien::TELEPORT_INVINCIBLE_FRAMES;
+    }
+    /// Whether the player has no hitboxes at all, so that nothing can touch them.
+    fn is_intangible(&self) -> bool {
+        self.dash_invincible_frames > 0 || self.teleport_invincible_frames > 0
+    }
+    /// Counts down the current dash, coming to a stop once it is over. Friction doesn't slow
+    /// dashes, which keep their speed until then. Dashing off a ledge keeps it for good.
+    fn update_dash(&mut self) {
+        if self.dash_invincible_frames > 0 {
+            self.dash_invincible_frames -= 1;
+        }
+        self.dash_cooldown = self.dash_cooldown.saturating_sub(1);
+        self.teleport_invincible_frames = self.teleport_invincible_frames.saturating_sub(1);
+        if let VerticalStance::OnGround(GroundStance::Dashing(ref mut frames_left)) = self.stance.0 {
+            *frames_left = frames_left.saturating_sub(1);
+            if *frames_left == 0 {
+                self.stance.0 = VerticalStance::OnGround(GroundStance::Standing);
+                self.velocity[0] = 0.;
+            }
+        }
+    }
+    /// Sends the player tumbling through the air.
+    fn tumble(&mut self) {
+        let jumps_spent = match self.stance.0 {
+            VerticalStance::InAir { jumps_spent, .. } => jumps_spent,
+            VerticalStance::OnGround(_) => 0,
+        };
+        self.stance.0 = VerticalStance::InAir {
+            jumps_spent,
+            stance: AirStance::Tumbling,
+        };
+        self.tech_window_frames = 0;
+    }
+    /// Rolls out of a hard landing towards `dir`, or in place, unhittable for the whole roll.
+    fn tech(&mut self, dir: Option<HorizontalStance>) {
+        log::info!("Teching {:?}", dir);
+        self.velocity[0] = dir.map_or(0., |dir| dir.sign() * self.stats.tech_roll_speed());
+        self.stance.0 = VerticalStance::OnGround(GroundStance::TechRolling(self.stats.tech_roll_frames()));
+        self.invulnerable_frames = self.stats.tech_roll_frames();
+        self.tech_window_frames = 0;
+        self.hitstun_frames = 0;
+    }
+    /// Counts down the tech window, knocking the player down if it runs out, and gets up once a
+    /// tech roll or knockdown is over.
+    fn update_knockdown(&mut self) {
+        if self.tech_window_frames > 0 {
+            self.tech_window_frames -= 1;
+            if self.tech_window_frames == 0 {
+                log::info!("Knocked down");
+                self.stance.0 = VerticalStance::OnGround(GroundStance::KnockedDown(self.stats.knockdown_frames()));
+                self.velocity[0] = 0.;
+            }
+            return;
+        }
+        if let VerticalStance::OnGround(GroundStance::TechRolling(ref mut frames_left))
+            | VerticalStance::OnGround(GroundStance::KnockedDown(ref mut frames_left)) = self.stance.0
+        {
+            *frames_left = frames_left.saturating_sub(1);
+            if *frames_left == 0 {
+                self.stance.0 = VerticalStance::OnGround(GroundStance::Standing);
+                self.velocity[0] = 0.;
+            }
+        }
+    }
+    /// Counts down a repair, healing once it's over.
+    fn update_repair(&mut self) {
+        if let VerticalStance::OnGround(GroundStance::Charging(ref mut frames_left)) = self.stance.0 {
+            *frames_left = frames_left.saturating_sub(1);
+            if *frames_left == 0 {
+                log::info!("Repaired");
+                self.stance.0 = VerticalStance::OnGround(GroundStance::Standing);
+                self.stats.heal(robot::REPAIR_AMOUNT);
+            }
+        }
+    }
+    fn fast_fall(&mut self) {
+        if let VerticalStance::InAir { ref mut stance, .. } = self.stance.0 {
+            *stance = AirStance::FastFalling;
+            self.velocity[1] *= self.stats.fast_fall_multiplier();
+        }
+    }
+    fn apply_fast_fall(&mut self) {
+        if let VerticalStance::InAir { stance: AirStance::FastFalling, .. } = self.stance.0 {
+            self.acceleration[1] += self.stats.fast_fall_acceleration();
+        }
+    }
+    /// Keeps falling players from going any faster than their fall speed, or their fast fall speed
+    /// while fast falling. Players tumbling from a hit aren't held back.
+    fn cap_fall_speed(&mut self) {
+        let cap = match self.stance.0 {
+            VerticalStance::InAir { stance: AirStance::Falling, .. } => self.stats.fall_speed(),
+            VerticalStance::InAir { stance: AirStance::FastFalling, .. } => self.stats.fast_fall_speed(),
+            _ => return,
+        };
+        self.velocity[1] = self.velocity[1].min(cap);
+    }
+    /// Starts falling once the top of a jump is reached.
+    fn update_air_stance(&mut self) {
+        if let VerticalStance::InAir { ref mut stance, .. } = self.stance.0 {
+            if let AirStance::Upping | AirStance::WallJumping = stance {
+                if self.velocity[1] >= 0. {
+                    *stance = AirStance::Falling;
+                }
+            }
+        }
+    }
+    /// Keeps track of the wall being pressed into, which only matters in the air.
+    fn handle_wall_collision(&mut self, wall: Option<HorizontalStance>) {
+        self.wall_contact = match self.stance.0 {
+            VerticalStance::InAir { .. } => wall,
+            VerticalStance::OnGround(_) => None,
+        };
+    }
+    /// Jumps up and away from the wall on the `wall` side, facing away from it. Spends no jumps.
+    fn wall_jump(&mut self, wall: HorizontalStance) {
+        let away = match wall {
+            HorizontalStance::Left => HorizontalStance::Right,
+            HorizontalStance::Right => HorizontalStance::Left,
+        };
+        self.velocity = na::Vector2::new(away.sign() * self.stats.wall_jump_speed(), -self.stats.jump_speed());
+        self.stance = (VerticalStance::InAir {
+            jumps_spent: 0,
+            stance: AirStance::WallJumping,
+        }, away);
+        self.jumped = true;
+    }
+    fn start_attack(&mut self, attack: Attack) {
+        self.sfx.push(SoundEvent::Attack);
+        self.movement = (Action::Attack(attack), 0);
+        self.attacks_started = self.attacks_started.wrapping_add(1);
+    }
+    /// Whether `strike` already hit the player.
+    pub fn was_struck_by(&self, strike: &Strike) -> bool {
+        self.struck_by.contains(&(strike.owner, strike.instance))
+    }
+    /// The part of the attack in progress the player is in, if any.
+    fn attack_phase(&self) -> Option<AttackPhase> {
+        self.movement.0.frame_data()?.phase(self.movement.1)
+    }
+    /// Rebuilds the boxes returned by `get_hitboxes` for the player's current state.
+    fn refresh_hitboxes(&mut self) {
+        self.hitboxes = if self.is_intangible() {
+            vec![]
+        } else if self.invulnerable_frames > 0 {
+            let attack_boxes = self.attack_boxes_out();
+            self.bboxes.iter().filter(|bbox| !bbox.tag.can_be_hit()).cloned().chain(attack_boxes).collect()
+        } else if self.shielding {
+            with_shield(&self.bboxes)
+        } else {
+            let attack_boxes = self.attack_boxes_out();
+            self.bboxes.iter().cloned().chain(attack_boxes).collect()
+        };
+    }
+    /// The boxes of the attack in progress whose windows contain its current frame, turned the way
+    /// the player is facing.
+    fn attack_boxes_out(&self) -> Vec<BoundingBox> {
+        let (action, frame) = &self.movement;
+        self.attack_bboxes.iter()
+            .filter(|(boxed_action, _)| boxed_action == action)
+            .flat_map(|(_, boxes)| boxes)
+            .filter(|(_, window)| window.contains(*frame))
+            .map(|(bbox, _)| match self.stance.1 {
+                HorizontalStance::Left => bbox.clone(),
+                HorizontalStance::Right => mirrored(bbox, &self.bboxes[0]),
+            })
+            .collect()
+    }
+    /// Counts the ticks of the attack in progress, going back to idle once it is over.
+    fn advance_attack(&mut self) {
+        if self.attack_phase().is_some() {
+            self.movement.1 += 1;
+            if self.attack_phase().is_none() {
+                self.movement = (Action::Idle, 0);
+            }
+        }
+    }
+    /// The action shown by the player's animation. Walking only counts while no attack is going.
+    fn animated_action(&self) -> Action {
+        match &self.movement.0 {
+            Action::Idle if self.is_walking => Action::Walk(self.stance.1.clone()),
+            action => action.clone(),
+        }
+    }
+    /// Counts the ticks the current action has been animated for, starting over whenever it
+    /// changes.
+    fn advance_animation(&mut self) {
+        let action = self.animated_action();
+        if self.animation.0 == action {
+            self.animation.1 += 1;
+        } else {
+            self.animation = (action, 0);
+        }
+    }
+    /// The name of the frame to draw, if the character has an animation for what it is doing.
+    pub fn frame_name(&self) -> Option<&str> {
+        animation::find(&self.animations, &self.animation.0)?.frame_at(self.animation.1)
+    }
+    /// Draws the player's own boxes, for debugging.
+    pub fn draw_boxes(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
+        for bbox in &self.bboxes {
+            let mut box_param = drawn_at(param, self.position);
+            box_param.color = DebugChannel::Hurtboxes.color();
+            bbox.draw(ctx, box_param)?;
+        }
+        Ok(())
+    }
+    /// Draws the boxes of the attack the player has out, if any, for debugging.
+    pub fn draw_attack_boxes(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
+        for bbox in self.attack_boxes_out() {
+            let mut box_param = drawn_at(param, self.position);
+            box_param.color = DebugChannel::Attacks.color();
+            bbox.draw(ctx, box_param)?;
+        }
+        Ok(())
+    }
+    /// Counts down how long the held player can still be thrown for, letting go once time is up.
+    fn update_hold(&mut self) {
+        if self.grabbed_player.is_some() {
+            self.grab_hold_frames = self.grab_hold_frames.saturating_sub(1);
+            if self.grab_hold_frames == 0 {
+                log::info!("Grabbed player broke free");
+                self.grabbed_player = None;
+            }
+        }
+    }
+    /// Drains the shield while it's up, and regenerates it while it's down.
+    fn update_shield(&mut self) {
+        if self.shielding {
+            self.damage_shield(self.stats.shield_drain_rate());
+        } else {
+            self.shield_health = (self.shield_health + self.stats.shield_regen_rate()).min(self.stats.max_shield_health());
+        }
+    }
+    /// Blocks with the shield. Players on the ground stand still behind it.
+    fn raise_shield(&mut self) {
+        self.shielding = true;
+        if let VerticalStance::OnGround(GroundStance::Standing) = self.stance.0 {
+            self.stance.0 = VerticalStance::OnGround(GroundStance::Attack(Attack::Shielding));
+        }
+    }
+    fn lower_shield(&mut self) {
+        self.shielding = false;
+        if let VerticalStance::OnGround(GroundStance::Attack(Attack::Shielding)) = self.stance.0 {
+            self.stance.0 = VerticalStance::OnGround(GroundStance::Standing);
+        }
+    }
+    /// Breaks the shield once it runs out of health, stunning the player.
+    fn damage_shield(&mut self, amount: f32) {
+        self.shield_health -= amount;
+        if self.shield_health <= 0. {
+            log::info!("Shield broke");
+            self.shield_health = 0.;
+            self.lower_shield();
+            // The tick the shield broke on counts towards the stun, but its input was already
+            // handled.
+            let frames_left = self.stats.shield_break_stun_frames().saturating_add(1);
+            add_buff(&mut self.buff, Buff::Stun { frames_left });
+        }
+    }
+    /// Slows standing players down by `stats.friction()` until they stop.
+    fn apply_ground_friction(&mut self) {
+        if let VerticalStance::OnGround(GroundStance::Standing)
+            | VerticalStance::OnGround(GroundStance::Attack(Attack::Shielding)) = self.stance.0 {
+            if !self.is_walking {
+                let speed = (self.velocity[0].abs() - self.stats.friction()).max(0.);
+                self.velocity[0] = speed * self.velocity[0].signum();
+            }
+        }
+    }
+    /// The knockback taken when hit from `source` with the base knockback in `stats`.
+    pub fn knockback_from(&self, source: na::Vector2<f32>) -> na::Vector2<f32> {
+        self.knockback_from_attack(source, self.stats.knockback_base())
+    }
+    /// The knockback taken when hit from `source` by an attack with the given base knockback.
+    /// Points away from `source`, or straight up if hit from exactly where the player is.
+    pub fn knockback_from_attack(&self, source: na::Vector2<f32>, base: f32) -> na::Vector2<f32> {
+        let away = self.position - source;
+        let direction = if away.norm() < f32::EPSILON {
+            na::Vector2::new(0., -1.)
+        } else {
+            away.normalize()
+        };
+        self.knockback_towards(direction, base)
+    }
+    /// The knockback taken along the unit vector `direction` from an attack with the given base
+    /// knockback.
+    pub fn knockback_towards(&self, direction: na::Vector2<f32>, base: f32) -> na::Vector2<f32> {
+        let magnitude = (base + self.stats.damage_pct() * self.stats.knockback_growth()) / self.stats.weight();
+        self.handicap.scale_knockback_taken(direction * magnitude)
+    }
+    /// The attack in progress, if any of its boxes are out.
+    pub fn strike(&self, owner: usize) -> Option<Strike> {
+        match &self.movement.0 {
+            Action::Attack(attack) if !self.attack_boxes_out().is_empty() => {
+                let data = attack.frame_data()?;
+                Some(Strike::new(
+                    owner,
+                    attack.clone(),
+                    self.position,
+                    self.scale_damage_dealt(data.damage),
+                    data.knockback,
+                    self.attacks_started,
+                ))
+            },
+            _ => None,
+        }
+    }
+    /// Whether a grab is reaching for players, i.e. its `Grab` box is out.
+    pub fn is_grabbing(&self) -> bool {
+        self.movement.0 == Action::Grab && self.grabbed_player.is_none() && !self.attack_boxes_out().is_empty()
+    }
+    /// Starts holding the player at `grabbed`. The grab stops reaching, going straight to its
+    /// recovery.
+    pub fn hold(&mut self, grabbed: usize) {
+        if let Some(data) = self.movement.0.frame_data() {
+            self.movement.1 = self.movement.1.max(data.startup + data.active);
+        }
+        self.grabbed_player = Some(grabbed);
+        self.grab_hold_frames = GRAB_HOLD_FRAMES;
+        self.wants_to_throw = None;
+        self.refresh_hitboxes();
+    }
+    /// Gets held by the player at `holder`, dropping whatever was being done.
+    pub fn be_held(&mut self, holder: usize) {
+        self.held_by = Some(holder);
+        self.movement = (Action::Idle, 0);
+        self.lower_shield();
+        self.grabbed_player = None;
+        self.velocity = na::Vector2::zeros();
+        self.refresh_hitboxes();
+    }
+    /// Stops being held.
+    pub fn release(&mut self) {
+        self.held_by = None;
+    }
+    pub fn grabbed_player(&self) -> Option<usize> {
+        self.grabbed_player
+    }
+    pub fn held_by(&self) -> Option<usize> {
+        self.held_by
+    }
+    /// The player to throw and which way, if a throw was input since the last call. Lets go of
+    /// the player.
+    pub fn take_throw_request(&mut self) -> Option<(usize, AttackDir)> {
+        let dir = self.wants_to_throw.take()?;
+        Some((self.grabbed_player.take()?, dir))
+    }
+    /// The unit vector a throw in `dir` sends the thrown player along. Side throws go up and
+    /// forward.
+    pub fn throw_direction(&self, dir: &AttackDir) -> na::Vector2<f32> {
+        match dir {
+            AttackDir::Up => na::Vector2::new(0., -1.),
+            AttackDir::Down => na::Vector2::new(0., 1.),
+            AttackDir::Side => {
+                na::Vector2::new(self.stance.1.sign(), -1.).normalize()
+            },
+        }
+    }
+    pub fn scale_damage_dealt(&self, damage: f32) -> f32 {
+        self.handicap.scale_damage_dealt(damage)
+    }
+    pub fn handicap(&self) -> &Handicap {
+        &self.handicap
+    }
+    /// Plays with `handicap` from the next round on.
+    pub fn set_handicap(&mut self, handicap: Handicap) {
+        self.handicap = handicap;
+    }
+    pub fn damage_pct(&self) -> f32 {
+        self.stats.damage_pct()
+    }
+    pub fn stocks(&self) -> u8 {
+        self.stats.stocks()
+    }
+    /// The damage the player has done to others this battle.
+    pub fn damage_dealt(&self) -> f32 {
+        self.damage_dealt
+    }
+    pub fn add_damage_dealt(&mut self, amount: f32) {
+        self.damage_dealt += amount;
+    }
+    /// The stocks the player has lost this battle.
+    pub fn stocks_lost(&self) -> u8 {
+        self.stats.max_stocks().saturating_sub(self.stats.stocks())
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn race(&self) -> &Race {
+        &self.race
+    }
+    /// Whether the player has `attack` at all. Abilities depend on the race, and melee attacks on
+    /// the boxes the character was built with.
+    pub fn has_attack(&self, attack: &Attack) -> bool {
+        match attack {
+            Attack::Ranged | Attack::Shielding => true,
+            Attack::Ability(ability) => self.abilities.contains(ability),
+            Attack::Basics(..) | Attack::DashAttack => self.attack_bboxes
+                .iter()
+                .any(|(action, _)| matches!(action, Action::Attack(a) if a == attack)),
+        }
+    }
+    pub fn skill_tree(&self) -> &SkillTree {
+        &self.skill_tree
+    }
+    /// Unlocks a node of the player's skill tree, changing their stats right away if it's a stat
+    /// modifier.
+    #[cfg(test)]
+    pub fn unlock_skill(&mut self, node_id: usize) -> Result<(), crate::skill_tree::SkillTreeError> {
+        self.skill_tree.unlock(node_id)?;
+        self.stats.apply_modifier(&self.skill_tree.nodes[&node_id].effect);
+        Ok(())
+    }
+    pub fn is_eliminated(&self) -> bool {
+        self.stats.stocks() == 0
+    }
+    /// Loses a stock and respawns, unless that was the last stock.
+    pub fn knock_out(&mut self) {
+        if self.stats.lose_stock() {
+            self.respawn();
+            self.invulnerable_frames = SPAWN_INVULNERABLE_FRAMES;
+            self.refresh_hitboxes();
         }
     }
+    /// A hash of everything that makes up the character, for checking that two runs were played
+    /// with the same one.
+    pub fn character_hash(&self) -> WalpurgisResult<u64> {
+        hash::content_hash(&(&self.race, &self.stats, &self.handicap))
+    }
+    /// Gets the player ready for a new round: back at their spawn point with `stocks` stocks, or as
+    /// many as they started the battle with, evened out by their handicap, and nothing left over
+    /// from the last round.
+    pub fn start_round(&mut self, stocks: Option<u8>) {
+        let stocks = stocks.unwrap_or_else(|| {
+            (i32::from(self.stats.max_stocks()) - self.stocks_from_handicap).max(1) as u8
+        });
+        let handicapped = self.handicap.stocks(u32::from(stocks)).min(u32::from(u8::MAX)) as u8;
+        self.stocks_from_handicap = i32::from(handicapped) - i32::from(stocks);
+        self.stats.refill_stocks(handicapped);
+        self.stats.take_damage(self.handicap.starting_damage);
+        self.frames_since_damage = 0;
+        self.ignore_ticks_left = 0;
+        self.jump_held_frames = 0;
+        self.is_jump_held = false;
+        self.wall_contact = None;
+        self.dash_cooldown = 0;
+        self.invulnerable_frames = 0;
+        self.tech_window_frames = 0;
+        self.di_direction = None;
+        self.di_angle = 0.;
+        self.wants_to_fire = None;
+        self.ranged_cooldown = 0;
+        self.ability_cooldown = [0; ABILITY_SLOTS];
+        self.respawn();
+    }
+    /// Moves the player's spawn point, and the player along with it.
+    pub fn spawn_at(&mut self, spawn_point: na::Vector2<f32>) {
+        self.spawn_point = spawn_point;
+        self.respawn();
+    }
+    fn respawn(&mut self) {
+        self.position = self.spawn_point;
+        self.velocity = na::Vector2::zeros();
+        self.acceleration = na::Vector2::zeros();
+        self.stance.0 = VerticalStance::InAir {
+            jumps_spent: 0,
+            stance: AirStance::Falling,
+        };
+        self.platforms_to_ignore.clear();
+        self.buff = race::starting_buffs(&self.race);
+        self.hitstun_frames = 0;
+        self.struck_by.clear();
+        self.dash_invincible_frames = 0;
+        self.teleport_invincible_frames = 0;
+        self.pending_ability = None;
+        self.movement = (Action::Idle, 0);
+        self.shielding = false;
+        self.shield_health = self.stats.max_shield_health();
+        self.grabbed_player = None;
+        self.wants_to_throw = None;
+        self.held_by = None;
+        self.refresh_hitboxes();
+    }
+    /// Where and which way to fire a projectile from, if one was fired since the last call.
+    pub fn take_fire_request(&mut self) -> Option<(Attack, na::Vector2<f32>, HorizontalStance)> {
+        let attack = self.wants_to_fire.take()?;
+        // Just outside the edge of the first hitbox being faced.
+        let bbox = &self.bboxes[0];
+        let x = match self.stance.1 {
+            HorizontalStance::Left => bbox.pos[0],
+            HorizontalStance::Right => bbox.pos[0] + bbox.size[0],
+        };
+        let y = bbox.pos[1] + bbox.size[1] / 2.;
+        Some((attack, self.position + na::Vector2::new(x, y), self.stance.1.clone()))
+    }
+    /// Whether a jump was started since the last call.
+    pub fn take_jumped(&mut self) -> bool {
+        std::mem::replace(&mut self.jumped, false)
+    }
+    /// The sounds made since the last call.
+    pub fn take_sfx(&mut self) -> Vec<SoundEvent> {
+        std::mem::take(&mut self.sfx)
+    }
+    pub fn is_in_hitstun(&self) -> bool {
+        self.hitstun_frames > 0
+    }
+    pub fn is_ignoring_platform(&self, platform: usize) -> bool {
+        self.platforms_to_ignore.contains(&platform)
+    }
+    pub fn velocity(&self) -> na::Vector2<f32> {
+        self.velocity
+    }
+    /// How far a moving platform carried the player on the last tick.
+    pub fn carried(&self) -> na::Vector2<f32> {
+        self.carried
+    }
     pub fn handle_push(&mut self, dir: na::Vector2<f32>) {
         self.acceleration += dir;
     }
 }
 
-/// A `Player` to be used for testing.
-pub fn test_player(ctx: &mut Context) -> WalpurgisResult<Player> {
-    let torso = Image::from_rgba8(
-        ctx, 1 /* width */, 2 /* height */,
-        &[
-            255, 0, 0, 0,
-            0, 255, 0, 0,
-        ]
-    )?;
-    let bboxes = vec![
-        BoundingBox {
-            mode: None,
-            pos: na::Vector2::new(0_f32, 0_f32),
-            size: na::Vector2::new(30_f32, 30_f32),
-            ori: 0_f32,
-        },
-    ];
+/// A `Player` to be used for testing, without any assets. Doesn't need a `Context`.
+#[cfg(test)]
+pub fn bare_test_player() -> Player {
+    bare_test_player_at(na::Vector2::new(100_f32, 0_f32))
+}
 
-    Ok(Player {
-        mode: None,
-        sprites: vec![
-            torso,
-        ],
-        sfx: vec![],
-
-        position: na::Vector2::new(100_f32, 0_f32),
-        velocity: na::Vector2::new(0_f32, 0_f32),
-        acceleration: na::Vector2::new(0_f32, 0_f32),
-        bboxes,
-
-        buff: vec![],
-        stance: (
-            VerticalStance::OnGround(GroundStance::Standing),
-            HorizontalStance::Left,
-        ),
-        movement: (Action::Idle, 0),
-
-        race: Race::Alien,
-        stats: Stats {},
-        abilities: vec![],
-        inputs: InputScheme::default(),
-
-        platforms_to_ignore: vec![],
-        touched_platforms: vec![],
-    })
+/// A `bare_test_player` spawned at `position`.
+#[cfg(test)]
+pub fn bare_test_player_at(position: na::Vector2<f32>) -> Player {
+    Player::without_sprites(&test_blueprint(Race::Alien, position), InputScheme::default())
+}
+
+/// The blueprint of a 30x30 `race` character without any assets, spawned at `position`.
+#[cfg(test)]
+pub fn test_blueprint(race: Race, position: na::Vector2<f32>) -> PlayerBlueprint {
+    PlayerBlueprint {
+        name: "Test".to_owned(),
+        stats: Some(Stats::for_race(&race)),
+        spawn_position: position,
+        ..PlayerBlueprint::placeholder(race)
+    }
+}
+
+/// `bboxes` with each `Hurt` box covered by a shield.
+fn with_shield(bboxes: &[BoundingBox]) -> Vec<BoundingBox> {
+    bboxes.iter()
+        .map(|bbox| match bbox.tag {
+            HitboxTag::Hurt => BoundingBox {
+                tag: HitboxTag::Shield,
+                ..bbox.clone()
+            },
+            _ => bbox.clone(),
+        })
+        .collect()
+}
+
+/// Every action that puts a box out in front of the player.
+fn melee_attacks() -> Vec<Action> {
+    let classes = [BasicClass::Light, BasicClass::Air, BasicClass::Heavy];
+    let dirs = [AttackDir::Up, AttackDir::Down, AttackDir::Side];
+    classes.iter()
+        .flat_map(|class| dirs.iter().map(move |dir| Action::Attack(Attack::Basics(class.clone(), dir.clone()))))
+        .chain(vec![Action::Attack(Attack::DashAttack), Action::Grab])
+        .collect()
+}
+
+/// An attack's box with the given tag, reaching out to the left of `body`.
+fn attack_box(body: &BoundingBox, tag: HitboxTag) -> BoundingBox {
+    BoundingBox {
+        mode: None,
+        tag,
+        pos: na::Vector2::new(body.pos[0] - ATTACK_REACH, body.pos[1]),
+        size: na::Vector2::new(ATTACK_REACH, body.size[1]),
+        ori: 0.,
+    }
+}
+
+/// An attack's box moved further out in front, by `DASH_ATTACK_LUNGE`.
+fn lunging(bbox: BoundingBox) -> BoundingBox {
+    BoundingBox {
+        pos: na::Vector2::new(bbox.pos[0] - DASH_ATTACK_LUNGE, bbox.pos[1]),
+        ..bbox
+    }
+}
+
+/// `bbox` flipped to the other side of `body`.
+fn mirrored(bbox: &BoundingBox, body: &BoundingBox) -> BoundingBox {
+    let x = 2. * body.pos[0] + body.size[0] - bbox.pos[0] - bbox.size[0];
+    BoundingBox {
+        pos: na::Vector2::new(x, bbox.pos[1]),
+        ..bbox.clone()
+    }
+}
+
+#[cfg(test)]
+mod player_test {
+    use super::*;
+    use crate::screens::battle::platform::Platform;
+    use crate::skill_tree::{SkillTreeError, StatField};
+    use super::race::mage;
+
+    const GRAVITY: f32 = 0.01;
+
+    fn airborne_player() -> Player {
+        let mut player = bare_test_player();
+        player.stance.0 = VerticalStance::InAir {
+            jumps_spent: 0,
+            stance: AirStance::Falling,
+        };
+        player.velocity = na::Vector2::new(0., 1.);
+        player
+    }
+
+    fn tick(player: &mut Player) {
+        player.apply_changeset(Changes {
+            force: na::Vector2::new(0., GRAVITY),
+            ..Default::default()
+        });
+        player.handle_phys_update();
+    }
+
+    /// The number of ticks until the player passes `y`.
+    fn ticks_to_reach(player: &mut Player, y: f32) -> u32 {
+        let mut ticks = 0;
+        while player.position[1] < y {
+            tick(player);
+            ticks += 1;
+        }
+        ticks
+    }
+
+    /// A platform just under the feet of a `bare_test_player`.
+    fn platform_below() -> Platform {
+        Platform::new(BoundingBox {
+            mode: None,
+            tag: HitboxTag::Solid,
+            pos: na::Vector2::new(50., 30.),
+            size: na::Vector2::new(200., 10.),
+            ori: 0.,
+        }, true)
+    }
+
+    fn tick_with_platforms(player: &mut Player, platforms: &[Platform]) {
+        let gravity = Changes {
+            force: na::Vector2::new(0., GRAVITY),
+            ..Default::default()
+        };
+        let changes = check_for_swept_collision_pairs(std::slice::from_ref(player), platforms)
+            .into_iter()
+            .filter_map(|c| interactions::handle_player_platform_collision(c).0)
+            .fold(gravity, |acc, changes| acc.merge(&changes));
+        player.apply_changeset(changes);
+        player.handle_phys_update();
+    }
+
+    #[test]
+    fn unlocking_skills_changes_stats() {
+        let mut player = bare_test_player();
+        let run_speed = player.stats.run_speed();
+        assert!(player.unlock_skill(0) == Err(SkillTreeError::NotEnoughPoints { id: 0, cost: 1, available: 0 }));
+        player.skill_tree.skill_points = 1;
+        player.unlock_skill(0).unwrap();
+        assert!((player.stats.run_speed() - (run_speed + 0.3)).abs() < 1e-6);
+        assert!(player.skill_tree().skill_points == 0);
+        // Skills that don't change stats leave them alone.
+        let stats = player.stats.clone();
+        player.skill_tree.skill_points = 5;
+        player.unlock_skill(1).unwrap();
+        player.unlock_skill(2).unwrap();
+        assert!(player.stats.get(StatField::RunSpeed) == stats.get(StatField::RunSpeed));
+        assert!(player.stats.dash_speed() > stats.dash_speed());
+    }
+
+    #[test]
+    fn stands_on_platform() {
+        let mut player = bare_test_player();
+        let platforms = [platform_below()];
+        for _ in 0..300 {
+            tick_with_platforms(&mut player, &platforms);
+        }
+        assert!(is_flush(&player, &platforms[0]));
+        match player.stance.0 {
+            VerticalStance::OnGround(_) => (),
+            _ => panic!("Expected to be on the ground."),
+        }
+    }
+
+    #[test]
+    fn drops_through_platform() {
+        let mut player = bare_test_player();
+        let platforms = [platform_below()];
+        tick_with_platforms(&mut player, &platforms);
+        player.handle_actions(vec![Action::DropThrough]);
+        for _ in 0..300 {
+            tick_with_platforms(&mut player, &platforms);
+        }
+        // Fully below the platform.
+        assert!(player.position[1] > 40.);
+        assert!(player.platforms_to_ignore.is_empty());
+    }
+
+    #[test]
+    fn does_not_drop_through_solid_platform() {
+        let mut player = bare_test_player();
+        let mut solid = platform_below();
+        solid.can_move_through = false;
+        let platforms = [solid];
+        tick_with_platforms(&mut player, &platforms);
+        player.handle_actions(vec![Action::DropThrough]);
+        for _ in 0..300 {
+            tick_with_platforms(&mut player, &platforms);
+        }
+        assert!(is_flush(&player, &platforms[0]));
+        assert!(player.platforms_to_ignore.is_empty());
+    }
+
+    #[test]
+    fn lands_on_platform_after_dropping_through() {
+        let mut player = bare_test_player();
+        let mut lower = platform_below();
+        lower.body.pos[1] = 200.;
+        let platforms = [platform_below(), lower];
+        tick_with_platforms(&mut player, &platforms);
+        player.handle_actions(vec![Action::DropThrough]);
+        for _ in 0..1000 {
+            tick_with_platforms(&mut player, &platforms);
+        }
+        assert!(is_flush(&player, &platforms[1]));
+    }
+
+    /// The bottom of the player is level with the top of `platform`.
+    fn is_flush(player: &Player, platform: &Platform) -> bool {
+        const EPSILON: f32 = 1e-3;
+        let bottom = player.position[1] + player.bboxes[0].size[1];
+        (bottom - platform.body.pos[1]).abs() < EPSILON
+    }
+
+    #[test]
+    fn lands_flush() {
+        let mut player = airborne_player();
+        player.position[1] = -100.;
+        let platforms = [platform_below()];
+        for _ in 0..300 {
+            tick_with_platforms(&mut player, &platforms);
+        }
+        assert!(is_flush(&player, &platforms[0]));
+        assert!(player.velocity[1] == 0.);
+    }
+
+    #[test]
+    fn lands_flush_when_deep_in_platform() {
+        // Moved almost all the way through the platform last tick.
+        let mut player = airborne_player();
+        player.velocity[1] = 9.;
+        player.position[1] = 8.;
+        let platforms = [platform_below()];
+        tick_with_platforms(&mut player, &platforms);
+        assert!(is_flush(&player, &platforms[0]));
+        match player.stance.0 {
+            VerticalStance::OnGround(_) => (),
+            _ => panic!("Expected to have landed."),
+        }
+    }
+
+    #[test]
+    fn lands_flush_on_corner() {
+        let mut player = airborne_player();
+        // Only the bottom left corner of the player overlaps the platform.
+        player.position = na::Vector2::new(245., 0.5);
+        let platforms = [platform_below()];
+        tick_with_platforms(&mut player, &platforms);
+        assert!(is_flush(&player, &platforms[0]));
+        assert!(player.position[0] == 245.);
+    }
+
+    #[test]
+    fn lands_on_thin_platform_at_high_speed() {
+        let mut thin = platform_below();
+        thin.body.size[1] = 1.;
+        let platforms = [thin];
+        let mut player = airborne_player();
+        player.position[1] = -20.;
+        // Would skip straight over the platform in a single tick.
+        player.velocity[1] = 50.;
+        tick_with_platforms(&mut player, &platforms);
+        assert!(is_flush(&player, &platforms[0]));
+        assert!(player.velocity[1] == 0.);
+    }
+
+    #[test]
+    fn bumps_head_on_solid_platform() {
+        let mut solid = platform_below();
+        solid.can_move_through = false;
+        let platforms = [solid];
+        let mut player = airborne_player();
+        player.velocity[1] = -2.;
+        // The top of the player is 1 into the bottom of the platform.
+        player.position[1] = 39.;
+        tick_with_platforms(&mut player, &platforms);
+        let top = player.position[1] - player.velocity[1];
+        assert!((top - 40.).abs() < 1e-3);
+    }
+
+    #[test]
+    fn jumps_through_passable_platform() {
+        let platforms = [platform_below()];
+        let mut player = airborne_player();
+        player.velocity[1] = -2.;
+        player.position[1] = 39.;
+        tick_with_platforms(&mut player, &platforms);
+        assert!(player.position[1] < 39.);
+        assert!(player.position[1] > 36.);
+    }
+
+    #[test]
+    fn fast_fall_is_faster() {
+        let mut falling = airborne_player();
+        let mut fast_falling = airborne_player();
+        fast_falling.fast_fall();
+
+        let target = falling.position[1] + 300.;
+        let falling_ticks = ticks_to_reach(&mut falling, target);
+        let fast_falling_ticks = ticks_to_reach(&mut fast_falling, target);
+        assert!(fast_falling_ticks < falling_ticks);
+        assert!(fast_falling.velocity[1] > falling.velocity[1]);
+    }
+
+    #[test]
+    fn fast_fall_multiplies_velocity() {
+        let mut player = airborne_player();
+        player.fast_fall();
+        assert!(player.velocity[1] == player.stats.fast_fall_multiplier());
+        match player.stance.0 {
+            VerticalStance::InAir { stance: AirStance::FastFalling, .. } => (),
+            _ => panic!("Expected to be fast falling."),
+        }
+    }
+
+    #[test]
+    fn fast_fall_accelerates() {
+        let mut player = airborne_player();
+        player.fast_fall();
+        let before = player.velocity[1];
+        tick(&mut player);
+        let expected = before + GRAVITY + player.stats.fast_fall_acceleration();
+        assert!((player.velocity[1] - expected).abs() < 1e-5);
+    }
+
+    #[test]
+    fn falling_levels_off_at_fall_speed() {
+        let mut player = airborne_player();
+        for _ in 0..1000 {
+            tick(&mut player);
+            assert!(player.velocity[1] <= player.stats.fall_speed());
+        }
+        assert!(player.velocity[1] == player.stats.fall_speed());
+        let before = player.position[1];
+        tick(&mut player);
+        assert!((player.position[1] - before - player.stats.fall_speed()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn fast_fall_levels_off_higher() {
+        let mut falling = airborne_player();
+        let mut fast_falling = airborne_player();
+        fast_falling.fast_fall();
+        for _ in 0..1000 {
+            tick(&mut falling);
+            tick(&mut fast_falling);
+            assert!(fast_falling.velocity[1] <= fast_falling.stats.fast_fall_speed());
+        }
+        assert!(falling.velocity[1] == falling.stats.fall_speed());
+        assert!(fast_falling.velocity[1] == fast_falling.stats.fast_fall_speed());
+        assert!(fast_falling.stats.fast_fall_speed() > falling.stats.fall_speed());
+    }
+
+    #[test]
+    fn dash_velocity() {
+        let mut player = bare_test_player();
+        let platforms = [platform_below()];
+        tick_with_platforms(&mut player, &platforms);
+        player.handle_actions(vec![Action::Dash(HorizontalStance::Right)]);
+        for _ in 1..player.stats.dash_duration() {
+            tick_with_platforms(&mut player, &platforms);
+            assert!(player.velocity[0] == player.stats.dash_speed());
+        }
+        tick_with_platforms(&mut player, &platforms);
+        assert!(player.velocity[0] == 0.);
+        match player.stance.0 {
+            VerticalStance::OnGround(GroundStance::Standing) => (),
+            _ => panic!("Expected to be standing after the dash."),
+        }
+    }
+
+    #[test]
+    fn dash_cooldown() {
+        let mut player = bare_test_player();
+        let platforms = [platform_below()];
+        tick_with_platforms(&mut player, &platforms);
+        player.handle_actions(vec![Action::Dash(HorizontalStance::Left)]);
+        let mut ticks = 0;
+        while player.is_dashing() {
+            tick_with_platforms(&mut player, &platforms);
+            ticks += 1;
+        }
+        // Standing again, but too soon to dash.
+        player.handle_actions(vec![Action::Dash(HorizontalStance::Right)]);
+        assert!(!player.is_dashing() && player.velocity[0] == 0.);
+        for _ in ticks..player.stats.dash_cooldown() {
+            tick_with_platforms(&mut player, &platforms);
+        }
+        player.handle_actions(vec![Action::Dash(HorizontalStance::Right)]);
+        assert!(player.is_dashing() && player.velocity[0] == player.stats.dash_speed());
+    }
+
+    #[test]
+    fn dash_attack_only_while_dashing() {
+        let mut player = bare_test_player();
+        let platforms = [platform_below()];
+        tick_with_platforms(&mut player, &platforms);
+        // Only made by attacking during a dash.
+        player.handle_actions(vec![Action::Attack(Attack::DashAttack)]);
+        assert!(player.movement.0 == Action::Idle);
+        player.handle_actions(vec![Action::Dash(HorizontalStance::Left)]);
+        tick_with_platforms(&mut player, &platforms);
+        player.handle_actions(light_attack());
+        assert!(player.movement == (Action::Attack(Attack::DashAttack), 0));
+        let data = Attack::DashAttack.frame_data().unwrap();
+        for _ in 0..data.startup {
+            tick_with_platforms(&mut player, &platforms);
+        }
+        // Out further ahead than a light attack would be.
+        let reach = player.attack_boxes_out()[0].pos[0] - player.bboxes[0].pos[0];
+        assert!(reach == -(ATTACK_REACH + DASH_ATTACK_LUNGE));
+        assert!(player.strike(0).unwrap().attack == Attack::DashAttack);
+        // Once the dash is over, attacking is attacking.
+        for _ in 0..100 {
+            tick_with_platforms(&mut player, &platforms);
+        }
+        assert!(!player.is_dashing() && player.attack_phase().is_none());
+        player.handle_actions(light_attack());
+        assert!(player.movement.0 == light_attack()[0]);
+    }
+
+    #[test]
+    fn dashing_off_ledge_keeps_momentum() {
+        let mut player = bare_test_player();
+        let mut ledge = platform_below();
+        ledge.body.size[0] = 100.;
+        let platforms = [ledge];
+        tick_with_platforms(&mut player, &platforms);
+        player.handle_actions(vec![Action::Dash(HorizontalStance::Right)]);
+        for _ in 0..60 {
+            tick_with_platforms(&mut player, &platforms);
+        }
+        match player.stance.0 {
+            VerticalStance::InAir { .. } => (),
+            _ => panic!("Expected to have dashed off the ledge."),
+        }
+        assert!(player.velocity[0] == player.stats.dash_speed());
+        assert!(player.position[0] > 150.);
+    }
+
+    #[test]
+    fn air_drift_accelerates_up_to_air_speed() {
+        let mut player = airborne_player();
+        let facing = player.stance.1.clone();
+        player.handle_actions(vec![Action::Walk(HorizontalStance::Right)]);
+        // Drifting speeds up rather than moving the player directly.
+        assert!(player.position[0] == 100.);
+        tick(&mut player);
+        assert!((player.velocity[0] - player.stats.air_acceleration()).abs() < 1e-6);
+        assert!(player.stance.1 == facing);
+        for _ in 0..1000 {
+            player.handle_actions(vec![Action::Walk(HorizontalStance::Right)]);
+            tick(&mut player);
+            assert!(player.velocity[0] <= player.stats.air_speed() + 1e-6);
+        }
+        assert!((player.velocity[0] - player.stats.air_speed()).abs() < 1e-6);
+        // Drifting back slows down.
+        player.handle_actions(vec![Action::Walk(HorizontalStance::Left)]);
+        tick(&mut player);
+        assert!(player.velocity[0] < player.stats.air_speed());
+    }
+
+    #[test]
+    fn landing_keeps_momentum_up_to_run_speed() {
+        for (speed, kept) in &[(1., 1.), (-5., -3.), (5., 3.)] {
+            let mut player = airborne_player();
+            player.velocity[0] = *speed;
+            player.apply_changeset(Changes {
+                contacted_platforms: vec![0],
+                ..Default::default()
+            });
+            match player.stance.0 {
+                VerticalStance::OnGround(_) => (),
+                _ => panic!("Expected to have landed."),
+            }
+            assert!(player.velocity[0] == *kept);
+        }
+    }
+
+    #[test]
+    fn dash_is_capped() {
+        let mut player = bare_test_player();
+        player.velocity[0] = -3.;
+        player.handle_actions(vec![Action::Dash(HorizontalStance::Left)]);
+        assert!(player.velocity[0] == -player.stats.dash_speed());
+        assert!(player.stance.1 == HorizontalStance::Left);
+    }
+
+    #[test]
+    fn dash_intangibility() {
+        let mut player = bare_test_player();
+        let platforms = [platform_below()];
+        tick_with_platforms(&mut player, &platforms);
+        player.handle_actions(vec![Action::Dash(HorizontalStance::Left)]);
+        for _ in 0..player.stats.dash_intangible_frames() {
+            assert!(player.get_hitboxes().is_empty());
+            tick_with_platforms(&mut player, &platforms);
+        }
+        assert!(!player.get_hitboxes().is_empty());
+        // Didn't sink through the platform while intangible.
+        for _ in 0..100 {
+            tick_with_platforms(&mut player, &platforms);
+        }
+        assert!(player.position[1] < 5.);
+    }
+
+    #[test]
+    fn no_dash_in_air() {
+        let mut player = airborne_player();
+        player.handle_actions(vec![Action::Dash(HorizontalStance::Right)]);
+        assert!(player.velocity[0] == 0.);
+        assert!(player.get_hitboxes().len() == 2);
+    }
+
+    /// Two players overlapping side by side.
+    fn touching_players() -> [Player; 2] {
+        let mut right = bare_test_player();
+        right.position[0] += 20.;
+        [bare_test_player(), right]
+    }
+
+    /// The changes to each of two players from colliding, in the order the players were given.
+    fn collide(players: &[Player; 2]) -> (Option<Changes>, Option<Changes>) {
+        let c = check_for_collisions(players).pop().unwrap();
+        let flipped = c.ids.0 != 0;
+        let (changes0, changes1) = interactions::handle_player_player_collision(c);
+        if flipped { (changes1, changes0) } else { (changes0, changes1) }
+    }
+
+    fn light_attack() -> Vec<Action> {
+        vec![Action::Attack(Attack::Basics(BasicClass::Light, AttackDir::Side))]
+    }
+
+    /// Starts a light attack and skips straight to its active frames.
+    fn strike_now(player: &mut Player) {
+        player.handle_actions(light_attack());
+        player.movement.1 = BasicClass::Light.frame_data().startup;
+        player.refresh_hitboxes();
+    }
+
+    #[test]
+    fn players_push_each_other_apart() {
+        let players = touching_players();
+        let (left, right) = collide(&players);
+        let (left, right) = (left.unwrap(), right.unwrap());
+        assert!(left.force[0] < 0.);
+        assert!(right.force[0] > 0.);
+        assert!((left.force + right.force).norm() < 1e-5);
+        // Touching isn't a hit.
+        assert!(left.hitstun == 0 && right.hitstun == 0);
+        assert!(left.effects.is_empty() && right.effects.is_empty());
+    }
+
+    #[test]
+    fn only_the_struck_player_is_hurt() {
+        let mut players = touching_players();
+        players[0].stance.1 = HorizontalStance::Right;
+        strike_now(&mut players[0]);
+        let (attacker, defender) = collide(&players);
+        let (attacker, defender) = (attacker.unwrap(), defender.unwrap());
+        assert!(attacker.hitstun == 0 && attacker.effects.is_empty());
+        assert!(defender.hitstun > 0);
+        let struck = Effect::Struck { owner: 0, instance: 1 };
+        assert!(defender.effects == vec![Effect::Damage(BasicClass::Light.frame_data().damage), struck]);
+
+        let c = check_for_collisions(&players).pop().unwrap();
+        let landed = interactions::landed_strikes(&c);
+        assert!(landed == vec![(0, 1)]);
+    }
+
+    #[test]
+    fn each_swing_hits_once() {
+        let mut players = touching_players();
+        players[0].stance.1 = HorizontalStance::Right;
+        strike_now(&mut players[0]);
+        let (_, defender) = collide(&players);
+        players[1].apply_changeset(defender.unwrap());
+        // Still overlapping once the hitstun is over, but the swing already hit.
+        players[1].hitstun_frames = 0;
+        let (_, defender) = collide(&players);
+        assert!(defender.unwrap().effects.is_empty());
+        let c = check_for_collisions(&players).pop().unwrap();
+        assert!(interactions::landed_strikes(&c).is_empty());
+        // The next swing hits again.
+        players[0].movement = (Action::Idle, 0);
+        strike_now(&mut players[0]);
+        let (_, defender) = collide(&players);
+        assert!(defender.unwrap().effects.contains(&Effect::Struck { owner: 0, instance: 2 }));
+    }
+
+    #[test]
+    fn attacks_facing_away_miss() {
+        let mut players = touching_players();
+        // Facing left, away from the other player.
+        strike_now(&mut players[0]);
+        let (attacker, defender) = collide(&players);
+        assert!(attacker.unwrap().effects.is_empty());
+        assert!(defender.unwrap().effects.is_empty());
+    }
+
+    #[test]
+    fn knockback_grows_with_damage() {
+        let mut player = bare_test_player();
+        let source = player.position - na::Vector2::new(10., 0.);
+        let fresh = player.knockback_from(source);
+        assert!(fresh == na::Vector2::new(player.stats.knockback_base(), 0.));
+        player.stats.set(StatField::DamagePct, 100.);
+        assert!(player.knockback_from(source).norm() > fresh.norm());
+        player.handicap.knockback_taken_multiplier = 0.;
+        assert!(player.knockback_from(source).norm() == 0.);
+    }
+
+    #[test]
+    fn heavier_players_fly_less() {
+        let mut player = bare_test_player();
+        let source = player.position - na::Vector2::new(10., 0.);
+        let light = player.knockback_from_attack(source, 2.);
+        assert!(light == na::Vector2::new(2., 0.));
+        player.stats.set(StatField::Weight, 2.);
+        assert!(player.knockback_from_attack(source, 2.) == light / 2.);
+    }
+
+    fn attack_hitbox(player: &Player) -> Option<&BoundingBox> {
+        player.get_hitboxes().iter().find(|hb| hb.tag == HitboxTag::Hit)
+    }
+
+    #[test]
+    fn light_attack_strikes_in_front() {
+        let mut player = bare_test_player();
+        assert!(player.strike(0).is_none());
+        assert!(attack_hitbox(&player).is_none());
+        strike_now(&mut player);
+        let strike = player.strike(0).unwrap();
+        let hitbox = attack_hitbox(&player).unwrap();
+        // Facing left, so the hitbox ends where the player starts.
+        assert!(hitbox.pos[0] + hitbox.size[0] == 0.);
+        assert!(strike.damage == BasicClass::Light.frame_data().damage);
+        player.stance.1 = HorizontalStance::Right;
+        player.refresh_hitboxes();
+        assert!(attack_hitbox(&player).unwrap().pos[0] == 30.);
+    }
+
+    /// The ticks of an attack started on tick 0 on which its hitbox is out.
+    fn active_ticks(class: BasicClass) -> Vec<FrameNumber> {
+        let mut player = bare_test_player();
+        player.handle_actions(vec![Action::Attack(Attack::Basics(class.clone(), AttackDir::Side))]);
+        let mut ticks = vec![];
+        for tick in 0..=class.frame_data().total() {
+            if attack_hitbox(&player).is_some() {
+                assert!(player.strike(0).is_some());
+                ticks.push(tick);
+            }
+            player.handle_phys_update();
+        }
+        assert!(player.movement == (Action::Idle, 0));
+        ticks
+    }
+
+    #[test]
+    fn hitbox_only_out_on_active_frames() {
+        // Light: 3 startup, 3 active.
+        assert!(active_ticks(BasicClass::Light) == vec![3, 4, 5]);
+        // Heavy: 12 startup, 5 active.
+        assert!(active_ticks(BasicClass::Heavy) == vec![12, 13, 14, 15, 16]);
+    }
+
+    #[test]
+    fn boxes_only_out_in_their_windows() {
+        let mut player = bare_test_player();
+        let attack = light_attack()[0].clone();
+        let early = attack_box(&player.bboxes[0], HitboxTag::Hit);
+        let late = attack_box(&player.bboxes[0], HitboxTag::Grab);
+        player.attack_bboxes = vec![(attack.clone(), vec![
+            (early, HitboxWindow { start_frame: 1, end_frame: 3 }),
+            (late, HitboxWindow { start_frame: 2, end_frame: 5 }),
+        ])];
+        player.handle_actions(vec![attack]);
+        let mut out = vec![];
+        for _ in 0..6 {
+            out.push(player.get_hitboxes().len() - player.bboxes.len());
+            player.handle_phys_update();
+        }
+        assert!(out == vec![0, 1, 2, 1, 1, 0]);
+    }
+
+    #[test]
+    fn attacks_ignored_until_recovered() {
+        let mut player = bare_test_player();
+        let heavy = vec![Action::Attack(Attack::Basics(BasicClass::Heavy, AttackDir::Side))];
+        player.handle_actions(light_attack());
+        for _ in 0..BasicClass::Light.frame_data().total() {
+            player.handle_actions(heavy.clone());
+            assert!(player.movement.0 == light_attack()[0]);
+            player.handle_phys_update();
+        }
+        player.handle_actions(heavy.clone());
+        assert!(player.movement == (heavy[0].clone(), 0));
+    }
+
+    #[test]
+    fn getting_hit_interrupts_attacks() {
+        let mut player = bare_test_player();
+        strike_now(&mut player);
+        player.apply_changeset(Changes {
+            hitstun: 5,
+            ..Default::default()
+        });
+        assert!(player.strike(0).is_none());
+        assert!(player.movement == (Action::Idle, 0));
+    }
+
+    #[test]
+    fn simultaneous_strikes_merge() {
+        let mut left = bare_test_player_at(na::Vector2::new(55., 0.));
+        left.stance.1 = HorizontalStance::Right;
+        strike_now(&mut left);
+        let mut right = bare_test_player_at(na::Vector2::new(145., 0.));
+        strike_now(&mut right);
+        let mut players = [left, bare_test_player(), right];
+
+        let changes = check_for_collisions(&players)
+            .into_iter()
+            .filter_map(|c| match c.ids {
+                (1, _) => interactions::handle_player_player_collision(c).0,
+                (_, 1) => interactions::handle_player_player_collision(c).1,
+                _ => None,
+            })
+            .fold(Changes::default(), |acc, changes| acc.merge(&changes));
+        assert!(changes.hitstun > 0);
+        // Hit equally hard from both sides.
+        assert!(changes.force.norm() < 1e-5);
+        players[1].apply_changeset(changes);
+        assert!(players[1].damage_pct() == 2. * BasicClass::Light.frame_data().damage);
+        assert!(players[0].damage_pct() == 0. && players[2].damage_pct() == 0.);
+    }
+
+    /// A player standing just in front of another's light attack, and their changes from it.
+    fn struck_player(shielding: bool) -> (Player, Changes) {
+        let mut attacker = bare_test_player_at(na::Vector2::new(130., 0.));
+        strike_now(&mut attacker);
+        let mut defender = bare_test_player();
+        if shielding {
+            defender.handle_actions(vec![Action::Shield]);
+        }
+        let players = [defender, attacker];
+        let changes = collide(&players).0.unwrap();
+        let [defender, _] = players;
+        (defender, changes)
+    }
+
+    /// Two touching players, the left one facing the other with its grab out.
+    fn grabbing_players() -> [Player; 2] {
+        let mut players = touching_players();
+        players[0].stance.1 = HorizontalStance::Right;
+        players[0].handle_actions(vec![Action::Grab]);
+        players[0].movement.1 = Action::Grab.frame_data().unwrap().startup;
+        players[0].refresh_hitboxes();
+        players
+    }
+
+    /// Holds on to whoever the first player's grab catches.
+    fn grab(players: &mut [Player; 2]) -> Option<(usize, usize)> {
+        let c = check_for_collisions(&players[..]).pop().unwrap();
+        let grab = interactions::handle_player_grab_collision(&c);
+        if let Some((grabber, grabbed)) = grab {
+            players[grabber].hold(grabbed);
+            players[grabbed].be_held(grabber);
+        }
+        grab
+    }
+
+    #[test]
+    fn grabs_hold_then_throw() {
+        let mut players = grabbing_players();
+        assert!(players[0].get_hitboxes().iter().any(|hb| hb.tag == HitboxTag::Grab));
+        assert!(players[0].strike(0).is_none());
+        // Grabbing alone doesn't hurt.
+        let (_, grabbed) = collide(&players);
+        assert!(grabbed.iter().all(|changes| changes.effects.is_empty()));
+
+        assert!(grab(&mut players) == Some((0, 1)));
+        assert!(players[0].grabbed_player() == Some(1));
+        assert!(players[1].held_by() == Some(0));
+        // Held players can't do anything.
+        let position = players[1].get_offset();
+        players[1].handle_actions(vec![Action::Walk(HorizontalStance::Right), Action::Shield]);
+        assert!(players[1].get_offset() == position && !players[1].shielding);
+
+        players[0].handle_actions(vec![Action::Throw(AttackDir::Up)]);
+        assert!(players[0].take_throw_request() == Some((1, AttackDir::Up)));
+        assert!(players[0].grabbed_player().is_none());
+        let thrown = interactions::throw(&players[0], &players[1], &AttackDir::Up).unwrap();
+        assert!(thrown.effects == vec![Effect::Damage(Action::Grab.frame_data().unwrap().damage)]);
+        assert!(thrown.force[0] == 0. && thrown.force[1] < 0.);
+    }
+
+    #[test]
+    fn throws_only_while_holding() {
+        let mut player = bare_test_player();
+        player.handle_actions(vec![Action::Throw(AttackDir::Side)]);
+        assert!(player.take_throw_request().is_none());
+    }
+
+    #[test]
+    fn side_throws_go_forward() {
+        let players = grabbing_players();
+        let forward = players[0].throw_direction(&AttackDir::Side);
+        assert!(forward[0] > 0. && forward[1] < 0.);
+        assert!(players[1].throw_direction(&AttackDir::Side)[0] < 0.);
+    }
+
+    #[test]
+    fn grabs_go_through_shields() {
+        let mut players = grabbing_players();
+        players[1].handle_actions(vec![Action::Shield]);
+        assert!(grab(&mut players) == Some((0, 1)));
+        assert!(!players[1].shielding);
+    }
+
+    #[test]
+    fn held_players_break_free() {
+        let mut players = grabbing_players();
+        grab(&mut players);
+        for _ in 0..GRAB_HOLD_FRAMES {
+            assert!(players[0].grabbed_player() == Some(1));
+            players[0].handle_phys_update();
+        }
+        assert!(players[0].grabbed_player().is_none());
+        players[0].handle_actions(vec![Action::Throw(AttackDir::Side)]);
+        assert!(players[0].take_throw_request().is_none());
+    }
+
+    #[test]
+    fn shield_blocks_all_but_chip_damage() {
+        let (mut defender, changes) = struck_player(true);
+        assert!(matches!(defender.stance.0, VerticalStance::OnGround(GroundStance::Attack(Attack::Shielding))));
+        assert!(changes.force == na::Vector2::zeros() && changes.hitstun == 0);
+        defender.apply_changeset(changes);
+        let damage = BasicClass::Light.frame_data().damage;
+        assert!((defender.damage_pct() - damage * interactions::SHIELD_CHIP_FRACTION).abs() < 1e-6);
+        assert!(defender.shield_health == defender.stats.max_shield_health() - damage);
+        assert!(defender.shielding);
+        // Walking is ignored behind the shield.
+        let x = defender.position[0];
+        defender.handle_actions(vec![Action::Walk(HorizontalStance::Right), Action::Shield]);
+        assert!(defender.position[0] == x);
+        let (mut unshielded, changes) = struck_player(false);
+        unshielded.apply_changeset(changes);
+        assert!(unshielded.damage_pct() == damage);
+    }
+
+    #[test]
+    fn shield_break_stuns() {
+        let (mut defender, changes) = struck_player(true);
+        defender.shield_health = 1.;
+        defender.apply_changeset(Changes {
+            hitstun: 0,
+            contacted_platforms: vec![0],
+            ..changes
+        });
+        assert!(!defender.shielding);
+        assert!(matches!(defender.stance.0, VerticalStance::OnGround(GroundStance::Standing)));
+        assert!(defender.get_hitboxes().iter().all(|hb| hb.tag != HitboxTag::Shield));
+        defender.handle_phys_update();
+        let x = defender.position[0];
+        for _ in 0..defender.stats.shield_break_stun_frames() {
+            assert!(defender.is_stunned());
+            defender.handle_actions(vec![Action::Walk(HorizontalStance::Right), Action::Shield]);
+            assert!(defender.position[0] == x);
+            assert!(!defender.shielding);
+            defender.handle_phys_update();
+        }
+        assert!(!defender.is_stunned());
+        defender.handle_actions(vec![Action::Walk(HorizontalStance::Right)]);
+        assert!(defender.position[0] == x + 2.);
+    }
+
+    #[test]
+    fn shield_drains_while_up_and_regenerates_when_lowered() {
+        let mut player = bare_test_player();
+        let full = player.stats.max_shield_health();
+        player.handle_actions(vec![Action::Shield]);
+        assert!(player.get_hitboxes().iter().any(|hb| hb.tag == HitboxTag::Shield));
+        player.handle_phys_update();
+        assert!((player.shield_health - (full - player.stats.shield_drain_rate())).abs() < 1e-4);
+        player.shield_health = 10.;
+        player.handle_actions(vec![]);
+        for _ in 0..10 {
+            player.handle_phys_update();
+        }
+        assert!((player.shield_health - (10. + 10. * player.stats.shield_regen_rate())).abs() < 1e-4);
+        for _ in 0..1000 {
+            player.handle_phys_update();
+        }
+        assert!(player.shield_health == full);
+        // Held up for long enough, the shield breaks by itself.
+        let mut ticks = 0;
+        while !player.is_stunned() {
+            player.handle_actions(vec![Action::Shield]);
+            player.handle_phys_update();
+            ticks += 1;
+        }
+        assert!((ticks as f32 - full / player.stats.shield_drain_rate()).abs() <= 1.);
+    }
+
+    #[test]
+    fn no_knockback_during_hitstun() {
+        let mut players = touching_players();
+        players[0].stance.1 = HorizontalStance::Right;
+        strike_now(&mut players[0]);
+        players[1].hitstun_frames = 3;
+        let (_, defender) = collide(&players);
+        assert!(defender.unwrap().effects.is_empty());
+        let c = check_for_collisions(&players).pop().unwrap();
+        assert!(interactions::landed_strikes(&c).is_empty());
+    }
+
+    #[test]
+    fn hitstun_blocks_walking() {
+        let mut player = bare_test_player();
+        player.apply_changeset(Changes {
+            hitstun: 5,
+            // Stays standing.
+            contacted_platforms: vec![0],
+            ..Default::default()
+        });
+        let x = player.position[0];
+        for _ in 0..5 {
+            player.handle_actions(vec![Action::Walk(HorizontalStance::Right)]);
+            assert!(player.position[0] == x);
+        }
+        assert!(!player.is_in_hitstun());
+        player.handle_actions(vec![Action::Walk(HorizontalStance::Right)]);
+        assert!(player.position[0] == x + 2.);
+    }
+
+    /// The angle from `from` to `to`, clockwise on screen.
+    fn angle_between(from: na::Vector2<f32>, to: na::Vector2<f32>) -> f32 {
+        (from[0] * to[1] - from[1] * to[0]).atan2(from.dot(&to))
+    }
+
+    #[test]
+    fn di_turns_knockback_towards_held_direction() {
+        let mut player = bare_test_player();
+        player.apply_changeset(Changes { hitstun: 10, ..Default::default() });
+        let launch = na::Vector2::new(0., -5.);
+        player.velocity = launch;
+
+        player.handle_actions(vec![Action::Walk(HorizontalStance::Right)]);
+        player.handle_phys_update();
+        assert!(player.velocity[0] > 0.);
+        assert!((player.velocity.norm() - launch.norm()).abs() < 1e-4);
+        assert!((angle_between(launch, player.velocity).abs() - DI_MAX_ANGLE).abs() < 1e-4);
+        assert!(player.is_in_hitstun());
+
+        // Holding on doesn't turn it any further.
+        let turned = player.velocity;
+        player.handle_actions(vec![Action::Walk(HorizontalStance::Right)]);
+        player.handle_phys_update();
+        assert!((player.velocity - turned).norm() < 1e-4);
+
+        // Switching sides swings it over to the other side of the launch.
+        player.handle_actions(vec![Action::Walk(HorizontalStance::Left)]);
+        player.handle_phys_update();
+        assert!(player.velocity[0] < 0.);
+        assert!((angle_between(player.velocity, launch).abs() - DI_MAX_ANGLE).abs() < 1e-4);
+
+        // Letting go keeps the trajectory.
+        let turned = player.velocity;
+        player.handle_actions(vec![]);
+        player.handle_phys_update();
+        assert!((player.velocity - turned).norm() < 1e-4);
+    }
+
+    #[test]
+    fn di_only_during_hitstun_and_never_past_held_direction() {
+        let mut player = bare_test_player();
+        player.velocity = na::Vector2::new(0., -5.);
+        player.handle_actions(vec![Action::Walk(HorizontalStance::Right)]);
+        player.handle_phys_update();
+        assert!(player.velocity == na::Vector2::new(0., -5.));
+
+        // Already going almost the held way, so it is only turned the rest of the way.
+        player.apply_changeset(Changes { hitstun: 10, ..Default::default() });
+        let launch = BoundingBox::rotate(na::Vector2::new(5., 0.), 0.1);
+        player.velocity = launch;
+        player.handle_actions(vec![Action::Walk(HorizontalStance::Right)]);
+        player.handle_phys_update();
+        assert!(player.velocity[1].abs() < 1e-4);
+        assert!((player.velocity[0] - 5.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn damage_effects() {
+        let mut player = bare_test_player();
+        player.apply_changeset(Changes {
+            effects: vec![Effect::Damage(10.)],
+            ..Default::default()
+        }.merge(&Changes {
+            effects: vec![Effect::Damage(5.)],
+            ..Default::default()
+        }));
+        assert!(player.damage_pct() == 15.);
+    }
+
+    #[test]
+    fn knock_out_respawns() {
+        let mut player = airborne_player();
+        player.stats.take_damage(50.);
+        player.position[1] = 5000.;
+        player.knock_out();
+        assert!(player.stocks() == player.stats.max_stocks() - 1);
+        assert!(player.damage_pct() == 0.);
+        assert!(player.position == player.spawn_point);
+        assert!(player.velocity == na::Vector2::zeros());
+    }
+
+    #[test]
+    fn handicap_applied_every_round() {
+        let mut player = airborne_player();
+        player.set_handicap(Handicap { stock_offset: 1, starting_damage: 50., ..Default::default() });
+        player.start_round(Some(3));
+        assert!(player.stocks() == 4 && player.damage_pct() == 50.);
+        player.knock_out();
+        assert!(player.stocks_lost() == 1 && player.damage_pct() == 0.);
+        // Rounds without a set number of stocks don't stack the handicap on the last one's.
+        player.start_round(None);
+        player.start_round(None);
+        assert!(player.stocks() == 4 && player.damage_pct() == 50.);
+    }
+
+    #[test]
+    fn respawning_is_briefly_invulnerable() {
+        let can_be_hit = |player: &Player| player.get_hitboxes().iter().any(|bbox| bbox.tag.can_be_hit());
+        let mut player = airborne_player();
+        player.platforms_to_ignore = vec![0];
+        player.knock_out();
+        assert!(player.platforms_to_ignore.is_empty());
+        assert!(!can_be_hit(&player));
+        // Still solid, so it lands on platforms.
+        assert!(player.get_hitboxes().iter().any(|bbox| bbox.tag == HitboxTag::Solid));
+        for _ in 0..SPAWN_INVULNERABLE_FRAMES {
+            assert!(!can_be_hit(&player));
+            player.handle_phys_update();
+        }
+        assert!(can_be_hit(&player));
+        // Spawning at the start of a battle doesn't count.
+        let mut player = bare_test_player();
+        player.spawn_at(na::Vector2::new(0., 0.));
+        assert!(can_be_hit(&player));
+    }
+
+    #[test]
+    fn last_stock_eliminates() {
+        let mut player = bare_test_player();
+        player.stats.set(StatField::Stocks, 1.);
+        player.position[1] = 5000.;
+        player.knock_out();
+        assert!(player.is_eliminated());
+        assert!(player.position[1] == 5000.);
+    }
+
+    #[test]
+    fn ranged_cooldown() {
+        let mut player = bare_test_player();
+        player.handle_actions(vec![Action::Attack(Attack::Ranged)]);
+        let (attack, position, direction) = player.take_fire_request().unwrap();
+        assert!(attack == Attack::Ranged);
+        assert!(direction == HorizontalStance::Left);
+        assert!(position == player.position + na::Vector2::new(0., 15.));
+        assert!(player.take_fire_request().is_none());
+        player.handle_actions(vec![Action::Attack(Attack::Ranged)]);
+        assert!(player.take_fire_request().is_none());
+        for _ in 0..RANGED_COOLDOWN {
+            player.handle_phys_update();
+        }
+        player.handle_actions(vec![Action::Attack(Attack::Ranged)]);
+        assert!(player.take_fire_request().is_some());
+    }
+
+    #[test]
+    fn only_mages_hurl_fireballs() {
+        let mut player = bare_test_player();
+        player.handle_actions(vec![Action::UseAbility(0)]);
+        assert!(player.take_fire_request().is_none());
+
+        let mut mage = Player::new(Race::Mage, InputScheme::default()).unwrap();
+        mage.handle_actions(vec![Action::UseAbility(0), Action::UseAbility(1)]);
+        assert!(mage.take_fire_request().unwrap().0 == Attack::Ability(Ability::Fireball));
+        assert!(mage.take_fire_request().is_none());
+        for _ in 1..Ability::Fireball.data().cooldown {
+            mage.handle_phys_update();
+            mage.handle_actions(vec![Action::UseAbility(0)]);
+            assert!(mage.take_fire_request().is_none());
+        }
+        mage.handle_phys_update();
+        mage.handle_actions(vec![Action::UseAbility(0)]);
+        assert!(mage.take_fire_request().is_some());
+        // Fireballs don't wait on the ranged attack, or the other way around.
+        mage.handle_actions(vec![Action::Attack(Attack::Ranged)]);
+        assert!(mage.take_fire_request().unwrap().0 == Attack::Ranged);
+    }
+
+    #[test]
+    fn aliens_teleport_ahead_without_hitboxes() {
+        let mut player = Player::new(Race::Alien, InputScheme::default()).unwrap();
+        let start = player.position;
+        player.handle_actions(vec![Action::UseAbility(0)]);
+        assert!(player.position == start);
+        player.handle_actions(vec![Action::UseAbility(1)]);
+        assert!(player.position == start - na::Vector2::new(alien::TELEPORT_DISTANCE, 0.));
+        assert!(player.get_hitboxes().is_empty());
+        tick_on_ground(&mut player);
+        assert!(!player.get_hitboxes().is_empty());
+        // Still standing: the ground wasn't let go of while intangible.
+        assert!(matches!(player.stance.0, VerticalStance::OnGround(GroundStance::Standing)));
+
+        let teleported = player.position;
+        for _ in 1..Ability::Teleport.data().cooldown {
+            player.handle_actions(vec![Action::UseAbility(1)]);
+            tick_on_ground(&mut player);
+        }
+        assert!(player.position == teleported);
+        player.handle_actions(vec![Action::UseAbility(1)]);
+        assert!(player.position == teleported - na::Vector2::new(alien::TELEPORT_DISTANCE, 0.));
+    }
+
+    #[test]
+    fn mages_cast_haste_once_done_casting() {
+        let mut mage = Player::new(Race::Mage, InputScheme::default()).unwrap();
+        mage.handle_actions(vec![Action::UseAbility(1)]);
+        assert!(mage.ability_cooldown(1) == mage::HASTE_COOLDOWN);
+        for _ in 1..mage::HASTE_STARTUP {
+            tick_on_ground(&mut mage);
+        }
+        assert!(mage.buff.is_empty());
+        tick_on_ground(&mut mage);
+        // Counting the tick it was cast on.
+        let haste = Buff::SpeedUp { multiplier: mage::HASTE_MULTIPLIER, frames_left: mage::HASTE_FRAMES - 1 };
+        assert!(mage.buff == vec![haste]);
+        // Not again until the cooldown is over.
+        mage.buff.clear();
+        for _ in mage::HASTE_STARTUP + 1..mage::HASTE_COOLDOWN {
+            mage.handle_actions(vec![Action::UseAbility(1)]);
+            tick_on_ground(&mut mage);
+        }
+        assert!(mage.buff.is_empty() && mage.ability_cooldown(1) == 1);
+        tick_on_ground(&mut mage);
+        mage.handle_actions(vec![Action::UseAbility(1)]);
+        assert!(mage.ability_cooldown(1) == mage::HASTE_COOLDOWN);
+
+        // Getting hit while casting cancels it, cooldown and all.
+        let hit = Changes { hitstun: 10, ..Default::default() };
+        mage.apply_changeset(hit);
+        for _ in 0..mage::HASTE_STARTUP {
+            tick_on_ground(&mut mage);
+        }
+        assert!(mage.buff.is_empty());
+    }
+
+    #[test]
+    fn ground_friction_stops_sliding() {
+        let mut player = bare_test_player();
+        let platforms = [platform_below()];
+        tick_with_platforms(&mut player, &platforms);
+        player.stats.set(StatField::Friction, 0.25);
+        player.velocity[0] = -1.;
+        for expected in &[-0.75, -0.5, -0.25, 0., 0.] {
+            tick_with_platforms(&mut player, &platforms);
+            assert!(player.velocity[0] == *expected);
+        }
+    }
+
+    #[test]
+    fn walking_keeps_sliding() {
+        let mut player = bare_test_player();
+        let platforms = [platform_below()];
+        tick_with_platforms(&mut player, &platforms);
+        player.velocity[0] = 1.;
+        for _ in 0..10 {
+            player.handle_actions(vec![Action::Walk(HorizontalStance::Right)]);
+            tick_with_platforms(&mut player, &platforms);
+        }
+        assert!(player.velocity[0] == 1.);
+        tick_with_platforms(&mut player, &platforms);
+        assert!(player.velocity[0] == 1. - player.stats.friction());
+    }
+
+    #[test]
+    fn fast_fall_only_while_falling() {
+        let mut player = bare_test_player();
+        player.handle_actions(vec![Action::FastFall]);
+        match player.stance.0 {
+            VerticalStance::OnGround(_) => (),
+            _ => panic!("Expected to stay on the ground."),
+        }
+    }
+
+    /// A tall wall just right of the test player.
+    fn wall_right() -> Platform {
+        Platform::new(BoundingBox {
+            mode: None,
+            tag: HitboxTag::Solid,
+            pos: na::Vector2::new(132., -200.),
+            size: na::Vector2::new(10., 400.),
+            ori: 0.,
+        }, false)
+    }
+
+    /// Drifts an airborne player into the wall on their right until they touch it.
+    fn against_wall_right() -> Player {
+        let mut player = airborne_player();
+        player.velocity = na::Vector2::new(2., 0.);
+        let platforms = [wall_right()];
+        for _ in 0..3 {
+            tick_with_platforms(&mut player, &platforms);
+        }
+        player
+    }
+
+    #[test]
+    fn wall_jump_pushes_off_wall() {
+        let mut player = against_wall_right();
+        assert!(player.wall_contact == Some(HorizontalStance::Right));
+        player.perform_actions(vec![Action::Jump]);
+        assert!(player.velocity[0] < 0.);
+        assert!(player.velocity[1] < 0.);
+        assert!(player.wall_contact.is_none());
+        match player.stance {
+            (VerticalStance::InAir { jumps_spent: 0, stance: AirStance::WallJumping }, HorizontalStance::Left) => (),
+            _ => panic!("Expected to be wall jumping away from the wall, got {:?}", player.stance),
+        }
+        // Rising stops once the jump peaks.
+        while player.velocity[1] < 0. {
+            tick(&mut player);
+        }
+        tick(&mut player);
+        match player.stance.0 {
+            VerticalStance::InAir { stance: AirStance::Falling, .. } => (),
+            _ => panic!("Expected to fall after the wall jump, got {:?}", player.stance),
+        }
+    }
+
+    #[test]
+    fn buffs_expire_on_time() {
+        let mut player = bare_test_player();
+        let buff = |buff| Changes { effects: vec![Effect::Buff(buff)], ..Default::default() };
+        player.apply_changeset(buff(Buff::SpeedUp { multiplier: 2., frames_left: 3 }));
+        for _ in 0..2 {
+            player.handle_phys_update();
+            assert!(player.walk_speed() == 2. * player.stats.walk_speed());
+        }
+        player.handle_phys_update();
+        assert!(player.walk_speed() == player.stats.walk_speed());
+        assert!(player.buff.is_empty());
+
+        // Reapplying refreshes the duration instead of stacking.
+        player.apply_changeset(buff(Buff::SpeedUp { multiplier: 2., frames_left: 3 }));
+        player.handle_phys_update();
+        player.apply_changeset(buff(Buff::SpeedUp { multiplier: 2., frames_left: 3 }));
+        for _ in 0..2 {
+            player.handle_phys_update();
+            assert!(player.walk_speed() == 2. * player.stats.walk_speed());
+        }
+        player.handle_phys_update();
+        assert!(player.buff.is_empty());
+    }
+
+    #[test]
+    fn damage_resist_scales_damage_taken() {
+        let mut player = bare_test_player();
+        player.apply_changeset(Changes {
+            effects: vec![Effect::Buff(Buff::DamageResist { fraction: 0.25, frames_left: 10 }), Effect::Damage(8.)],
+            ..Default::default()
+        });
+        assert!(player.damage_pct() == 6.);
+    }
+
+    #[test]
+    fn stun_locks_out_input() {
+        let mut player = bare_test_player();
+        tick_on_ground(&mut player);
+        player.apply_changeset(Changes {
+            contacted_platforms: vec![0],
+            effects: vec![Effect::Buff(Buff::Stun { frames_left: 3 })],
+            ..Default::default()
+        });
+        player.handle_phys_update();
+        let x = player.position[0];
+        // Applied on the last tick, so input is ignored for the 2 ticks left.
+        for _ in 0..2 {
+            assert!(player.is_stunned());
+            player.handle_actions(vec![Action::Walk(HorizontalStance::Right)]);
+            assert!(player.position[0] == x);
+            tick_on_ground(&mut player);
+        }
+        assert!(!player.is_stunned());
+        player.handle_actions(vec![Action::Walk(HorizontalStance::Right)]);
+        assert!(player.position[0] == x + player.stats.walk_speed());
+    }
+
+    #[test]
+    fn robot_armor_takes_first_hit_each_stock() {
+        let mut player = bare_test_player();
+        player.race = Race::Robot;
+        player.respawn();
+        let hit = || Changes { effects: vec![Effect::Damage(5.)], ..Default::default() };
+        assert!(player.has_armor());
+        player.apply_changeset(hit());
+        assert!(player.damage_pct() == 0.);
+        assert!(!player.has_armor() && player.buff.is_empty());
+        player.apply_changeset(hit());
+        player.apply_changeset(hit());
+        assert!(player.damage_pct() == 10.);
+
+        // Armor comes back with the next stock.
+        player.knock_out();
+        assert!(player.has_armor());
+        player.apply_changeset(hit());
+        assert!(player.damage_pct() == 0.);
+
+        assert!(!bare_test_player().has_armor());
+    }
+
+    #[test]
+    fn robots_repair_standing_still() {
+        let mut player = Player::new(Race::Robot, InputScheme::default()).unwrap();
+        player.respawn();
+        player.stats.take_damage(30.);
+        // Repairs need solid ground.
+        player.handle_actions(vec![Action::UseAbility(0)]);
+        assert!(!player.is_charging());
+        tick_on_ground(&mut player);
+        player.handle_actions(vec![Action::UseAbility(0)]);
+        assert!(player.is_charging() && !player.has_armor());
+
+        let start = player.position;
+        for _ in 1..robot::CHARGE_FRAMES {
+            player.handle_actions(vec![Action::Walk(HorizontalStance::Right), Action::Jump, Action::Shield]);
+            tick_on_ground(&mut player);
+            assert!(player.damage_pct() == 30.);
+        }
+        assert!(player.position == start && player.is_charging());
+        tick_on_ground(&mut player);
+        assert!(player.damage_pct() == 30. - robot::REPAIR_AMOUNT);
+        assert!(!player.is_charging() && player.has_armor());
+        // Long cooldown.
+        player.handle_actions(vec![Action::UseAbility(0)]);
+        assert!(!player.is_charging());
+    }
+
+    #[test]
+    fn aliens_heal_when_left_alone() {
+        let mut player = bare_test_player();
+        let hit = || Changes { effects: vec![Effect::Damage(5.)], ..Default::default() };
+        player.apply_changeset(hit());
+        for _ in 0..alien::REGEN_PAUSE_FRAMES + alien::REGEN_INTERVAL {
+            player.passive_tick();
+        }
+        assert!(player.damage_pct() == 5. - alien::REGEN_RATE);
+        for _ in 0..10 * alien::REGEN_INTERVAL {
+            player.passive_tick();
+        }
+        assert!(player.damage_pct() == 0.);
+
+        // Getting hit again starts the wait over.
+        player.apply_changeset(hit());
+        for _ in 0..alien::REGEN_PAUSE_FRAMES + alien::REGEN_INTERVAL - 1 {
+            player.passive_tick();
+        }
+        player.apply_changeset(hit());
+        for _ in 0..alien::REGEN_PAUSE_FRAMES {
+            player.passive_tick();
+        }
+        assert!(player.damage_pct() == 10.);
+
+        // Other races don't heal.
+        player.race = Race::Robot;
+        for _ in 0..alien::REGEN_PAUSE_FRAMES + alien::REGEN_INTERVAL {
+            player.passive_tick();
+        }
+        assert!(player.damage_pct() == 10.);
+    }
+
+    #[test]
+    fn air_jumps_run_out() {
+        let mut player = bare_test_player();
+        player.stats.set(StatField::MaxAirJumps, 2.);
+        tick_on_ground(&mut player);
+        player.perform_actions(vec![Action::Jump]);
+        for expected in 2..=3 {
+            player.velocity[1] = 1.;
+            player.perform_actions(vec![Action::Jump]);
+            assert!(player.velocity[1] == -player.stats.jump_speed());
+            match player.stance.0 {
+                VerticalStance::InAir { jumps_spent, stance: AirStance::Upping } => assert!(jumps_spent == expected),
+                _ => panic!("Expected to jump in the air, got {:?}", player.stance),
+            }
+        }
+        player.velocity[1] = 1.;
+        player.perform_actions(vec![Action::Jump]);
+        assert!(player.velocity[1] == 1.);
+
+        // Walking off a platform spends the jump off the ground.
+        let mut player = airborne_player();
+        player.stats.set(StatField::MaxAirJumps, 1.);
+        player.perform_actions(vec![Action::Jump]);
+        assert!(player.velocity[1] < 0.);
+        player.velocity[1] = 1.;
+        player.perform_actions(vec![Action::Jump]);
+        assert!(player.velocity[1] == 1.);
+    }
+
+    #[test]
+    fn wall_jump_needs_wall_contact() {
+        let mut player = airborne_player();
+        player.stats.set(StatField::MaxAirJumps, 0.);
+        player.velocity = na::Vector2::new(2., 0.);
+        tick(&mut player);
+        assert!(player.wall_contact.is_none());
+        player.perform_actions(vec![Action::Jump]);
+        assert!(player.velocity[0] == 2.);
+        assert!(!player.take_jumped());
+
+        // Walls are ignored on the ground.
+        let mut player = bare_test_player();
+        player.apply_changeset(Changes {
+            contacted_platforms: vec![0],
+            wall_contact: Some(HorizontalStance::Right),
+            ..Default::default()
+        });
+        assert!(player.wall_contact.is_none());
+    }
+
+    /// An airborne player sent tumbling by a strong hit.
+    fn tumbling_player() -> Player {
+        let mut player = airborne_player();
+        player.apply_changeset(Changes {
+            hitstun: TUMBLE_HITSTUN,
+            ..Default::default()
+        });
+        player
+    }
+
+    /// Updates a player standing on a platform.
+    fn tick_on_ground(player: &mut Player) {
+        player.apply_changeset(Changes {
+            contacted_platforms: vec![0],
+            ..Default::default()
+        });
+        player.handle_phys_update();
+    }
+
+    #[test]
+    fn only_strong_hits_tumble() {
+        let mut player = airborne_player();
+        player.apply_changeset(Changes {
+            hitstun: TUMBLE_HITSTUN - 1,
+            ..Default::default()
+        });
+        match player.stance.0 {
+            VerticalStance::InAir { stance: AirStance::Falling, .. } => (),
+            _ => panic!("Expected a weak hit not to tumble, got {:?}", player.stance),
+        }
+        match tumbling_player().stance.0 {
+            VerticalStance::InAir { stance: AirStance::Tumbling, .. } => (),
+            other => panic!("Expected to tumble, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn tumbling_players_cannot_be_hit_but_still_land() {
+        let mut player = tumbling_player();
+        player.position = na::Vector2::new(100., -10.);
+        assert!(!player.get_hitboxes().iter().any(|bbox| bbox.tag.can_be_hit()));
+        let platforms = [platform_below()];
+        for _ in 0..30 {
+            tick_with_platforms(&mut player, &platforms);
+        }
+        assert!(matches!(player.stance.0, VerticalStance::OnGround(_)));
+        assert!(player.get_offset()[1] < 30.);
+        // Nothing hits, even once the hitstun is over.
+        player.hitstun_frames = 0;
+        let mut attacker = bare_test_player_at(player.position - na::Vector2::new(20., 0.));
+        attacker.stance.1 = HorizontalStance::Right;
+        strike_now(&mut attacker);
+        let players = [attacker, player];
+        let (_, defender) = collide(&players);
+        assert!(defender.iter().all(|changes| changes.effects.is_empty()));
+    }
+
+    #[test]
+    fn tech_rolls_out_of_hard_landing() {
+        let can_be_hit = |player: &Player| player.get_hitboxes().iter().any(|bbox| bbox.tag.can_be_hit());
+        let mut player = tumbling_player();
+        tick_on_ground(&mut player);
+        assert!(player.tech_window_frames > 0);
+        // Still in hitstun, but teching is allowed.
+        assert!(player.hitstun_frames > 0);
+        player.handle_actions(vec![Action::Walk(HorizontalStance::Right)]);
+        assert!(player.velocity[0] == player.stats.tech_roll_speed());
+        assert!(player.hitstun_frames == 0);
+        for _ in 0..player.stats.tech_roll_frames() {
+            assert!(!can_be_hit(&player));
+            match player.stance.0 {
+                VerticalStance::OnGround(GroundStance::TechRolling(_)) => (),
+                _ => panic!("Expected to be tech rolling, got {:?}", player.stance),
+            }
+            tick_on_ground(&mut player);
+        }
+        assert!(can_be_hit(&player));
+        assert!(player.velocity[0] == 0.);
+        match player.stance.0 {
+            VerticalStance::OnGround(GroundStance::Standing) => (),
+            _ => panic!("Expected to get up after the roll, got {:?}", player.stance),
+        }
+
+        // Jumping techs in place.
+        let mut player = tumbling_player();
+        tick_on_ground(&mut player);
+        player.handle_actions(vec![Action::Jump]);
+        assert!(player.velocity[0] == 0.);
+        assert!(!player.take_jumped());
+        assert!(!can_be_hit(&player));
+    }
+
+    #[test]
+    fn missed_tech_knocks_down() {
+        let mut player = tumbling_player();
+        tick_on_ground(&mut player);
+        for _ in 0..TECH_WINDOW {
+            player.handle_actions(vec![Action::Shield]);
+            tick_on_ground(&mut player);
+        }
+        match player.stance.0 {
+            VerticalStance::OnGround(GroundStance::KnockedDown(_)) => (),
+            _ => panic!("Expected to be knocked down, got {:?}", player.stance),
+        }
+        // Too late to tech, and nothing else can be done while down either.
+        let position = player.position;
+        for _ in 0..player.stats.knockdown_frames() {
+            player.handle_actions(vec![Action::Walk(HorizontalStance::Right)]);
+            assert!(player.position == position);
+            tick_on_ground(&mut player);
+        }
+        match player.stance.0 {
+            VerticalStance::OnGround(GroundStance::Standing) => (),
+            _ => panic!("Expected to get up, got {:?}", player.stance),
+        }
+        assert!(player.stats.knockdown_frames() > player.stats.tech_roll_frames());
+    }
+
+    #[test]
+    fn animation_follows_actions() {
+        let mut player = bare_test_player();
+        let animation = |frames: &[&str], frame_duration, looping| Animation {
+            frames: frames.iter().map(|frame| frame.to_string()).collect(),
+            frame_duration,
+            looping,
+        };
+        player.animations = vec![
+            (Action::Idle, animation(&["idle_0", "idle_1"], 2, true)),
+            (Action::Walk(HorizontalStance::Left), animation(&["walk_0", "walk_1"], 1, true)),
+            (light_attack()[0].clone(), animation(&["jab_0", "jab_1", "jab_2"], 2, false)),
+        ];
+        let shown = |player: &mut Player, actions: Vec<Action>, ticks| -> Vec<String> {
+            (0..ticks)
+                .map(|_| {
+                    player.handle_actions(actions.clone());
+                    tick_on_ground(player);
+                    player.frame_name().unwrap().to_owned()
+                })
+                .collect()
+        };
+        assert!(player.frame_name() == Some("idle_0"));
+        assert!(shown(&mut player, vec![], 4) == ["idle_0", "idle_1", "idle_1", "idle_0"]);
+        // Walking right uses the walk animation, drawn mirrored.
+        assert!(shown(&mut player, vec![Action::Walk(HorizontalStance::Right)], 3) == ["walk_0", "walk_1", "walk_0"]);
+        assert!(player.stance.1 == HorizontalStance::Right);
+        // The attack holds its last frame until it is over, then idling starts over.
+        let mut attack = shown(&mut player, light_attack(), 1);
+        attack.extend(shown(&mut player, vec![], 13));
+        let mut expected = vec!["jab_0", "jab_0", "jab_1", "jab_1"];
+        expected.extend(vec!["jab_2"; 9]);
+        expected.push("idle_0");
+        assert!(attack == expected);
+    }
 }
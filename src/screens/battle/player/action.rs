@@ -1,29 +1,80 @@
+use serde::{Serialize, Deserialize};
+
 use super::meta::*;
 use super::stance::HorizontalStance;
 
 /// Actions available for the player to take.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Action {
     Idle,
     Walk(HorizontalStance),
     Dash(HorizontalStance),
     Jump,
+    FastFall,
+    DropThrough,
+    /// Holds up a shield, which takes hits in place of the player.
+    Shield,
     Attack(Attack),
+    /// Reaches for a player in front, holding on to them if they are caught.
+    Grab,
+    /// Throws the player being held.
+    Throw(AttackDir),
+    /// Recovers from a hard landing by rolling the given way, or in place.
+    Tech(Option<HorizontalStance>),
+    /// Uses the ability in the given slot, if the player has one there.
+    UseAbility(usize),
+}
+
+impl Action {
+    /// The tech this action stands for right after a hard landing, if any. Jumping techs in place
+    /// and moving rolls that way.
+    pub fn into_tech(self) -> Option<Option<HorizontalStance>> {
+        match self {
+            Action::Tech(dir) => Some(dir),
+            Action::Jump => Some(None),
+            Action::Walk(dir) | Action::Dash(dir) => Some(Some(dir)),
+            _ => None,
+        }
+    }
+
+    /// The timing of actions which put a box in front of the player.
+    pub fn frame_data(&self) -> Option<FrameData> {
+        match self {
+            Action::Attack(attack) => attack.frame_data(),
+            // The damage and knockback are dealt by the throw.
+            Action::Grab => Some(FrameData { startup: 6, active: 2, recovery: 24, damage: 4., knockback: 2. }),
+            _ => None,
+        }
+    }
 }
 
 /// Different types of attacks.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Attack {
+    /// Made by attacking while dashing, reaching further ahead.
     DashAttack,
+    /// Fires a `Projectile`.
+    Ranged,
     Shielding,
     Basics(BasicClass, AttackDir),
     Ability(Ability),
 }
 
+impl Attack {
+    /// The timing and strength of attacks which put a hitbox in front of the player.
+    pub fn frame_data(&self) -> Option<FrameData> {
+        match self {
+            Attack::Basics(class, _) => Some(class.frame_data()),
+            Attack::DashAttack => Some(FrameData { startup: 2, active: 6, recovery: 16, damage: 8., knockback: 2. }),
+            _ => None,
+        }
+    }
+}
+
 /// The direction of an attack.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum AttackDir {
     Up,
     Down,
     Side,
-}
\ No newline at end of file
+}
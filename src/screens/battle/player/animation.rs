@@ -0,0 +1,84 @@
+//! Sprite animations, picked by the action the player is taking.
+//!
+//! Frames are named in the character's sprite sheet. They face left, like attack boxes do, and
+//! players facing right are drawn mirrored.
+use serde::{Serialize, Deserialize};
+
+use super::FrameNumber;
+use super::action::Action;
+
+/// A sequence of frames shown one after another.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Animation {
+    /// Names of frames in the character's sprite sheet, in the order they are shown.
+    pub frames: Vec<String>,
+    /// The number of ticks each frame is shown for.
+    pub frame_duration: FrameNumber,
+    /// Whether to start over after the last frame. Animations that don't loop hold their last
+    /// frame instead.
+    pub looping: bool,
+}
+
+impl Animation {
+    /// The frame shown `ticks` ticks into the animation, or `None` if it has no frames.
+    pub fn frame_at(&self, ticks: u32) -> Option<&str> {
+        if self.frames.is_empty() {
+            return None;
+        }
+        let frame = (ticks / u32::from(self.frame_duration.max(1))) as usize;
+        let frame = if self.looping {
+            frame % self.frames.len()
+        } else {
+            frame.min(self.frames.len() - 1)
+        };
+        Some(&self.frames[frame])
+    }
+}
+
+/// The animation for `action`. Actions without one of their own use the one for the same kind of
+/// action, so a single walk animation covers both directions, and fall back to the idle one.
+pub fn find<'a>(animations: &'a [(Action, Animation)], action: &Action) -> Option<&'a Animation> {
+    let kind = std::mem::discriminant(action);
+    animations.iter().find(|(animated, _)| animated == action)
+        .or_else(|| animations.iter().find(|(animated, _)| std::mem::discriminant(animated) == kind))
+        .or_else(|| animations.iter().find(|(animated, _)| *animated == Action::Idle))
+        .map(|(_, animation)| animation)
+}
+
+#[cfg(test)]
+mod animation_test {
+    use super::*;
+    use super::super::stance::HorizontalStance;
+
+    fn animation(frames: &[&str], frame_duration: FrameNumber, looping: bool) -> Animation {
+        Animation { frames: frames.iter().map(|frame| frame.to_string()).collect(), frame_duration, looping }
+    }
+
+    #[test]
+    fn looping_wraps() {
+        let walk = animation(&["a", "b", "c"], 2, true);
+        let shown: Vec<_> = (0..8).map(|tick| walk.frame_at(tick).unwrap()).collect();
+        assert!(shown == vec!["a", "a", "b", "b", "c", "c", "a", "a"]);
+    }
+
+    #[test]
+    fn one_shot_holds_last_frame() {
+        let attack = animation(&["wind_up", "swing"], 1, false);
+        let shown: Vec<_> = (0..5).map(|tick| attack.frame_at(tick).unwrap()).collect();
+        assert!(shown == vec!["wind_up", "swing", "swing", "swing", "swing"]);
+        assert!(animation(&[], 1, false).frame_at(0).is_none());
+    }
+
+    #[test]
+    fn falls_back_to_same_kind_then_idle() {
+        let idle = animation(&["idle"], 1, true);
+        let walk = animation(&["walk"], 1, true);
+        let animations = vec![
+            (Action::Idle, idle.clone()),
+            (Action::Walk(HorizontalStance::Left), walk.clone()),
+        ];
+        assert!(find(&animations, &Action::Walk(HorizontalStance::Right)) == Some(&walk));
+        assert!(find(&animations, &Action::Jump) == Some(&idle));
+        assert!(find(&[], &Action::Idle).is_none());
+    }
+}
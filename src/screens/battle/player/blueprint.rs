@@ -0,0 +1,187 @@
+//! Characters described in RON files, the same way arenas are.
+//!
+//! A `PlayerBlueprint` only holds data, so it can be loaded and checked without a `Context`.
+//! Turning one into a `Player` loads its sprite sheet.
+use ggez::nalgebra as na;
+use ron::de::from_reader;
+use serde::{Serialize, Deserialize};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    physics::{BoundingBox, HitboxTag},
+    util::result::{WalpurgisError, WalpurgisResult},
+};
+use super::action::Action;
+use super::animation::Animation;
+use super::meta::{Race, Stats};
+
+/// Everything that makes up a character before it is put into a battle.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerBlueprint {
+    pub name: String,
+    pub race: Race,
+    /// Includes the walking speed and jump strength. Without any, the character gets the base stats
+    /// of its race. Stats left unset among others keep their defaults.
+    #[serde(default)]
+    pub stats: Option<Stats>,
+    /// The character's own boxes. The first one is the body attacks reach out from.
+    pub bboxes: Vec<BoundingBox>,
+    /// Where the character spawns in arenas without spawn points.
+    pub spawn_position: na::Vector2<f32>,
+    /// The sprite sheet's descriptor file relative to the asset root.
+    #[serde(default)]
+    pub sprite_sheet: Option<PathBuf>,
+    /// The animation shown for each action, as frames of `sprite_sheet`.
+    #[serde(default)]
+    pub animations: Vec<(Action, Animation)>,
+}
+
+impl PlayerBlueprint {
+    /// A 30x30 character of `race` without any sprites, named after its race, for races nobody has
+    /// made a character of yet.
+    pub fn placeholder(race: Race) -> Self {
+        let bbox = |tag| BoundingBox {
+            mode: None,
+            tag,
+            pos: na::Vector2::new(0., 0.),
+            size: na::Vector2::new(30., 30.),
+            ori: 0.,
+        };
+        PlayerBlueprint {
+            name: format!("{:?}", race),
+            race,
+            stats: None,
+            bboxes: vec![bbox(HitboxTag::Solid), bbox(HitboxTag::Hurt)],
+            spawn_position: na::Vector2::new(100., 0.),
+            sprite_sheet: None,
+            animations: vec![],
+        }
+    }
+
+    /// Tries to load a `PlayerBlueprint` from the given file. Parse errors name the file.
+    pub fn load<P: AsRef<Path>>(blueprint_file: P) -> WalpurgisResult<Self> {
+        let blueprint_file = blueprint_file.as_ref();
+        let f = File::open(blueprint_file).map_err(|e| WalpurgisError::reading(blueprint_file, "character", e))?;
+        let blueprint: Self = from_reader(f).map_err(|e| WalpurgisError::parsing(blueprint_file, "character", e))?;
+        blueprint.validate()?;
+        Ok(blueprint)
+    }
+
+    /// Every `.ron` file in `character_dir`, in order of file name.
+    pub fn files<P: AsRef<Path>>(character_dir: P) -> WalpurgisResult<Vec<PathBuf>> {
+        let character_dir = character_dir.as_ref();
+        let mut files = vec![];
+        let unreadable = |e| WalpurgisError::reading(character_dir, "character directory", e);
+        for entry in fs::read_dir(character_dir).map_err(unreadable)? {
+            let path = entry.map_err(unreadable)?.path();
+            if path.extension().and_then(|ext| ext.to_str()) == Some("ron") {
+                files.push(path);
+            }
+        }
+        if files.is_empty() {
+            Err(WalpurgisError::AssetNotFound { path: character_dir.to_path_buf(), what: "character" })?
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// The character's stats, falling back to the base stats of its race.
+    pub fn stats(&self) -> Stats {
+        self.stats.clone().unwrap_or_else(|| Stats::for_race(&self.race))
+    }
+
+    /// Checks that a `Player` can be built from the blueprint.
+    pub fn validate(&self) -> WalpurgisResult {
+        if self.bboxes.is_empty() {
+            Err(format!("Character `{}` has no bounding boxes.", self.name))?
+        }
+        if self.sprite_sheet.is_none() && !self.animations.is_empty() {
+            Err(format!("Character `{}` has animations but no sprite sheet.", self.name))?
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod blueprint_test {
+    use super::*;
+    use crate::physics::Collidable;
+    use crate::screens::battle::player::{self, Player, inputs::InputScheme};
+
+    fn alien_file() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("data/characters/alien.ron")
+    }
+
+    #[test]
+    fn checked_in_blueprint_parses() {
+        let blueprint = PlayerBlueprint::load(alien_file()).unwrap();
+        assert!(blueprint.race == Race::Alien);
+        let tags: Vec<_> = blueprint.bboxes.iter().map(|bbox| bbox.tag).collect();
+        assert!(tags == vec![HitboxTag::Solid, HitboxTag::Hurt]);
+        // The test player is the same character.
+        let player = Player::without_sprites(&blueprint, InputScheme::default());
+        let test_player = player::bare_test_player();
+        assert!(player.character_hash().unwrap() == test_player.character_hash().unwrap());
+        assert!(player.get_offset() == test_player.get_offset());
+    }
+
+    #[test]
+    fn bad_file_named_in_error() {
+        let path = std::env::temp_dir().join(format!("walpurgis_bad_character_{}.ron", std::process::id()));
+        fs::write(&path, "(name: \"Broken\", race: Alien,").unwrap();
+        match PlayerBlueprint::load(&path) {
+            Err(e @ WalpurgisError::AssetParse { .. }) => assert!(e.to_string().contains(&path.display().to_string())),
+            other => panic!("Expected a parse error, got {:?}", other),
+        }
+        fs::remove_file(&path).unwrap();
+        match PlayerBlueprint::load(&path) {
+            Err(e @ WalpurgisError::AssetNotFound { .. }) => assert!(e.to_string().contains(&path.display().to_string())),
+            other => panic!("Expected a missing file error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn empty_directory_named_in_error() {
+        let dir = std::env::temp_dir().join(format!("walpurgis_no_characters_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let message = PlayerBlueprint::files(&dir).unwrap_err().to_string();
+        fs::remove_dir(&dir).unwrap();
+        assert!(message.contains(&dir.display().to_string()));
+    }
+
+    #[test]
+    fn blueprint_needs_boxes() {
+        let blueprint: PlayerBlueprint = ron::de::from_str("(name: \"Empty\", race: Robot, bboxes: [], spawn_position: [0, 0])").unwrap();
+        assert!(blueprint.validate().is_err());
+    }
+
+    #[test]
+    fn dash_tuned_per_character() {
+        let blueprint: PlayerBlueprint = ron::de::from_str("(
+            name: \"Sprinter\",
+            race: Robot,
+            stats: Some((dash_speed: 8, dash_cooldown: 10)),
+            bboxes: [(tag: Solid, pos: [0, 0], size: [10, 10], ori: 0)],
+            spawn_position: [0, 0],
+        )").unwrap();
+        let stats = blueprint.stats();
+        assert!(stats.dash_speed() == 8. && stats.dash_cooldown() == 10);
+        // The rest keep their defaults.
+        assert!(stats.dash_duration() == Stats::default().dash_duration());
+    }
+
+    #[test]
+    fn animations_need_a_sprite_sheet() {
+        let blueprint: PlayerBlueprint = ron::de::from_str("(
+            name: \"Flipbook\",
+            race: Robot,
+            bboxes: [(tag: Solid, pos: [0, 0], size: [10, 10], ori: 0)],
+            spawn_position: [0, 0],
+            animations: [(Idle, (frames: [\"idle_0\", \"idle_1\"], frame_duration: 4, looping: true))],
+        )").unwrap();
+        assert!(blueprint.validate().is_err());
+        let fixed = PlayerBlueprint { sprite_sheet: Some("characters/flipbook.ron".into()), ..blueprint };
+        assert!(fixed.validate().is_ok());
+    }
+}
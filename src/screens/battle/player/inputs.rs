@@ -5,12 +5,25 @@
 //! |   Input                  |   Event                          |
 //! |--------------------------|----------------------------------|
 //! | A / D                    |  (Walk, Left \| Right)           |
-//! | Shift                    | Dash                             |
+//! | A / D in the air         | Drift                            |
+//! | A / D in hitstun         | Directional influence            |
+//! | Shift + A / D            |  (Dash, Left \| Right)           |
 //! | Space                    |  Jump                            |
-//! | Mouse 0                  | Attack                           |
+//! | Space against a wall     | Wall jump                        |
+//! | Space / A / D on landing | Tech (after a tumble)            |
+//! | S while falling          | FastFall                         |
+//! | S + Space                | DropThrough                      |
+//! | Mouse 0 / F              | Attack                           |
+//! | A / D tapped + Attack    | Smash (heavy side attack)        |
+//! | A / D tapped twice       | Smash, instead of walking        |
+//! | E                        | Ranged                           |
+//! | R                        | Ability (fireball, repair)       |
+//! | T                        | Second ability (teleport, haste) |
 //! | Mouse 1                  | Heavy                            |
 //! | Attack while dashing     | DashAttack                       |
-//! | Q                        | Shielding                        |
+//! | Q                        | Shield                           |
+//! | G                        | Grab                             |
+//! | G while holding a player | Throw (S + G throws down)        |
 //! | W + Attack               | UpAttack                         |
 //! | S + Attack               | DownAttack                       |
 //! | Configurable (1)         | OffensiveSpecial                 |
@@ -19,6 +32,39 @@
 //! | Configurable (4)         | Wildcard2                        |
 //! | Configurable (5)         | Wildcard3                        |
 //!
+//! A second local player uses the arrow keys and the keys by the right shift key instead:
+//!
+//! |   Input                  |   Event                          |
+//! |--------------------------|----------------------------------|
+//! | Left / Right             |  (Walk, Left \| Right)           |
+//! | Shift + Left / Right     |  (Dash, Left \| Right)           |
+//! | Up                       |  Jump                            |
+//! | Down while falling       | FastFall                         |
+//! | Down + Up                | DropThrough                      |
+//! | .                        | Attack                           |
+//! | ,                        | Ranged                           |
+//! | '                        | Ability                          |
+//! | [                        | Second ability                   |
+//! | ;                        | Shield                           |
+//! | /                        | Grab                             |
+//!
+//! The second player also plays on the first gamepad used, and any player can be given a gamepad
+//! in the settings. Gamepads use the standard layout, next to the player's keys:
+//!
+//! |   Input                   |   Event                          |
+//! |---------------------------|----------------------------------|
+//! | Left stick left / right   |  (Walk, Left \| Right)           |
+//! | D-pad left / right        |  (Dash, Left \| Right)           |
+//! | South button (A on Xbox)  |  Jump                            |
+//! | Left stick down           | FastFall                         |
+//! | Left stick down + South   | DropThrough                      |
+//! | West button (X on Xbox)   |  Attack                          |
+//! | North button (Y on Xbox)  | Ranged                           |
+//! | East button (B on Xbox)   | Ability                          |
+//! | Left bumper               | Second ability                   |
+//! | Right trigger             | Shield                           |
+//! | Right bumper              | Grab                             |
+//!
 //! ### Directions
 //! Ott-san has decided that we will only support 8 directions: 4 cardinal and 4 diagonal.
 //!
@@ -36,18 +82,46 @@
 /// ```
 ///
 use ggez::Context;
-use ggez::event::{KeyCode, KeyMods};
+use ggez::event::{Axis, Button, KeyCode, KeyMods};
 use ggez::input::keyboard;
+use std::collections::HashMap;
 
-use super::action::Action;
+use super::action::{Action, Attack, AttackDir};
+use super::meta::BasicClass;
 use super::stance::HorizontalStance;
 
-use crate::inputs::Input;
+use crate::inputs::{Binding, Input, InputBuffer, KeyPress, PadId, PadState};
+use crate::inputs::keys::KeyBinding;
+use crate::settings::{Controls, KeyBindings};
+use crate::util::result::WalpurgisResult;
+
+/// The gamepad each local profile is read from unless the player is given another one. The second
+/// player gets the first gamepad used.
+const DEFAULT_GAMEPADS: [Option<PadId>; 2] = [None, Some(0)];
+/// Attacking within this many ticks of starting to walk smashes instead.
+const SMASH_WINDOW: u64 = 3;
+/// Starting to walk the same way twice within this many ticks smashes instead.
+const DOUBLE_TAP_WINDOW: u64 = 12;
+
+/// What a player's inputs do on screens outside of battle, where each player has a cursor of their
+/// own.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MenuCommand {
+    Left,
+    Right,
+    Down,
+    Confirm,
+    Back,
+}
 
 #[derive(Debug)]
 pub struct InputScheme {
     continuous: ContinuousScheme,
     fire_once: FireOnceScheme,
+    /// The gamepad the gamepad bindings are read from, if the player has one.
+    gamepad: Option<PadState>,
+    /// The player's recent presses.
+    history: InputBuffer,
     // dash: Button,
     // jump: Button,
     // attack: Button,
@@ -56,41 +130,363 @@ pub struct InputScheme {
 }
 
 impl InputScheme {
-    pub fn get_possible_actions(&self, ctx: &mut Context, fire_once_key_buffer: &Vec<Input>) -> Vec<Action> {
-        let mut all_actions = self.continuous.get_possible_actions(ctx);
-        all_actions.append(&mut self.fire_once.get_possible_actions(ctx, fire_once_key_buffer));
+    /// The controls of every player that can play on one keyboard, in player order.
+    pub fn local_profiles() -> Vec<InputScheme> {
+        vec![InputScheme::default(), InputScheme::arrows()]
+    }
+
+    /// The controls of player `idx`: their default profile with any keys set in `controls` in place
+    /// of the defaults, and their gamepad if they have one.
+    pub fn for_player(idx: usize, controls: &Controls) -> WalpurgisResult<InputScheme> {
+        let bindings = controls.players.get(idx);
+        let mut profiles = InputScheme::local_profiles();
+        let profile_count = profiles.len();
+        if idx >= profile_count {
+            Err(format!("Only {} players can share the keyboard.", profile_count))?
+        }
+        let mut scheme = profiles.swap_remove(idx);
+        for (action, input) in bindings.map(KeyBindings::valid).unwrap_or_default() {
+            match scheme.named_mut().iter_mut().find(|(named, _)| *named == action) {
+                Some((_, bound)) => rebind_key(bound, input),
+                None => Err(format!("There is no `{}` action to bind.", action))?,
+            }
+        }
+        scheme.unbind_duplicate_keys();
+        // A default gamepad given to another player in the settings is theirs instead.
+        let default_pad = DEFAULT_GAMEPADS[idx]
+            .filter(|pad| controls.players.iter().all(|bindings| bindings.gamepad != Some(*pad)));
+        if let Some(pad) = bindings.and_then(|bindings| bindings.gamepad).or(default_pad) {
+            scheme.add_gamepad(pad, controls.gamepad_deadzone);
+        }
+        Ok(scheme)
+    }
+
+    /// Every action's bindings, by the name they are set by in the settings.
+    fn named_mut(&mut self) -> [(&'static str, &mut Vec<Binding>); 12] {
+        [
+            ("walk_left", &mut self.continuous.walk_left),
+            ("walk_right", &mut self.continuous.walk_right),
+            ("fast_fall", &mut self.continuous.fast_fall),
+            ("shield", &mut self.continuous.shield),
+            ("jump", &mut self.fire_once.jump),
+            ("dash_left", &mut self.fire_once.dash_left),
+            ("dash_right", &mut self.fire_once.dash_right),
+            ("ranged", &mut self.fire_once.ranged),
+            ("attack", &mut self.fire_once.light_attack),
+            ("grab", &mut self.fire_once.grab),
+            ("ability", &mut self.fire_once.ability),
+            ("second_ability", &mut self.fire_once.second_ability),
+        ]
+    }
+
+    /// Keeps each key only on the first action it is bound to, warning about the others.
+    fn unbind_duplicate_keys(&mut self) {
+        let mut taken: Vec<(&'static str, Binding)> = vec![];
+        for (action, bound) in self.named_mut().iter_mut() {
+            bound.retain(|binding| {
+                if let Binding::Keyboard(key, mods) = *binding {
+                    if let Some((first, _)) = taken.iter().find(|(_, other)| other == binding) {
+                        let key = KeyBinding((key, mods));
+                        log::warn!("`{}` is bound to both `{}` and `{}`. Keeping it on `{}`.", key, first, action, first);
+                        return false;
+                    }
+                    taken.push((action, *binding));
+                }
+                true
+            });
+        }
+    }
+
+    /// The controls of the second local player, away from the default ones.
+    fn arrows() -> Self {
+        InputScheme {
+            continuous: ContinuousScheme {
+                walk_left: key(KeyCode::Left, KeyMods::NONE),
+                walk_right: key(KeyCode::Right, KeyMods::NONE),
+                fast_fall: key(KeyCode::Down, KeyMods::NONE),
+                shield: key(KeyCode::Semicolon, KeyMods::NONE),
+            },
+            fire_once: FireOnceScheme {
+                jump: key(KeyCode::Up, KeyMods::NONE),
+                dash_left: key(KeyCode::Left, KeyMods::SHIFT),
+                dash_right: key(KeyCode::Right, KeyMods::SHIFT),
+                ranged: key(KeyCode::Comma, KeyMods::NONE),
+                light_attack: key(KeyCode::Period, KeyMods::NONE),
+                grab: key(KeyCode::Slash, KeyMods::NONE),
+                ability: key(KeyCode::Apostrophe, KeyMods::NONE),
+                second_ability: key(KeyCode::LBracket, KeyMods::NONE),
+                last_direction_press: HashMap::new(),
+                walking: vec![],
+            },
+            gamepad: None,
+            history: InputBuffer::default(),
+        }
+    }
+
+    /// Binds the standard gamepad layout on gamepad `pad`, next to the keys. The stick has to be
+    /// pushed past `deadzone`, from 0 to 1, before it counts.
+    pub fn add_gamepad(&mut self, pad: PadId, deadzone: f32) {
+        self.continuous.walk_left.push(Binding::GamepadAxis(Axis::LeftStickX, -deadzone));
+        self.continuous.walk_right.push(Binding::GamepadAxis(Axis::LeftStickX, deadzone));
+        // Up is positive on gamepads.
+        self.continuous.fast_fall.push(Binding::GamepadAxis(Axis::LeftStickY, -deadzone));
+        self.continuous.fast_fall.push(Binding::GamepadButton(Button::DPadDown));
+        self.continuous.shield.push(Binding::GamepadButton(Button::RightTrigger2));
+        self.fire_once.jump.push(Binding::GamepadButton(Button::South));
+        self.fire_once.dash_left.push(Binding::GamepadButton(Button::DPadLeft));
+        self.fire_once.dash_right.push(Binding::GamepadButton(Button::DPadRight));
+        self.fire_once.ranged.push(Binding::GamepadButton(Button::North));
+        self.fire_once.light_attack.push(Binding::GamepadButton(Button::West));
+        self.fire_once.grab.push(Binding::GamepadButton(Button::RightTrigger));
+        self.fire_once.ability.push(Binding::GamepadButton(Button::East));
+        self.fire_once.second_ability.push(Binding::GamepadButton(Button::LeftTrigger));
+        self.gamepad = Some(PadState::new(pad));
+    }
+
+    pub fn get_possible_actions(&mut self, ctx: &mut Context, fire_once_key_buffer: &[Input]) -> Vec<Action> {
+        let mods = keyboard::active_mods(ctx);
+        let keys: Vec<KeyPress> = keyboard::pressed_keys(ctx).iter().map(|key| (*key, mods)).collect();
+        self.get_actions_for(&keys, fire_once_key_buffer)
+    }
+
+    /// Gets the actions of the inputs pressed since the last tick, along with the ones whose
+    /// bindings are held. `keys` are the keys held, with the modifiers held along with them.
+    fn get_actions_for(&mut self, keys: &[KeyPress], fire_once_buffer: &[Input]) -> Vec<Action> {
+        if let Some(gamepad) = &mut self.gamepad {
+            gamepad.press(fire_once_buffer);
+        }
+        self.history.advance(fire_once_buffer);
+        let pad = self.gamepad.as_ref();
+        let mut all_actions = self.continuous.get_possible_actions(keys, pad);
+        let mut fire_once_actions = self.fire_once.get_possible_actions(fire_once_buffer, pad.map(|pad| pad.pad));
+        let smash = Action::Attack(Attack::Basics(BasicClass::Heavy, AttackDir::Side));
+        // Attacking right as a direction is tapped smashes.
+        if self.continuous.walk_tapped_within(&self.history, pad.map(|pad| pad.pad), SMASH_WINDOW) {
+            for action in &mut fire_once_actions {
+                if let Action::Attack(Attack::Basics(class @ BasicClass::Light, AttackDir::Side)) = action {
+                    *class = BasicClass::Heavy;
+                }
+            }
+        }
+        // So does tapping a direction twice, in place of walking.
+        let walking = all_actions.iter()
+            .filter_map(|action| match action {
+                Action::Walk(dir) => Some(dir.clone()),
+                _ => None,
+            })
+            .collect();
+        if let Some(dir) = self.fire_once.double_tapped(walking, self.history.frame(), DOUBLE_TAP_WINDOW) {
+            all_actions.retain(|action| *action != Action::Walk(dir.clone()));
+            if !fire_once_actions.contains(&smash) {
+                fire_once_actions.push(smash);
+            }
+        }
+        // Jumping while holding down drops through platforms instead.
+        let holding_down = self.continuous.fast_fall.iter().any(|binding| binding.is_held(keys, pad));
+        if holding_down {
+            for action in &mut fire_once_actions {
+                if let Action::Jump = action {
+                    *action = Action::DropThrough;
+                }
+            }
+        }
+        // The grab key also throws, which is only done while holding a player.
+        if fire_once_actions.contains(&Action::Grab) {
+            let dir = if holding_down { AttackDir::Down } else { AttackDir::Side };
+            fire_once_actions.push(Action::Throw(dir));
+        }
+        all_actions.append(&mut fire_once_actions);
         all_actions
     }
+
+    /// Every key bound to an action.
+    #[cfg(test)]
+    pub fn bindings(&self) -> Vec<KeyPress> {
+        let bound = [
+            &self.continuous.walk_left,
+            &self.continuous.walk_right,
+            &self.continuous.fast_fall,
+            &self.continuous.shield,
+            &self.fire_once.jump,
+            &self.fire_once.dash_left,
+            &self.fire_once.dash_right,
+            &self.fire_once.ranged,
+            &self.fire_once.light_attack,
+            &self.fire_once.grab,
+            &self.fire_once.ability,
+            &self.fire_once.second_ability,
+        ];
+        bound.iter()
+            .flat_map(|bindings| bindings.iter())
+            .filter_map(|binding| match *binding {
+                Binding::Keyboard(key, mods) => Some((key, mods)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// What `input` does to the player's cursor, if anything. Walking and dashing move it, jumping
+    /// and attacking confirm and shielding goes back. Sticks are left out, since they report every
+    /// bit of movement.
+    pub fn menu_command(&self, input: &Input) -> Option<MenuCommand> {
+        if let Input::Axis(..) = input {
+            return None;
+        }
+        let pad = self.gamepad.as_ref().map(|pad| pad.pad);
+        let commands = [
+            (&self.continuous.walk_left, MenuCommand::Left),
+            (&self.fire_once.dash_left, MenuCommand::Left),
+            (&self.continuous.walk_right, MenuCommand::Right),
+            (&self.fire_once.dash_right, MenuCommand::Right),
+            (&self.continuous.fast_fall, MenuCommand::Down),
+            (&self.fire_once.jump, MenuCommand::Confirm),
+            (&self.fire_once.light_attack, MenuCommand::Confirm),
+            (&self.continuous.shield, MenuCommand::Back),
+        ];
+        commands.iter()
+            .find(|(bindings, _)| bindings.iter().any(|binding| binding.is_pressed_by(input, pad)))
+            .map(|(_, command)| *command)
+    }
+
+    /// Gets the actions whose bindings were released.
+    pub fn get_released_actions(&mut self, released_key_buffer: &[Input]) -> Vec<Action> {
+        if let Some(gamepad) = &mut self.gamepad {
+            gamepad.release(released_key_buffer);
+        }
+        let pad = self.gamepad.as_ref().map(|pad| pad.pad);
+        let mut actions = vec![];
+        for input in released_key_buffer {
+            if self.fire_once.jump.iter().any(|binding| binding.is_released_by(input, pad)) {
+                actions.push(Action::Jump);
+            }
+        }
+        actions
+    }
+}
+
+/// A single key binding.
+fn key(key: KeyCode, mods: KeyMods) -> Vec<Binding> {
+    vec![Binding::Keyboard(key, mods)]
 }
 
+/// Binds `input` in place of the keys in `bound`, keeping any gamepad bindings.
+fn rebind_key(bound: &mut Vec<Binding>, (key, mods): KeyPress) {
+    bound.retain(|binding| !matches!(binding, Binding::Keyboard(..)));
+    bound.insert(0, Binding::Keyboard(key, mods));
+}
+
+/// Actions taken for as long as any of their bindings are held.
 #[derive(Debug)]
 pub struct ContinuousScheme {
-    pub walk_left: (KeyCode, KeyMods),
-    pub walk_right: (KeyCode, KeyMods),
+    pub walk_left: Vec<Binding>,
+    pub walk_right: Vec<Binding>,
+    pub fast_fall: Vec<Binding>,
+    pub shield: Vec<Binding>,
 }
 
 impl ContinuousScheme {
-    pub fn get_possible_actions(&self, ctx: &mut Context) -> Vec<Action> {
+    pub fn get_possible_actions(&self, keys: &[KeyPress], pad: Option<&PadState>) -> Vec<Action> {
+        let held = |bindings: &[Binding]| bindings.iter().any(|binding| binding.is_held(keys, pad));
         let mut actions = vec![];
-        let mods = keyboard::active_mods(ctx);
-        for key in keyboard::pressed_keys(ctx) {
-            if (*key, mods) == self.walk_left {
-                actions.push(Action::Walk(HorizontalStance::Left));
-            }
-            if (*key, mods) == self.walk_right {
-                actions.push(Action::Walk(HorizontalStance::Right));
-            }
+        if held(&self.walk_left) {
+            actions.push(Action::Walk(HorizontalStance::Left));
+        }
+        if held(&self.walk_right) {
+            actions.push(Action::Walk(HorizontalStance::Right));
+        }
+        if held(&self.fast_fall) {
+            actions.push(Action::FastFall);
+        }
+        if held(&self.shield) {
+            actions.push(Action::Shield);
         }
         actions
     }
+
+    /// Whether either walking binding was pressed within the last `frames` ticks of `history`.
+    /// Gamepad inputs only count if they come from `pad`.
+    pub fn walk_tapped_within(&self, history: &InputBuffer, pad: Option<PadId>, frames: u64) -> bool {
+        history.any_pressed_within(frames, |input| {
+            self.walk_left.iter().chain(&self.walk_right).any(|binding| binding.is_pressed_by(input, pad))
+        })
+    }
 }
 
+/// Actions taken once each time one of their bindings is pressed.
 #[derive(Debug)]
-pub struct FireOnceScheme;
+pub struct FireOnceScheme {
+    pub jump: Vec<Binding>,
+    pub dash_left: Vec<Binding>,
+    pub dash_right: Vec<Binding>,
+    pub ranged: Vec<Binding>,
+    pub light_attack: Vec<Binding>,
+    pub grab: Vec<Binding>,
+    /// Uses the ability in the first slot.
+    pub ability: Vec<Binding>,
+    /// Uses the ability in the second slot.
+    pub second_ability: Vec<Binding>,
+    /// The tick walking in each direction was last started on.
+    last_direction_press: HashMap<HorizontalStance, u64>,
+    /// The directions walked in on the last tick, to tell when walking starts.
+    walking: Vec<HorizontalStance>,
+}
 
 impl FireOnceScheme {
-    pub fn get_possible_actions(&self, _ctx: &mut Context, _fire_once_key_buffer: &Vec<Input>) -> Vec<Action> {
-        vec![]
+    /// Takes in the directions walked in on tick `frame`. Returns a direction walking started in
+    /// again within `window` ticks of last starting in it. Walking starts when a key is pressed or
+    /// the stick is pushed past the deadzone, so a held key or stick never double taps.
+    pub fn double_tapped(&mut self, walking: Vec<HorizontalStance>, frame: u64, window: u64) -> Option<HorizontalStance> {
+        let mut tapped = None;
+        let started: Vec<_> = walking.iter().filter(|dir| !self.walking.contains(dir)).collect();
+        for dir in started {
+            match self.last_direction_press.get(dir) {
+                // The second tap doesn't count as the first of another double tap.
+                Some(last) if frame - last <= window => {
+                    self.last_direction_press.remove(dir);
+                    tapped = Some(dir.clone());
+                },
+                _ => {
+                    self.last_direction_press.insert(dir.clone(), frame);
+                },
+            }
+        }
+        self.walking = walking;
+        tapped
+    }
+
+
+    /// Gets the actions pressed in `fire_once_key_buffer`. Gamepad inputs only count if they come
+    /// from `pad`.
+    pub fn get_possible_actions(&self, fire_once_key_buffer: &[Input], pad: Option<PadId>) -> Vec<Action> {
+        let mut actions = vec![];
+        for input in fire_once_key_buffer {
+            let pressed = |bindings: &[Binding]| bindings.iter().any(|binding| binding.is_pressed_by(input, pad));
+            if pressed(&self.jump) {
+                actions.push(Action::Jump);
+            }
+            if pressed(&self.dash_left) {
+                actions.push(Action::Dash(HorizontalStance::Left));
+            }
+            if pressed(&self.dash_right) {
+                actions.push(Action::Dash(HorizontalStance::Right));
+            }
+            if pressed(&self.ranged) {
+                actions.push(Action::Attack(Attack::Ranged));
+            }
+            if pressed(&self.light_attack) {
+                actions.push(Action::Attack(Attack::Basics(BasicClass::Light, AttackDir::Side)));
+            }
+            if pressed(&self.grab) {
+                actions.push(Action::Grab);
+            }
+            if pressed(&self.ability) {
+                actions.push(Action::UseAbility(0));
+            }
+            if pressed(&self.second_ability) {
+                actions.push(Action::UseAbility(1));
+            }
+        }
+        actions
     }
 }
 
@@ -98,10 +494,233 @@ impl Default for InputScheme {
     fn default() -> Self {
         InputScheme {
             continuous: ContinuousScheme {
-                walk_left: (KeyCode::A, KeyMods::NONE),
-                walk_right: (KeyCode::D, KeyMods::NONE),
+                walk_left: key(KeyCode::A, KeyMods::NONE),
+                walk_right: key(KeyCode::D, KeyMods::NONE),
+                fast_fall: key(KeyCode::S, KeyMods::NONE),
+                shield: key(KeyCode::Q, KeyMods::NONE),
             },
-            fire_once: FireOnceScheme,
+            fire_once: FireOnceScheme {
+                jump: key(KeyCode::Space, KeyMods::NONE),
+                dash_left: key(KeyCode::A, KeyMods::SHIFT),
+                dash_right: key(KeyCode::D, KeyMods::SHIFT),
+                ranged: key(KeyCode::E, KeyMods::NONE),
+                light_attack: key(KeyCode::F, KeyMods::NONE),
+                grab: key(KeyCode::G, KeyMods::NONE),
+                ability: key(KeyCode::R, KeyMods::NONE),
+                second_ability: key(KeyCode::T, KeyMods::NONE),
+                last_direction_press: HashMap::new(),
+                walking: vec![],
+            },
+            gamepad: None,
+            history: InputBuffer::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod inputs_test {
+    use super::*;
+    use crate::inputs::layout::{self, KeyboardLayout};
+    use crate::settings::Lenient;
+
+    #[test]
+    fn default_bindings_have_labels() {
+        for scheme in InputScheme::local_profiles() {
+            for (key, _) in scheme.bindings() {
+                for keyboard in KeyboardLayout::ALL.iter() {
+                    assert!(layout::known_label(key, *keyboard).is_some(), "{:?} has no label in {:?}", key, keyboard);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn settings_override_defaults() {
+        let bindings = KeyBindings {
+            jump: Some(Lenient::Valid(KeyBinding((KeyCode::W, KeyMods::NONE)))),
+            attack: Some(Lenient::Valid(KeyBinding((KeyCode::J, KeyMods::CTRL)))),
+            grab: Some(Lenient::Invalid("Spcae".into())),
+            ..Default::default()
+        };
+        let controls = Controls { players: vec![bindings], ..Default::default() };
+        let scheme = InputScheme::for_player(0, &controls).unwrap();
+        assert!(scheme.fire_once.jump == key(KeyCode::W, KeyMods::NONE));
+        assert!(scheme.fire_once.light_attack == key(KeyCode::J, KeyMods::CTRL));
+        assert!(scheme.fire_once.grab == InputScheme::default().fire_once.grab);
+        assert!(scheme.continuous.walk_left == InputScheme::default().continuous.walk_left);
+        assert!(scheme.gamepad.is_none());
+
+        let second = InputScheme::for_player(1, &controls).unwrap();
+        assert!(second.bindings() == InputScheme::arrows().bindings());
+        // Keys set in the settings keep the gamepad bound next to them.
+        assert!(second.fire_once.jump[1..] == [Binding::GamepadButton(Button::South)]);
+        assert!(InputScheme::for_player(InputScheme::local_profiles().len(), &controls).is_err());
+    }
+
+    #[test]
+    fn keys_bound_twice_stay_on_first_action() {
+        let bindings = KeyBindings {
+            walk_left: Some(Lenient::Valid(KeyBinding((KeyCode::D, KeyMods::NONE)))),
+            grab: Some(Lenient::Valid(KeyBinding((KeyCode::E, KeyMods::NONE)))),
+            ..Default::default()
+        };
+        let controls = Controls { players: vec![bindings], ..Default::default() };
+        let scheme = InputScheme::for_player(0, &controls).unwrap();
+        assert!(scheme.continuous.walk_left == key(KeyCode::D, KeyMods::NONE));
+        assert!(scheme.continuous.walk_right.is_empty());
+        assert!(scheme.fire_once.ranged == key(KeyCode::E, KeyMods::NONE));
+        assert!(scheme.fire_once.grab.is_empty());
+        assert!(scheme.bindings().len() == InputScheme::default().bindings().len() - 2);
+    }
+
+    #[test]
+    fn players_can_be_given_a_gamepad() {
+        let controls = Controls {
+            players: vec![KeyBindings::default(), KeyBindings { gamepad: Some(3), ..Default::default() }],
+            gamepad_deadzone: 0.4,
+            ..Default::default()
+        };
+        assert!(InputScheme::for_player(0, &controls).unwrap().gamepad.is_none());
+        let scheme = InputScheme::for_player(1, &controls).unwrap();
+        assert!(scheme.gamepad.unwrap().pad == 3);
+        assert!(scheme.continuous.walk_left.contains(&Binding::GamepadAxis(Axis::LeftStickX, -0.4)));
+    }
+
+    #[test]
+    fn second_player_defaults_to_first_gamepad() {
+        let controls = Controls::default();
+        assert!(InputScheme::for_player(0, &controls).unwrap().gamepad.is_none());
+        assert!(InputScheme::for_player(1, &controls).unwrap().gamepad.unwrap().pad == 0);
+        // Unless the first player is given it.
+        let controls = Controls {
+            players: vec![KeyBindings { gamepad: Some(0), ..Default::default() }],
+            ..Default::default()
+        };
+        assert!(InputScheme::for_player(0, &controls).unwrap().gamepad.unwrap().pad == 0);
+        assert!(InputScheme::for_player(1, &controls).unwrap().gamepad.is_none());
+    }
+
+    #[test]
+    fn gamepad_buttons_and_stick() {
+        let mut scheme = InputScheme::default();
+        scheme.add_gamepad(0, 0.25);
+        let light = Action::Attack(Attack::Basics(BasicClass::Light, AttackDir::Side));
+        let pressed = [Input::Button(Button::South, 0), Input::Button(Button::West, 0), Input::Button(Button::South, 1)];
+        assert!(scheme.get_actions_for(&[], &pressed) == vec![Action::Jump, light]);
+
+        // Inside the deadzone the stick does nothing.
+        assert!(scheme.get_actions_for(&[], &[Input::Axis(Axis::LeftStickX, 0.2, 0)]).is_empty());
+        assert!(scheme.get_actions_for(&[], &[Input::Axis(Axis::LeftStickX, -0.3, 0)])
+            == vec![Action::Walk(HorizontalStance::Left)]);
+        // Keeps walking until the stick moves again.
+        assert!(scheme.get_actions_for(&[], &[]) == vec![Action::Walk(HorizontalStance::Left)]);
+        let moves = [Input::Axis(Axis::LeftStickX, 1., 0), Input::Axis(Axis::LeftStickY, 1., 0), Input::Axis(Axis::LeftStickX, -1., 1)];
+        assert!(scheme.get_actions_for(&[], &moves) == vec![Action::Walk(HorizontalStance::Right)]);
+        assert!(scheme.get_actions_for(&[], &[Input::Axis(Axis::LeftStickX, 0., 0)]).is_empty());
+    }
+
+    #[test]
+    fn gamepad_buttons_held_and_combined() {
+        let mut scheme = InputScheme::default();
+        scheme.add_gamepad(1, 0.25);
+        // The shield stays up until its button is let go of.
+        assert!(scheme.get_actions_for(&[], &[Input::Button(Button::RightTrigger2, 1)]) == vec![Action::Shield]);
+        assert!(scheme.get_actions_for(&[], &[]) == vec![Action::Shield]);
+        scheme.get_released_actions(&[Input::Button(Button::RightTrigger2, 1)]);
+        assert!(scheme.get_actions_for(&[], &[]).is_empty());
+        // Holding the stick down drops through platforms, also alongside keys.
+        let down_jump = [Input::Axis(Axis::LeftStickY, -0.9, 1), Input::Key(KeyCode::Space, KeyMods::NONE)];
+        assert!(scheme.get_actions_for(&[], &down_jump) == vec![Action::FastFall, Action::DropThrough]);
+        // Keys still work next to the gamepad.
+        let keys = [(KeyCode::D, KeyMods::NONE)];
+        assert!(scheme.get_actions_for(&keys, &[Input::Button(Button::South, 1)])
+            == vec![Action::Walk(HorizontalStance::Right), Action::FastFall, Action::DropThrough]);
+    }
+
+    #[test]
+    fn attacking_right_after_tapping_a_direction_smashes() {
+        let mut scheme = InputScheme::default();
+        let held = [(KeyCode::D, KeyMods::NONE)];
+        let attack = [Input::Key(KeyCode::F, KeyMods::NONE)];
+        let smash = Action::Attack(Attack::Basics(BasicClass::Heavy, AttackDir::Side));
+        let light = Action::Attack(Attack::Basics(BasicClass::Light, AttackDir::Side));
+        scheme.get_actions_for(&held, &[Input::Key(KeyCode::D, KeyMods::NONE)]);
+        scheme.get_actions_for(&held, &[]);
+        assert!(scheme.get_actions_for(&held, &attack) == vec![Action::Walk(HorizontalStance::Right), smash.clone()]);
+        // The tap is too long ago by now.
+        assert!(scheme.get_actions_for(&held, &attack) == vec![Action::Walk(HorizontalStance::Right), light]);
+
+        // Flicking the stick counts as a tap too.
+        scheme.add_gamepad(0, 0.25);
+        let flick_and_attack = [Input::Axis(Axis::LeftStickX, -1., 0), Input::Button(Button::West, 0)];
+        assert!(scheme.get_actions_for(&[], &flick_and_attack) == vec![Action::Walk(HorizontalStance::Left), smash]);
+    }
+
+    #[test]
+    fn tapping_a_direction_twice_quickly_smashes() {
+        let mut scheme = InputScheme::default();
+        let held = [(KeyCode::A, KeyMods::NONE)];
+        let tap = [Input::Key(KeyCode::A, KeyMods::NONE)];
+        let walk = Action::Walk(HorizontalStance::Left);
+        let smash = Action::Attack(Attack::Basics(BasicClass::Heavy, AttackDir::Side));
+        let mut tick = |keys: &[KeyPress], pressed: &[Input]| scheme.get_actions_for(keys, pressed);
+
+        // Slow: the second tap comes after the window.
+        assert!(tick(&held, &tap) == vec![walk.clone()]);
+        for _ in 0..DOUBLE_TAP_WINDOW {
+            assert!(tick(&[], &[]).is_empty());
+        }
+        assert!(tick(&held, &tap) == vec![walk.clone()]);
+        // Fast: let go and tap again right away.
+        tick(&[], &[]);
+        assert!(tick(&held, &tap) == vec![smash]);
+        // Holding on afterwards walks, and a third tap starts over.
+        assert!(tick(&held, &[]) == vec![walk.clone()]);
+        tick(&[], &[]);
+        assert!(tick(&held, &tap) == vec![walk.clone()]);
+        // Holding never counts as tapping again.
+        for _ in 0..DOUBLE_TAP_WINDOW {
+            assert!(tick(&held, &[]) == vec![walk.clone()]);
+        }
+    }
+
+    #[test]
+    fn releasing_gamepad_jump_ends_jump() {
+        let mut scheme = InputScheme::default();
+        assert!(scheme.get_released_actions(&[Input::Button(Button::South, 0)]).is_empty());
+        scheme.add_gamepad(0, 0.25);
+        assert!(scheme.get_released_actions(&[Input::Button(Button::South, 0)]) == vec![Action::Jump]);
+        assert!(scheme.get_released_actions(&[Input::Button(Button::South, 1)]).is_empty());
+        assert!(scheme.get_released_actions(&[Input::Key(KeyCode::Space, KeyMods::SHIFT)]) == vec![Action::Jump]);
+    }
+
+    #[test]
+    fn menus_driven_by_own_bindings() {
+        let (first, mut second) = (InputScheme::default(), InputScheme::arrows());
+        let key = |key| Input::Key(key, KeyMods::NONE);
+        assert!(first.menu_command(&key(KeyCode::A)) == Some(MenuCommand::Left));
+        assert!(first.menu_command(&key(KeyCode::Space)) == Some(MenuCommand::Confirm));
+        assert!(first.menu_command(&key(KeyCode::Left)).is_none());
+        assert!(second.menu_command(&key(KeyCode::Right)) == Some(MenuCommand::Right));
+        assert!(second.menu_command(&key(KeyCode::Semicolon)) == Some(MenuCommand::Back));
+        assert!(second.menu_command(&key(KeyCode::Down)) == Some(MenuCommand::Down));
+        second.add_gamepad(1, 0.25);
+        assert!(second.menu_command(&Input::Button(Button::DPadLeft, 1)) == Some(MenuCommand::Left));
+        assert!(second.menu_command(&Input::Button(Button::DPadLeft, 0)).is_none());
+        assert!(second.menu_command(&Input::Button(Button::DPadDown, 1)) == Some(MenuCommand::Down));
+        assert!(second.menu_command(&Input::Axis(Axis::LeftStickX, 1., 1)).is_none());
+    }
+
+    #[test]
+    fn local_profiles_share_no_keys() {
+        let profiles = InputScheme::local_profiles();
+        assert!(profiles.len() >= 2);
+        for (idx, profile) in profiles.iter().enumerate() {
+            for other in &profiles[idx + 1..] {
+                for (key, _) in profile.bindings() {
+                    assert!(other.bindings().iter().all(|(other_key, _)| *other_key != key), "{:?} is bound twice", key);
+                }
+            }
         }
     }
 }
@@ -1,13 +1,96 @@
+use ggez::nalgebra as na;
+use serde::{Serialize, Deserialize};
+
+use crate::skill_tree::StatField;
+use super::FrameNumber;
+use super::race::{alien, mage, robot};
+
 /// Categories of basic attacks.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum BasicClass {
     Air,
     Heavy,
     Light,
 }
 
+/// The parts of an attack. Only active attacks can hit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttackPhase {
+    Startup,
+    Active,
+    Recovery,
+}
+
+/// The timing and strength of an attack.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameData {
+    /// Ticks before the hitbox comes out.
+    pub startup: FrameNumber,
+    /// Ticks the hitbox stays out for.
+    pub active: FrameNumber,
+    /// Ticks after the hitbox is gone before the player can attack again.
+    pub recovery: FrameNumber,
+    /// The damage dealt on hit, in percent.
+    pub damage: f32,
+    /// The knockback dealt on hit at 0% damage.
+    pub knockback: f32,
+}
+
+impl FrameData {
+    /// The number of ticks the whole attack takes.
+    pub fn total(&self) -> FrameNumber {
+        self.startup + self.active + self.recovery
+    }
+
+    /// Which part of the attack the given tick of it falls in, or `None` once it is over.
+    pub fn phase(&self, frame: FrameNumber) -> Option<AttackPhase> {
+        if frame < self.startup {
+            Some(AttackPhase::Startup)
+        } else if frame < self.startup + self.active {
+            Some(AttackPhase::Active)
+        } else if frame < self.total() {
+            Some(AttackPhase::Recovery)
+        } else {
+            None
+        }
+    }
+
+    /// The frames a hitbox is out on when it lasts for the whole active phase.
+    pub fn active_window(&self) -> HitboxWindow {
+        HitboxWindow {
+            start_frame: self.startup,
+            end_frame: self.startup + self.active,
+        }
+    }
+}
+
+/// The frames of an attack a hitbox is out on, from `start_frame` up to but not including
+/// `end_frame`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HitboxWindow {
+    pub start_frame: FrameNumber,
+    pub end_frame: FrameNumber,
+}
+
+impl HitboxWindow {
+    pub fn contains(&self, frame: FrameNumber) -> bool {
+        self.start_frame <= frame && frame < self.end_frame
+    }
+}
+
+impl BasicClass {
+    /// Light attacks are fast and weak, heavy attacks slow and strong.
+    pub fn frame_data(&self) -> FrameData {
+        match self {
+            BasicClass::Light => FrameData { startup: 3, active: 3, recovery: 8, damage: 6., knockback: 1. },
+            BasicClass::Air => FrameData { startup: 5, active: 4, recovery: 12, damage: 9., knockback: 1.5 },
+            BasicClass::Heavy => FrameData { startup: 12, active: 5, recovery: 24, damage: 15., knockback: 3. },
+        }
+    }
+}
+
 /// The race of the player character.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Race {
     /// The aliens are the ultimate forms of biological evolution.
     Alien,
@@ -17,21 +100,718 @@ pub enum Race {
     Mage,
 }
 
-/// Buffs, aka effects with a timeout that affect stats.
-#[derive(Debug)]
+impl Race {
+    /// Every race, in the order they're picked from.
+    pub const ALL: [Race; 3] = [Race::Alien, Race::Robot, Race::Mage];
+}
+
+/// Buffs, aka effects with a timeout that affect stats. Debuffs are buffs too.
+///
+/// A buff lasts `frames_left` ticks, counting the one it was applied on, or until it is used up if
+/// it has no `frames_left`. A player only has one buff of each kind: a new one replaces the old
+/// one, strength and duration both.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Buff {
-    // TODO: ALL THE BUFFS (AND DEBUFFS WHICH ARE ALSO BUFFS AND THERE IS NO REASON FOR THIS TO BE
-    // CAPS HOLY CRAP IT'S ANOTHER LINE)
+    /// Multiplies walking speed.
+    SpeedUp { multiplier: f32, frames_left: FrameNumber },
+    /// Takes away a fraction of all damage taken, from 0 to 1.
+    DamageResist { fraction: f32, frames_left: FrameNumber },
+    /// Ignores all input.
+    Stun { frames_left: FrameNumber },
+    /// Takes the damage of the next hit, then breaks.
+    Armor,
+}
+
+impl Buff {
+    /// Counts down a tick. Returns whether the buff is still in effect.
+    pub fn tick(&mut self) -> bool {
+        match self {
+            Buff::SpeedUp { frames_left, .. }
+            | Buff::DamageResist { frames_left, .. }
+            | Buff::Stun { frames_left } => {
+                *frames_left = frames_left.saturating_sub(1);
+                *frames_left > 0
+            },
+            Buff::Armor => true,
+        }
+    }
+
+    pub fn is_same_kind(&self, other: &Buff) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
 }
 
-/// A comprehensive summary of stats and perks taken in the basic skill tree.
-#[derive(Default, Debug)]
+/// Adds `buff` to `buffs`, replacing any buff of the same kind.
+pub fn add_buff(buffs: &mut Vec<Buff>, buff: Buff) {
+    buffs.retain(|active| !active.is_same_kind(&buff));
+    buffs.push(buff);
+}
+
+/// A comprehensive summary of stats and perks taken in the basic skill tree. The defaults are the
+/// Alien's, which are balanced.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Stats {
-    // TODO: ???
+    /// The distance walked every tick.
+    walk_speed: f32,
+    /// The fastest horizontal speed kept when landing.
+    run_speed: f32,
+    /// The horizontal acceleration applied every tick left or right is held in the air.
+    air_acceleration: f32,
+    /// The fastest horizontal speed drifting in the air can reach.
+    air_speed: f32,
+    /// The upwards speed at the start of a jump.
+    jump_speed: f32,
+    /// The speed away from the wall at the start of a wall jump.
+    wall_jump_speed: f32,
+    /// The extra upwards acceleration applied every tick the jump key is held.
+    jump_hold_acceleration: f32,
+    /// The number of ticks the jump key can be held for extra height.
+    max_jump_hold_frames: FrameNumber,
+    /// The number of jumps that can be made in the air before landing. Leaving the ground without
+    /// jumping spends the jump off it.
+    max_air_jumps: u8,
+    /// Multiplies the falling speed when starting to fast fall.
+    fast_fall_multiplier: f32,
+    /// The extra downwards acceleration applied every tick while fast falling.
+    fast_fall_acceleration: f32,
+    /// The fastest the player falls on their own. Being launched can send them down faster.
+    fall_speed: f32,
+    /// The fastest the player falls while fast falling.
+    fast_fall_speed: f32,
+    /// The fastest horizontal speed a dash can reach.
+    dash_speed: f32,
+    /// The number of ticks a dash lasts.
+    dash_duration: FrameNumber,
+    /// The number of ticks at the start of a dash during which the player can't be hit.
+    dash_intangible_frames: FrameNumber,
+    /// The number of ticks after starting a dash before another can be started.
+    dash_cooldown: FrameNumber,
+    /// The horizontal speed lost every tick while standing without walking.
+    friction: f32,
+    /// The damage taken so far, in percent. More damage means more knockback.
+    damage_pct: f32,
+    /// The lives left.
+    stocks: u8,
+    /// The lives the player started with.
+    max_stocks: u8,
+    /// The knockback taken from attacks without their own base knockback, e.g. projectiles, at 0%
+    /// damage.
+    knockback_base: f32,
+    /// The knockback added per percent of damage.
+    knockback_growth: f32,
+    /// Divides all knockback taken. Heavier characters are harder to launch.
+    weight: f32,
+    /// The damage a shield can take before breaking.
+    max_shield_health: f32,
+    /// The shield health regained every tick while not shielding.
+    shield_regen_rate: f32,
+    /// The shield health lost every tick while shielding.
+    shield_drain_rate: f32,
+    /// The number of ticks all input is ignored for after the shield breaks.
+    shield_break_stun_frames: FrameNumber,
+    /// The speed of a tech roll.
+    tech_roll_speed: f32,
+    /// The number of ticks a tech roll lasts, all of which the player can't be hit.
+    tech_roll_frames: FrameNumber,
+    /// The number of ticks spent lying on the ground after missing a tech.
+    knockdown_frames: FrameNumber,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats {
+            walk_speed: 2.,
+            run_speed: 3.,
+            air_acceleration: 0.1,
+            air_speed: 2.,
+            jump_speed: 1.5,
+            wall_jump_speed: 3.,
+            jump_hold_acceleration: 0.008,
+            max_jump_hold_frames: 20,
+            max_air_jumps: 2,
+            fast_fall_multiplier: 1.5,
+            fast_fall_acceleration: 0.01,
+            fall_speed: 3.,
+            fast_fall_speed: 4.5,
+            dash_speed: 6.,
+            dash_duration: 12,
+            dash_intangible_frames: 6,
+            dash_cooldown: 24,
+            friction: 0.15,
+            damage_pct: 0.,
+            stocks: 3,
+            max_stocks: 3,
+            knockback_base: 0.5,
+            knockback_growth: 0.02,
+            weight: 1.,
+            max_shield_health: 50.,
+            shield_regen_rate: 0.1,
+            shield_drain_rate: 0.15,
+            shield_break_stun_frames: 120,
+            tech_roll_speed: 4.,
+            tech_roll_frames: 16,
+            knockdown_frames: 45,
+        }
+    }
+}
+
+impl Stats {
+    /// The base stats of every character of `race`.
+    pub fn for_race(race: &Race) -> Stats {
+        match race {
+            Race::Alien => Stats::default(),
+            // Slow and heavy, barely leaving the ground.
+            Race::Robot => Stats {
+                walk_speed: 1.6,
+                run_speed: 2.4,
+                air_speed: 1.6,
+                jump_speed: 1.3,
+                max_air_jumps: 1,
+                fast_fall_multiplier: 2.,
+                fall_speed: 3.5,
+                fast_fall_speed: 5.5,
+                dash_speed: 5.,
+                dash_cooldown: 36,
+                weight: 1.4,
+                max_shield_health: 70.,
+                ..Stats::default()
+            },
+            // Light and floaty, hard to keep on the ground but easy to launch.
+            Race::Mage => Stats {
+                walk_speed: 1.8,
+                air_acceleration: 0.12,
+                air_speed: 2.4,
+                jump_speed: 1.4,
+                max_air_jumps: 3,
+                fast_fall_multiplier: 1.2,
+                fall_speed: 2.4,
+                fast_fall_speed: 3.6,
+                weight: 0.75,
+                max_shield_health: 40.,
+                ..Stats::default()
+            },
+        }
+    }
+
+    /// The value of `field`. Whole-number stats are converted.
+    #[cfg(test)]
+    pub fn get(&self, field: StatField) -> f32 {
+        match field {
+            StatField::WalkSpeed => self.walk_speed,
+            StatField::RunSpeed => self.run_speed,
+            StatField::AirAcceleration => self.air_acceleration,
+            StatField::AirSpeed => self.air_speed,
+            StatField::JumpSpeed => self.jump_speed,
+            StatField::WallJumpSpeed => self.wall_jump_speed,
+            StatField::JumpHoldAcceleration => self.jump_hold_acceleration,
+            StatField::MaxJumpHoldFrames => self.max_jump_hold_frames as f32,
+            StatField::MaxAirJumps => self.max_air_jumps as f32,
+            StatField::FastFallMultiplier => self.fast_fall_multiplier,
+            StatField::FastFallAcceleration => self.fast_fall_acceleration,
+            StatField::FallSpeed => self.fall_speed,
+            StatField::FastFallSpeed => self.fast_fall_speed,
+            StatField::DashSpeed => self.dash_speed,
+            StatField::DashDuration => self.dash_duration as f32,
+            StatField::DashIntangibleFrames => self.dash_intangible_frames as f32,
+            StatField::DashCooldown => self.dash_cooldown as f32,
+            StatField::Friction => self.friction,
+            StatField::DamagePct => self.damage_pct,
+            StatField::Stocks => self.stocks as f32,
+            StatField::MaxStocks => self.max_stocks as f32,
+            StatField::KnockbackBase => self.knockback_base,
+            StatField::KnockbackGrowth => self.knockback_growth,
+            StatField::Weight => self.weight,
+            StatField::MaxShieldHealth => self.max_shield_health,
+            StatField::ShieldRegenRate => self.shield_regen_rate,
+            StatField::ShieldDrainRate => self.shield_drain_rate,
+            StatField::ShieldBreakStunFrames => self.shield_break_stun_frames as f32,
+            StatField::TechRollSpeed => self.tech_roll_speed,
+            StatField::TechRollFrames => self.tech_roll_frames as f32,
+            StatField::KnockdownFrames => self.knockdown_frames as f32,
+        }
+    }
+
+    /// Sets `field` to `value`, rounded to the nearest whole number for stats counted in whole
+    /// numbers, which can't go below zero.
+    pub fn set(&mut self, field: StatField, value: f32) {
+        match field {
+            StatField::WalkSpeed => self.walk_speed = value,
+            StatField::RunSpeed => self.run_speed = value,
+            StatField::AirAcceleration => self.air_acceleration = value,
+            StatField::AirSpeed => self.air_speed = value,
+            StatField::JumpSpeed => self.jump_speed = value,
+            StatField::WallJumpSpeed => self.wall_jump_speed = value,
+            StatField::JumpHoldAcceleration => self.jump_hold_acceleration = value,
+            StatField::MaxJumpHoldFrames => self.max_jump_hold_frames = value.round() as FrameNumber,
+            StatField::MaxAirJumps => self.max_air_jumps = value.round() as u8,
+            StatField::FastFallMultiplier => self.fast_fall_multiplier = value,
+            StatField::FastFallAcceleration => self.fast_fall_acceleration = value,
+            StatField::FallSpeed => self.fall_speed = value,
+            StatField::FastFallSpeed => self.fast_fall_speed = value,
+            StatField::DashSpeed => self.dash_speed = value,
+            StatField::DashDuration => self.dash_duration = value.round() as FrameNumber,
+            StatField::DashIntangibleFrames => self.dash_intangible_frames = value.round() as FrameNumber,
+            StatField::DashCooldown => self.dash_cooldown = value.round() as FrameNumber,
+            StatField::Friction => self.friction = value,
+            StatField::DamagePct => self.damage_pct = value,
+            StatField::Stocks => self.stocks = value.round() as u8,
+            StatField::MaxStocks => self.max_stocks = value.round() as u8,
+            StatField::KnockbackBase => self.knockback_base = value,
+            StatField::KnockbackGrowth => self.knockback_growth = value,
+            StatField::Weight => self.weight = value,
+            StatField::MaxShieldHealth => self.max_shield_health = value,
+            StatField::ShieldRegenRate => self.shield_regen_rate = value,
+            StatField::ShieldDrainRate => self.shield_drain_rate = value,
+            StatField::ShieldBreakStunFrames => self.shield_break_stun_frames = value.round() as FrameNumber,
+            StatField::TechRollSpeed => self.tech_roll_speed = value,
+            StatField::TechRollFrames => self.tech_roll_frames = value.round() as FrameNumber,
+            StatField::KnockdownFrames => self.knockdown_frames = value.round() as FrameNumber,
+        }
+    }
+
+    /// Applies the stat change of a skill. Skills that don't change stats are left alone.
+    #[cfg(test)]
+    pub fn apply_modifier(&mut self, effect: &crate::skill_tree::SkillEffect) {
+        if let crate::skill_tree::SkillEffect::StatModifier { field, delta } = effect {
+            self.set(*field, self.get(*field) + delta);
+        }
+    }
+
+    pub fn walk_speed(&self) -> f32 {
+        self.walk_speed
+    }
+    pub fn run_speed(&self) -> f32 {
+        self.run_speed
+    }
+    pub fn air_acceleration(&self) -> f32 {
+        self.air_acceleration
+    }
+    pub fn air_speed(&self) -> f32 {
+        self.air_speed
+    }
+    pub fn jump_speed(&self) -> f32 {
+        self.jump_speed
+    }
+    pub fn wall_jump_speed(&self) -> f32 {
+        self.wall_jump_speed
+    }
+    pub fn jump_hold_acceleration(&self) -> f32 {
+        self.jump_hold_acceleration
+    }
+    pub fn max_jump_hold_frames(&self) -> FrameNumber {
+        self.max_jump_hold_frames
+    }
+    pub fn max_air_jumps(&self) -> u8 {
+        self.max_air_jumps
+    }
+    pub fn fast_fall_multiplier(&self) -> f32 {
+        self.fast_fall_multiplier
+    }
+    pub fn fast_fall_acceleration(&self) -> f32 {
+        self.fast_fall_acceleration
+    }
+    pub fn fall_speed(&self) -> f32 {
+        self.fall_speed
+    }
+    pub fn fast_fall_speed(&self) -> f32 {
+        self.fast_fall_speed
+    }
+    pub fn dash_speed(&self) -> f32 {
+        self.dash_speed
+    }
+    pub fn dash_duration(&self) -> FrameNumber {
+        self.dash_duration
+    }
+    pub fn dash_intangible_frames(&self) -> FrameNumber {
+        self.dash_intangible_frames
+    }
+    pub fn dash_cooldown(&self) -> FrameNumber {
+        self.dash_cooldown
+    }
+    pub fn friction(&self) -> f32 {
+        self.friction
+    }
+    pub fn damage_pct(&self) -> f32 {
+        self.damage_pct
+    }
+    pub fn stocks(&self) -> u8 {
+        self.stocks
+    }
+    pub fn max_stocks(&self) -> u8 {
+        self.max_stocks
+    }
+    pub fn knockback_base(&self) -> f32 {
+        self.knockback_base
+    }
+    pub fn knockback_growth(&self) -> f32 {
+        self.knockback_growth
+    }
+    pub fn weight(&self) -> f32 {
+        self.weight
+    }
+    pub fn max_shield_health(&self) -> f32 {
+        self.max_shield_health
+    }
+    pub fn shield_regen_rate(&self) -> f32 {
+        self.shield_regen_rate
+    }
+    pub fn shield_drain_rate(&self) -> f32 {
+        self.shield_drain_rate
+    }
+    pub fn shield_break_stun_frames(&self) -> FrameNumber {
+        self.shield_break_stun_frames
+    }
+    pub fn tech_roll_speed(&self) -> f32 {
+        self.tech_roll_speed
+    }
+    pub fn tech_roll_frames(&self) -> FrameNumber {
+        self.tech_roll_frames
+    }
+    pub fn knockdown_frames(&self) -> FrameNumber {
+        self.knockdown_frames
+    }
+
+    pub fn take_damage(&mut self, amount: f32) {
+        self.damage_pct += amount;
+    }
+
+    /// Takes away damage, down to none.
+    pub fn heal(&mut self, amount: f32) {
+        self.damage_pct = (self.damage_pct - amount).max(0.);
+    }
+
+    /// Starts over with `stocks` stocks out of as many, and no damage.
+    pub fn refill_stocks(&mut self, stocks: u8) {
+        self.stocks = stocks;
+        self.max_stocks = stocks;
+        self.damage_pct = 0.;
+    }
+
+    /// Loses a stock, healing all damage. Returns whether any stocks are left.
+    pub fn lose_stock(&mut self) -> bool {
+        self.stocks = self.stocks.saturating_sub(1);
+        self.damage_pct = 0.;
+        self.stocks > 0
+    }
+}
+
+/// Effects applied to a player by whatever they collided with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Effect {
+    /// Adds damage, in percent.
+    Damage(f32),
+    /// Damages the shield instead of the player.
+    ShieldDamage(f32),
+    /// Adds a buff, or a debuff.
+    Buff(Buff),
+    /// Marks the `instance`th attack of the player at `owner` as having hit, so it can't hit again.
+    Struck { owner: usize, instance: u32 },
+}
+
+/// Per-player adjustments for evening out matches between players of different skill.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Handicap {
+    /// The damage the player starts with.
+    pub starting_damage: f32,
+    /// Added to the number of stocks every player starts with. May be negative.
+    pub stock_offset: i32,
+    /// Multiplies all damage the player deals.
+    pub damage_dealt_multiplier: f32,
+    /// Multiplies all knockback the player takes.
+    pub knockback_taken_multiplier: f32,
+}
+
+impl Default for Handicap {
+    fn default() -> Self {
+        Handicap {
+            starting_damage: 0.,
+            stock_offset: 0,
+            damage_dealt_multiplier: 1.,
+            knockback_taken_multiplier: 1.,
+        }
+    }
+}
+
+impl Handicap {
+    /// The handicaps players pick from before a battle, starting with none.
+    pub fn presets() -> Vec<Handicap> {
+        let stocks = |stock_offset| Handicap { stock_offset, ..Default::default() };
+        let damage = |starting_damage| Handicap { starting_damage, ..Default::default() };
+        vec![Handicap::default(), stocks(1), stocks(2), stocks(-1), damage(50.), damage(100.)]
+    }
+
+    /// What the handicap does, as players would put it, e.g. "+1 stock, starts at 50%".
+    pub fn label(&self) -> String {
+        let mut parts = vec![];
+        match self.stock_offset {
+            0 => (),
+            1 | -1 => parts.push(format!("{:+} stock", self.stock_offset)),
+            offset => parts.push(format!("{:+} stocks", offset)),
+        }
+        if self.starting_damage != 0. {
+            parts.push(format!("starts at {:.0}%", self.starting_damage));
+        }
+        if (self.damage_dealt_multiplier - 1.).abs() > f32::EPSILON {
+            parts.push(format!("deals {:.0}% damage", self.damage_dealt_multiplier * 100.));
+        }
+        if (self.knockback_taken_multiplier - 1.).abs() > f32::EPSILON {
+            parts.push(format!("takes {:.0}% knockback", self.knockback_taken_multiplier * 100.));
+        }
+        if parts.is_empty() {
+            return "No handicap".to_owned();
+        }
+        let label = parts.join(", ");
+        let mut chars = label.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => label,
+        }
+    }
+
+    /// The number of stocks the player starts with. Every player gets at least one.
+    pub fn stocks(&self, match_stocks: u32) -> u32 {
+        (match_stocks as i32 + self.stock_offset).max(1) as u32
+    }
+
+    pub fn scale_damage_dealt(&self, damage: f32) -> f32 {
+        damage * self.damage_dealt_multiplier
+    }
+
+    pub fn scale_knockback_taken(&self, knockback: na::Vector2<f32>) -> na::Vector2<f32> {
+        knockback * self.knockback_taken_multiplier
+    }
 }
 
 /// Abilities are special active skills.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Ability {
-    // TODO: ALL THE ABILITIES
+    /// Hurls a fireball, which flies like a ranged attack but hits harder.
+    Fireball,
+    /// Blinks a short way ahead, straight through anything in between.
+    Teleport,
+    /// Heals some damage after standing still for a while.
+    Repair,
+    /// Walks faster for a while.
+    Haste,
+}
+
+/// The number of ability slots, each used with its own input.
+pub const ABILITY_SLOTS: usize = 5;
+
+/// What an ability does once its startup is over.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AbilityEffect {
+    /// Fires the ability as a projectile.
+    Projectile,
+    /// Moves this far along the way the player faces.
+    Teleport(f32),
+    /// Stands still to charge for this many ticks. Only works while standing.
+    Charge(FrameNumber),
+    /// Gives the player using it a buff.
+    SelfBuff(Buff),
+}
+
+/// How an `Ability` is used.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AbilityData {
+    /// The slot the ability is used from, below `ABILITY_SLOTS`.
+    pub slot: usize,
+    /// The number of ticks before the ability can be used again, counted from when it is used.
+    pub cooldown: FrameNumber,
+    /// The number of ticks between using the ability and its effect. Getting hit in between
+    /// cancels it.
+    pub startup: FrameNumber,
+    pub effect: AbilityEffect,
+}
+
+impl Ability {
+    pub fn data(&self) -> AbilityData {
+        match self {
+            Ability::Fireball => AbilityData {
+                slot: 0,
+                cooldown: mage::FIREBALL_COOLDOWN,
+                startup: 0,
+                effect: AbilityEffect::Projectile,
+            },
+            Ability::Teleport => AbilityData {
+                slot: 1,
+                cooldown: alien::TELEPORT_COOLDOWN,
+                startup: 0,
+                effect: AbilityEffect::Teleport(alien::TELEPORT_DISTANCE),
+            },
+            Ability::Repair => AbilityData {
+                slot: 0,
+                cooldown: robot::REPAIR_COOLDOWN,
+                startup: 0,
+                effect: AbilityEffect::Charge(robot::CHARGE_FRAMES),
+            },
+            Ability::Haste => AbilityData {
+                slot: 1,
+                cooldown: mage::HASTE_COOLDOWN,
+                startup: mage::HASTE_STARTUP,
+                effect: AbilityEffect::SelfBuff(Buff::SpeedUp {
+                    multiplier: mage::HASTE_MULTIPLIER,
+                    frames_left: mage::HASTE_FRAMES,
+                }),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod stats_test {
+    use super::*;
+
+    #[test]
+    fn races_differ() {
+        let (alien, robot, mage) = (Stats::for_race(&Race::Alien), Stats::for_race(&Race::Robot), Stats::for_race(&Race::Mage));
+        assert!(robot.max_air_jumps == 1);
+        assert!(robot.max_air_jumps < alien.max_air_jumps && alien.max_air_jumps < mage.max_air_jumps);
+        assert!(robot.weight > alien.weight && alien.weight > mage.weight);
+        assert!(robot.walk_speed < alien.walk_speed);
+    }
+
+    #[test]
+    fn damage_adds_up() {
+        let mut stats = Stats::default();
+        stats.take_damage(12.5);
+        stats.take_damage(7.5);
+        assert!(stats.damage_pct == 20.);
+    }
+
+    #[test]
+    fn losing_stocks() {
+        let mut stats = Stats::default();
+        stats.take_damage(80.);
+        assert!(stats.lose_stock());
+        assert!(stats.stocks == stats.max_stocks - 1);
+        assert!(stats.damage_pct == 0.);
+        assert!(stats.lose_stock());
+        assert!(!stats.lose_stock());
+        assert!(stats.stocks == 0);
+        assert!(!stats.lose_stock());
+        assert!(stats.stocks == 0);
+    }
+}
+
+#[cfg(test)]
+mod buff_test {
+    use super::*;
+
+    #[test]
+    fn buffs_count_down_to_expiry() {
+        let mut buff = Buff::Stun { frames_left: 2 };
+        assert!(buff.tick());
+        assert!(buff == Buff::Stun { frames_left: 1 });
+        assert!(!buff.tick());
+        assert!(!buff.tick());
+        assert!(buff == Buff::Stun { frames_left: 0 });
+        let mut armor = Buff::Armor;
+        assert!(armor.tick() && armor == Buff::Armor);
+    }
+
+    #[test]
+    fn same_kind_refreshes_instead_of_stacking() {
+        let mut buffs = vec![];
+        add_buff(&mut buffs, Buff::SpeedUp { multiplier: 2., frames_left: 10 });
+        add_buff(&mut buffs, Buff::Stun { frames_left: 5 });
+        add_buff(&mut buffs, Buff::SpeedUp { multiplier: 1.5, frames_left: 30 });
+        assert!(buffs == vec![Buff::Stun { frames_left: 5 }, Buff::SpeedUp { multiplier: 1.5, frames_left: 30 }]);
+    }
+}
+
+#[cfg(test)]
+mod frame_data_test {
+    use super::*;
+
+    #[test]
+    fn heavy_is_slower_and_stronger_than_light() {
+        let (light, heavy) = (BasicClass::Light.frame_data(), BasicClass::Heavy.frame_data());
+        assert!(light.startup < heavy.startup);
+        assert!(light.total() < heavy.total());
+        assert!(light.damage < heavy.damage);
+        assert!(light.knockback < heavy.knockback);
+    }
+
+    #[test]
+    fn phases_follow_frame_data() {
+        let data = FrameData { startup: 2, active: 1, recovery: 2, damage: 0., knockback: 0. };
+        let phases: Vec<_> = (0..6).map(|frame| data.phase(frame)).collect();
+        assert!(phases == vec![
+            Some(AttackPhase::Startup),
+            Some(AttackPhase::Startup),
+            Some(AttackPhase::Active),
+            Some(AttackPhase::Recovery),
+            Some(AttackPhase::Recovery),
+            None,
+        ]);
+    }
+
+    #[test]
+    fn active_window_covers_active_phase() {
+        let data = BasicClass::Heavy.frame_data();
+        let window = data.active_window();
+        for frame in 0..=data.total() {
+            assert!(window.contains(frame) == (data.phase(frame) == Some(AttackPhase::Active)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod handicap_test {
+    use super::*;
+
+    #[test]
+    fn handicap_default_is_neutral() {
+        let handicap = Handicap::default();
+        assert!(handicap.stocks(3) == 3);
+        assert!(handicap.scale_damage_dealt(12.) == 12.);
+        assert!(handicap.scale_knockback_taken(na::Vector2::new(1., -2.)) == na::Vector2::new(1., -2.));
+    }
+
+    #[test]
+    fn handicap_damage_dealt() {
+        let handicap = Handicap {
+            damage_dealt_multiplier: 1.5,
+            ..Default::default()
+        };
+        assert!(handicap.scale_damage_dealt(12.) == 18.);
+    }
+
+    #[test]
+    fn handicap_knockback_taken() {
+        let handicap = Handicap {
+            knockback_taken_multiplier: 0.5,
+            ..Default::default()
+        };
+        assert!(handicap.scale_knockback_taken(na::Vector2::new(4., -2.)) == na::Vector2::new(2., -1.));
+    }
+
+    #[test]
+    fn handicap_stocks() {
+        let fewer = Handicap {
+            stock_offset: -1,
+            ..Default::default()
+        };
+        let more = Handicap {
+            stock_offset: 1,
+            ..Default::default()
+        };
+        assert!(fewer.stocks(3) == 2);
+        assert!(more.stocks(3) == 4);
+        assert!(fewer.stocks(1) == 1);
+    }
+
+    #[test]
+    fn handicap_labels() {
+        let labels: Vec<_> = Handicap::presets().iter().map(Handicap::label).collect();
+        assert!(labels == vec!["No handicap", "+1 stock", "+2 stocks", "-1 stock", "Starts at 50%", "Starts at 100%"]);
+        let mixed = Handicap {
+            stock_offset: 1,
+            knockback_taken_multiplier: 0.5,
+            ..Default::default()
+        };
+        assert!(mixed.label() == "+1 stock, takes 50% knockback");
+    }
 }
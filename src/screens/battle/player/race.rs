@@ -0,0 +1,29 @@
+//! What sets each race apart beyond its stats.
+use super::meta::{Ability, Buff, Race};
+
+pub mod alien;
+pub mod mage;
+pub mod robot;
+
+/// Abilities that work all the time, without the player doing anything.
+pub trait Passive {
+    /// Runs once a tick, after physics has been resolved.
+    fn passive_tick(&mut self);
+}
+
+/// The abilities of every character of `race`.
+pub fn abilities(race: &Race) -> Vec<Ability> {
+    match race {
+        Race::Alien => vec![Ability::Teleport],
+        Race::Mage => vec![Ability::Fireball, Ability::Haste],
+        Race::Robot => vec![Ability::Repair],
+    }
+}
+
+/// The buffs a player of `race` starts every stock with.
+pub fn starting_buffs(race: &Race) -> Vec<Buff> {
+    match race {
+        Race::Robot => vec![Buff::Armor],
+        Race::Alien | Race::Mage => vec![],
+    }
+}
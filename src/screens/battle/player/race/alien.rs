@@ -0,0 +1,42 @@
+//! Aliens heal over time, as long as they stay out of harm's way, and can teleport a short way
+//! ahead through anything in between.
+use crate::screens::battle::player::FrameNumber;
+
+/// The damage healed at a time, in percent.
+pub const REGEN_RATE: f32 = 1.;
+/// The number of ticks between heals.
+pub const REGEN_INTERVAL: u32 = 60;
+/// The number of ticks after taking damage before healing starts.
+pub const REGEN_PAUSE_FRAMES: u32 = 180;
+
+/// How far a teleport moves the alien along the way they face.
+pub const TELEPORT_DISTANCE: f32 = 120.;
+/// The number of ticks an alien has no hitboxes for after teleporting.
+pub const TELEPORT_INVINCIBLE_FRAMES: FrameNumber = 1;
+/// The number of ticks between teleports.
+pub const TELEPORT_COOLDOWN: FrameNumber = 120;
+
+/// The damage healed on the tick `frames_since_damage` ticks after last taking damage.
+pub fn regen(frames_since_damage: u32) -> f32 {
+    match frames_since_damage.checked_sub(REGEN_PAUSE_FRAMES) {
+        Some(since_pause) if since_pause > 0 && since_pause % REGEN_INTERVAL == 0 => REGEN_RATE,
+        _ => 0.,
+    }
+}
+
+#[cfg(test)]
+mod alien_test {
+    use super::*;
+
+    #[test]
+    fn heals_every_interval_after_pause() {
+        let healed_on: Vec<_> = (0..REGEN_PAUSE_FRAMES + 3 * REGEN_INTERVAL + 1)
+            .filter(|frame| regen(*frame) > 0.)
+            .collect();
+        assert!(healed_on == vec![
+            REGEN_PAUSE_FRAMES + REGEN_INTERVAL,
+            REGEN_PAUSE_FRAMES + 2 * REGEN_INTERVAL,
+            REGEN_PAUSE_FRAMES + 3 * REGEN_INTERVAL,
+        ]);
+    }
+}
@@ -0,0 +1,22 @@
+//! Mages hurl fireballs: bigger, faster and stronger than a ranged attack, but slower to repeat.
+//!
+//! Mages can also cast haste on themselves to walk faster for a while, once they're done casting.
+use crate::screens::battle::player::FrameNumber;
+
+/// The horizontal speed of a fireball.
+pub const FIREBALL_SPEED: f32 = 6.;
+/// The damage a fireball deals on hit, in percent.
+pub const FIREBALL_DAMAGE: f32 = 12.;
+/// The width and height of a fireball.
+pub const FIREBALL_SIZE: f32 = 14.;
+/// The number of ticks between fireballs.
+pub const FIREBALL_COOLDOWN: FrameNumber = 90;
+
+/// How much faster haste makes a mage walk.
+pub const HASTE_MULTIPLIER: f32 = 1.5;
+/// The number of ticks haste lasts for.
+pub const HASTE_FRAMES: FrameNumber = 180;
+/// The number of ticks spent casting haste.
+pub const HASTE_STARTUP: FrameNumber = 15;
+/// The number of ticks between casting haste.
+pub const HASTE_COOLDOWN: FrameNumber = 240;
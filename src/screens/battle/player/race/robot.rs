@@ -0,0 +1,16 @@
+//! Robots start every stock armored. The armor takes the damage of the first hit and breaks.
+//!
+//! Robots can also stand still to repair themselves, with their armor down until they're done.
+use ggez::graphics::Color;
+
+use crate::screens::battle::player::FrameNumber;
+
+/// Tints robots while their armor holds.
+pub const ARMOR_COLOR: Color = Color { r: 0.7, g: 0.8, b: 1., a: 1. };
+
+/// The damage a repair heals, in percent.
+pub const REPAIR_AMOUNT: f32 = 20.;
+/// The number of ticks a repair takes, standing still and unarmored.
+pub const CHARGE_FRAMES: FrameNumber = 90;
+/// The number of ticks between repairs.
+pub const REPAIR_COOLDOWN: FrameNumber = 240;
@@ -1,12 +1,25 @@
+use serde::{Serialize, Deserialize};
+
 use super::action::Attack;
+use super::FrameNumber;
 
 /// Whether the player character faces left or right.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum HorizontalStance {
     Left,
     Right,
 }
 
+impl HorizontalStance {
+    /// -1 for left and 1 for right, for scaling horizontal movement.
+    pub fn sign(&self) -> f32 {
+        match self {
+            HorizontalStance::Left => -1.,
+            HorizontalStance::Right => 1.,
+        }
+    }
+}
+
 /// What actions are currently being animated. As well as a bit of state.
 #[derive(Debug)]
 pub enum VerticalStance {
@@ -23,6 +36,10 @@ pub enum AirStance {
     FastFalling,
     Falling,
     Upping,
+    /// Rising after jumping off a wall.
+    WallJumping,
+    /// Launched by a strong hit. Landing like this knocks the player down unless they tech.
+    Tumbling,
     Attack(Attack),
 }
 
@@ -30,5 +47,13 @@ pub enum AirStance {
 #[derive(Debug)]
 pub enum GroundStance {
     Standing,
+    /// Dashing, with the number of ticks left in the dash.
+    Dashing(FrameNumber),
+    /// Rolling out of a hard landing, with the number of ticks left in the roll.
+    TechRolling(FrameNumber),
+    /// Lying on the ground after missing a tech, with the number of ticks left until getting up.
+    KnockedDown(FrameNumber),
+    /// Standing still to use an ability, with the number of ticks left until it takes effect.
+    Charging(FrameNumber),
     Attack(Attack),
 }
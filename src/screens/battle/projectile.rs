@@ -0,0 +1,144 @@
+use ggez::{Context, GameResult};
+use ggez::graphics::{Drawable, DrawParam, Rect, BlendMode};
+use ggez::nalgebra as na;
+
+use crate::{
+    physics::{BoundingBox, Collidable, HitboxTag, collision::Mergeable, drawn_at},
+    screens::battle::{
+        layer,
+        player::{action::Attack, meta::Ability, race::mage, stance::HorizontalStance},
+    },
+};
+
+/// The horizontal speed of a fired projectile.
+const SPEED: f32 = 4.;
+/// The number of ticks a projectile flies for before vanishing.
+const LIFETIME: u32 = 90;
+/// The damage dealt on hit, in percent.
+pub const DAMAGE: f32 = 5.;
+/// The width and height of a projectile.
+const SIZE: f32 = 8.;
+
+/// Something fired by a player, which flies in a straight line until it hits something.
+#[derive(Debug)]
+pub struct Projectile {
+    /// `ggez`-specific. Not used for anything atm.
+    mode: Option<BlendMode>,
+    pub position: na::Vector2<f32>,
+    pub velocity: na::Vector2<f32>,
+    /// The index of the player who fired the projectile. Projectiles never hit their owner.
+    pub owner: usize,
+    /// Ticks left until the projectile vanishes.
+    pub lifetime: u32,
+    /// The damage dealt on hit, in percent.
+    pub damage: f32,
+    /// The attack that fired the projectile.
+    pub attack: Attack,
+    body: BoundingBox,
+    /// Whether the projectile hit something and should be removed.
+    hit: bool,
+}
+
+impl Projectile {
+    /// Fires a projectile from `position`, centered vertically on it.
+    pub fn new(owner: usize, position: na::Vector2<f32>, direction: &HorizontalStance) -> Self {
+        Self::launch(owner, position, direction, SPEED, SIZE, DAMAGE, Attack::Ranged)
+    }
+
+    /// Hurls a mage's fireball from `position`, centered vertically on it.
+    pub fn fireball(owner: usize, position: na::Vector2<f32>, direction: &HorizontalStance) -> Self {
+        let attack = Attack::Ability(Ability::Fireball);
+        Self::launch(owner, position, direction, mage::FIREBALL_SPEED, mage::FIREBALL_SIZE, mage::FIREBALL_DAMAGE, attack)
+    }
+
+    fn launch(
+        owner: usize,
+        position: na::Vector2<f32>,
+        direction: &HorizontalStance,
+        speed: f32,
+        size: f32,
+        damage: f32,
+        attack: Attack,
+    ) -> Self {
+        Projectile {
+            mode: None,
+            position: position - na::Vector2::new(size, size) / 2.,
+            velocity: na::Vector2::new(direction.sign() * speed, 0.),
+            owner,
+            lifetime: LIFETIME,
+            damage,
+            attack,
+            body: BoundingBox {
+                mode: None,
+                tag: HitboxTag::Hit,
+                pos: na::Vector2::zeros(),
+                size: na::Vector2::new(size, size),
+                ori: 0.,
+            },
+            hit: false,
+        }
+    }
+
+    /// Whether the projectile should be removed, having hit something or run out of time.
+    pub fn is_spent(&self) -> bool {
+        self.hit || self.lifetime == 0
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Changes {
+    pub hit: bool,
+}
+
+impl Mergeable for Changes {
+    fn merge(&self, other: &Self) -> Self {
+        Changes {
+            hit: self.hit || other.hit,
+        }
+    }
+}
+
+impl Collidable for Projectile {
+    type ChangeSet = Changes;
+
+    fn get_hitboxes<'tick>(&'tick self) -> &'tick[BoundingBox] {
+        std::slice::from_ref(&self.body)
+    }
+    fn apply_changeset(&mut self, changes: Self::ChangeSet) {
+        self.hit |= changes.hit;
+    }
+    fn handle_phys_update(&mut self) {
+        self.position += self.velocity;
+        self.lifetime = self.lifetime.saturating_sub(1);
+    }
+    fn get_offset(&self) -> na::Vector2<f32> {
+        self.position
+    }
+    fn get_velocity(&self) -> na::Vector2<f32> {
+        self.velocity
+    }
+    fn collision_layer(&self) -> u32 {
+        layer::PROJECTILE
+    }
+    fn collision_mask(&self) -> u32 {
+        layer::PROJECTILE_MASK
+    }
+}
+
+impl Drawable for Projectile {
+    fn draw(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
+        self.body.draw(ctx, drawn_at(param, self.position))
+    }
+
+    fn dimensions(&self, _ctx: &mut Context) -> Option<Rect> {
+        None
+    }
+
+    fn set_blend_mode(&mut self, mode: Option<BlendMode>) {
+        self.mode = mode;
+    }
+
+    fn blend_mode(&self) -> Option<BlendMode> {
+        self.mode
+    }
+}
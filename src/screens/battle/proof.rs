@@ -0,0 +1,290 @@
+//! Verifiable records of combo trial runs, so that completion times can be compared.
+//!
+//! A `RunProof` holds hashes of the content a run was played on along with every input made
+//! during it. A run is verified by replaying its inputs headlessly against the local content and
+//! checking that the trial is completed on exactly the claimed tick. Runs played on different
+//! content, or whose replay diverges from the claim, are rejected.
+use ron::de::from_reader;
+use ron::ser::{to_string_pretty, PrettyConfig};
+use serde::{Serialize, Deserialize};
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+
+use crate::{
+    persistence::{PersistenceWorker, WriteJob, WritePriority},
+    util::{hash, result::{WalpurgisError, WalpurgisResult}},
+    screens::battle::{
+        TickInput,
+        arena::Arena,
+        player::{Player, action::Action, blueprint::PlayerBlueprint, inputs::InputScheme},
+        trial::{ComboTrial, Tick, TrialRun, TrialStatus},
+        tuning::Tuning,
+    },
+};
+
+/// The name of the file the proof of the last trial completed is saved to, next to the save.
+pub const LATEST_PROOF: &str = "latest_run_proof.ron";
+
+/// How a run proof fails to hold up against the local content.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProofMismatch {
+    /// The run was played on a different arena.
+    Arena,
+    /// The run was played on a different version of the trial with this name.
+    Trial(String),
+    /// The run was played with a different character.
+    Character,
+    /// The replay completes the trial, but not on the claimed tick.
+    CompletionTick { claimed: Tick, actual: Tick },
+    /// The replay hasn't completed the trial by `tick`.
+    Incomplete { tick: Tick, status: TrialStatus },
+}
+
+impl fmt::Display for ProofMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProofMismatch::Arena => write!(f, "The run was played on a different arena."),
+            ProofMismatch::Trial(name) => write!(f, "The run was played on a different version of trial `{}`.", name),
+            ProofMismatch::Character => write!(f, "The run was played with a different character."),
+            ProofMismatch::CompletionTick { claimed, actual } => {
+                write!(f, "The run claims to complete on tick {} but completes on tick {}.", claimed, actual)
+            },
+            ProofMismatch::Incomplete { tick, status } => {
+                write!(f, "The run is not complete by tick {}: {:?}", tick, status)
+            },
+        }
+    }
+}
+
+/// The actions performed and let go of on a single tick of a run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TracedInput {
+    pub tick: Tick,
+    pub actions: Vec<Action>,
+    #[serde(default)]
+    pub released: Vec<Action>,
+}
+
+/// Everything needed to check a claimed completion of a trial.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunProof {
+    pub arena_hash: u64,
+    pub trial_hash: u64,
+    pub character_hash: u64,
+    /// The runner's inputs. Ticks without any actions are left out.
+    pub inputs: Vec<TracedInput>,
+    /// The tick on which the last step of the trial was completed.
+    pub completion_tick: Tick,
+}
+
+impl RunProof {
+    /// Records `run`, which was completed on the last tick played.
+    pub fn of(run: &TrialRun) -> WalpurgisResult<Self> {
+        Ok(Self {
+            arena_hash: hash::content_hash(run.arena())?,
+            trial_hash: hash::content_hash(run.trial())?,
+            character_hash: character_hash(run.blueprint())?,
+            inputs: run.trace().to_vec(),
+            completion_tick: run.elapsed_ticks().saturating_sub(1),
+        })
+    }
+
+    /// Tries to load a `RunProof` from the given file. Errors name the file.
+    pub fn load<P: AsRef<Path>>(proof_file: P) -> WalpurgisResult<Self> {
+        let proof_file = proof_file.as_ref();
+        let f = File::open(proof_file).map_err(|e| WalpurgisError::reading(proof_file, "run proof", e))?;
+        from_reader(f).map_err(|e| WalpurgisError::parsing(proof_file, "run proof", e))
+    }
+
+    /// Queues the proof to be written to `proof_file` by `persistence`.
+    pub fn save<P: AsRef<Path>>(&self, proof_file: P, persistence: &PersistenceWorker) -> WalpurgisResult {
+        let serialized = to_string_pretty(self, PrettyConfig::default())
+            .map_err(|e| format!("Failed to serialize run proof: {}", e))?;
+        let job = WriteJob::new(proof_file.as_ref(), serialized.into_bytes(), WritePriority::Low).atomic();
+        if !persistence.enqueue(job) {
+            Err(format!("Dropped run proof write to `{}`.", proof_file.as_ref().display()))?
+        }
+        Ok(())
+    }
+
+    /// Checks the run against `arena` and `trial`, as loaded from the local files, played with
+    /// `blueprint` tuned by `tuning`. Returns the completion tick if the run is legitimate.
+    pub fn verify(
+        &self,
+        arena: Arena,
+        trial: ComboTrial,
+        blueprint: &PlayerBlueprint,
+        tuning: &Tuning,
+    ) -> WalpurgisResult<Tick> {
+        if hash::content_hash(&arena)? != self.arena_hash {
+            Err(ProofMismatch::Arena)?
+        }
+        if hash::content_hash(&trial)? != self.trial_hash {
+            Err(ProofMismatch::Trial(trial.name.clone()))?
+        }
+        let mut run = TrialRun::headless(trial, blueprint, tuning, arena)?;
+        if character_hash(run.blueprint())? != self.character_hash {
+            Err(ProofMismatch::Character)?
+        }
+
+        match replay(&mut run, &self.inputs, self.completion_tick) {
+            (TrialStatus::Complete, tick) if tick == self.completion_tick => Ok(tick),
+            (TrialStatus::Complete, actual) => Err(ProofMismatch::CompletionTick { claimed: self.completion_tick, actual })?,
+            (status, tick) => Err(ProofMismatch::Incomplete { tick, status })?,
+        }
+    }
+}
+
+/// A hash of the character built from `blueprint`, as it starts a run.
+fn character_hash(blueprint: &PlayerBlueprint) -> WalpurgisResult<u64> {
+    Player::without_sprites(blueprint, InputScheme::default()).character_hash()
+}
+
+/// Plays `inputs` through `run`, stopping once the trial is finished or `last_tick` has been
+/// simulated. Returns the final status and the tick it was reached on.
+pub fn replay(run: &mut TrialRun, inputs: &[TracedInput], last_tick: Tick) -> (TrialStatus, Tick) {
+    for tick in 0..=last_tick {
+        let mut input = TickInput::default();
+        for traced in inputs.iter().filter(|input| input.tick == tick) {
+            input.actions.extend(traced.actions.iter().cloned());
+            input.released.extend(traced.released.iter().cloned());
+        }
+        let status = run.tick(input);
+        if !matches!(status, TrialStatus::InProgress(_)) {
+            return (status, tick);
+        }
+    }
+    (run.validator().status().clone(), last_tick)
+}
+
+#[cfg(test)]
+mod proof_test {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use crate::{
+        persistence::{self, RealFileSystem},
+        screens::battle::player::meta::{Race, Stats},
+        skill_tree::StatField,
+    };
+
+    const ARENA: &str = "(
+        name: \"Proof\",
+        platforms: [(body: (pos: [-100, 100], size: [300, 10], ori: 0), can_move_through: false)],
+        spawn_points: [[0, 70], [40, 70]],
+    )";
+    const TRIAL: &str = "(
+        name: \"Proof\",
+        race: Alien,
+        dummy_position: [-40, 70],
+        dummy_damage: 10,
+        steps: [
+            (action: Walk(Left), window: 0),
+            (action: Attack(Basics(Light, Side)), window: 30),
+        ],
+    )";
+
+    /// Writes `contents` to a file unique to the test.
+    fn write_temp(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("walpurgis_{}_{}.ron", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn inputs() -> Vec<TracedInput> {
+        use crate::screens::battle::player::{action::{Attack, AttackDir}, meta::BasicClass, stance::HorizontalStance};
+        vec![
+            TracedInput { tick: 10, actions: vec![Action::Walk(HorizontalStance::Left)], released: vec![] },
+            TracedInput {
+                tick: 15,
+                actions: vec![Action::Attack(Attack::Basics(BasicClass::Light, AttackDir::Side))],
+                released: vec![],
+            },
+        ]
+    }
+
+    fn blueprint() -> PlayerBlueprint {
+        PlayerBlueprint::placeholder(Race::Alien)
+    }
+
+    /// Verifies `proof` against the arena and trial in the given files.
+    fn verify(proof: &RunProof, arena_file: &Path, trial_file: &Path, blueprint: &PlayerBlueprint) -> WalpurgisResult<Tick> {
+        let (arena, trial) = (Arena::load(arena_file)?, ComboTrial::load(trial_file)?);
+        proof.verify(arena, trial, blueprint, &Tuning::default())
+    }
+
+    /// The mismatch a verification failed with.
+    fn mismatch(verified: WalpurgisResult<Tick>) -> ProofMismatch {
+        match verified {
+            Err(WalpurgisError::ProofMismatch(mismatch)) => mismatch,
+            other => panic!("Expected a proof mismatch, got {:?}", other),
+        }
+    }
+
+    /// Plays the inputs through a run and records a proof of it.
+    fn record(name: &str) -> (RunProof, PathBuf, PathBuf) {
+        let arena_file = write_temp(&format!("{}_arena", name), ARENA);
+        let trial_file = write_temp(&format!("{}_trial", name), TRIAL);
+        let arena = Arena::load(&arena_file).unwrap();
+        let trial = ComboTrial::load(&trial_file).unwrap();
+        let mut run = TrialRun::headless(trial, &blueprint(), &Tuning::default(), arena).unwrap();
+        let (status, _) = replay(&mut run, &inputs(), 600);
+        assert!(status == TrialStatus::Complete);
+        let proof = RunProof::of(&run).unwrap();
+        // Only the ticks with inputs are traced.
+        assert!(proof.inputs == inputs());
+        (proof, arena_file, trial_file)
+    }
+
+    #[test]
+    fn recorded_run_verifies() {
+        let (proof, arena_file, trial_file) = record("verifies");
+        // The attack starts on tick 15 and lands once its startup is over.
+        assert!(proof.completion_tick == 18);
+        let proof_file = write_temp("verifies_proof", "");
+        let mut worker = PersistenceWorker::new(RealFileSystem, persistence::DEFAULT_CAPACITY);
+        proof.save(&proof_file, &worker).unwrap();
+        assert!(worker.shutdown(Duration::from_secs(10)) == 0);
+        let loaded = RunProof::load(&proof_file).unwrap();
+        assert!(loaded == proof);
+        assert!(verify(&loaded, &arena_file, &trial_file, &blueprint()).unwrap() == 18);
+    }
+
+    #[test]
+    fn tampered_tick_rejected() {
+        let (mut proof, arena_file, trial_file) = record("tampered");
+        proof.completion_tick -= 1;
+        assert!(matches!(mismatch(verify(&proof, &arena_file, &trial_file, &blueprint())), ProofMismatch::Incomplete { tick: 17, .. }));
+        proof.completion_tick += 2;
+        let late = mismatch(verify(&proof, &arena_file, &trial_file, &blueprint()));
+        assert!(late == ProofMismatch::CompletionTick { claimed: 19, actual: 18 });
+    }
+
+    #[test]
+    fn tampered_inputs_rejected() {
+        let (mut proof, arena_file, trial_file) = record("inputs");
+        proof.inputs.pop();
+        assert!(matches!(mismatch(verify(&proof, &arena_file, &trial_file, &blueprint())), ProofMismatch::Incomplete { .. }));
+    }
+
+    #[test]
+    fn modified_arena_rejected() {
+        let (proof, arena_file, trial_file) = record("modified");
+        // Reformatting doesn't change the content.
+        fs::write(&arena_file, ARENA.split_whitespace().collect::<Vec<_>>().join(" ")).unwrap();
+        assert!(verify(&proof, &arena_file, &trial_file, &blueprint()).is_ok());
+        fs::write(&arena_file, ARENA.replace("[300, 10]", "[300, 11]")).unwrap();
+        assert!(mismatch(verify(&proof, &arena_file, &trial_file, &blueprint())) == ProofMismatch::Arena);
+    }
+
+    #[test]
+    fn other_character_rejected() {
+        let (proof, arena_file, trial_file) = record("character");
+        let mut stats = Stats::for_race(&Race::Alien);
+        stats.set(StatField::Weight, stats.weight() * 2.);
+        let heavier = PlayerBlueprint { stats: Some(stats), ..blueprint() };
+        assert!(mismatch(verify(&proof, &arena_file, &trial_file, &heavier)) == ProofMismatch::Character);
+    }
+}
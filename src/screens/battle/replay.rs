@@ -0,0 +1,193 @@
+//! Recordings of battles that play back exactly.
+//!
+//! A `Replay` holds what a battle was started with, the arena, the tuning, the rounds and each
+//! player's character and handicap, along with the inputs of every tick. Ticks depend on nothing else, so feeding the recorded inputs to a
+//! battle started the same way plays it out again tick for tick.
+use ron::de::from_str;
+use ron::ser::{to_string_pretty, PrettyConfig};
+use serde::{Serialize, Deserialize};
+use std::fs;
+use std::path::Path;
+
+use crate::{
+    persistence::{PersistenceWorker, WriteJob, WritePriority},
+    settings::MatchConfig,
+    util::{hash, result::{WalpurgisError, WalpurgisResult}},
+    screens::battle::{
+        TickInput,
+        arena::Arena,
+        player::{blueprint::PlayerBlueprint, meta::Handicap},
+        tuning::Tuning,
+    },
+};
+
+/// The version of the replay format. Bumped whenever older replays would stop playing back the
+/// same way.
+pub const REPLAY_VERSION: u32 = 1;
+
+/// Where the last battle recorded is saved once it's over.
+pub const LATEST_REPLAY: &str = "latest_replay.ron";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    /// The version of the format the replay was recorded in.
+    pub version: u32,
+    /// The name of the arena the battle was played in.
+    pub arena: String,
+    /// The hash of the arena's contents, so that battles aren't played back in a changed arena.
+    pub arena_hash: u64,
+    /// Each player's character, in order.
+    pub blueprints: Vec<PlayerBlueprint>,
+    /// The gravity the battle was played with. Replays recorded before tuning was loaded were
+    /// played with the defaults.
+    #[serde(default)]
+    pub tuning: Tuning,
+    /// The rounds the battle was played in. Replays recorded before battles had rounds were
+    /// played in a single one.
+    #[serde(default)]
+    pub rounds: MatchConfig,
+    /// Each player's handicap, in order. Replays recorded before handicaps were played without
+    /// any.
+    #[serde(default)]
+    pub handicaps: Vec<Handicap>,
+    /// The inputs of every tick, in order.
+    pub ticks: Vec<Vec<TickInput>>,
+}
+
+/// Only the version of a replay, which can be read from replays of any version.
+#[derive(Deserialize)]
+struct Versioned {
+    version: u32,
+}
+
+impl Replay {
+    /// An empty recording of a battle in `arena` between `blueprints` with `handicaps`, played
+    /// with `tuning`.
+    pub fn new(
+        arena: &Arena,
+        blueprints: Vec<PlayerBlueprint>,
+        handicaps: Vec<Handicap>,
+        tuning: Tuning,
+        rounds: MatchConfig,
+    ) -> WalpurgisResult<Self> {
+        Ok(Self {
+            version: REPLAY_VERSION,
+            arena: arena.name().to_owned(),
+            arena_hash: hash::content_hash(arena)?,
+            blueprints,
+            tuning,
+            rounds,
+            handicaps,
+            ticks: vec![],
+        })
+    }
+
+    /// Tries to load a `Replay` from the given file. Errors name the file, and replays recorded
+    /// in other versions of the format are rejected.
+    pub fn load<P: AsRef<Path>>(replay_file: P) -> WalpurgisResult<Self> {
+        let replay_file = replay_file.as_ref();
+        let contents = fs::read_to_string(replay_file).map_err(|e| WalpurgisError::reading(replay_file, "replay", e))?;
+        Self::parse(&contents, replay_file)
+    }
+
+    /// Parses the contents of a replay file, `source`. Replays that don't parse are blamed on their
+    /// version when it isn't this one.
+    fn parse(contents: &str, source: &Path) -> WalpurgisResult<Self> {
+        let e = match from_str::<Self>(contents) {
+            Ok(replay) if replay.version == REPLAY_VERSION => return Ok(replay),
+            Ok(replay) => return Err(Self::unsupported(source, replay.version)),
+            Err(e) => e,
+        };
+        match from_str::<Versioned>(contents) {
+            Ok(Versioned { version }) if version != REPLAY_VERSION => Err(Self::unsupported(source, version)),
+            _ => Err(WalpurgisError::parsing(source, "replay", e)),
+        }
+    }
+
+    fn unsupported(source: &Path, found: u32) -> WalpurgisError {
+        WalpurgisError::UnsupportedVersion {
+            path: source.to_path_buf(),
+            what: "replay",
+            found,
+            supported: REPLAY_VERSION,
+        }
+    }
+
+    /// Queues the replay to be written to `replay_file` by `persistence`.
+    pub fn save<P: AsRef<Path>>(&self, replay_file: P, persistence: &PersistenceWorker) -> WalpurgisResult {
+        let serialized = to_string_pretty(self, PrettyConfig::default())
+            .map_err(|e| format!("Failed to serialize replay: {}", e))?;
+        let job = WriteJob::new(replay_file.as_ref(), serialized.into_bytes(), WritePriority::Normal).atomic();
+        if !persistence.enqueue(job) {
+            Err(format!("Dropped replay write to `{}`.", replay_file.as_ref().display()))?
+        }
+        Ok(())
+    }
+
+    /// Checks that `arena` is the one the battle was played in.
+    pub fn check_arena(&self, arena: &Arena) -> WalpurgisResult {
+        if arena.name() != self.arena || hash::content_hash(arena)? != self.arena_hash {
+            Err(format!("The replay was played on a different version of arena `{}`.", self.arena))?
+        }
+        Ok(())
+    }
+}
+
+/// Hands out the recorded inputs of a replay, a tick at a time.
+#[derive(Debug)]
+pub struct Playback {
+    ticks: std::vec::IntoIter<Vec<TickInput>>,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        Self { ticks: replay.ticks.into_iter() }
+    }
+
+    /// The inputs of the next tick. Once the recording is over, nobody does anything.
+    pub fn next_tick(&mut self) -> Vec<TickInput> {
+        self.ticks.next().unwrap_or_default()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.ticks.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod replay_test {
+    use super::*;
+
+    fn arena() -> Arena {
+        ron::de::from_str("(name: \"Empty\", platforms: [])").unwrap()
+    }
+
+    fn serialized(replay: &Replay) -> String {
+        to_string_pretty(replay, PrettyConfig::default()).unwrap()
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut replay = Replay::new(&arena(), vec![], vec![], Tuning::default(), MatchConfig::default()).unwrap();
+        replay.ticks.push(TickInput::only(1, vec![crate::screens::battle::player::action::Action::Jump]));
+        let parsed = Replay::parse(&serialized(&replay), Path::new("replay.ron")).unwrap();
+        assert!(parsed.ticks == replay.ticks);
+        assert!(parsed.check_arena(&arena()).is_ok());
+        let renamed: Arena = ron::de::from_str("(name: \"Other\", platforms: [])").unwrap();
+        assert!(parsed.check_arena(&renamed).is_err());
+    }
+
+    #[test]
+    fn other_versions_rejected() {
+        let mut replay = Replay::new(&arena(), vec![], vec![], Tuning::default(), MatchConfig::default()).unwrap();
+        replay.version = REPLAY_VERSION + 1;
+        let message = match Replay::parse(&serialized(&replay), Path::new("old_replay.ron")) {
+            Err(e @ WalpurgisError::UnsupportedVersion { .. }) => e.to_string(),
+            other => panic!("Expected a version error, got {:?}", other),
+        };
+        assert!(message.contains("old_replay.ron") && message.contains(&(REPLAY_VERSION + 1).to_string()));
+        // Even if the rest of the format changed entirely.
+        let future = format!("(version: {}, frames: {{}})", REPLAY_VERSION + 1);
+        assert!(matches!(Replay::parse(&future, Path::new("x.ron")), Err(WalpurgisError::UnsupportedVersion { .. })));
+    }
+}
@@ -0,0 +1,41 @@
+use ggez::nalgebra as na;
+
+use crate::screens::battle::player::action::Attack;
+
+/// A melee attack in progress. Lands wherever the attacker's `Hit` boxes overlap another player's
+/// `Hurt` boxes.
+#[derive(Debug)]
+pub struct Strike {
+    /// The index of the attacking player. Strikes never hit their owner.
+    pub owner: usize,
+    pub attack: Attack,
+    /// Where the attacker stands. Hits knock away from here.
+    pub position: na::Vector2<f32>,
+    /// The damage dealt on hit, in percent.
+    pub damage: f32,
+    /// The knockback dealt on hit at 0% damage.
+    pub knockback: f32,
+    /// Which of the owner's attacks this is, so that a swing lasting several ticks only hits each
+    /// player once.
+    pub instance: u32,
+}
+
+impl Strike {
+    pub fn new(
+        owner: usize,
+        attack: Attack,
+        position: na::Vector2<f32>,
+        damage: f32,
+        knockback: f32,
+        instance: u32,
+    ) -> Self {
+        Strike {
+            owner,
+            attack,
+            position,
+            damage,
+            knockback,
+            instance,
+        }
+    }
+}
@@ -0,0 +1,282 @@
+//! Trails showing where each player has been over the last few seconds.
+//!
+//! Trails are drawn from what the battle recorded every tick rather than from the players
+//! themselves, so they look the same whenever recorded ticks are played back. The path is thinned
+//! out before drawing, colored by speed from cool to hot, and marked every few ticks and wherever
+//! the player jumped or got hit. That work is done by `Trail::prepare` before drawing, so that
+//! drawing only turns the prepared path into a mesh.
+use ggez::{Context, GameResult};
+use ggez::graphics::{Color, DrawMode, Mesh, MeshBuilder};
+use ggez::nalgebra as na;
+use std::collections::VecDeque;
+
+use crate::physics::screen_point as point;
+use crate::walpurgis::TICKS_PER_SECOND;
+
+type V2 = na::Vector2<f32>;
+
+/// The number of ticks a trail reaches back.
+pub const TRAIL_TICKS: usize = 3 * TICKS_PER_SECOND as usize;
+/// Ticks between the markers along a trail.
+pub const TICK_MARKER_INTERVAL: u64 = 10;
+/// How far a point may stray from a straight line through its neighbours and still be dropped.
+pub const SIMPLIFY_TOLERANCE: f32 = 0.5;
+/// The speed at which trails are the hottest color. About a dash.
+const HOT_SPEED: f32 = 6.;
+
+const LINE_WIDTH: f32 = 2.;
+const MARKER_RADIUS: f32 = 2.;
+const ICON_SIZE: f32 = 5.;
+
+/// Something that happened to a player worth marking on their trail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrailEvent {
+    Jump,
+    Hit,
+}
+
+/// A player's movement on one tick.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrailSample {
+    pub tick: u64,
+    pub position: V2,
+    pub speed: f32,
+    pub events: Vec<TrailEvent>,
+}
+
+/// What gets drawn of a trail.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TrailPath {
+    /// Lines between the samples left after simplifying, with the color of their speed.
+    pub segments: Vec<(V2, V2, Color)>,
+    pub tick_markers: Vec<V2>,
+    pub event_markers: Vec<(V2, TrailEvent)>,
+}
+
+/// The samples of a player's last `TRAIL_TICKS` ticks.
+#[derive(Debug, Clone, Default)]
+pub struct Trail {
+    samples: VecDeque<TrailSample>,
+    /// Worked out from `samples` by `prepare`. Forgotten whenever the samples change.
+    path: Option<TrailPath>,
+}
+
+impl Trail {
+    /// Adds the latest tick, forgetting the oldest one once the trail is full.
+    pub fn record(&mut self, sample: TrailSample) {
+        if self.samples.len() == TRAIL_TICKS {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+        self.path = None;
+    }
+
+    /// Forgets everything, e.g. after respawning so the trail doesn't cross the screen.
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.path = None;
+    }
+
+    pub fn samples(&self) -> Vec<TrailSample> {
+        self.samples.iter().cloned().collect()
+    }
+
+    /// Works out the path to draw, if the samples changed since it was last worked out.
+    pub fn prepare(&mut self) {
+        if self.path.is_some() {
+            return;
+        }
+        let samples = self.samples();
+        let kept = simplify(&samples, SIMPLIFY_TOLERANCE);
+        let segments = kept.windows(2)
+            .map(|pair| {
+                let (from, to) = (&samples[pair[0]], &samples[pair[1]]);
+                (from.position, to.position, speed_color((from.speed + to.speed) / 2., HOT_SPEED))
+            })
+            .collect();
+        self.path = Some(TrailPath {
+            segments,
+            tick_markers: tick_markers(&samples, TICK_MARKER_INTERVAL),
+            event_markers: event_markers(&samples),
+        });
+    }
+
+    /// Builds the prepared path as one mesh, or nothing if there isn't a prepared path to draw.
+    pub fn mesh(&self, ctx: &mut Context) -> GameResult<Option<Mesh>> {
+        let path = match &self.path {
+            Some(path) if !path.segments.is_empty() => path,
+            _ => return Ok(None),
+        };
+        let mut builder = MeshBuilder::new();
+        for (from, to, color) in &path.segments {
+            builder.line(&[point(*from), point(*to)], LINE_WIDTH, *color)?;
+        }
+        for position in &path.tick_markers {
+            builder.circle(DrawMode::fill(), point(*position), MARKER_RADIUS, 0.5, Color::new(1., 1., 1., 0.8));
+        }
+        for (position, event) in &path.event_markers {
+            event_icon(&mut builder, *position, *event)?;
+        }
+        Ok(Some(builder.build(ctx)?))
+    }
+}
+
+/// The indices of the samples left after dropping every one that lies within `tolerance` of a
+/// straight line from the last one kept to the next one. The first and last samples are always
+/// kept.
+pub fn simplify(samples: &[TrailSample], tolerance: f32) -> Vec<usize> {
+    if samples.len() <= 2 {
+        return (0..samples.len()).collect();
+    }
+    let mut kept = vec![0];
+    for idx in 1..samples.len() - 1 {
+        let anchor = samples[kept[kept.len() - 1]].position;
+        let (position, next) = (samples[idx].position, samples[idx + 1].position);
+        if distance_to_line(position, anchor, next) > tolerance {
+            kept.push(idx);
+        }
+    }
+    kept.push(samples.len() - 1);
+    kept
+}
+
+/// The distance from `point` to the line through `a` and `b`, or to `a` if they are the same.
+fn distance_to_line(point: V2, a: V2, b: V2) -> f32 {
+    let line = b - a;
+    if line.norm() < f32::EPSILON {
+        return (point - a).norm();
+    }
+    let offset = point - a;
+    (line[0] * offset[1] - line[1] * offset[0]).abs() / line.norm()
+}
+
+/// Blue when standing still, going through green to red at `hot_speed` and beyond.
+pub fn speed_color(speed: f32, hot_speed: f32) -> Color {
+    let heat = (speed / hot_speed).clamp(0., 1.);
+    if heat < 0.5 {
+        Color::new(0., heat * 2., 1. - heat * 2., 1.)
+    } else {
+        Color::new((heat - 0.5) * 2., 1. - (heat - 0.5) * 2., 0., 1.)
+    }
+}
+
+/// Where the player was on every tick that is a multiple of `interval`.
+pub fn tick_markers(samples: &[TrailSample], interval: u64) -> Vec<V2> {
+    samples.iter()
+        .filter(|sample| sample.tick % interval == 0)
+        .map(|sample| sample.position)
+        .collect()
+}
+
+/// Where each event happened.
+pub fn event_markers(samples: &[TrailSample]) -> Vec<(V2, TrailEvent)> {
+    samples.iter()
+        .flat_map(|sample| sample.events.iter().map(move |event| (sample.position, *event)))
+        .collect()
+}
+
+/// Jumps are marked with an upwards arrowhead and hits with a cross.
+fn event_icon(builder: &mut MeshBuilder, position: V2, event: TrailEvent) -> GameResult {
+    let at = |x: f32, y: f32| point(position + V2::new(x, y) * ICON_SIZE);
+    match event {
+        TrailEvent::Jump => {
+            builder.line(&[at(-1., 0.5), at(0., -0.5), at(1., 0.5)], LINE_WIDTH, Color::new(0.4, 1., 0.4, 1.))?;
+        },
+        TrailEvent::Hit => {
+            let color = Color::new(1., 0.3, 0.3, 1.);
+            builder.line(&[at(-1., -1.), at(1., 1.)], LINE_WIDTH, color)?;
+            builder.line(&[at(-1., 1.), at(1., -1.)], LINE_WIDTH, color)?;
+        },
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod trail_test {
+    use super::*;
+
+    fn sample(tick: u64, x: f32, y: f32) -> TrailSample {
+        TrailSample { tick, position: V2::new(x, y), speed: 0., events: vec![] }
+    }
+
+    #[test]
+    fn straight_runs_collapse() {
+        let samples: Vec<_> = (0..10).map(|tick| sample(tick, tick as f32, 0.)).collect();
+        assert!(simplify(&samples, SIMPLIFY_TOLERANCE) == vec![0, 9]);
+    }
+
+    #[test]
+    fn corners_are_kept() {
+        // Right along the ground, then straight up.
+        let mut samples: Vec<_> = (0..5).map(|tick| sample(tick, tick as f32 * 10., 0.)).collect();
+        samples.extend((1..5).map(|step| sample(4 + step, 40., step as f32 * -10.)));
+        assert!(simplify(&samples, SIMPLIFY_TOLERANCE) == vec![0, 4, 8]);
+        // Wobbles within the tolerance are smoothed over.
+        samples[2].position[1] = 0.2;
+        assert!(simplify(&samples, SIMPLIFY_TOLERANCE) == vec![0, 4, 8]);
+        assert!(simplify(&samples, 0.1).contains(&2));
+    }
+
+    #[test]
+    fn short_trails_kept_whole() {
+        assert!(simplify(&[], SIMPLIFY_TOLERANCE).is_empty());
+        assert!(simplify(&[sample(0, 0., 0.)], SIMPLIFY_TOLERANCE) == vec![0]);
+        let still = vec![sample(0, 0., 0.), sample(1, 0., 0.), sample(2, 0., 0.)];
+        assert!(simplify(&still, SIMPLIFY_TOLERANCE) == vec![0, 2]);
+    }
+
+    #[test]
+    fn faster_is_hotter() {
+        let still = speed_color(0., HOT_SPEED);
+        let hot = speed_color(HOT_SPEED, HOT_SPEED);
+        assert!(still.b == 1. && still.r == 0.);
+        assert!(hot.r == 1. && hot.b == 0.);
+        assert!(speed_color(HOT_SPEED * 10., HOT_SPEED) == hot);
+        let reds: Vec<_> = (0..=6).map(|speed| speed_color(speed as f32, HOT_SPEED).r).collect();
+        let blues: Vec<_> = (0..=6).map(|speed| speed_color(speed as f32, HOT_SPEED).b).collect();
+        assert!(reds.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(blues.windows(2).all(|pair| pair[0] >= pair[1]));
+    }
+
+    #[test]
+    fn markers_placed_on_their_ticks() {
+        let mut samples: Vec<_> = (5..30).map(|tick| sample(tick, tick as f32, 0.)).collect();
+        samples[3].events.push(TrailEvent::Jump);
+        samples[7].events = vec![TrailEvent::Hit, TrailEvent::Jump];
+        assert!(tick_markers(&samples, 10) == vec![V2::new(10., 0.), V2::new(20., 0.)]);
+        assert!(event_markers(&samples) == vec![
+            (V2::new(8., 0.), TrailEvent::Jump),
+            (V2::new(12., 0.), TrailEvent::Hit),
+            (V2::new(12., 0.), TrailEvent::Jump),
+        ]);
+    }
+
+    #[test]
+    fn trail_forgets_oldest_ticks() {
+        let mut trail = Trail::default();
+        for tick in 0..TRAIL_TICKS as u64 + 5 {
+            trail.record(sample(tick, 0., 0.));
+        }
+        let samples = trail.samples();
+        assert!(samples.len() == TRAIL_TICKS);
+        assert!(samples[0].tick == 5);
+    }
+
+    #[test]
+    fn path_prepared_until_samples_change() {
+        let mut trail = Trail::default();
+        trail.prepare();
+        assert!(trail.path.as_ref().unwrap().segments.is_empty());
+        trail.record(sample(0, 0., 0.));
+        assert!(trail.path.is_none());
+        trail.record(sample(1, 10., 0.));
+        trail.prepare();
+        let path = trail.path.as_ref().unwrap().clone();
+        assert!(path.segments.len() == 1);
+        assert!(path.tick_markers == vec![V2::new(0., 0.)]);
+        trail.prepare();
+        assert!(trail.path == Some(path));
+        trail.clear();
+        assert!(trail.path.is_none());
+    }
+}
@@ -0,0 +1,730 @@
+//! Combo trials: data-defined challenges where a sequence of actions must be performed on a
+//! training dummy in order.
+//!
+//! ## Definitions
+//! Trials are stored as RON files, one per trial. Steps reference `Action`s directly, so a trial
+//! referencing an attack that doesn't exist is rejected when it is deserialized.
+//!
+//! ## Validation
+//! A `TrialValidator` is a pure state machine. It is fed `TrialEvent`s along with the tick on which
+//! they happened and reports a `TrialStatus`. Every step after the first must be performed within
+//! its window, measured in ticks from the previous step. Hits that don't match the expected step
+//! fail the trial, while non-attack actions that don't match are ignored (the player is free to
+//! walk around between steps).
+//!
+//! ## Playing
+//! A `TrialRun` plays a trial out in a battle between the runner and a dummy of the same
+//! character, feeding its validator as it goes, and traces the runner's inputs so that a
+//! completed run can be proved. Failing the trial puts both back where they started.
+use ggez::Context;
+use ggez::nalgebra as na;
+use ron::de::from_reader;
+use serde::{Serialize, Deserialize};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    assets::AssetManager,
+    physics::Collidable,
+    settings,
+    text::Fonts,
+    timing::SimEvent,
+    util::result::{WalpurgisError, WalpurgisResult},
+    screens::battle::{
+        self, BattleData, TickInput,
+        proof::TracedInput,
+        arena::Arena,
+        player::{
+            Player, Changes as PlayerChangeSet,
+            action::{Action, Attack},
+            blueprint::PlayerBlueprint,
+            inputs::InputScheme,
+            meta::{Effect, Race},
+        },
+        tuning::{Tuning, TUNING_FILE},
+    },
+};
+
+/// A tick of the game loop.
+pub type Tick = u32;
+
+/// The index of the player doing the trial.
+pub const RUNNER: usize = 0;
+/// The index of the trial's training dummy.
+pub const DUMMY: usize = 1;
+
+/// A single step of a `ComboTrial`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrialStep {
+    /// The action to be performed. `Action::Attack`s must connect with the dummy.
+    pub action: Action,
+    /// The number of ticks after the previous step within which this one must be performed.
+    /// Ignored for the first step.
+    pub window: Tick,
+}
+
+/// A challenge for a single race.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComboTrial {
+    /// Name of the trial.
+    pub name: String,
+    /// The race the trial is meant for.
+    pub race: Race,
+    /// Where the dummy is placed at the start of the trial and on every reset.
+    pub dummy_position: na::Vector2<f32>,
+    /// The damage the dummy starts with.
+    pub dummy_damage: f32,
+    /// The steps to perform, in order.
+    pub steps: Vec<TrialStep>,
+}
+
+impl ComboTrial {
+    /// Tries to load a `ComboTrial` from the given file. Errors name the file.
+    pub fn load<P: AsRef<Path>>(trial_file: P) -> WalpurgisResult<Self> {
+        let trial_file = trial_file.as_ref();
+        let f = File::open(trial_file).map_err(|e| WalpurgisError::reading(trial_file, "combo trial", e))?;
+        let trial: Self = from_reader(f).map_err(|e| WalpurgisError::parsing(trial_file, "combo trial", e))?;
+        trial.validate()?;
+        Ok(trial)
+    }
+
+    /// Every `.ron` file in `trial_dir`, in order of file name.
+    pub fn files<P: AsRef<Path>>(trial_dir: P) -> WalpurgisResult<Vec<PathBuf>> {
+        let trial_dir = trial_dir.as_ref();
+        let mut files = vec![];
+        let unreadable = |e| WalpurgisError::reading(trial_dir, "trial directory", e);
+        for entry in fs::read_dir(trial_dir).map_err(unreadable)? {
+            let path = entry.map_err(unreadable)?.path();
+            if path.extension().and_then(|ext| ext.to_str()) == Some("ron") {
+                files.push(path);
+            }
+        }
+        if files.is_empty() {
+            Err(WalpurgisError::AssetNotFound { path: trial_dir.to_path_buf(), what: "combo trial" })?
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// Checks that the trial can actually be completed.
+    pub fn validate(&self) -> WalpurgisResult {
+        if self.steps.is_empty() {
+            Err(format!("Trial `{}` has no steps.", self.name))?
+        }
+        for (idx, step) in self.steps.iter().enumerate() {
+            if let Action::Idle = step.action {
+                Err(format!("Step {} of trial `{}` asks for `Idle`.", idx, self.name))?
+            }
+            if idx > 0 && step.window == 0 {
+                Err(format!("Step {} of trial `{}` has an empty window.", idx, self.name))?
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that `runner` can perform every step of the trial.
+    pub fn check_character(&self, runner: &Player) -> WalpurgisResult {
+        if *runner.race() != self.race {
+            Err(format!(
+                "Trial `{}` is for the {:?} race, but `{}` is {:?}.",
+                self.name,
+                self.race,
+                runner.name(),
+                runner.race(),
+            ))?
+        }
+        for (idx, step) in self.steps.iter().enumerate() {
+            if let Action::Attack(attack) = &step.action {
+                if !runner.has_attack(attack) {
+                    Err(format!(
+                        "Step {} of trial `{}` asks for {:?}, which `{}` doesn't have.",
+                        idx,
+                        self.name,
+                        attack,
+                        runner.name(),
+                    ))?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The events from a battle that a trial is interested in.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrialEvent {
+    /// An attack by the player connected with the dummy.
+    HitLanded(Attack),
+    /// The player started performing an action.
+    ActionStarted(Action),
+    /// Either the player or the dummy lost a stock.
+    KnockedOut,
+}
+
+/// Why a trial was failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrialFailure {
+    /// The step wasn't performed within its window.
+    TimedOut { step: usize },
+    /// An attack other than the one expected by the step connected.
+    WrongHit { step: usize, attack: Attack },
+    /// Someone was knocked out before the trial was over.
+    KnockedOut { step: usize },
+}
+
+/// The progress made on a trial.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrialStatus {
+    /// The number of steps completed so far.
+    InProgress(usize),
+    Failed(TrialFailure),
+    Complete,
+}
+
+/// Validates a stream of `TrialEvent`s against the steps of a `ComboTrial`.
+#[derive(Debug)]
+pub struct TrialValidator {
+    steps: Vec<TrialStep>,
+    status: TrialStatus,
+    /// The tick on which the last step was completed.
+    last_step_tick: Tick,
+}
+
+impl TrialValidator {
+    pub fn new(trial: &ComboTrial) -> Self {
+        Self {
+            steps: trial.steps.clone(),
+            status: TrialStatus::InProgress(0),
+            last_step_tick: 0,
+        }
+    }
+
+    pub fn status(&self) -> &TrialStatus {
+        &self.status
+    }
+
+    /// Whether the given step has been completed. Useful for drawing checkmarks.
+    pub fn is_step_complete(&self, step: usize) -> bool {
+        match self.status {
+            TrialStatus::Complete => true,
+            TrialStatus::InProgress(completed) => step < completed,
+            TrialStatus::Failed(TrialFailure::TimedOut { step: failed })
+            | TrialStatus::Failed(TrialFailure::WrongHit { step: failed, .. })
+            | TrialStatus::Failed(TrialFailure::KnockedOut { step: failed }) => step < failed,
+        }
+    }
+
+    /// Starts the trial over from the first step.
+    pub fn reset(&mut self) {
+        self.status = TrialStatus::InProgress(0);
+        self.last_step_tick = 0;
+    }
+
+    /// Fails the trial if the window for the current step has lapsed by `tick`.
+    pub fn handle_tick(&mut self, tick: Tick) -> &TrialStatus {
+        if let TrialStatus::InProgress(step) = self.status {
+            if step > 0 && tick > self.last_step_tick + self.steps[step].window {
+                self.status = TrialStatus::Failed(TrialFailure::TimedOut { step });
+            }
+        }
+        &self.status
+    }
+
+    /// Advances the trial with an event that happened on `tick`.
+    pub fn handle_event(&mut self, tick: Tick, event: &TrialEvent) -> &TrialStatus {
+        self.handle_tick(tick);
+        let step = match self.status {
+            TrialStatus::InProgress(step) => step,
+            _ => return &self.status,
+        };
+
+        let expected = &self.steps[step].action;
+        let matches = match (event, expected) {
+            (TrialEvent::HitLanded(attack), Action::Attack(expected)) if attack == expected => true,
+            (TrialEvent::HitLanded(attack), _) => {
+                self.status = TrialStatus::Failed(TrialFailure::WrongHit {
+                    step,
+                    attack: attack.clone(),
+                });
+                false
+            },
+            (TrialEvent::KnockedOut, _) => {
+                self.status = TrialStatus::Failed(TrialFailure::KnockedOut { step });
+                false
+            },
+            // Attacks only count once they connect.
+            (TrialEvent::ActionStarted(_), Action::Attack(_)) => false,
+            (TrialEvent::ActionStarted(action), expected) => action == expected,
+        };
+
+        if matches {
+            self.last_step_tick = tick;
+            self.status = if step + 1 == self.steps.len() {
+                TrialStatus::Complete
+            } else {
+                TrialStatus::InProgress(step + 1)
+            };
+        }
+        &self.status
+    }
+}
+
+/// A trial being played, in a battle between the runner and a dummy built from the same
+/// character.
+#[derive(Debug)]
+pub struct TrialRun {
+    trial: ComboTrial,
+    /// The character both players are built from, already tuned.
+    blueprint: PlayerBlueprint,
+    /// The tuning the battle is played with.
+    tuning: Tuning,
+    /// The arena as it was loaded, since playing in it changes it.
+    arena: Arena,
+    battle: BattleData,
+    validator: TrialValidator,
+    /// The ticks played since the trial was last started.
+    tick: Tick,
+    /// The runner's inputs since the trial was last started, for proving the run.
+    trace: Vec<TracedInput>,
+}
+
+impl TrialRun {
+    /// Loads the first trial in the asset root, played in the first arena by the first local
+    /// player with a character of the trial's race.
+    pub fn load_first(
+        ctx: &mut Context,
+        settings: &settings::Settings,
+        assets: &mut AssetManager,
+        fonts: &Fonts,
+    ) -> WalpurgisResult<Self> {
+        let trial_file = ComboTrial::files(assets.root().join("trials"))?.remove(0);
+        let trial = ComboTrial::load(&trial_file)?;
+        let (_, arena) = assets.first_arena()?;
+        let mut arena = Arena::clone(&arena);
+        arena.load_background(ctx, assets)?;
+        let tuning = Tuning::load(assets.root().join(TUNING_FILE))?;
+        let picked = battle::pick_blueprints(&assets.blueprints()?, &[], std::slice::from_ref(&trial.race), 1).remove(0);
+        let blueprint = tuning.tune(&picked);
+        let players = vec![
+            Player::from_blueprint(ctx, assets, &blueprint, InputScheme::for_player(RUNNER, &settings.controls)?)?,
+            Player::from_blueprint(ctx, assets, &blueprint, InputScheme::default())?,
+        ];
+        let mut run = Self::new(trial, blueprint, &tuning, arena, players, fonts)?;
+        run.battle.finish_loading(ctx, assets.root(), &settings.audio);
+        Ok(run)
+    }
+
+    /// Plays `trial` with `blueprint`, tuned by `tuning`, without a `Context`.
+    pub fn headless(trial: ComboTrial, blueprint: &PlayerBlueprint, tuning: &Tuning, arena: Arena) -> WalpurgisResult<Self> {
+        let blueprint = tuning.tune(blueprint);
+        let players = vec![
+            Player::without_sprites(&blueprint, InputScheme::default()),
+            Player::without_sprites(&blueprint, InputScheme::default()),
+        ];
+        Self::new(trial, blueprint, tuning, arena, players, &Fonts::default())
+    }
+
+    /// Starts `trial` with `players`, the runner and then the dummy. Fails if the runner can't
+    /// perform every step.
+    fn new(
+        trial: ComboTrial,
+        blueprint: PlayerBlueprint,
+        tuning: &Tuning,
+        arena: Arena,
+        players: Vec<Player>,
+        fonts: &Fonts,
+    ) -> WalpurgisResult<Self> {
+        trial.check_character(&players[RUNNER])?;
+        let mut battle = BattleData::new(arena.clone(), players, fonts);
+        battle.tune(tuning);
+        let mut run = Self {
+            validator: TrialValidator::new(&trial),
+            trial,
+            blueprint,
+            tuning: tuning.clone(),
+            arena,
+            battle,
+            tick: 0,
+            trace: vec![],
+        };
+        run.place_dummy();
+        Ok(run)
+    }
+
+    /// Starts the trial over, with both players built afresh and the arena as it was loaded.
+    pub fn restart(&mut self) {
+        let players = std::mem::take(&mut self.battle.players)
+            .into_iter()
+            .map(|player| player.rebuilt(&self.blueprint))
+            .collect();
+        self.battle.restart(self.arena.clone(), players);
+        self.place_dummy();
+        self.validator.reset();
+        self.tick = 0;
+        self.trace.clear();
+    }
+
+    /// Puts the dummy where the trial wants it, as damaged as the trial wants it.
+    fn place_dummy(&mut self) {
+        let dummy = &mut self.battle.players[DUMMY];
+        dummy.spawn_at(self.trial.dummy_position);
+        dummy.apply_changeset(PlayerChangeSet {
+            effects: vec![Effect::Damage(self.trial.dummy_damage)],
+            ..Default::default()
+        });
+    }
+
+    /// Plays a tick with the runner's `input`, and returns the trial's status after it. A failed
+    /// trial is started over once the failure has been reported.
+    pub fn tick(&mut self, input: TickInput) -> TrialStatus {
+        let tick = self.tick;
+        for action in &input.actions {
+            self.validator.handle_event(tick, &TrialEvent::ActionStarted(action.clone()));
+        }
+        if !input.actions.is_empty() || !input.released.is_empty() {
+            self.trace.push(TracedInput { tick, actions: input.actions.clone(), released: input.released.clone() });
+        }
+        self.battle.handle_update(&[input]);
+        for hit in self.battle.hits() {
+            if hit.attacker == RUNNER && hit.defender == DUMMY {
+                self.validator.handle_event(tick, &TrialEvent::HitLanded(hit.attack.clone()));
+            }
+        }
+        if self.battle.tick_events().contains(&SimEvent::Ko) {
+            self.validator.handle_event(tick, &TrialEvent::KnockedOut);
+        }
+        let status = self.validator.handle_tick(tick).clone();
+        self.tick += 1;
+        if let TrialStatus::Failed(failure) = &status {
+            log::info!("Trial `{}` failed: {:?}", self.trial.name, failure);
+            self.restart();
+        }
+        status
+    }
+
+    /// The number of ticks played since the trial was last started.
+    pub fn elapsed_ticks(&self) -> Tick {
+        self.tick
+    }
+
+    pub fn trial(&self) -> &ComboTrial {
+        &self.trial
+    }
+
+    /// The arena as it was before the trial was played in it.
+    pub fn arena(&self) -> &Arena {
+        &self.arena
+    }
+
+    /// The runner's character, as tuned.
+    pub fn blueprint(&self) -> &PlayerBlueprint {
+        &self.blueprint
+    }
+
+    pub fn tuning(&self) -> &Tuning {
+        &self.tuning
+    }
+
+    /// The runner's inputs since the trial was last started, leaving out ticks without any.
+    pub fn trace(&self) -> &[TracedInput] {
+        &self.trace
+    }
+
+    pub fn validator(&self) -> &TrialValidator {
+        &self.validator
+    }
+
+    pub fn battle(&self) -> &BattleData {
+        &self.battle
+    }
+
+    pub fn battle_mut(&mut self) -> &mut BattleData {
+        &mut self.battle
+    }
+}
+
+#[cfg(test)]
+mod trial_test {
+    use super::*;
+    use crate::screens::battle::player::{
+        action::AttackDir,
+        meta::{Ability, BasicClass},
+        stance::HorizontalStance,
+    };
+
+    fn jab() -> Attack {
+        Attack::Basics(BasicClass::Light, AttackDir::Side)
+    }
+    fn up_tilt() -> Attack {
+        Attack::Basics(BasicClass::Light, AttackDir::Up)
+    }
+    fn up_air() -> Attack {
+        Attack::Basics(BasicClass::Air, AttackDir::Up)
+    }
+
+    fn step(action: Action) -> TrialStep {
+        TrialStep {
+            action,
+            window: 10,
+        }
+    }
+
+    /// Jab, jab, up-tilt, jump, up-air.
+    fn build_trial() -> ComboTrial {
+        ComboTrial {
+            name: "Test".into(),
+            race: Race::Alien,
+            dummy_position: na::Vector2::new(0., 0.),
+            dummy_damage: 0.,
+            steps: vec![
+                step(Action::Attack(jab())),
+                step(Action::Attack(jab())),
+                step(Action::Attack(up_tilt())),
+                step(Action::Jump),
+                step(Action::Attack(up_air())),
+            ],
+        }
+    }
+
+    fn feed(validator: &mut TrialValidator, events: &[(Tick, TrialEvent)]) -> TrialStatus {
+        for (tick, event) in events {
+            validator.handle_event(*tick, event);
+        }
+        validator.status().clone()
+    }
+
+    fn successful_stream() -> Vec<(Tick, TrialEvent)> {
+        vec![
+            (100, TrialEvent::HitLanded(jab())),
+            (105, TrialEvent::HitLanded(jab())),
+            (112, TrialEvent::HitLanded(up_tilt())),
+            (115, TrialEvent::ActionStarted(Action::Jump)),
+            (120, TrialEvent::HitLanded(up_air())),
+        ]
+    }
+
+    #[test]
+    fn trial_complete() {
+        let mut validator = TrialValidator::new(&build_trial());
+        assert!(feed(&mut validator, &successful_stream()) == TrialStatus::Complete);
+        assert!(validator.is_step_complete(4));
+    }
+
+    #[test]
+    fn trial_progress() {
+        let mut validator = TrialValidator::new(&build_trial());
+        let stream = successful_stream();
+        assert!(feed(&mut validator, &stream[..3]) == TrialStatus::InProgress(3));
+        assert!(validator.is_step_complete(2));
+        assert!(!validator.is_step_complete(3));
+    }
+
+    #[test]
+    fn trial_ignores_unrelated_actions() {
+        let mut validator = TrialValidator::new(&build_trial());
+        let mut stream = successful_stream();
+        stream.insert(1, (102, TrialEvent::ActionStarted(Action::Walk(HorizontalStance::Left))));
+        stream.insert(4, (113, TrialEvent::ActionStarted(Action::Attack(up_air()))));
+        assert!(feed(&mut validator, &stream) == TrialStatus::Complete);
+    }
+
+    #[test]
+    fn trial_attacks_must_connect() {
+        let mut validator = TrialValidator::new(&build_trial());
+        let status = feed(&mut validator, &[
+            (100, TrialEvent::ActionStarted(Action::Attack(jab()))),
+        ]);
+        assert!(status == TrialStatus::InProgress(0));
+    }
+
+    #[test]
+    fn trial_wrong_hit() {
+        let mut validator = TrialValidator::new(&build_trial());
+        let status = feed(&mut validator, &[
+            (100, TrialEvent::HitLanded(jab())),
+            (105, TrialEvent::HitLanded(up_tilt())),
+        ]);
+        assert!(status == TrialStatus::Failed(TrialFailure::WrongHit { step: 1, attack: up_tilt() }));
+        assert!(validator.is_step_complete(0));
+        assert!(!validator.is_step_complete(1));
+    }
+
+    #[test]
+    fn trial_extra_hit() {
+        let mut validator = TrialValidator::new(&build_trial());
+        let status = feed(&mut validator, &[
+            (100, TrialEvent::HitLanded(jab())),
+            (105, TrialEvent::HitLanded(jab())),
+            (108, TrialEvent::HitLanded(jab())),
+        ]);
+        assert!(status == TrialStatus::Failed(TrialFailure::WrongHit { step: 2, attack: jab() }));
+    }
+
+    #[test]
+    fn trial_timed_out() {
+        let mut validator = TrialValidator::new(&build_trial());
+        let status = feed(&mut validator, &[
+            (100, TrialEvent::HitLanded(jab())),
+            (111, TrialEvent::HitLanded(jab())),
+        ]);
+        assert!(status == TrialStatus::Failed(TrialFailure::TimedOut { step: 1 }));
+    }
+
+    #[test]
+    fn trial_timed_out_without_events() {
+        let mut validator = TrialValidator::new(&build_trial());
+        validator.handle_event(100, &TrialEvent::HitLanded(jab()));
+        assert!(*validator.handle_tick(110) == TrialStatus::InProgress(1));
+        assert!(*validator.handle_tick(111) == TrialStatus::Failed(TrialFailure::TimedOut { step: 1 }));
+    }
+
+    #[test]
+    fn trial_first_step_waits() {
+        let mut validator = TrialValidator::new(&build_trial());
+        assert!(*validator.handle_tick(10_000) == TrialStatus::InProgress(0));
+    }
+
+    #[test]
+    fn trial_ignores_events_once_finished() {
+        let mut validator = TrialValidator::new(&build_trial());
+        let mut stream = successful_stream();
+        stream.push((125, TrialEvent::HitLanded(jab())));
+        assert!(feed(&mut validator, &stream) == TrialStatus::Complete);
+
+        let mut validator = TrialValidator::new(&build_trial());
+        let status = feed(&mut validator, &[
+            (100, TrialEvent::HitLanded(up_air())),
+            (101, TrialEvent::HitLanded(jab())),
+        ]);
+        assert!(status == TrialStatus::Failed(TrialFailure::WrongHit { step: 0, attack: up_air() }));
+    }
+
+    #[test]
+    fn trial_reset() {
+        let mut validator = TrialValidator::new(&build_trial());
+        feed(&mut validator, &[(100, TrialEvent::HitLanded(up_air()))]);
+        validator.reset();
+        assert!(feed(&mut validator, &successful_stream()) == TrialStatus::Complete);
+    }
+
+    #[test]
+    fn trial_validate() {
+        assert!(build_trial().validate().is_ok());
+
+        let mut trial = build_trial();
+        trial.steps.clear();
+        assert!(trial.validate().is_err());
+
+        let mut trial = build_trial();
+        trial.steps[2].window = 0;
+        assert!(trial.validate().is_err());
+
+        let mut trial = build_trial();
+        trial.steps[0].window = 0;
+        assert!(trial.validate().is_ok());
+        trial.steps[1].action = Action::Idle;
+        assert!(trial.validate().is_err());
+    }
+
+    #[test]
+    fn trial_deserialize() {
+        let trial: ComboTrial = ron::de::from_str(r#"(
+            name: "Test",
+            race: Robot,
+            dummy_position: [300, 400],
+            dummy_damage: 20,
+            steps: [
+                (action: Attack(Basics(Light, Side)), window: 0),
+                (action: Jump, window: 10),
+            ],
+        )"#).unwrap();
+        assert!(trial.steps[0].action == Action::Attack(jab()));
+        assert!(trial.steps[1].action == Action::Jump);
+
+        let unknown_attack: Result<ComboTrial, _> = ron::de::from_str(r#"(
+            name: "Test",
+            race: Robot,
+            dummy_position: [300, 400],
+            dummy_damage: 20,
+            steps: [(action: Attack(Basics(Light, Sideways)), window: 0)],
+        )"#);
+        assert!(unknown_attack.is_err());
+    }
+
+    #[test]
+    fn trial_knocked_out() {
+        let mut validator = TrialValidator::new(&build_trial());
+        let status = feed(&mut validator, &[
+            (100, TrialEvent::HitLanded(jab())),
+            (102, TrialEvent::KnockedOut),
+        ]);
+        assert!(status == TrialStatus::Failed(TrialFailure::KnockedOut { step: 1 }));
+        assert!(validator.is_step_complete(0));
+        assert!(!validator.is_step_complete(1));
+    }
+
+    #[test]
+    fn trial_checks_character() {
+        let alien = Player::without_sprites(&PlayerBlueprint::placeholder(Race::Alien), InputScheme::default());
+        let robot = Player::without_sprites(&PlayerBlueprint::placeholder(Race::Robot), InputScheme::default());
+        let mut trial = build_trial();
+        assert!(trial.check_character(&alien).is_ok());
+        assert!(trial.check_character(&robot).is_err());
+
+        trial.steps.push(step(Action::Attack(Attack::Ability(Ability::Teleport))));
+        assert!(trial.check_character(&alien).is_ok());
+        trial.steps.push(step(Action::Attack(Attack::Ability(Ability::Fireball))));
+        assert!(trial.check_character(&alien).is_err());
+    }
+
+    fn trial_run() -> TrialRun {
+        let arena = ron::de::from_str("(
+            name: \"Trial\",
+            platforms: [(body: (pos: [-100, 100], size: [300, 10], ori: 0), can_move_through: false)],
+            spawn_points: [[0, 70], [40, 70]],
+        )").unwrap();
+        let mut trial = build_trial();
+        trial.dummy_position = na::Vector2::new(-40., 70.);
+        trial.dummy_damage = 10.;
+        TrialRun::headless(trial, &PlayerBlueprint::placeholder(Race::Alien), &Tuning::default(), arena).unwrap()
+    }
+
+    #[test]
+    fn trial_run_starts_with_dummy_in_place() {
+        let run = trial_run();
+        let dummy = &run.battle().players()[DUMMY];
+        assert!(dummy.get_offset() == na::Vector2::new(-40., 70.));
+        assert!(dummy.damage_pct() == 10.);
+    }
+
+    #[test]
+    fn trial_run_resets_dummy_on_failure() {
+        let mut run = trial_run();
+        run.trial.steps = vec![step(Action::Walk(HorizontalStance::Left)), step(Action::Jump)];
+        run.validator = TrialValidator::new(&run.trial);
+        run.battle_mut().players[DUMMY].apply_changeset(PlayerChangeSet {
+            effects: vec![Effect::Damage(25.)],
+            ..Default::default()
+        });
+        run.battle_mut().players[DUMMY].spawn_at(na::Vector2::new(60., 70.));
+        let walk = TickInput { actions: vec![Action::Walk(HorizontalStance::Left)], released: vec![] };
+        assert!(run.tick(walk) == TrialStatus::InProgress(1));
+        for _ in 0..10 {
+            assert!(run.tick(TickInput::default()) == TrialStatus::InProgress(1));
+        }
+        assert!(run.tick(TickInput::default()) == TrialStatus::Failed(TrialFailure::TimedOut { step: 1 }));
+        assert!(run.elapsed_ticks() == 0);
+        assert!(*run.validator().status() == TrialStatus::InProgress(0));
+        let dummy = &run.battle().players()[DUMMY];
+        assert!(dummy.get_offset() == na::Vector2::new(-40., 70.));
+        assert!(dummy.damage_pct() == 10.);
+        assert!(run.battle().elapsed_ticks() == 0);
+    }
+
+    #[test]
+    fn trial_run_rejects_wrong_race() {
+        let arena: Arena = ron::de::from_str("(name: \"Trial\", platforms: [])").unwrap();
+        let run = TrialRun::headless(build_trial(), &PlayerBlueprint::placeholder(Race::Mage), &Tuning::default(), arena);
+        assert!(run.is_err());
+    }
+}
@@ -0,0 +1,40 @@
+//! Areas that notice players entering them without pushing them around.
+use ggez::nalgebra as na;
+use serde::{Serialize, Deserialize};
+
+use crate::physics::{Collidable, BoundingBox};
+use crate::screens::battle::layer;
+
+/// What happens to players entering a `TriggerZone`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TriggerKind {
+    /// Beyond the edge of the arena. Players entering lose a stock.
+    BlastZone,
+    /// Only reported, for abilities and pickups to react to.
+    Named(String),
+}
+
+/// An area that fires `BattleData::on_trigger_enter` for players entering it. Players inside are
+/// never pushed, hurt or stood on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TriggerZone {
+    pub kind: TriggerKind,
+    /// The space covered by the trigger.
+    pub body: BoundingBox,
+}
+
+impl Collidable for TriggerZone {
+    type ChangeSet = ();
+    fn get_hitboxes(&self) -> &[BoundingBox] {
+        self.body.get_hitboxes()
+    }
+    fn get_offset(&self) -> na::Vector2<f32> {
+        na::Vector2::new(0_f32, 0_f32)
+    }
+    fn collision_layer(&self) -> u32 {
+        layer::TRIGGER
+    }
+    fn collision_mask(&self) -> u32 {
+        layer::TRIGGER_MASK
+    }
+}
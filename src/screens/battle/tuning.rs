@@ -0,0 +1,207 @@
+//! The numbers every battle is played with, read from `tuning.ron` in the asset root so that
+//! they can be balanced without recompiling:
+//! ```text
+//! (
+//!     gravity: 0.01,
+//!     terminal_velocity: 8,
+//!     walk_speed: Some(2.2),
+//! )
+//! ```
+//! Gravity and terminal velocity hold for everyone. The rest override the base stats of every
+//! character's race, except for characters whose blueprint has stats of its own.
+use ggez::nalgebra as na;
+use ron::de::from_reader;
+use serde::{Serialize, Deserialize};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use crate::{
+    skill_tree::StatField,
+    util::result::{WalpurgisError, WalpurgisResult},
+};
+use super::player::{blueprint::PlayerBlueprint, meta::Stats};
+
+/// Where the tuning is kept, relative to the asset root.
+pub const TUNING_FILE: &str = "tuning.ron";
+
+/// The most air jumps a character may have. Any more and there'd be no coming down.
+const MAX_AIR_JUMPS: u8 = 10;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Tuning {
+    /// The downwards acceleration applied to every player every tick.
+    pub gravity: f32,
+    /// The falling speed past which gravity stops speeding players up. Knockback can still send
+    /// them down faster.
+    pub terminal_velocity: f32,
+    pub walk_speed: Option<f32>,
+    pub dash_speed: Option<f32>,
+    /// The upwards speed at the start of a jump.
+    pub jump_speed: Option<f32>,
+    pub max_air_jumps: Option<u8>,
+    pub friction: Option<f32>,
+    /// The horizontal acceleration of drifting in the air.
+    pub air_acceleration: Option<f32>,
+    /// The fastest drifting in the air can go.
+    pub air_speed: Option<f32>,
+    /// The fastest characters fall on their own, before fast falling.
+    pub fall_speed: Option<f32>,
+    pub fast_fall_speed: Option<f32>,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Tuning {
+            gravity: 0.01,
+            terminal_velocity: 8.,
+            walk_speed: None,
+            dash_speed: None,
+            jump_speed: None,
+            max_air_jumps: None,
+            friction: None,
+            air_acceleration: None,
+            air_speed: None,
+            fall_speed: None,
+            fast_fall_speed: None,
+        }
+    }
+}
+
+impl Tuning {
+    /// Loads and checks the tuning in `tuning_file`, falling back to the defaults if there is no
+    /// such file. Errors name the file and the field at fault.
+    pub fn load<P: AsRef<Path>>(tuning_file: P) -> WalpurgisResult<Self> {
+        let tuning_file = tuning_file.as_ref();
+        let f = match File::open(tuning_file) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                log::info!("No tuning at `{}`, using the defaults.", tuning_file.display());
+                return Ok(Tuning::default());
+            },
+            Err(e) => Err(WalpurgisError::reading(tuning_file, "tuning", e))?,
+        };
+        let tuning: Self = from_reader(f).map_err(|e| WalpurgisError::parsing(tuning_file, "tuning", e))?;
+        tuning.validate().map_err(|e| format!("`{}`: {}", tuning_file.display(), e))?;
+        Ok(tuning)
+    }
+
+    /// Checks that battles can be played with the tuning.
+    pub fn validate(&self) -> WalpurgisResult {
+        let non_negative = [
+            ("gravity", Some(self.gravity)),
+            ("walk_speed", self.walk_speed),
+            ("dash_speed", self.dash_speed),
+            ("jump_speed", self.jump_speed),
+            ("friction", self.friction),
+            ("air_acceleration", self.air_acceleration),
+            ("air_speed", self.air_speed),
+            ("fall_speed", self.fall_speed),
+            ("fast_fall_speed", self.fast_fall_speed),
+        ];
+        for (field, value) in non_negative.iter() {
+            match value {
+                Some(value) if !(*value >= 0.) => Err(format!("`{}` can't be negative, but is {}.", field, value))?,
+                _ => (),
+            }
+        }
+        if !(self.terminal_velocity > 0.) {
+            Err(format!("`terminal_velocity` has to be above 0, but is {}.", self.terminal_velocity))?
+        }
+        match self.max_air_jumps {
+            Some(jumps) if jumps > MAX_AIR_JUMPS => {
+                Err(format!("`max_air_jumps` can be at most {}, but is {}.", MAX_AIR_JUMPS, jumps))?
+            },
+            _ => (),
+        }
+        Ok(())
+    }
+
+    pub fn gravity(&self) -> na::Vector2<f32> {
+        // +y is down.
+        na::Vector2::new(0., self.gravity)
+    }
+
+    /// `blueprint` with the stats it's played with: its own if it has any, and otherwise its race's
+    /// with the tuning's overrides.
+    pub fn tune(&self, blueprint: &PlayerBlueprint) -> PlayerBlueprint {
+        let mut tuned = blueprint.clone();
+        if tuned.stats.is_none() {
+            let mut stats = Stats::for_race(&blueprint.race);
+            let overrides = [
+                (StatField::WalkSpeed, self.walk_speed),
+                (StatField::DashSpeed, self.dash_speed),
+                (StatField::JumpSpeed, self.jump_speed),
+                (StatField::MaxAirJumps, self.max_air_jumps.map(f32::from)),
+                (StatField::Friction, self.friction),
+                (StatField::AirAcceleration, self.air_acceleration),
+                (StatField::AirSpeed, self.air_speed),
+                (StatField::FallSpeed, self.fall_speed),
+                (StatField::FastFallSpeed, self.fast_fall_speed),
+            ];
+            for (field, value) in overrides.iter() {
+                if let Some(value) = value {
+                    stats.set(*field, *value);
+                }
+            }
+            tuned.stats = Some(stats);
+        }
+        tuned
+    }
+}
+
+#[cfg(test)]
+mod tuning_test {
+    use super::*;
+    use crate::screens::battle::player::{self, meta::Race};
+
+    fn parse(contents: &str) -> Tuning {
+        ron::de::from_str(contents).unwrap()
+    }
+
+    #[test]
+    fn defaults_without_file() {
+        let missing = std::env::temp_dir().join(format!("walpurgis_no_tuning_{}.ron", std::process::id()));
+        assert!(Tuning::load(&missing).unwrap() == Tuning::default());
+        assert!(parse("()") == Tuning::default());
+        assert!(Tuning::default().validate().is_ok());
+        let shipped = Path::new(env!("CARGO_MANIFEST_DIR")).join("data").join(TUNING_FILE);
+        assert!(Tuning::load(shipped).unwrap() == Tuning::default());
+    }
+
+    #[test]
+    fn blueprint_stats_over_tuning_over_race() {
+        let tuning = parse("(walk_speed: Some(5), max_air_jumps: Some(4))");
+        let mut blueprint = player::test_blueprint(Race::Robot, na::Vector2::zeros());
+        blueprint.stats = None;
+        let stats = tuning.tune(&blueprint).stats.unwrap();
+        assert!(stats.get(StatField::WalkSpeed) == 5. && stats.get(StatField::MaxAirJumps) == 4.);
+        // Anything not tuned stays the race's.
+        assert!(stats.get(StatField::Weight) == Stats::for_race(&Race::Robot).get(StatField::Weight));
+        let mut own = Stats::for_race(&Race::Robot);
+        own.set(StatField::WalkSpeed, 3.);
+        blueprint.stats = Some(own);
+        assert!(tuning.tune(&blueprint).stats.unwrap().get(StatField::WalkSpeed) == 3.);
+    }
+
+    #[test]
+    fn bad_fields_named() {
+        let message = |contents| parse(contents).validate().unwrap_err().to_string();
+        assert!(message("(gravity: -0.01)").contains("`gravity`"));
+        assert!(message("(terminal_velocity: 0)").contains("`terminal_velocity`"));
+        assert!(message("(max_air_jumps: Some(11))").contains("`max_air_jumps`"));
+        assert!(message("(friction: Some(-1))").contains("`friction`"));
+        assert!(message("(fall_speed: Some(-3))").contains("`fall_speed`"));
+        assert!(parse("(max_air_jumps: Some(10))").validate().is_ok());
+    }
+
+    #[test]
+    fn load_errors_name_file() {
+        let path = std::env::temp_dir().join(format!("walpurgis_bad_tuning_{}.ron", std::process::id()));
+        std::fs::write(&path, "(gravity: -1)").unwrap();
+        let message = Tuning::load(&path).unwrap_err().to_string();
+        std::fs::remove_file(&path).unwrap();
+        assert!(message.contains(&path.display().to_string()) && message.contains("`gravity`"));
+    }
+}
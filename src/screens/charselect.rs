@@ -0,0 +1,335 @@
+use ggez::{Context, GameResult};
+use ggez::graphics::{self, Color, Drawable, DrawMode, DrawParam, MeshBuilder, Rect, Text, BlendMode};
+
+use crate::{
+    audio::SoundEvent,
+    inputs::{HandleInput, Input},
+    screens::{
+        Transition,
+        battle::{hud::player_color, player::{inputs::{InputScheme, MenuCommand}, meta::{Handicap, Race}}},
+    },
+    viewport,
+};
+
+const PORTRAIT_SIZE: f32 = 120.;
+/// The space between neighbouring portraits.
+const PORTRAIT_GAP: f32 = 40.;
+/// How far each player's cursor sits outside of the one before it, so that cursors on the same
+/// portrait don't hide each other.
+const CURSOR_SPACING: f32 = 6.;
+const CURSOR_WIDTH: f32 = 3.;
+
+/// The colour of each race's portrait, until they have art of their own.
+fn portrait_color(race: &Race) -> Color {
+    match race {
+        Race::Alien => Color::from_rgb(90, 170, 80),
+        Race::Robot => Color::from_rgb(140, 150, 160),
+        Race::Mage => Color::from_rgb(130, 80, 180),
+    }
+}
+
+/// Where the portrait of `Race::ALL[idx]` is drawn, in a row across the middle of the screen.
+fn portrait(idx: usize, screen: Rect) -> Rect {
+    let count = Race::ALL.len() as f32;
+    let row = count * PORTRAIT_SIZE + (count - 1.) * PORTRAIT_GAP;
+    let x = (screen.w - row) / 2. + idx as f32 * (PORTRAIT_SIZE + PORTRAIT_GAP);
+    Rect::new(x, screen.h / 3., PORTRAIT_SIZE, PORTRAIT_SIZE)
+}
+
+/// What one player has picked so far.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerSlot {
+    pub race: Race,
+    /// One of `Handicap::presets`.
+    pub handicap: Handicap,
+    /// Ready players can't change their race until they back out.
+    pub ready: bool,
+}
+
+/// Where every local player picks the race they play before a battle. Each player moves their own
+/// cursor with their own controls, and the battle starts once everyone is ready.
+#[derive(Debug)]
+pub struct CharSelectData {
+    /// `ggez`-specific. Not really used for anything atm.
+    mode: Option<BlendMode>,
+    /// One for each player, in player order.
+    slots: Vec<PlayerSlot>,
+    ready_count: usize,
+    /// The controls each player's inputs are read with.
+    schemes: Vec<InputScheme>,
+    /// Built once, since the race names never change.
+    title: Text,
+    names: Vec<Text>,
+    hint: Text,
+    /// Each player's pick and whether they're ready, rebuilt whenever either changes.
+    statuses: Vec<Text>,
+    /// The transition picked since the last update, if any.
+    chosen: Option<Transition>,
+    /// The sounds made since the last `take_sounds`.
+    sounds: Vec<SoundEvent>,
+}
+
+impl CharSelectData {
+    /// A slot for each player in `schemes`, played with those controls. Players start off on
+    /// different races.
+    pub fn new(schemes: Vec<InputScheme>) -> Self {
+        let slots = (0..schemes.len())
+            .map(|idx| PlayerSlot {
+                race: Race::ALL[idx % Race::ALL.len()].clone(),
+                handicap: Handicap::default(),
+                ready: false,
+            })
+            .collect();
+        let mut select = Self {
+            mode: None,
+            slots,
+            ready_count: 0,
+            schemes,
+            title: Text::new("Choose your race"),
+            names: Race::ALL.iter().map(|race| Text::new(format!("{:?}", race))).collect(),
+            hint: Text::new("Down changes your handicap"),
+            statuses: vec![],
+            chosen: None,
+            sounds: vec![],
+        };
+        select.refresh_statuses();
+        select
+    }
+
+    /// Starts the battle once everyone is ready, or goes back to the main menu.
+    pub fn handle_update(&mut self) -> Transition {
+        self.chosen.take().unwrap_or(Transition::None)
+    }
+
+    /// The race each player picked, in player order.
+    pub fn races(&self) -> Vec<Race> {
+        self.slots.iter().map(|slot| slot.race.clone()).collect()
+    }
+
+    /// The handicap each player picked, in player order.
+    pub fn handicaps(&self) -> Vec<Handicap> {
+        self.slots.iter().map(|slot| slot.handicap.clone()).collect()
+    }
+
+    /// The sounds made since the last call, in order.
+    pub fn take_sounds(&mut self) -> Vec<SoundEvent> {
+        std::mem::take(&mut self.sounds)
+    }
+
+    /// Hands each input to the player it's bound for.
+    fn navigate(&mut self, pressed: &[Input]) {
+        for input in pressed {
+            let command = self.schemes.iter()
+                .enumerate()
+                .find_map(|(idx, scheme)| scheme.menu_command(input).map(|command| (idx, command)));
+            if let Some((idx, command)) = command {
+                self.command(idx, command);
+            }
+        }
+        self.refresh_statuses();
+    }
+
+    /// Moves the cursor of player `idx` left and right, wrapping around at either end, cycles
+    /// their handicap on Down, readies them on Confirm and backs them out on Back. Backing out of
+    /// a slot that isn't ready goes back to the main menu.
+    fn command(&mut self, idx: usize, command: MenuCommand) {
+        let count = Race::ALL.len();
+        let slot = &mut self.slots[idx];
+        let picked = Race::ALL.iter().position(|race| *race == slot.race).unwrap_or(0);
+        match (command, slot.ready) {
+            (MenuCommand::Left, false) => {
+                slot.race = Race::ALL[(picked + count - 1) % count].clone();
+                self.sounds.push(SoundEvent::MenuMove);
+            },
+            (MenuCommand::Right, false) => {
+                slot.race = Race::ALL[(picked + 1) % count].clone();
+                self.sounds.push(SoundEvent::MenuMove);
+            },
+            (MenuCommand::Down, false) => {
+                let handicaps = Handicap::presets();
+                let picked = handicaps.iter().position(|handicap| *handicap == slot.handicap).unwrap_or(0);
+                slot.handicap = handicaps[(picked + 1) % handicaps.len()].clone();
+                self.sounds.push(SoundEvent::MenuMove);
+            },
+            (MenuCommand::Confirm, false) => {
+                slot.ready = true;
+                self.ready_count += 1;
+                self.sounds.push(SoundEvent::MenuSelect);
+                if self.ready_count == self.slots.len() {
+                    self.chosen = Some(Transition::StartBattle);
+                }
+            },
+            (MenuCommand::Back, true) => {
+                slot.ready = false;
+                self.ready_count -= 1;
+                self.sounds.push(SoundEvent::MenuMove);
+            },
+            (MenuCommand::Back, false) => {
+                self.chosen = Some(Transition::MainMenu);
+                self.sounds.push(SoundEvent::MenuSelect);
+            },
+            (MenuCommand::Left, true)
+            | (MenuCommand::Right, true)
+            | (MenuCommand::Down, true)
+            | (MenuCommand::Confirm, true) => (),
+        }
+    }
+
+    fn refresh_statuses(&mut self) {
+        let statuses: Vec<_> = self.slots.iter()
+            .enumerate()
+            .map(|(idx, slot)| format!(
+                "P{} {:?} ({}) {}",
+                idx + 1,
+                slot.race,
+                slot.handicap.label(),
+                if slot.ready { "Ready!" } else { "..." },
+            ))
+            .collect();
+        if statuses.iter().cloned().ne(self.statuses.iter().map(Text::contents)) {
+            self.statuses = statuses.into_iter().map(Text::new).collect();
+        }
+    }
+}
+
+impl Drawable for CharSelectData {
+    /// Draws a portrait for each race with its name below, each player's cursor around the race
+    /// they're on, and a line for each player with their handicap and whether they're ready.
+    fn draw(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
+        let screen = viewport::virtual_screen();
+        let mut title_param = param;
+        title_param.dest.x += (screen.w - self.title.width(ctx) as f32) / 2.;
+        title_param.dest.y += screen.h / 6.;
+        self.title.draw(ctx, title_param)?;
+
+        let mut portraits = MeshBuilder::new();
+        for (idx, race) in Race::ALL.iter().enumerate() {
+            portraits.rectangle(DrawMode::fill(), portrait(idx, screen), portrait_color(race));
+        }
+        for (idx, slot) in self.slots.iter().enumerate() {
+            let picked = Race::ALL.iter().position(|race| *race == slot.race).unwrap_or(0);
+            let mut cursor = portrait(picked, screen);
+            let grown = CURSOR_SPACING * (idx + 1) as f32;
+            cursor.translate([-grown, -grown]);
+            cursor.w += 2. * grown;
+            cursor.h += 2. * grown;
+            portraits.rectangle(DrawMode::stroke(CURSOR_WIDTH), cursor, player_color(idx));
+        }
+        portraits.build(ctx)?.draw(ctx, param.color(graphics::WHITE))?;
+
+        for (idx, name) in self.names.iter().enumerate() {
+            let bounds = portrait(idx, screen);
+            let mut name_param = param;
+            name_param.dest.x += bounds.x + (bounds.w - name.width(ctx) as f32) / 2.;
+            name_param.dest.y += bounds.bottom() + CURSOR_SPACING * (self.slots.len() + 1) as f32;
+            name.draw(ctx, name_param)?;
+        }
+
+        let mut y = portrait(0, screen).bottom() + 80.;
+        for (idx, status) in self.statuses.iter().enumerate() {
+            let mut status_param = param.color(player_color(idx));
+            status_param.dest.x += (screen.w - status.width(ctx) as f32) / 2.;
+            status_param.dest.y += y;
+            status.draw(ctx, status_param)?;
+            y += status.height(ctx) as f32 + 10.;
+        }
+        let mut hint_param = param;
+        hint_param.dest.x += (screen.w - self.hint.width(ctx) as f32) / 2.;
+        hint_param.dest.y += y + 20.;
+        self.hint.draw(ctx, hint_param)?;
+        Ok(())
+    }
+
+    fn dimensions(&self, _ctx: &mut Context) -> Option<Rect> {
+        None
+    }
+
+    fn set_blend_mode(&mut self, mode: Option<BlendMode>) {
+        self.mode = mode;
+    }
+
+    fn blend_mode(&self) -> Option<BlendMode> {
+        self.mode
+    }
+}
+
+impl HandleInput for CharSelectData {
+    fn handle_input(&mut self, _ctx: &mut Context, fire_once_key_buffer: &Vec<Input>) {
+        self.navigate(fire_once_key_buffer);
+    }
+}
+
+#[cfg(test)]
+mod charselect_test {
+    use super::*;
+    use ggez::event::{Button, KeyCode, KeyMods};
+    use crate::{screens::battle::{self, player::Player}, settings::Controls};
+
+    fn key(key: KeyCode) -> Input {
+        Input::Key(key, KeyMods::NONE)
+    }
+
+    fn two_players() -> CharSelectData {
+        let controls = Controls::default();
+        CharSelectData::new((0..2).map(|idx| InputScheme::for_player(idx, &controls).unwrap()).collect())
+    }
+
+    #[test]
+    fn everyone_ready_starts_battle_with_their_races() {
+        let mut select = two_players();
+        assert!(select.races() == vec![Race::Alien, Race::Robot]);
+        // Each player's keys only move their own cursor.
+        select.navigate(&[key(KeyCode::D), key(KeyCode::D), key(KeyCode::Space)]);
+        assert!(select.slots[0] == PlayerSlot { race: Race::Mage, handicap: Handicap::default(), ready: true });
+        assert!(select.slots[1] == PlayerSlot { race: Race::Robot, handicap: Handicap::default(), ready: false });
+        assert!(select.handle_update() == Transition::None);
+        select.navigate(&[key(KeyCode::Left), key(KeyCode::Left)]);
+        // Ready players stay put.
+        select.navigate(&[key(KeyCode::A)]);
+        assert!(select.races() == vec![Race::Mage, Race::Mage]);
+        select.navigate(&[Input::Button(Button::DPadRight, 0), key(KeyCode::Period)]);
+        assert!(select.handle_update() == Transition::StartBattle);
+        assert!(select.handle_update() == Transition::None);
+
+        let races = select.races();
+        assert!(races == vec![Race::Mage, Race::Alien]);
+        let players = races.iter()
+            .map(|race| Player::new(race.clone(), InputScheme::default()).unwrap())
+            .collect();
+        let battle = battle::test_battle(players);
+        let battle_races: Vec<_> = battle.players().iter().map(|player| player.race().clone()).collect();
+        assert!(battle_races == races);
+    }
+
+    #[test]
+    fn backing_out_unreadies_then_leaves() {
+        let mut select = two_players();
+        select.navigate(&[key(KeyCode::Space), key(KeyCode::Q)]);
+        assert!(!select.slots[0].ready && select.ready_count == 0);
+        select.navigate(&[key(KeyCode::Semicolon)]);
+        assert!(select.handle_update() == Transition::MainMenu);
+        assert!(select.take_sounds() == vec![SoundEvent::MenuSelect, SoundEvent::MenuMove, SoundEvent::MenuSelect]);
+    }
+
+    #[test]
+    fn statuses_follow_slots() {
+        let mut select = two_players();
+        let contents = |select: &CharSelectData| -> Vec<String> {
+            select.statuses.iter().map(Text::contents).collect()
+        };
+        assert!(contents(&select) == vec!["P1 Alien (No handicap) ...", "P2 Robot (No handicap) ..."]);
+        select.navigate(&[key(KeyCode::Up)]);
+        assert!(contents(&select) == vec!["P1 Alien (No handicap) ...", "P2 Robot (No handicap) Ready!"]);
+    }
+
+    #[test]
+    fn down_cycles_handicap_until_ready() {
+        let mut select = two_players();
+        select.navigate(&[key(KeyCode::S), key(KeyCode::S), key(KeyCode::Space), key(KeyCode::S)]);
+        assert!(select.slots[0].handicap == Handicap::presets()[2]);
+        // Every preset, then back to none.
+        let presets = Handicap::presets().len();
+        select.navigate(&vec![key(KeyCode::Down); presets]);
+        assert!(select.handicaps() == vec![Handicap::presets()[2].clone(), Handicap::default()]);
+    }
+}
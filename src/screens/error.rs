@@ -0,0 +1,188 @@
+use ggez::{Context, GameResult};
+use ggez::event::{Button, KeyCode};
+use ggez::graphics::{Drawable, DrawParam, Rect, Text, TextFragment, BlendMode};
+
+use crate::{
+    audio::SoundEvent,
+    inputs::{HandleInput, Input},
+    screens::{Transition, mainmenu::HIGHLIGHT},
+    text::{self, MeasureText},
+    util::result::WalpurgisError,
+    viewport,
+};
+
+/// How far the message stays from either side of the screen.
+const MARGIN: f32 = 80.;
+
+/// The choices under the message, top to bottom.
+const ENTRIES: [(&str, Transition); 2] = [("Back to Main Menu", Transition::MainMenu), ("Quit", Transition::Quit)];
+
+/// Explains why the screen asked for couldn't be shown, instead of taking the whole game down.
+#[derive(Debug)]
+pub struct ErrorData {
+    /// `ggez`-specific. Not really used for anything atm.
+    mode: Option<BlendMode>,
+    /// Built once, since the error never changes.
+    headline: Text,
+    message: Text,
+    labels: Vec<Text>,
+    /// The index of the highlighted entry in `ENTRIES`.
+    selected: usize,
+    /// The transition picked since the last update, if any.
+    chosen: Option<Transition>,
+    /// The sounds made since the last `take_sounds`.
+    sounds: Vec<SoundEvent>,
+}
+
+impl ErrorData {
+    /// Shows `error`, wrapped to fit the screen as measured by `measure`.
+    pub fn new<M: MeasureText>(error: &WalpurgisError, measure: &mut M) -> Self {
+        let mut message = Text::default();
+        let lines = text::wrap_lines(&error.to_string(), viewport::VIRTUAL_WIDTH - 2. * MARGIN, measure);
+        for line in lines {
+            message.add(TextFragment::new(line + "\n"));
+        }
+        Self {
+            mode: None,
+            headline: Text::new("Something went wrong"),
+            message,
+            labels: ENTRIES.iter().map(|(label, _)| Text::new(*label)).collect(),
+            selected: 0,
+            chosen: None,
+            sounds: vec![],
+        }
+    }
+
+    /// Acts on the entry picked since the last update, if any.
+    pub fn handle_update(&mut self) -> Transition {
+        self.chosen.take().unwrap_or(Transition::None)
+    }
+
+    /// The sounds made since the last call, in order.
+    pub fn take_sounds(&mut self) -> Vec<SoundEvent> {
+        std::mem::take(&mut self.sounds)
+    }
+
+    /// Moves the highlight up and down, wrapping around at either end, and picks the highlighted
+    /// entry on Enter.
+    fn navigate(&mut self, pressed: &[Input]) {
+        let count = ENTRIES.len();
+        for input in pressed {
+            match input {
+                Input::Key(KeyCode::Up, _) | Input::Key(KeyCode::W, _) | Input::Button(Button::DPadUp, _) => {
+                    self.selected = (self.selected + count - 1) % count;
+                    self.sounds.push(SoundEvent::MenuMove);
+                },
+                Input::Key(KeyCode::Down, _) | Input::Key(KeyCode::S, _) | Input::Button(Button::DPadDown, _) => {
+                    self.selected = (self.selected + 1) % count;
+                    self.sounds.push(SoundEvent::MenuMove);
+                },
+                Input::Key(KeyCode::Return, _) | Input::Button(Button::South, _) => {
+                    self.chosen = Some(ENTRIES[self.selected].1);
+                    self.sounds.push(SoundEvent::MenuSelect);
+                },
+                _ => (),
+            }
+        }
+    }
+}
+
+impl Drawable for ErrorData {
+    fn draw(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
+        let screen = viewport::virtual_screen();
+        let mut y = screen.h / 6.;
+        for text in &[&self.headline, &self.message] {
+            let mut text_param = param;
+            text_param.dest.x += (screen.w - text.width(ctx) as f32) / 2.;
+            text_param.dest.y += y;
+            text.draw(ctx, text_param)?;
+            y += text.height(ctx) as f32 + 40.;
+        }
+        for (idx, label) in self.labels.iter().enumerate() {
+            let mut label_param = param;
+            if idx == self.selected {
+                label_param.color = HIGHLIGHT;
+            }
+            label_param.dest.x += (screen.w - label.width(ctx) as f32) / 2.;
+            label_param.dest.y += y;
+            label.draw(ctx, label_param)?;
+            y += label.height(ctx) as f32 + 20.;
+        }
+        Ok(())
+    }
+
+    fn dimensions(&self, _ctx: &mut Context) -> Option<Rect> {
+        None
+    }
+
+    fn set_blend_mode(&mut self, mode: Option<BlendMode>) {
+        self.mode = mode;
+    }
+
+    fn blend_mode(&self) -> Option<BlendMode> {
+        self.mode
+    }
+}
+
+impl HandleInput for ErrorData {
+    fn handle_input(&mut self, _ctx: &mut Context, fire_once_key_buffer: &Vec<Input>) {
+        self.navigate(fire_once_key_buffer);
+    }
+}
+
+#[cfg(test)]
+mod error_test {
+    use super::*;
+    use ggez::event::KeyMods;
+    use std::fs;
+    use crate::{assets::AssetManager, screens::Screen};
+
+    const UP: Input = Input::Key(KeyCode::Up, KeyMods::NONE);
+    const ENTER: Input = Input::Key(KeyCode::Return, KeyMods::NONE);
+
+    /// Every character is 10 wide.
+    struct FixedWidth;
+    impl MeasureText for FixedWidth {
+        fn width(&mut self, text: &Text) -> f32 {
+            text.contents().chars().count() as f32 * 10.
+        }
+    }
+
+    #[test]
+    fn message_wrapped_to_screen() {
+        let error = WalpurgisError::Generic(vec!["word"; 100].join(" "));
+        let error = ErrorData::new(&error, &mut FixedWidth);
+        let fragments = error.message.fragments();
+        assert!(fragments.len() > 1);
+        let widest = viewport::VIRTUAL_WIDTH - 2. * MARGIN;
+        assert!(fragments.iter().all(|fragment| fragment.text.trim_end().len() as f32 * 10. <= widest));
+        assert!(error.message.contents().matches("word").count() == 100);
+    }
+
+    #[test]
+    fn corrupt_arena_shown_instead_of_battle() {
+        let dir = std::env::temp_dir().join(format!("walpurgis_corrupt_arena_{}", std::process::id()));
+        fs::create_dir_all(dir.join("arenas")).unwrap();
+        fs::write(dir.join("arenas").join("corrupt.ron"), "(name: \"Corrupt\", platforms: [").unwrap();
+        let mut assets = AssetManager::new(&dir);
+        let loaded = assets.first_arena().map(|_| Screen::main_menu());
+        let screen = Screen::loaded_or_error(loaded, &mut FixedWidth);
+        fs::remove_dir_all(&dir).unwrap();
+        match screen {
+            Screen::Error(error) => assert!(error.message.contents().contains("corrupt.ron")),
+            other => panic!("Expected the error screen, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn back_to_menu_or_quit() {
+        let mut error = ErrorData::new(&WalpurgisError::Generic("Oops".to_owned()), &mut FixedWidth);
+        assert!(error.handle_update() == Transition::None);
+        error.navigate(&[ENTER]);
+        assert!(error.handle_update() == Transition::MainMenu);
+        assert!(error.handle_update() == Transition::None);
+        error.navigate(&[UP, ENTER]);
+        assert!(error.handle_update() == Transition::Quit);
+        assert!(error.take_sounds() == vec![SoundEvent::MenuSelect, SoundEvent::MenuMove, SoundEvent::MenuSelect]);
+    }
+}
@@ -1,19 +1,142 @@
 use ggez::{Context, GameResult};
-use ggez::graphics::{Drawable, DrawParam, Rect, Text, BlendMode};
+use ggez::event::{Button, KeyCode};
+use ggez::graphics::{Color, Drawable, DrawParam, Rect, Text, BlendMode};
 
-use crate::inputs::{HandleInput, Input};
+use crate::{
+    audio::SoundEvent,
+    inputs::{HandleInput, Input},
+    screens::Transition,
+    viewport,
+};
+
+/// The colour of the entry that Enter would pick.
+pub const HIGHLIGHT: Color = Color { r: 1., g: 0.85, b: 0.2, a: 1. };
+
+/// What a button on the main menu does when it's picked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MenuAction {
+    StartGame,
+    ComboTrial,
+    WatchReplay,
+    Settings,
+    Quit,
+}
+
+impl MenuAction {
+    fn transition(self) -> Transition {
+        match self {
+            MenuAction::StartGame => Transition::CharacterSelect,
+            MenuAction::ComboTrial => Transition::ComboTrial,
+            MenuAction::WatchReplay => Transition::WatchReplay,
+            MenuAction::Settings => Transition::Settings,
+            MenuAction::Quit => Transition::Quit,
+        }
+    }
+}
+
+/// An entry on the main menu.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MenuButton {
+    pub label: String,
+    pub action: MenuAction,
+}
+
+impl MenuButton {
+    fn new(label: &str, action: MenuAction) -> Self {
+        Self { label: label.to_owned(), action }
+    }
+}
 
 #[derive(Debug)]
 pub struct MainMenuData {
     /// `ggez`-specific. Not really used for anything atm.
     mode: Option<BlendMode>,
+    /// The buttons to pick from, top to bottom.
+    buttons: Vec<MenuButton>,
+    /// The index of the highlighted button in `buttons`.
+    selected: usize,
+    /// The action picked since the last update, if any.
+    chosen: Option<MenuAction>,
+    /// The buttons' labels, built once since they never change.
+    labels: Vec<Text>,
+    /// The sounds made since the last `take_sounds`.
+    sounds: Vec<SoundEvent>,
+}
+
+impl Default for MainMenuData {
+    fn default() -> Self {
+        let buttons = vec![
+            MenuButton::new("Start Battle", MenuAction::StartGame),
+            MenuButton::new("Combo Trial", MenuAction::ComboTrial),
+            MenuButton::new("Watch Replay", MenuAction::WatchReplay),
+            MenuButton::new("Settings", MenuAction::Settings),
+            MenuButton::new("Quit", MenuAction::Quit),
+        ];
+        Self {
+            mode: None,
+            labels: buttons.iter().map(|button| Text::new(button.label.as_str())).collect(),
+            buttons,
+            selected: 0,
+            chosen: None,
+            sounds: vec![],
+        }
+    }
 }
+
 impl MainMenuData {
-    pub fn handle_update(&mut self) {
+    /// Acts on the button picked since the last update, if any.
+    pub fn handle_update(&mut self) -> Transition {
+        self.chosen.take().map_or(Transition::None, MenuAction::transition)
+    }
+
+    /// What the highlighted button does.
+    pub fn selected(&self) -> MenuAction {
+        self.buttons[self.selected].action
+    }
+
+    /// The sounds made since the last call, in order.
+    pub fn take_sounds(&mut self) -> Vec<SoundEvent> {
+        std::mem::take(&mut self.sounds)
+    }
+
+    /// Moves the highlight up and down, wrapping around at either end, and picks the highlighted
+    /// button on Enter. Characters are picked on the character select screen instead.
+    fn navigate(&mut self, pressed: &[Input]) {
+        let count = self.buttons.len();
+        for input in pressed {
+            match input {
+                Input::Key(KeyCode::Up, _) | Input::Key(KeyCode::W, _) | Input::Button(Button::DPadUp, _) => {
+                    self.selected = (self.selected + count - 1) % count;
+                    self.sounds.push(SoundEvent::MenuMove);
+                },
+                Input::Key(KeyCode::Down, _) | Input::Key(KeyCode::S, _) | Input::Button(Button::DPadDown, _) => {
+                    self.selected = (self.selected + 1) % count;
+                    self.sounds.push(SoundEvent::MenuMove);
+                },
+                Input::Key(KeyCode::Return, _) | Input::Button(Button::South, _) => {
+                    self.chosen = Some(self.selected());
+                    self.sounds.push(SoundEvent::MenuSelect);
+                },
+                _ => (),
+            }
+        }
     }
 }
+
 impl Drawable for MainMenuData {
     fn draw(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
+        let screen = viewport::virtual_screen();
+        let mut y = screen.h / 3.;
+        for (idx, label) in self.labels.iter().enumerate() {
+            let mut label_param = param;
+            if idx == self.selected {
+                label_param.color = HIGHLIGHT;
+            }
+            label_param.dest.x += (screen.w - label.width(ctx) as f32) / 2.;
+            label_param.dest.y += y;
+            label.draw(ctx, label_param)?;
+            y += label.height(ctx) as f32 + 20.;
+        }
         Ok(())
     }
 
@@ -29,8 +152,74 @@ impl Drawable for MainMenuData {
         self.mode
     }
 }
+
 impl HandleInput for MainMenuData {
-    fn handle_input(&mut self, ctx: &mut Context, fire_once_key_buffer: &Vec<Input>) {
+    fn handle_input(&mut self, _ctx: &mut Context, fire_once_key_buffer: &Vec<Input>) {
+        self.navigate(fire_once_key_buffer);
     }
 }
 
+#[cfg(test)]
+mod mainmenu_test {
+    use super::*;
+    use ggez::event::KeyMods;
+
+    const UP: Input = Input::Key(KeyCode::Up, KeyMods::NONE);
+    const DOWN: Input = Input::Key(KeyCode::Down, KeyMods::NONE);
+    const ENTER: Input = Input::Key(KeyCode::Return, KeyMods::NONE);
+
+    #[test]
+    fn navigation_wraps_around() {
+        let mut menu = MainMenuData::default();
+        assert!(menu.selected() == MenuAction::StartGame);
+        menu.navigate(&[UP]);
+        assert!(menu.selected() == MenuAction::Quit);
+        menu.navigate(&[DOWN]);
+        assert!(menu.selected() == MenuAction::StartGame);
+        menu.navigate(&[DOWN]);
+        assert!(menu.selected() == MenuAction::ComboTrial);
+        menu.navigate(&[DOWN, DOWN, DOWN, DOWN, DOWN]);
+        assert!(menu.selected() == MenuAction::ComboTrial);
+        menu.navigate(&[DOWN]);
+        assert!(menu.selected() == MenuAction::WatchReplay);
+        menu.navigate(&[DOWN]);
+        assert!(menu.selected() == MenuAction::Settings);
+        menu.navigate(&[DOWN]);
+        assert!(menu.selected() == MenuAction::Quit);
+        menu.navigate(&[Input::Button(Button::DPadDown, 0)]);
+        assert!(menu.selected() == MenuAction::StartGame);
+    }
+
+    #[test]
+    fn enter_signals_transition_once() {
+        let mut menu = MainMenuData::default();
+        assert!(menu.handle_update() == Transition::None);
+        menu.navigate(&[ENTER]);
+        assert!(menu.handle_update() == Transition::CharacterSelect);
+        assert!(menu.handle_update() == Transition::None);
+        menu.navigate(&[DOWN, ENTER]);
+        assert!(menu.handle_update() == Transition::ComboTrial);
+        menu.navigate(&[DOWN, ENTER]);
+        assert!(menu.handle_update() == Transition::WatchReplay);
+        menu.navigate(&[DOWN, ENTER]);
+        assert!(menu.handle_update() == Transition::Settings);
+        menu.navigate(&[DOWN, ENTER]);
+        assert!(menu.handle_update() == Transition::Quit);
+    }
+
+    #[test]
+    fn left_and_right_pick_nothing() {
+        let mut menu = MainMenuData::default();
+        menu.navigate(&[Input::Key(KeyCode::Right, KeyMods::NONE), Input::Button(Button::DPadLeft, 0)]);
+        assert!(menu.selected() == MenuAction::StartGame);
+        assert!(menu.take_sounds().is_empty());
+    }
+
+    #[test]
+    fn navigation_makes_sounds() {
+        let mut menu = MainMenuData::default();
+        menu.navigate(&[DOWN, UP, Input::Key(KeyCode::X, KeyMods::NONE), ENTER]);
+        assert!(menu.take_sounds() == vec![SoundEvent::MenuMove, SoundEvent::MenuMove, SoundEvent::MenuSelect]);
+        assert!(menu.take_sounds().is_empty());
+    }
+}
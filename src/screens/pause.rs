@@ -0,0 +1,222 @@
+use ggez::{Context, GameResult};
+use ggez::event::{Button, KeyCode, KeyMods};
+use ggez::graphics::{self, Color, Drawable, DrawMode, DrawParam, Mesh, Rect, Text, BlendMode};
+use std::path::Path;
+
+use crate::{
+    audio::SoundEvent,
+    debug::DebugChannels,
+    inputs::{HandleInput, Input, layout::{self, KeyboardLayout}},
+    screens::{Transition, battle::{BattleData, arena::Arena}, mainmenu::HIGHLIGHT},
+    viewport,
+    walpurgis::{PAUSE_KEY, QUIT_KEY},
+};
+
+/// Drawn over the battle so that the menu stands out.
+const DIM: Color = Color { r: 0., g: 0., b: 0., a: 0.6 };
+
+/// The choices on the pause menu, top to bottom.
+const ENTRIES: [(&str, Transition); 2] = [("Resume", Transition::Resume), ("Quit to Menu", Transition::MainMenu)];
+
+/// A suspended battle. The battle is drawn frozen and dimmed underneath a menu to resume it or
+/// give it up.
+#[derive(Debug)]
+pub struct PauseData {
+    /// `ggez`-specific. Not really used for anything atm.
+    mode: Option<BlendMode>,
+    /// The battle that was paused.
+    battle: BattleData,
+    /// Built once so that the overlay doesn't lay its glyphs out again every frame.
+    paused: Text,
+    labels: Vec<Text>,
+    hint: Text,
+    /// The index of the highlighted entry in `ENTRIES`.
+    selected: usize,
+    /// The transition picked since the last update, if any.
+    chosen: Option<Transition>,
+    /// The sounds made since the last `take_sounds`.
+    sounds: Vec<SoundEvent>,
+}
+
+impl PauseData {
+    pub fn new(battle: BattleData, layout: KeyboardLayout) -> Self {
+        let hint = [
+            layout::prompt("resume", &[(PAUSE_KEY, KeyMods::NONE)], layout),
+            layout::prompt("quit", &[(QUIT_KEY, KeyMods::NONE)], layout),
+        ].join("  ");
+        Self {
+            mode: None,
+            battle,
+            paused: Text::new("Paused"),
+            labels: ENTRIES.iter().map(|(label, _)| Text::new(*label)).collect(),
+            hint: Text::new(hint),
+            selected: 0,
+            chosen: None,
+            sounds: vec![],
+        }
+    }
+
+    /// Gives back the paused battle.
+    pub fn resume(self) -> BattleData {
+        self.battle
+    }
+
+    pub fn set_debug_channels(&mut self, channels: &DebugChannels) {
+        self.battle.set_debug_channels(channels);
+    }
+
+    pub fn prepare(&mut self) {
+        self.battle.prepare();
+    }
+
+    pub fn reload_arena(&mut self, arena_file: &Path, arena: &Arena) {
+        self.battle.reload_arena(arena_file, arena);
+    }
+
+    /// Acts on the entry picked since the last update, if any. The battle does not advance while
+    /// paused.
+    pub fn handle_update(&mut self) -> Transition {
+        self.chosen.take().unwrap_or(Transition::None)
+    }
+
+    /// Advances the paused battle by a single tick, for debugging.
+    pub fn step(&mut self) {
+        let inputs = self.battle.take_inputs();
+        self.battle.handle_update(&inputs);
+    }
+
+    pub fn battle(&self) -> &BattleData {
+        &self.battle
+    }
+
+    /// The sounds made by the menu, and by the battle while stepping through it.
+    pub fn take_sounds(&mut self) -> Vec<SoundEvent> {
+        let mut sounds = self.battle.take_sounds();
+        sounds.append(&mut self.sounds);
+        sounds
+    }
+
+    /// Moves the highlight up and down, wrapping around at either end, and picks the highlighted
+    /// entry on Enter.
+    fn navigate(&mut self, pressed: &[Input]) {
+        let count = ENTRIES.len();
+        for input in pressed {
+            match input {
+                Input::Key(KeyCode::Up, _) | Input::Key(KeyCode::W, _) | Input::Button(Button::DPadUp, _) => {
+                    self.selected = (self.selected + count - 1) % count;
+                    self.sounds.push(SoundEvent::MenuMove);
+                },
+                Input::Key(KeyCode::Down, _) | Input::Key(KeyCode::S, _) | Input::Button(Button::DPadDown, _) => {
+                    self.selected = (self.selected + 1) % count;
+                    self.sounds.push(SoundEvent::MenuMove);
+                },
+                Input::Key(KeyCode::Return, _) | Input::Button(Button::South, _) => {
+                    self.chosen = Some(ENTRIES[self.selected].1);
+                    self.sounds.push(SoundEvent::MenuSelect);
+                },
+                _ => (),
+            }
+        }
+    }
+}
+
+impl Drawable for PauseData {
+    fn draw(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
+        self.battle.draw(ctx, param)?;
+
+        let screen = viewport::virtual_screen();
+        let mut dim = Mesh::new_rectangle(ctx, DrawMode::fill(), screen, DIM)?;
+        dim.set_blend_mode(Some(BlendMode::Alpha));
+        dim.draw(ctx, param.color(graphics::WHITE))?;
+
+        let mut y = screen.h / 3.;
+        let entries = self.labels.iter().enumerate().map(|(idx, label)| (Some(idx), label));
+        for (idx, text) in std::iter::once((None, &self.paused)).chain(entries) {
+            let mut text_param = param;
+            if idx == Some(self.selected) {
+                text_param.color = HIGHLIGHT;
+            }
+            text_param.dest.x += (screen.w - text.width(ctx) as f32) / 2.;
+            text_param.dest.y += y;
+            text.draw(ctx, text_param)?;
+            y += text.height(ctx) as f32 + 20.;
+        }
+
+        let mut hint_param = param;
+        hint_param.dest.x += (screen.w - self.hint.width(ctx) as f32) / 2.;
+        hint_param.dest.y += y + 20.;
+        self.hint.draw(ctx, hint_param)
+    }
+
+    fn dimensions(&self, _ctx: &mut Context) -> Option<Rect> {
+        None
+    }
+
+    fn set_blend_mode(&mut self, mode: Option<BlendMode>) {
+        self.mode = mode;
+    }
+
+    fn blend_mode(&self) -> Option<BlendMode> {
+        self.mode
+    }
+}
+
+impl HandleInput for PauseData {
+    /// Only the menu sees presses. The battle gets none of them.
+    fn handle_input(&mut self, _ctx: &mut Context, fire_once_key_buffer: &Vec<Input>) {
+        self.navigate(fire_once_key_buffer);
+    }
+
+    /// Releases still reach the battle so that held keys aren't stuck once it resumes.
+    fn handle_key_up(&mut self, ctx: &mut Context, released_key_buffer: &Vec<Input>) {
+        self.battle.handle_key_up(ctx, released_key_buffer);
+    }
+}
+
+#[cfg(test)]
+mod pause_test {
+    use super::*;
+    use ggez::event::KeyMods;
+    use crate::{
+        physics::collision::Collidable,
+        screens::battle::{self, TickInput, player::{self, action::Action}},
+    };
+
+    const UP: Input = Input::Key(KeyCode::Up, KeyMods::NONE);
+    const DOWN: Input = Input::Key(KeyCode::Down, KeyMods::NONE);
+    const ENTER: Input = Input::Key(KeyCode::Return, KeyMods::NONE);
+
+    fn paused() -> PauseData {
+        let battle = battle::test_battle(vec![player::bare_test_player()]);
+        PauseData::new(battle, KeyboardLayout::Qwerty)
+    }
+
+    #[test]
+    fn battle_frozen_while_paused() {
+        let mut pause = paused();
+        let start = pause.battle().players()[0].get_offset();
+        for _ in 0..60 {
+            assert!(pause.handle_update() == Transition::None);
+        }
+        assert!(pause.battle().elapsed_ticks() == 0);
+        assert!(pause.battle().players()[0].get_offset() == start);
+        let mut battle = pause.resume();
+        battle.handle_update(&TickInput::only(0, vec![Action::Jump]));
+        assert!(battle.elapsed_ticks() == 1);
+    }
+
+    #[test]
+    fn resume_or_quit_to_menu() {
+        let mut pause = paused();
+        pause.navigate(&[ENTER]);
+        assert!(pause.handle_update() == Transition::Resume);
+        assert!(pause.handle_update() == Transition::None);
+        pause.navigate(&[UP, ENTER]);
+        assert!(pause.handle_update() == Transition::MainMenu);
+        pause.navigate(&[DOWN]);
+        assert!(pause.selected == 0);
+        assert!(pause.take_sounds() == vec![
+            SoundEvent::MenuSelect, SoundEvent::MenuMove, SoundEvent::MenuSelect, SoundEvent::MenuMove,
+        ]);
+    }
+}
@@ -0,0 +1,391 @@
+use ggez::{Context, GameResult};
+use ggez::event::{Button, KeyCode, KeyMods};
+use ggez::graphics::{Drawable, DrawParam, Rect, Text, BlendMode};
+
+use crate::{
+    audio::SoundEvent,
+    saves::SaveData,
+    inputs::{HandleInput, Input, layout::{self, KeyboardLayout}},
+    screens::{Transition, battle::{self, BattleData, MatchState, player::meta::Race}},
+    skill_tree::SkillTree,
+    viewport,
+    walpurgis::QUIT_KEY,
+};
+
+/// How a player did in a finished battle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostMatchStats {
+    pub name: String,
+    pub damage_dealt: f32,
+    pub stocks_lost: u8,
+    pub skill_points: u32,
+}
+
+impl PostMatchStats {
+    /// How each player in `battle` did, in player order.
+    pub fn of(battle: &BattleData) -> Vec<Self> {
+        let rewards = match battle.match_state() {
+            MatchState::PostMatch(rewards) => rewards.as_slice(),
+            _ => &[],
+        };
+        battle.players().iter()
+            .enumerate()
+            .map(|(idx, player)| PostMatchStats {
+                name: player.name().to_owned(),
+                damage_dealt: player.damage_dealt(),
+                stocks_lost: player.stocks_lost(),
+                skill_points: rewards.iter().find(|(player, _)| *player == idx).map_or(0, |(_, points)| *points),
+            })
+            .collect()
+    }
+}
+
+/// The headline of a battle's results, naming the winner if there is one.
+fn headline(winner: Option<usize>, is_draw: bool, player_stats: &[PostMatchStats]) -> String {
+    match winner.and_then(|idx| player_stats.get(idx).map(|stats| (idx, stats))) {
+        Some((idx, stats)) => format!("P{} {} wins!", idx + 1, stats.name),
+        None if is_draw => "Draw".to_owned(),
+        None => "No contest".to_owned(),
+    }
+}
+
+/// A line for each player, followed by how long the battle took.
+fn stats_table(player_stats: &[PostMatchStats], elapsed_ticks: u64) -> String {
+    let mut lines: Vec<_> = player_stats.iter()
+        .enumerate()
+        .map(|(idx, stats)| format!(
+            "P{} {}: {:.0} damage dealt, {} stocks lost, +{} skill points",
+            idx + 1,
+            stats.name,
+            stats.damage_dealt,
+            stats.stocks_lost,
+            stats.skill_points,
+        ))
+        .collect();
+    lines.push(format!("Time: {}", battle::format_timer(elapsed_ticks)));
+    lines.join("\n")
+}
+
+/// The outcome of a finished battle, where players spend the skill points they earned before
+/// moving on.
+#[derive(Debug)]
+pub struct ResultsData {
+    /// `ggez`-specific. Not really used for anything atm.
+    mode: Option<BlendMode>,
+    /// Built once, since the results never change.
+    headline: Text,
+    details: Text,
+    hint: Text,
+    /// Each player's skill tree, with the points they earned added.
+    trees: Vec<SkillTree>,
+    /// The race of each player, whose tree they spend their points on.
+    races: Vec<Race>,
+    /// The player whose tree is shown.
+    player: usize,
+    /// The index of the highlighted node, in id order.
+    node: usize,
+    /// Why the last unlock failed, if it did.
+    error: Option<String>,
+    /// The selected tree, rebuilt whenever it changes.
+    tree_text: Text,
+    /// Whether the players are done spending points.
+    done: bool,
+    /// The sounds made since the last `take_sounds`.
+    sounds: Vec<SoundEvent>,
+}
+
+impl ResultsData {
+    /// The results of `battle`, where players spend their points on their trees in `save`, or on
+    /// fresh ones for races they haven't played yet.
+    pub fn new(battle: &BattleData, layout: KeyboardLayout, save: &SaveData) -> Self {
+        let player_stats = PostMatchStats::of(battle);
+        let headline = headline(battle.winner(), battle.is_draw(), &player_stats);
+        let details = stats_table(&player_stats, battle.elapsed_ticks());
+        let key = |key| (key, KeyMods::NONE);
+        let hint = [
+            layout::prompt("player", &[key(KeyCode::Left), key(KeyCode::Right)], layout),
+            layout::prompt("skill", &[key(KeyCode::Up), key(KeyCode::Down)], layout),
+            layout::prompt("unlock", &[key(KeyCode::Return)], layout),
+            layout::prompt("continue", &[key(KeyCode::Space)], layout),
+            layout::prompt("quit", &[key(QUIT_KEY)], layout),
+        ].join("  ");
+        let trees = battle.players().iter()
+            .enumerate()
+            .map(|(idx, player)| {
+                let mut tree = save.skill_tree(idx, player.race()).unwrap_or_else(|| player.skill_tree()).clone();
+                tree.skill_points += player_stats[idx].skill_points;
+                tree
+            })
+            .collect();
+        let races = battle.players().iter().map(|player| player.race().clone()).collect();
+        let mut results = Self {
+            mode: None,
+            headline: Text::new(headline),
+            details: Text::new(details),
+            hint: Text::new(hint),
+            trees,
+            races,
+            player: 0,
+            node: 0,
+            error: None,
+            tree_text: Text::default(),
+            done: false,
+            sounds: vec![],
+        };
+        results.refresh_tree();
+        results
+    }
+
+    /// Moves on to the main menu once the players are done.
+    pub fn handle_update(&mut self) -> Transition {
+        if self.done {
+            self.done = false;
+            Transition::MainMenu
+        } else {
+            Transition::None
+        }
+    }
+
+    /// Each player's race and skill tree, as spent so far.
+    pub fn skill_trees(&self) -> Vec<(Race, SkillTree)> {
+        self.races.iter().cloned().zip(self.trees.iter().cloned()).collect()
+    }
+
+    /// The sounds made since the last call, in order.
+    pub fn take_sounds(&mut self) -> Vec<SoundEvent> {
+        std::mem::take(&mut self.sounds)
+    }
+
+    /// Picks a player with left and right, a node of their tree with up and down, unlocks it on
+    /// Enter and moves on with Space. Selections wrap around at either end.
+    fn navigate(&mut self, pressed: &[Input]) {
+        for input in pressed {
+            let sound = match input {
+                Input::Key(KeyCode::Left, _) | Input::Key(KeyCode::A, _) | Input::Button(Button::DPadLeft, _) => {
+                    self.select_player(self.trees.len().saturating_sub(1));
+                    SoundEvent::MenuMove
+                },
+                Input::Key(KeyCode::Right, _) | Input::Key(KeyCode::D, _) | Input::Button(Button::DPadRight, _) => {
+                    self.select_player(1);
+                    SoundEvent::MenuMove
+                },
+                Input::Key(KeyCode::Up, _) | Input::Key(KeyCode::W, _) | Input::Button(Button::DPadUp, _) => {
+                    self.select_node(self.node_count().saturating_sub(1));
+                    SoundEvent::MenuMove
+                },
+                Input::Key(KeyCode::Down, _) | Input::Key(KeyCode::S, _) | Input::Button(Button::DPadDown, _) => {
+                    self.select_node(1);
+                    SoundEvent::MenuMove
+                },
+                Input::Key(KeyCode::Return, _) | Input::Button(Button::South, _) => {
+                    self.unlock_selected();
+                    SoundEvent::MenuSelect
+                },
+                Input::Key(KeyCode::Space, _) | Input::Button(Button::Start, _) => {
+                    self.done = true;
+                    SoundEvent::MenuSelect
+                },
+                _ => continue,
+            };
+            self.sounds.push(sound);
+        }
+    }
+
+    fn node_count(&self) -> usize {
+        self.trees.get(self.player).map_or(0, |tree| tree.nodes.len())
+    }
+
+    /// Moves the player selection `step` places along, wrapping around.
+    fn select_player(&mut self, step: usize) {
+        if !self.trees.is_empty() {
+            self.player = (self.player + step) % self.trees.len();
+            self.node = 0;
+            self.error = None;
+            self.refresh_tree();
+        }
+    }
+
+    /// Moves the node selection `step` places along, wrapping around.
+    fn select_node(&mut self, step: usize) {
+        let count = self.node_count();
+        if count > 0 {
+            self.node = (self.node + step) % count;
+            self.refresh_tree();
+        }
+    }
+
+    fn unlock_selected(&mut self) {
+        let tree = match self.trees.get_mut(self.player) {
+            Some(tree) => tree,
+            None => return,
+        };
+        if let Some(&id) = tree.nodes.keys().nth(self.node) {
+            self.error = tree.unlock(id).err().map(|e| e.to_string());
+            self.refresh_tree();
+        }
+    }
+
+    /// Lists the selected player's nodes, marking the unlocked, affordable and highlighted ones.
+    fn refresh_tree(&mut self) {
+        let tree = match self.trees.get(self.player) {
+            Some(tree) => tree,
+            None => return,
+        };
+        let mut lines = vec![format!("Player {}: {} skill points to spend", self.player + 1, tree.skill_points)];
+        for (idx, (id, node)) in tree.nodes.iter().enumerate() {
+            let cursor = if idx == self.node { ">" } else { " " };
+            let state = if tree.is_unlocked(*id) {
+                "x"
+            } else if tree.can_unlock(*id) {
+                "+"
+            } else {
+                " "
+            };
+            lines.push(format!("{} [{}] {} ({}): {}", cursor, state, node.name, node.cost, node.description));
+        }
+        lines.extend(self.error.clone());
+        self.tree_text = Text::new(lines.join("\n"));
+    }
+}
+
+impl Drawable for ResultsData {
+    fn draw(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
+        let screen = viewport::virtual_screen();
+        let mut y = screen.h / 6.;
+        for text in &[&self.headline, &self.details, &self.tree_text, &self.hint] {
+            let mut text_param = param;
+            text_param.dest.x += (screen.w - text.width(ctx) as f32) / 2.;
+            text_param.dest.y += y;
+            text.draw(ctx, text_param)?;
+            y += text.height(ctx) as f32 + 20.;
+        }
+        Ok(())
+    }
+
+    fn dimensions(&self, _ctx: &mut Context) -> Option<Rect> {
+        None
+    }
+
+    fn set_blend_mode(&mut self, mode: Option<BlendMode>) {
+        self.mode = mode;
+    }
+
+    fn blend_mode(&self) -> Option<BlendMode> {
+        self.mode
+    }
+}
+
+impl HandleInput for ResultsData {
+    fn handle_input(&mut self, _ctx: &mut Context, fire_once_key_buffer: &Vec<Input>) {
+        self.navigate(fire_once_key_buffer);
+    }
+}
+
+#[cfg(test)]
+mod results_test {
+    use super::*;
+    use crate::screens::battle::player::{self, meta::{Handicap, Race}};
+
+    const RIGHT: Input = Input::Key(KeyCode::Right, KeyMods::NONE);
+    const DOWN: Input = Input::Key(KeyCode::Down, KeyMods::NONE);
+    const ENTER: Input = Input::Key(KeyCode::Return, KeyMods::NONE);
+    const SPACE: Input = Input::Key(KeyCode::Space, KeyMods::NONE);
+
+    fn results(trees: Vec<SkillTree>) -> ResultsData {
+        ResultsData {
+            mode: None,
+            headline: Text::default(),
+            details: Text::default(),
+            hint: Text::default(),
+            races: vec![Race::Alien; trees.len()],
+            trees,
+            player: 0,
+            node: 0,
+            error: None,
+            tree_text: Text::default(),
+            done: false,
+            sounds: vec![],
+        }
+    }
+
+    #[test]
+    fn earned_points_spent_per_player() {
+        let mut trees = vec![SkillTree::for_race(&Race::Alien), SkillTree::for_race(&Race::Robot)];
+        trees[1].skill_points = 3;
+        let mut results = results(trees);
+        // Player 1 has nothing to spend.
+        results.navigate(&[ENTER]);
+        assert!(results.trees[0].unlocked.is_empty());
+        assert!(results.error.is_some());
+        results.navigate(&[RIGHT, ENTER, DOWN, ENTER]);
+        assert!(results.trees[1].is_unlocked(0) && results.trees[1].is_unlocked(1));
+        assert!(results.trees[1].skill_points == 0);
+        assert!(results.error.is_none());
+    }
+
+    #[test]
+    fn stats_of_finished_battle() {
+        let mut players = vec![player::bare_test_player(), player::bare_test_player()];
+        players[0].add_damage_dealt(123.4);
+        players[0].knock_out();
+        for _ in 0..3 {
+            players[1].knock_out();
+        }
+        let mut battle = battle::test_battle(players);
+        battle.handle_update(&[]);
+        let player_stats = PostMatchStats::of(&battle);
+        let rewards = battle.compute_rewards();
+        assert!(player_stats.len() == 2);
+        assert!(player_stats[0].damage_dealt == 123.4 && player_stats[0].stocks_lost == 1);
+        assert!(player_stats[1].damage_dealt == 0. && player_stats[1].stocks_lost == 3);
+        assert!(player_stats.iter().zip(&rewards).all(|(stats, (_, points))| stats.skill_points == *points));
+        assert!(headline(battle.winner(), battle.is_draw(), &player_stats) == "P1 Test wins!");
+        let results = ResultsData::new(&battle, KeyboardLayout::Qwerty, &SaveData::default());
+        assert!(results.details.contents().starts_with("P1 Test: 123 damage dealt, 1 stocks lost"));
+        assert!(results.trees[0].skill_points == player_stats[0].skill_points);
+    }
+
+    #[test]
+    fn handicapped_winner_outlasts_rather_than_outscores() {
+        let mut players = vec![player::bare_test_player(), player::bare_test_player()];
+        players[0].set_handicap(Handicap { stock_offset: 1, ..Default::default() });
+        for player in &mut players {
+            player.start_round(None);
+            for _ in 0..3 {
+                player.knock_out();
+            }
+        }
+        let mut battle = battle::test_battle(players);
+        battle.handle_update(&[]);
+        let player_stats = PostMatchStats::of(&battle);
+        // Both lost as many stocks, but only the second ran out.
+        assert!(player_stats.iter().all(|stats| stats.stocks_lost == 3));
+        assert!(battle.winner() == Some(0) && !battle.is_draw());
+        assert!(headline(battle.winner(), battle.is_draw(), &player_stats) == "P1 Test wins!");
+    }
+
+    #[test]
+    fn stats_listed_per_player() {
+        let player_stats = vec![
+            PostMatchStats { name: "Alien".to_owned(), damage_dealt: 87.6, stocks_lost: 2, skill_points: 9 },
+            PostMatchStats { name: "Robot".to_owned(), damage_dealt: 240., stocks_lost: 0, skill_points: 14 },
+        ];
+        let table = stats_table(&player_stats, 90 * 60);
+        assert!(table == "P1 Alien: 88 damage dealt, 2 stocks lost, +9 skill points\n\
+            P2 Robot: 240 damage dealt, 0 stocks lost, +14 skill points\n\
+            Time: 01:30");
+        assert!(headline(Some(1), false, &player_stats) == "P2 Robot wins!");
+        assert!(headline(None, true, &player_stats) == "Draw");
+        assert!(headline(None, false, &[]) == "No contest");
+    }
+
+    #[test]
+    fn space_continues_to_main_menu() {
+        let mut results = results(vec![]);
+        results.navigate(&[RIGHT, DOWN, ENTER]);
+        assert!(results.handle_update() == Transition::None);
+        results.navigate(&[SPACE]);
+        assert!(results.handle_update() == Transition::MainMenu);
+        assert!(results.handle_update() == Transition::None);
+    }
+}
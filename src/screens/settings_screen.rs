@@ -0,0 +1,268 @@
+use ggez::{Context, GameResult};
+use ggez::event::{Button, KeyCode, KeyMods};
+use ggez::graphics::{Drawable, DrawParam, Rect, Text, BlendMode};
+
+use crate::{
+    audio::SoundEvent,
+    inputs::{HandleInput, Input, layout::{self, KeyboardLayout}},
+    screens::{Transition, mainmenu::HIGHLIGHT},
+    settings::{self, Settings},
+    viewport,
+};
+
+/// How much Left and Right change a volume by.
+const VOLUME_STEP: f32 = 0.1;
+
+/// The settings that can be looked at in game, top to bottom.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SettingsEntry {
+    LogLevel,
+    MasterVolume,
+    MusicVolume,
+    SfxVolume,
+    Fullscreen,
+    /// Only shown, since the assets can't be swapped while they're in use.
+    AssetRoot,
+}
+
+impl SettingsEntry {
+    pub const ALL: [SettingsEntry; 6] = [
+        SettingsEntry::LogLevel,
+        SettingsEntry::MasterVolume,
+        SettingsEntry::MusicVolume,
+        SettingsEntry::SfxVolume,
+        SettingsEntry::Fullscreen,
+        SettingsEntry::AssetRoot,
+    ];
+
+    /// The entry's name along with its value in `settings`.
+    fn label(self, settings: &Settings) -> String {
+        let percent = |volume: f32| format!("< {:.0}% >", volume * 100.);
+        match self {
+            SettingsEntry::LogLevel => format!("Log level: < {} >", settings.logging.level),
+            SettingsEntry::MasterVolume => format!("Master volume: {}", percent(settings.audio.master_volume)),
+            SettingsEntry::MusicVolume => format!("Music volume: {}", percent(settings.audio.music_volume)),
+            SettingsEntry::SfxVolume => format!("Sound effects volume: {}", percent(settings.audio.sfx_volume)),
+            SettingsEntry::Fullscreen => {
+                format!("Fullscreen: < {} >", if settings.window.fullscreen { "On" } else { "Off" })
+            },
+            SettingsEntry::AssetRoot => format!("Asset root: {}", settings.assets.root.display()),
+        }
+    }
+
+    /// Steps the entry's value in `settings` by `step`, which is 1 or -1. Log levels and volumes
+    /// stop at either end. Returns whether anything changed.
+    fn adjust(self, settings: &mut Settings, step: i32) -> bool {
+        let volume = |volume: &mut f32| {
+            let stepped = ((*volume + step as f32 * VOLUME_STEP) / VOLUME_STEP).round() * VOLUME_STEP;
+            let changed = stepped.clamp(0., 1.) != *volume;
+            *volume = stepped.clamp(0., 1.);
+            changed
+        };
+        match self {
+            SettingsEntry::LogLevel => {
+                let levels = &settings::LOG_LEVELS;
+                let level = settings.logging.level.to_lowercase();
+                // Unknown levels are read as `info`, as they are when logging is set up.
+                let current = levels.iter().position(|known| *known == level).unwrap_or(3) as i32;
+                let stepped = (current + step).clamp(0, levels.len() as i32 - 1) as usize;
+                let changed = levels[stepped] != settings.logging.level;
+                settings.logging.level = levels[stepped].to_owned();
+                changed
+            },
+            SettingsEntry::MasterVolume => volume(&mut settings.audio.master_volume),
+            SettingsEntry::MusicVolume => volume(&mut settings.audio.music_volume),
+            SettingsEntry::SfxVolume => volume(&mut settings.audio.sfx_volume),
+            SettingsEntry::Fullscreen => {
+                settings.window.fullscreen = !settings.window.fullscreen;
+                true
+            },
+            SettingsEntry::AssetRoot => false,
+        }
+    }
+}
+
+/// Changes the settings while the game runs. Nothing is kept until the changes are saved.
+#[derive(Debug)]
+pub struct SettingsScreenData {
+    /// `ggez`-specific. Not really used for anything atm.
+    mode: Option<BlendMode>,
+    /// The settings with the changes made so far.
+    settings: Settings,
+    /// The index of the highlighted entry in `SettingsEntry::ALL`.
+    selected: usize,
+    /// Each entry along with its value, rebuilt whenever the value changes.
+    labels: Vec<Text>,
+    hint: Text,
+    /// The transition picked since the last update, if any.
+    chosen: Option<Transition>,
+    /// The sounds made since the last `take_sounds`.
+    sounds: Vec<SoundEvent>,
+}
+
+impl SettingsScreenData {
+    /// Starts off from a copy of `settings`, with keys labelled for `layout`.
+    pub fn new(settings: Settings, layout: KeyboardLayout) -> Self {
+        let labels = SettingsEntry::ALL.iter().map(|entry| Text::new(entry.label(&settings))).collect();
+        Self {
+            mode: None,
+            settings,
+            selected: 0,
+            labels,
+            hint: Text::new([
+                layout::prompt("save", &[(KeyCode::Return, KeyMods::NONE)], layout),
+                layout::prompt("discard", &[(KeyCode::Escape, KeyMods::NONE)], layout),
+            ].join("  ")),
+            chosen: None,
+            sounds: vec![],
+        }
+    }
+
+    /// Saves or discards the changes once either is picked, going back to the main menu.
+    pub fn handle_update(&mut self) -> Transition {
+        self.chosen.take().unwrap_or(Transition::None)
+    }
+
+    pub fn selected(&self) -> SettingsEntry {
+        SettingsEntry::ALL[self.selected]
+    }
+
+    /// The settings with the changes made so far.
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// The sounds made since the last call, in order.
+    pub fn take_sounds(&mut self) -> Vec<SoundEvent> {
+        std::mem::take(&mut self.sounds)
+    }
+
+    /// Moves the highlight up and down, wrapping around at either end, and changes the highlighted
+    /// setting with left and right. Enter saves and Escape discards.
+    fn navigate(&mut self, pressed: &[Input]) {
+        let count = SettingsEntry::ALL.len();
+        for input in pressed {
+            match input {
+                Input::Key(KeyCode::Up, _) | Input::Key(KeyCode::W, _) | Input::Button(Button::DPadUp, _) => {
+                    self.selected = (self.selected + count - 1) % count;
+                    self.sounds.push(SoundEvent::MenuMove);
+                },
+                Input::Key(KeyCode::Down, _) | Input::Key(KeyCode::S, _) | Input::Button(Button::DPadDown, _) => {
+                    self.selected = (self.selected + 1) % count;
+                    self.sounds.push(SoundEvent::MenuMove);
+                },
+                Input::Key(KeyCode::Left, _) | Input::Key(KeyCode::A, _) | Input::Button(Button::DPadLeft, _) => {
+                    self.adjust(-1);
+                },
+                Input::Key(KeyCode::Right, _) | Input::Key(KeyCode::D, _) | Input::Button(Button::DPadRight, _) => {
+                    self.adjust(1);
+                },
+                Input::Key(KeyCode::Return, _) | Input::Button(Button::South, _) => {
+                    self.chosen = Some(Transition::SaveSettings);
+                    self.sounds.push(SoundEvent::MenuSelect);
+                },
+                Input::Key(KeyCode::Escape, _) | Input::Button(Button::East, _) => {
+                    self.chosen = Some(Transition::MainMenu);
+                    self.sounds.push(SoundEvent::MenuSelect);
+                },
+                _ => (),
+            }
+        }
+    }
+
+    fn adjust(&mut self, step: i32) {
+        let entry = self.selected();
+        if entry.adjust(&mut self.settings, step) {
+            self.labels[self.selected] = Text::new(entry.label(&self.settings));
+            self.sounds.push(SoundEvent::MenuMove);
+        }
+    }
+}
+
+impl Drawable for SettingsScreenData {
+    fn draw(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
+        let screen = viewport::virtual_screen();
+        let mut y = screen.h / 4.;
+        for (idx, label) in self.labels.iter().enumerate() {
+            let mut label_param = param;
+            if idx == self.selected {
+                label_param.color = HIGHLIGHT;
+            }
+            label_param.dest.x += (screen.w - label.width(ctx) as f32) / 2.;
+            label_param.dest.y += y;
+            label.draw(ctx, label_param)?;
+            y += label.height(ctx) as f32 + 20.;
+        }
+        let mut hint_param = param;
+        hint_param.dest.x += (screen.w - self.hint.width(ctx) as f32) / 2.;
+        hint_param.dest.y += y + 20.;
+        self.hint.draw(ctx, hint_param)
+    }
+
+    fn dimensions(&self, _ctx: &mut Context) -> Option<Rect> {
+        None
+    }
+
+    fn set_blend_mode(&mut self, mode: Option<BlendMode>) {
+        self.mode = mode;
+    }
+
+    fn blend_mode(&self) -> Option<BlendMode> {
+        self.mode
+    }
+}
+
+impl HandleInput for SettingsScreenData {
+    fn handle_input(&mut self, _ctx: &mut Context, fire_once_key_buffer: &Vec<Input>) {
+        self.navigate(fire_once_key_buffer);
+    }
+}
+
+#[cfg(test)]
+mod settings_screen_test {
+    use super::*;
+
+    const UP: Input = Input::Key(KeyCode::Up, KeyMods::NONE);
+    const DOWN: Input = Input::Key(KeyCode::Down, KeyMods::NONE);
+    const LEFT: Input = Input::Key(KeyCode::Left, KeyMods::NONE);
+    const RIGHT: Input = Input::Key(KeyCode::Right, KeyMods::NONE);
+
+    #[test]
+    fn volumes_stepped_within_range() {
+        let mut screen = SettingsScreenData::new(Settings::default(), KeyboardLayout::Qwerty);
+        screen.navigate(&[DOWN, LEFT, LEFT, LEFT]);
+        assert!(screen.selected() == SettingsEntry::MasterVolume);
+        assert!((screen.settings().audio.master_volume - 0.7).abs() < 1e-6);
+        screen.navigate(&[RIGHT, RIGHT, RIGHT, RIGHT, RIGHT]);
+        assert!(screen.settings().audio.master_volume == 1.);
+        assert!(screen.labels[1].contents() == "Master volume: < 100% >");
+        // Only changes make a sound.
+        assert!(screen.take_sounds().len() == 7);
+    }
+
+    #[test]
+    fn log_level_and_fullscreen_cycled() {
+        let mut screen = SettingsScreenData::new(Settings::default(), KeyboardLayout::Qwerty);
+        screen.navigate(&[RIGHT, RIGHT, RIGHT]);
+        assert!(screen.settings().logging.level == "trace");
+        screen.navigate(&[LEFT]);
+        assert!(screen.settings().logging.level == "debug");
+        screen.navigate(&[UP, UP, LEFT]);
+        assert!(screen.selected() == SettingsEntry::Fullscreen);
+        assert!(screen.settings().window.fullscreen);
+        // The asset root is only shown.
+        screen.navigate(&[DOWN, RIGHT]);
+        assert!(screen.settings().assets.root == Settings::default().assets.root);
+    }
+
+    #[test]
+    fn enter_saves_escape_discards() {
+        let mut screen = SettingsScreenData::new(Settings::default(), KeyboardLayout::Qwerty);
+        assert!(screen.handle_update() == Transition::None);
+        screen.navigate(&[Input::Key(KeyCode::Return, KeyMods::NONE)]);
+        assert!(screen.handle_update() == Transition::SaveSettings);
+        screen.navigate(&[Input::Button(Button::East, 0)]);
+        assert!(screen.handle_update() == Transition::MainMenu);
+        assert!(screen.handle_update() == Transition::None);
+    }
+}
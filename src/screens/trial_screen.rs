@@ -0,0 +1,259 @@
+use ggez::{Context, GameResult};
+use ggez::event::KeyCode;
+use ggez::graphics::{self, Color, Drawable, DrawParam, MeshBuilder, Rect, Text, BlendMode};
+
+use crate::{
+    audio::SoundEvent,
+    debug::DebugChannels,
+    inputs::{HandleInput, Input},
+    screens::{
+        Transition,
+        battle::{
+            BattleData,
+            player::action::{Action, Attack},
+            proof::RunProof,
+            trial::{Tick, TrialRun, TrialStatus},
+        },
+        mainmenu::HIGHLIGHT,
+    },
+    util::result::WalpurgisResult,
+    walpurgis::TICKS_PER_SECOND,
+};
+
+/// How long a completed trial is left up before it starts over.
+const COMPLETE_TICKS: u32 = 2 * TICKS_PER_SECOND;
+const CHECKMARK_COLOR: Color = Color { r: 0.3, g: 0.9, b: 0.3, a: 1. };
+const CHECKMARK_SIZE: f32 = 16.;
+/// How far the step list sits from the top left of the screen.
+const MARGIN: f32 = 20.;
+
+/// A step's action as players would put it, e.g. "Light side attack".
+fn step_label(action: &Action) -> String {
+    match action {
+        Action::Attack(Attack::Basics(class, dir)) => {
+            format!("{:?} {} attack", class, format!("{:?}", dir).to_lowercase())
+        },
+        Action::Attack(Attack::Ability(ability)) => format!("{:?}", ability),
+        Action::Attack(attack) => format!("{:?}", attack),
+        action => format!("{:?}", action),
+    }
+}
+
+/// Replays `proof` in the content `run` is played on. Returns the tick the proven run completes
+/// the trial on.
+fn verify(proof: &RunProof, run: &TrialRun) -> WalpurgisResult<Tick> {
+    proof.verify(run.arena().clone(), run.trial().clone(), run.blueprint(), run.tuning())
+}
+
+fn last_run_label(completion_tick: Tick) -> String {
+    format!("Last run: {} ticks (verified)", completion_tick + 1)
+}
+
+/// A combo trial being played. The steps are listed over the battle, checked off as they're
+/// performed, and the trial starts over whenever it's failed or a while after it's completed.
+#[derive(Debug)]
+pub struct TrialScreenData {
+    /// `ggez`-specific. Not really used for anything atm.
+    mode: Option<BlendMode>,
+    run: TrialRun,
+    /// Built once, since the trial never changes.
+    title: Text,
+    steps: Vec<Text>,
+    cleared: Text,
+    /// Whether the profile has completed the trial, now or before.
+    is_cleared: bool,
+    /// The ticks left until the completed trial starts over, once it's been completed.
+    celebration: Option<u32>,
+    /// The name of the trial, once it's completed and until the completion is saved.
+    completed: Option<String>,
+    /// The proof of the completed run, until it's saved.
+    proof: Option<RunProof>,
+    /// How long the last verified run took, if there is one.
+    last_run: Option<Text>,
+    /// The transition picked since the last update, if any.
+    chosen: Option<Transition>,
+    /// The sounds made since the last `take_sounds`.
+    sounds: Vec<SoundEvent>,
+}
+
+impl TrialScreenData {
+    /// Plays `run`, which the profile has already completed if `is_cleared`, showing how long the
+    /// run in `last_proof` took if it holds up.
+    pub fn new(run: TrialRun, is_cleared: bool, last_proof: Option<RunProof>) -> Self {
+        let last_run = last_proof.and_then(|proof| {
+            verify(&proof, &run).map_err(|e| log::warn!("The last run proof doesn't hold up: {}", e)).ok()
+        });
+        let trial = run.trial();
+        Self {
+            mode: None,
+            title: Text::new(trial.name.as_str()),
+            steps: trial.steps.iter().map(|step| Text::new(step_label(&step.action))).collect(),
+            cleared: Text::new("Cleared!"),
+            run,
+            is_cleared,
+            celebration: None,
+            completed: None,
+            proof: None,
+            last_run: last_run.map(|tick| Text::new(last_run_label(tick))),
+            chosen: None,
+            sounds: vec![],
+        }
+    }
+
+    /// Plays a tick of the trial, or of the wait after it's completed.
+    pub fn handle_update(&mut self) -> Transition {
+        if let Some(transition) = self.chosen.take() {
+            return transition;
+        }
+        let input = self.run.battle_mut().take_inputs().into_iter().next().unwrap_or_default();
+        match self.celebration {
+            Some(0) => {
+                self.celebration = None;
+                self.run.restart();
+            },
+            Some(ticks) => self.celebration = Some(ticks - 1),
+            None => {
+                if self.run.tick(input) == TrialStatus::Complete {
+                    log::info!("Trial `{}` completed in {} ticks.", self.run.trial().name, self.run.elapsed_ticks());
+                    self.celebration = Some(COMPLETE_TICKS);
+                    self.is_cleared = true;
+                    self.completed = Some(self.run.trial().name.clone());
+                    match RunProof::of(&self.run).and_then(|proof| Ok((verify(&proof, &self.run)?, proof))) {
+                        Ok((tick, proof)) => {
+                            self.last_run = Some(Text::new(last_run_label(tick)));
+                            self.proof = Some(proof);
+                        },
+                        Err(e) => log::warn!("Failed to prove the run, it won't be saved: {}", e),
+                    }
+                    self.sounds.push(SoundEvent::MenuSelect);
+                }
+            },
+        }
+        Transition::None
+    }
+
+    /// The name of the trial if it was completed since the last call.
+    pub fn take_completed(&mut self) -> Option<String> {
+        self.completed.take()
+    }
+
+    /// The proof of the run if one was completed since the last call.
+    pub fn take_proof(&mut self) -> Option<RunProof> {
+        self.proof.take()
+    }
+
+    pub fn battle(&self) -> &BattleData {
+        self.run.battle()
+    }
+
+    pub fn set_debug_channels(&mut self, channels: &DebugChannels) {
+        self.run.battle_mut().set_debug_channels(channels);
+    }
+
+    pub fn prepare(&mut self) {
+        self.run.battle_mut().prepare();
+    }
+
+    /// The sounds made by the battle, then by the trial.
+    pub fn take_sounds(&mut self) -> Vec<SoundEvent> {
+        let mut sounds = self.run.battle_mut().take_sounds();
+        sounds.append(&mut self.sounds);
+        sounds
+    }
+}
+
+impl Drawable for TrialScreenData {
+    fn draw(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
+        self.run.battle().draw(ctx, param)?;
+
+        let mut title_param = param;
+        title_param.dest.x += MARGIN;
+        title_param.dest.y += MARGIN;
+        self.title.draw(ctx, title_param)?;
+        if self.is_cleared {
+            let mut cleared_param = param.color(CHECKMARK_COLOR);
+            cleared_param.dest.x += MARGIN + self.title.width(ctx) as f32 + MARGIN;
+            cleared_param.dest.y += MARGIN;
+            self.cleared.draw(ctx, cleared_param)?;
+        }
+
+        let validator = self.run.validator();
+        let current = match validator.status() {
+            TrialStatus::InProgress(step) => Some(*step),
+            TrialStatus::Failed(_) | TrialStatus::Complete => None,
+        };
+        let mut checkmarks = MeshBuilder::new();
+        let mut any_checked = false;
+        let mut y = MARGIN + self.title.height(ctx) as f32 + MARGIN;
+        let x = MARGIN + CHECKMARK_SIZE + 10.;
+        for (idx, step) in self.steps.iter().enumerate() {
+            if validator.is_step_complete(idx) {
+                let bottom = y + step.height(ctx) as f32 * 0.8;
+                checkmarks.line(&[
+                    [MARGIN, bottom - CHECKMARK_SIZE / 2.],
+                    [MARGIN + CHECKMARK_SIZE / 3., bottom],
+                    [MARGIN + CHECKMARK_SIZE, bottom - CHECKMARK_SIZE],
+                ], 3., CHECKMARK_COLOR)?;
+                any_checked = true;
+            }
+            let mut step_param = param;
+            if current == Some(idx) {
+                step_param.color = HIGHLIGHT;
+            }
+            step_param.dest.x += x;
+            step_param.dest.y += y;
+            step.draw(ctx, step_param)?;
+            y += step.height(ctx) as f32 + 8.;
+        }
+        if any_checked {
+            checkmarks.build(ctx)?.draw(ctx, param.color(graphics::WHITE))?;
+        }
+        if let Some(last_run) = &self.last_run {
+            let mut last_run_param = param;
+            last_run_param.dest.x += MARGIN;
+            last_run_param.dest.y += y + MARGIN;
+            last_run.draw(ctx, last_run_param)?;
+        }
+        Ok(())
+    }
+
+    fn dimensions(&self, _ctx: &mut Context) -> Option<Rect> {
+        None
+    }
+
+    fn set_blend_mode(&mut self, mode: Option<BlendMode>) {
+        self.mode = mode;
+    }
+
+    fn blend_mode(&self) -> Option<BlendMode> {
+        self.mode
+    }
+}
+
+impl HandleInput for TrialScreenData {
+    /// Escape goes back to the main menu. Everything else is played in the battle.
+    fn handle_input(&mut self, ctx: &mut Context, fire_once_key_buffer: &Vec<Input>) {
+        if fire_once_key_buffer.iter().any(|input| matches!(input, Input::Key(KeyCode::Escape, _))) {
+            self.chosen = Some(Transition::MainMenu);
+            self.sounds.push(SoundEvent::MenuSelect);
+            return;
+        }
+        self.run.battle_mut().handle_input(ctx, fire_once_key_buffer);
+    }
+
+    fn handle_key_up(&mut self, ctx: &mut Context, released_key_buffer: &Vec<Input>) {
+        self.run.battle_mut().handle_key_up(ctx, released_key_buffer);
+    }
+}
+
+#[cfg(test)]
+mod trial_screen_test {
+    use super::*;
+    use crate::screens::battle::player::{meta::BasicClass, action::AttackDir};
+
+    #[test]
+    fn steps_labelled_for_players() {
+        assert!(step_label(&Action::Attack(Attack::Basics(BasicClass::Light, AttackDir::Side))) == "Light side attack");
+        assert!(step_label(&Action::Jump) == "Jump");
+    }
+}
@@ -1,8 +1,26 @@
-use std::path::PathBuf;
-use config::{Config, ConfigError, File};
-use serde::{Serialize, Deserialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use config::{Config, File};
+use ggez::conf::FullscreenType;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::IntoDeserializer;
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::debug::DebugChannel;
+use crate::inputs::{KeyPress, PadId};
+use crate::inputs::keys::{self, KeyBinding};
+use crate::inputs::layout::KeyboardLayout;
+use crate::persistence::{PersistenceWorker, WriteJob, WritePriority};
+use crate::util::result::{WalpurgisError, WalpurgisResult};
+
+/// The settings file, read on startup and written back when settings are changed in game.
+const CFG_PATH: &str = "walpurgis.toml";
+
+/// Every `logging.level` accepted, from quietest to noisiest.
+pub const LOG_LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Logging {
     pub level: String,
     pub file: PathBuf,
@@ -19,7 +37,7 @@ impl Default for Logging {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Assets {
     pub root: PathBuf,
 }
@@ -32,19 +50,678 @@ impl Default for Assets {
     }
 }
 
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct Fonts {
+    /// A font file relative to the asset root. Uses the font bundled with `ggez` if unset.
+    pub file: Option<PathBuf>,
+}
+
+/// A setting chosen from a fixed set of values, which can list them.
+pub trait Choices: fmt::Debug + Sized + 'static {
+    fn choices() -> &'static [Self];
+}
+
+impl Choices for KeyboardLayout {
+    fn choices() -> &'static [Self] {
+        &KeyboardLayout::ALL
+    }
+}
+
+impl Choices for DebugChannel {
+    fn choices() -> &'static [Self] {
+        &DebugChannel::ALL
+    }
+}
+
+/// Wraps a setting so that a typo in its value only resets that setting, rather than failing to
+/// load the whole file. Invalid values are kept so that they can be reported.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Lenient<T> {
+    Valid(T),
+    Invalid(String),
+}
+
+impl<T> Lenient<T> {
+    pub fn valid(&self) -> Option<&T> {
+        match self {
+            Lenient::Valid(value) => Some(value),
+            Lenient::Invalid(_) => None,
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Lenient<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        let parsed = T::deserialize(IntoDeserializer::<serde::de::value::Error>::into_deserializer(raw.as_str()));
+        Ok(match parsed {
+            Ok(value) => Lenient::Valid(value),
+            Err(_) => Lenient::Invalid(raw),
+        })
+    }
+}
+
+impl<T: Serialize> Serialize for Lenient<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Lenient::Valid(value) => value.serialize(serializer),
+            Lenient::Invalid(raw) => raw.serialize(serializer),
+        }
+    }
+}
+
+/// A setting whose value was not accepted and was reset to its default.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidSetting {
+    /// The path to the setting, e.g. `controls.keyboard_layout`.
+    pub field: String,
+    pub value: String,
+    pub accepted: Vec<String>,
+}
+
+impl InvalidSetting {
+    fn check<T: Choices>(field: &str, setting: &Lenient<T>) -> Option<Self> {
+        match setting {
+            Lenient::Valid(_) => None,
+            Lenient::Invalid(value) => Some(InvalidSetting {
+                field: field.into(),
+                value: value.clone(),
+                accepted: T::choices().iter().map(|choice| format!("{:?}", choice)).collect(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for InvalidSetting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "`{}` can't be `{}`, it must be one of: {}. Using the default instead.",
+            self.field,
+            self.value,
+            self.accepted.join(", "),
+        )
+    }
+}
+
+/// One player's keys, written like `jump = "Space"` or `dash_left = "Shift + A"`. Unset actions
+/// keep that player's default key. A player can also be given a gamepad, by its number in the
+/// order gamepads are first used. The second player gets the first gamepad unless it is given to
+/// another player.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub walk_left: Option<Lenient<KeyBinding>>,
+    pub walk_right: Option<Lenient<KeyBinding>>,
+    pub fast_fall: Option<Lenient<KeyBinding>>,
+    pub shield: Option<Lenient<KeyBinding>>,
+    pub jump: Option<Lenient<KeyBinding>>,
+    pub dash_left: Option<Lenient<KeyBinding>>,
+    pub dash_right: Option<Lenient<KeyBinding>>,
+    pub ranged: Option<Lenient<KeyBinding>>,
+    pub attack: Option<Lenient<KeyBinding>>,
+    pub grab: Option<Lenient<KeyBinding>>,
+    pub ability: Option<Lenient<KeyBinding>>,
+    pub second_ability: Option<Lenient<KeyBinding>>,
+    pub gamepad: Option<PadId>,
+}
+
+impl KeyBindings {
+    /// Every action along with the key set for it, if any.
+    fn named(&self) -> [(&'static str, &Option<Lenient<KeyBinding>>); 12] {
+        [
+            ("walk_left", &self.walk_left),
+            ("walk_right", &self.walk_right),
+            ("fast_fall", &self.fast_fall),
+            ("shield", &self.shield),
+            ("jump", &self.jump),
+            ("dash_left", &self.dash_left),
+            ("dash_right", &self.dash_right),
+            ("ranged", &self.ranged),
+            ("attack", &self.attack),
+            ("grab", &self.grab),
+            ("ability", &self.ability),
+            ("second_ability", &self.second_ability),
+        ]
+    }
+
+    /// The actions set to a valid key, with their keys.
+    pub fn valid(&self) -> Vec<(&'static str, KeyPress)> {
+        self.named()
+            .iter()
+            .filter_map(|(action, binding)| {
+                binding.as_ref().and_then(Lenient::valid).map(|KeyBinding(input)| (*action, *input))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Controls {
+    /// The layout keys are labelled for. Guessed from the locale if unset.
+    pub keyboard_layout: Option<Lenient<KeyboardLayout>>,
+    /// The number of players sharing the keyboard.
+    pub local_players: usize,
+    /// Each player's keys, written as `[[controls.players]]` tables in player order.
+    pub players: Vec<KeyBindings>,
+    /// How far a gamepad stick has to be pushed before it counts, from 0 to 1.
+    pub gamepad_deadzone: f32,
+}
+impl Default for Controls {
+    fn default() -> Self {
+        Self {
+            keyboard_layout: None,
+            local_players: 2,
+            players: vec![],
+            gamepad_deadzone: 0.25,
+        }
+    }
+}
+
+impl Controls {
+    pub fn keyboard_layout(&self) -> Option<KeyboardLayout> {
+        self.keyboard_layout.as_ref().and_then(Lenient::valid).cloned()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Audio {
+    /// The most ambient sounds that may play at once. The nearest ones are played.
+    pub max_ambient_emitters: usize,
+    /// Plays fewer sounds at once, for slower machines.
+    #[serde(default)]
+    pub low_detail: bool,
+    /// Scales both of the other volumes, from 0 to 1.
+    #[serde(default = "full_volume")]
+    pub master_volume: f32,
+    /// Starts out a little under the sound effects, so that they aren't drowned out.
+    #[serde(default = "default_music_volume")]
+    pub music_volume: f32,
+    #[serde(default = "full_volume")]
+    pub sfx_volume: f32,
+}
+impl Default for Audio {
+    fn default() -> Self {
+        Self {
+            max_ambient_emitters: 8,
+            low_detail: false,
+            master_volume: 1.,
+            music_volume: default_music_volume(),
+            sfx_volume: 1.,
+        }
+    }
+}
+
+fn full_volume() -> f32 {
+    1.
+}
+
+fn default_music_volume() -> f32 {
+    0.8
+}
+
+impl Audio {
+    /// The number of ambient sounds that may play at once, taking `low_detail` into account.
+    pub fn ambient_emitter_cap(&self) -> usize {
+        const LOW_DETAIL_EMITTERS: usize = 2;
+
+        if self.low_detail {
+            self.max_ambient_emitters.min(LOW_DETAIL_EMITTERS)
+        } else {
+            self.max_ambient_emitters
+        }
+    }
+
+    /// The volume music plays at, scaled by the master volume.
+    pub fn effective_music_volume(&self) -> f32 {
+        (self.master_volume * self.music_volume).clamp(0., 1.)
+    }
+
+    /// The volume sound effects play at, scaled by the master volume.
+    pub fn effective_sfx_volume(&self) -> f32 {
+        (self.master_volume * self.sfx_volume).clamp(0., 1.)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Power {
+    /// Minutes without input in the background before a battle is paused.
+    pub idle_pause_minutes: f32,
+}
+impl Default for Power {
+    fn default() -> Self {
+        Self {
+            idle_pause_minutes: 5.,
+        }
+    }
+}
+
+impl Power {
+    pub fn idle_after(&self) -> Duration {
+        Duration::from_secs_f32(self.idle_pause_minutes.max(0.) * 60.)
+    }
+}
+
+/// The skill points each player earns when a battle ends.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rewards {
+    /// For taking part, win or lose.
+    pub participation: u32,
+    /// For being the last player standing.
+    pub victory: u32,
+    /// For each stock left.
+    pub per_stock: u32,
+    /// For each full 100% of damage done to others.
+    pub per_hundred_damage: u32,
+    /// For each full minute the battle lasted, up to `max_duration_points`.
+    pub per_minute: u32,
+    pub max_duration_points: u32,
+}
+impl Default for Rewards {
+    fn default() -> Self {
+        Self {
+            participation: 1,
+            victory: 3,
+            per_stock: 1,
+            per_hundred_damage: 2,
+            per_minute: 1,
+            max_duration_points: 3,
+        }
+    }
+}
+
+/// How many rounds make up a battle, and how each round is played.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MatchConfig {
+    /// The rounds a player has to win to win the battle.
+    pub rounds_to_win: u32,
+    /// The length of each round in ticks, if rounds can run out of time. The player with the most
+    /// stocks left wins a round that does, then the one with the least damage.
+    pub time_limit_ticks: Option<u64>,
+    /// The stocks each player starts every round with. Players start with their character's own
+    /// if not set.
+    pub stocks_per_round: Option<u8>,
+}
+impl Default for MatchConfig {
+    fn default() -> Self {
+        Self {
+            rounds_to_win: 1,
+            time_limit_ticks: None,
+            stocks_per_round: None,
+        }
+    }
+}
+
+/// The window the game is played in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Window {
+    /// The size the window opens at when not fullscreen, in pixels.
+    pub width: f32,
+    pub height: f32,
+    /// Covers the whole screen instead of opening a window. Toggled in game.
+    pub fullscreen: bool,
+}
+impl Default for Window {
+    fn default() -> Self {
+        Self {
+            width: 1280.,
+            height: 720.,
+            fullscreen: false,
+        }
+    }
+}
+
+impl Window {
+    pub fn fullscreen_type(&self) -> FullscreenType {
+        if self.fullscreen {
+            FullscreenType::Desktop
+        } else {
+            FullscreenType::Windowed
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct Saves {
+    /// Where the save is kept. Uses the user's data directory if unset.
+    pub file: Option<PathBuf>,
+}
+
+/// Only used in debug builds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DebugDraw {
+    pub channels: Vec<Lenient<DebugChannel>>,
+}
+impl Default for DebugDraw {
+    fn default() -> Self {
+        Self {
+            channels: vec![Lenient::Valid(DebugChannel::Hurtboxes)],
+        }
+    }
+}
+
+/// Shortcuts for working on the game.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Development {
+    /// Starts a battle right away instead of showing the main menu.
+    pub skip_main_menu: bool,
+    /// Records battles, saving the last one finished to be watched from the main menu.
+    pub record_replays: bool,
+    /// Re-reads arena and character files whenever they change, swapping the arena's new layout
+    /// into the battle being played.
+    pub hot_reload: bool,
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Settings {
     pub logging: Logging,
     pub assets: Assets,
+    #[serde(default)]
+    pub fonts: Fonts,
+    #[serde(default)]
+    pub controls: Controls,
+    #[serde(default)]
+    pub audio: Audio,
+    #[serde(default)]
+    pub power: Power,
+    #[serde(default)]
+    pub window: Window,
+    #[serde(default)]
+    pub rewards: Rewards,
+    #[serde(default)]
+    pub rounds: MatchConfig,
+    #[serde(default)]
+    pub saves: Saves,
+    #[serde(default)]
+    pub debug_draw: DebugDraw,
+    #[serde(default)]
+    pub development: Development,
+}
+
+impl Settings {
+    /// Every setting whose value was not accepted.
+    pub fn invalid_settings(&self) -> Vec<InvalidSetting> {
+        let mut invalid = vec![];
+        if log::LevelFilter::from_str(&self.logging.level).is_err() {
+            invalid.push(InvalidSetting {
+                field: "logging.level".into(),
+                value: self.logging.level.clone(),
+                accepted: LOG_LEVELS.iter().map(|level| level.to_string()).collect(),
+            });
+        }
+        if let Some(layout) = &self.controls.keyboard_layout {
+            invalid.extend(InvalidSetting::check("controls.keyboard_layout", layout));
+        }
+        for (idx, bindings) in self.controls.players.iter().enumerate() {
+            for (action, binding) in bindings.named().iter() {
+                if let Some(Lenient::Invalid(value)) = binding {
+                    invalid.push(InvalidSetting {
+                        field: format!("controls.players[{}].{}", idx, action),
+                        value: value.clone(),
+                        accepted: keys::key_names().iter().map(|name| name.to_string()).collect(),
+                    });
+                }
+            }
+        }
+        for channel in &self.debug_draw.channels {
+            invalid.extend(InvalidSetting::check("debug_draw.channels", channel));
+        }
+        invalid
+    }
+}
+
+/// Something wrong with the settings file. None of these stop the game from starting.
+#[derive(Debug)]
+pub enum SettingsProblem {
+    /// The file couldn't be read at all, so every setting is at its default.
+    Unreadable(WalpurgisError),
+    Invalid(InvalidSetting),
+}
+
+impl fmt::Display for SettingsProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingsProblem::Unreadable(e) => {
+                write!(f, "Failed to read settings from `{}`, using the defaults: {}", CFG_PATH, e)
+            },
+            SettingsProblem::Invalid(invalid) => invalid.fmt(f),
+        }
+    }
+}
+
+/// A short notice telling the player that some of their settings were reset, if any were.
+pub fn problems_notice(problems: &[SettingsProblem]) -> Option<String> {
+    match problems {
+        [] => None,
+        [SettingsProblem::Unreadable(_)] => Some("Settings couldn't be read and were reset - see log".into()),
+        [_] => Some("1 setting had an invalid value and was reset - see log".into()),
+        _ => Some(format!("{} settings had invalid values and were reset - see log", problems.len())),
+    }
 }
 
-pub fn load() -> Result<Settings, ConfigError> {
-    const CFG_PATH: &str = "walpurgis.toml";
+/// Loads the settings, falling back to defaults for anything that can't be read. Problems are
+/// returned rather than logged, since logging is set up from the settings.
+pub fn load() -> (Settings, Vec<SettingsProblem>) {
+    match read(Path::new(CFG_PATH)) {
+        Ok(settings) => {
+            let problems = settings.invalid_settings()
+                .into_iter()
+                .map(SettingsProblem::Invalid)
+                .collect();
+            (settings, problems)
+        },
+        Err(e) => (Settings::default(), vec![SettingsProblem::Unreadable(e)]),
+    }
+}
 
-    log::info!("Reading configuration file `{}`.", CFG_PATH);
-    let cfg = File::with_name(CFG_PATH).required(false);
+fn read(cfg_path: &Path) -> WalpurgisResult<Settings> {
+    log::info!("Reading configuration file `{}`.", cfg_path.display());
+    let cfg = File::from(cfg_path).required(false);
 
     let mut s = Config::default();
     s.merge(cfg)?;
-    s.try_into()
+    Ok(s.try_into()?)
+}
+
+/// Queues `settings` to be written back to the settings file by `persistence`, so that changes
+/// made in game are kept. Invalid values are written back as they were read.
+pub fn save(settings: &Settings, persistence: &PersistenceWorker) -> WalpurgisResult {
+    save_to(Path::new(CFG_PATH), settings, persistence)
+}
+
+fn save_to(cfg_path: &Path, settings: &Settings, persistence: &PersistenceWorker) -> WalpurgisResult {
+    let job = WriteJob::new(cfg_path, to_toml(settings)?.into_bytes(), WritePriority::Normal).atomic();
+    if !persistence.enqueue(job) {
+        Err(format!("Dropped settings write to `{}`.", cfg_path.display()))?
+    }
+    Ok(())
+}
+
+fn to_toml(settings: &Settings) -> WalpurgisResult<String> {
+    // Going through a `Value` writes each table's plain values before its subtables, which TOML
+    // requires but the field order of the settings doesn't follow.
+    let value = toml::Value::try_from(settings).map_err(|e| format!("Failed to serialize settings: {}", e))?;
+    Ok(toml::to_string_pretty(&value).map_err(|e| format!("Failed to serialize settings: {}", e))?)
+}
+
+#[cfg(test)]
+mod settings_test {
+    use super::*;
+    use config::FileFormat;
+    use ggez::event::{KeyCode, KeyMods};
+    use crate::persistence::{self, RealFileSystem};
+
+    fn parse(toml: &str) -> Settings {
+        let mut s = Config::default();
+        s.merge(File::from_str(toml, FileFormat::Toml)).unwrap();
+        s.try_into().unwrap()
+    }
+
+    const LOGGING: &str = "[logging]\nlevel = \"debug\"\nfile = \"test.log\"\n[assets]\nroot = \"assets\"\n";
+
+    #[test]
+    fn lenient_valid() {
+        let settings = parse(&format!("{}[controls]\nkeyboard_layout = \"Azerty\"", LOGGING));
+        assert!(settings.controls.keyboard_layout() == Some(KeyboardLayout::Azerty));
+        assert!(settings.invalid_settings().is_empty());
+    }
+
+    #[test]
+    fn lenient_invalid() {
+        let settings = parse(&format!("{}[controls]\nkeyboard_layout = \"Dvorak\"", LOGGING));
+        assert!(settings.controls.keyboard_layout().is_none());
+        let invalid = settings.invalid_settings();
+        assert!(invalid.len() == 1);
+        assert!(invalid[0].field == "controls.keyboard_layout");
+        assert!(invalid[0].value == "Dvorak");
+        assert!(invalid[0].accepted == vec!["Qwerty", "Azerty", "Qwertz"]);
+    }
+
+    #[test]
+    fn lenient_missing() {
+        let settings = parse(LOGGING);
+        assert!(settings.controls.keyboard_layout().is_none());
+        assert!(settings.debug_draw.channels == DebugDraw::default().channels);
+        assert!(settings.invalid_settings().is_empty());
+    }
+
+    #[test]
+    fn bad_field_keeps_others() {
+        let settings = parse(&format!(
+            "{}[controls]\nkeyboard_layout = \"Qwertz\"\n[debug_draw]\nchannels = [\"Velocities\", \"Hitboxes\"]",
+            LOGGING,
+        ));
+        assert!(settings.logging.level == "debug");
+        assert!(settings.assets.root.as_path() == std::path::Path::new("assets"));
+        assert!(settings.controls.keyboard_layout() == Some(KeyboardLayout::Qwertz));
+        assert!(settings.debug_draw.channels[0] == Lenient::Valid(DebugChannel::Velocities));
+        let invalid = settings.invalid_settings();
+        assert!(invalid.len() == 1);
+        assert!(invalid[0].field == "debug_draw.channels");
+    }
+
+    #[test]
+    fn key_bindings() {
+        let settings = parse(&format!(
+            "{}[[controls.players]]\njump = \"W\"\ndash_left = \"shift + left\"\ngamepad = 1\n[[controls.players]]\ngrab = \"Spcae\"",
+            LOGGING,
+        ));
+        let players = &settings.controls.players;
+        assert!(players.len() == 2);
+        assert!(players[0].valid() == vec![
+            ("jump", (KeyCode::W, KeyMods::NONE)),
+            ("dash_left", (KeyCode::Left, KeyMods::SHIFT)),
+        ]);
+        assert!(players[1].valid().is_empty());
+        assert!(players[0].gamepad == Some(1) && players[1].gamepad.is_none());
+        assert!(settings.controls.gamepad_deadzone == Controls::default().gamepad_deadzone);
+        let invalid = settings.invalid_settings();
+        assert!(invalid.len() == 1);
+        assert!(invalid[0].field == "controls.players[1].grab");
+        assert!(invalid[0].value == "Spcae");
+        assert!(invalid[0].accepted.contains(&"Space".to_owned()));
+    }
+
+    #[test]
+    fn changed_bindings_round_trip() {
+        let mut settings = parse(&format!("{}[controls]\nkeyboard_layout = \"Azerty\"", LOGGING));
+        settings.controls.players = vec![
+            KeyBindings {
+                jump: Some(Lenient::Valid(KeyBinding((KeyCode::W, KeyMods::CTRL)))),
+                grab: Some(Lenient::Invalid("Spcae".into())),
+                ..Default::default()
+            },
+            KeyBindings { gamepad: Some(2), ..Default::default() },
+        ];
+        settings.controls.gamepad_deadzone = 0.5;
+        let saved = parse(&to_toml(&settings).unwrap());
+        let players = &saved.controls.players;
+        assert!(players[0].valid() == vec![("jump", (KeyCode::W, KeyMods::CTRL))]);
+        assert!(players[0].grab == Some(Lenient::Invalid("Spcae".into())));
+        assert!(players[1].gamepad == Some(2));
+        assert!(saved.controls.gamepad_deadzone == 0.5);
+        assert!(saved.controls.keyboard_layout() == Some(KeyboardLayout::Azerty));
+        assert!(saved.logging.level == "debug");
+    }
+
+    #[test]
+    fn saved_settings_read_back() {
+        let cfg_path = std::env::temp_dir().join(format!("walpurgis_settings_{}.toml", std::process::id()));
+        let mut settings = Settings::default();
+        settings.audio.sfx_volume = 0.3;
+        let mut worker = PersistenceWorker::new(RealFileSystem, persistence::DEFAULT_CAPACITY);
+        save_to(&cfg_path, &settings, &worker).unwrap();
+        assert!(worker.shutdown(Duration::from_secs(10)) == 0);
+        let saved = read(&cfg_path).unwrap();
+        std::fs::remove_file(&cfg_path).unwrap();
+        assert!(saved.audio.sfx_volume == 0.3);
+        assert!(saved.audio.music_volume == Audio::default().music_volume);
+        assert!(saved.logging.level == Logging::default().level);
+    }
+
+    #[test]
+    fn low_detail_caps_emitters() {
+        let settings = parse(LOGGING);
+        assert!(settings.audio.ambient_emitter_cap() == 8);
+        let settings = parse(&format!("{}[audio]\nmax_ambient_emitters = 5\nlow_detail = true", LOGGING));
+        assert!(settings.audio.ambient_emitter_cap() == 2);
+    }
+
+    #[test]
+    fn master_volume_scales() {
+        let settings = parse(&format!("{}[audio]\nmax_ambient_emitters = 5\nmaster_volume = 0.5\nsfx_volume = 0.5", LOGGING));
+        assert!(settings.audio.effective_music_volume() == 0.4);
+        assert!(settings.audio.effective_sfx_volume() == 0.25);
+    }
+
+    #[test]
+    fn idle_pause_minutes() {
+        assert!(parse(LOGGING).power.idle_after() == Duration::from_secs(300));
+        let settings = parse(&format!("{}[power]\nidle_pause_minutes = 0.5", LOGGING));
+        assert!(settings.power.idle_after() == Duration::from_secs(30));
+    }
+
+    #[test]
+    fn partial_window() {
+        let window = parse(LOGGING).window;
+        assert!(window.width == 1280. && window.fullscreen_type() == FullscreenType::Windowed);
+        let window = parse(&format!("{}[window]\nfullscreen = true", LOGGING)).window;
+        assert!(window.height == 720. && window.fullscreen_type() == FullscreenType::Desktop);
+    }
+
+    #[test]
+    fn partial_rewards() {
+        assert!(parse(LOGGING).rewards.victory == Rewards::default().victory);
+        let settings = parse(&format!("{}[rewards]\nvictory = 5", LOGGING));
+        assert!(settings.rewards.victory == 5);
+        assert!(settings.rewards.per_stock == Rewards::default().per_stock);
+        assert!(settings.rounds == MatchConfig::default());
+        let settings = parse(&format!("{}[rounds]\nrounds_to_win = 2\ntime_limit_ticks = 3600", LOGGING));
+        assert!(settings.rounds.rounds_to_win == 2 && settings.rounds.time_limit_ticks == Some(3600));
+        assert!(settings.rounds.stocks_per_round.is_none());
+    }
+
+    #[test]
+    fn invalid_log_level() {
+        let settings = parse("[logging]\nlevel = \"loud\"\nfile = \"test.log\"\n[assets]\nroot = \"assets\"\n");
+        assert!(settings.invalid_settings()[0].field == "logging.level");
+    }
+
+    #[test]
+    fn notice() {
+        let invalid = || SettingsProblem::Invalid(InvalidSetting {
+            field: "a".into(),
+            value: "b".into(),
+            accepted: vec![],
+        });
+        assert!(problems_notice(&[]).is_none());
+        assert!(problems_notice(&[invalid()]).unwrap().starts_with("1 setting had"));
+        assert!(problems_notice(&[invalid(), invalid(), invalid()]).unwrap().starts_with("3 settings had"));
+    }
 }
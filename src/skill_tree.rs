@@ -0,0 +1,257 @@
+//! Skill trees: the perks a character unlocks by spending the skill points they earn.
+//!
+//! Every race has its own tree. Nodes cost points to unlock and can only be unlocked once all of
+//! their prerequisites are.
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use crate::screens::battle::player::meta::Race;
+
+/// Every field of `Stats`, by name.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum StatField {
+    WalkSpeed,
+    RunSpeed,
+    AirAcceleration,
+    AirSpeed,
+    JumpSpeed,
+    WallJumpSpeed,
+    JumpHoldAcceleration,
+    MaxJumpHoldFrames,
+    MaxAirJumps,
+    FastFallMultiplier,
+    FastFallAcceleration,
+    FallSpeed,
+    FastFallSpeed,
+    DashSpeed,
+    DashDuration,
+    DashIntangibleFrames,
+    DashCooldown,
+    Friction,
+    DamagePct,
+    Stocks,
+    MaxStocks,
+    KnockbackBase,
+    KnockbackGrowth,
+    Weight,
+    MaxShieldHealth,
+    ShieldRegenRate,
+    ShieldDrainRate,
+    ShieldBreakStunFrames,
+    TechRollSpeed,
+    TechRollFrames,
+    KnockdownFrames,
+}
+
+/// What unlocking a skill does.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SkillEffect {
+    /// Adds `delta` to a stat.
+    StatModifier { field: StatField, delta: f32 },
+    /// Unlocks the race's ability in the given slot.
+    UnlockAbility(usize),
+    /// Anything else, by name.
+    Custom(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SkillNode {
+    pub id: usize,
+    pub name: String,
+    pub description: String,
+    /// The skill points spent unlocking the node.
+    pub cost: u32,
+    /// The ids of the nodes that have to be unlocked first.
+    pub prerequisites: Vec<usize>,
+    pub effect: SkillEffect,
+}
+
+/// Kept in order of ids, so that trees are saved and hashed the same way every time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SkillTree {
+    /// Every node in the tree, by id.
+    pub nodes: BTreeMap<usize, SkillNode>,
+    /// The ids of the nodes unlocked so far.
+    pub unlocked: BTreeSet<usize>,
+    /// The points left to spend.
+    pub skill_points: u32,
+}
+
+/// Why a node couldn't be unlocked.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SkillTreeError {
+    /// There is no node with this id.
+    UnknownNode(usize),
+    AlreadyUnlocked(usize),
+    /// Some of the node's prerequisites are still locked.
+    MissingPrerequisites { id: usize, missing: Vec<usize> },
+    NotEnoughPoints { id: usize, cost: u32, available: u32 },
+}
+
+impl fmt::Display for SkillTreeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SkillTreeError::UnknownNode(id) => write!(f, "There is no skill {}.", id),
+            SkillTreeError::AlreadyUnlocked(id) => write!(f, "Skill {} is already unlocked.", id),
+            SkillTreeError::MissingPrerequisites { id, missing } => {
+                write!(f, "Skill {} needs skills {:?} unlocked first.", id, missing)
+            },
+            SkillTreeError::NotEnoughPoints { id, cost, available } => {
+                write!(f, "Skill {} costs {} points, but only {} are left.", id, cost, available)
+            },
+        }
+    }
+}
+
+impl std::error::Error for SkillTreeError {}
+
+impl SkillTree {
+    /// A tree of `nodes` with nothing unlocked and no points to spend.
+    pub fn new(nodes: Vec<SkillNode>) -> Self {
+        Self {
+            nodes: nodes.into_iter().map(|node| (node.id, node)).collect(),
+            unlocked: BTreeSet::new(),
+            skill_points: 0,
+        }
+    }
+
+    /// The tree every character of `race` starts with.
+    pub fn for_race(race: &Race) -> Self {
+        use SkillEffect::*;
+        use StatField::*;
+        let nodes = match race {
+            Race::Alien => vec![
+                node(0, "Fleet Footed", "Run faster.", 1, &[], StatModifier { field: RunSpeed, delta: 0.3 }),
+                node(1, "Long Stride", "Dash faster.", 2, &[0], StatModifier { field: DashSpeed, delta: 0.5 }),
+                node(2, "Phase Step", "Teleport ahead.", 3, &[1], UnlockAbility(1)),
+                node(3, "Dense Hide", "Get launched less.", 1, &[], StatModifier { field: Weight, delta: 0.1 }),
+                node(4, "Apex Predator", "Adapt to every opponent.", 5, &[2, 3], Custom("apex_predator".to_owned())),
+            ],
+            Race::Robot => vec![
+                node(0, "Reinforced Plating", "Get launched less.", 1, &[], StatModifier { field: Weight, delta: 0.2 }),
+                node(1, "Hardened Shield", "Shield more damage.", 2, &[0], StatModifier { field: MaxShieldHealth, delta: 15. }),
+                node(2, "Self Repair", "Charge up to heal damage.", 3, &[1], UnlockAbility(0)),
+                node(3, "Thrusters", "Jump higher.", 1, &[], StatModifier { field: JumpSpeed, delta: 0.1 }),
+                node(4, "Overclock", "Run hotter than designed.", 5, &[2, 3], Custom("overclock".to_owned())),
+            ],
+            Race::Mage => vec![
+                node(0, "Levitation", "Drift faster in the air.", 1, &[], StatModifier { field: AirSpeed, delta: 0.2 }),
+                node(1, "Updraft", "Jump higher.", 2, &[0], StatModifier { field: JumpSpeed, delta: 0.1 }),
+                node(2, "Fireball", "Hurl fireballs.", 3, &[1], UnlockAbility(0)),
+                node(3, "Mana Shield", "Regain shield faster.", 1, &[], StatModifier { field: ShieldRegenRate, delta: 0.05 }),
+                node(4, "Archmage", "Master every school of magic.", 5, &[2, 3], Custom("archmage".to_owned())),
+            ],
+        };
+        Self::new(nodes)
+    }
+
+    pub fn is_unlocked(&self, id: usize) -> bool {
+        self.unlocked.contains(&id)
+    }
+
+    pub fn can_unlock(&self, id: usize) -> bool {
+        self.check_unlock(id).is_ok()
+    }
+
+    /// Spends the node's cost to unlock it.
+    pub fn unlock(&mut self, id: usize) -> Result<(), SkillTreeError> {
+        let cost = self.check_unlock(id)?;
+        self.skill_points -= cost;
+        self.unlocked.insert(id);
+        Ok(())
+    }
+
+    /// The cost of unlocking node `id`, if it can be unlocked.
+    fn check_unlock(&self, id: usize) -> Result<u32, SkillTreeError> {
+        let node = self.nodes.get(&id).ok_or(SkillTreeError::UnknownNode(id))?;
+        if self.is_unlocked(id) {
+            return Err(SkillTreeError::AlreadyUnlocked(id));
+        }
+        let missing: Vec<usize> = node.prerequisites.iter()
+            .copied()
+            .filter(|prerequisite| !self.is_unlocked(*prerequisite))
+            .collect();
+        if !missing.is_empty() {
+            return Err(SkillTreeError::MissingPrerequisites { id, missing });
+        }
+        if node.cost > self.skill_points {
+            return Err(SkillTreeError::NotEnoughPoints { id, cost: node.cost, available: self.skill_points });
+        }
+        Ok(node.cost)
+    }
+}
+
+fn node(
+    id: usize,
+    name: &str,
+    description: &str,
+    cost: u32,
+    prerequisites: &[usize],
+    effect: SkillEffect,
+) -> SkillNode {
+    SkillNode {
+        id,
+        name: name.to_owned(),
+        description: description.to_owned(),
+        cost,
+        prerequisites: prerequisites.to_vec(),
+        effect,
+    }
+}
+
+#[cfg(test)]
+mod skill_tree_test {
+    use super::*;
+
+    const RACES: [Race; 3] = [Race::Alien, Race::Robot, Race::Mage];
+
+    #[test]
+    fn prerequisites_unlocked_first() {
+        let mut tree = SkillTree::for_race(&Race::Alien);
+        tree.skill_points = 10;
+        assert!(!tree.can_unlock(1));
+        assert!(tree.unlock(1) == Err(SkillTreeError::MissingPrerequisites { id: 1, missing: vec![0] }));
+        assert!(tree.unlock(0).is_ok());
+        assert!(tree.can_unlock(1));
+        assert!(tree.unlock(1).is_ok());
+        assert!(tree.unlock(4) == Err(SkillTreeError::MissingPrerequisites { id: 4, missing: vec![2, 3] }));
+        assert!(tree.unlock(0) == Err(SkillTreeError::AlreadyUnlocked(0)));
+        assert!(tree.unlock(99) == Err(SkillTreeError::UnknownNode(99)));
+    }
+
+    #[test]
+    fn unlocking_spends_points() {
+        let mut tree = SkillTree::for_race(&Race::Robot);
+        tree.skill_points = 2;
+        tree.unlock(0).unwrap();
+        assert!(tree.skill_points == 1);
+        assert!(tree.unlock(1) == Err(SkillTreeError::NotEnoughPoints { id: 1, cost: 2, available: 1 }));
+        assert!(tree.skill_points == 1 && !tree.is_unlocked(1));
+        tree.unlock(3).unwrap();
+        assert!(tree.skill_points == 0);
+        assert!(tree.unlocked == [0, 3].iter().copied().collect());
+    }
+
+    #[test]
+    fn round_trips_through_ron() {
+        for race in &RACES {
+            let mut tree = SkillTree::for_race(race);
+            tree.skill_points = 4;
+            tree.unlock(0).unwrap();
+            let serialized = ron::ser::to_string(&tree).unwrap();
+            assert!(ron::de::from_str::<SkillTree>(&serialized).unwrap() == tree);
+        }
+    }
+
+    #[test]
+    fn race_trees_well_formed() {
+        for race in &RACES {
+            let tree = SkillTree::for_race(race);
+            for (id, node) in &tree.nodes {
+                assert!(node.id == *id);
+                assert!(node.prerequisites.iter().all(|prerequisite| tree.nodes.contains_key(prerequisite)));
+            }
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Sprite sheets: one texture holding many named frames.
+//!
+//! A sheet is described by a RON file naming the texture, its size and where each frame lies in
+//! it, in pixels:
+//! ```text
+//! (
+//!     texture: "characters/alien.png",
+//!     size: (128, 64),
+//!     frames: {
+//!         "idle_0": (x: 0, y: 0, w: 32, h: 32),
+//!         "idle_1": (x: 32, y: 0, w: 32, h: 32),
+//!     },
+//! )
+//! ```
+//! A `SheetDescriptor` only holds data, so it can be parsed and checked without a `Context`.
+//! Loading a `SpriteSheet` loads its texture and checks it against the descriptor.
+use ggez::{filesystem, Context, GameResult};
+use ggez::graphics::{Drawable, DrawParam, Image, Rect};
+use ron::de::from_reader;
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    assets::AssetManager,
+    util::result::{WalpurgisError, WalpurgisResult},
+};
+
+/// Where a frame lies in its texture, in pixels from the top left corner.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Frame {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+/// The contents of a sprite sheet's RON file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SheetDescriptor {
+    /// The texture file relative to the asset root.
+    pub texture: PathBuf,
+    /// The width and height of the texture in pixels.
+    pub size: (u32, u32),
+    pub frames: BTreeMap<String, Frame>,
+}
+
+impl SheetDescriptor {
+    /// Parses and checks a descriptor. Errors name `source`, the file it was read from.
+    pub fn from_reader<R: Read>(reader: R, source: &Path) -> WalpurgisResult<Self> {
+        let descriptor: Self = from_reader(reader).map_err(|e| WalpurgisError::parsing(source, "sprite sheet", e))?;
+        descriptor.validate(source)?;
+        Ok(descriptor)
+    }
+
+    /// Checks that every frame lies inside the texture and isn't empty.
+    fn validate(&self, source: &Path) -> WalpurgisResult {
+        let (width, height) = self.size;
+        for (name, frame) in &self.frames {
+            if frame.w == 0 || frame.h == 0 {
+                Err(format!("`{}`: Frame `{}` is empty.", source.display(), name))?
+            }
+            if frame.x + frame.w > width || frame.y + frame.h > height {
+                Err(format!(
+                    "`{}`: Frame `{}` reaches ({}, {}), outside of the {}x{} texture.",
+                    source.display(),
+                    name,
+                    frame.x + frame.w,
+                    frame.y + frame.h,
+                    width,
+                    height,
+                ))?
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the texture loaded is the size the descriptor was written for.
+    pub fn check_texture_size(&self, width: u32, height: u32) -> WalpurgisResult {
+        if (width, height) != self.size {
+            Err(format!(
+                "`{}` is {}x{}, but its sprite sheet describes it as {}x{}.",
+                self.texture.display(),
+                width,
+                height,
+                self.size.0,
+                self.size.1,
+            ))?
+        }
+        Ok(())
+    }
+
+    pub fn frame(&self, name: &str) -> Option<Frame> {
+        self.frames.get(name).cloned()
+    }
+
+    /// Frame `name` as fractions of the texture, as `DrawParam::src` takes it.
+    pub fn src(&self, name: &str) -> Option<Rect> {
+        let frame = self.frame(name)?;
+        let (width, height) = (self.size.0 as f32, self.size.1 as f32);
+        Some(Rect::new(
+            frame.x as f32 / width,
+            frame.y as f32 / height,
+            frame.w as f32 / width,
+            frame.h as f32 / height,
+        ))
+    }
+}
+
+/// A texture along with the frames cut out of it.
+#[derive(Debug, Clone)]
+pub struct SpriteSheet {
+    image: Image,
+    descriptor: SheetDescriptor,
+}
+
+impl SpriteSheet {
+    /// Loads the sheet described in `descriptor_file`, relative to the asset root, sharing its
+    /// texture with every other sheet using it. The asset root has to be mounted in `ggez`'s
+    /// filesystem.
+    pub fn load(ctx: &mut Context, assets: &mut AssetManager, descriptor_file: &Path) -> WalpurgisResult<Self> {
+        log::info!("Loading sprite sheet `{}`.", descriptor_file.display());
+        let root = Path::new("/");
+        let reader = filesystem::open(ctx, root.join(descriptor_file))?;
+        let descriptor = SheetDescriptor::from_reader(reader, descriptor_file)?;
+        let image = assets.image(ctx, &descriptor.texture)?;
+        descriptor.check_texture_size(u32::from(image.width()), u32::from(image.height()))?;
+        Ok(SpriteSheet { image, descriptor })
+    }
+
+    pub fn frame(&self, name: &str) -> Option<Frame> {
+        self.descriptor.frame(name)
+    }
+
+    /// Draws frame `name` with its top left corner at `param.dest`. Frames the sheet doesn't have
+    /// are skipped.
+    pub fn draw_frame(&self, ctx: &mut Context, name: &str, param: DrawParam) -> GameResult {
+        match self.descriptor.src(name) {
+            Some(src) => self.image.draw(ctx, param.src(src)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod sprites_test {
+    use super::*;
+
+    const SHEET: &str = "(
+        texture: \"sheet.png\",
+        size: (64, 32),
+        frames: {
+            \"idle\": (x: 0, y: 0, w: 32, h: 32),
+            \"walk\": (x: 32, y: 16, w: 16, h: 16),
+        },
+    )";
+
+    fn parse(descriptor: &str) -> WalpurgisResult<SheetDescriptor> {
+        SheetDescriptor::from_reader(descriptor.as_bytes(), Path::new("sheet.ron"))
+    }
+
+    #[test]
+    fn frames_as_fractions_of_texture() {
+        let descriptor = parse(SHEET).unwrap();
+        assert!(descriptor.frame("walk") == Some(Frame { x: 32, y: 16, w: 16, h: 16 }));
+        assert!(descriptor.src("idle") == Some(Rect::new(0., 0., 0.5, 1.)));
+        assert!(descriptor.src("walk") == Some(Rect::new(0.5, 0.5, 0.25, 0.5)));
+        assert!(descriptor.src("jump").is_none());
+    }
+
+    #[test]
+    fn frames_outside_texture_rejected() {
+        match parse(&SHEET.replace("w: 16, h: 16", "w: 16, h: 17")) {
+            Err(WalpurgisError::Generic(e)) => assert!(e.contains("`walk`") && e.contains("64x32")),
+            other => panic!("Expected a frame error, got {:?}", other),
+        }
+        assert!(parse(&SHEET.replace("w: 16, h: 16", "w: 0, h: 16")).is_err());
+    }
+
+    #[test]
+    fn texture_size_mismatch_rejected() {
+        let descriptor = parse(SHEET).unwrap();
+        assert!(descriptor.check_texture_size(64, 32).is_ok());
+        match descriptor.check_texture_size(32, 64) {
+            Err(WalpurgisError::Generic(e)) => assert!(e.contains("sheet.png") && e.contains("32x64")),
+            other => panic!("Expected a size error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn bad_descriptor_named_in_error() {
+        match parse("(texture: \"sheet.png\",") {
+            Err(e @ WalpurgisError::AssetParse { .. }) => assert!(e.to_string().contains("sheet.ron")),
+            other => panic!("Expected a parse error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,363 @@
+//! Helpers for drawing text without rebuilding glyph geometry every frame.
+//!
+//! Strings that rarely change (banners, labels) are kept in a `TextCache`, keyed by contents and
+//! style, and evicted once they go unused. Strings that change constantly (the timer, damage
+//! numbers) would thrash such a cache, so they are composed from a `GlyphAtlas` of single
+//! characters instead and never add entries.
+use ggez::{Context, GameResult};
+use ggez::graphics::{self, Color, DrawParam, Font, Scale, Text, TextFragment};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::{
+    settings,
+    util::result::{WalpurgisError, WalpurgisResult},
+};
+
+/// The frame on which a cached entry was last used.
+pub type FrameStamp = u64;
+
+/// Refers to a font loaded into `Fonts`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FontId(usize);
+
+/// The fonts used by the game. Fonts should only be loaded once, so they all live here. Copies
+/// share the loaded fonts.
+#[derive(Debug, Clone)]
+pub struct Fonts {
+    fonts: Vec<Font>,
+}
+
+impl Fonts {
+    /// Loads the font file selected in the settings, falling back to the font bundled with
+    /// `ggez` when none is selected.
+    pub fn load(ctx: &mut Context, assets: &settings::Assets, fonts: &settings::Fonts) -> WalpurgisResult<Self> {
+        let default = match &fonts.file {
+            Some(file) => Self::load_font(ctx, assets.root.join(file))?,
+            None => Font::default(),
+        };
+        Ok(Self {
+            fonts: vec![default],
+        })
+    }
+
+    fn load_font<P: AsRef<Path>>(ctx: &mut Context, font_file: P) -> WalpurgisResult<Font> {
+        let font_file = font_file.as_ref();
+        log::info!("Loading font `{}`.", font_file.display());
+        let bytes = fs::read(font_file).map_err(|e| WalpurgisError::reading(font_file, "font", e))?;
+        Ok(Font::new_glyph_font_bytes(ctx, &bytes)?)
+    }
+
+    pub fn default_id(&self) -> FontId {
+        FontId(0)
+    }
+
+    pub fn get(&self, id: FontId) -> Font {
+        self.fonts[id.0]
+    }
+}
+
+impl Default for Fonts {
+    /// Only the font bundled with `ggez`.
+    fn default() -> Self {
+        Self {
+            fonts: vec![Font::default()],
+        }
+    }
+}
+
+/// Everything that distinguishes one cached text from another.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TextKey {
+    pub contents: String,
+    pub font: FontId,
+    /// Font size in pixels.
+    pub size: u32,
+    pub color: (u8, u8, u8, u8),
+}
+
+impl TextKey {
+    pub fn new<S: Into<String>>(contents: S, font: FontId, size: u32, color: Color) -> Self {
+        Self {
+            contents: contents.into(),
+            font,
+            size,
+            color: color.to_rgba(),
+        }
+    }
+
+    /// Builds the `Text` described by the key.
+    pub fn build(&self, fonts: &Fonts) -> Text {
+        let (r, g, b, a) = self.color;
+        Text::new(TextFragment::new(self.contents.as_str())
+            .font(fonts.get(self.font))
+            .scale(Scale::uniform(self.size as f32))
+            .color(Color::from_rgba(r, g, b, a)))
+    }
+}
+
+/// A least recently used cache of built texts.
+#[derive(Debug)]
+pub struct TextCache<T> {
+    entries: HashMap<TextKey, (T, FrameStamp)>,
+    /// The most entries kept at once.
+    capacity: usize,
+    hits: u64,
+    misses: u64,
+}
+
+impl<T> TextCache<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            capacity,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Gets the entry for `key`, building it with `build` if it isn't cached.
+    pub fn get_or_insert_with<F>(&mut self, key: TextKey, frame: FrameStamp, build: F) -> &T
+    where
+        F: FnOnce(&TextKey) -> T,
+    {
+        if self.entries.contains_key(&key) {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+            if self.entries.len() >= self.capacity {
+                self.evict_least_recently_used();
+            }
+            let value = build(&key);
+            self.entries.insert(key.clone(), (value, frame));
+        }
+        let entry = self.entries.get_mut(&key).expect("Entry was just inserted.");
+        entry.1 = frame;
+        &entry.0
+    }
+
+    /// The entry for `key`, if it's cached. Doesn't count as a use.
+    pub fn get(&self, key: &TextKey) -> Option<&T> {
+        self.entries.get(key).map(|(value, _)| value)
+    }
+
+    /// Drops every entry not used on or after `frame`.
+    pub fn evict_unused_since(&mut self, frame: FrameStamp) {
+        self.entries.retain(|_, (_, last_used)| *last_used >= frame);
+    }
+
+    fn evict_least_recently_used(&mut self) {
+        let oldest = self.entries.iter()
+            .min_by_key(|(_, (_, last_used))| *last_used)
+            .map(|(key, _)| key.clone());
+        if let Some(oldest) = oldest {
+            self.entries.remove(&oldest);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// The counters, for the debug overlay.
+    pub fn describe(&self) -> String {
+        format!("Text cache: {}/{} entries, {} hits, {} misses", self.len(), self.capacity, self.hits(), self.misses())
+    }
+}
+
+/// Anything that can tell how wide a `Text` is. Lets layout math be tested without a `Context`.
+pub trait MeasureText {
+    fn width(&mut self, text: &Text) -> f32;
+}
+
+impl MeasureText for Context {
+    fn width(&mut self, text: &Text) -> f32 {
+        text.width(self) as f32
+    }
+}
+
+/// Breaks `contents` into lines no wider than `max_width`, between words. Line breaks already in
+/// `contents` are kept, and words too wide to fit on any line get one to themselves.
+pub fn wrap_lines<M: MeasureText>(contents: &str, max_width: f32, measure: &mut M) -> Vec<String> {
+    let mut lines = vec![];
+    for paragraph in contents.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let longer = if line.is_empty() { word.to_owned() } else { format!("{} {}", line, word) };
+            if line.is_empty() || measure.width(&Text::new(longer.as_str())) <= max_width {
+                line = longer;
+            } else {
+                lines.push(std::mem::replace(&mut line, word.to_owned()));
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Pre-built single character texts, composed to draw strings that change every frame.
+#[derive(Debug)]
+pub struct GlyphAtlas {
+    glyphs: Vec<(char, Text)>,
+}
+
+impl GlyphAtlas {
+    pub fn new(charset: &str, font: Font, size: u32) -> Self {
+        let glyphs = charset.chars()
+            .map(|c| {
+                let mut text = Text::new(c);
+                text.set_font(font, Scale::uniform(size as f32));
+                (c, text)
+            })
+            .collect();
+        Self {
+            glyphs,
+        }
+    }
+
+    /// Enough glyphs for timers and percentages.
+    pub fn digits(font: Font, size: u32) -> Self {
+        Self::new("0123456789:.%", font, size)
+    }
+
+    fn index_of(&self, c: char) -> Option<usize> {
+        self.glyphs.iter().position(|(glyph, _)| *glyph == c)
+    }
+
+    /// Lays out `contents` left to right. Returns the index of each glyph along with its
+    /// horizontal offset. Characters missing from the atlas are skipped.
+    pub fn layout<M: MeasureText>(&self, contents: &str, measure: &mut M) -> Vec<(usize, f32)> {
+        let mut x = 0.;
+        contents.chars()
+            .filter_map(|c| self.index_of(c))
+            .map(|idx| {
+                let offset = x;
+                x += measure.width(&self.glyphs[idx].1);
+                (idx, offset)
+            })
+            .collect()
+    }
+
+    /// The total width of `contents` once laid out.
+    pub fn width<M: MeasureText>(&self, contents: &str, measure: &mut M) -> f32 {
+        contents.chars()
+            .filter_map(|c| self.index_of(c))
+            .map(|idx| measure.width(&self.glyphs[idx].1))
+            .sum()
+    }
+
+    pub fn draw(&self, ctx: &mut Context, contents: &str, param: DrawParam) -> GameResult {
+        for (idx, offset) in self.layout(contents, ctx) {
+            let mut glyph_param = param;
+            glyph_param.dest.x += offset;
+            graphics::draw(ctx, &self.glyphs[idx].1, glyph_param)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod text_test {
+    use super::*;
+
+    /// Every character is 10 wide, except `1`, which is 4 wide.
+    struct FixedWidth;
+    impl MeasureText for FixedWidth {
+        fn width(&mut self, text: &Text) -> f32 {
+            text.contents().chars().map(|c| if c == '1' { 4. } else { 10. }).sum()
+        }
+    }
+
+    fn key(contents: &str) -> TextKey {
+        TextKey::new(contents, FontId(0), 16, graphics::WHITE)
+    }
+
+    #[test]
+    fn cache_hits_and_misses() {
+        let mut cache = TextCache::new(4);
+        assert!(*cache.get_or_insert_with(key("a"), 0, |k| k.contents.clone()) == "a");
+        assert!(*cache.get_or_insert_with(key("a"), 1, |_| panic!("Should be cached.")) == "a");
+        cache.get_or_insert_with(key("b"), 1, |k| k.contents.clone());
+        assert!(cache.hits() == 1);
+        assert!(cache.misses() == 2);
+        assert!(cache.len() == 2);
+        assert!(cache.get(&key("b")) == Some(&"b".to_owned()) && cache.get(&key("c")).is_none());
+        assert!(cache.describe() == "Text cache: 2/4 entries, 1 hits, 2 misses");
+    }
+
+    #[test]
+    fn cache_keys_on_style() {
+        let mut cache = TextCache::new(4);
+        cache.get_or_insert_with(key("a"), 0, |_| ());
+        cache.get_or_insert_with(TextKey { size: 32, ..key("a") }, 0, |_| ());
+        cache.get_or_insert_with(TextKey::new("a", FontId(0), 16, graphics::BLACK), 0, |_| ());
+        cache.get_or_insert_with(TextKey { font: FontId(1), ..key("a") }, 0, |_| ());
+        assert!(cache.len() == 4);
+        assert!(cache.misses() == 4);
+    }
+
+    #[test]
+    fn cache_evicts_least_recently_used() {
+        let mut cache = TextCache::new(2);
+        cache.get_or_insert_with(key("a"), 0, |_| ());
+        cache.get_or_insert_with(key("b"), 1, |_| ());
+        cache.get_or_insert_with(key("a"), 2, |_| ());
+        cache.get_or_insert_with(key("c"), 3, |_| ());
+        assert!(cache.len() == 2);
+        cache.get_or_insert_with(key("a"), 4, |_| panic!("`a` was used more recently than `b`."));
+        cache.get_or_insert_with(key("c"), 4, |_| panic!("`c` was just inserted."));
+    }
+
+    #[test]
+    fn cache_evicts_unused() {
+        let mut cache = TextCache::new(8);
+        cache.get_or_insert_with(key("a"), 0, |_| ());
+        cache.get_or_insert_with(key("b"), 5, |_| ());
+        cache.get_or_insert_with(key("c"), 10, |_| ());
+        cache.evict_unused_since(5);
+        assert!(cache.len() == 2);
+        cache.evict_unused_since(11);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn atlas_layout() {
+        let atlas = GlyphAtlas::digits(Font::default(), 16);
+        let layout = atlas.layout("01:1", &mut FixedWidth);
+        let offsets: Vec<_> = layout.iter().map(|(_, x)| *x).collect();
+        assert!(offsets == vec![0., 10., 14., 24.]);
+        assert!(layout[1].0 == layout[3].0);
+        assert!(atlas.width("01:1", &mut FixedWidth) == 28.);
+    }
+
+    #[test]
+    fn atlas_skips_missing_glyphs() {
+        let atlas = GlyphAtlas::digits(Font::default(), 16);
+        let layout = atlas.layout("1a1", &mut FixedWidth);
+        assert!(layout.len() == 2);
+        assert!(layout[1].1 == 4.);
+    }
+
+    #[test]
+    fn lines_wrapped_between_words() {
+        // Each character is 10 wide, so 5 fit on a line.
+        assert!(wrap_lines("ab cd ef", 50., &mut FixedWidth) == vec!["ab cd", "ef"]);
+        assert!(wrap_lines("ab  cd", 50., &mut FixedWidth) == vec!["ab cd"]);
+        assert!(wrap_lines("abcdefgh ij", 50., &mut FixedWidth) == vec!["abcdefgh", "ij"]);
+        assert!(wrap_lines("ab\n\ncd", 50., &mut FixedWidth) == vec!["ab", "", "cd"]);
+        assert!(wrap_lines("", 50., &mut FixedWidth).is_empty());
+    }
+}
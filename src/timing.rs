@@ -0,0 +1,362 @@
+//! Debug controls over how fast the simulation runs.
+//!
+//! Ticks are always the same length. Changing the speed changes how many ticks are run per update
+//! window instead, so the physics behave exactly the same at every speed.
+//!
+//! | Key | Control                              |
+//! |-----|--------------------------------------|
+//! | F5  | Slower                               |
+//! | F6  | Faster                               |
+//! | F7  | Step one tick while paused           |
+//! | F8  | Run until the next collision, pause  |
+//!
+//! The same controls, and running until a given tick, can be typed into the debug console as
+//! `SimCommand`s.
+//!
+//! ### Precedence
+//! 1. Pausing stops the simulation outright. Only steps advance it while paused.
+//! 2. Anything in game that slows time, e.g. hitlag, should be counted in ticks. It then slows
+//!    down and speeds up along with the speed set here.
+//! 3. The speed set here decides how many ticks run per update window.
+use ggez::event::KeyCode;
+use std::time::Duration;
+
+/// Multipliers for the simulation speed, as a fraction of ticks per update window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimSpeed {
+    Quarter,
+    Half,
+    Normal,
+    Double,
+    Quadruple,
+}
+
+impl SimSpeed {
+    pub const ALL: [SimSpeed; 5] = [
+        SimSpeed::Quarter,
+        SimSpeed::Half,
+        SimSpeed::Normal,
+        SimSpeed::Double,
+        SimSpeed::Quadruple,
+    ];
+
+    /// The number of ticks run per number of update windows.
+    fn ticks_per_windows(self) -> (u32, u32) {
+        match self {
+            SimSpeed::Quarter => (1, 4),
+            SimSpeed::Half => (1, 2),
+            SimSpeed::Normal => (1, 1),
+            SimSpeed::Double => (2, 1),
+            SimSpeed::Quadruple => (4, 1),
+        }
+    }
+
+    pub fn multiplier(self) -> f32 {
+        let (ticks, windows) = self.ticks_per_windows();
+        ticks as f32 / windows as f32
+    }
+
+    fn from_multiplier(multiplier: f32) -> Option<Self> {
+        Self::ALL.iter().cloned().find(|speed| (speed.multiplier() - multiplier).abs() < f32::EPSILON)
+    }
+
+    pub fn slower(self) -> Self {
+        let idx = Self::ALL.iter().position(|speed| *speed == self).unwrap_or(0);
+        Self::ALL[idx.saturating_sub(1)]
+    }
+
+    pub fn faster(self) -> Self {
+        let idx = Self::ALL.iter().position(|speed| *speed == self).unwrap_or(0);
+        Self::ALL[(idx + 1).min(Self::ALL.len() - 1)]
+    }
+}
+
+/// Things that happen during a tick which the simulation can be run until.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimEvent {
+    /// Two players collided.
+    Collision,
+    /// A player lost a stock.
+    Ko,
+}
+
+/// When to stop a run and pause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunUntil {
+    /// Once the given tick has been simulated.
+    Tick(u64),
+    /// After the next tick with the given event.
+    Event(SimEvent),
+}
+
+/// A control command, as typed into a console.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SimCommand {
+    Speed(SimSpeed),
+    Step(u64),
+    RunUntil(RunUntil),
+}
+
+impl SimCommand {
+    /// Parses commands such as `speed 0.5`, `step 10`, `until tick 600`, `until collision` and
+    /// `until ko`.
+    pub fn parse(command: &str) -> Result<Self, String> {
+        let words: Vec<_> = command.split_whitespace().collect();
+        match words.as_slice() {
+            ["speed", multiplier] => multiplier.parse().ok()
+                .and_then(SimSpeed::from_multiplier)
+                .map(SimCommand::Speed)
+                .ok_or_else(|| format!("Speed must be one of 0.25, 0.5, 1, 2 or 4, not `{}`.", multiplier)),
+            ["step", ticks] => ticks.parse()
+                .map(SimCommand::Step)
+                .map_err(|_| format!("Can't step `{}` ticks.", ticks)),
+            ["until", "tick", tick] => tick.parse()
+                .map(|tick| SimCommand::RunUntil(RunUntil::Tick(tick)))
+                .map_err(|_| format!("`{}` is not a tick.", tick)),
+            ["until", "collision"] => Ok(SimCommand::RunUntil(RunUntil::Event(SimEvent::Collision))),
+            ["until", "ko"] => Ok(SimCommand::RunUntil(RunUntil::Event(SimEvent::Ko))),
+            _ => Err(format!("Unknown command `{}`.", command)),
+        }
+    }
+}
+
+/// Turns the time that passed between frames into update windows, like
+/// `ggez::timer::check_update_time`. Long frames, e.g. in low power mode, are made up for with
+/// several windows at once, so the same number of windows run however often frames are drawn.
+#[derive(Debug, Default)]
+pub struct WindowClock {
+    /// Time passed that hasn't been used up by windows yet.
+    residual: Duration,
+}
+
+impl WindowClock {
+    pub fn advance(&mut self, frame: Duration) {
+        self.residual += frame;
+    }
+
+    /// Uses up one window if enough time has passed for it.
+    pub fn take_window(&mut self, windows_per_second: u32) -> bool {
+        let window = Duration::from_secs(1) / windows_per_second;
+        if self.residual >= window {
+            self.residual -= window;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Decides how many ticks to simulate in each update window.
+#[derive(Debug)]
+pub struct SimClock {
+    speed: SimSpeed,
+    /// Update windows that haven't yet added up to a whole tick at slow speeds.
+    carry: u32,
+    /// Ticks left to step through while paused.
+    steps_left: u64,
+    run_until: Option<RunUntil>,
+}
+
+impl Default for SimClock {
+    fn default() -> Self {
+        SimClock {
+            speed: SimSpeed::Normal,
+            carry: 0,
+            steps_left: 0,
+            run_until: None,
+        }
+    }
+}
+
+impl SimClock {
+    pub fn speed(&self) -> SimSpeed {
+        self.speed
+    }
+
+    pub fn apply(&mut self, command: SimCommand) {
+        log::debug!("Simulation command: {:?}", command);
+        match command {
+            SimCommand::Speed(speed) => {
+                self.speed = speed;
+                self.carry = 0;
+            },
+            SimCommand::Step(ticks) => self.steps_left += ticks,
+            SimCommand::RunUntil(condition) => self.run_until = Some(condition),
+        }
+    }
+
+    /// Handles a control key. Returns whether the key was used.
+    pub fn handle_key(&mut self, key: KeyCode) -> bool {
+        let command = match key {
+            KeyCode::F5 => SimCommand::Speed(self.speed.slower()),
+            KeyCode::F6 => SimCommand::Speed(self.speed.faster()),
+            KeyCode::F7 => SimCommand::Step(1),
+            KeyCode::F8 => SimCommand::RunUntil(RunUntil::Event(SimEvent::Collision)),
+            _ => return false,
+        };
+        self.apply(command);
+        true
+    }
+
+    /// The speed and the run in progress, e.g. "0.5x speed, running until tick 600".
+    pub fn describe(&self) -> String {
+        let speed = self.speed().multiplier();
+        match self.run_until {
+            Some(RunUntil::Tick(tick)) => format!("{}x speed, running until tick {}", speed, tick),
+            Some(RunUntil::Event(event)) => format!("{}x speed, running until the next {:?}", speed, event),
+            None => format!("{}x speed", speed),
+        }
+    }
+
+    /// Whether a run is in progress. Runs should start unpaused.
+    pub fn is_running_until(&self) -> bool {
+        self.run_until.is_some()
+    }
+
+    /// The number of ticks to simulate in the current update window.
+    pub fn ticks_for_window(&mut self, paused: bool) -> u32 {
+        if paused && self.steps_left == 0 {
+            return 0;
+        }
+        let (ticks, windows) = self.speed.ticks_per_windows();
+        self.carry += ticks;
+        let due = self.carry / windows;
+        self.carry %= windows;
+        if paused {
+            let steps = u64::from(due).min(self.steps_left);
+            self.steps_left -= steps;
+            steps as u32
+        } else {
+            due
+        }
+    }
+
+    /// Checks a simulated tick against the current run. Returns whether to pause now, in which
+    /// case the rest of the window's ticks should not be simulated.
+    pub fn after_tick(&mut self, tick: u64, events: &[SimEvent]) -> bool {
+        let done = match self.run_until {
+            Some(RunUntil::Tick(until)) => tick >= until,
+            Some(RunUntil::Event(event)) => events.contains(&event),
+            None => false,
+        };
+        if done {
+            log::info!("Stopped on tick {} after {:?}.", tick, self.run_until);
+            self.run_until = None;
+        }
+        done
+    }
+}
+
+#[cfg(test)]
+mod timing_test {
+    use super::*;
+
+    /// The total ticks run over `windows` update windows.
+    fn total_ticks(clock: &mut SimClock, windows: u32, paused: bool) -> u32 {
+        (0..windows).map(|_| clock.ticks_for_window(paused)).sum()
+    }
+
+    #[test]
+    fn ticks_at_each_speed() {
+        for speed in SimSpeed::ALL.iter() {
+            let mut clock = SimClock::default();
+            clock.apply(SimCommand::Speed(*speed));
+            // Ten seconds of update windows.
+            let ticks = total_ticks(&mut clock, 600, false);
+            assert!(ticks as f32 == 600. * speed.multiplier());
+        }
+    }
+
+    #[test]
+    fn slow_speeds_spread_ticks_out() {
+        let mut clock = SimClock::default();
+        clock.apply(SimCommand::Speed(SimSpeed::Quarter));
+        let ticks: Vec<_> = (0..8).map(|_| clock.ticks_for_window(false)).collect();
+        assert!(ticks == vec![0, 0, 0, 1, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn nothing_runs_while_paused() {
+        let mut clock = SimClock::default();
+        assert!(total_ticks(&mut clock, 600, true) == 0);
+    }
+
+    #[test]
+    fn steps_are_exact() {
+        for speed in SimSpeed::ALL.iter() {
+            let mut clock = SimClock::default();
+            clock.apply(SimCommand::Speed(*speed));
+            clock.apply(SimCommand::Step(7));
+            assert!(total_ticks(&mut clock, 600, true) == 7);
+        }
+    }
+
+    /// Runs the clock the way the game loop does, with a collision on `collision_tick`. Returns
+    /// the tick the run stopped on.
+    fn run(clock: &mut SimClock, collision_tick: u64) -> Option<u64> {
+        let mut tick = 0;
+        for _ in 0..1000 {
+            for _ in 0..clock.ticks_for_window(false) {
+                tick += 1;
+                let events = if tick == collision_tick { vec![SimEvent::Collision] } else { vec![] };
+                if clock.after_tick(tick, &events) {
+                    return Some(tick);
+                }
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn run_until_stops_on_exact_tick() {
+        let mut clock = SimClock::default();
+        clock.apply(SimCommand::Speed(SimSpeed::Quadruple));
+        clock.apply(SimCommand::RunUntil(RunUntil::Tick(101)));
+        assert!(run(&mut clock, 0) == Some(101));
+        assert!(!clock.is_running_until());
+    }
+
+    #[test]
+    fn run_until_event() {
+        let mut clock = SimClock::default();
+        clock.apply(SimCommand::Speed(SimSpeed::Double));
+        clock.apply(SimCommand::RunUntil(RunUntil::Event(SimEvent::Collision)));
+        assert!(run(&mut clock, 37) == Some(37));
+        clock.apply(SimCommand::RunUntil(RunUntil::Event(SimEvent::Ko)));
+        assert!(run(&mut clock, 37).is_none());
+    }
+
+    #[test]
+    fn speed_keys() {
+        let mut clock = SimClock::default();
+        assert!(clock.handle_key(KeyCode::F5));
+        assert!(clock.speed() == SimSpeed::Half);
+        for _ in 0..10 {
+            clock.handle_key(KeyCode::F6);
+        }
+        assert!(clock.speed() == SimSpeed::Quadruple);
+        assert!(!clock.handle_key(KeyCode::A));
+    }
+
+    #[test]
+    fn described_for_console() {
+        let mut clock = SimClock::default();
+        assert!(clock.describe() == "1x speed");
+        clock.apply(SimCommand::Speed(SimSpeed::Quarter));
+        clock.apply(SimCommand::RunUntil(RunUntil::Tick(600)));
+        assert!(clock.describe() == "0.25x speed, running until tick 600");
+        clock.apply(SimCommand::RunUntil(RunUntil::Event(SimEvent::Ko)));
+        assert!(clock.describe() == "0.25x speed, running until the next Ko");
+    }
+
+    #[test]
+    fn parse_commands() {
+        assert!(SimCommand::parse("speed 0.25") == Ok(SimCommand::Speed(SimSpeed::Quarter)));
+        assert!(SimCommand::parse("step 10") == Ok(SimCommand::Step(10)));
+        assert!(SimCommand::parse("until tick 600") == Ok(SimCommand::RunUntil(RunUntil::Tick(600))));
+        assert!(SimCommand::parse("until ko") == Ok(SimCommand::RunUntil(RunUntil::Event(SimEvent::Ko))));
+        assert!(SimCommand::parse("speed 3").is_err());
+        assert!(SimCommand::parse("step -1").is_err());
+        assert!(SimCommand::parse("fly").is_err());
+    }
+}
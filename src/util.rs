@@ -1,3 +1,4 @@
 pub mod cartesian;
+pub mod hash;
 pub mod result;
 pub mod tuple;
@@ -0,0 +1,44 @@
+//! Hashes of game content that stay the same across builds and platforms.
+//!
+//! Content is hashed in its parsed form, re-serialized to compact RON, so that reformatting a file
+//! doesn't change its hash while changing any value does. FNV-1a is used instead of `std`'s hasher
+//! since the latter may change between Rust releases.
+use serde::Serialize;
+
+use crate::util::result::WalpurgisResult;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// The 64 bit FNV-1a hash of `bytes`.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME))
+}
+
+/// Hashes `content` by its serialized values rather than by how its file was formatted.
+pub fn content_hash<T: Serialize>(content: &T) -> WalpurgisResult<u64> {
+    let serialized = ron::ser::to_string(content)
+        .map_err(|e| format!("Failed to serialize content for hashing: {}", e))?;
+    Ok(fnv1a(serialized.as_bytes()))
+}
+
+#[cfg(test)]
+mod hash_test {
+    use super::*;
+
+    #[test]
+    fn fnv1a_known_values() {
+        assert!(fnv1a(b"") == 0xcbf2_9ce4_8422_2325);
+        assert!(fnv1a(b"a") == 0xaf63_dc4c_8601_ec8c);
+        assert!(fnv1a(b"foobar") == 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn content_hash_ignores_formatting() {
+        let compact: Vec<(String, f32)> = ron::de::from_str("[(\"a\",1.5)]").unwrap();
+        let spaced: Vec<(String, f32)> = ron::de::from_str("[\n    (\n        \"a\",\n        1.5,\n    ),\n]").unwrap();
+        let changed: Vec<(String, f32)> = ron::de::from_str("[(\"a\",2.5)]").unwrap();
+        assert!(content_hash(&compact).unwrap() == content_hash(&spaced).unwrap());
+        assert!(content_hash(&compact).unwrap() != content_hash(&changed).unwrap());
+    }
+}
@@ -1,12 +1,115 @@
+use std::{fmt, io};
+use std::path::{Path, PathBuf};
+
+use config::ConfigError;
+
+use crate::screens::battle::proof::ProofMismatch;
+
 #[derive(Debug)]
 pub enum WalpurgisError {
     GGEZ(ggez::error::GameError),
     IO(std::io::Error),
     Ron(ron::de::Error),
+    Config(ConfigError),
+    /// There is no `what` at `path`, or a directory of them is empty.
+    AssetNotFound { path: PathBuf, what: &'static str },
+    /// The `what` at `path` exists but couldn't be read.
+    AssetUnreadable { path: PathBuf, what: &'static str, source: io::Error },
+    /// The `what` at `path` was read but isn't valid RON for it.
+    AssetParse { path: PathBuf, what: &'static str, source: ron::de::Error },
+    /// The `what` at `path` is in a version of its format that can't be read.
+    UnsupportedVersion { path: PathBuf, what: &'static str, found: u32, supported: u32 },
+    /// The save at the path doesn't match its checksum, e.g. after being cut short or edited.
+    CorruptSave(PathBuf),
+    /// A battle was set up for `players` local players, but its arena only has `spawn_points`.
+    WrongPlayerCount { players: usize, spawn_points: usize },
+    /// The `animation` of `character` shows `frame`, which its sprite sheet doesn't have.
+    MissingFrame { character: String, animation: String, frame: String },
+    /// A run proof doesn't hold up when replayed against the local content.
+    ProofMismatch(ProofMismatch),
     Generic(String),
 }
 pub type WalpurgisResult<T = ()> = Result<T, WalpurgisError>;
 
+impl WalpurgisError {
+    /// The error for failing to open or read the `what` at `path`.
+    pub fn reading(path: &Path, what: &'static str, source: io::Error) -> Self {
+        let path = path.to_path_buf();
+        match source.kind() {
+            io::ErrorKind::NotFound => WalpurgisError::AssetNotFound { path, what },
+            _ => WalpurgisError::AssetUnreadable { path, what, source },
+        }
+    }
+
+    /// The error for failing to parse the `what` at `path`.
+    pub fn parsing(path: &Path, what: &'static str, source: ron::de::Error) -> Self {
+        WalpurgisError::AssetParse { path: path.to_path_buf(), what, source }
+    }
+}
+
+impl fmt::Display for WalpurgisError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WalpurgisError::GGEZ(e) => write!(f, "{}", e),
+            WalpurgisError::IO(e) => write!(f, "{}", e),
+            WalpurgisError::Ron(e) => write!(f, "{}", e),
+            WalpurgisError::Config(e) => write!(f, "{}", e),
+            WalpurgisError::AssetNotFound { path, what } => write!(f, "No {} found at `{}`.", what, path.display()),
+            WalpurgisError::AssetUnreadable { path, what, source } => {
+                write!(f, "Couldn't read {} `{}`: {}", what, path.display(), source)
+            },
+            WalpurgisError::AssetParse { path, what, source } => {
+                write!(f, "Couldn't parse {} `{}`: {}", what, path.display(), source)
+            },
+            WalpurgisError::UnsupportedVersion { path, what, found, supported } => write!(
+                f,
+                "The {} `{}` is in version {} of its format, but only version {} is supported.",
+                what,
+                path.display(),
+                found,
+                supported,
+            ),
+            WalpurgisError::CorruptSave(path) => {
+                write!(f, "The save `{}` doesn't match its checksum, so it may be corrupted.", path.display())
+            },
+            WalpurgisError::WrongPlayerCount { players: 0, .. } => write!(f, "A battle needs at least one player."),
+            WalpurgisError::WrongPlayerCount { players, spawn_points } => write!(
+                f,
+                "The arena has {} spawn points, which isn't enough for {} players.",
+                spawn_points,
+                players,
+            ),
+            WalpurgisError::MissingFrame { character, animation, frame } => write!(
+                f,
+                "The {} animation of character `{}` shows frame `{}`, which its sprite sheet doesn't have.",
+                animation,
+                character,
+                frame,
+            ),
+            WalpurgisError::ProofMismatch(mismatch) => write!(f, "{}", mismatch),
+            WalpurgisError::Generic(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for WalpurgisError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WalpurgisError::GGEZ(e) => Some(e),
+            WalpurgisError::IO(e) | WalpurgisError::AssetUnreadable { source: e, .. } => Some(e),
+            WalpurgisError::Ron(e) | WalpurgisError::AssetParse { source: e, .. } => Some(e),
+            WalpurgisError::Config(e) => Some(e),
+            WalpurgisError::AssetNotFound { .. }
+                | WalpurgisError::UnsupportedVersion { .. }
+                | WalpurgisError::CorruptSave(_)
+                | WalpurgisError::WrongPlayerCount { .. }
+                | WalpurgisError::MissingFrame { .. }
+                | WalpurgisError::ProofMismatch(_)
+                | WalpurgisError::Generic(_) => None,
+        }
+    }
+}
+
 impl std::convert::From<ggez::error::GameError> for WalpurgisError {
     fn from(e: ggez::error::GameError) -> WalpurgisError {
         WalpurgisError::GGEZ(e)
@@ -19,6 +122,12 @@ impl std::convert::From<String> for WalpurgisError {
     }
 }
 
+impl std::convert::From<ProofMismatch> for WalpurgisError {
+    fn from(e: ProofMismatch) -> WalpurgisError {
+        WalpurgisError::ProofMismatch(e)
+    }
+}
+
 impl std::convert::From<std::io::Error> for WalpurgisError {
     fn from(e: std::io::Error) -> WalpurgisError {
         WalpurgisError::IO(e)
@@ -30,3 +139,35 @@ impl std::convert::From<ron::de::Error> for WalpurgisError {
         WalpurgisError::Ron(e)
     }
 }
+
+impl std::convert::From<ConfigError> for WalpurgisError {
+    fn from(e: ConfigError) -> WalpurgisError {
+        WalpurgisError::Config(e)
+    }
+}
+
+#[cfg(test)]
+mod result_test {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn missing_files_named() {
+        let path = Path::new("data/arenas/nowhere.ron");
+        let e = WalpurgisError::reading(path, "arena", io::Error::from(io::ErrorKind::NotFound));
+        assert!(e.to_string() == "No arena found at `data/arenas/nowhere.ron`.");
+        assert!(e.source().is_none());
+
+        let e = WalpurgisError::reading(path, "arena", io::Error::from(io::ErrorKind::PermissionDenied));
+        assert!(e.to_string().starts_with("Couldn't read arena `data/arenas/nowhere.ron`: "));
+        assert!(e.source().is_some());
+    }
+
+    #[test]
+    fn parse_errors_named() {
+        let source = ron::de::from_str::<u32>("nope").unwrap_err();
+        let e = WalpurgisError::parsing(Path::new("data/characters/alien.ron"), "character", source.clone());
+        assert!(e.to_string() == format!("Couldn't parse character `data/characters/alien.ron`: {}", source));
+        assert!(e.source().unwrap().to_string() == source.to_string());
+    }
+}
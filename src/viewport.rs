@@ -0,0 +1,130 @@
+//! Fits the game into windows of any size.
+//!
+//! Everything is drawn on a virtual screen `VIRTUAL_WIDTH` by `VIRTUAL_HEIGHT` units big, which is
+//! scaled up or down as far as fits in the window without changing its shape. Whatever is left of
+//! the window on either side is covered by black bars, so that resizing the window never changes
+//! what is in view.
+use ggez::graphics::Rect;
+use ggez::nalgebra as na;
+
+pub const VIRTUAL_WIDTH: f32 = 1280.;
+pub const VIRTUAL_HEIGHT: f32 = 720.;
+
+/// The virtual screen everything is laid out on.
+pub fn virtual_screen() -> Rect {
+    Rect::new(0., 0., VIRTUAL_WIDTH, VIRTUAL_HEIGHT)
+}
+
+/// Where the virtual screen goes in a window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Letterbox {
+    /// Window pixels per virtual unit.
+    pub scale: f32,
+    /// Where the virtual screen's top left corner is in the window, in pixels.
+    pub offset: na::Vector2<f32>,
+    /// The size of the window in pixels.
+    window: na::Vector2<f32>,
+}
+
+impl Default for Letterbox {
+    /// A window exactly the size of the virtual screen.
+    fn default() -> Self {
+        Self {
+            scale: 1.,
+            offset: na::Vector2::zeros(),
+            window: na::Vector2::new(VIRTUAL_WIDTH, VIRTUAL_HEIGHT),
+        }
+    }
+}
+
+impl Letterbox {
+    /// Fits the virtual screen into a window `width` by `height` pixels big, unless the window has
+    /// no room at all, e.g. while minimized.
+    pub fn fit(width: f32, height: f32) -> Option<Self> {
+        if !(width >= 1. && height >= 1.) {
+            return None;
+        }
+        let scale = (width / VIRTUAL_WIDTH).min(height / VIRTUAL_HEIGHT);
+        let window = na::Vector2::new(width, height);
+        let offset = (window - na::Vector2::new(VIRTUAL_WIDTH, VIRTUAL_HEIGHT) * scale) / 2.;
+        Some(Self { scale, offset, window })
+    }
+
+    /// The whole window in virtual units, for `graphics::set_screen_coordinates`. Starts left of
+    /// and above the virtual screen wherever there are bars there.
+    pub fn screen_coordinates(&self) -> Rect {
+        Rect::new(
+            -self.offset[0] / self.scale,
+            -self.offset[1] / self.scale,
+            self.window[0] / self.scale,
+            self.window[1] / self.scale,
+        )
+    }
+
+    /// The bars covering the window around the virtual screen, in virtual units. Either the two
+    /// at the sides or the two at the top and bottom, or none if the window fits exactly.
+    pub fn bars(&self) -> Vec<Rect> {
+        let window = self.screen_coordinates();
+        let bars = if self.offset[0] > self.offset[1] {
+            [
+                Rect::new(window.x, 0., -window.x, VIRTUAL_HEIGHT),
+                Rect::new(VIRTUAL_WIDTH, 0., -window.x, VIRTUAL_HEIGHT),
+            ]
+        } else {
+            [
+                Rect::new(0., window.y, VIRTUAL_WIDTH, -window.y),
+                Rect::new(0., VIRTUAL_HEIGHT, VIRTUAL_WIDTH, -window.y),
+            ]
+        };
+        bars.iter().filter(|bar| bar.w > 0. && bar.h > 0.).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod viewport_test {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn same_shape_fills_window() {
+        let letterbox = Letterbox::fit(1920., 1080.).unwrap();
+        assert!(letterbox.scale == 1.5 && letterbox.offset == na::Vector2::zeros());
+        assert!(letterbox.screen_coordinates() == virtual_screen());
+        assert!(letterbox.bars().is_empty());
+        assert!(Letterbox::fit(VIRTUAL_WIDTH, VIRTUAL_HEIGHT).unwrap() == Letterbox::default());
+    }
+
+    #[test]
+    fn wide_windows_get_side_bars() {
+        let letterbox = Letterbox::fit(1600., 600.).unwrap();
+        // 600 / 720 tall, so 1066.7 of the 1600 pixels wide.
+        assert!(close(letterbox.scale, 600. / 720.));
+        assert!(close(letterbox.offset[0], (1600. - 1280. * 600. / 720.) / 2.) && letterbox.offset[1] == 0.);
+        let screen = letterbox.screen_coordinates();
+        assert!(close(screen.x, -320.) && close(screen.w, 1920.) && screen.y == 0. && close(screen.h, 720.));
+        let bars = letterbox.bars();
+        assert!(bars.len() == 2);
+        assert!(close(bars[0].x, -320.) && close(bars[0].w, 320.) && bars[1].x == VIRTUAL_WIDTH);
+    }
+
+    #[test]
+    fn narrow_windows_get_top_and_bottom_bars() {
+        let letterbox = Letterbox::fit(100., 1000.).unwrap();
+        assert!(close(letterbox.scale, 100. / 1280.));
+        assert!(letterbox.offset[0] == 0. && close(letterbox.offset[1], (1000. - 56.25) / 2.));
+        let bars = letterbox.bars();
+        assert!(bars.len() == 2);
+        assert!(bars[0].w == VIRTUAL_WIDTH && close(bars[0].y + bars[0].h, 0.));
+        assert!(bars[1].y == VIRTUAL_HEIGHT && close(bars[1].h, bars[0].h));
+    }
+
+    #[test]
+    fn empty_windows_not_fitted() {
+        assert!(Letterbox::fit(0., 720.).is_none());
+        assert!(Letterbox::fit(1280., 0.).is_none());
+        assert!(Letterbox::fit(f32::NAN, 720.).is_none());
+    }
+}
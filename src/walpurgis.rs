@@ -1,31 +1,347 @@
-use ggez::{Context, GameResult};
-use ggez::event::{self, EventHandler, KeyCode, KeyMods};
-use ggez::graphics::{self, Drawable, DrawParam};
+use ggez::{filesystem, timer, Context, GameResult};
+use ggez::event::{self, Axis, Button, EventHandler, GamepadId, KeyCode, KeyMods};
+use ggez::graphics::{self, DrawMode, Drawable, DrawParam, Mesh, Text};
+
+use std::path::PathBuf;
+use std::time::Duration;
 
 use crate::{
-    screens,
+    assets::AssetManager,
+    audio::{AudioManager, GgezSounds},
+    debug::{self, ConsoleCommand, DebugChannels, DebugConsole},
+    persistence::{self, PersistenceWorker, RealFileSystem},
+    power::{self, PowerMonitor, PowerState},
+    saves::{self, SaveData},
+    screens::{self, Transition, battle::proof},
     settings,
-    inputs::{HandleInput, Input},
+    text::Fonts,
+    timing::{SimClock, WindowClock},
+    inputs::{HandleInput, Input, InputQueue, PadId, layout::KeyboardLayout},
     util::result::WalpurgisResult,
+    viewport::{self, Letterbox},
 };
 
+/// The number of physics updates run per second.
+pub const TICKS_PER_SECOND: u32 = 60;
+
+/// Pauses and resumes battles.
+pub const PAUSE_KEY: KeyCode = KeyCode::Escape;
+/// Quits the game while paused.
+pub const QUIT_KEY: KeyCode = KeyCode::Q;
+/// Switches between fullscreen and windowed.
+pub const FULLSCREEN_KEY: KeyCode = KeyCode::F11;
+
+/// How long notices stay on screen, in update windows.
+const NOTICE_DURATION: u32 = 5 * TICKS_PER_SECOND;
+
+/// How often changed assets are reloaded, if hot reloading is on.
+const HOT_RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long quitting may wait for queued file writes to finish.
+const SHUTDOWN_WRITE_BUDGET: Duration = Duration::from_secs(2);
+
+/// A message shown once over the first screen, e.g. about settings that were reset.
+struct Notice {
+    text: Text,
+    windows_left: u32,
+}
+
 /// This is the global game state.
 pub struct Walpurgis {
     // TODO: Some shared state.
     /// Screen specific state.
     screen: screens::Screen,
-    fire_once_key_buffer: Vec<Input>,
+    /// Presses and releases, each waiting for the update window it was assigned to.
+    input_queue: InputQueue,
+    /// Gamepads in the order they were first used, so that each one's index is its `PadId`.
+    gamepads: Vec<GamepadId>,
+    debug_channels: DebugChannels,
+    /// Only opened in debug builds.
+    console: DebugConsole,
+    keyboard_layout: KeyboardLayout,
+    sim_clock: SimClock,
+    /// Counts the update windows due.
+    windows: WindowClock,
+    power: PowerMonitor,
+    /// Where the virtual screen is drawn in the window.
+    letterbox: Letterbox,
+    notice: Option<Notice>,
+    /// Writes files off the main thread.
+    persistence: PersistenceWorker,
+    /// Plays the sounds made on every screen, and their music.
+    audio: AudioManager,
+    /// Every arena, character and texture loaded so far.
+    assets: AssetManager,
+    /// When changed assets were last looked for.
+    last_reload: Duration,
+    /// Everyone's progress, written back to `save_file` whenever it changes.
+    save: SaveData,
+    save_file: PathBuf,
+    /// Where the proof of the last trial completed is saved, next to `save_file`.
+    proof_file: PathBuf,
+    /// Kept for starting battles from the menu.
+    settings: settings::Settings,
+    fonts: Fonts,
 }
 
 impl Walpurgis {
     /// Create a new game state.
-    pub fn new(ctx: &mut Context, assets: &settings::Assets) -> WalpurgisResult<Self> {
+    pub fn new(
+        ctx: &mut Context,
+        settings: settings::Settings,
+        settings_problems: &[settings::SettingsProblem],
+    ) -> WalpurgisResult<Self> {
         // Load/create resources here: images, fonts, sounds, etc.
+        let debug_channels = DebugChannels::from_settings(&settings.debug_draw);
+        let fonts = Fonts::load(ctx, &settings.assets, &settings.fonts)?;
+        let save_file = saves::save_file(&settings.saves, filesystem::user_data_dir(ctx));
+        let save = SaveData::load_or_default(&save_file);
+        let proof_file = save_file.with_file_name(proof::LATEST_PROOF);
+        let mut assets = AssetManager::new(&settings.assets.root);
+        // Sprites are loaded through `ggez`, which only looks in its own resource directories.
+        if let Err(e) = assets.mount(ctx) {
+            log::error!("Failed to mount the asset root: {}", e);
+        }
+        let mut screen = if settings.development.skip_main_menu {
+            let battle = screens::Screen::first_battle(ctx, &settings, &mut assets, &fonts, &save.characters(), &[], &[]);
+            if let Err(e) = &battle {
+                log::error!("Failed to start a battle: {:?}", e);
+            }
+            screens::Screen::loaded_or_error(battle, ctx)
+        } else {
+            screens::Screen::main_menu()
+        };
+        screen.set_debug_channels(&debug_channels);
+        let keyboard_layout = settings.controls.keyboard_layout().unwrap_or_else(KeyboardLayout::guess);
+        log::info!("Labelling keys for {:?}.", keyboard_layout);
+        let (width, height) = graphics::size(ctx);
+        let letterbox = Letterbox::fit(width, height).unwrap_or_default();
+        graphics::set_screen_coordinates(ctx, letterbox.screen_coordinates())?;
         Ok(Walpurgis {
-            screen: screens::Screen::first_battle(ctx, assets)?,
-            fire_once_key_buffer: vec![],
+            screen,
+            input_queue: InputQueue::default(),
+            gamepads: vec![],
+            debug_channels,
+            console: DebugConsole::default(),
+            keyboard_layout,
+            sim_clock: SimClock::default(),
+            windows: WindowClock::default(),
+            power: PowerMonitor::new(settings.power.idle_after()),
+            letterbox,
+            notice: settings::problems_notice(settings_problems).map(|notice| Notice {
+                text: Text::new(notice),
+                windows_left: NOTICE_DURATION,
+            }),
+            persistence: PersistenceWorker::new(RealFileSystem, persistence::DEFAULT_CAPACITY),
+            audio: AudioManager::new(Box::new(GgezSounds::load(ctx, &settings.assets.root)), &settings.audio),
+            assets,
+            last_reload: Duration::default(),
+            save,
+            save_file,
+            proof_file,
+            settings,
+            fonts,
         })
     }
+
+    /// Switches to the screen asked for by the current one.
+    fn transition(&mut self, ctx: &mut Context, transition: Transition) {
+        match transition {
+            Transition::None => (),
+            Transition::CharacterSelect => {
+                match screens::Screen::character_select(&self.settings.controls) {
+                    Ok(screen) => {
+                        log::info!("Picking races.");
+                        self.show(screen);
+                    },
+                    Err(e) => {
+                        log::error!("Failed to set up character select: {}", e);
+                        self.show(screens::Screen::error(&e, ctx));
+                    },
+                }
+            },
+            Transition::StartBattle => {
+                let characters = self.save.characters();
+                let races = self.screen.picked_races();
+                let handicaps = self.screen.picked_handicaps();
+                match screens::Screen::first_battle(ctx, &self.settings, &mut self.assets, &self.fonts, &characters, &races, &handicaps) {
+                    Ok(screen) => {
+                        log::info!("Starting a battle.");
+                        self.show(screen);
+                    },
+                    Err(e) => {
+                        log::error!("Failed to start a battle: {:?}", e);
+                        self.show(screens::Screen::error(&e, ctx));
+                    },
+                }
+            },
+            Transition::WatchReplay => {
+                match screens::Screen::latest_replay(ctx, &mut self.assets, &self.settings.audio, &self.fonts) {
+                    Ok(screen) => {
+                        log::info!("Watching the last replay.");
+                        self.show(screen);
+                    },
+                    Err(e) => {
+                        log::error!("Failed to load the replay: {}", e);
+                        self.show(screens::Screen::error(&e, ctx));
+                    },
+                }
+            },
+            Transition::ComboTrial => {
+                match screens::Screen::first_trial(ctx, &self.settings, &mut self.assets, &self.fonts, &self.save, &self.proof_file) {
+                    Ok(screen) => {
+                        log::info!("Starting a combo trial.");
+                        self.show(screen);
+                    },
+                    Err(e) => {
+                        log::error!("Failed to start a combo trial: {}", e);
+                        self.show(screens::Screen::error(&e, ctx));
+                    },
+                }
+            },
+            Transition::Resume => {
+                log::info!("Resuming from the pause menu.");
+                self.screen.toggle_pause(self.keyboard_layout);
+            },
+            Transition::MainMenu => {
+                log::info!("Back to the main menu.");
+                self.keep_skill_trees();
+                self.screen = screens::Screen::main_menu();
+            },
+            Transition::Settings => {
+                log::info!("Changing the settings.");
+                self.show(screens::Screen::settings(&self.settings, self.keyboard_layout));
+            },
+            Transition::SaveSettings => {
+                if let Some(settings) = self.screen.edited_settings().cloned() {
+                    self.change_settings(ctx, settings);
+                }
+                self.screen = screens::Screen::main_menu();
+            },
+            Transition::Quit => {
+                log::info!("Quitting from the menu.");
+                event::quit(ctx);
+            },
+        }
+    }
+
+    fn show(&mut self, mut screen: screens::Screen) {
+        screen.set_debug_channels(&self.debug_channels);
+        self.screen = screen;
+    }
+
+    /// Saves the skill trees spent on the results screen, if that's the screen being left.
+    fn keep_skill_trees(&mut self) {
+        let trees = self.screen.skill_trees();
+        if trees.is_empty() {
+            return;
+        }
+        for (idx, (race, tree)) in trees.into_iter().enumerate() {
+            self.save.profile_mut(idx).skill_trees.insert(race, tree);
+        }
+        self.write_save();
+    }
+
+    /// Runs a line typed into the debug console, replying with what it did.
+    fn run_console_command(&mut self, line: &str) {
+        let reply = match ConsoleCommand::parse(line) {
+            Ok(ConsoleCommand::Draw { channel, enabled }) => {
+                self.debug_channels.apply(channel, enabled);
+                self.screen.set_debug_channels(&self.debug_channels);
+                self.debug_channels.describe()
+            },
+            Ok(ConsoleCommand::Sim(command)) => {
+                self.sim_clock.apply(command);
+                self.resume_for_run();
+                self.sim_clock.describe()
+            },
+            Err(e) => e,
+        };
+        log::debug!("Console: `{}`: {}", line, reply);
+        self.console.set_reply(reply);
+    }
+
+    /// Unpauses the battle if a run was just started, since runs only go on while unpaused.
+    fn resume_for_run(&mut self) {
+        if self.sim_clock.is_running_until() && self.screen.is_paused() {
+            self.screen.toggle_pause(self.keyboard_layout);
+        }
+    }
+
+    fn write_save(&self) {
+        if let Err(e) = self.save.save(&self.save_file, &self.persistence) {
+            log::error!("Failed to save: {}", e);
+        }
+    }
+
+    /// Reloads the arena and character files changed since they were last looked for, if hot
+    /// reloading is on. Arenas reloaded take effect right away, characters from the next battle.
+    fn reload_assets(&mut self, ctx: &mut Context) {
+        let now = timer::time_since_start(ctx);
+        if !self.settings.development.hot_reload || now < self.last_reload + HOT_RELOAD_INTERVAL {
+            return;
+        }
+        self.last_reload = now;
+        for file in self.assets.poll_reload() {
+            if let Some(arena) = self.assets.loaded_arena(&file) {
+                self.screen.reload_arena(&file, &arena);
+            }
+        }
+    }
+
+    /// Goes fullscreen or back to a window, keeping the choice in the settings for next time.
+    fn toggle_fullscreen(&mut self, ctx: &mut Context) {
+        let mut settings = self.settings.clone();
+        settings.window.fullscreen = !settings.window.fullscreen;
+        self.change_settings(ctx, settings);
+    }
+
+    /// Plays on with `settings` and saves them for next time. The logging level only takes
+    /// effect from the next start, since logging is set up once.
+    fn change_settings(&mut self, ctx: &mut Context, settings: settings::Settings) {
+        if settings.window.fullscreen != self.settings.window.fullscreen {
+            log::info!("Fullscreen {}.", if settings.window.fullscreen { "on" } else { "off" });
+            if let Err(e) = graphics::set_fullscreen(ctx, settings.window.fullscreen_type()) {
+                log::error!("Couldn't switch fullscreen: {}", e);
+            }
+        }
+        if settings.logging.level != self.settings.logging.level {
+            log::info!("Logging at `{}` from the next start.", settings.logging.level);
+        }
+        self.audio.set_volumes(&settings.audio);
+        self.settings = settings;
+        if let Err(e) = settings::save(&self.settings, &self.persistence) {
+            log::error!("Failed to save the settings: {}", e);
+        }
+    }
+
+    /// Covers the window around the virtual screen, so that nothing drawn past its edges shows.
+    fn draw_letterbox(&self, ctx: &mut Context) -> GameResult {
+        for bar in self.letterbox.bars() {
+            let mesh = Mesh::new_rectangle(ctx, DrawMode::fill(), bar, graphics::BLACK)?;
+            graphics::draw(ctx, &mesh, DrawParam::new())?;
+        }
+        Ok(())
+    }
+
+    /// The `PadId` of gamepad `id`, numbering it if it hasn't been used before.
+    fn pad_id(&mut self, id: GamepadId) -> PadId {
+        match self.gamepads.iter().position(|known| *known == id) {
+            Some(pad) => pad,
+            None => {
+                self.gamepads.push(id);
+                self.gamepads.len() - 1
+            },
+        }
+    }
+
+    /// Finishes up once the game loop has stopped, giving queued writes a chance to land.
+    pub fn shutdown(&mut self) {
+        let unwritten = self.persistence.shutdown(SHUTDOWN_WRITE_BUDGET);
+        if unwritten > 0 {
+            log::error!("Quit with {} file writes still queued.", unwritten);
+        }
+    }
 }
 
 impl EventHandler for Walpurgis {
@@ -36,35 +352,170 @@ impl EventHandler for Walpurgis {
     /// 4. Update components
     ///     * Players
     ///     * Arena
-    /// 5. Re-render
+    /// 5. Prepare the screen for drawing
+    ///
+    /// In the background, battles are paused and trials held once idle, and frames are slowed down
+    /// to save power.
     fn update(&mut self, ctx: &mut Context) -> GameResult {
-        while ggez::timer::check_update_time(ctx, 60) {
-            self.screen.handle_input(ctx, &self.fire_once_key_buffer);
-            self.fire_once_key_buffer.clear();
+        let power_state = self.power.state(timer::time_since_start(ctx));
+        // Trials have no pause menu, so they're held where they are until there's input again.
+        let held = power_state == PowerState::Idle && self.screen.is_simulating();
+        if held && self.screen.is_battle() {
+            log::info!("No input in the background for a while. Pausing.");
+            self.screen.toggle_pause(self.keyboard_layout);
+        }
+        self.windows.advance(timer::delta(ctx));
+        while self.windows.take_window(TICKS_PER_SECOND) {
+            let (pressed, released) = self.input_queue.begin_window();
+            self.screen.handle_input(ctx, &pressed);
+            self.screen.handle_key_up(ctx, &released);
+            if self.screen.is_paused() {
+                // Paused battles aren't ticked, but their menu still has to be acted on.
+                let transition = self.screen.handle_update();
+                self.transition(ctx, transition);
+            }
+
+            if let Some(notice) = &mut self.notice {
+                notice.windows_left -= 1;
+                if notice.windows_left == 0 {
+                    self.notice = None;
+                }
+            }
 
-            self.screen.handle_update();
+            for _ in 0..self.sim_clock.ticks_for_window(self.screen.is_paused() || held) {
+                let transition = self.screen.step();
+                if transition != Transition::None {
+                    self.transition(ctx, transition);
+                    break;
+                }
+                let tick = self.screen.elapsed_ticks().unwrap_or(0);
+                if self.sim_clock.after_tick(tick, self.screen.tick_events()) {
+                    if !self.screen.is_paused() {
+                        self.screen.toggle_pause(self.keyboard_layout);
+                    }
+                    break;
+                }
+            }
+            let sounds = self.screen.take_sounds();
+            self.audio.handle_events(&sounds);
+            if let Some(record) = self.screen.finish_battle(self.keyboard_layout, &self.persistence, &self.save) {
+                self.save.record_match(record);
+                self.write_save();
+            }
+            if let Some(trial) = self.screen.take_completed_trial() {
+                if self.save.profile_mut(0).complete_trial(&trial) {
+                    self.write_save();
+                }
+            }
+            if let Some(proof) = self.screen.take_trial_proof() {
+                if let Err(e) = proof.save(&self.proof_file, &self.persistence) {
+                    log::error!("Failed to save the run proof: {}", e);
+                }
+            }
+        }
+        self.reload_assets(ctx);
+        self.audio.set_soundtrack(self.screen.soundtrack());
+        self.screen.prepare();
+        self.persistence.poll();
+        if power_state != PowerState::Focused {
+            timer::sleep(power::LOW_POWER_FRAME);
         }
         Ok(())
     }
 
+    /// Draws without changing anything: the game may be drawn any number of times between ticks,
+    /// so everything that changes over time is advanced in `update`, and anything drawing needs
+    /// worked out ahead of time is done by `Screen::prepare`.
     fn draw(&mut self, ctx: &mut Context)-> GameResult {
         graphics::clear(ctx, graphics::BLACK);
         self.screen.draw(ctx, DrawParam::new())?;
+        if let Some(notice) = &self.notice {
+            let dest = [10., viewport::VIRTUAL_HEIGHT - 10. - notice.text.height(ctx) as f32];
+            notice.text.draw(ctx, DrawParam::new().dest(dest))?;
+        }
+        self.console.draw(ctx, DrawParam::new())?;
+        self.draw_letterbox(ctx)?;
         graphics::present(ctx)
     }
 
     fn key_down_event(&mut self, ctx: &mut Context, key: KeyCode, mods: KeyMods, repeat: bool) {
+        self.power.handle_input(timer::time_since_start(ctx));
         if repeat {
             return;
         }
         match key {
-            KeyCode::Escape => {
-                log::info!("Escape pressed. Stopping game loop.");
+            debug::CONSOLE_KEY if cfg!(debug_assertions) => self.console.toggle(),
+            key if self.console.is_open() => {
+                if let Some(line) = self.console.handle_key(key) {
+                    self.run_console_command(&line);
+                }
+            }
+            PAUSE_KEY if self.screen.is_battle() || self.screen.is_paused() => {
+                log::info!("Pause key pressed. Toggling pause.");
+                self.screen.toggle_pause(self.keyboard_layout);
+            }
+            QUIT_KEY if self.screen.is_paused() || self.screen.is_finished() => {
+                log::info!("Quit key pressed. Stopping game loop.");
                 event::quit(ctx);
             }
+            FULLSCREEN_KEY => self.toggle_fullscreen(ctx),
+            key if self.debug_channels.handle_key(key) => {
+                self.screen.set_debug_channels(&self.debug_channels);
+            }
+            key if cfg!(debug_assertions) && self.sim_clock.handle_key(key) => {
+                log::info!("Simulation: {}", self.sim_clock.describe());
+                self.resume_for_run();
+            }
             key => {
-                self.fire_once_key_buffer.push((key, mods));
+                self.input_queue.press(Input::Key(key, mods));
             }
         }
     }
+
+    fn text_input_event(&mut self, _ctx: &mut Context, ch: char) {
+        self.console.type_char(ch);
+    }
+
+    fn key_up_event(&mut self, ctx: &mut Context, key: KeyCode, mods: KeyMods) {
+        self.power.handle_input(timer::time_since_start(ctx));
+        self.input_queue.release(Input::Key(key, mods));
+    }
+
+    fn gamepad_button_down_event(&mut self, ctx: &mut Context, btn: Button, id: GamepadId) {
+        self.power.handle_input(timer::time_since_start(ctx));
+        let pad = self.pad_id(id);
+        self.input_queue.press(Input::Button(btn, pad));
+    }
+
+    fn gamepad_button_up_event(&mut self, ctx: &mut Context, btn: Button, id: GamepadId) {
+        self.power.handle_input(timer::time_since_start(ctx));
+        let pad = self.pad_id(id);
+        self.input_queue.release(Input::Button(btn, pad));
+    }
+
+    /// Sticks are handled like presses, with the latest position of each one winning.
+    fn gamepad_axis_event(&mut self, ctx: &mut Context, axis: Axis, value: f32, id: GamepadId) {
+        self.power.handle_input(timer::time_since_start(ctx));
+        let pad = self.pad_id(id);
+        self.input_queue.press(Input::Axis(axis, value, pad));
+    }
+
+    fn focus_event(&mut self, ctx: &mut Context, gained: bool) {
+        log::info!("{} focus.", if gained { "Gained" } else { "Lost" });
+        self.power.handle_focus(gained, timer::time_since_start(ctx));
+    }
+
+    /// Scales the virtual screen to fit the new window, leaving what's in view as it was.
+    /// Minimized windows are left as they were.
+    fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) {
+        log::info!("Resized to {}x{}.", width, height);
+        let letterbox = match Letterbox::fit(width, height) {
+            Some(letterbox) => letterbox,
+            None => return,
+        };
+        if let Err(e) = graphics::set_screen_coordinates(ctx, letterbox.screen_coordinates()) {
+            log::error!("Couldn't resize the screen: {}", e);
+        }
+        self.letterbox = letterbox;
+    }
 }